@@ -9,3 +9,14 @@ pub fn ax_framebuffer_info() -> AxDisplayInfo {
 pub fn ax_framebuffer_flush() {
     axdisplay::framebuffer_flush()
 }
+
+/// Gets the resolution the display prefers.
+pub fn ax_display_preferred_resolution() -> crate::AxResult<(u32, u32)> {
+    axdisplay::preferred_resolution()
+}
+
+/// Changes the resolution of the display, and returns the new framebuffer
+/// information.
+pub fn ax_framebuffer_set_resolution(width: u32, height: u32) -> crate::AxResult<AxDisplayInfo> {
+    axdisplay::framebuffer_set_resolution(width, height)
+}
@@ -1,7 +1,8 @@
-use alloc::string::String;
+use alloc::{string::String, vec::Vec};
 use axerrno::AxResult;
 use axfs::fops::{Directory, File};
 
+pub use axfs::api::DiskUsage as AxDiskUsage;
 pub use axfs::fops::DirEntry as AxDirEntry;
 pub use axfs::fops::FileAttr as AxFileAttr;
 pub use axfs::fops::FilePerm as AxFilePerm;
@@ -85,3 +86,11 @@ pub fn ax_current_dir() -> AxResult<String> {
 pub fn ax_set_current_dir(path: &str) -> AxResult {
     axfs::api::set_current_dir(path)
 }
+
+pub fn ax_disk_usage(path: &str) -> AxResult<AxDiskUsage> {
+    axfs::api::disk_usage(path)
+}
+
+pub fn ax_mounts() -> AxResult<Vec<(String, AxDiskUsage)>> {
+    axfs::api::mounts()
+}
@@ -12,6 +12,12 @@ cfg_alloc! {
     }
 }
 
+cfg_paging! {
+    pub fn ax_hotplug_memory(paddr: usize, size: usize) -> crate::AxResult {
+        axmm::hotplug_memory(paddr.into(), size)
+    }
+}
+
 cfg_dma! {
     pub use axdma::DMAInfo;
 
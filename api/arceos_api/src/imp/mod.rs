@@ -1,6 +1,15 @@
 mod mem;
 mod task;
 
+cfg_alloc! {
+    mod env {
+        pub use axruntime::env::{
+            remove_var as ax_unsetenv, set_var as ax_setenv, var as ax_getenv, vars as ax_envs,
+        };
+    }
+    pub use env::*;
+}
+
 cfg_fs! {
     mod fs;
     pub use fs::*;
@@ -16,6 +25,11 @@ cfg_display! {
     pub use display::*;
 }
 
+cfg_plugin! {
+    mod process;
+    pub use process::*;
+}
+
 mod stdio {
     use core::fmt;
 
@@ -1,7 +1,8 @@
 use crate::io::AxPollState;
 use axerrno::AxResult;
-use axnet::{UdpSocket, TcpSocket};
+use axnet::{ConnectOptions, UdpSocket, TcpSocket};
 use core::net::{IpAddr, SocketAddr};
+use core::time::Duration;
 
 /// A handle to a TCP socket.
 pub struct AxTcpSocketHandle(TcpSocket);
@@ -34,6 +35,28 @@ pub fn ax_tcp_connect(socket: &AxTcpSocketHandle, addr: SocketAddr) -> AxResult
     socket.0.connect(addr)
 }
 
+pub fn ax_tcp_connect_timeout(
+    socket: &AxTcpSocketHandle,
+    addr: SocketAddr,
+    timeout: Duration,
+) -> AxResult {
+    socket.0.connect_timeout(addr, Some(timeout))
+}
+
+pub fn ax_tcp_connect_any(
+    addrs: &[SocketAddr],
+    attempt_delay: Duration,
+    attempt_timeout: Option<Duration>,
+    timeout: Option<Duration>,
+) -> AxResult<AxTcpSocketHandle> {
+    let options = ConnectOptions {
+        attempt_delay,
+        attempt_timeout,
+        timeout,
+    };
+    TcpSocket::connect_any(addrs, &options).map(AxTcpSocketHandle)
+}
+
 pub fn ax_tcp_bind(socket: &AxTcpSocketHandle, addr: SocketAddr) -> AxResult {
     socket.0.bind(addr)
 }
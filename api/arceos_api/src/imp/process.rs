@@ -0,0 +1,40 @@
+use axerrno::AxResult;
+
+pub use axplugin::{MainArgs as AxProgramArgs, Plugin as AxProgram};
+
+pub fn ax_load_program(path: &str) -> AxResult<AxProgram> {
+    #[cfg(feature = "fs")]
+    {
+        axplugin::load_file(path)
+    }
+    #[cfg(not(feature = "fs"))]
+    {
+        let _ = path;
+        axerrno::ax_err!(Unsupported, "loading programs requires the `fs` feature")
+    }
+}
+
+pub fn ax_program_args(args: &[&str], envs: &[&str]) -> AxResult<AxProgramArgs> {
+    AxProgramArgs::new(args, envs)
+}
+
+pub fn ax_run_program(program: &AxProgram, args: &AxProgramArgs) -> AxResult<i32> {
+    program.run_main(args)
+}
+
+/// The `exit` of the programs, which ends the task running the program with
+/// its exit code, rather than the whole system.
+#[cfg(feature = "multitask")]
+extern "C" fn exit(exit_code: core::ffi::c_int) -> ! {
+    axtask::exit(exit_code)
+}
+
+#[cfg(feature = "multitask")]
+extern "C" fn _exit(exit_code: core::ffi::c_int) -> ! {
+    axtask::exit(exit_code)
+}
+
+#[cfg(feature = "multitask")]
+axplugin::export_symbol!(exit);
+#[cfg(feature = "multitask")]
+axplugin::export_symbol!(_exit);
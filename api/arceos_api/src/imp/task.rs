@@ -42,6 +42,9 @@ cfg_task! {
     /// A mask to specify the CPU affinity.
     pub use axtask::AxCpuMask;
 
+    /// The resources used by the tasks of a task group.
+    pub use axtask::GroupUsage as AxGroupUsage;
+
     /// A handle to a wait queue.
     ///
     /// A wait queue is used to store sleeping tasks waiting for a certain event
@@ -96,6 +99,17 @@ cfg_task! {
         }
     }
 
+    pub fn ax_set_current_group(name: Option<&str>) {
+        axtask::set_current_group(name.map(axtask::task_group));
+    }
+
+    pub fn ax_group_usage(name: &str) -> Option<AxGroupUsage> {
+        axtask::task_groups()
+            .into_iter()
+            .find(|group| group.name() == name)
+            .map(|group| group.usage())
+    }
+
     pub fn ax_wait_queue_wait(wq: &AxWaitQueueHandle, timeout: Option<Duration>) -> bool {
         #[cfg(feature = "irq")]
         if let Some(dur) = timeout {
@@ -35,6 +35,26 @@ pub mod sys {
     }
 }
 
+/// Environment variables.
+///
+/// Each process has its own environment, copied from its parent.
+pub mod env {
+    define_api! {
+        @cfg "alloc";
+        /// Returns the value of the environment variable `key`.
+        pub fn ax_getenv(key: &str) -> Option<alloc::string::String>;
+        /// Returns all environment variables, as `(key, value)` pairs.
+        pub fn ax_envs() -> alloc::vec::Vec<(alloc::string::String, alloc::string::String)>;
+        /// Sets the environment variable `key` to `value`.
+        ///
+        /// Fails if `key` is empty or contains `=` or NUL, or `value`
+        /// contains NUL.
+        pub fn ax_setenv(key: &str, value: &str) -> crate::AxResult;
+        /// Removes the environment variable `key`.
+        pub fn ax_unsetenv(key: &str);
+    }
+}
+
 /// Time-related operations.
 pub mod time {
     define_api_type! {
@@ -75,6 +95,15 @@ pub mod mem {
         pub unsafe fn ax_dealloc(ptr: NonNull<u8>, layout: Layout);
     }
 
+    define_api! {
+        @cfg "paging";
+        /// Adds the RAM at `[paddr, paddr + size)`, discovered after boot, to
+        /// the direct map and the global allocator (memory hotplug).
+        ///
+        /// Both `paddr` and `size` must be 4K-aligned.
+        pub fn ax_hotplug_memory(paddr: usize, size: usize) -> crate::AxResult;
+    }
+
     define_api_type! {
         @cfg "dma";
         pub type DMAInfo;
@@ -122,6 +151,7 @@ pub mod task {
         pub type AxTaskHandle;
         pub type AxWaitQueueHandle;
         pub type AxCpuMask;
+        pub type AxGroupUsage;
     }
 
     define_api! {
@@ -158,6 +188,12 @@ pub mod task {
         pub fn ax_set_current_priority(prio: isize) -> crate::AxResult;
         /// Sets the cpu affinity of the current task.
         pub fn ax_set_current_affinity(cpumask: AxCpuMask) -> crate::AxResult;
+        /// Moves the current task, and the tasks it spawns from now on, to the
+        /// task group `name`, or out of its group if `None`.
+        pub fn ax_set_current_group(name: Option<&str>);
+        /// Returns the resources used by the tasks of the group `name`, or
+        /// `None` if there's no such group.
+        pub fn ax_group_usage(name: &str) -> Option<AxGroupUsage>;
         /// Blocks the current task and put it into the wait queue, until
         /// other tasks notify the wait queue, or the the given duration has
         /// elapsed (if specified).
@@ -192,6 +228,7 @@ pub mod fs {
         pub type AxFilePerm;
         pub type AxDirEntry;
         pub type AxSeekFrom;
+        pub type AxDiskUsage;
         #[cfg(feature = "myfs")]
         pub type AxDisk;
         #[cfg(feature = "myfs")]
@@ -260,6 +297,12 @@ pub mod fs {
         pub fn ax_current_dir() -> AxResult<alloc::string::String>;
         /// Changes the current working directory to the specified path.
         pub fn ax_set_current_dir(path: &str) -> AxResult;
+
+        /// Returns the disk space usage of the filesystem containing `path`.
+        pub fn ax_disk_usage(path: &str) -> AxResult<AxDiskUsage>;
+        /// Returns all mount points with the disk space usage of their
+        /// filesystems, starting with the root filesystem.
+        pub fn ax_mounts() -> AxResult<alloc::vec::Vec<(alloc::string::String, AxDiskUsage)>>;
     }
 }
 
@@ -267,6 +310,7 @@ pub mod fs {
 pub mod net {
     use crate::{AxResult, io::AxPollState};
     use core::net::{IpAddr, SocketAddr};
+    use core::time::Duration;
 
     define_api_type! {
         @cfg "net";
@@ -290,6 +334,25 @@ pub mod net {
 
         /// Connects the TCP socket to the given address and port.
         pub fn ax_tcp_connect(handle: &AxTcpSocketHandle, addr: SocketAddr) -> AxResult;
+        /// Connects the TCP socket to the given address and port, failing if
+        /// the connection isn't established before the timeout expires.
+        pub fn ax_tcp_connect_timeout(
+            handle: &AxTcpSocketHandle,
+            addr: SocketAddr,
+            timeout: Duration,
+        ) -> AxResult;
+        /// Connects a new TCP socket to the first of the given addresses that
+        /// accepts the connection.
+        ///
+        /// The attempts are started `attempt_delay` apart and run in parallel.
+        /// Each one is aborted after `attempt_timeout`, and all of them after
+        /// `timeout`.
+        pub fn ax_tcp_connect_any(
+            addrs: &[SocketAddr],
+            attempt_delay: Duration,
+            attempt_timeout: Option<Duration>,
+            timeout: Option<Duration>,
+        ) -> AxResult<AxTcpSocketHandle>;
         /// Binds the TCP socket to the given address and port.
         pub fn ax_tcp_bind(socket: &AxTcpSocketHandle, addr: SocketAddr) -> AxResult;
         /// Starts listening on the bound address and port.
@@ -371,6 +434,43 @@ pub mod display {
         pub fn ax_framebuffer_info() -> AxDisplayInfo;
         /// Flushes the framebuffer, i.e. show on the screen.
         pub fn ax_framebuffer_flush();
+        /// Gets the resolution the display prefers.
+        pub fn ax_display_preferred_resolution() -> crate::AxResult<(u32, u32)>;
+        /// Changes the resolution of the display, and returns the new
+        /// framebuffer information. The old framebuffer must not be used
+        /// anymore.
+        pub fn ax_framebuffer_set_resolution(width: u32, height: u32) -> crate::AxResult<AxDisplayInfo>;
+    }
+}
+
+/// Running programs.
+///
+/// A program is a kernel-space plugin loaded by the `axplugin` module, which
+/// defines a C `main` function.
+pub mod process {
+    use crate::AxResult;
+
+    define_api_type! {
+        @cfg "plugin";
+        pub type AxProgram;
+        pub type AxProgramArgs;
+    }
+
+    define_api! {
+        @cfg "plugin";
+
+        /// Loads the program at the given path.
+        pub fn ax_load_program(path: &str) -> AxResult<AxProgram>;
+        /// Builds the arguments of the `main` function of a program.
+        ///
+        /// The environment variables in `envs` are in the form of `KEY=VALUE`.
+        pub fn ax_program_args(args: &[&str], envs: &[&str]) -> AxResult<AxProgramArgs>;
+        /// Runs the `main` function of the program in the current task, and
+        /// returns its exit code.
+        ///
+        /// With the `multitask` feature, a program calling `exit` ends the
+        /// current task with the exit code instead of returning.
+        pub fn ax_run_program(program: &AxProgram, args: &AxProgramArgs) -> AxResult<i32>;
     }
 }
 
@@ -406,6 +506,12 @@ pub mod modules {
     pub use axmm;
     #[cfg(feature = "net")]
     pub use axnet;
+    #[cfg(feature = "plugin")]
+    pub use axplugin;
+    #[cfg(feature = "prof")]
+    pub use axprof;
     #[cfg(feature = "multitask")]
     pub use axtask;
+    #[cfg(feature = "trace")]
+    pub use axtrace;
 }
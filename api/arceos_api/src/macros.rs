@@ -89,6 +89,10 @@ macro_rules! cfg_alloc {
     ($($item:item)*) => { _cfg_common!{ "alloc" $($item)* } }
 }
 
+macro_rules! cfg_paging {
+    ($($item:item)*) => { _cfg_common!{ "paging" $($item)* } }
+}
+
 macro_rules! cfg_dma {
     ($($item:item)*) => { _cfg_common!{ "dma" $($item)* } }
 }
@@ -105,6 +109,10 @@ macro_rules! cfg_display {
     ($($item:item)*) => { _cfg_common!{ "display" $($item)* } }
 }
 
+macro_rules! cfg_plugin {
+    ($($item:item)*) => { _cfg_common!{ "plugin" $($item)* } }
+}
+
 macro_rules! cfg_task {
     ($($item:item)*) => { _cfg_common!{ "multitask" $($item)* } }
 }
@@ -52,7 +52,19 @@ typedef struct {{
             "iovec",
             "clockid_t",
             "rlimit",
+            "ip_mreq",
             "aibuf",
+            "sigset_t",
+            "sigaction",
+            "siginfo_t",
+            "stack_t",
+            "itimerval",
+            "itimerspec",
+            "sigevent",
+            "timer_t",
+            "cpu_set_t",
+            "tcp_info",
+            "flock",
         ];
         let allow_vars = [
             "CLOCK_.*",
@@ -60,14 +72,39 @@ typedef struct {{
             "AF_.*",
             "SOCK_.*",
             "IPPROTO_.*",
+            "SOL_.*",
+            "SO_.*",
+            "TCP_.*",
+            "TCPI_.*",
+            "UDP_.*",
+            "IP_.*",
             "FD_.*",
             "F_.*",
             "_SC_.*",
             "EPOLL_CTL_.*",
             "EPOLL.*",
             "RLIMIT_.*",
+            "PRIO_.*",
             "EAI_.*",
+            "AI_.*",
+            "NI_.*",
             "MAXADDRS",
+            "SIG.*",
+            "SA_.*",
+            "SI_.*",
+            "SEGV_.*",
+            "BUS_.*",
+            "FPE_.*",
+            "ILL_.*",
+            "SS_.*",
+            "MINSIGSTKSZ",
+            "ITIMER_.*",
+            "TIMER_ABSTIME",
+            "CLONE_.*",
+            "PROT_.*",
+            "MAP_.*",
+            "XATTR_.*",
+            "LOCK_.*",
         ];
 
         #[derive(Debug)]
@@ -0,0 +1,44 @@
+use core::ffi::{c_char, c_int};
+
+use axerrno::LinuxError;
+use axruntime::env;
+
+use crate::utils::char_ptr_to_str;
+
+/// Returns the environment as a NULL-terminated array of `KEY=VALUE` strings.
+///
+/// The array is up to date until the next change of the environment, but it
+/// and the strings stay valid as long as the process lives.
+pub fn sys_environ() -> *mut *mut c_char {
+    env::c_environ() as _
+}
+
+/// Add or change an environment variable.
+///
+/// If `name` already exists, it's changed only if `overwrite` is nonzero.
+pub fn sys_setenv(name: *const c_char, value: *const c_char, overwrite: c_int) -> c_int {
+    syscall_body!(sys_setenv, {
+        let name = char_ptr_to_str(name).map_err(|_| LinuxError::EINVAL)?;
+        let value = char_ptr_to_str(value)?;
+        debug!("sys_setenv <= {:?} {:?} {}", name, value, overwrite);
+        if overwrite != 0 {
+            env::set_var(name, value)?;
+        } else {
+            env::set_var_if_absent(name, value)?;
+        }
+        Ok(0)
+    })
+}
+
+/// Remove an environment variable.
+pub fn sys_unsetenv(name: *const c_char) -> c_int {
+    syscall_body!(sys_unsetenv, {
+        let name = char_ptr_to_str(name).map_err(|_| LinuxError::EINVAL)?;
+        debug!("sys_unsetenv <= {:?}", name);
+        if !env::is_valid_key(name) {
+            return Err(LinuxError::EINVAL);
+        }
+        env::remove_var(name);
+        Ok(0)
+    })
+}
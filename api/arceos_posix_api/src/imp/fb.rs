@@ -0,0 +1,277 @@
+//! The framebuffer device `/dev/fb0`, on top of [`axdisplay`].
+//!
+//! It implements the part of the Linux fbdev interface that a linear 32-bit
+//! framebuffer needs:
+//!
+//! - `read`, `write` and `lseek` on the pixels, and `mmap` (see
+//!   [`sys_mmap`](super::mman::sys_mmap)).
+//! - `FBIOGET_VSCREENINFO` and `FBIOGET_FSCREENINFO` to get the resolution
+//!   and the layout of the framebuffer.
+//! - `FBIOPUT_VSCREENINFO` to change the resolution (only `xres`, `yres` and
+//!   `bits_per_pixel`, which must be 32). It fails with `EBUSY` while the
+//!   framebuffer is mapped, as it's reallocated.
+//! - `FBIOPAN_DISPLAY` to show the framebuffer on the screen.
+//!
+//! The display only shows what's in the framebuffer when it's flushed: after
+//! each `write`, and with `FBIOPAN_DISPLAY` after drawing to a mapping.
+
+use alloc::sync::Arc;
+use core::ffi::c_int;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use axerrno::{LinuxError, LinuxResult};
+use axhal::mem::{MemoryAddr, virt_to_phys};
+use axio::{PollState, SeekFrom};
+use spin::Mutex;
+
+use super::fd_ops::{FileLike, add_file_like};
+use crate::ctypes;
+
+/// The path of the framebuffer device.
+pub(crate) const FB_PATH: &str = "/dev/fb0";
+
+const FBIOGET_VSCREENINFO: u32 = 0x4600;
+const FBIOPUT_VSCREENINFO: u32 = 0x4601;
+const FBIOGET_FSCREENINFO: u32 = 0x4602;
+const FBIOPAN_DISPLAY: u32 = 0x4606;
+
+const FB_TYPE_PACKED_PIXELS: u32 = 0;
+const FB_VISUAL_TRUECOLOR: u32 = 2;
+const BITS_PER_PIXEL: u32 = 32;
+
+/// The number of mappings of the framebuffer.
+static MAPPINGS: AtomicUsize = AtomicUsize::new(0);
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+struct FbBitfield {
+    offset: u32,
+    length: u32,
+    msb_right: u32,
+}
+
+/// `struct fb_var_screeninfo`
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+struct FbVarScreeninfo {
+    xres: u32,
+    yres: u32,
+    xres_virtual: u32,
+    yres_virtual: u32,
+    xoffset: u32,
+    yoffset: u32,
+    bits_per_pixel: u32,
+    grayscale: u32,
+    red: FbBitfield,
+    green: FbBitfield,
+    blue: FbBitfield,
+    transp: FbBitfield,
+    nonstd: u32,
+    activate: u32,
+    height: u32,
+    width: u32,
+    accel_flags: u32,
+    pixclock: u32,
+    left_margin: u32,
+    right_margin: u32,
+    upper_margin: u32,
+    lower_margin: u32,
+    hsync_len: u32,
+    vsync_len: u32,
+    sync: u32,
+    vmode: u32,
+    rotate: u32,
+    colorspace: u32,
+    reserved: [u32; 4],
+}
+
+/// `struct fb_fix_screeninfo`
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+struct FbFixScreeninfo {
+    id: [u8; 16],
+    smem_start: usize,
+    smem_len: u32,
+    type_: u32,
+    type_aux: u32,
+    visual: u32,
+    xpanstep: u16,
+    ypanstep: u16,
+    ywrapstep: u16,
+    line_length: u32,
+    mmio_start: usize,
+    mmio_len: u32,
+    accel: u32,
+    capabilities: u16,
+    reserved: [u16; 2],
+}
+
+fn var_screeninfo(info: &axdisplay::DisplayInfo) -> FbVarScreeninfo {
+    let bitfield = |offset| FbBitfield {
+        offset,
+        length: 8,
+        msb_right: 0,
+    };
+    FbVarScreeninfo {
+        xres: info.width,
+        yres: info.height,
+        xres_virtual: info.width,
+        yres_virtual: info.height,
+        bits_per_pixel: BITS_PER_PIXEL,
+        // BGRX in memory
+        red: bitfield(16),
+        green: bitfield(8),
+        blue: bitfield(0),
+        ..Default::default()
+    }
+}
+
+fn fix_screeninfo(info: &axdisplay::DisplayInfo) -> FbFixScreeninfo {
+    let mut id = [0; 16];
+    id[..10].copy_from_slice(b"virtio_gpu");
+    FbFixScreeninfo {
+        id,
+        smem_start: virt_to_phys(info.fb_base_vaddr.into()).as_usize(),
+        smem_len: info.fb_size as u32,
+        type_: FB_TYPE_PACKED_PIXELS,
+        visual: FB_VISUAL_TRUECOLOR,
+        line_length: info.width * BITS_PER_PIXEL / 8,
+        ..Default::default()
+    }
+}
+
+/// A file opened from `/dev/fb0`.
+pub(crate) struct Framebuffer {
+    pos: Mutex<usize>,
+}
+
+impl Framebuffer {
+    /// Sets the position of the next `read` or `write`.
+    pub(crate) fn seek(&self, pos: SeekFrom) -> LinuxResult<u64> {
+        let size = axdisplay::framebuffer_info().fb_size as i64;
+        let mut cur = self.pos.lock();
+        let new = match pos {
+            SeekFrom::Start(off) => off as i64,
+            SeekFrom::Current(off) => *cur as i64 + off,
+            SeekFrom::End(off) => size + off,
+        };
+        if new < 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        *cur = new as usize;
+        Ok(new as u64)
+    }
+
+    fn set_var_screeninfo(&self, var: &FbVarScreeninfo) -> LinuxResult {
+        if var.bits_per_pixel != 0 && var.bits_per_pixel != BITS_PER_PIXEL {
+            return Err(LinuxError::EINVAL);
+        }
+        let info = axdisplay::framebuffer_info();
+        if (var.xres, var.yres) == (info.width, info.height) {
+            return Ok(());
+        }
+        if MAPPINGS.load(Ordering::Acquire) > 0 {
+            return Err(LinuxError::EBUSY);
+        }
+        axdisplay::framebuffer_set_resolution(var.xres, var.yres)?;
+        Ok(())
+    }
+}
+
+impl FileLike for Framebuffer {
+    fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        let info = axdisplay::framebuffer_info();
+        let mut pos = self.pos.lock();
+        let len = buf.len().min(info.fb_size.saturating_sub(*pos));
+        let src = (info.fb_base_vaddr + *pos) as *const u8;
+        unsafe { src.copy_to_nonoverlapping(buf.as_mut_ptr(), len) };
+        *pos += len;
+        Ok(len)
+    }
+
+    fn write(&self, buf: &[u8]) -> LinuxResult<usize> {
+        let info = axdisplay::framebuffer_info();
+        let mut pos = self.pos.lock();
+        if *pos >= info.fb_size && !buf.is_empty() {
+            return Err(LinuxError::ENOSPC);
+        }
+        let len = buf.len().min(info.fb_size - *pos);
+        let dst = (info.fb_base_vaddr + *pos) as *mut u8;
+        unsafe { dst.copy_from_nonoverlapping(buf.as_ptr(), len) };
+        *pos += len;
+        drop(pos);
+        axdisplay::framebuffer_flush();
+        Ok(len)
+    }
+
+    fn stat(&self) -> LinuxResult<ctypes::stat> {
+        let st_mode = 0o20000 | 0o660u32; // S_IFCHR | rw-rw----
+        Ok(ctypes::stat {
+            st_ino: 1,
+            st_nlink: 1,
+            st_mode,
+            st_size: axdisplay::framebuffer_info().fb_size as _,
+            ..Default::default()
+        })
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn core::any::Any + Send + Sync> {
+        self
+    }
+
+    fn poll(&self) -> LinuxResult<PollState> {
+        Ok(PollState {
+            readable: true,
+            writable: true,
+        })
+    }
+
+    fn set_nonblocking(&self, _nonblocking: bool) -> LinuxResult {
+        Ok(())
+    }
+
+    fn ioctl(&self, cmd: u32, arg: usize) -> LinuxResult<c_int> {
+        if arg == 0 && cmd != FBIOPAN_DISPLAY {
+            return Err(LinuxError::EFAULT);
+        }
+        match cmd {
+            FBIOGET_VSCREENINFO => {
+                let var = var_screeninfo(&axdisplay::framebuffer_info());
+                unsafe { (arg as *mut FbVarScreeninfo).write(var) };
+            }
+            FBIOPUT_VSCREENINFO => {
+                let var = unsafe { (arg as *const FbVarScreeninfo).read() };
+                self.set_var_screeninfo(&var)?;
+                // the actual settings are returned
+                let var = var_screeninfo(&axdisplay::framebuffer_info());
+                unsafe { (arg as *mut FbVarScreeninfo).write(var) };
+            }
+            FBIOGET_FSCREENINFO => {
+                let fix = fix_screeninfo(&axdisplay::framebuffer_info());
+                unsafe { (arg as *mut FbFixScreeninfo).write(fix) };
+            }
+            FBIOPAN_DISPLAY => axdisplay::framebuffer_flush(),
+            _ => return Err(LinuxError::ENOTTY),
+        }
+        Ok(0)
+    }
+
+    fn mmap(&self, offset: usize, len: usize) -> LinuxResult<axhal::mem::PhysAddr> {
+        let info = axdisplay::framebuffer_info();
+        match offset.checked_add(len) {
+            Some(end) if end <= info.fb_size.align_up_4k() => {}
+            _ => return Err(LinuxError::EINVAL),
+        }
+        MAPPINGS.fetch_add(1, Ordering::AcqRel);
+        Ok(virt_to_phys((info.fb_base_vaddr + offset).into()))
+    }
+
+    fn munmap(&self) {
+        MAPPINGS.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// Opens the framebuffer.
+pub(crate) fn open() -> LinuxResult<c_int> {
+    add_file_like(Arc::new(Framebuffer { pos: Mutex::new(0) }))
+}
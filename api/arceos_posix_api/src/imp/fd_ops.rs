@@ -1,4 +1,4 @@
-use alloc::sync::Arc;
+use alloc::{sync::Arc, vec::Vec};
 use core::ffi::c_int;
 
 use axerrno::{LinuxError, LinuxResult};
@@ -8,10 +8,14 @@ use flatten_objects::FlattenObjects;
 use spin::RwLock;
 
 use crate::ctypes;
+use crate::imp::resources::nofile_limit;
 use crate::imp::stdio::{stdin, stdout};
 
 pub const AX_FILE_LIMIT: usize = 1024;
 
+pub(crate) const FIONREAD: u32 = 0x541B;
+const FIONBIO: u32 = 0x5421;
+
 #[allow(dead_code)]
 pub trait FileLike: Send + Sync {
     fn read(&self, buf: &mut [u8]) -> LinuxResult<usize>;
@@ -20,15 +24,39 @@ pub trait FileLike: Send + Sync {
     fn into_any(self: Arc<Self>) -> Arc<dyn core::any::Any + Send + Sync>;
     fn poll(&self) -> LinuxResult<PollState>;
     fn set_nonblocking(&self, nonblocking: bool) -> LinuxResult;
+
+    /// Performs a device-specific control operation.
+    ///
+    /// `FIONBIO` is handled by [`sys_ioctl`] with
+    /// [`set_nonblocking`](Self::set_nonblocking).
+    fn ioctl(&self, _cmd: u32, _arg: usize) -> LinuxResult<c_int> {
+        Err(LinuxError::ENOTTY)
+    }
+
+    /// Returns the physical address of the memory at `offset` of the file,
+    /// `len` bytes long, to be mapped by `mmap`.
+    ///
+    /// The memory must stay there until the matching [`munmap`](Self::munmap).
+    #[cfg(feature = "mmap")]
+    fn mmap(&self, _offset: usize, _len: usize) -> LinuxResult<axhal::mem::PhysAddr> {
+        Err(LinuxError::ENODEV)
+    }
+
+    /// Called when a mapping returned by [`mmap`](Self::mmap) is removed.
+    #[cfg(feature = "mmap")]
+    fn munmap(&self) {}
 }
 
+/// A file descriptor table, which may be shared by several tasks.
+pub type FdTable = RwLock<FlattenObjects<Arc<dyn FileLike>, AX_FILE_LIMIT>>;
+
 def_resource! {
-    pub static FD_TABLE: ResArc<RwLock<FlattenObjects<Arc<dyn FileLike>, AX_FILE_LIMIT>>> = ResArc::new();
+    pub static FD_TABLE: ResArc<FdTable> = ResArc::new();
 }
 
 impl FD_TABLE {
     /// Return a copy of the inner table.
-    pub fn copy_inner(&self) -> RwLock<FlattenObjects<Arc<dyn FileLike>, AX_FILE_LIMIT>> {
+    pub fn copy_inner(&self) -> FdTable {
         let table = self.read();
         let mut new_table = FlattenObjects::new();
         for id in table.ids() {
@@ -48,8 +76,18 @@ pub fn get_file_like(fd: c_int) -> LinuxResult<Arc<dyn FileLike>> {
 }
 
 /// Add a file to the file descriptor table.
+///
+/// Fails with `EMFILE` if the new file descriptor would reach `RLIMIT_NOFILE`.
 pub fn add_file_like(f: Arc<dyn FileLike>) -> LinuxResult<c_int> {
-    Ok(FD_TABLE.write().add(f).map_err(|_| LinuxError::EMFILE)? as c_int)
+    let mut table = FD_TABLE.write();
+    let fd = table.add(f).map_err(|_| LinuxError::EMFILE)?;
+    if fd >= nofile_limit() {
+        table.remove(fd);
+        return Err(LinuxError::EMFILE);
+    }
+    #[cfg(feature = "multitask")]
+    axtask::account_open_files(1);
+    Ok(fd as c_int)
 }
 
 /// Close a file by `fd`.
@@ -58,10 +96,94 @@ pub fn close_file_like(fd: c_int) -> LinuxResult {
         .write()
         .remove(fd as usize)
         .ok_or(LinuxError::EBADF)?;
+    #[cfg(feature = "fs")]
+    super::file_lock::close_file(&f);
     drop(f);
+    #[cfg(feature = "multitask")]
+    axtask::account_open_files(-1);
     Ok(())
 }
 
+/// Files taken out of a file descriptor table to be installed into another
+/// one, the equivalent of an `SCM_RIGHTS` message.
+///
+/// The files stay open while in transit, even if the sender closes its file
+/// descriptors in the meantime.
+pub struct FileTransfer {
+    files: Vec<Arc<dyn FileLike>>,
+}
+
+impl FileTransfer {
+    /// Takes the files referred to by `fds` in the current fd table.
+    pub fn from_fds(fds: &[c_int]) -> LinuxResult<Self> {
+        Self::from_table(&FD_TABLE, fds)
+    }
+
+    /// Takes the files referred to by `fds` in the given fd table.
+    ///
+    /// Fails with `EBADF` if any of the file descriptors is not open.
+    pub fn from_table(table: &FdTable, fds: &[c_int]) -> LinuxResult<Self> {
+        let table = table.read();
+        let files = fds
+            .iter()
+            .map(|&fd| {
+                let fd = usize::try_from(fd).map_err(|_| LinuxError::EBADF)?;
+                table.get(fd).cloned().ok_or(LinuxError::EBADF)
+            })
+            .collect::<LinuxResult<_>>()?;
+        Ok(Self { files })
+    }
+
+    /// Returns the number of files in transit.
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    /// Returns `true` if there are no files in transit.
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Installs the files into the current fd table, returns the new file
+    /// descriptors in the original order.
+    pub fn install(self) -> LinuxResult<Vec<c_int>> {
+        self.install_into(&FD_TABLE)
+    }
+
+    /// Installs the files into the given fd table, returns the new file
+    /// descriptors in the original order.
+    ///
+    /// Either all files are installed, or none of them and `EMFILE` is
+    /// returned, in which case the files are closed.
+    pub fn install_into(self, table: &FdTable) -> LinuxResult<Vec<c_int>> {
+        let mut table = table.write();
+        if table.count() + self.files.len() > AX_FILE_LIMIT {
+            return Err(LinuxError::EMFILE);
+        }
+        let fds: Vec<usize> = self
+            .files
+            .into_iter()
+            .map(|f| table.add(f).unwrap_or_else(|_| unreachable!()))
+            .collect();
+        if fds.iter().any(|&fd| fd >= nofile_limit()) {
+            for fd in fds {
+                table.remove(fd);
+            }
+            return Err(LinuxError::EMFILE);
+        }
+        Ok(fds.into_iter().map(|fd| fd as c_int).collect())
+    }
+}
+
+/// Duplicates the files referred to by `fds` in the current fd table into the
+/// `target` fd table, returns the new file descriptors in `target`.
+///
+/// `target` is usually obtained by [`ResArc::share`] on the [`FD_TABLE`] of
+/// another task.
+pub fn send_file_likes(target: &FdTable, fds: &[c_int]) -> LinuxResult<Vec<c_int>> {
+    FileTransfer::from_fds(fds)?.install_into(target)
+}
+
 /// Close a file by `fd`.
 pub fn sys_close(fd: c_int) -> c_int {
     debug!("sys_close <= {}", fd);
@@ -97,7 +219,7 @@ pub fn sys_dup2(old_fd: c_int, new_fd: c_int) -> c_int {
                 return Ok(r);
             }
         }
-        if new_fd as usize >= AX_FILE_LIMIT {
+        if new_fd < 0 || new_fd as usize >= nofile_limit() {
             return Err(LinuxError::EBADF);
         }
 
@@ -130,6 +252,10 @@ pub fn sys_fcntl(fd: c_int, cmd: c_int, arg: usize) -> c_int {
                 get_file_like(fd)?.set_nonblocking(arg & (ctypes::O_NONBLOCK as usize) > 0)?;
                 Ok(0)
             }
+            #[cfg(feature = "fs")]
+            ctypes::F_GETLK | ctypes::F_SETLK | ctypes::F_SETLKW => {
+                super::file_lock::fcntl_lock(fd, cmd as u32, arg)
+            }
             _ => {
                 warn!("unsupported fcntl parameters: cmd {}", cmd);
                 Ok(0)
@@ -138,6 +264,67 @@ pub fn sys_fcntl(fd: c_int, cmd: c_int, arg: usize) -> c_int {
     })
 }
 
+/// Control a device.
+pub fn sys_ioctl(fd: c_int, request: c_int, arg: usize) -> c_int {
+    debug!(
+        "sys_ioctl <= fd: {} request: {:#x} arg: {:#x}",
+        fd, request, arg
+    );
+    syscall_body!(sys_ioctl, {
+        let f = get_file_like(fd)?;
+        match request as u32 {
+            FIONBIO => {
+                f.set_nonblocking(ioctl_read_int(arg)? != 0)?;
+                Ok(0)
+            }
+            cmd => f.ioctl(cmd, arg),
+        }
+    })
+}
+
+/// Checks that the `len` bytes at `arg`, the argument of an `ioctl`, are
+/// mapped and can be read, or written if `write`. Fails with `EFAULT`
+/// otherwise.
+pub(crate) fn check_ioctl_arg(arg: usize, len: usize, write: bool) -> LinuxResult {
+    let end = arg.checked_add(len).ok_or(LinuxError::EFAULT)?;
+    if arg == 0 {
+        return Err(LinuxError::EFAULT);
+    }
+    #[cfg(feature = "paging")]
+    {
+        use axhal::paging::MappingFlags;
+        let flags = if write {
+            MappingFlags::WRITE
+        } else {
+            MappingFlags::READ
+        };
+        let range = memory_addr::VirtAddrRange::new(arg.into(), end.into());
+        if !axmm::kernel_aspace()
+            .lock()
+            .check_region_access(range, flags)
+        {
+            return Err(LinuxError::EFAULT);
+        }
+    }
+    #[cfg(not(feature = "paging"))]
+    let _ = (end, write);
+    Ok(())
+}
+
+/// Reads the integer argument of an `ioctl` (e.g. `FIONBIO`) at `arg`.
+pub(crate) fn ioctl_read_int(arg: usize) -> LinuxResult<c_int> {
+    check_ioctl_arg(arg, size_of::<c_int>(), false)?;
+    Ok(unsafe { (arg as *const c_int).read_unaligned() })
+}
+
+/// Stores the integer result of an `ioctl` (e.g. `FIONREAD`) at `arg`.
+pub(crate) fn ioctl_write_int(arg: usize, value: usize) -> LinuxResult<c_int> {
+    check_ioctl_arg(arg, size_of::<c_int>(), true)?;
+    let value = value.min(c_int::MAX as usize) as c_int;
+    unsafe { (arg as *mut c_int).write_unaligned(value) };
+    Ok(0)
+}
+
 #[ctor_bare::register_ctor]
 fn init_stdio() {
     let mut fd_table = flatten_objects::FlattenObjects::new();
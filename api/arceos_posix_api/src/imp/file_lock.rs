@@ -0,0 +1,518 @@
+//! Advisory file locks: the whole-file locks of `flock` and the record locks
+//! of `fcntl`.
+//!
+//! The files are identified by a [`FileId`] shared by their open files, as the
+//! filesystems don't have stable inode numbers: the files opened on the same
+//! node, or by the same canonical path while the file is open, share it. So
+//! the locks follow a file renamed or unlinked while it's open. The two kinds
+//! of locks don't interact, as on Linux.
+//!
+//! A `flock` lock belongs to an open file description, shared by the
+//! duplicated file descriptors, and is released when the last of them is
+//! closed. A record lock belongs to a task (a process, see `getpid`), and all
+//! its record locks on a file are released when it closes any descriptor of
+//! the file.
+//!
+//! The blocked tasks wait for any lock to be released, then try again. A
+//! `F_SETLKW` that would wait for a task waiting for the caller, directly or
+//! not, fails with `EDEADLK` instead.
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::{Arc, Weak};
+use alloc::{vec, vec::Vec};
+use core::ffi::c_int;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use axerrno::{LinuxError, LinuxResult};
+use axfs::fops::NodeId;
+use axio::SeekFrom;
+use spin::Mutex;
+
+use super::fd_ops::FileLike;
+use super::fs::File;
+use crate::ctypes;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LockKind {
+    Shared,
+    Exclusive,
+}
+
+impl LockKind {
+    fn conflicts(self, other: Self) -> bool {
+        self == Self::Exclusive || other == Self::Exclusive
+    }
+}
+
+/// The identity of a file, shared by its open files, which keys its locks.
+pub(crate) struct FileId {
+    /// The node the file was first opened on.
+    node: NodeId,
+    /// The canonical path the file was first opened by.
+    path: String,
+}
+
+/// The identities of the open files, by node and by canonical path.
+struct FileIds {
+    by_node: BTreeMap<NodeId, Weak<FileId>>,
+    by_path: BTreeMap<String, Weak<FileId>>,
+}
+
+static FILE_IDS: Mutex<FileIds> = Mutex::new(FileIds {
+    by_node: BTreeMap::new(),
+    by_path: BTreeMap::new(),
+});
+
+/// Returns the identity of the file opened on `node` by `path`.
+///
+/// The files opened on the same node share it, e.g. by the hard links of a
+/// ramfs file. The filesystems creating a node on each lookup (e.g. FAT) give
+/// different nodes to the opens of a file, which are matched by path instead.
+pub(crate) fn file_id(node: NodeId, path: &str) -> Arc<FileId> {
+    let path = axfs::api::canonicalize(path).unwrap_or_else(|_| path.into());
+    let mut ids = FILE_IDS.lock();
+    let found = [ids.by_node.get(&node), ids.by_path.get(&path)]
+        .into_iter()
+        .flatten()
+        .find_map(Weak::upgrade);
+    if let Some(id) = found {
+        return id;
+    }
+    let id = Arc::new(FileId { node, path });
+    ids.by_node.insert(id.node.clone(), Arc::downgrade(&id));
+    ids.by_path.insert(id.path.clone(), Arc::downgrade(&id));
+    id
+}
+
+impl FileId {
+    fn key(&self) -> usize {
+        self as *const Self as usize
+    }
+}
+
+impl Drop for FileId {
+    /// Forgets the file once all its open files are closed, along with the
+    /// locks left on it, so that its key can be reused.
+    fn drop(&mut self) {
+        {
+            let this: *const Self = self;
+            let mut ids = FILE_IDS.lock();
+            if ids
+                .by_node
+                .get(&self.node)
+                .is_some_and(|id| id.as_ptr() == this)
+            {
+                ids.by_node.remove(&self.node);
+            }
+            if ids
+                .by_path
+                .get(&self.path)
+                .is_some_and(|id| id.as_ptr() == this)
+            {
+                ids.by_path.remove(&self.path);
+            }
+        }
+        let mut table = LOCKS.lock();
+        if table.files.remove(&self.key()).is_some() {
+            wake_waiters();
+        }
+    }
+}
+
+/// A record lock of the bytes `start..end`, up to the end of the file if
+/// `end` is `u64::MAX`.
+#[derive(Debug, Clone, Copy)]
+struct Record {
+    pid: u64,
+    kind: LockKind,
+    start: u64,
+    end: u64,
+}
+
+impl Record {
+    fn overlaps(&self, start: u64, end: u64) -> bool {
+        self.start < end && start < self.end
+    }
+
+    fn conflicts(&self, other: &Record) -> bool {
+        self.pid != other.pid
+            && self.overlaps(other.start, other.end)
+            && self.kind.conflicts(other.kind)
+    }
+
+    fn write_to(&self, fl: &mut ctypes::flock) {
+        fl.l_type = match self.kind {
+            LockKind::Shared => ctypes::F_RDLCK,
+            LockKind::Exclusive => ctypes::F_WRLCK,
+        } as _;
+        fl.l_whence = 0; // SEEK_SET
+        fl.l_start = self.start as _;
+        fl.l_len = match self.end {
+            u64::MAX => 0,
+            end => (end - self.start) as _,
+        };
+        fl.l_pid = self.pid as _;
+    }
+}
+
+/// The locks of a file.
+#[derive(Default)]
+struct FileLocks {
+    /// The `flock` locks, by owning open file description.
+    flocks: Vec<(usize, LockKind)>,
+    records: Vec<Record>,
+}
+
+impl FileLocks {
+    fn is_empty(&self) -> bool {
+        self.flocks.is_empty() && self.records.is_empty()
+    }
+
+    fn flock_conflicts(&self, owner: usize, kind: LockKind) -> bool {
+        self.flocks
+            .iter()
+            .any(|&(other, other_kind)| other != owner && other_kind.conflicts(kind))
+    }
+
+    fn record_conflict(&self, lock: &Record) -> Option<&Record> {
+        self.records.iter().find(|r| r.conflicts(lock))
+    }
+
+    /// Removes the bytes `start..end` from the record locks of `pid`,
+    /// splitting the locks if needed.
+    ///
+    /// Returns `true` if any lock has been changed.
+    fn unlock_records(&mut self, pid: u64, start: u64, end: u64) -> bool {
+        let len = self.records.len();
+        let mut rest = Vec::new();
+        self.records.retain(|r| {
+            if r.pid != pid || !r.overlaps(start, end) {
+                return true;
+            }
+            if r.start < start {
+                rest.push(Record { end: start, ..*r });
+            }
+            if end < r.end {
+                rest.push(Record { start: end, ..*r });
+            }
+            false
+        });
+        let changed = self.records.len() != len;
+        self.records.extend(rest);
+        changed
+    }
+}
+
+struct LockTable {
+    /// The locks of the files, by the keys of their [`FileId`]s.
+    files: BTreeMap<usize, FileLocks>,
+    /// The record locks the blocked tasks wait for, by task.
+    waiting: BTreeMap<u64, (usize, Record)>,
+}
+
+impl LockTable {
+    /// Calls `f` on the locks of `key`, removed if they become empty.
+    fn update<R>(&mut self, key: usize, f: impl FnOnce(&mut FileLocks) -> R) -> Option<R> {
+        let locks = self.files.get_mut(&key)?;
+        let res = f(locks);
+        if locks.is_empty() {
+            self.files.remove(&key);
+        }
+        Some(res)
+    }
+
+    /// Returns `true` if the task of `lock` waiting for it would deadlock,
+    /// i.e. a task holding a conflicting lock waits, directly or not, for a
+    /// lock of that task.
+    fn would_deadlock(&self, key: usize, lock: &Record) -> bool {
+        let mut visited = Vec::new();
+        let mut pending = vec![(key, *lock)];
+        while let Some((key, wanted)) = pending.pop() {
+            let Some(locks) = self.files.get(&key) else {
+                continue;
+            };
+            for holder in locks.records.iter().filter(|r| r.conflicts(&wanted)) {
+                if holder.pid == lock.pid {
+                    return true;
+                }
+                if !visited.contains(&holder.pid) {
+                    visited.push(holder.pid);
+                    if let Some(&(key, wanted)) = self.waiting.get(&holder.pid) {
+                        pending.push((key, wanted));
+                    }
+                }
+            }
+        }
+        false
+    }
+}
+
+static LOCKS: Mutex<LockTable> = Mutex::new(LockTable {
+    files: BTreeMap::new(),
+    waiting: BTreeMap::new(),
+});
+
+/// Incremented whenever a lock is released, for the blocked tasks to try
+/// again.
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+#[cfg(feature = "multitask")]
+static LOCK_WQ: axtask::WaitQueue = axtask::WaitQueue::new();
+
+/// Wakes up the blocked tasks, after a lock has been released.
+///
+/// It must be called with the lock table locked.
+fn wake_waiters() {
+    GENERATION.fetch_add(1, Ordering::Release);
+    #[cfg(feature = "multitask")]
+    LOCK_WQ.notify_all(false);
+}
+
+/// Blocks until a lock is released after `generation`, or the task is
+/// interrupted by a signal.
+#[cfg(feature = "multitask")]
+fn wait_release(generation: u64) -> LinuxResult {
+    let released = || GENERATION.load(Ordering::Acquire) != generation;
+    match LOCK_WQ.wait_until_interruptible(released) {
+        axtask::WaitResult::Interrupted => super::signal::check_interrupt(true),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(not(feature = "multitask"))]
+fn wait_release(_generation: u64) -> LinuxResult {
+    // there is no other task to release it
+    Err(LinuxError::EDEADLK)
+}
+
+/// Calls `try_lock` until it takes the lock and returns `true`, waiting for
+/// a lock to be released between the attempts if `wait`, or fails with
+/// `EAGAIN` otherwise.
+fn acquire(
+    wait: bool,
+    mut try_lock: impl FnMut(&mut LockTable) -> LinuxResult<bool>,
+) -> LinuxResult {
+    loop {
+        let generation = {
+            let mut table = LOCKS.lock();
+            if try_lock(&mut table)? {
+                return Ok(());
+            }
+            if !wait {
+                return Err(LinuxError::EAGAIN);
+            }
+            GENERATION.load(Ordering::Acquire)
+        };
+        wait_release(generation)?;
+    }
+}
+
+fn current_pid() -> u64 {
+    #[cfg(feature = "multitask")]
+    {
+        axtask::current().id().as_u64()
+    }
+    #[cfg(not(feature = "multitask"))]
+    {
+        2 // `main` task ID
+    }
+}
+
+fn key_of(file: &File) -> usize {
+    file.file_id().key()
+}
+
+/// The open file description, owning the `flock` locks.
+fn owner_of(file: &File) -> usize {
+    file as *const File as usize
+}
+
+/// Applies or removes a `flock` lock on the open file `file`.
+///
+/// An existing lock is released before being converted, so other tasks may
+/// take the lock in the meantime.
+pub(crate) fn flock(file: &File, operation: c_int) -> LinuxResult {
+    let operation = operation as u32;
+    let owner = owner_of(file);
+    let key = key_of(file);
+    let kind = match operation & !ctypes::LOCK_NB {
+        ctypes::LOCK_SH => LockKind::Shared,
+        ctypes::LOCK_EX => LockKind::Exclusive,
+        ctypes::LOCK_UN => {
+            release_flocks(file);
+            return Ok(());
+        }
+        _ => return Err(LinuxError::EINVAL),
+    };
+    release_flocks(file);
+    acquire(operation & ctypes::LOCK_NB == 0, |table| {
+        if let Some(locks) = table.files.get(&key) {
+            if locks.flock_conflicts(owner, kind) {
+                return Ok(false);
+            }
+        }
+        table
+            .files
+            .entry(key)
+            .or_default()
+            .flocks
+            .push((owner, kind));
+        Ok(true)
+    })
+}
+
+/// Releases the `flock` lock of the open file `file`, if any.
+///
+/// It's called when the last descriptor of the file is closed.
+pub(crate) fn release_flocks(file: &File) {
+    let owner = owner_of(file);
+    let mut table = LOCKS.lock();
+    let released = table.update(key_of(file), |locks| {
+        let len = locks.flocks.len();
+        locks.flocks.retain(|&(other, _)| other != owner);
+        locks.flocks.len() != len
+    });
+    if released == Some(true) {
+        wake_waiters();
+    }
+}
+
+/// Releases the record locks of the current task on the file of a closed
+/// descriptor.
+pub(crate) fn close_file(f: &Arc<dyn FileLike>) {
+    let Ok(file) = f.clone().into_any().downcast::<File>() else {
+        return;
+    };
+    let mut table = LOCKS.lock();
+    if table.update(key_of(&file), |locks| {
+        locks.unlock_records(current_pid(), 0, u64::MAX)
+    }) == Some(true)
+    {
+        wake_waiters();
+    }
+}
+
+/// Returns the bytes `start..end` of `file` described by `fl`.
+fn record_range(file: &File, fl: &ctypes::flock) -> LinuxResult<(u64, u64)> {
+    let base = match fl.l_whence {
+        0 => 0,                                                      // SEEK_SET
+        1 => file.inner().lock().seek(SeekFrom::Current(0))? as i64, // SEEK_CUR
+        2 => file.inner().lock().get_attr()?.size() as i64,          // SEEK_END
+        _ => return Err(LinuxError::EINVAL),
+    };
+    let start = base
+        .checked_add(i64::from(fl.l_start))
+        .ok_or(LinuxError::EOVERFLOW)?;
+    let len = i64::from(fl.l_len);
+    let (start, end) = if len >= 0 {
+        (start, start.checked_add(len).ok_or(LinuxError::EOVERFLOW)?)
+    } else {
+        (start + len, start)
+    };
+    if start < 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    Ok((start as u64, if len == 0 { u64::MAX } else { end as u64 }))
+}
+
+/// Takes the record lock `lock` on `key`, after releasing the bytes it
+/// covers from the other locks of the task.
+fn set_record(key: usize, lock: Record, wait: bool) -> LinuxResult {
+    let res = acquire(wait, |table| {
+        if table
+            .files
+            .get(&key)
+            .is_some_and(|locks| locks.record_conflict(&lock).is_some())
+        {
+            if wait {
+                if table.would_deadlock(key, &lock) {
+                    return Err(LinuxError::EDEADLK);
+                }
+                table.waiting.insert(lock.pid, (key, lock));
+            }
+            return Ok(false);
+        }
+        let locks = table.files.entry(key).or_default();
+        // converting a lock releases its bytes for the other kind of lock
+        if locks.unlock_records(lock.pid, lock.start, lock.end) {
+            wake_waiters();
+        }
+        locks.records.push(lock);
+        Ok(true)
+    });
+    if wait {
+        LOCKS.lock().waiting.remove(&lock.pid);
+    }
+    res
+}
+
+/// Handles the `F_GETLK`, `F_SETLK` and `F_SETLKW` commands of `fcntl` on
+/// `fd`, `arg` pointing to a `struct flock`.
+pub(crate) fn fcntl_lock(fd: c_int, cmd: u32, arg: usize) -> LinuxResult<c_int> {
+    let fl = arg as *mut ctypes::flock;
+    if fl.is_null() {
+        return Err(LinuxError::EFAULT);
+    }
+    // Safety: checked above, the caller provides a valid `struct flock`.
+    let fl = unsafe { &mut *fl };
+    let file = File::from_fd(fd)?;
+    let key = key_of(&file);
+    let (start, end) = record_range(&file, fl)?;
+    let pid = current_pid();
+    let kind = match fl.l_type as u32 {
+        ctypes::F_RDLCK => Some(LockKind::Shared),
+        ctypes::F_WRLCK => Some(LockKind::Exclusive),
+        ctypes::F_UNLCK => None,
+        _ => return Err(LinuxError::EINVAL),
+    };
+
+    match (cmd, kind) {
+        (ctypes::F_GETLK, Some(kind)) => {
+            let lock = Record {
+                pid,
+                kind,
+                start,
+                end,
+            };
+            let table = LOCKS.lock();
+            match table
+                .files
+                .get(&key)
+                .and_then(|locks| locks.record_conflict(&lock))
+            {
+                Some(holder) => holder.write_to(fl),
+                None => fl.l_type = ctypes::F_UNLCK as _,
+            }
+        }
+        (ctypes::F_GETLK, None) => return Err(LinuxError::EINVAL),
+        (_, Some(kind)) => {
+            let lock = Record {
+                pid,
+                kind,
+                start,
+                end,
+            };
+            set_record(key, lock, cmd == ctypes::F_SETLKW)?;
+        }
+        (_, None) => {
+            let mut table = LOCKS.lock();
+            if table.update(key, |locks| locks.unlock_records(pid, start, end)) == Some(true) {
+                wake_waiters();
+            }
+        }
+    }
+    Ok(0)
+}
+
+/// Applies or removes an advisory lock on the open file `fd`.
+///
+/// `operation` is one of `LOCK_SH`, `LOCK_EX` or `LOCK_UN`, with `LOCK_NB`
+/// not to block if the lock is held by another open file.
+pub fn sys_flock(fd: c_int, operation: c_int) -> c_int {
+    debug!("sys_flock <= fd: {} operation: {}", fd, operation);
+    syscall_body!(sys_flock, {
+        flock(&File::from_fd(fd)?, operation)?;
+        Ok(0)
+    })
+}
@@ -1,13 +1,16 @@
 use alloc::string::{String, ToString};
 use alloc::sync::Arc;
-use core::ffi::{c_char, c_int};
+use alloc::vec;
+use core::ffi::{c_char, c_int, c_uint, c_void};
 
-use axerrno::{LinuxError, LinuxResult};
+use axerrno::{AxError, LinuxError, LinuxResult};
+use axfs::api::XattrSetMode;
 use axfs::fops::OpenOptions;
 use axio::{PollState, SeekFrom};
 use axsync::Mutex;
 
-use super::fd_ops::{FileLike, get_file_like};
+use super::fd_ops::{FIONREAD, FileLike, get_file_like, ioctl_write_int};
+use super::file_lock::{self, FileId};
 use crate::AT_FDCWD;
 use crate::{ctypes, utils::char_ptr_to_str};
 
@@ -15,13 +18,16 @@ use crate::{ctypes, utils::char_ptr_to_str};
 pub struct File {
     inner: Mutex<axfs::fops::File>,
     path: String,
+    id: Arc<FileId>,
 }
 
 impl File {
     fn new(inner: axfs::fops::File, path: String) -> Self {
+        let id = file_lock::file_id(inner.node_id(), &path);
         Self {
             inner: Mutex::new(inner),
             path,
+            id,
         }
     }
 
@@ -29,7 +35,7 @@ impl File {
         super::fd_ops::add_file_like(Arc::new(self))
     }
 
-    fn from_fd(fd: c_int) -> LinuxResult<Arc<Self>> {
+    pub(crate) fn from_fd(fd: c_int) -> LinuxResult<Arc<Self>> {
         let f = super::fd_ops::get_file_like(fd)?;
         f.into_any()
             .downcast::<Self>()
@@ -45,6 +51,17 @@ impl File {
     pub fn inner(&self) -> &Mutex<axfs::fops::File> {
         &self.inner
     }
+
+    /// Get the identity of the file, which keys its locks.
+    pub(crate) fn file_id(&self) -> &FileId {
+        &self.id
+    }
+}
+
+impl Drop for File {
+    fn drop(&mut self) {
+        file_lock::release_flocks(self);
+    }
 }
 
 impl FileLike for File {
@@ -53,6 +70,12 @@ impl FileLike for File {
     }
 
     fn write(&self, buf: &[u8]) -> LinuxResult<usize> {
+        if let Some(res) = write_proc_sys_vm(&self.path, buf) {
+            return res;
+        }
+        if let Some(res) = write_randomize_va_space(&self.path, buf) {
+            return res;
+        }
         Ok(self.inner.lock().write(buf)?)
     }
 
@@ -88,6 +111,70 @@ impl FileLike for File {
     fn set_nonblocking(&self, _nonblocking: bool) -> LinuxResult {
         Ok(())
     }
+
+    fn ioctl(&self, cmd: u32, arg: usize) -> LinuxResult<c_int> {
+        match cmd {
+            FIONREAD => {
+                let mut file = self.inner.lock();
+                let size = file.get_attr()?.size();
+                let pos = file.seek(SeekFrom::Current(0))?;
+                ioctl_write_int(arg, size.saturating_sub(pos) as usize)
+            }
+            _ => Err(LinuxError::ENOTTY),
+        }
+    }
+}
+
+/// Handles the writes to the cache controls in `/proc/sys/vm`, or returns
+/// `None` if `path` is another file.
+///
+/// `drop_caches` drops the cached blocks if bit 0 is set. There are no
+/// dentries or inodes to drop for bit 1, and bit 2 (no more messages) is
+/// ignored.
+fn write_proc_sys_vm(path: &str, buf: &[u8]) -> Option<LinuxResult<usize>> {
+    if !path.ends_with("/drop_caches") && !path.ends_with("/vfs_cache_pressure") {
+        return None;
+    }
+    let value = || -> LinuxResult<u32> {
+        core::str::from_utf8(buf)
+            .ok()
+            .and_then(|s| s.trim().parse().ok())
+            .ok_or(LinuxError::EINVAL)
+    };
+    let res = match axfs::api::canonicalize(path).ok()?.as_str() {
+        "/proc/sys/vm/drop_caches" => value().and_then(|value| match value {
+            1..=4 => {
+                if value & 1 != 0 {
+                    axfs::cache::drop_caches();
+                }
+                Ok(())
+            }
+            _ => Err(LinuxError::EINVAL),
+        }),
+        "/proc/sys/vm/vfs_cache_pressure" => value().map(axfs::cache::set_cache_pressure),
+        _ => return None,
+    };
+    Some(res.map(|_| buf.len()))
+}
+
+/// Handles the writes to `/proc/sys/kernel/randomize_va_space`, the level of
+/// the ASLR (see [`axmm::set_randomize_va_space`]), or returns `None` if
+/// `path` is another file.
+fn write_randomize_va_space(path: &str, buf: &[u8]) -> Option<LinuxResult<usize>> {
+    const PATH: &str = "/proc/sys/kernel/randomize_va_space";
+    if !path.ends_with("/randomize_va_space") || axfs::api::canonicalize(path).ok()? != PATH {
+        return None;
+    }
+    let res = core::str::from_utf8(buf)
+        .ok()
+        .and_then(|s| s.trim().parse().ok())
+        .ok_or(LinuxError::EINVAL)
+        .and_then(|level: u8| {
+            axmm::set_randomize_va_space(level)?;
+            axfs::api::write(PATH, alloc::format!("{}\n", level))?;
+            Ok(buf.len())
+        });
+    Some(res)
 }
 
 /// Convert open flags to [`OpenOptions`].
@@ -118,6 +205,9 @@ fn flags_to_options(flags: c_int, _mode: ctypes::mode_t) -> OpenOptions {
     if flags & ctypes::O_DIRECTORY != 0 {
         options.directory(true);
     }
+    if flags & ctypes::O_DIRECT != 0 {
+        options.direct(true);
+    }
     options
 }
 
@@ -129,6 +219,40 @@ pub fn sys_open(filename: *const c_char, flags: c_int, mode: ctypes::mode_t) ->
     let filename = char_ptr_to_str(filename);
     debug!("sys_open <= {:?} {:#o} {:#o}", filename, flags, mode);
     syscall_body!(sys_open, {
+        #[cfg(feature = "kcov")]
+        if filename == Ok(super::kcov::KCOV_PATH) {
+            return super::kcov::open();
+        }
+        #[cfg(feature = "display")]
+        if filename == Ok(super::fb::FB_PATH) {
+            return super::fb::open();
+        }
+        if filename == Ok(super::kmsg::KMSG_PATH) {
+            return super::kmsg::open();
+        }
+        if filename == Ok(super::kmsg::LOG_FILTER_PATH) {
+            return super::kmsg::open_log_filter();
+        }
+        #[cfg(feature = "pty")]
+        if let Some(fd) = filename.ok().and_then(|f| super::pty::open(f, flags)) {
+            return fd;
+        }
+        #[cfg(feature = "trace")]
+        if let Some(fd) = filename.ok().and_then(|f| super::trace::open(f, flags)) {
+            return fd;
+        }
+        #[cfg(feature = "prof")]
+        if filename == Ok(super::prof::PROFILE_PATH) {
+            return super::prof::open();
+        }
+        #[cfg(feature = "net")]
+        if let Ok(filename) = filename {
+            super::net::update_proc_net_file(filename);
+        }
+        #[cfg(feature = "multitask")]
+        if let Ok(filename) = filename {
+            super::task::update_proc_task_file(filename);
+        }
         add_file_or_directory_fd(
             axfs::fops::File::open,
             axfs::fops::Directory::open_dir,
@@ -222,11 +346,155 @@ pub fn sys_lseek(fd: c_int, offset: ctypes::off_t, whence: c_int) -> ctypes::off
             2 => SeekFrom::End(offset as _),
             _ => return Err(LinuxError::EINVAL),
         };
+        #[cfg(feature = "display")]
+        if let Ok(fb) = get_file_like(fd)?
+            .into_any()
+            .downcast::<super::fb::Framebuffer>()
+        {
+            return fb.seek(pos);
+        }
         let off = File::from_fd(fd)?.inner.lock().seek(pos)?;
         Ok(off)
     })
 }
 
+/// The size of the buffer of the copies between files in the kernel.
+const COPY_CHUNK: usize = 64 * 1024;
+
+/// The position in a file of a copy in the kernel: at `*offset` if it's not
+/// null, or at the file offset. Either is updated by [`finish`](Self::finish).
+struct CopyPos {
+    file: Arc<File>,
+    offset: *mut ctypes::off_t,
+    pos: u64,
+}
+
+impl CopyPos {
+    fn new(fd: c_int, offset: *mut ctypes::off_t) -> LinuxResult<Self> {
+        let file = File::from_fd(fd)?;
+        let pos = if offset.is_null() {
+            file.inner.lock().seek(SeekFrom::Current(0))?
+        } else {
+            u64::try_from(unsafe { *offset }).map_err(|_| LinuxError::EINVAL)?
+        };
+        Ok(Self { file, offset, pos })
+    }
+
+    fn finish(&self) -> LinuxResult {
+        if self.offset.is_null() {
+            self.file.inner.lock().seek(SeekFrom::Start(self.pos))?;
+        } else {
+            unsafe { *self.offset = self.pos as _ };
+        }
+        Ok(())
+    }
+}
+
+/// Copies at most `count` bytes from `src` by `write`, which returns the
+/// number of bytes written. Returns the number of bytes copied.
+///
+/// The copy stops at the end of the file, at a short write, or at an error,
+/// which is returned only if nothing has been copied.
+fn copy_from(
+    src: &mut CopyPos,
+    count: usize,
+    mut write: impl FnMut(&[u8]) -> LinuxResult<usize>,
+) -> LinuxResult<usize> {
+    let mut buf = vec![0; count.min(COPY_CHUNK)];
+    let mut copied = 0;
+    while copied < count {
+        let len = buf.len().min(count - copied);
+        // not holding the lock of the source while writing, which may be
+        // the same file
+        let res = src.file.inner.lock().read_at(src.pos, &mut buf[..len]);
+        let res = res
+            .map_err(LinuxError::from)
+            .and_then(|read| Ok((read, write(&buf[..read])?)));
+        match res {
+            Ok((0, _)) => break,
+            Ok((read, written)) => {
+                copied += written;
+                src.pos += written as u64;
+                if written < read {
+                    break;
+                }
+            }
+            Err(e) if copied == 0 => return Err(e),
+            Err(_) => break,
+        }
+    }
+    Ok(copied)
+}
+
+/// Copy data from the file `in_fd` to `out_fd`, e.g. a socket, without
+/// going through the user space.
+///
+/// The data is read at `*offset`, which is then updated, or at the file
+/// offset of `in_fd` if `offset` is null. Return the number of bytes copied.
+pub fn sys_sendfile(
+    out_fd: c_int,
+    in_fd: c_int,
+    offset: *mut ctypes::off_t,
+    count: usize,
+) -> ctypes::ssize_t {
+    debug!(
+        "sys_sendfile <= {} {} {:#x} {}",
+        out_fd, in_fd, offset as usize, count
+    );
+    syscall_body!(sys_sendfile, {
+        let out = get_file_like(out_fd)?;
+        let mut src = CopyPos::new(in_fd, offset)?;
+        let copied = copy_from(&mut src, count, |data| out.write(data))?;
+        src.finish()?;
+        Ok(copied as ctypes::ssize_t)
+    })
+}
+
+/// Copy a range of data from the file `fd_in` to the file `fd_out`, without
+/// going through the user space.
+///
+/// Each side is at `*off_in` or `*off_out`, which are then updated, or at
+/// its file offset if the pointer is null. `flags` must be 0. Return the
+/// number of bytes copied.
+pub fn sys_copy_file_range(
+    fd_in: c_int,
+    off_in: *mut ctypes::off_t,
+    fd_out: c_int,
+    off_out: *mut ctypes::off_t,
+    len: usize,
+    flags: c_uint,
+) -> ctypes::ssize_t {
+    debug!(
+        "sys_copy_file_range <= {} {:#x} {} {:#x} {} {}",
+        fd_in, off_in as usize, fd_out, off_out as usize, len, flags
+    );
+    syscall_body!(sys_copy_file_range, {
+        if flags != 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        let mut src = CopyPos::new(fd_in, off_in)?;
+        let mut dst = CopyPos::new(fd_out, off_out)?;
+        let same_file = Arc::ptr_eq(&src.file, &dst.file)
+            || axfs::api::canonicalize(src.file.path())?
+                == axfs::api::canonicalize(dst.file.path())?;
+        let len_u64 = len as u64;
+        if same_file
+            && src.pos < dst.pos.saturating_add(len_u64)
+            && dst.pos < src.pos.saturating_add(len_u64)
+        {
+            return Err(LinuxError::EINVAL);
+        }
+        let copied = copy_from(&mut src, len, |data| {
+            let written = dst.file.inner.lock().write_at(dst.pos, data)?;
+            dst.pos += written as u64;
+            Ok(written)
+        })?;
+        src.finish()?;
+        dst.finish()?;
+        Ok(copied as ctypes::ssize_t)
+    })
+}
+
 /// Get the file metadata by `path` and write into `buf`.
 ///
 /// Return 0 if success.
@@ -310,6 +578,166 @@ pub fn sys_rename(old: *const c_char, new: *const c_char) -> c_int {
     })
 }
 
+/// Converts the errors of the extended attribute operations, where a missing
+/// attribute is `ENODATA`.
+fn xattr_error(err: AxError) -> LinuxError {
+    match err {
+        AxError::NotFound => LinuxError::ENODATA,
+        AxError::Unsupported => LinuxError::EOPNOTSUPP,
+        err => LinuxError::from(err),
+    }
+}
+
+/// Returns the buffer of `size` bytes at `ptr` for the xattr syscalls, which
+/// may be null to query the size.
+fn xattr_buf<'a>(ptr: *mut c_void, size: usize) -> &'a mut [u8] {
+    if ptr.is_null() || size == 0 {
+        return &mut [];
+    }
+    unsafe { core::slice::from_raw_parts_mut(ptr as *mut u8, size) }
+}
+
+/// Reads the extended attribute `name` of `path` into `buf`, or only returns
+/// its size if `buf` is empty.
+fn get_xattr(path: &str, name: &str, buf: &mut [u8]) -> LinuxResult<ctypes::ssize_t> {
+    // a missing file is `ENOENT`
+    axfs::api::metadata(path)?;
+    let size = axfs::api::get_xattr(path, name, buf).map_err(xattr_error)?;
+    if !buf.is_empty() && size > buf.len() {
+        return Err(LinuxError::ERANGE);
+    }
+    Ok(size as _)
+}
+
+/// Get the value of the extended attribute `name` of the file at `path`.
+///
+/// Return the size of the value. If `size` is 0, only the size is returned.
+pub fn sys_getxattr(
+    path: *const c_char,
+    name: *const c_char,
+    value: *mut c_void,
+    size: usize,
+) -> ctypes::ssize_t {
+    debug!(
+        "sys_getxattr <= {:?} {:?} {:#x} {}",
+        char_ptr_to_str(path),
+        char_ptr_to_str(name),
+        value as usize,
+        size
+    );
+    syscall_body!(sys_getxattr, {
+        get_xattr(
+            char_ptr_to_str(path)?,
+            char_ptr_to_str(name)?,
+            xattr_buf(value, size),
+        )
+    })
+}
+
+/// Get the value of the extended attribute `name` of the file or directory
+/// opened as `fd`.
+///
+/// Return the size of the value. If `size` is 0, only the size is returned.
+pub fn sys_fgetxattr(
+    fd: c_int,
+    name: *const c_char,
+    value: *mut c_void,
+    size: usize,
+) -> ctypes::ssize_t {
+    debug!(
+        "sys_fgetxattr <= {} {:?} {:#x} {}",
+        fd,
+        char_ptr_to_str(name),
+        value as usize,
+        size
+    );
+    syscall_body!(sys_fgetxattr, {
+        let path = match File::from_fd(fd) {
+            Ok(file) => file.path().to_string(),
+            Err(_) => Directory::from_fd(fd)?.path().to_string(),
+        };
+        get_xattr(&path, char_ptr_to_str(name)?, xattr_buf(value, size))
+    })
+}
+
+/// Set the value of the extended attribute `name` of the file at `path`.
+///
+/// `flags` may be `XATTR_CREATE` to fail if the attribute exists, or
+/// `XATTR_REPLACE` to fail if it doesn't. Return 0 if success.
+pub fn sys_setxattr(
+    path: *const c_char,
+    name: *const c_char,
+    value: *const c_void,
+    size: usize,
+    flags: c_int,
+) -> c_int {
+    debug!(
+        "sys_setxattr <= {:?} {:?} {:#x} {} {:#x}",
+        char_ptr_to_str(path),
+        char_ptr_to_str(name),
+        value as usize,
+        size,
+        flags
+    );
+    syscall_body!(sys_setxattr, {
+        let path = char_ptr_to_str(path)?;
+        let name = char_ptr_to_str(name)?;
+        let mode = match flags as u32 {
+            0 => XattrSetMode::Any,
+            ctypes::XATTR_CREATE => XattrSetMode::Create,
+            ctypes::XATTR_REPLACE => XattrSetMode::Replace,
+            _ => return Err(LinuxError::EINVAL),
+        };
+        if value.is_null() && size != 0 {
+            return Err(LinuxError::EFAULT);
+        }
+        let value = xattr_buf(value as *mut c_void, size);
+        axfs::api::metadata(path)?;
+        axfs::api::set_xattr(path, name, value, mode).map_err(xattr_error)?;
+        Ok(0)
+    })
+}
+
+/// List the names of the extended attributes of the file at `path` into
+/// `list`, each followed by a NUL.
+///
+/// Return the size of the list. If `size` is 0, only the size is returned.
+pub fn sys_listxattr(path: *const c_char, list: *mut c_char, size: usize) -> ctypes::ssize_t {
+    debug!(
+        "sys_listxattr <= {:?} {:#x} {}",
+        char_ptr_to_str(path),
+        list as usize,
+        size
+    );
+    syscall_body!(sys_listxattr, {
+        let path = char_ptr_to_str(path)?;
+        let buf = xattr_buf(list as *mut c_void, size);
+        axfs::api::metadata(path)?;
+        let len = axfs::api::list_xattr(path, buf).map_err(xattr_error)?;
+        if size != 0 && len > size {
+            return Err(LinuxError::ERANGE);
+        }
+        Ok(len)
+    })
+}
+
+/// Remove the extended attribute `name` of the file at `path`.
+///
+/// Return 0 if success.
+pub fn sys_removexattr(path: *const c_char, name: *const c_char) -> c_int {
+    debug!(
+        "sys_removexattr <= {:?} {:?}",
+        char_ptr_to_str(path),
+        char_ptr_to_str(name)
+    );
+    syscall_body!(sys_removexattr, {
+        let path = char_ptr_to_str(path)?;
+        axfs::api::metadata(path)?;
+        axfs::api::remove_xattr(path, char_ptr_to_str(name)?).map_err(xattr_error)?;
+        Ok(0)
+    })
+}
+
 /// Directory wrapper for `axfs::fops::Directory`.
 pub struct Directory {
     inner: Mutex<axfs::fops::Directory>,
@@ -0,0 +1,831 @@
+//! Fast user-space locking.
+//!
+//! Waiters are kept in a hashed table of buckets keyed on the futex address.
+//! Since all tasks share one address space, the virtual address identifies a
+//! futex as well as the physical one does.
+//!
+//! The PI operations keep the owner's TID in the futex word as Linux does, and
+//! the owner inherits the highest priority of the waiters until it unlocks
+//! the futex. The priorities are the nice values of the CFS scheduler, the
+//! other schedulers have none. The boost isn't passed on to the owners of the
+//! futexes the owner waits for in turn.
+//!
+//! The robust futexes held by a thread, listed by `set_robust_list`, are
+//! released with `FUTEX_OWNER_DIED` set when it exits, waking up a waiter.
+//!
+//! The waits are interrupted by signals. As the deadlines are absolute, they
+//! are restarted if the handlers were installed with `SA_RESTART`, even with a
+//! timeout.
+
+use alloc::collections::{BTreeMap, VecDeque, btree_map::Entry};
+use alloc::sync::Arc;
+use core::ffi::{c_int, c_long, c_uint, c_void};
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use core::time::Duration;
+
+use axerrno::{LinuxError, LinuxResult};
+use axhal::time::monotonic_time;
+use axtask::{AxTaskRef, WaitQueue, WaitResult};
+use spin::{Mutex, MutexGuard};
+
+use crate::ctypes;
+use crate::imp::pthread::Pthread;
+use crate::imp::signal::check_interrupt;
+use crate::imp::time::clock_now;
+
+const FUTEX_WAIT: c_int = 0;
+const FUTEX_WAKE: c_int = 1;
+const FUTEX_REQUEUE: c_int = 3;
+const FUTEX_CMP_REQUEUE: c_int = 4;
+const FUTEX_LOCK_PI: c_int = 6;
+const FUTEX_UNLOCK_PI: c_int = 7;
+const FUTEX_TRYLOCK_PI: c_int = 8;
+const FUTEX_WAIT_BITSET: c_int = 9;
+const FUTEX_WAKE_BITSET: c_int = 10;
+
+const FUTEX_PRIVATE_FLAG: c_int = 128;
+const FUTEX_CLOCK_REALTIME: c_int = 256;
+const FUTEX_CMD_MASK: c_int = !(FUTEX_PRIVATE_FLAG | FUTEX_CLOCK_REALTIME);
+
+const FUTEX_BITSET_MATCH_ANY: u32 = u32::MAX;
+
+const FUTEX_WAITERS: u32 = 0x8000_0000;
+const FUTEX_OWNER_DIED: u32 = 0x4000_0000;
+const FUTEX_TID_MASK: u32 = 0x3fff_ffff;
+
+/// The most entries of a robust list released, as the list may be cyclic.
+const ROBUST_LIST_LIMIT: usize = 2048;
+
+const BUCKET_COUNT: usize = 64;
+
+struct Waiter {
+    /// The futex address, changed on requeue.
+    key: AtomicUsize,
+    bitset: u32,
+    tid: u32,
+    /// The priority of the task, inherited by the owner of a PI futex.
+    prio: isize,
+    woken: AtomicBool,
+    wq: WaitQueue,
+}
+
+type Bucket = Mutex<VecDeque<Arc<Waiter>>>;
+
+static FUTEX_TABLE: [Bucket; BUCKET_COUNT] = [const { Mutex::new(VecDeque::new()) }; BUCKET_COUNT];
+
+/// The owners of PI futexes boosted by their waiters, by TID.
+static PI_BOOSTS: Mutex<BTreeMap<u32, PiBoost>> = Mutex::new(BTreeMap::new());
+
+/// The heads of the robust lists of the threads, by task ID.
+static ROBUST_LISTS: Mutex<BTreeMap<u64, usize>> = Mutex::new(BTreeMap::new());
+
+struct PiBoost {
+    task: AxTaskRef,
+    /// The priority before the first boost.
+    base: isize,
+    /// The highest priority of the waiters of each PI futex it owns.
+    inherited: BTreeMap<usize, isize>,
+}
+
+impl PiBoost {
+    /// The lower nice value is the higher priority.
+    fn priority(&self) -> isize {
+        self.inherited.values().copied().fold(self.base, isize::min)
+    }
+}
+
+/// The layout of `struct robust_list_head`.
+#[repr(C)]
+struct RobustListHead {
+    next: usize,
+    futex_offset: c_long,
+    list_op_pending: usize,
+}
+
+fn bucket_of(key: usize) -> &'static Bucket {
+    // futex words are 4-byte aligned
+    &FUTEX_TABLE[(key >> 2) % BUCKET_COUNT]
+}
+
+/// Locks the buckets of two keys in a consistent order.
+fn lock_two(
+    key1: usize,
+    key2: usize,
+) -> (
+    MutexGuard<'static, VecDeque<Arc<Waiter>>>,
+    Option<MutexGuard<'static, VecDeque<Arc<Waiter>>>>,
+) {
+    let (b1, b2) = (bucket_of(key1), bucket_of(key2));
+    if core::ptr::eq(b1, b2) {
+        (b1.lock(), None)
+    } else if (b1 as *const Bucket) < (b2 as *const Bucket) {
+        let g1 = b1.lock();
+        (g1, Some(b2.lock()))
+    } else {
+        let g2 = b2.lock();
+        (b1.lock(), Some(g2))
+    }
+}
+
+fn futex_word<'a>(uaddr: *mut u32) -> LinuxResult<&'a AtomicU32> {
+    if uaddr.is_null() {
+        return Err(LinuxError::EFAULT);
+    }
+    if !uaddr.is_aligned() {
+        return Err(LinuxError::EINVAL);
+    }
+    // Safety: the address is valid as checked above, and futex words are
+    // only accessed atomically.
+    Ok(unsafe { AtomicU32::from_ptr(uaddr) })
+}
+
+fn current_tid() -> u32 {
+    axtask::current().id().as_u64() as u32 & FUTEX_TID_MASK
+}
+
+fn new_waiter(key: usize, bitset: u32) -> Arc<Waiter> {
+    Arc::new(Waiter {
+        key: AtomicUsize::new(key),
+        bitset,
+        tid: current_tid(),
+        prio: axtask::current().priority(),
+        woken: AtomicBool::new(false),
+        wq: WaitQueue::new(),
+    })
+}
+
+/// Returns the highest priority of the waiters on `key`.
+fn top_priority(bucket: &VecDeque<Arc<Waiter>>, key: usize) -> Option<isize> {
+    bucket
+        .iter()
+        .filter(|w| w.key.load(Ordering::Relaxed) == key)
+        .map(|w| w.prio)
+        .min()
+}
+
+/// Sets the priority the owner `tid` inherits from the waiters of the PI
+/// futex `key`, or stops it with `None`, and updates its priority.
+///
+/// The bucket of `key` must be locked.
+fn inherit_priority(tid: u32, key: usize, prio: Option<isize>) {
+    let mut boosts = PI_BOOSTS.lock();
+    let boost = match boosts.entry(tid) {
+        Entry::Occupied(entry) => entry.into_mut(),
+        Entry::Vacant(entry) => {
+            // the owner may have exited
+            let (Some(_), Some(task)) = (prio, Pthread::task(tid as u64)) else {
+                return;
+            };
+            entry.insert(PiBoost {
+                base: task.priority(),
+                task,
+                inherited: BTreeMap::new(),
+            })
+        }
+    };
+    match prio {
+        Some(prio) => boost.inherited.insert(key, prio),
+        None => boost.inherited.remove(&key),
+    };
+    axtask::set_task_priority(&boost.task, boost.priority());
+    if boost.inherited.is_empty() {
+        boosts.remove(&tid);
+    }
+}
+
+/// Converts the timeout argument into a deadline of the monotonic clock.
+fn deadline_of(
+    timeout: *const ctypes::timespec,
+    absolute: bool,
+    realtime: bool,
+) -> LinuxResult<Option<Duration>> {
+    if timeout.is_null() {
+        return Ok(None);
+    }
+    let ts = unsafe { *timeout };
+    if ts.tv_sec < 0 || !(0..1_000_000_000).contains(&ts.tv_nsec) {
+        return Err(LinuxError::EINVAL);
+    }
+    let dur = Duration::from(ts);
+    let now = monotonic_time();
+    if !absolute {
+        return Ok(Some(now + dur));
+    }
+    // absolute timeouts are measured by the clocks of the time namespace
+    let clk = if realtime {
+        ctypes::CLOCK_REALTIME
+    } else {
+        ctypes::CLOCK_MONOTONIC
+    };
+    Ok(Some(now + dur.saturating_sub(clock_now(clk as _)?)))
+}
+
+/// Blocks until the waiter is woken, the deadline has passed, or the task is
+/// interrupted by a signal.
+///
+/// A waiter that isn't woken is removed from its bucket.
+fn block(waiter: &Waiter, deadline: Option<Duration>) -> WaitResult {
+    let woken = || waiter.woken.load(Ordering::Acquire);
+    let result = match deadline {
+        None => waiter.wq.wait_until_interruptible(woken),
+        Some(deadline) => {
+            #[cfg(feature = "irq")]
+            {
+                let now = monotonic_time();
+                if deadline > now {
+                    waiter
+                        .wq
+                        .wait_timeout_until_interruptible(deadline - now, woken)
+                } else {
+                    WaitResult::TimedOut
+                }
+            }
+            #[cfg(not(feature = "irq"))]
+            loop {
+                if woken() {
+                    break WaitResult::Notified;
+                } else if axtask::interrupt_pending() {
+                    break WaitResult::Interrupted;
+                } else if monotonic_time() >= deadline {
+                    break WaitResult::TimedOut;
+                }
+                axtask::yield_now();
+            }
+        }
+    };
+    if woken() || dequeue(waiter) {
+        WaitResult::Notified
+    } else {
+        result
+    }
+}
+
+/// Removes a waiter that has timed out or been interrupted from its bucket.
+///
+/// Returns `true` if it has been woken in the meantime.
+fn dequeue(waiter: &Waiter) -> bool {
+    loop {
+        let key = waiter.key.load(Ordering::Acquire);
+        let mut bucket = bucket_of(key).lock();
+        if waiter.woken.load(Ordering::Acquire) {
+            return true;
+        }
+        // it may have been requeued before we got the lock
+        if waiter.key.load(Ordering::Acquire) == key {
+            bucket.retain(|w| !core::ptr::eq(w.as_ref(), waiter));
+            return false;
+        }
+    }
+}
+
+/// Wakes at most `count` waiters on `key` whose bitsets intersect `bitset`.
+fn wake(bucket: &mut VecDeque<Arc<Waiter>>, key: usize, bitset: u32, count: usize) -> usize {
+    let mut woken = 0;
+    bucket.retain(|w| {
+        if woken < count && w.key.load(Ordering::Relaxed) == key && w.bitset & bitset != 0 {
+            w.woken.store(true, Ordering::Release);
+            w.wq.notify_one(false);
+            woken += 1;
+            false
+        } else {
+            true
+        }
+    });
+    woken
+}
+
+fn futex_wait(
+    uaddr: *mut u32,
+    val: u32,
+    deadline: Option<Duration>,
+    bitset: u32,
+) -> LinuxResult<c_int> {
+    if bitset == 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let word = futex_word(uaddr)?;
+    let key = uaddr as usize;
+    loop {
+        let waiter = new_waiter(key, bitset);
+        {
+            let mut bucket = bucket_of(key).lock();
+            if word.load(Ordering::SeqCst) != val {
+                return Err(LinuxError::EAGAIN);
+            }
+            bucket.push_back(waiter.clone());
+        }
+        match block(&waiter, deadline) {
+            WaitResult::Notified => return Ok(0),
+            WaitResult::TimedOut => return Err(LinuxError::ETIMEDOUT),
+            // restarted on the original futex, even if it was requeued
+            WaitResult::Interrupted => check_interrupt(true)?,
+        }
+    }
+}
+
+fn futex_wake(uaddr: *mut u32, count: u32, bitset: u32) -> LinuxResult<c_int> {
+    if bitset == 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    futex_word(uaddr)?;
+    let key = uaddr as usize;
+    let woken = wake(&mut bucket_of(key).lock(), key, bitset, count as usize);
+    Ok(woken as c_int)
+}
+
+fn futex_requeue(
+    uaddr: *mut u32,
+    count: u32,
+    uaddr2: *mut u32,
+    count2: u32,
+    cmpval: Option<u32>,
+) -> LinuxResult<c_int> {
+    let word = futex_word(uaddr)?;
+    futex_word(uaddr2)?;
+    let (key1, key2) = (uaddr as usize, uaddr2 as usize);
+
+    let (mut bucket1, mut bucket2) = lock_two(key1, key2);
+    if cmpval.is_some_and(|val| word.load(Ordering::SeqCst) != val) {
+        return Err(LinuxError::EAGAIN);
+    }
+    let woken = wake(&mut bucket1, key1, FUTEX_BITSET_MATCH_ANY, count as usize);
+
+    let mut requeued = 0;
+    let mut moved = VecDeque::new();
+    bucket1.retain(|w| {
+        if requeued < count2 as usize && w.key.load(Ordering::Relaxed) == key1 {
+            w.key.store(key2, Ordering::Release);
+            moved.push_back(w.clone());
+            requeued += 1;
+            false
+        } else {
+            true
+        }
+    });
+    match &mut bucket2 {
+        Some(bucket2) => bucket2.extend(moved),
+        None => bucket1.extend(moved),
+    }
+    Ok((woken + requeued) as c_int)
+}
+
+fn futex_lock_pi(
+    uaddr: *mut u32,
+    deadline: Option<Duration>,
+    try_lock: bool,
+) -> LinuxResult<c_int> {
+    let word = futex_word(uaddr)?;
+    let key = uaddr as usize;
+    let tid = current_tid();
+    loop {
+        let waiter = {
+            let mut bucket = bucket_of(key).lock();
+            let val = word.load(Ordering::SeqCst);
+            let owner = val & FUTEX_TID_MASK;
+            if owner == tid {
+                return Err(LinuxError::EDEADLK);
+            }
+            if owner == 0 {
+                // keep the waiters bit for the ones still waiting, and the
+                // owner died bit for the new owner to see
+                let new = tid | (val & (FUTEX_WAITERS | FUTEX_OWNER_DIED));
+                if word
+                    .compare_exchange(val, new, Ordering::SeqCst, Ordering::SeqCst)
+                    .is_ok()
+                {
+                    return Ok(0);
+                }
+                continue;
+            }
+            if try_lock {
+                return Err(LinuxError::EAGAIN);
+            }
+            if word
+                .compare_exchange(val, val | FUTEX_WAITERS, Ordering::SeqCst, Ordering::SeqCst)
+                .is_err()
+            {
+                continue;
+            }
+            let waiter = new_waiter(key, FUTEX_BITSET_MATCH_ANY);
+            bucket.push_back(waiter.clone());
+            inherit_priority(owner, key, top_priority(&bucket, key));
+            waiter
+        };
+        let result = block(&waiter, deadline);
+        if result != WaitResult::Notified {
+            // the owner no longer inherits the priority of this waiter
+            let bucket = bucket_of(key).lock();
+            let owner = word.load(Ordering::SeqCst) & FUTEX_TID_MASK;
+            inherit_priority(owner, key, top_priority(&bucket, key));
+        }
+        match result {
+            WaitResult::Notified => {}
+            WaitResult::TimedOut => return Err(LinuxError::ETIMEDOUT),
+            WaitResult::Interrupted => {
+                check_interrupt(true)?;
+                continue;
+            }
+        }
+        // the lock has been handed over by the unlocking owner
+        if word.load(Ordering::SeqCst) & FUTEX_TID_MASK == tid {
+            return Ok(0);
+        }
+    }
+}
+
+fn futex_unlock_pi(uaddr: *mut u32) -> LinuxResult<c_int> {
+    let word = futex_word(uaddr)?;
+    let key = uaddr as usize;
+    let tid = current_tid();
+
+    let mut bucket = bucket_of(key).lock();
+    let val = word.load(Ordering::SeqCst);
+    if val & FUTEX_TID_MASK != tid {
+        return Err(LinuxError::EPERM);
+    }
+    hand_over_pi(&mut bucket, word, key, tid, 0);
+    Ok(0)
+}
+
+/// Hands the PI futex `key` owned by `tid` over to its first waiter, or
+/// unlocks it if there's none, with the `flags` set in the futex word.
+fn hand_over_pi(
+    bucket: &mut VecDeque<Arc<Waiter>>,
+    word: &AtomicU32,
+    key: usize,
+    tid: u32,
+    flags: u32,
+) {
+    inherit_priority(tid, key, None);
+    let next = bucket
+        .iter()
+        .position(|w| w.key.load(Ordering::Relaxed) == key)
+        .map(|i| bucket.remove(i).unwrap());
+    match next {
+        Some(next) => {
+            let top = top_priority(bucket, key);
+            let new = next.tid | flags | if top.is_some() { FUTEX_WAITERS } else { 0 };
+            word.store(new, Ordering::SeqCst);
+            inherit_priority(next.tid, key, top);
+            next.woken.store(true, Ordering::Release);
+            next.wq.notify_one(false);
+        }
+        None => word.store(flags, Ordering::SeqCst),
+    }
+}
+
+/// Reads a word of the robust list at `addr`.
+fn read_robust_word(addr: usize) -> Option<usize> {
+    let ptr = addr as *const usize;
+    if ptr.is_null() || !ptr.is_aligned() {
+        return None;
+    }
+    // Safety: the list is in the only address space, and checked above.
+    Some(unsafe { ptr.read_volatile() })
+}
+
+/// Releases the robust futex at `uaddr` if it's held by `tid`, which has
+/// exited, as `handle_futex_death` of Linux does.
+fn release_robust_futex(uaddr: usize, tid: u32, pi: bool, pending: bool) {
+    let Ok(word) = futex_word(uaddr as *mut u32) else {
+        return;
+    };
+    let key = uaddr;
+    let mut bucket = bucket_of(key).lock();
+    let val = word.load(Ordering::SeqCst);
+    // it died after unlocking the futex but before waking up a waiter
+    if pending && !pi && val == 0 {
+        wake(&mut bucket, key, FUTEX_BITSET_MATCH_ANY, 1);
+        return;
+    }
+    if val & FUTEX_TID_MASK != tid {
+        return;
+    }
+    if pi {
+        hand_over_pi(&mut bucket, word, key, tid, FUTEX_OWNER_DIED);
+        return;
+    }
+    // the waiters bit is kept for the waiters not woken up
+    word.store((val & FUTEX_WAITERS) | FUTEX_OWNER_DIED, Ordering::SeqCst);
+    if val & FUTEX_WAITERS != 0 {
+        wake(&mut bucket, key, FUTEX_BITSET_MATCH_ANY, 1);
+    }
+}
+
+/// Releases the robust futexes held by the current thread, called when it
+/// exits.
+pub(crate) fn exit_robust_list() {
+    let Some(head) = ROBUST_LISTS.lock().remove(&axtask::current().id().as_u64()) else {
+        return;
+    };
+    let tid = current_tid();
+    let field = |offset| read_robust_word(head + offset);
+    let (Some(mut entry), Some(offset), Some(pending)) = (
+        field(0),
+        field(core::mem::offset_of!(RobustListHead, futex_offset)),
+        field(core::mem::offset_of!(RobustListHead, list_op_pending)),
+    ) else {
+        return;
+    };
+    let offset = offset as isize;
+    // the lowest bit of the pointers tells a PI futex
+    let pending_entry = pending & !1;
+    for _ in 0..ROBUST_LIST_LIMIT {
+        let addr = entry & !1;
+        if addr == head {
+            break;
+        }
+        let Some(next) = read_robust_word(addr) else {
+            break;
+        };
+        // the pending one is released last
+        if addr != pending_entry {
+            release_robust_futex(addr.wrapping_add_signed(offset), tid, entry & 1 != 0, false);
+        }
+        entry = next;
+    }
+    if pending_entry != 0 {
+        release_robust_futex(
+            pending_entry.wrapping_add_signed(offset),
+            tid,
+            pending & 1 != 0,
+            true,
+        );
+    }
+}
+
+/// Sets the head of the robust list of the current thread, whose futexes
+/// are released when it exits.
+///
+/// `len` must be the size of `struct robust_list_head`.
+pub fn sys_set_robust_list(head: *const c_void, len: usize) -> c_int {
+    debug!("sys_set_robust_list <= {:#x} {}", head as usize, len);
+    syscall_body!(sys_set_robust_list, {
+        if len != size_of::<RobustListHead>() {
+            return Err(LinuxError::EINVAL);
+        }
+        let tid = axtask::current().id().as_u64();
+        ROBUST_LISTS.lock().insert(tid, head as usize);
+        Ok(0)
+    })
+}
+
+/// Gets the head of the robust list of the thread `tid`, or the current
+/// thread if it's 0.
+pub unsafe fn sys_get_robust_list(
+    tid: c_int,
+    head_ptr: *mut *const c_void,
+    len_ptr: *mut usize,
+) -> c_int {
+    debug!("sys_get_robust_list <= {}", tid);
+    syscall_body!(sys_get_robust_list, {
+        if head_ptr.is_null() || len_ptr.is_null() {
+            return Err(LinuxError::EFAULT);
+        }
+        let tid = match tid {
+            0 => axtask::current().id().as_u64(),
+            1.. if Pthread::exists(tid as u64) => tid as u64,
+            _ => return Err(LinuxError::ESRCH),
+        };
+        let head = ROBUST_LISTS.lock().get(&tid).copied().unwrap_or(0);
+        unsafe {
+            head_ptr.write(head as *const c_void);
+            len_ptr.write(size_of::<RobustListHead>());
+        }
+        Ok(0)
+    })
+}
+
+/// Fast user-space locking.
+///
+/// Supports `FUTEX_WAIT`, `FUTEX_WAKE`, `FUTEX_REQUEUE`, `FUTEX_CMP_REQUEUE`,
+/// `FUTEX_WAIT_BITSET`, `FUTEX_WAKE_BITSET`, `FUTEX_LOCK_PI`,
+/// `FUTEX_TRYLOCK_PI` and `FUTEX_UNLOCK_PI`. `FUTEX_PRIVATE_FLAG` makes no
+/// difference as there's only one address space.
+pub unsafe fn sys_futex(
+    uaddr: *mut u32,
+    futex_op: c_int,
+    val: u32,
+    timeout: *const ctypes::timespec,
+    uaddr2: *mut u32,
+    val3: u32,
+) -> c_int {
+    debug!(
+        "sys_futex <= {:#x} {:#x} {} {:#x}",
+        uaddr as usize, futex_op, val, val3
+    );
+    syscall_body!(sys_futex, {
+        let realtime = futex_op & FUTEX_CLOCK_REALTIME != 0;
+        let cmd = futex_op & FUTEX_CMD_MASK;
+        if realtime && !matches!(cmd, FUTEX_WAIT | FUTEX_WAIT_BITSET) {
+            return Err(LinuxError::ENOSYS);
+        }
+        // `timeout` is a number for the requeue operations
+        let count2 = timeout as usize as c_uint;
+        match cmd {
+            FUTEX_WAIT => {
+                let deadline = deadline_of(timeout, false, realtime)?;
+                futex_wait(uaddr, val, deadline, FUTEX_BITSET_MATCH_ANY)
+            }
+            FUTEX_WAIT_BITSET => {
+                let deadline = deadline_of(timeout, true, realtime)?;
+                futex_wait(uaddr, val, deadline, val3)
+            }
+            FUTEX_WAKE => futex_wake(uaddr, val, FUTEX_BITSET_MATCH_ANY),
+            FUTEX_WAKE_BITSET => futex_wake(uaddr, val, val3),
+            FUTEX_REQUEUE => futex_requeue(uaddr, val, uaddr2, count2, None),
+            FUTEX_CMP_REQUEUE => futex_requeue(uaddr, val, uaddr2, count2, Some(val3)),
+            // the timeout of FUTEX_LOCK_PI is absolute, measured against CLOCK_REALTIME
+            FUTEX_LOCK_PI => futex_lock_pi(uaddr, deadline_of(timeout, true, true)?, false),
+            FUTEX_TRYLOCK_PI => futex_lock_pi(uaddr, None, true),
+            FUTEX_UNLOCK_PI => futex_unlock_pi(uaddr),
+            _ => Err(LinuxError::ENOSYS),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_init as init;
+
+    fn waiters(word: &AtomicU32) -> usize {
+        let key = word.as_ptr() as usize;
+        let bucket = bucket_of(key).lock();
+        bucket
+            .iter()
+            .filter(|w| w.key.load(Ordering::Relaxed) == key)
+            .count()
+    }
+
+    /// Yields until `cond` holds, for the tasks spawned to run.
+    fn yield_until(cond: impl Fn() -> bool) {
+        while !cond() {
+            axtask::yield_now();
+        }
+    }
+
+    #[test]
+    fn test_wait_wake() {
+        let _guard = init();
+        static WORD: AtomicU32 = AtomicU32::new(0);
+        static DONE: AtomicUsize = AtomicUsize::new(0);
+
+        let uaddr = WORD.as_ptr();
+        assert_eq!(
+            futex_wait(uaddr, 1, None, FUTEX_BITSET_MATCH_ANY),
+            Err(LinuxError::EAGAIN)
+        );
+        for bitset in [0b01, 0b10] {
+            axtask::spawn(move || {
+                assert_eq!(futex_wait(WORD.as_ptr(), 0, None, bitset), Ok(0));
+                DONE.fetch_add(1, Ordering::AcqRel);
+            });
+        }
+        yield_until(|| waiters(&WORD) == 2);
+        // only the waiters whose bitsets intersect are woken up
+        assert_eq!(futex_wake(uaddr, u32::MAX, 0b10), Ok(1));
+        yield_until(|| DONE.load(Ordering::Acquire) == 1);
+        assert_eq!(futex_wake(uaddr, u32::MAX, FUTEX_BITSET_MATCH_ANY), Ok(1));
+        yield_until(|| DONE.load(Ordering::Acquire) == 2);
+        assert_eq!(futex_wake(uaddr, 1, FUTEX_BITSET_MATCH_ANY), Ok(0));
+    }
+
+    #[test]
+    fn test_wait_timeout() {
+        let _guard = init();
+        static WORD: AtomicU32 = AtomicU32::new(0);
+
+        let deadline = Some(monotonic_time());
+        assert_eq!(
+            futex_wait(WORD.as_ptr(), 0, deadline, FUTEX_BITSET_MATCH_ANY),
+            Err(LinuxError::ETIMEDOUT)
+        );
+        // the waiter that timed out is removed
+        assert_eq!(waiters(&WORD), 0);
+    }
+
+    #[test]
+    fn test_requeue() {
+        let _guard = init();
+        static FROM: AtomicU32 = AtomicU32::new(0);
+        static TO: AtomicU32 = AtomicU32::new(0);
+        static DONE: AtomicUsize = AtomicUsize::new(0);
+
+        for _ in 0..3 {
+            axtask::spawn(|| {
+                assert_eq!(
+                    futex_wait(FROM.as_ptr(), 0, None, FUTEX_BITSET_MATCH_ANY),
+                    Ok(0)
+                );
+                DONE.fetch_add(1, Ordering::AcqRel);
+            });
+        }
+        yield_until(|| waiters(&FROM) == 3);
+        let (from, to) = (FROM.as_ptr(), TO.as_ptr());
+        assert_eq!(
+            futex_requeue(from, 1, to, 1, Some(1)),
+            Err(LinuxError::EAGAIN)
+        );
+        // wakes up one, and moves one
+        assert_eq!(futex_requeue(from, 1, to, 1, Some(0)), Ok(2));
+        assert_eq!((waiters(&FROM), waiters(&TO)), (1, 1));
+        assert_eq!(futex_wake(to, u32::MAX, FUTEX_BITSET_MATCH_ANY), Ok(1));
+        assert_eq!(futex_wake(from, u32::MAX, FUTEX_BITSET_MATCH_ANY), Ok(1));
+        yield_until(|| DONE.load(Ordering::Acquire) == 3);
+    }
+
+    #[test]
+    fn test_pi_hand_over() {
+        let _guard = init();
+        static WORD: AtomicU32 = AtomicU32::new(0);
+        static WAITER_TID: AtomicU32 = AtomicU32::new(0);
+        static DONE: AtomicBool = AtomicBool::new(false);
+
+        let uaddr = WORD.as_ptr();
+        assert_eq!(futex_lock_pi(uaddr, None, false), Ok(0));
+        assert_eq!(WORD.load(Ordering::SeqCst), current_tid());
+        assert_eq!(futex_lock_pi(uaddr, None, false), Err(LinuxError::EDEADLK));
+
+        axtask::spawn(|| {
+            WAITER_TID.store(current_tid(), Ordering::Release);
+            assert_eq!(
+                futex_lock_pi(WORD.as_ptr(), None, true),
+                Err(LinuxError::EAGAIN)
+            );
+            assert_eq!(futex_lock_pi(WORD.as_ptr(), None, false), Ok(0));
+            DONE.store(true, Ordering::Release);
+            assert_eq!(futex_unlock_pi(WORD.as_ptr()), Ok(0));
+        });
+        yield_until(|| waiters(&WORD) == 1);
+        assert_ne!(WORD.load(Ordering::SeqCst) & FUTEX_WAITERS, 0);
+
+        // the lock goes to the waiter, which has no waiters of its own
+        assert_eq!(futex_unlock_pi(uaddr), Ok(0));
+        assert_eq!(
+            WORD.load(Ordering::SeqCst),
+            WAITER_TID.load(Ordering::Acquire)
+        );
+        assert_eq!(futex_unlock_pi(uaddr), Err(LinuxError::EPERM));
+        yield_until(|| DONE.load(Ordering::Acquire));
+        yield_until(|| WORD.load(Ordering::SeqCst) == 0);
+    }
+
+    #[test]
+    fn test_robust_list() {
+        let _guard = init();
+
+        /// A lock in the robust list, with the futex word after the link.
+        #[repr(C)]
+        struct RobustLock {
+            next: usize,
+            word: AtomicU32,
+        }
+
+        let tid = current_tid();
+        let mut head = RobustListHead {
+            next: 0,
+            futex_offset: core::mem::offset_of!(RobustLock, word) as c_long,
+            list_op_pending: 0,
+        };
+        let head_addr = &raw const head as usize;
+        // held, held by another thread, and being locked
+        let mut locks = [tid, tid + 1, tid | FUTEX_WAITERS].map(|val| RobustLock {
+            next: 0,
+            word: AtomicU32::new(val),
+        });
+        let addr = |locks: &[RobustLock; 3], i: usize| &raw const locks[i] as usize;
+        // head -> 1 -> 0 -> head, with 2 pending
+        locks[0].next = head_addr;
+        locks[1].next = addr(&locks, 0);
+        head.next = addr(&locks, 1);
+        head.list_op_pending = addr(&locks, 2);
+
+        let len = size_of::<RobustListHead>();
+        assert_eq!(
+            sys_set_robust_list(head_addr as _, len + 1),
+            -LinuxError::EINVAL.code()
+        );
+        assert_eq!(sys_set_robust_list(head_addr as _, len), 0);
+        let (mut got_head, mut got_len) = (core::ptr::null(), 0);
+        assert_eq!(
+            unsafe { sys_get_robust_list(0, &mut got_head, &mut got_len) },
+            0
+        );
+        assert_eq!((got_head as usize, got_len), (head_addr, len));
+
+        exit_robust_list();
+        assert_eq!(locks[0].word.load(Ordering::SeqCst), FUTEX_OWNER_DIED);
+        assert_eq!(locks[1].word.load(Ordering::SeqCst), tid + 1);
+        assert_eq!(
+            locks[2].word.load(Ordering::SeqCst),
+            FUTEX_WAITERS | FUTEX_OWNER_DIED
+        );
+        // the list is released once
+        assert_eq!(
+            unsafe { sys_get_robust_list(0, &mut got_head, &mut got_len) },
+            0
+        );
+        assert!(got_head.is_null());
+    }
+}
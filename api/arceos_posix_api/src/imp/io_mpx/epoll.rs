@@ -199,7 +199,7 @@ pub unsafe fn sys_epoll_wait(
                 debug!("    timeout!");
                 return Ok(0);
             }
-            crate::sys_sched_yield();
+            super::yield_interruptible()?;
         }
     })
 }
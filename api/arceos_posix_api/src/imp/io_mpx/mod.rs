@@ -14,3 +14,20 @@ mod select;
 pub use self::epoll::{sys_epoll_create, sys_epoll_ctl, sys_epoll_wait};
 #[cfg(feature = "select")]
 pub use self::select::sys_select;
+
+/// Yields the CPU between two polls of the file descriptors.
+///
+/// It fails with `EINTR` if a signal interrupts the wait: `select` and
+/// `epoll_wait` are never restarted, as on Linux.
+fn yield_interruptible() -> axerrno::LinuxResult {
+    #[cfg(feature = "multitask")]
+    {
+        axtask::yield_now();
+        crate::imp::signal::check_interrupt(false)
+    }
+    #[cfg(not(feature = "multitask"))]
+    {
+        crate::sys_sched_yield();
+        Ok(())
+    }
+}
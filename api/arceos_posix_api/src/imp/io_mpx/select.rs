@@ -145,7 +145,7 @@ pub unsafe fn sys_select(
                 debug!("    timeout!");
                 return Ok(0);
             }
-            crate::sys_sched_yield();
+            super::yield_interruptible()?;
         }
     })
 }
@@ -0,0 +1,199 @@
+//! Kcov-style coverage collection for fuzzing.
+//!
+//! The Rust code is built with `-C passes=sancov-module` and
+//! `-C llvm-args=-sanitizer-coverage-trace-pc-guard` (`make KCOV=y`), so
+//! that every edge calls `__sanitizer_cov_trace_pc_guard` with the address of
+//! its guard, a unique ID of the edge. The hook lives in axlibc and is written
+//! in C, which is not instrumented, so it can stop the recursion before
+//! calling [`trace_edge`]. A task collects the edges it covers after enabling
+//! tracing on a file opened from `/dev/kcov`:
+//!
+//! ```c
+//! int fd = open("/dev/kcov", O_RDWR);
+//! ioctl(fd, KCOV_INIT_TRACE, COVER_SIZE);
+//! ioctl(fd, KCOV_ENABLE, KCOV_TRACE_PC);
+//! /* ... run syscalls ... */
+//! read(fd, cover, COVER_SIZE * sizeof(uint64_t));
+//! ioctl(fd, KCOV_DISABLE, 0);
+//! ```
+//!
+//! There's no `mmap`, so the trace is read with `read`, which returns the
+//! number of entries as a `u64` followed by the entries. `KCOV_ENABLE` resets
+//! the trace.
+//!
+//! Only one CPU records at a time: the edges covered on other CPUs while one
+//! is in the hook are dropped.
+
+use alloc::{boxed::Box, collections::BTreeMap, sync::Arc};
+use core::ffi::c_int;
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use axerrno::{LinuxError, LinuxResult};
+use axio::PollState;
+use spin::{Mutex, RwLock};
+
+use super::fd_ops::{FileLike, add_file_like};
+use crate::ctypes;
+
+/// The path of the coverage device.
+pub(crate) const KCOV_PATH: &str = "/dev/kcov";
+
+const KCOV_INIT_TRACE: u32 = 0x8008_6301;
+const KCOV_ENABLE: u32 = 0x6364;
+const KCOV_DISABLE: u32 = 0x6365;
+const KCOV_TRACE_PC: usize = 0;
+
+/// The maximum number of entries in a trace.
+const MAX_TRACE_SIZE: usize = 1 << 24;
+
+/// The trace buffer, whose first word is the number of entries.
+struct Area {
+    buf: Box<[AtomicU64]>,
+}
+
+impl Area {
+    fn new(size: usize) -> Self {
+        Self {
+            buf: (0..size).map(|_| AtomicU64::new(0)).collect(),
+        }
+    }
+
+    fn push(&self, entry: u64) {
+        let n = self.buf[0].load(Ordering::Relaxed) as usize;
+        if n + 1 < self.buf.len() {
+            self.buf[n + 1].store(entry, Ordering::Relaxed);
+            self.buf[0].store(n as u64 + 1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// The number of tasks being traced, to make the hook cheap when it's zero.
+static TRACING: AtomicUsize = AtomicUsize::new(0);
+static AREAS: RwLock<BTreeMap<u64, Arc<Area>>> = RwLock::new(BTreeMap::new());
+
+/// Records the edge `guard` in the trace of the current task, if it's being
+/// traced.
+///
+/// It must not be reentered, as everything it calls is instrumented too.
+pub fn trace_edge(guard: usize) {
+    if TRACING.load(Ordering::Relaxed) == 0 {
+        return;
+    }
+    if let Some(area) = AREAS.read().get(&axtask::current().id().as_u64()) {
+        area.push(guard as u64);
+    }
+}
+
+/// A file opened from `/dev/kcov`.
+struct Kcov {
+    area: Mutex<Option<Arc<Area>>>,
+}
+
+impl Kcov {
+    fn enable(&self) -> LinuxResult {
+        let area = self.area.lock().clone().ok_or(LinuxError::EINVAL)?;
+        let tid = axtask::current().id().as_u64();
+        let mut areas = AREAS.write();
+        if areas.contains_key(&tid) || areas.values().any(|a| Arc::ptr_eq(a, &area)) {
+            return Err(LinuxError::EBUSY);
+        }
+        area.buf[0].store(0, Ordering::Relaxed);
+        areas.insert(tid, area);
+        TRACING.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn disable(&self) -> LinuxResult {
+        let area = self.area.lock().clone().ok_or(LinuxError::EINVAL)?;
+        let tid = axtask::current().id().as_u64();
+        let mut areas = AREAS.write();
+        match areas.get(&tid) {
+            Some(a) if Arc::ptr_eq(a, &area) => {
+                areas.remove(&tid);
+                TRACING.fetch_sub(1, Ordering::Relaxed);
+                Ok(())
+            }
+            _ => Err(LinuxError::EINVAL),
+        }
+    }
+}
+
+impl Drop for Kcov {
+    fn drop(&mut self) {
+        if let Some(area) = self.area.get_mut() {
+            let mut areas = AREAS.write();
+            let len = areas.len();
+            areas.retain(|_, a| !Arc::ptr_eq(a, area));
+            TRACING.fetch_sub(len - areas.len(), Ordering::Relaxed);
+        }
+    }
+}
+
+impl FileLike for Kcov {
+    fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        let area = self.area.lock().clone().ok_or(LinuxError::EINVAL)?;
+        let n = area.buf[0].load(Ordering::Relaxed) as usize;
+        let mut len = 0;
+        for (word, chunk) in area.buf[..n + 1].iter().zip(buf.chunks_exact_mut(8)) {
+            chunk.copy_from_slice(&word.load(Ordering::Relaxed).to_ne_bytes());
+            len += 8;
+        }
+        Ok(len)
+    }
+
+    fn write(&self, _buf: &[u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EINVAL)
+    }
+
+    fn stat(&self) -> LinuxResult<ctypes::stat> {
+        let st_mode = 0o20000 | 0o600u32; // S_IFCHR | rw-------
+        Ok(ctypes::stat {
+            st_ino: 1,
+            st_nlink: 1,
+            st_mode,
+            ..Default::default()
+        })
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn core::any::Any + Send + Sync> {
+        self
+    }
+
+    fn poll(&self) -> LinuxResult<PollState> {
+        Ok(PollState {
+            readable: true,
+            writable: false,
+        })
+    }
+
+    fn set_nonblocking(&self, _nonblocking: bool) -> LinuxResult {
+        Ok(())
+    }
+
+    fn ioctl(&self, cmd: u32, arg: usize) -> LinuxResult<c_int> {
+        match cmd {
+            KCOV_INIT_TRACE => {
+                if !(2..=MAX_TRACE_SIZE).contains(&arg) {
+                    return Err(LinuxError::EINVAL);
+                }
+                let mut area = self.area.lock();
+                if area.is_some() {
+                    return Err(LinuxError::EBUSY);
+                }
+                *area = Some(Arc::new(Area::new(arg)));
+            }
+            KCOV_ENABLE if arg == KCOV_TRACE_PC => self.enable()?,
+            KCOV_ENABLE => return Err(LinuxError::EOPNOTSUPP),
+            KCOV_DISABLE => self.disable()?,
+            _ => return Err(LinuxError::ENOTTY),
+        }
+        Ok(0)
+    }
+}
+
+/// Opens a new coverage collection file.
+pub(crate) fn open() -> LinuxResult<c_int> {
+    add_file_like(Arc::new(Kcov {
+        area: Mutex::new(None),
+    }))
+}
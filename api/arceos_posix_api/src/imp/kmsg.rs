@@ -0,0 +1,342 @@
+//! The kernel log, read with `syslog(2)` or from `/proc/kmsg`.
+//!
+//! The records are formatted as by Linux, one per line:
+//! `<priority>[  secs.usecs] text`, where the priority is the one of
+//! `syslog(3)` matching the level of the record. Reading with
+//! `SYSLOG_ACTION_READ` or from `/proc/kmsg` consumes the records, and blocks
+//! until there are some, while `SYSLOG_ACTION_READ_ALL` only reads the latest
+//! ones. There's a single reader position, shared by both.
+//!
+//! The levels of the records logged by each target are read from and written
+//! to `/proc/sys/kernel/log_filter`, e.g. `echo arceos_posix_api=warn >
+//! /proc/sys/kernel/log_filter` silences the debug lines of the syscalls.
+//! What's written is applied on the current filter, keeping the levels of the
+//! other targets.
+
+use core::ffi::{c_char, c_int};
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use axerrno::{LinuxError, LinuxResult};
+use axlog::{KMSG_LEN, KMSG_TEXT_MAX, KmsgRecord, Level, LevelFilter};
+
+#[cfg(feature = "fs")]
+use {
+    super::fd_ops::{FileLike, add_file_like},
+    crate::ctypes,
+    alloc::{string::ToString, sync::Arc},
+    axio::PollState,
+};
+
+/// The path of the kernel log file.
+#[cfg(feature = "fs")]
+pub(crate) const KMSG_PATH: &str = "/proc/kmsg";
+
+/// The path of the file of the log filter.
+#[cfg(feature = "fs")]
+pub(crate) const LOG_FILTER_PATH: &str = "/proc/sys/kernel/log_filter";
+
+const SYSLOG_ACTION_CLOSE: c_int = 0;
+const SYSLOG_ACTION_OPEN: c_int = 1;
+const SYSLOG_ACTION_READ: c_int = 2;
+const SYSLOG_ACTION_READ_ALL: c_int = 3;
+const SYSLOG_ACTION_READ_CLEAR: c_int = 4;
+const SYSLOG_ACTION_CLEAR: c_int = 5;
+const SYSLOG_ACTION_CONSOLE_OFF: c_int = 6;
+const SYSLOG_ACTION_CONSOLE_ON: c_int = 7;
+const SYSLOG_ACTION_CONSOLE_LEVEL: c_int = 8;
+const SYSLOG_ACTION_SIZE_UNREAD: c_int = 9;
+const SYSLOG_ACTION_SIZE_BUFFER: c_int = 10;
+
+/// The sequence number of the next record to be consumed.
+static READ_SEQ: AtomicU64 = AtomicU64::new(0);
+
+/// The sequence number of the oldest record not cleared by
+/// `SYSLOG_ACTION_CLEAR`.
+static CLEAR_SEQ: AtomicU64 = AtomicU64::new(0);
+
+/// The console level before `SYSLOG_ACTION_CONSOLE_OFF`, as a [`LevelFilter`].
+static SAVED_CONSOLE_LEVEL: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+/// A record formatted as a line.
+struct Line {
+    buf: [u8; KMSG_TEXT_MAX + 32],
+    len: usize,
+}
+
+impl Write for Line {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let len = s.len().min(self.buf.len() - self.len);
+        self.buf[self.len..self.len + len].copy_from_slice(&s.as_bytes()[..len]);
+        self.len += len;
+        Ok(())
+    }
+}
+
+impl Line {
+    fn new(record: &KmsgRecord) -> Self {
+        let priority = match record.level {
+            Level::Error => 3,                // LOG_ERR
+            Level::Warn => 4,                 // LOG_WARNING
+            Level::Info => 6,                 // LOG_INFO
+            Level::Debug | Level::Trace => 7, // LOG_DEBUG
+        };
+        let mut line = Self {
+            buf: [0; KMSG_TEXT_MAX + 32],
+            len: 0,
+        };
+        writeln!(line, "<{}>{}", priority, record).ok();
+        line
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+}
+
+/// Copies the records from `seq` on to `buf` as lines, as many as fit, and
+/// returns the number of bytes copied and the sequence number of the next
+/// record.
+///
+/// A first line longer than `buf` is truncated.
+fn copy_lines(mut seq: u64, buf: &mut [u8]) -> (usize, u64) {
+    let mut len = 0;
+    while let Some(record) = axlog::kmsg_read(seq) {
+        let line = Line::new(&record);
+        let line = line.as_bytes();
+        if len + line.len() > buf.len() {
+            if len == 0 {
+                buf.copy_from_slice(&line[..buf.len()]);
+                return (buf.len(), record.seq + 1);
+            }
+            break;
+        }
+        buf[len..len + line.len()].copy_from_slice(line);
+        len += line.len();
+        seq = record.seq + 1;
+    }
+    (len, seq)
+}
+
+/// Returns the number of bytes of the records from `seq` on as lines.
+fn lines_len(mut seq: u64) -> usize {
+    let mut len = 0;
+    while let Some(record) = axlog::kmsg_read(seq) {
+        len += Line::new(&record).len;
+        seq = record.seq + 1;
+    }
+    len
+}
+
+/// Consumes the records, blocking until there are some.
+fn read(buf: &mut [u8]) -> LinuxResult<usize> {
+    loop {
+        let seq = READ_SEQ.load(Ordering::Acquire);
+        if seq < axlog::kmsg_next_seq() {
+            let (len, next) = copy_lines(seq, buf);
+            READ_SEQ.fetch_max(next, Ordering::AcqRel);
+            return Ok(len);
+        }
+        #[cfg(feature = "multitask")]
+        super::signal::check_interrupt(true)?;
+        crate::sys_sched_yield();
+    }
+}
+
+/// Reads the latest records not cleared, as many as fit in `buf`.
+fn read_all(buf: &mut [u8]) -> usize {
+    let mut seq = CLEAR_SEQ
+        .load(Ordering::Acquire)
+        .max(axlog::kmsg_first_seq());
+    let mut total = lines_len(seq);
+    while total > buf.len() {
+        match axlog::kmsg_read(seq) {
+            Some(record) => {
+                total -= Line::new(&record).len;
+                seq = record.seq + 1;
+            }
+            None => break,
+        }
+    }
+    copy_lines(seq, buf).0
+}
+
+/// Reads or controls the kernel log, as `syslog(2)`.
+///
+/// `buf` and `len` are only used by the actions reading.
+pub fn sys_syslog(typ: c_int, buf: *mut c_char, len: c_int) -> c_int {
+    debug!("sys_syslog <= {} {:#x} {}", typ, buf as usize, len);
+    syscall_body!(sys_syslog, {
+        let user_buf = || {
+            if len < 0 || buf.is_null() {
+                return Err(LinuxError::EINVAL);
+            }
+            Ok(unsafe { core::slice::from_raw_parts_mut(buf as *mut u8, len as usize) })
+        };
+        match typ {
+            SYSLOG_ACTION_CLOSE | SYSLOG_ACTION_OPEN => Ok(0),
+            SYSLOG_ACTION_READ => {
+                let buf = user_buf()?;
+                if buf.is_empty() {
+                    return Ok(0);
+                }
+                Ok(read(buf)? as c_int)
+            }
+            SYSLOG_ACTION_READ_ALL | SYSLOG_ACTION_READ_CLEAR => {
+                let len = read_all(user_buf()?);
+                if typ == SYSLOG_ACTION_READ_CLEAR {
+                    CLEAR_SEQ.store(axlog::kmsg_next_seq(), Ordering::Release);
+                }
+                Ok(len as c_int)
+            }
+            SYSLOG_ACTION_CLEAR => {
+                CLEAR_SEQ.store(axlog::kmsg_next_seq(), Ordering::Release);
+                Ok(0)
+            }
+            SYSLOG_ACTION_CONSOLE_OFF => {
+                let level = axlog::console_level() as usize;
+                if SAVED_CONSOLE_LEVEL
+                    .compare_exchange(usize::MAX, level, Ordering::AcqRel, Ordering::Acquire)
+                    .is_ok()
+                {
+                    axlog::set_console_level(LevelFilter::Off);
+                }
+                Ok(0)
+            }
+            SYSLOG_ACTION_CONSOLE_ON => {
+                let level = SAVED_CONSOLE_LEVEL.swap(usize::MAX, Ordering::AcqRel);
+                if let Some(level) = LevelFilter::iter().nth(level) {
+                    axlog::set_console_level(level);
+                }
+                Ok(0)
+            }
+            SYSLOG_ACTION_CONSOLE_LEVEL => {
+                // the records of a priority less than `len` are printed
+                let level = match len {
+                    1..=3 => LevelFilter::Off,
+                    4 => LevelFilter::Error,
+                    5 | 6 => LevelFilter::Warn,
+                    7 => LevelFilter::Info,
+                    8 => LevelFilter::Trace,
+                    _ => return Err(LinuxError::EINVAL),
+                };
+                SAVED_CONSOLE_LEVEL.store(usize::MAX, Ordering::Release);
+                axlog::set_console_level(level);
+                Ok(0)
+            }
+            SYSLOG_ACTION_SIZE_UNREAD => Ok(lines_len(READ_SEQ.load(Ordering::Acquire)) as c_int),
+            SYSLOG_ACTION_SIZE_BUFFER => Ok(KMSG_LEN as c_int),
+            _ => Err(LinuxError::EINVAL),
+        }
+    })
+}
+
+/// `/proc/kmsg`, reading as `SYSLOG_ACTION_READ`.
+#[cfg(feature = "fs")]
+struct ProcKmsg;
+
+#[cfg(feature = "fs")]
+impl FileLike for ProcKmsg {
+    fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        read(buf)
+    }
+
+    fn write(&self, _buf: &[u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EBADF)
+    }
+
+    fn stat(&self) -> LinuxResult<ctypes::stat> {
+        let st_mode = 0o100000 | 0o400u32; // S_IFREG | r--------
+        Ok(ctypes::stat {
+            st_ino: 1,
+            st_nlink: 1,
+            st_mode,
+            st_blksize: 1024,
+            ..Default::default()
+        })
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn core::any::Any + Send + Sync> {
+        self
+    }
+
+    fn poll(&self) -> LinuxResult<PollState> {
+        Ok(PollState {
+            readable: READ_SEQ.load(Ordering::Acquire) < axlog::kmsg_next_seq(),
+            writable: false,
+        })
+    }
+
+    fn set_nonblocking(&self, _nonblocking: bool) -> LinuxResult {
+        Ok(())
+    }
+}
+
+/// Opens the kernel log file.
+#[cfg(feature = "fs")]
+pub(crate) fn open() -> LinuxResult<c_int> {
+    add_file_like(Arc::new(ProcKmsg))
+}
+
+/// `/proc/sys/kernel/log_filter`.
+#[cfg(feature = "fs")]
+struct ProcLogFilter {
+    /// The offset of the next read.
+    pos: AtomicUsize,
+}
+
+#[cfg(feature = "fs")]
+impl FileLike for ProcLogFilter {
+    fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        let mut filter = axlog::log_filter().to_string();
+        filter.push('\n');
+        let pos = self.pos.load(Ordering::Acquire).min(filter.len());
+        let len = buf.len().min(filter.len() - pos);
+        buf[..len].copy_from_slice(&filter.as_bytes()[pos..pos + len]);
+        self.pos.fetch_add(len, Ordering::AcqRel);
+        Ok(len)
+    }
+
+    fn write(&self, buf: &[u8]) -> LinuxResult<usize> {
+        let spec = core::str::from_utf8(buf).map_err(|_| LinuxError::EINVAL)?;
+        axlog::update_log_filter(spec).map_err(|_| LinuxError::EINVAL)?;
+        Ok(buf.len())
+    }
+
+    fn stat(&self) -> LinuxResult<ctypes::stat> {
+        let st_mode = 0o100000 | 0o644u32; // S_IFREG | rw-r--r--
+        Ok(ctypes::stat {
+            st_ino: 1,
+            st_nlink: 1,
+            st_mode,
+            st_blksize: 1024,
+            ..Default::default()
+        })
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn core::any::Any + Send + Sync> {
+        self
+    }
+
+    fn poll(&self) -> LinuxResult<PollState> {
+        Ok(PollState {
+            readable: true,
+            writable: true,
+        })
+    }
+
+    fn set_nonblocking(&self, _nonblocking: bool) -> LinuxResult {
+        Ok(())
+    }
+}
+
+/// Opens the file of the log filter.
+#[cfg(feature = "fs")]
+pub(crate) fn open_log_filter() -> LinuxResult<c_int> {
+    add_file_like(Arc::new(ProcLogFilter {
+        pos: AtomicUsize::new(0),
+    }))
+}
@@ -0,0 +1,129 @@
+//! Memory mappings of files (`mmap`).
+//!
+//! Only the shared mappings of the files backed by memory, like the
+//! framebuffer `/dev/fb0`, are supported: the memory of the file is mapped
+//! once more in the kernel address space, where the programs run. There are
+//! no anonymous or private mappings.
+
+use alloc::{collections::BTreeMap, sync::Arc};
+use core::ffi::{c_int, c_void};
+
+use axerrno::LinuxError;
+use axhal::mem::{MemoryAddr, VirtAddr};
+use axhal::paging::MappingFlags;
+use memory_addr::VirtAddrRange;
+use spin::Mutex;
+
+use super::fd_ops::{FileLike, get_file_like};
+use crate::ctypes;
+
+/// The mappings by their start address: their size and their file.
+static MAPPINGS: Mutex<BTreeMap<usize, (usize, Arc<dyn FileLike>)>> = Mutex::new(BTreeMap::new());
+
+fn prot_to_flags(prot: c_int) -> MappingFlags {
+    let prot = prot as u32;
+    let mut flags = MappingFlags::empty();
+    if prot & ctypes::PROT_READ != 0 {
+        flags |= MappingFlags::READ;
+    }
+    if prot & ctypes::PROT_WRITE != 0 {
+        flags |= MappingFlags::WRITE;
+    }
+    if prot & ctypes::PROT_EXEC != 0 {
+        flags |= MappingFlags::EXECUTE;
+    }
+    flags
+}
+
+/// Maps `len` bytes at `off` of the file `fd`, and returns the address of
+/// the mapping.
+///
+/// `addr` is only a hint, `MAP_FIXED` is not supported. Without a hint, the
+/// mappings are placed from a random address (see [`axmm::AddrLayout`]).
+///
+/// The mappings are in the kernel address space, which refuses the ones both
+/// writable and executable (W^X) with `EACCES`.
+pub fn sys_mmap(
+    addr: *mut c_void,
+    len: ctypes::size_t,
+    prot: c_int,
+    flags: c_int,
+    fd: c_int,
+    off: ctypes::off_t,
+) -> *mut c_void {
+    debug!(
+        "sys_mmap <= addr: {:#x}, len: {}, prot: {:#x}, flags: {:#x}, fd: {}, off: {}",
+        addr as usize, len, prot, flags, fd, off
+    );
+    syscall_body!(sys_mmap, {
+        let flags = flags as u32;
+        if len == 0 || off < 0 || !(off as usize).is_aligned_4k() {
+            return Err(LinuxError::EINVAL);
+        }
+        if flags & ctypes::MAP_FIXED != 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        if flags & ctypes::MAP_ANONYMOUS != 0 {
+            return Err(LinuxError::EOPNOTSUPP);
+        }
+        match flags & ctypes::MAP_TYPE {
+            ctypes::MAP_SHARED | ctypes::MAP_SHARED_VALIDATE => {}
+            ctypes::MAP_PRIVATE => return Err(LinuxError::EOPNOTSUPP),
+            _ => return Err(LinuxError::EINVAL),
+        }
+
+        let file = get_file_like(fd)?;
+        let size = (len as usize).align_up_4k();
+        let paddr = file.mmap(off as usize, size)?;
+        let mut aspace = axmm::kernel_aspace().lock();
+        let limit = VirtAddrRange::new(aspace.base(), aspace.end());
+        // without a hint, from the randomized base of the mappings
+        let hint = if addr.is_null() {
+            aspace.layout().mmap_base()
+        } else {
+            VirtAddr::from(addr as usize)
+                .align_down_4k()
+                .max(aspace.base())
+        };
+        let res = aspace
+            .find_free_area(hint, size, limit)
+            .or_else(|| aspace.find_free_area(aspace.base(), size, limit))
+            .ok_or(LinuxError::ENOMEM)
+            .and_then(|start| {
+                aspace.map_linear(start, paddr, size, prot_to_flags(prot))?;
+                Ok(start)
+            });
+        let start = match res {
+            Ok(start) => start,
+            Err(e) => {
+                file.munmap();
+                return Err(e);
+            }
+        };
+        MAPPINGS.lock().insert(start.as_usize(), (size, file));
+        Ok(start.as_mut_ptr() as *mut c_void)
+    })
+}
+
+/// Removes the mapping at `addr`.
+///
+/// The whole mapping must be removed at once.
+pub fn sys_munmap(addr: *mut c_void, len: ctypes::size_t) -> c_int {
+    debug!("sys_munmap <= addr: {:#x}, len: {}", addr as usize, len);
+    syscall_body!(sys_munmap, {
+        let start = addr as usize;
+        let mut mappings = MAPPINGS.lock();
+        let size = match mappings.get(&start) {
+            Some(&(size, _)) if (len as usize).align_up_4k() == size => size,
+            Some(_) => return Err(LinuxError::EINVAL),
+            // nothing mapped there
+            None if start.is_aligned_4k() => return Ok(0),
+            None => return Err(LinuxError::EINVAL),
+        };
+        axmm::kernel_aspace().lock().unmap(start.into(), size)?;
+        let (_, file) = mappings.remove(&start).unwrap();
+        drop(mappings);
+        file.munmap();
+        Ok(0)
+    })
+}
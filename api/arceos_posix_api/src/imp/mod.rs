@@ -1,22 +1,48 @@
 mod stdio;
+mod tty;
 
 pub mod io;
+pub mod kmsg;
 pub mod resources;
 pub mod sys;
 pub mod task;
 pub mod time;
 
+#[cfg(feature = "alloc")]
+pub mod env;
+#[cfg(feature = "display")]
+pub mod fb;
 #[cfg(feature = "fd")]
 pub mod fd_ops;
 #[cfg(feature = "fs")]
+pub mod file_lock;
+#[cfg(feature = "fs")]
 pub mod fs;
+#[cfg(feature = "multitask")]
+pub mod futex;
 #[cfg(any(feature = "select", feature = "epoll"))]
 pub mod io_mpx;
+#[cfg(feature = "kcov")]
+pub mod kcov;
+#[cfg(feature = "mmap")]
+pub mod mman;
 #[cfg(feature = "net")]
 pub mod net;
 #[cfg(feature = "fs")]
 pub mod path_link;
 #[cfg(feature = "pipe")]
 pub mod pipe;
+#[cfg(feature = "prof")]
+pub mod prof;
 #[cfg(feature = "multitask")]
 pub mod pthread;
+#[cfg(feature = "pty")]
+pub mod pty;
+#[cfg(feature = "multitask")]
+pub mod session;
+#[cfg(feature = "multitask")]
+pub mod signal;
+#[cfg(feature = "multitask")]
+pub mod timer;
+#[cfg(feature = "trace")]
+pub mod trace;
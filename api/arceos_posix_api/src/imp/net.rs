@@ -1,20 +1,48 @@
-use alloc::{sync::Arc, vec, vec::Vec};
+use alloc::{string::ToString, sync::Arc, vec, vec::Vec};
 use core::ffi::{c_char, c_int, c_void};
 use core::mem::size_of;
 use core::net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4};
+use core::time::Duration;
 
-use axerrno::{LinuxError, LinuxResult};
+use axerrno::{AxError, LinuxError, LinuxResult};
 use axio::PollState;
-use axnet::{TcpSocket, UdpSocket};
+use axnet::{BpfInsn, BpfProgram, IcmpSocket, RawSocket, TcpInfo, TcpSocket, UdpSocket};
 use axsync::Mutex;
 
-use super::fd_ops::FileLike;
+use super::fd_ops::{FIONREAD, FileLike, ioctl_write_int};
 use crate::ctypes;
 use crate::utils::char_ptr_to_str;
 
+/// Upper bound of `TCP_KEEPIDLE` and `TCP_KEEPINTVL`, in seconds.
+const TCP_MAX_KEEPALIVE_SECS: c_int = 32767;
+/// Upper bound of `TCP_KEEPCNT`.
+const TCP_MAX_KEEPALIVE_COUNT: c_int = 127;
+
+/// Options of a socket, as seen by `getsockopt` and `setsockopt`.
+#[derive(Debug, Default, Clone, Copy)]
+struct SocketOptions {
+    reuse_addr: bool,
+    reuse_port: bool,
+    keepalive: bool,
+    keepalive_idle: Duration,
+    keepalive_interval: Duration,
+    keepalive_count: u32,
+    nodelay: bool,
+    recv_timeout: Option<Duration>,
+    send_timeout: Option<Duration>,
+    recv_buf_size: usize,
+    send_buf_size: usize,
+    header_included: bool,
+    max_pacing_rate: Option<u64>,
+    multicast_ttl: u8,
+    segment_size: Option<u16>,
+}
+
 pub enum Socket {
     Udp(Mutex<UdpSocket>),
     Tcp(Mutex<TcpSocket>),
+    Icmp(Mutex<IcmpSocket>),
+    Raw(Mutex<RawSocket>),
 }
 
 impl Socket {
@@ -29,24 +57,83 @@ impl Socket {
             .map_err(|_| LinuxError::EINVAL)
     }
 
-    fn send(&self, buf: &[u8]) -> LinuxResult<usize> {
+    fn is_nonblocking(&self) -> bool {
         match self {
+            Socket::Udp(udpsocket) => udpsocket.lock().is_nonblocking(),
+            Socket::Tcp(tcpsocket) => tcpsocket.lock().is_nonblocking(),
+            Socket::Icmp(icmpsocket) => icmpsocket.lock().is_nonblocking(),
+            Socket::Raw(rawsocket) => rawsocket.lock().is_nonblocking(),
+        }
+    }
+
+    /// Returns whether a blocking call on the socket has been interrupted by
+    /// a signal.
+    #[cfg(feature = "multitask")]
+    fn interrupted(&self) -> bool {
+        axtask::interrupt_pending() && !self.is_nonblocking()
+    }
+
+    /// Runs the blocking call `op`, and handles the signals interrupting it.
+    ///
+    /// The interrupted call is restarted if the handlers were installed with
+    /// `SA_RESTART`, unless the socket has a timeout in the direction given
+    /// by `sending`, as on Linux. Otherwise, it fails with `EINTR`.
+    #[cfg(feature = "multitask")]
+    fn interruptible<T>(
+        &self,
+        sending: bool,
+        mut op: impl FnMut() -> LinuxResult<T>,
+    ) -> LinuxResult<T> {
+        loop {
+            match op() {
+                Err(LinuxError::EAGAIN) if self.interrupted() => {
+                    let opts = self.options();
+                    let timeout = if sending {
+                        opts.send_timeout
+                    } else {
+                        opts.recv_timeout
+                    };
+                    super::signal::check_interrupt(timeout.is_none())?;
+                }
+                res => return res,
+            }
+        }
+    }
+
+    /// Without tasks, there's no signal to interrupt the call.
+    #[cfg(not(feature = "multitask"))]
+    fn interruptible<T>(
+        &self,
+        _sending: bool,
+        op: impl FnOnce() -> LinuxResult<T>,
+    ) -> LinuxResult<T> {
+        op()
+    }
+
+    fn send(&self, buf: &[u8]) -> LinuxResult<usize> {
+        self.interruptible(true, || match self {
             Socket::Udp(udpsocket) => Ok(udpsocket.lock().send(buf)?),
             Socket::Tcp(tcpsocket) => Ok(tcpsocket.lock().send(buf)?),
-        }
+            Socket::Icmp(icmpsocket) => Ok(icmpsocket.lock().send(buf)?),
+            Socket::Raw(rawsocket) => Ok(rawsocket.lock().send(buf)?),
+        })
     }
 
     fn recv(&self, buf: &mut [u8]) -> LinuxResult<usize> {
-        match self {
+        self.interruptible(false, || match self {
             Socket::Udp(udpsocket) => Ok(udpsocket.lock().recv_from(buf).map(|e| e.0)?),
             Socket::Tcp(tcpsocket) => Ok(tcpsocket.lock().recv(buf)?),
-        }
+            Socket::Icmp(icmpsocket) => Ok(icmpsocket.lock().recv_from(buf).map(|e| e.0)?),
+            Socket::Raw(rawsocket) => Ok(rawsocket.lock().recv_from(buf).map(|e| e.0)?),
+        })
     }
 
     pub fn poll(&self) -> LinuxResult<PollState> {
         match self {
             Socket::Udp(udpsocket) => Ok(udpsocket.lock().poll()?),
             Socket::Tcp(tcpsocket) => Ok(tcpsocket.lock().poll()?),
+            Socket::Icmp(icmpsocket) => Ok(icmpsocket.lock().poll()?),
+            Socket::Raw(rawsocket) => Ok(rawsocket.lock().poll()?),
         }
     }
 
@@ -54,6 +141,12 @@ impl Socket {
         match self {
             Socket::Udp(udpsocket) => Ok(udpsocket.lock().local_addr()?),
             Socket::Tcp(tcpsocket) => Ok(tcpsocket.lock().local_addr()?),
+            // the identifier of a ping socket is reported as its port, as on Linux
+            Socket::Icmp(icmpsocket) => Ok(SocketAddr::new(
+                Ipv4Addr::UNSPECIFIED.into(),
+                icmpsocket.lock().ident(),
+            )),
+            Socket::Raw(_) => Ok(SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 0)),
         }
     }
 
@@ -61,6 +154,8 @@ impl Socket {
         match self {
             Socket::Udp(udpsocket) => Ok(udpsocket.lock().peer_addr()?),
             Socket::Tcp(tcpsocket) => Ok(tcpsocket.lock().peer_addr()?),
+            Socket::Icmp(icmpsocket) => Ok(SocketAddr::new(icmpsocket.lock().peer_addr()?, 0)),
+            Socket::Raw(rawsocket) => Ok(SocketAddr::new(rawsocket.lock().peer_addr()?, 0)),
         }
     }
 
@@ -68,46 +163,217 @@ impl Socket {
         match self {
             Socket::Udp(udpsocket) => Ok(udpsocket.lock().bind(addr)?),
             Socket::Tcp(tcpsocket) => Ok(tcpsocket.lock().bind(addr)?),
+            Socket::Icmp(_) | Socket::Raw(_) => Err(LinuxError::EOPNOTSUPP),
         }
     }
 
     fn connect(&self, addr: SocketAddr) -> LinuxResult {
         match self {
             Socket::Udp(udpsocket) => Ok(udpsocket.lock().connect(addr)?),
-            Socket::Tcp(tcpsocket) => Ok(tcpsocket.lock().connect(addr)?),
+            Socket::Tcp(tcpsocket) => {
+                let tcpsocket = tcpsocket.lock();
+                match tcpsocket.connect(addr) {
+                    Ok(()) => Ok(()),
+                    // the connection goes on after a signal, as on Linux
+                    #[cfg(feature = "multitask")]
+                    Err(AxError::WouldBlock) if self.interrupted() => {
+                        super::signal::handle_signals();
+                        Err(LinuxError::EINTR)
+                    }
+                    // nonblocking connect is initiated, the result is reported by `SO_ERROR`
+                    Err(AxError::WouldBlock) => Err(LinuxError::EINPROGRESS),
+                    Err(AxError::AlreadyExists) => {
+                        tcpsocket.poll()?; // update the state of the pending connection
+                        if let Some(e) = tcpsocket.take_error() {
+                            Err(e.into())
+                        } else if tcpsocket.peer_addr().is_ok() {
+                            Err(LinuxError::EISCONN)
+                        } else {
+                            Err(LinuxError::EALREADY)
+                        }
+                    }
+                    Err(e) => Err(e.into()),
+                }
+            }
+            Socket::Icmp(icmpsocket) => Ok(icmpsocket.lock().connect(addr.ip())?),
+            Socket::Raw(rawsocket) => Ok(rawsocket.lock().connect(addr.ip())?),
         }
     }
 
-    fn sendto(&self, buf: &[u8], addr: SocketAddr) -> LinuxResult<usize> {
+    fn take_error(&self) -> Option<LinuxError> {
         match self {
+            Socket::Udp(_) | Socket::Icmp(_) | Socket::Raw(_) => None,
+            Socket::Tcp(tcpsocket) => tcpsocket.lock().take_error().map(LinuxError::from),
+        }
+    }
+
+    fn sendto(&self, buf: &[u8], addr: SocketAddr) -> LinuxResult<usize> {
+        self.interruptible(true, || match self {
             // diff: must bind before sendto
             Socket::Udp(udpsocket) => Ok(udpsocket.lock().send_to(buf, addr)?),
             Socket::Tcp(_) => Err(LinuxError::EISCONN),
-        }
+            Socket::Icmp(icmpsocket) => Ok(icmpsocket.lock().send_to(buf, addr.ip())?),
+            Socket::Raw(rawsocket) => Ok(rawsocket.lock().send_to(buf, addr.ip())?),
+        })
     }
 
     fn recvfrom(&self, buf: &mut [u8]) -> LinuxResult<(usize, Option<SocketAddr>)> {
-        match self {
+        self.interruptible(false, || match self {
             // diff: must bind before recvfrom
             Socket::Udp(udpsocket) => Ok(udpsocket
                 .lock()
                 .recv_from(buf)
                 .map(|res| (res.0, Some(res.1)))?),
             Socket::Tcp(tcpsocket) => Ok(tcpsocket.lock().recv(buf).map(|res| (res, None))?),
-        }
+            Socket::Icmp(icmpsocket) => Ok(icmpsocket
+                .lock()
+                .recv_from(buf)
+                .map(|(len, ip)| (len, Some(SocketAddr::new(ip, 0))))?),
+            Socket::Raw(rawsocket) => Ok(rawsocket
+                .lock()
+                .recv_from(buf)
+                .map(|(len, ip)| (len, Some(SocketAddr::new(ip, 0))))?),
+        })
     }
 
     fn listen(&self) -> LinuxResult {
         match self {
-            Socket::Udp(_) => Err(LinuxError::EOPNOTSUPP),
+            Socket::Udp(_) | Socket::Icmp(_) | Socket::Raw(_) => Err(LinuxError::EOPNOTSUPP),
             Socket::Tcp(tcpsocket) => Ok(tcpsocket.lock().listen()?),
         }
     }
 
     fn accept(&self) -> LinuxResult<TcpSocket> {
-        match self {
-            Socket::Udp(_) => Err(LinuxError::EOPNOTSUPP),
+        self.interruptible(false, || match self {
+            Socket::Udp(_) | Socket::Icmp(_) | Socket::Raw(_) => Err(LinuxError::EOPNOTSUPP),
             Socket::Tcp(tcpsocket) => Ok(tcpsocket.lock().accept()?),
+        })
+    }
+
+    fn socket_type(&self) -> u32 {
+        match self {
+            Socket::Udp(_) => ctypes::SOCK_DGRAM,
+            Socket::Tcp(_) => ctypes::SOCK_STREAM,
+            Socket::Icmp(_) => ctypes::SOCK_DGRAM,
+            Socket::Raw(_) => ctypes::SOCK_RAW,
+        }
+    }
+
+    fn options(&self) -> SocketOptions {
+        match self {
+            Socket::Udp(udpsocket) => {
+                let udpsocket = udpsocket.lock();
+                SocketOptions {
+                    reuse_addr: udpsocket.reuse_addr(),
+                    recv_timeout: udpsocket.recv_timeout(),
+                    send_timeout: udpsocket.send_timeout(),
+                    recv_buf_size: udpsocket.recv_buffer_size(),
+                    send_buf_size: udpsocket.send_buffer_size(),
+                    max_pacing_rate: udpsocket.max_pacing_rate(),
+                    multicast_ttl: udpsocket.multicast_ttl_v4(),
+                    segment_size: udpsocket.segment_size(),
+                    ..Default::default()
+                }
+            }
+            Socket::Tcp(tcpsocket) => {
+                let tcpsocket = tcpsocket.lock();
+                SocketOptions {
+                    reuse_addr: tcpsocket.reuse_addr(),
+                    reuse_port: tcpsocket.reuse_port(),
+                    keepalive: tcpsocket.keepalive(),
+                    keepalive_idle: tcpsocket.keepalive_idle(),
+                    keepalive_interval: tcpsocket.keepalive_interval(),
+                    keepalive_count: tcpsocket.keepalive_count(),
+                    nodelay: tcpsocket.nodelay(),
+                    recv_timeout: tcpsocket.recv_timeout(),
+                    send_timeout: tcpsocket.send_timeout(),
+                    recv_buf_size: tcpsocket.recv_buffer_size(),
+                    send_buf_size: tcpsocket.send_buffer_size(),
+                    max_pacing_rate: tcpsocket.max_pacing_rate(),
+                    ..Default::default()
+                }
+            }
+            Socket::Icmp(icmpsocket) => {
+                let icmpsocket = icmpsocket.lock();
+                SocketOptions {
+                    recv_timeout: icmpsocket.recv_timeout(),
+                    send_timeout: icmpsocket.send_timeout(),
+                    recv_buf_size: icmpsocket.recv_buffer_size(),
+                    send_buf_size: icmpsocket.send_buffer_size(),
+                    ..Default::default()
+                }
+            }
+            Socket::Raw(rawsocket) => {
+                let rawsocket = rawsocket.lock();
+                SocketOptions {
+                    recv_timeout: rawsocket.recv_timeout(),
+                    send_timeout: rawsocket.send_timeout(),
+                    recv_buf_size: rawsocket.recv_buffer_size(),
+                    send_buf_size: rawsocket.send_buffer_size(),
+                    header_included: rawsocket.header_included(),
+                    ..Default::default()
+                }
+            }
+        }
+    }
+
+    fn set_options(&self, opts: &SocketOptions) -> LinuxResult {
+        match self {
+            Socket::Udp(udpsocket) => {
+                let udpsocket = udpsocket.lock();
+                // the fallible ones first, so that nothing changes on errors
+                udpsocket.set_segment_size(opts.segment_size)?;
+                udpsocket.set_reuse_addr(opts.reuse_addr);
+                udpsocket.set_recv_timeout(opts.recv_timeout);
+                udpsocket.set_send_timeout(opts.send_timeout);
+                udpsocket.set_max_pacing_rate(opts.max_pacing_rate);
+                udpsocket.set_multicast_ttl_v4(opts.multicast_ttl);
+            }
+            Socket::Tcp(tcpsocket) => {
+                let tcpsocket = tcpsocket.lock();
+                tcpsocket.set_reuse_addr(opts.reuse_addr);
+                tcpsocket.set_reuse_port(opts.reuse_port);
+                tcpsocket.set_keepalive_idle(opts.keepalive_idle);
+                tcpsocket.set_keepalive_interval(opts.keepalive_interval);
+                tcpsocket.set_keepalive_count(opts.keepalive_count);
+                tcpsocket.set_keepalive(opts.keepalive);
+                tcpsocket.set_nodelay(opts.nodelay);
+                tcpsocket.set_recv_timeout(opts.recv_timeout);
+                tcpsocket.set_send_timeout(opts.send_timeout);
+                tcpsocket.set_max_pacing_rate(opts.max_pacing_rate);
+            }
+            Socket::Icmp(icmpsocket) => {
+                let icmpsocket = icmpsocket.lock();
+                icmpsocket.set_recv_timeout(opts.recv_timeout);
+                icmpsocket.set_send_timeout(opts.send_timeout);
+            }
+            Socket::Raw(rawsocket) => {
+                let rawsocket = rawsocket.lock();
+                rawsocket.set_recv_timeout(opts.recv_timeout);
+                rawsocket.set_send_timeout(opts.send_timeout);
+                rawsocket.set_header_included(opts.header_included);
+            }
+        }
+        Ok(())
+    }
+
+    fn attach_filter(&self, program: BpfProgram) -> LinuxResult {
+        match self {
+            Socket::Udp(udpsocket) => udpsocket.lock().attach_filter(program),
+            Socket::Icmp(icmpsocket) => icmpsocket.lock().attach_filter(program),
+            Socket::Raw(rawsocket) => rawsocket.lock().attach_filter(program),
+            // the segments of a stream can't be dropped by the receiver
+            Socket::Tcp(_) => return Err(LinuxError::EOPNOTSUPP),
+        }
+        Ok(())
+    }
+
+    fn detach_filter(&self) -> LinuxResult {
+        match self {
+            Socket::Udp(udpsocket) => Ok(udpsocket.lock().detach_filter()?),
+            Socket::Icmp(icmpsocket) => Ok(icmpsocket.lock().detach_filter()?),
+            Socket::Raw(rawsocket) => Ok(rawsocket.lock().detach_filter()?),
+            Socket::Tcp(_) => Err(LinuxError::ENOENT),
         }
     }
 
@@ -126,6 +392,8 @@ impl Socket {
                 tcpsocket.shutdown()?;
                 Ok(())
             }
+            Socket::Icmp(icmpsocket) => Ok(icmpsocket.lock().shutdown()?),
+            Socket::Raw(rawsocket) => Ok(rawsocket.lock().shutdown()?),
         }
     }
 }
@@ -165,9 +433,23 @@ impl FileLike for Socket {
         match self {
             Socket::Udp(udpsocket) => udpsocket.lock().set_nonblocking(nonblock),
             Socket::Tcp(tcpsocket) => tcpsocket.lock().set_nonblocking(nonblock),
+            Socket::Icmp(icmpsocket) => icmpsocket.lock().set_nonblocking(nonblock),
+            Socket::Raw(rawsocket) => rawsocket.lock().set_nonblocking(nonblock),
         }
         Ok(())
     }
+
+    fn ioctl(&self, cmd: u32, arg: usize) -> LinuxResult<c_int> {
+        match (cmd, self) {
+            (FIONREAD, Socket::Tcp(tcpsocket)) => {
+                ioctl_write_int(arg, tcpsocket.lock().recv_queue_len())
+            }
+            (FIONREAD, Socket::Udp(udpsocket)) => {
+                ioctl_write_int(arg, udpsocket.lock().recv_queue_len())
+            }
+            _ => Err(LinuxError::ENOTTY),
+        }
+    }
 }
 
 impl From<SocketAddrV4> for ctypes::sockaddr_in {
@@ -232,18 +514,27 @@ fn from_sockaddr(
 pub fn sys_socket(domain: c_int, socktype: c_int, protocol: c_int) -> c_int {
     debug!("sys_socket <= {} {} {}", domain, socktype, protocol);
     let (domain, socktype, protocol) = (domain as u32, socktype as u32, protocol as u32);
+    let nonblock = socktype & ctypes::SOCK_NONBLOCK != 0;
+    let socktype = socktype & !(ctypes::SOCK_NONBLOCK | ctypes::SOCK_CLOEXEC);
     syscall_body!(sys_socket, {
-        match (domain, socktype, protocol) {
+        let socket = match (domain, socktype, protocol) {
             (ctypes::AF_INET, ctypes::SOCK_STREAM, ctypes::IPPROTO_TCP)
             | (ctypes::AF_INET, ctypes::SOCK_STREAM, 0) => {
-                Socket::Tcp(Mutex::new(TcpSocket::new())).add_to_fd_table()
+                Socket::Tcp(Mutex::new(TcpSocket::new()))
             }
             (ctypes::AF_INET, ctypes::SOCK_DGRAM, ctypes::IPPROTO_UDP)
-            | (ctypes::AF_INET, ctypes::SOCK_DGRAM, 0) => {
-                Socket::Udp(Mutex::new(UdpSocket::new())).add_to_fd_table()
+            | (ctypes::AF_INET, ctypes::SOCK_DGRAM, 0) => Socket::Udp(Mutex::new(UdpSocket::new())),
+            (ctypes::AF_INET, ctypes::SOCK_DGRAM, ctypes::IPPROTO_ICMP) => {
+                Socket::Icmp(Mutex::new(IcmpSocket::new()))
             }
-            _ => Err(LinuxError::EINVAL),
-        }
+            (ctypes::AF_INET, ctypes::SOCK_RAW, 0) => return Err(LinuxError::EPROTONOSUPPORT),
+            (ctypes::AF_INET, ctypes::SOCK_RAW, proto) if proto <= ctypes::IPPROTO_RAW => {
+                Socket::Raw(Mutex::new(RawSocket::new(proto as u8)))
+            }
+            _ => return Err(LinuxError::EINVAL),
+        };
+        socket.set_nonblocking(nonblock)?;
+        socket.add_to_fd_table()
     })
 }
 
@@ -443,14 +734,15 @@ pub fn sys_shutdown(
 
 /// Query addresses for a domain name.
 ///
-/// Only IPv4. Ports are always 0. Ignore servname and hint.
-/// Results' ai_flags and ai_canonname are 0 or NULL.
+/// Only IPv4. Only numeric `servname` is supported. `ai_socktype`,
+/// `ai_protocol` and the `AI_NUMERICHOST` and `AI_PASSIVE` flags of `hints`
+/// are respected. Results' ai_flags and ai_canonname are 0 or NULL.
 ///
-/// Return address number if success.
+/// Return address number if success, or 0 if the name does not exist.
 pub unsafe fn sys_getaddrinfo(
     nodename: *const c_char,
     servname: *const c_char,
-    _hints: *const ctypes::addrinfo,
+    hints: *const ctypes::addrinfo,
     res: *mut *mut ctypes::addrinfo,
 ) -> c_int {
     let name = char_ptr_to_str(nodename);
@@ -464,13 +756,38 @@ pub unsafe fn sys_getaddrinfo(
             return Err(LinuxError::EFAULT);
         }
 
+        let (flags, socktype, protocol) = if hints.is_null() {
+            (0, 0, 0)
+        } else {
+            let hints = unsafe { &*hints };
+            (hints.ai_flags, hints.ai_socktype, hints.ai_protocol)
+        };
+        let (socktype, protocol) = match (socktype as u32, protocol as u32) {
+            (0 | ctypes::SOCK_STREAM, 0 | ctypes::IPPROTO_TCP) => {
+                (ctypes::SOCK_STREAM, ctypes::IPPROTO_TCP)
+            }
+            (ctypes::SOCK_DGRAM, 0 | ctypes::IPPROTO_UDP) => {
+                (ctypes::SOCK_DGRAM, ctypes::IPPROTO_UDP)
+            }
+            (ctypes::SOCK_RAW, protocol) => (ctypes::SOCK_RAW, protocol),
+            _ => return Err(LinuxError::ESOCKTNOSUPPORT),
+        };
+
         let port = port.map_or(0, |p| p.parse::<u16>().unwrap_or(0));
         let ip_addrs = if let Ok(domain) = name {
             if let Ok(a) = domain.parse::<IpAddr>() {
                 vec![a]
+            } else if flags & ctypes::AI_NUMERICHOST as c_int != 0 {
+                return Ok(0);
             } else {
-                axnet::dns_query(domain)?
+                match axnet::dns_query(domain) {
+                    Ok(addrs) => addrs,
+                    Err(AxError::NotFound) => return Ok(0),
+                    Err(e) => return Err(e.into()),
+                }
             }
+        } else if flags & ctypes::AI_PASSIVE as c_int != 0 {
+            vec![Ipv4Addr::UNSPECIFIED.into()]
         } else {
             vec![Ipv4Addr::LOCALHOST.into()]
         };
@@ -486,9 +803,8 @@ pub unsafe fn sys_getaddrinfo(
                 IpAddr::V4(ip) => ctypes::aibuf {
                     ai: ctypes::addrinfo {
                         ai_family: ctypes::AF_INET as _,
-                        // TODO: This is a hard-code part, only return TCP parameters
-                        ai_socktype: ctypes::SOCK_STREAM as _,
-                        ai_protocol: ctypes::IPPROTO_TCP as _,
+                        ai_socktype: socktype as _,
+                        ai_protocol: protocol as _,
                         ai_addrlen: size_of::<ctypes::sockaddr_in>() as _,
                         ai_addr: core::ptr::null_mut(),
                         ai_canonname: core::ptr::null_mut(),
@@ -532,6 +848,76 @@ pub unsafe fn sys_freeaddrinfo(res: *mut ctypes::addrinfo) {
     drop(vec);
 }
 
+/// Copy `s` into the C string buffer `buf` of `len` bytes, with the
+/// terminating NUL.
+fn write_c_str(buf: *mut c_char, len: ctypes::socklen_t, s: &str) -> LinuxResult {
+    if s.len() >= len as usize {
+        return Err(LinuxError::ENOSPC);
+    }
+    unsafe {
+        core::ptr::copy_nonoverlapping(s.as_ptr(), buf as *mut u8, s.len());
+        *buf.add(s.len()) = 0;
+    }
+    Ok(())
+}
+
+/// Translate a socket address to a host name and a service name.
+///
+/// Only IPv4. The service is always numeric, as there is no services
+/// database. Errors that `getnameinfo` reports as `EAI_*` codes are returned
+/// as `EAFNOSUPPORT` (`EAI_FAMILY`), `ENOENT` (`EAI_NONAME`), `EAGAIN`
+/// (`EAI_AGAIN`) and `ENOSPC` (`EAI_OVERFLOW`).
+///
+/// Return 0 if success.
+pub unsafe fn sys_getnameinfo(
+    addr: *const ctypes::sockaddr,
+    addrlen: ctypes::socklen_t,
+    host: *mut c_char,
+    hostlen: ctypes::socklen_t,
+    serv: *mut c_char,
+    servlen: ctypes::socklen_t,
+    flags: c_int,
+) -> c_int {
+    debug!(
+        "sys_getnameinfo <= {:#x} {} {:#x} {} {:#x} {} {:#x}",
+        addr as usize, addrlen, host as usize, hostlen, serv as usize, servlen, flags
+    );
+    syscall_body!(sys_getnameinfo, {
+        if addr.is_null() {
+            return Err(LinuxError::EFAULT);
+        }
+        if addrlen < size_of::<ctypes::sockaddr_in>() as _
+            || unsafe { (*addr).sa_family } != ctypes::AF_INET as u16
+        {
+            return Err(LinuxError::EAFNOSUPPORT);
+        }
+        let addr = SocketAddr::V4(unsafe { *(addr as *const ctypes::sockaddr_in) }.into());
+        let flags = flags as u32;
+
+        if !host.is_null() && hostlen > 0 {
+            let numeric = addr.ip().to_string();
+            let name = if flags & ctypes::NI_NUMERICHOST != 0 {
+                numeric
+            } else {
+                match axnet::dns_reverse_query(addr.ip()) {
+                    Ok(name) if flags & ctypes::NI_NOFQDN != 0 => {
+                        name.split('.').next().unwrap_or_default().into()
+                    }
+                    Ok(name) => name,
+                    Err(_) if flags & ctypes::NI_NAMEREQD == 0 => numeric,
+                    Err(AxError::NotFound) => return Err(LinuxError::ENOENT),
+                    Err(_) => return Err(LinuxError::EAGAIN),
+                }
+            };
+            write_c_str(host, hostlen, &name)?;
+        }
+        if !serv.is_null() && servlen > 0 {
+            write_c_str(serv, servlen, &addr.port().to_string())?;
+        }
+        Ok(0)
+    })
+}
+
 /// Get current address to which the socket sockfd is bound.
 pub unsafe fn sys_getsockname(
     sock_fd: c_int,
@@ -579,3 +965,408 @@ pub unsafe fn sys_getpeername(
         Ok(0)
     })
 }
+
+/// Read the option value of type `T` passed to `setsockopt`.
+fn read_optval<T: Copy>(optval: *const c_void, optlen: ctypes::socklen_t) -> LinuxResult<T> {
+    if optval.is_null() {
+        return Err(LinuxError::EFAULT);
+    }
+    if (optlen as usize) < size_of::<T>() {
+        return Err(LinuxError::EINVAL);
+    }
+    Ok(unsafe { (optval as *const T).read_unaligned() })
+}
+
+/// Write the option value returned by `getsockopt`, truncated to `*optlen`.
+fn write_optval<T: Copy>(
+    optval: *mut c_void,
+    optlen: *mut ctypes::socklen_t,
+    val: T,
+) -> LinuxResult {
+    if optval.is_null() || optlen.is_null() {
+        return Err(LinuxError::EFAULT);
+    }
+    let len = unsafe { *optlen as usize }.min(size_of::<T>());
+    unsafe {
+        core::ptr::copy_nonoverlapping(&val as *const T as *const u8, optval as *mut u8, len);
+        *optlen = len as _;
+    }
+    Ok(())
+}
+
+fn to_ctypes_tcp_info(info: &TcpInfo, max_pacing_rate: Option<u64>) -> ctypes::tcp_info {
+    let micros = |d: Duration| d.as_micros().min(u32::MAX as u128) as u32;
+    let millis = |d: Duration| d.as_millis().min(u32::MAX as u128) as u32;
+    let mut tcpi = ctypes::tcp_info {
+        tcpi_state: info.state as u8,
+        tcpi_retransmits: info.retransmits,
+        tcpi_rto: micros(info.rto),
+        tcpi_snd_mss: info.snd_mss,
+        tcpi_rcv_mss: info.rcv_mss,
+        tcpi_unacked: info.unacked,
+        tcpi_last_data_sent: millis(info.last_data_sent),
+        tcpi_last_data_recv: millis(info.last_data_recv),
+        tcpi_last_ack_recv: millis(info.last_ack_recv),
+        tcpi_pmtu: info.pmtu,
+        tcpi_rtt: micros(info.rtt),
+        tcpi_rttvar: micros(info.rttvar),
+        tcpi_snd_ssthresh: i32::MAX as u32, // no congestion control
+        tcpi_snd_cwnd: info.snd_cwnd,
+        tcpi_advmss: info.rcv_mss,
+        tcpi_total_retrans: info.total_retrans,
+        tcpi_pacing_rate: max_pacing_rate.unwrap_or(u64::MAX),
+        tcpi_max_pacing_rate: max_pacing_rate.unwrap_or(u64::MAX),
+        tcpi_bytes_acked: info.bytes_acked,
+        tcpi_bytes_received: info.bytes_received,
+        tcpi_segs_out: info.segs_out,
+        tcpi_segs_in: info.segs_in,
+        tcpi_notsent_bytes: info.notsent_bytes as u32,
+        tcpi_min_rtt: micros(info.min_rtt),
+        tcpi_data_segs_in: info.data_segs_in,
+        tcpi_data_segs_out: info.data_segs_out,
+        tcpi_bytes_sent: info.bytes_sent,
+        tcpi_bytes_retrans: info.bytes_retrans,
+        tcpi_snd_wnd: info.snd_wnd,
+        ..Default::default()
+    };
+    if info.snd_wscale != 0 || info.rcv_wscale != 0 {
+        tcpi.tcpi_options = ctypes::TCPI_OPT_WSCALE as u8;
+        tcpi.set_tcpi_snd_wscale(info.snd_wscale);
+        tcpi.set_tcpi_rcv_wscale(info.rcv_wscale);
+    }
+    tcpi
+}
+
+fn read_timeout(optval: *const c_void, optlen: ctypes::socklen_t) -> LinuxResult<Option<Duration>> {
+    let tv = read_optval::<ctypes::timeval>(optval, optlen)?;
+    if tv.tv_sec < 0 || !(0..1_000_000).contains(&tv.tv_usec) {
+        return Err(LinuxError::EDOM);
+    }
+    let timeout = Duration::from(tv);
+    Ok((!timeout.is_zero()).then_some(timeout))
+}
+
+/// Get options on a socket.
+///
+/// Return 0 if success.
+pub unsafe fn sys_getsockopt(
+    socket_fd: c_int,
+    level: c_int,
+    optname: c_int,
+    optval: *mut c_void,
+    optlen: *mut ctypes::socklen_t,
+) -> c_int {
+    debug!(
+        "sys_getsockopt <= {} {} {} {:#x} {:#x}",
+        socket_fd, level, optname, optval as usize, optlen as usize
+    );
+    syscall_body!(sys_getsockopt, {
+        let socket = Socket::from_fd(socket_fd)?;
+        let opts = socket.options();
+        match (level as u32, optname as u32) {
+            (ctypes::SOL_SOCKET, ctypes::SO_TYPE) => {
+                write_optval(optval, optlen, socket.socket_type() as c_int)?
+            }
+            (ctypes::SOL_SOCKET, ctypes::SO_ERROR) => {
+                let err = socket.take_error().map_or(0, |e| e.code());
+                write_optval(optval, optlen, err as c_int)?
+            }
+            (ctypes::SOL_SOCKET, ctypes::SO_REUSEADDR) => {
+                write_optval(optval, optlen, opts.reuse_addr as c_int)?
+            }
+            (ctypes::SOL_SOCKET, ctypes::SO_REUSEPORT) => {
+                write_optval(optval, optlen, opts.reuse_port as c_int)?
+            }
+            (ctypes::SOL_SOCKET, ctypes::SO_KEEPALIVE) => {
+                write_optval(optval, optlen, opts.keepalive as c_int)?
+            }
+            (ctypes::SOL_SOCKET, ctypes::SO_RCVBUF) => {
+                write_optval(optval, optlen, opts.recv_buf_size as c_int)?
+            }
+            (ctypes::SOL_SOCKET, ctypes::SO_SNDBUF) => {
+                write_optval(optval, optlen, opts.send_buf_size as c_int)?
+            }
+            (ctypes::SOL_SOCKET, ctypes::SO_RCVTIMEO) => write_optval(
+                optval,
+                optlen,
+                ctypes::timeval::from(opts.recv_timeout.unwrap_or_default()),
+            )?,
+            (ctypes::SOL_SOCKET, ctypes::SO_SNDTIMEO) => write_optval(
+                optval,
+                optlen,
+                ctypes::timeval::from(opts.send_timeout.unwrap_or_default()),
+            )?,
+            (ctypes::SOL_SOCKET, ctypes::SO_MAX_PACING_RATE) => {
+                let rate = opts.max_pacing_rate.unwrap_or(u64::MAX);
+                if !optlen.is_null() && unsafe { *optlen } as usize >= size_of::<u64>() {
+                    write_optval(optval, optlen, rate)?
+                } else {
+                    write_optval(optval, optlen, rate.min(u32::MAX as u64) as u32)?
+                }
+            }
+            (ctypes::IPPROTO_TCP, ctypes::TCP_NODELAY) => {
+                if !matches!(*socket, Socket::Tcp(_)) {
+                    return Err(LinuxError::EOPNOTSUPP);
+                }
+                write_optval(optval, optlen, opts.nodelay as c_int)?
+            }
+            (ctypes::IPPROTO_TCP, ctypes::TCP_KEEPIDLE) => {
+                if !matches!(*socket, Socket::Tcp(_)) {
+                    return Err(LinuxError::EOPNOTSUPP);
+                }
+                write_optval(optval, optlen, opts.keepalive_idle.as_secs() as c_int)?
+            }
+            (ctypes::IPPROTO_TCP, ctypes::TCP_KEEPINTVL) => {
+                if !matches!(*socket, Socket::Tcp(_)) {
+                    return Err(LinuxError::EOPNOTSUPP);
+                }
+                write_optval(optval, optlen, opts.keepalive_interval.as_secs() as c_int)?
+            }
+            (ctypes::IPPROTO_TCP, ctypes::TCP_KEEPCNT) => {
+                if !matches!(*socket, Socket::Tcp(_)) {
+                    return Err(LinuxError::EOPNOTSUPP);
+                }
+                write_optval(optval, optlen, opts.keepalive_count as c_int)?
+            }
+            (ctypes::IPPROTO_TCP, ctypes::TCP_INFO) => {
+                let Socket::Tcp(tcpsocket) = &*socket else {
+                    return Err(LinuxError::EOPNOTSUPP);
+                };
+                let info = tcpsocket.lock().tcp_info();
+                write_optval(
+                    optval,
+                    optlen,
+                    to_ctypes_tcp_info(&info, opts.max_pacing_rate),
+                )?
+            }
+            (ctypes::IPPROTO_IP, ctypes::IP_HDRINCL) => {
+                if !matches!(*socket, Socket::Raw(_)) {
+                    return Err(LinuxError::ENOPROTOOPT);
+                }
+                write_optval(optval, optlen, opts.header_included as c_int)?
+            }
+            (ctypes::IPPROTO_IP, ctypes::IP_MULTICAST_TTL) => {
+                if !matches!(*socket, Socket::Udp(_)) {
+                    return Err(LinuxError::ENOPROTOOPT);
+                }
+                write_optval(optval, optlen, opts.multicast_ttl as c_int)?
+            }
+            (ctypes::IPPROTO_UDP, ctypes::UDP_SEGMENT) => {
+                if !matches!(*socket, Socket::Udp(_)) {
+                    return Err(LinuxError::ENOPROTOOPT);
+                }
+                let size = opts.segment_size.unwrap_or_default();
+                write_optval(optval, optlen, size as c_int)?
+            }
+            _ => {
+                warn!("sys_getsockopt: unsupported option {} {}", level, optname);
+                return Err(LinuxError::ENOPROTOOPT);
+            }
+        }
+        Ok(0)
+    })
+}
+
+/// Set options on a socket.
+///
+/// Return 0 if success.
+pub unsafe fn sys_setsockopt(
+    socket_fd: c_int,
+    level: c_int,
+    optname: c_int,
+    optval: *const c_void,
+    optlen: ctypes::socklen_t,
+) -> c_int {
+    debug!(
+        "sys_setsockopt <= {} {} {} {:#x} {}",
+        socket_fd, level, optname, optval as usize, optlen
+    );
+    syscall_body!(sys_setsockopt, {
+        let socket = Socket::from_fd(socket_fd)?;
+        let mut opts = socket.options();
+        match (level as u32, optname as u32) {
+            (ctypes::SOL_SOCKET, ctypes::SO_REUSEADDR) => {
+                opts.reuse_addr = read_optval::<c_int>(optval, optlen)? != 0
+            }
+            (ctypes::SOL_SOCKET, ctypes::SO_REUSEPORT) => {
+                // only listening TCP sockets can share a port
+                if !matches!(*socket, Socket::Tcp(_)) {
+                    return Err(LinuxError::ENOPROTOOPT);
+                }
+                opts.reuse_port = read_optval::<c_int>(optval, optlen)? != 0
+            }
+            (ctypes::SOL_SOCKET, ctypes::SO_KEEPALIVE) => {
+                opts.keepalive = read_optval::<c_int>(optval, optlen)? != 0
+            }
+            (ctypes::SOL_SOCKET, ctypes::SO_RCVBUF | ctypes::SO_SNDBUF) => {
+                // smoltcp buffers are allocated with the socket, keep the default size.
+                read_optval::<c_int>(optval, optlen)?;
+                debug!("sys_setsockopt: buffer size is fixed, ignored");
+            }
+            (ctypes::SOL_SOCKET, ctypes::SO_RCVTIMEO) => {
+                opts.recv_timeout = read_timeout(optval, optlen)?
+            }
+            (ctypes::SOL_SOCKET, ctypes::SO_SNDTIMEO) => {
+                opts.send_timeout = read_timeout(optval, optlen)?
+            }
+            (ctypes::SOL_SOCKET, ctypes::SO_MAX_PACING_RATE) => {
+                if !matches!(*socket, Socket::Tcp(_) | Socket::Udp(_)) {
+                    return Err(LinuxError::ENOPROTOOPT);
+                }
+                // either 32 or 64 bits, all ones means unlimited
+                let rate = if optlen as usize >= size_of::<u64>() {
+                    read_optval::<u64>(optval, optlen)?
+                } else {
+                    match read_optval::<u32>(optval, optlen)? {
+                        u32::MAX => u64::MAX,
+                        rate => rate as u64,
+                    }
+                };
+                if rate == 0 {
+                    return Err(LinuxError::EINVAL);
+                }
+                opts.max_pacing_rate = (rate != u64::MAX).then_some(rate);
+            }
+            (ctypes::SOL_SOCKET, ctypes::SO_ATTACH_FILTER) => {
+                let fprog = read_optval::<ctypes::sock_fprog>(optval, optlen)?;
+                if fprog.filter.is_null() {
+                    return Err(LinuxError::EFAULT);
+                }
+                let filter =
+                    unsafe { core::slice::from_raw_parts(fprog.filter, fprog.len as usize) };
+                let insns: Vec<_> = filter
+                    .iter()
+                    .map(|insn| BpfInsn {
+                        code: insn.code,
+                        jt: insn.jt,
+                        jf: insn.jf,
+                        k: insn.k,
+                    })
+                    .collect();
+                socket.attach_filter(BpfProgram::new(&insns)?)?;
+            }
+            (ctypes::SOL_SOCKET, ctypes::SO_DETACH_FILTER) => socket.detach_filter()?,
+            (ctypes::IPPROTO_TCP, ctypes::TCP_NODELAY) => {
+                if !matches!(*socket, Socket::Tcp(_)) {
+                    return Err(LinuxError::EOPNOTSUPP);
+                }
+                opts.nodelay = read_optval::<c_int>(optval, optlen)? != 0
+            }
+            (ctypes::IPPROTO_TCP, ctypes::TCP_KEEPIDLE | ctypes::TCP_KEEPINTVL) => {
+                if !matches!(*socket, Socket::Tcp(_)) {
+                    return Err(LinuxError::EOPNOTSUPP);
+                }
+                // in seconds, with the same bounds as Linux
+                let secs = match read_optval::<c_int>(optval, optlen)? {
+                    secs @ 1..=TCP_MAX_KEEPALIVE_SECS => Duration::from_secs(secs as u64),
+                    _ => return Err(LinuxError::EINVAL),
+                };
+                if optname as u32 == ctypes::TCP_KEEPIDLE {
+                    opts.keepalive_idle = secs;
+                } else {
+                    opts.keepalive_interval = secs;
+                }
+            }
+            (ctypes::IPPROTO_TCP, ctypes::TCP_KEEPCNT) => {
+                if !matches!(*socket, Socket::Tcp(_)) {
+                    return Err(LinuxError::EOPNOTSUPP);
+                }
+                opts.keepalive_count = match read_optval::<c_int>(optval, optlen)? {
+                    count @ 1..=TCP_MAX_KEEPALIVE_COUNT => count as u32,
+                    _ => return Err(LinuxError::EINVAL),
+                };
+            }
+            (ctypes::IPPROTO_IP, ctypes::IP_HDRINCL) => {
+                if !matches!(*socket, Socket::Raw(_)) {
+                    return Err(LinuxError::ENOPROTOOPT);
+                }
+                opts.header_included = read_optval::<c_int>(optval, optlen)? != 0
+            }
+            (ctypes::IPPROTO_IP, ctypes::IP_MULTICAST_TTL) => {
+                if !matches!(*socket, Socket::Udp(_)) {
+                    return Err(LinuxError::ENOPROTOOPT);
+                }
+                // either an int or a byte
+                let ttl = if optlen as usize >= size_of::<c_int>() {
+                    read_optval::<c_int>(optval, optlen)?
+                } else {
+                    read_optval::<u8>(optval, optlen)? as c_int
+                };
+                opts.multicast_ttl = match ttl {
+                    // the default
+                    -1 => 1,
+                    0..=255 => ttl as u8,
+                    _ => return Err(LinuxError::EINVAL),
+                };
+            }
+            (ctypes::IPPROTO_UDP, ctypes::UDP_SEGMENT) => {
+                if !matches!(*socket, Socket::Udp(_)) {
+                    return Err(LinuxError::ENOPROTOOPT);
+                }
+                // 0 disables segmentation
+                opts.segment_size = match read_optval::<c_int>(optval, optlen)? {
+                    0 => None,
+                    size @ 1..=0xffff => Some(size as u16),
+                    _ => return Err(LinuxError::EINVAL),
+                };
+            }
+            (ctypes::IPPROTO_IP, ctypes::IP_ADD_MEMBERSHIP | ctypes::IP_DROP_MEMBERSHIP) => {
+                let Socket::Udp(udpsocket) = &*socket else {
+                    return Err(LinuxError::ENOPROTOOPT);
+                };
+                // `struct ip_mreqn` starts with the same fields
+                let mreq = read_optval::<ctypes::ip_mreq>(optval, optlen)?;
+                let multiaddr = Ipv4Addr::from(mreq.imr_multiaddr.s_addr.to_ne_bytes());
+                let interface = Ipv4Addr::from(mreq.imr_interface.s_addr.to_ne_bytes());
+                let udpsocket = udpsocket.lock();
+                if optname as u32 == ctypes::IP_ADD_MEMBERSHIP {
+                    udpsocket.join_multicast_v4(multiaddr, interface)?;
+                } else {
+                    udpsocket.leave_multicast_v4(multiaddr, interface)?;
+                }
+            }
+            _ => {
+                warn!("sys_setsockopt: unsupported option {} {}", level, optname);
+                return Err(LinuxError::ENOPROTOOPT);
+            }
+        }
+        socket.set_options(&opts)?;
+        Ok(0)
+    })
+}
+
+/// Move the calling process into new namespaces.
+///
+/// Only `CLONE_NEWNET` is supported: the process gets a new network namespace
+/// with only a loopback interface. The sockets already open stay in the old
+/// namespace.
+///
+/// Return 0 if success.
+pub fn sys_unshare(flags: c_int) -> c_int {
+    debug!("sys_unshare <= {:#x}", flags);
+    syscall_body!(sys_unshare, {
+        if flags as u32 & !ctypes::CLONE_NEWNET != 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        if flags as u32 & ctypes::CLONE_NEWNET != 0 {
+            axnet::unshare_net_namespace();
+        }
+        Ok(0)
+    })
+}
+
+/// Writes the main routing table (`/proc/net/route`) or the TCP connections
+/// (`/proc/net/tcp`) of the current network namespace to their file in the
+/// procfs, before it's opened.
+///
+/// Other paths are ignored.
+#[cfg(feature = "fs")]
+pub(crate) fn update_proc_net_file(path: &str) {
+    let content = match path {
+        "/proc/net/route" => axnet::proc_net_route(),
+        "/proc/net/tcp" => axnet::proc_net_tcp(),
+        _ => return,
+    };
+    // fails if there's no procfs
+    axfs::api::write(path, content).ok();
+}
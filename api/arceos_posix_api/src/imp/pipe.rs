@@ -5,7 +5,7 @@ use axerrno::{LinuxError, LinuxResult};
 use axio::PollState;
 use axsync::Mutex;
 
-use super::fd_ops::{FileLike, add_file_like, close_file_like};
+use super::fd_ops::{FIONREAD, FileLike, add_file_like, close_file_like, ioctl_write_int};
 use crate::ctypes;
 
 #[derive(Copy, Clone, PartialEq)]
@@ -188,6 +188,15 @@ impl FileLike for Pipe {
     fn set_nonblocking(&self, _nonblocking: bool) -> LinuxResult {
         Ok(())
     }
+
+    fn ioctl(&self, cmd: u32, arg: usize) -> LinuxResult<c_int> {
+        match cmd {
+            FIONREAD if self.readable() => {
+                ioctl_write_int(arg, self.buffer.lock().available_read())
+            }
+            _ => Err(LinuxError::ENOTTY),
+        }
+    }
 }
 
 /// Create a pipe
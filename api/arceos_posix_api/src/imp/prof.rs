@@ -0,0 +1,110 @@
+//! The file of the sampling profiler, `/proc/profile`.
+//!
+//! Reading it gives the samples taken, in the folded format of the flame graph
+//! tools (see [`axprof::report_folded`]), as they were when it was opened.
+//! Writing to it controls the profiler:
+//!
+//! - `pc` or `fp`, optionally followed by the number of the samples kept:
+//!   starts it, sampling the PCs alone or the stacks by the frame pointers,
+//!   and discards the samples taken before;
+//! - `0` or `off`: stops it.
+//!
+//! E.g. `echo fp > /proc/profile`, run the workload, `echo 0 > /proc/profile`
+//! and `cat /proc/profile | flamegraph.pl > prof.svg` on the host.
+
+use alloc::{string::String, sync::Arc};
+use core::ffi::c_int;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use axerrno::{LinuxError, LinuxResult};
+use axio::PollState;
+use axprof::SampleMode;
+
+use super::fd_ops::{FileLike, add_file_like};
+use crate::ctypes;
+
+/// The path of the file of the profiler.
+pub(crate) const PROFILE_PATH: &str = "/proc/profile";
+
+struct ProfileFile {
+    /// The report when it was opened.
+    content: String,
+    /// The offset of the next read.
+    pos: AtomicUsize,
+}
+
+/// Applies a command written to the file.
+fn control(cmd: &str) -> LinuxResult {
+    let mut args = cmd.split_whitespace();
+    let mode = match args.next() {
+        Some("0" | "off") if args.next().is_none() => {
+            axprof::stop();
+            return Ok(());
+        }
+        Some("pc") => SampleMode::Pc,
+        Some("fp") => SampleMode::FramePointer,
+        _ => return Err(LinuxError::EINVAL),
+    };
+    let capacity = match args.next() {
+        Some(n) => n.parse().map_err(|_| LinuxError::EINVAL)?,
+        None => axprof::DEFAULT_CAPACITY,
+    };
+    if args.next().is_some() {
+        return Err(LinuxError::EINVAL);
+    }
+    axprof::start(mode, capacity);
+    Ok(())
+}
+
+impl FileLike for ProfileFile {
+    fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        let content = self.content.as_bytes();
+        let pos = self.pos.load(Ordering::Acquire).min(content.len());
+        let len = buf.len().min(content.len() - pos);
+        buf[..len].copy_from_slice(&content[pos..pos + len]);
+        self.pos.fetch_add(len, Ordering::AcqRel);
+        Ok(len)
+    }
+
+    fn write(&self, buf: &[u8]) -> LinuxResult<usize> {
+        let cmd = core::str::from_utf8(buf).map_err(|_| LinuxError::EINVAL)?;
+        control(cmd)?;
+        Ok(buf.len())
+    }
+
+    fn stat(&self) -> LinuxResult<ctypes::stat> {
+        Ok(ctypes::stat {
+            st_ino: 1,
+            st_nlink: 1,
+            st_mode: 0o100644, // S_IFREG | rw-r--r--
+            st_size: self.content.len() as _,
+            st_blksize: 4096,
+            ..Default::default()
+        })
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn core::any::Any + Send + Sync> {
+        self
+    }
+
+    fn poll(&self) -> LinuxResult<PollState> {
+        Ok(PollState {
+            readable: true,
+            writable: true,
+        })
+    }
+
+    fn set_nonblocking(&self, _nonblocking: bool) -> LinuxResult {
+        Ok(())
+    }
+}
+
+/// Opens `/proc/profile`.
+pub(crate) fn open() -> LinuxResult<c_int> {
+    let mut content = String::new();
+    axprof::report_folded(&mut content).ok();
+    add_file_like(Arc::new(ProfileFile {
+        content,
+        pos: AtomicUsize::new(0),
+    }))
+}
@@ -1,6 +1,8 @@
 use alloc::{boxed::Box, collections::BTreeMap, sync::Arc};
 use core::cell::UnsafeCell;
 use core::ffi::{c_int, c_void};
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
 
 use axerrno::{LinuxError, LinuxResult};
 use axtask::AxTaskRef;
@@ -27,6 +29,10 @@ lazy_static::lazy_static! {
     };
 }
 
+/// The CPU time consumed by the threads that have been joined, in
+/// nanoseconds.
+static JOINED_CPU_TIME: AtomicU64 = AtomicU64::new(0);
+
 struct Packet<T> {
     result: UnsafeCell<T>,
 }
@@ -55,12 +61,16 @@ impl Pthread {
         let main = move || {
             let arg = arg_wrapper;
             let ret = start_routine(arg.0);
+            super::futex::exit_robust_list();
             unsafe { *their_packet.result.get() = ret };
             drop(their_packet);
         };
 
-        let task_inner = axtask::spawn(main);
+        let stack_size = super::resources::thread_stack_size()?;
+        let task_inner =
+            axtask::try_spawn_raw(main, "".into(), stack_size).ok_or(LinuxError::EAGAIN)?;
         let tid = task_inner.id().as_u64();
+        super::session::inherit(tid);
         let thread = Pthread {
             inner: task_inner,
             retval: my_packet,
@@ -70,6 +80,50 @@ impl Pthread {
         Ok(ptr)
     }
 
+    /// Returns whether there's a thread of ID `tid`.
+    pub(crate) fn exists(tid: u64) -> bool {
+        TID_TO_PTHREAD.read().contains_key(&tid)
+    }
+
+    /// Returns the CPU time consumed by all threads, including the ones that
+    /// have been joined.
+    pub(crate) fn total_cpu_time() -> Duration {
+        let joined = Duration::from_nanos(JOINED_CPU_TIME.load(Ordering::Relaxed));
+        TID_TO_PTHREAD
+            .read()
+            .values()
+            .map(|ptr| unsafe { (*(ptr.0 as *const Pthread)).inner.cpu_time() })
+            .fold(joined, |total, time| total + time)
+    }
+
+    /// Returns the CPU time consumed by the thread `tid`, or `None` if there's
+    /// no such thread.
+    pub(crate) fn cpu_time(tid: u64) -> Option<Duration> {
+        TID_TO_PTHREAD
+            .read()
+            .get(&tid)
+            .map(|ptr| unsafe { (*(ptr.0 as *const Pthread)).inner.cpu_time() })
+    }
+
+    /// Returns the task of the thread `tid`, or `None` if there's no such
+    /// thread.
+    pub(crate) fn task(tid: u64) -> Option<AxTaskRef> {
+        TID_TO_PTHREAD
+            .read()
+            .get(&tid)
+            .map(|ptr| unsafe { (*(ptr.0 as *const Pthread)).inner.clone() })
+    }
+
+    /// Returns the ID of the thread `ptr`, or `None` if it's not a live
+    /// thread.
+    pub(crate) fn tid(ptr: ctypes::pthread_t) -> Option<u64> {
+        TID_TO_PTHREAD
+            .read()
+            .iter()
+            .find(|(_, thread)| thread.0 == ptr)
+            .map(|(&tid, _)| tid)
+    }
+
     fn current_ptr() -> *mut Pthread {
         let tid = axtask::current().id().as_u64();
         match TID_TO_PTHREAD.read().get(&tid) {
@@ -85,6 +139,7 @@ impl Pthread {
     fn exit_current(retval: *mut c_void) -> ! {
         let thread = Self::current().expect("fail to get current thread");
         unsafe { *thread.retval.result.get() = retval };
+        super::futex::exit_robust_list();
         axtask::exit(0);
     }
 
@@ -98,6 +153,10 @@ impl Pthread {
         let tid = thread.inner.id().as_u64();
         let retval = unsafe { *thread.retval.result.get() };
         TID_TO_PTHREAD.write().remove(&tid);
+        let cpu_time = thread.inner.cpu_time().as_nanos() as u64;
+        JOINED_CPU_TIME.fetch_add(cpu_time, Ordering::Relaxed);
+        super::signal::remove_task(tid);
+        super::session::remove_task(tid);
         drop(thread);
         Ok(retval)
     }
@@ -0,0 +1,312 @@
+//! Pseudo-terminals.
+//!
+//! Opening [`PTMX_PATH`] creates a pseudo-terminal, and returns its master.
+//! Its slave is `/dev/pts/N`, where `N` is told by `TIOCGPTN`, and can be
+//! opened once unlocked by `TIOCSPTLCK`. The slave is a terminal like the
+//! console (see the tty module): what's written to the master is typed on it,
+//! and what's written to it is read from the master, along with the echo.
+//!
+//! Reading from the master fails with `EIO` while no slave is open. Once the
+//! master is closed, the foreground process group of the slave gets `SIGHUP`,
+//! reading from the slave returns the end of file, and writing to it fails
+//! with `EIO`.
+//!
+//! The blocked tasks of both sides wait for a change of the terminal of the
+//! slave, see [`Terminal::wait`].
+
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::Arc;
+use core::ffi::c_int;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use axerrno::{LinuxError, LinuxResult};
+use axio::PollState;
+use spin::Mutex;
+
+use super::fd_ops::{FIONREAD, FileLike, add_file_like, ioctl_read_int, ioctl_write_int};
+use super::tty::Terminal;
+use crate::ctypes;
+
+/// The path of the pseudo-terminal multiplexer.
+pub(crate) const PTMX_PATH: &str = "/dev/ptmx";
+
+/// The directory of the slaves.
+const PTS_DIR: &str = "/dev/pts/";
+
+/// The most bytes of output a slave holds before writing to it blocks.
+const MAX_OUTPUT: usize = 4096;
+
+/// The most pseudo-terminals open at once, as the default `kernel.pty.max` of
+/// Linux.
+const MAX_PTYS: u32 = 4096;
+
+const TIOCGPTN: u32 = 0x80045430;
+const TIOCSPTLCK: u32 = 0x40045431;
+const TIOCGPTLCK: u32 = 0x80045439;
+
+struct Pty {
+    index: u32,
+    /// The slave.
+    term: Terminal,
+    /// The output of the slave and the echo, to be read from the master.
+    output: Mutex<VecDeque<u8>>,
+    /// Whether the slave can't be opened (`TIOCSPTLCK`).
+    locked: AtomicBool,
+    master_open: AtomicBool,
+    /// The number of open slaves.
+    slaves: AtomicUsize,
+}
+
+/// The pseudo-terminals whose master is open, by index.
+static PTYS: Mutex<BTreeMap<u32, Arc<Pty>>> = Mutex::new(BTreeMap::new());
+
+fn stat(index: u32) -> ctypes::stat {
+    let st_mode = 0o20000 | 0o620u32; // S_IFCHR | rw--w----
+    ctypes::stat {
+        st_ino: index as _,
+        st_nlink: 1,
+        st_mode,
+        st_blksize: 1024,
+        ..Default::default()
+    }
+}
+
+pub struct PtyMaster {
+    pty: Arc<Pty>,
+    nonblocking: AtomicBool,
+}
+
+pub struct PtySlave {
+    pty: Arc<Pty>,
+    nonblocking: AtomicBool,
+}
+
+impl PtyMaster {
+    fn nonblocking(&self) -> bool {
+        self.nonblocking.load(Ordering::Acquire)
+    }
+}
+
+impl PtySlave {
+    fn nonblocking(&self) -> bool {
+        self.nonblocking.load(Ordering::Acquire)
+    }
+}
+
+impl Drop for PtyMaster {
+    fn drop(&mut self) {
+        self.pty.master_open.store(false, Ordering::Release);
+        PTYS.lock().remove(&self.pty.index);
+        self.pty.term.hang_up();
+    }
+}
+
+impl Drop for PtySlave {
+    fn drop(&mut self) {
+        self.pty.slaves.fetch_sub(1, Ordering::AcqRel);
+        // reading from the master may fail now
+        self.pty.term.notify();
+    }
+}
+
+impl FileLike for PtyMaster {
+    fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            let seen = self.pty.term.events();
+            let mut output = self.pty.output.lock();
+            if !output.is_empty() {
+                let len = buf.len().min(output.len());
+                for (dst, src) in buf.iter_mut().zip(output.drain(..len)) {
+                    *dst = src;
+                }
+                drop(output);
+                // there's room for more output
+                self.pty.term.notify();
+                return Ok(len);
+            }
+            drop(output);
+            if self.pty.slaves.load(Ordering::Acquire) == 0 {
+                return Err(LinuxError::EIO);
+            }
+            if self.nonblocking() {
+                return Err(LinuxError::EAGAIN);
+            }
+            self.pty.term.wait(seen, None)?;
+        }
+    }
+
+    fn write(&self, buf: &[u8]) -> LinuxResult<usize> {
+        let mut written = 0;
+        while written < buf.len() {
+            let seen = self.pty.term.events();
+            let room = self.pty.term.room();
+            if room == 0 {
+                if written > 0 {
+                    break;
+                }
+                if self.nonblocking() {
+                    return Err(LinuxError::EAGAIN);
+                }
+                self.pty.term.wait(seen, None)?;
+                continue;
+            }
+            let len = room.min(buf.len() - written);
+            let echo = self.pty.term.input(&buf[written..written + len]);
+            if !echo.is_empty() {
+                self.pty.term.output(&echo, &mut self.pty.output.lock());
+                // the echo can be read now
+                self.pty.term.notify();
+            }
+            written += len;
+        }
+        Ok(written)
+    }
+
+    fn stat(&self) -> LinuxResult<ctypes::stat> {
+        Ok(stat(self.pty.index))
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn core::any::Any + Send + Sync> {
+        self
+    }
+
+    fn poll(&self) -> LinuxResult<PollState> {
+        Ok(PollState {
+            // reading fails while there is no slave, without blocking
+            readable: !self.pty.output.lock().is_empty()
+                || self.pty.slaves.load(Ordering::Acquire) == 0,
+            writable: self.pty.term.room() > 0,
+        })
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> LinuxResult {
+        self.nonblocking.store(nonblocking, Ordering::Release);
+        Ok(())
+    }
+
+    fn ioctl(&self, cmd: u32, arg: usize) -> LinuxResult<c_int> {
+        match cmd {
+            TIOCGPTN => ioctl_write_int(arg, self.pty.index as usize),
+            TIOCSPTLCK => {
+                let locked = ioctl_read_int(arg)? != 0;
+                self.pty.locked.store(locked, Ordering::Release);
+                Ok(0)
+            }
+            TIOCGPTLCK => ioctl_write_int(arg, self.pty.locked.load(Ordering::Acquire) as usize),
+            FIONREAD => ioctl_write_int(arg, self.pty.output.lock().len()),
+            // the settings and the window size are the ones of the slave
+            _ => self.pty.term.ioctl(cmd, arg),
+        }
+    }
+}
+
+impl FileLike for PtySlave {
+    fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        let hung_up = || !self.pty.master_open.load(Ordering::Acquire);
+        self.pty.term.read(buf, self.nonblocking(), hung_up)
+    }
+
+    fn write(&self, buf: &[u8]) -> LinuxResult<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            let seen = self.pty.term.events();
+            if !self.pty.master_open.load(Ordering::Acquire) {
+                return Err(LinuxError::EIO);
+            }
+            let mut output = self.pty.output.lock();
+            let room = MAX_OUTPUT.saturating_sub(output.len());
+            if room > 0 {
+                let len = room.min(buf.len());
+                self.pty.term.output(&buf[..len], &mut output);
+                drop(output);
+                self.pty.term.notify();
+                return Ok(len);
+            }
+            drop(output);
+            if self.nonblocking() {
+                return Err(LinuxError::EAGAIN);
+            }
+            self.pty.term.wait(seen, None)?;
+        }
+    }
+
+    fn stat(&self) -> LinuxResult<ctypes::stat> {
+        Ok(stat(self.pty.index))
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn core::any::Any + Send + Sync> {
+        self
+    }
+
+    fn poll(&self) -> LinuxResult<PollState> {
+        let hung_up = !self.pty.master_open.load(Ordering::Acquire);
+        Ok(PollState {
+            readable: self.pty.term.readable() || hung_up,
+            writable: self.pty.output.lock().len() < MAX_OUTPUT || hung_up,
+        })
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> LinuxResult {
+        self.nonblocking.store(nonblocking, Ordering::Release);
+        Ok(())
+    }
+
+    fn ioctl(&self, cmd: u32, arg: usize) -> LinuxResult<c_int> {
+        self.pty.term.ioctl(cmd, arg)
+    }
+}
+
+/// Creates a pseudo-terminal, and opens its master.
+fn open_master(nonblocking: bool) -> LinuxResult<c_int> {
+    let pty = {
+        let mut ptys = PTYS.lock();
+        let index = (0..MAX_PTYS)
+            .find(|i| !ptys.contains_key(i))
+            .ok_or(LinuxError::ENOSPC)?;
+        let pty = Arc::new(Pty {
+            index,
+            term: Terminal::new(),
+            output: Mutex::new(VecDeque::new()),
+            locked: AtomicBool::new(true),
+            master_open: AtomicBool::new(true),
+            slaves: AtomicUsize::new(0),
+        });
+        ptys.insert(index, pty.clone());
+        pty
+    };
+    add_file_like(Arc::new(PtyMaster {
+        pty,
+        nonblocking: AtomicBool::new(nonblocking),
+    }))
+}
+
+/// Opens the slave `name` in [`PTS_DIR`].
+fn open_slave(name: &str, nonblocking: bool) -> LinuxResult<c_int> {
+    let index = name.parse::<u32>().map_err(|_| LinuxError::ENOENT)?;
+    let pty = PTYS.lock().get(&index).cloned().ok_or(LinuxError::ENOENT)?;
+    if pty.locked.load(Ordering::Acquire) {
+        return Err(LinuxError::EIO);
+    }
+    pty.slaves.fetch_add(1, Ordering::AcqRel);
+    add_file_like(Arc::new(PtySlave {
+        pty,
+        nonblocking: AtomicBool::new(nonblocking),
+    }))
+}
+
+/// Opens `path` if it's [`PTMX_PATH`] or a slave, or returns `None` if it's
+/// neither.
+pub(crate) fn open(path: &str, flags: c_int) -> Option<LinuxResult<c_int>> {
+    let nonblocking = flags as u32 & ctypes::O_NONBLOCK != 0;
+    if path == PTMX_PATH {
+        Some(open_master(nonblocking))
+    } else {
+        let name = path.strip_prefix(PTS_DIR)?;
+        Some(open_slave(name, nonblocking))
+    }
+}
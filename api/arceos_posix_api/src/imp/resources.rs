@@ -1,51 +1,226 @@
-use crate::ctypes;
-use axerrno::LinuxError;
+//! Resource limits.
+//!
+//! All tasks belong to the same process, so they share one set of limits.
+//! The following ones are enforced:
+//!
+//! - `RLIMIT_NOFILE`: file descriptors can't reach the soft limit (`EMFILE`).
+//! - `RLIMIT_STACK`: the soft limit is the stack size of new threads, which
+//!   fail to be created (`EAGAIN`) if it can't be allocated.
+//! - `RLIMIT_AS` and `RLIMIT_DATA`: the memory allocated by `malloc` can't
+//!   exceed the soft limits (`ENOMEM`). It's the only memory an application
+//!   maps, as it shares the kernel's address space.
+
 use core::ffi::c_int;
+use core::sync::atomic::{AtomicUsize, Ordering};
 
-/// Get resource limitations
+use axerrno::{LinuxError, LinuxResult};
+use spin::Mutex;
+
+use crate::ctypes;
+
+const RLIM_INFINITY: u64 = u64::MAX;
+const NLIMITS: usize = ctypes::RLIMIT_NLIMITS as usize;
+
+#[cfg(feature = "fd")]
+const NOFILE_MAX: u64 = super::fd_ops::AX_FILE_LIMIT as u64;
+#[cfg(not(feature = "fd"))]
+const NOFILE_MAX: u64 = 0;
+
+/// The minimum stack size of a thread.
+#[cfg(feature = "multitask")]
+const MIN_STACK_SIZE: usize = 0x4000;
+
+static LIMITS: Mutex<[ctypes::rlimit; NLIMITS]> = Mutex::new({
+    let mut limits = [ctypes::rlimit {
+        rlim_cur: RLIM_INFINITY,
+        rlim_max: RLIM_INFINITY,
+    }; NLIMITS];
+    limits[ctypes::RLIMIT_STACK as usize].rlim_cur = axconfig::TASK_STACK_SIZE as u64;
+    limits[ctypes::RLIMIT_NOFILE as usize] = ctypes::rlimit {
+        rlim_cur: NOFILE_MAX,
+        rlim_max: NOFILE_MAX,
+    };
+    limits
+});
+
+/// The memory currently allocated by `malloc`.
+static MEMORY_USED: AtomicUsize = AtomicUsize::new(0);
+
+fn soft_limit(resource: u32) -> u64 {
+    LIMITS.lock()[resource as usize].rlim_cur
+}
+
+/// Returns the soft `RLIMIT_NOFILE`, i.e., the maximum file descriptor plus one.
+#[cfg(feature = "fd")]
+pub(crate) fn nofile_limit() -> usize {
+    soft_limit(ctypes::RLIMIT_NOFILE) as usize
+}
+
+/// Returns the stack size of new threads, given by the soft `RLIMIT_STACK`.
+///
+/// Fails with `EAGAIN` if such a stack can't fit in the physical memory.
+#[cfg(feature = "multitask")]
+pub(crate) fn thread_stack_size() -> LinuxResult<usize> {
+    let size = match soft_limit(ctypes::RLIMIT_STACK) {
+        RLIM_INFINITY => return Ok(axconfig::TASK_STACK_SIZE),
+        size => size,
+    };
+    usize::try_from(size)
+        .ok()
+        .and_then(|size| size.max(MIN_STACK_SIZE).checked_next_multiple_of(0x1000))
+        .filter(|&size| size <= axconfig::plat::PHYS_MEMORY_SIZE)
+        .ok_or(LinuxError::EAGAIN)
+}
+
+/// Accounts `size` bytes of memory allocated by the application.
 ///
-/// TODO: support more resource types
+/// Fails with `ENOMEM` if it would exceed the soft `RLIMIT_AS` or
+/// `RLIMIT_DATA`.
+pub fn charge_memory(size: usize) -> LinuxResult {
+    let limit = soft_limit(ctypes::RLIMIT_AS).min(soft_limit(ctypes::RLIMIT_DATA));
+    MEMORY_USED
+        .fetch_update(Ordering::AcqRel, Ordering::Acquire, |used| {
+            used.checked_add(size)
+                .filter(|&total| total as u64 <= limit)
+        })
+        .map(|_| ())
+        .map_err(|_| LinuxError::ENOMEM)
+}
+
+/// Accounts `size` bytes of memory freed by the application.
+pub fn uncharge_memory(size: usize) {
+    MEMORY_USED.fetch_sub(size, Ordering::AcqRel);
+}
+
+fn check_resource(resource: c_int) -> LinuxResult<u32> {
+    if (0..NLIMITS as c_int).contains(&resource) {
+        Ok(resource as u32)
+    } else {
+        Err(LinuxError::EINVAL)
+    }
+}
+
+/// Checks that `pid` is the current process, or 0.
+fn check_pid(pid: c_int) -> LinuxResult {
+    #[cfg(feature = "multitask")]
+    let exists = pid == 0 || (pid > 0 && super::pthread::Pthread::exists(pid as u64));
+    #[cfg(not(feature = "multitask"))]
+    let exists = pid == 0 || pid == super::task::sys_getpid();
+    if exists {
+        Ok(())
+    } else {
+        Err(LinuxError::ESRCH)
+    }
+}
+
+fn prlimit(
+    resource: c_int,
+    new_limit: *const ctypes::rlimit,
+    old_limit: *mut ctypes::rlimit,
+) -> LinuxResult {
+    let resource = check_resource(resource)?;
+    let mut limits = LIMITS.lock();
+    let old = limits[resource as usize];
+    if !new_limit.is_null() {
+        let new = unsafe { *new_limit };
+        if new.rlim_cur > new.rlim_max {
+            return Err(LinuxError::EINVAL);
+        }
+        if resource == ctypes::RLIMIT_NOFILE && new.rlim_max > NOFILE_MAX {
+            return Err(LinuxError::EPERM);
+        }
+        limits[resource as usize] = new;
+    }
+    if !old_limit.is_null() {
+        unsafe { *old_limit = old };
+    }
+    Ok(())
+}
+
+/// Get resource limitations
 pub unsafe fn sys_getrlimit(resource: c_int, rlimits: *mut ctypes::rlimit) -> c_int {
     debug!("sys_getrlimit <= {} {:#x}", resource, rlimits as usize);
     syscall_body!(sys_getrlimit, {
-        match resource as u32 {
-            ctypes::RLIMIT_DATA => {}
-            ctypes::RLIMIT_STACK => {}
-            ctypes::RLIMIT_NOFILE => {}
-            _ => return Err(LinuxError::EINVAL),
-        }
-        if rlimits.is_null() {
-            return Ok(0);
-        }
-        match resource as u32 {
-            ctypes::RLIMIT_STACK => unsafe {
-                (*rlimits).rlim_cur = axconfig::TASK_STACK_SIZE as _;
-                (*rlimits).rlim_max = axconfig::TASK_STACK_SIZE as _;
-            },
-            #[cfg(feature = "fd")]
-            ctypes::RLIMIT_NOFILE => unsafe {
-                (*rlimits).rlim_cur = super::fd_ops::AX_FILE_LIMIT as _;
-                (*rlimits).rlim_max = super::fd_ops::AX_FILE_LIMIT as _;
-            },
-            _ => {}
-        }
+        prlimit(resource, core::ptr::null(), rlimits)?;
         Ok(0)
     })
 }
 
 /// Set resource limitations
-///
-/// TODO: support more resource types
 pub unsafe fn sys_setrlimit(resource: c_int, rlimits: *mut crate::ctypes::rlimit) -> c_int {
     debug!("sys_setrlimit <= {} {:#x}", resource, rlimits as usize);
     syscall_body!(sys_setrlimit, {
-        match resource as u32 {
-            crate::ctypes::RLIMIT_DATA => {}
-            crate::ctypes::RLIMIT_STACK => {}
-            crate::ctypes::RLIMIT_NOFILE => {}
-            _ => return Err(LinuxError::EINVAL),
+        if rlimits.is_null() {
+            return Err(LinuxError::EFAULT);
         }
-        // Currently do not support set resources
+        prlimit(resource, rlimits, core::ptr::null_mut())?;
         Ok(0)
     })
 }
+
+/// Get and set the resource limitations of a process.
+///
+/// If `new_limit` is not null, the limit is set to it. If `old_limit` is not
+/// null, the previous limit is stored in it.
+pub unsafe fn sys_prlimit64(
+    pid: c_int,
+    resource: c_int,
+    new_limit: *const ctypes::rlimit,
+    old_limit: *mut ctypes::rlimit,
+) -> c_int {
+    debug!(
+        "sys_prlimit64 <= {} {} {:#x} {:#x}",
+        pid, resource, new_limit as usize, old_limit as usize
+    );
+    syscall_body!(sys_prlimit64, {
+        check_pid(pid)?;
+        prlimit(resource, new_limit, old_limit)?;
+        Ok(0)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use core::ffi::c_void;
+
+    use super::*;
+    use crate::imp::pthread::sys_pthread_create;
+    use crate::utils::test_init as init;
+
+    extern "C" fn unreachable_thread(_arg: *mut c_void) -> *mut c_void {
+        unreachable!()
+    }
+
+    #[test]
+    fn test_huge_stack_limit() {
+        let _guard = init();
+        let resource = ctypes::RLIMIT_STACK as c_int;
+        let mut old = ctypes::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        prlimit(resource, core::ptr::null(), &mut old).unwrap();
+
+        // too large to be rounded up, or to be allocated
+        let phys_size = axconfig::plat::PHYS_MEMORY_SIZE as u64;
+        for size in [RLIM_INFINITY - 1, phys_size + 1] {
+            let limit = ctypes::rlimit {
+                rlim_cur: size,
+                rlim_max: RLIM_INFINITY,
+            };
+            prlimit(resource, &limit, core::ptr::null_mut()).unwrap();
+            let mut thread = core::ptr::null_mut();
+            let ret = unsafe {
+                sys_pthread_create(
+                    &mut thread,
+                    core::ptr::null(),
+                    unreachable_thread,
+                    core::ptr::null_mut(),
+                )
+            };
+            assert_eq!(ret, -LinuxError::EAGAIN.code());
+            assert!(thread.is_null());
+        }
+        prlimit(resource, &old, core::ptr::null_mut()).unwrap();
+    }
+}
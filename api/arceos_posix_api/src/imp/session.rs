@@ -0,0 +1,251 @@
+//! Process groups, sessions and the controlling terminal.
+//!
+//! As with signals, each task is a process of its own ID. A task starts in
+//! the process group and session of the task that created it, and the initial
+//! task leads a group and a session of its own.
+//!
+//! The console is the only terminal. It's the controlling terminal of the
+//! session of the task that first uses it, until that session gives it up
+//! with `TIOCNOTTY`. Typing `^C`, `^\` or `^Z` sends `SIGINT`, `SIGQUIT` or
+//! `SIGTSTP` to its foreground process group. The console is polled, so the
+//! control characters are only seen while a task is reading from it.
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::ffi::c_int;
+
+use axerrno::{LinuxError, LinuxResult};
+use spin::RwLock;
+
+use crate::imp::pthread::Pthread;
+
+const TIOCSCTTY: u32 = 0x540E;
+const TIOCGPGRP: u32 = 0x540F;
+const TIOCSPGRP: u32 = 0x5410;
+const TIOCNOTTY: u32 = 0x5422;
+const TIOCGSID: u32 = 0x5429;
+
+#[derive(Clone, Copy)]
+struct Ids {
+    pgid: u64,
+    sid: u64,
+}
+
+/// The IDs of the tasks, except the ones leading both their group and session.
+static IDS: RwLock<BTreeMap<u64, Ids>> = RwLock::new(BTreeMap::new());
+
+/// The job control state of a terminal.
+pub(crate) struct JobControl {
+    /// The session it controls, or 0 if none.
+    sid: u64,
+    /// The foreground process group.
+    fg_pgrp: u64,
+}
+
+fn ids_of(tid: u64) -> Ids {
+    IDS.read().get(&tid).copied().unwrap_or(Ids {
+        pgid: tid,
+        sid: tid,
+    })
+}
+
+fn current_tid() -> u64 {
+    axtask::current().id().as_u64()
+}
+
+fn current_ids() -> Ids {
+    ids_of(current_tid())
+}
+
+/// Returns the process group ID of the current task.
+pub(crate) fn current_pgid() -> u64 {
+    current_ids().pgid
+}
+
+/// Resolves a `pid` argument, where 0 means the current task.
+fn task_of(pid: c_int) -> LinuxResult<u64> {
+    match pid {
+        0 => Ok(current_tid()),
+        _ if pid > 0 && Pthread::exists(pid as u64) => Ok(pid as u64),
+        _ if pid > 0 => Err(LinuxError::ESRCH),
+        _ => Err(LinuxError::EINVAL),
+    }
+}
+
+/// Returns the tasks in the process group `pgid`.
+pub(crate) fn members_of(pgid: u64) -> Vec<u64> {
+    let ids = IDS.read();
+    let mut members: Vec<u64> = ids
+        .iter()
+        .filter(|(_, ids)| ids.pgid == pgid)
+        .map(|(&tid, _)| tid)
+        .collect();
+    if !ids.contains_key(&pgid) && Pthread::exists(pgid) {
+        members.push(pgid);
+    }
+    members
+}
+
+/// Puts a new task into the process group and session of its creator.
+pub(crate) fn inherit(child: u64) {
+    let ids = current_ids();
+    IDS.write().insert(child, ids);
+}
+
+/// Forgets the IDs of an exited task.
+pub(crate) fn remove_task(tid: u64) {
+    IDS.write().remove(&tid);
+}
+
+impl JobControl {
+    /// A terminal controlling no session.
+    pub(crate) const fn new() -> Self {
+        Self { sid: 0, fg_pgrp: 0 }
+    }
+
+    /// A terminal controlling the session of the current task, with its
+    /// process group in the foreground.
+    pub(crate) fn current() -> Self {
+        let ids = current_ids();
+        Self {
+            sid: ids.sid,
+            fg_pgrp: ids.pgid,
+        }
+    }
+
+    /// Sends `sig` to the foreground process group.
+    pub(crate) fn signal(&self, sig: u32) {
+        if self.sid != 0 {
+            for tid in members_of(self.fg_pgrp) {
+                super::signal::send_signal(tid, sig).ok();
+            }
+        }
+    }
+
+    /// Handles the job control `ioctl`s of the terminal.
+    pub(crate) fn ioctl(&mut self, cmd: u32, arg: usize) -> LinuxResult<c_int> {
+        let tid = current_tid();
+        let ids = ids_of(tid);
+        let controlling = self.sid != 0 && self.sid == ids.sid;
+        match cmd {
+            TIOCGPGRP | TIOCGSID | TIOCSPGRP if !controlling => Err(LinuxError::ENOTTY),
+            TIOCGPGRP | TIOCGSID => {
+                if arg == 0 {
+                    return Err(LinuxError::EFAULT);
+                }
+                let id = if cmd == TIOCGPGRP {
+                    self.fg_pgrp
+                } else {
+                    self.sid
+                };
+                unsafe { *(arg as *mut c_int) = id as c_int };
+                Ok(0)
+            }
+            TIOCSPGRP => {
+                if arg == 0 {
+                    return Err(LinuxError::EFAULT);
+                }
+                let pgid = unsafe { *(arg as *const c_int) };
+                if pgid <= 0 {
+                    return Err(LinuxError::EINVAL);
+                }
+                let pgid = pgid as u64;
+                if !members_of(pgid).iter().any(|&t| ids_of(t).sid == ids.sid) {
+                    return Err(LinuxError::EPERM);
+                }
+                self.fg_pgrp = pgid;
+                Ok(0)
+            }
+            TIOCSCTTY => {
+                if ids.sid != tid {
+                    Err(LinuxError::EPERM)
+                } else if controlling {
+                    Ok(0)
+                } else if self.sid != 0 {
+                    Err(LinuxError::EPERM)
+                } else {
+                    self.sid = ids.sid;
+                    self.fg_pgrp = ids.pgid;
+                    Ok(0)
+                }
+            }
+            TIOCNOTTY => {
+                if !controlling {
+                    Err(LinuxError::ENOTTY)
+                } else {
+                    if ids.sid == tid {
+                        self.sid = 0;
+                    }
+                    Ok(0)
+                }
+            }
+            _ => Err(LinuxError::ENOTTY),
+        }
+    }
+}
+
+/// Set the process group ID of a task.
+///
+/// If `pid` is 0, the current task is used. If `pgid` is 0, the ID of the task
+/// is used, i.e., the task becomes the leader of a new group.
+pub fn sys_setpgid(pid: c_int, pgid: c_int) -> c_int {
+    debug!("sys_setpgid <= {} {}", pid, pgid);
+    syscall_body!(sys_setpgid, {
+        if pgid < 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        let tid = task_of(pid)?;
+        let pgid = if pgid == 0 { tid } else { pgid as u64 };
+        let ids = ids_of(tid);
+        if ids.sid == tid || ids.sid != current_ids().sid {
+            // session leaders can't move, and tasks can't move to other sessions
+            return Err(LinuxError::EPERM);
+        }
+        if pgid != tid && !members_of(pgid).iter().any(|&t| ids_of(t).sid == ids.sid) {
+            return Err(LinuxError::EPERM);
+        }
+        IDS.write().insert(tid, Ids { pgid, sid: ids.sid });
+        Ok(0)
+    })
+}
+
+/// Get the process group ID of a task.
+pub fn sys_getpgid(pid: c_int) -> c_int {
+    syscall_body!(sys_getpgid, Ok(ids_of(task_of(pid)?).pgid as c_int))
+}
+
+/// Create a new session led by the current task, in a new process group.
+///
+/// The new session has no controlling terminal.
+pub fn sys_setsid() -> c_int {
+    debug!("sys_setsid");
+    syscall_body!(sys_setsid, {
+        let tid = current_tid();
+        if !members_of(tid).is_empty() {
+            // a group leader can't leave its group
+            return Err(LinuxError::EPERM);
+        }
+        IDS.write().remove(&tid);
+        Ok(tid as c_int)
+    })
+}
+
+/// Get the session ID of a task.
+pub fn sys_getsid(pid: c_int) -> c_int {
+    syscall_body!(sys_getsid, Ok(ids_of(task_of(pid)?).sid as c_int))
+}
+
+/// Sends `sig` to every task in the process group `pgid`.
+pub(crate) fn kill_pgrp(pgid: u64, sig: c_int) -> LinuxResult {
+    let members = members_of(pgid);
+    if members.is_empty() {
+        return Err(LinuxError::ESRCH);
+    }
+    if sig != 0 {
+        let sig = super::signal::check_signal(sig)?;
+        for tid in members {
+            super::signal::send_signal(tid, sig)?;
+        }
+    }
+    Ok(())
+}
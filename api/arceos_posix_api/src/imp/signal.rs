@@ -0,0 +1,714 @@
+//! Signals.
+//!
+//! All tasks belong to the same process, so dispositions are shared, while
+//! each task has its own pending and blocked sets. Since applications run in
+//! the kernel's address space, a handler is called on the stack of the
+//! target task, at two kinds of points:
+//!
+//! - On the return from a trap (e.g., a timer interrupt) that interrupted the
+//!   code of the application, out of the system calls (see
+//!   [`axtask::syscall_depth`]). The trap frame is saved, and the task is
+//!   diverted to a trampoline calling the handlers (see
+//!   [`axhal::trap::set_upcall`]). When they return, the trampoline traps
+//!   again, and [`sigreturn`] puts the saved context back in the trap frame.
+//! - On return from `kill`, `sigprocmask`, `sched_yield` and the blocking
+//!   calls, where the handlers are simply called.
+//!
+//! The kernel locks are only held in the system calls, so the handlers never
+//! run while the task holds one, as long as each call runs in a
+//! [`SyscallGuard`](crate::utils::SyscallGuard).
+//!
+//! Sending a signal interrupts the target task with [`axtask::interrupt`], so
+//! the blocking calls (`nanosleep`, futexes, `select`, `poll`, `epoll_wait`
+//! and the socket calls) return early to handle it. They fail with `EINTR`,
+//! or are restarted if the handlers were installed with `SA_RESTART` and the
+//! call has no timeout, see [`check_interrupt`].
+//!
+//! Fatal signals terminate the whole system immediately, and stop signals
+//! suspend every task at its next check point until `SIGCONT`.
+//!
+//! Faults raised by the running task (`SIGSEGV`, `SIGBUS`, `SIGFPE` and
+//! `SIGILL`) are delivered synchronously from the trap handler, on the
+//! alternate signal stack if requested. If the handler returns, the faulting
+//! instruction is executed again. A fault that is blocked or has the default
+//! disposition makes the kernel panic as before.
+
+use alloc::{collections::BTreeMap, sync::Arc};
+use core::ffi::{c_int, c_void};
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use axerrno::{LinuxError, LinuxResult};
+use axhal::arch::TrapFrame;
+use axhal::trap::{EXCEPTION, ExceptionInfo, ExceptionKind, POST_TRAP, register_trap_handler};
+use axtask::WaitQueue;
+use kspin::SpinNoIrq;
+use spin::{Mutex, RwLock};
+
+use crate::ctypes;
+use crate::imp::pthread::Pthread;
+use crate::utils::SyscallGuard;
+
+/// The number of signals, numbered from 1.
+const NSIG: usize = 64;
+
+const SIG_DFL: usize = 0;
+const SIG_IGN: usize = 1;
+
+/// The set of signals that can't be caught, blocked or ignored.
+const UNBLOCKABLE: u64 = sig_bit(ctypes::SIGKILL) | sig_bit(ctypes::SIGSTOP);
+
+const fn sig_bit(sig: u32) -> u64 {
+    1 << (sig - 1)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DefaultAction {
+    Terminate,
+    Ignore,
+    Stop,
+    Continue,
+}
+
+fn default_action(sig: u32) -> DefaultAction {
+    match sig {
+        ctypes::SIGCHLD | ctypes::SIGURG | ctypes::SIGWINCH => DefaultAction::Ignore,
+        ctypes::SIGSTOP | ctypes::SIGTSTP | ctypes::SIGTTIN | ctypes::SIGTTOU => {
+            DefaultAction::Stop
+        }
+        ctypes::SIGCONT => DefaultAction::Continue,
+        _ => DefaultAction::Terminate,
+    }
+}
+
+#[derive(Clone, Copy)]
+struct SigAction {
+    /// `SIG_DFL`, `SIG_IGN` or the address of the handler.
+    handler: usize,
+    flags: u32,
+    mask: u64,
+}
+
+impl SigAction {
+    const DEFAULT: Self = Self {
+        handler: SIG_DFL,
+        flags: 0,
+        mask: 0,
+    };
+
+    fn is_ignored(&self, sig: u32) -> bool {
+        match self.handler {
+            SIG_IGN => true,
+            SIG_DFL => default_action(sig) == DefaultAction::Ignore,
+            _ => false,
+        }
+    }
+}
+
+static ACTIONS: Mutex<[SigAction; NSIG]> = Mutex::new([SigAction::DEFAULT; NSIG]);
+
+/// Whether the process has been stopped.
+static STOPPED: AtomicBool = AtomicBool::new(false);
+static STOP_WQ: WaitQueue = WaitQueue::new();
+
+/// Where a pending signal comes from, as reported in its `siginfo_t`.
+#[derive(Clone, Copy)]
+pub(crate) enum SigSource {
+    /// Sent by a task, with `kill` and the like.
+    User(u32),
+    /// Sent by the kernel, e.g., on expiration of an interval timer.
+    Kernel,
+    /// Sent on expiration of a POSIX timer.
+    Timer { id: i32, overrun: i32, value: usize },
+}
+
+impl SigSource {
+    fn siginfo(self, sig: u32) -> ctypes::siginfo_t {
+        match self {
+            SigSource::User(pid) => {
+                let mut info = siginfo(sig, ctypes::SI_USER as _);
+                unsafe { info.__si_fields.__si_common.__first.__piduid.si_pid = pid as _ };
+                info
+            }
+            SigSource::Kernel => siginfo(sig, ctypes::SI_KERNEL as _),
+            SigSource::Timer { id, overrun, value } => {
+                let mut info = siginfo(sig, ctypes::SI_TIMER as _);
+                unsafe {
+                    let common = &mut info.__si_fields.__si_common;
+                    common.__first.__timer.si_timerid = id;
+                    common.__first.__timer.si_overrun = overrun;
+                    common.__second.si_value.sival_ptr = value as _;
+                }
+                info
+            }
+        }
+    }
+}
+
+struct TaskSignals {
+    pending: AtomicU64,
+    blocked: AtomicU64,
+    /// The source of each pending signal.
+    sources: Mutex<[SigSource; NSIG]>,
+    altstack: Mutex<AltStack>,
+    /// Whether a handler is running on the alternate signal stack.
+    on_altstack: AtomicBool,
+    /// The context of the code diverted to the handlers on the return from a
+    /// trap, until the trampoline takes it, and once it gives it back.
+    upcall_frame: SpinNoIrq<Option<TrapFrame>>,
+}
+
+impl TaskSignals {
+    fn new() -> Self {
+        Self {
+            pending: AtomicU64::new(0),
+            blocked: AtomicU64::new(0),
+            sources: Mutex::new([SigSource::Kernel; NSIG]),
+            altstack: Mutex::new(AltStack {
+                sp: 0,
+                size: 0,
+                enabled: false,
+            }),
+            on_altstack: AtomicBool::new(false),
+            upcall_frame: SpinNoIrq::new(None),
+        }
+    }
+
+    /// Returns the pending signals that are not blocked.
+    fn deliverable(&self) -> u64 {
+        self.pending.load(Ordering::Acquire) & !self.blocked.load(Ordering::Acquire)
+    }
+}
+
+/// The alternate signal stack of a task.
+#[derive(Clone, Copy)]
+struct AltStack {
+    sp: usize,
+    size: usize,
+    enabled: bool,
+}
+
+static TASK_SIGNALS: RwLock<BTreeMap<u64, Arc<TaskSignals>>> = RwLock::new(BTreeMap::new());
+
+fn signals_of(tid: u64) -> Arc<TaskSignals> {
+    if let Some(signals) = TASK_SIGNALS.read().get(&tid) {
+        return signals.clone();
+    }
+    TASK_SIGNALS
+        .write()
+        .entry(tid)
+        .or_insert_with(|| Arc::new(TaskSignals::new()))
+        .clone()
+}
+
+fn current_signals() -> Arc<TaskSignals> {
+    signals_of(axtask::current().id().as_u64())
+}
+
+/// Frees the signal state of an exited task.
+pub(crate) fn remove_task(tid: u64) {
+    TASK_SIGNALS.write().remove(&tid);
+}
+
+pub(crate) fn check_signal(sig: c_int) -> LinuxResult<u32> {
+    if (1..=NSIG as c_int).contains(&sig) {
+        Ok(sig as u32)
+    } else {
+        Err(LinuxError::EINVAL)
+    }
+}
+
+/// Sends `sig` to the task `tid`, on behalf of the current task.
+pub(crate) fn send_signal(tid: u64, sig: u32) -> LinuxResult {
+    let sender = axtask::current().id().as_u64() as u32;
+    send_signal_from(tid, sig, SigSource::User(sender))
+}
+
+/// Sends `sig` to the task `tid`.
+pub(crate) fn send_signal_from(tid: u64, sig: u32, source: SigSource) -> LinuxResult {
+    if !Pthread::exists(tid) {
+        return Err(LinuxError::ESRCH);
+    }
+    let action = ACTIONS.lock()[sig as usize - 1];
+
+    if sig == ctypes::SIGCONT {
+        // discard the pending stop signals
+        for signals in TASK_SIGNALS.read().values() {
+            signals.pending.fetch_and(
+                !(sig_bit(ctypes::SIGSTOP)
+                    | sig_bit(ctypes::SIGTSTP)
+                    | sig_bit(ctypes::SIGTTIN)
+                    | sig_bit(ctypes::SIGTTOU)),
+                Ordering::AcqRel,
+            );
+        }
+        if STOPPED.swap(false, Ordering::AcqRel) {
+            STOP_WQ.notify_all(false);
+        }
+    } else if default_action(sig) == DefaultAction::Stop {
+        // a stop signal discards the pending SIGCONT
+        for signals in TASK_SIGNALS.read().values() {
+            signals
+                .pending
+                .fetch_and(!sig_bit(ctypes::SIGCONT), Ordering::AcqRel);
+        }
+    }
+    if action.is_ignored(sig) && sig != ctypes::SIGKILL && sig != ctypes::SIGSTOP {
+        return Ok(());
+    }
+
+    let signals = signals_of(tid);
+    let blocked = signals.blocked.load(Ordering::Acquire) & sig_bit(sig) != 0;
+    if !blocked
+        && (sig == ctypes::SIGKILL
+            || action.handler == SIG_DFL && default_action(sig) == DefaultAction::Terminate)
+    {
+        // the whole process dies, no matter which task receives it
+        terminate(sig);
+    }
+    signals.sources.lock()[sig as usize - 1] = source;
+    signals.pending.fetch_or(sig_bit(sig), Ordering::AcqRel);
+    if !blocked {
+        if let Some(task) = Pthread::task(tid) {
+            axtask::interrupt(&task);
+        }
+    }
+    Ok(())
+}
+
+fn terminate(sig: u32) -> ! {
+    warn!("terminated by signal {}", sig);
+    axhal::misc::terminate();
+}
+
+fn siginfo(sig: u32, code: c_int) -> ctypes::siginfo_t {
+    ctypes::siginfo_t {
+        si_signo: sig as _,
+        si_code: code,
+        ..Default::default()
+    }
+}
+
+/// The arguments of a handler called on the alternate signal stack.
+struct HandlerCall {
+    action: SigAction,
+    sig: u32,
+    info: ctypes::siginfo_t,
+}
+
+unsafe fn call_handler(action: &SigAction, sig: u32, info: &mut ctypes::siginfo_t) {
+    unsafe {
+        if action.flags & ctypes::SA_SIGINFO != 0 {
+            let handler: unsafe extern "C" fn(c_int, *mut ctypes::siginfo_t, *mut c_void) =
+                core::mem::transmute(action.handler);
+            handler(sig as _, info, core::ptr::null_mut());
+        } else {
+            let handler: unsafe extern "C" fn(c_int) = core::mem::transmute(action.handler);
+            handler(sig as _);
+        }
+    }
+}
+
+unsafe extern "C" fn call_handler_on_stack(arg: usize) {
+    let call = unsafe { &mut *(arg as *mut HandlerCall) };
+    unsafe { call_handler(&call.action, call.sig, &mut call.info) };
+}
+
+/// Runs the handler of `sig`, with the signals in its mask blocked.
+///
+/// Returns whether the interrupted call should be restarted.
+fn run_handler(signals: &TaskSignals, sig: u32, info: ctypes::siginfo_t) -> bool {
+    let action = {
+        let mut actions = ACTIONS.lock();
+        let action = actions[sig as usize - 1];
+        if action.flags & ctypes::SA_RESETHAND != 0 && action.handler > SIG_IGN {
+            actions[sig as usize - 1] = SigAction::DEFAULT;
+        }
+        action
+    };
+    match action.handler {
+        SIG_IGN => return true,
+        SIG_DFL => {
+            match default_action(sig) {
+                DefaultAction::Terminate => terminate(sig),
+                DefaultAction::Stop => {
+                    STOPPED.store(true, Ordering::Release);
+                }
+                DefaultAction::Ignore | DefaultAction::Continue => {}
+            }
+            return true;
+        }
+        _ => {}
+    }
+
+    let mut mask = action.mask;
+    if action.flags & ctypes::SA_NODEFER == 0 {
+        mask |= sig_bit(sig);
+    }
+    let old_blocked = signals
+        .blocked
+        .fetch_or(mask & !UNBLOCKABLE, Ordering::AcqRel);
+    let altstack = *signals.altstack.lock();
+    // the handler runs the code of the application, which may be diverted
+    // to other handlers, or jump out of it
+    let depth = axtask::syscall_depth();
+    axtask::set_syscall_depth(0);
+    if action.flags & ctypes::SA_ONSTACK != 0
+        && altstack.enabled
+        && !signals.on_altstack.swap(true, Ordering::AcqRel)
+    {
+        let mut call = HandlerCall { action, sig, info };
+        unsafe {
+            axhal::arch::call_on_stack(
+                altstack.sp + altstack.size,
+                call_handler_on_stack,
+                &mut call as *mut _ as usize,
+            );
+        }
+        signals.on_altstack.store(false, Ordering::Release);
+    } else {
+        let mut info = info;
+        unsafe { call_handler(&action, sig, &mut info) };
+    }
+    axtask::set_syscall_depth(depth);
+    signals.blocked.store(old_blocked, Ordering::Release);
+    action.flags & ctypes::SA_RESTART != 0
+}
+
+/// Handles the deliverable signals of the current task.
+///
+/// Returns `None` if there was no signal, or whether the interrupted call
+/// should be restarted, i.e., all handlers were installed with `SA_RESTART`.
+pub(crate) fn handle_signals() -> Option<bool> {
+    let _guard = SyscallGuard::enter();
+    let signals = current_signals();
+    let mut restart = None;
+    loop {
+        // cleared before checking, so a signal sent meanwhile interrupts again
+        axtask::clear_interrupt();
+        let deliverable = signals.deliverable();
+        if deliverable == 0 {
+            break;
+        }
+        let sig = deliverable.trailing_zeros() + 1;
+        signals.pending.fetch_and(!sig_bit(sig), Ordering::AcqRel);
+        let info = signals.sources.lock()[sig as usize - 1].siginfo(sig);
+        let handled = run_handler(&signals, sig, info);
+        restart = Some(restart.unwrap_or(true) && handled);
+    }
+    if STOPPED.load(Ordering::Acquire) {
+        STOP_WQ.wait_until(|| !STOPPED.load(Ordering::Acquire));
+    }
+    restart
+}
+
+/// Sleeps for `dur`, or until a signal can be delivered to the current task.
+///
+/// Returns `true` if it's interrupted.
+pub(crate) fn sleep_interruptible(dur: core::time::Duration) -> bool {
+    #[cfg(feature = "irq")]
+    {
+        WaitQueue::new().wait_timeout_interruptible(dur) == axtask::WaitResult::Interrupted
+    }
+    #[cfg(not(feature = "irq"))]
+    {
+        axtask::sleep(dur);
+        current_signals().deliverable() != 0
+    }
+}
+
+/// Handles the signals interrupting a blocking call, if any.
+///
+/// Returns `Ok(())` if the call should go on: there was no signal, or it's
+/// `restartable` and all the handlers were installed with `SA_RESTART`.
+/// Otherwise, it fails with `EINTR`.
+pub(crate) fn check_interrupt(restartable: bool) -> LinuxResult {
+    if !axtask::interrupt_pending() {
+        return Ok(());
+    }
+    match handle_signals() {
+        None => Ok(()),
+        Some(true) if restartable => Ok(()),
+        Some(_) => Err(LinuxError::EINTR),
+    }
+}
+
+/// Diverts the current task to its signal handlers on the return from a trap,
+/// if it interrupted the code of the application with signals to deliver.
+#[register_trap_handler(POST_TRAP)]
+fn divert_to_handlers(tf: &mut TrapFrame, from_user: bool) {
+    if from_user || axtask::current_may_uninit().is_none() {
+        return;
+    }
+    if axtask::syscall_depth() != 0 || !axtask::interrupt_pending() {
+        return;
+    }
+    let tid = axtask::current().id().as_u64();
+    // it can't be held by the interrupted code, but may be by another CPU
+    let Some(signals) = TASK_SIGNALS
+        .try_read()
+        .and_then(|map| map.get(&tid).cloned())
+    else {
+        return;
+    };
+    if signals.deliverable() == 0 {
+        return;
+    }
+    let mut frame = signals.upcall_frame.lock();
+    if frame.is_some() {
+        // interrupted in the trampoline, which runs the handlers anyway
+        return;
+    }
+    *frame = Some(*tf);
+    axhal::trap::set_upcall(tf, signal_trampoline, 0);
+}
+
+/// Runs the handlers of the current task diverted by [`divert_to_handlers`].
+extern "C" fn signal_trampoline(_arg: usize) {
+    let signals = {
+        let _guard = SyscallGuard::enter();
+        current_signals()
+    };
+    // kept on the stack meanwhile, as a handler may not return
+    let frame = signals.upcall_frame.lock().take();
+    handle_signals();
+    *signals.upcall_frame.lock() = frame;
+}
+
+/// Puts the context saved by [`divert_to_handlers`] back in `tf` once the
+/// handlers have run, as `rt_sigreturn`.
+///
+/// Returns `false` if there's no saved context.
+fn sigreturn(tf: &mut TrapFrame) -> bool {
+    let signals = {
+        let _guard = SyscallGuard::enter();
+        current_signals()
+    };
+    let frame = signals.upcall_frame.lock().take();
+    match frame {
+        Some(frame) => {
+            *tf = frame;
+            true
+        }
+        None => false,
+    }
+}
+
+/// Delivers a fault of the current task as a signal, or returns from the
+/// trampoline of the handlers.
+#[register_trap_handler(EXCEPTION)]
+fn handle_exception(tf: &mut TrapFrame, info: &ExceptionInfo) -> bool {
+    if axhal::trap::is_upcall_return(tf) {
+        return sigreturn(tf);
+    }
+    let (sig, code) = match info.kind {
+        ExceptionKind::PageFault => (ctypes::SIGSEGV, ctypes::SEGV_MAPERR),
+        ExceptionKind::Misaligned => (ctypes::SIGBUS, ctypes::BUS_ADRALN),
+        ExceptionKind::DivideError => (ctypes::SIGFPE, ctypes::FPE_INTDIV),
+        ExceptionKind::IllegalInstruction => (ctypes::SIGILL, ctypes::ILL_ILLOPC),
+    };
+    let signals = current_signals();
+    let handler = ACTIONS.lock()[sig as usize - 1].handler;
+    if signals.blocked.load(Ordering::Acquire) & sig_bit(sig) != 0 || handler <= SIG_IGN {
+        // a blocked or ignored fault can't be recovered from
+        return false;
+    }
+    let mut si = siginfo(sig, code as _);
+    unsafe { si.__si_fields.__sigfault.si_addr = info.fault_addr.as_usize() as _ };
+    run_handler(&signals, sig, si);
+    true
+}
+
+fn read_sigset(set: *const ctypes::sigset_t) -> Option<u64> {
+    (!set.is_null()).then(|| unsafe { (*set).__bits[0] as u64 })
+}
+
+fn write_sigset(set: *mut ctypes::sigset_t, mask: u64) {
+    if !set.is_null() {
+        unsafe {
+            *set = Default::default();
+            (*set).__bits[0] = mask as _;
+        }
+    }
+}
+
+/// Examine and change the action of a signal.
+pub unsafe fn sys_rt_sigaction(
+    signum: c_int,
+    act: *const ctypes::sigaction,
+    oldact: *mut ctypes::sigaction,
+) -> c_int {
+    debug!(
+        "sys_rt_sigaction <= {} {:#x} {:#x}",
+        signum, act as usize, oldact as usize
+    );
+    syscall_body!(sys_rt_sigaction, {
+        let sig = check_signal(signum)?;
+        let mut actions = ACTIONS.lock();
+        let old = actions[sig as usize - 1];
+        if !act.is_null() {
+            if sig_bit(sig) & UNBLOCKABLE != 0 {
+                return Err(LinuxError::EINVAL);
+            }
+            let act = unsafe { &*act };
+            actions[sig as usize - 1] = SigAction {
+                handler: unsafe { act.__sa_handler.sa_handler }.map_or(SIG_DFL, |f| f as usize),
+                flags: act.sa_flags as u32,
+                mask: read_sigset(&act.sa_mask).unwrap_or(0),
+            };
+        }
+        drop(actions);
+        if !oldact.is_null() {
+            let oldact = unsafe { &mut *oldact };
+            *oldact = Default::default();
+            oldact.__sa_handler.sa_handler = unsafe { core::mem::transmute(old.handler) };
+            oldact.sa_flags = old.flags as _;
+            write_sigset(&mut oldact.sa_mask, old.mask);
+        }
+        Ok(0)
+    })
+}
+
+/// Examine and change the blocked signals of the current task.
+pub unsafe fn sys_rt_sigprocmask(
+    how: c_int,
+    set: *const ctypes::sigset_t,
+    oldset: *mut ctypes::sigset_t,
+) -> c_int {
+    debug!("sys_rt_sigprocmask <= {} {:#x}", how, set as usize);
+    let ret = syscall_body!(sys_rt_sigprocmask, {
+        let signals = current_signals();
+        let old = signals.blocked.load(Ordering::Acquire);
+        if let Some(set) = read_sigset(set) {
+            let new = match how as u32 {
+                ctypes::SIG_BLOCK => old | set,
+                ctypes::SIG_UNBLOCK => old & !set,
+                ctypes::SIG_SETMASK => set,
+                _ => return Err(LinuxError::EINVAL),
+            };
+            signals.blocked.store(new & !UNBLOCKABLE, Ordering::Release);
+        }
+        write_sigset(oldset, old);
+        Ok(0)
+    });
+    handle_signals();
+    ret
+}
+
+/// Set and/or get the alternate signal stack of the current task.
+pub unsafe fn sys_sigaltstack(ss: *const ctypes::stack_t, old_ss: *mut ctypes::stack_t) -> c_int {
+    debug!(
+        "sys_sigaltstack <= {:#x} {:#x}",
+        ss as usize, old_ss as usize
+    );
+    syscall_body!(sys_sigaltstack, {
+        let signals = current_signals();
+        let on_altstack = signals.on_altstack.load(Ordering::Acquire);
+        let mut altstack = signals.altstack.lock();
+        if !old_ss.is_null() {
+            let flags = if on_altstack {
+                ctypes::SS_ONSTACK
+            } else if !altstack.enabled {
+                ctypes::SS_DISABLE
+            } else {
+                0
+            };
+            unsafe {
+                *old_ss = ctypes::stack_t {
+                    ss_sp: altstack.sp as _,
+                    ss_flags: flags as _,
+                    ss_size: altstack.size as _,
+                };
+            }
+        }
+        if !ss.is_null() {
+            let ss = unsafe { &*ss };
+            if on_altstack {
+                return Err(LinuxError::EPERM);
+            }
+            match ss.ss_flags as u32 {
+                ctypes::SS_DISABLE => altstack.enabled = false,
+                0 => {
+                    if (ss.ss_size as usize) < ctypes::MINSIGSTKSZ as usize {
+                        return Err(LinuxError::ENOMEM);
+                    }
+                    *altstack = AltStack {
+                        sp: ss.ss_sp as usize,
+                        size: ss.ss_size as usize,
+                        enabled: true,
+                    };
+                }
+                _ => return Err(LinuxError::EINVAL),
+            }
+        }
+        Ok(0)
+    })
+}
+
+/// Get the signals pending on the current task.
+pub unsafe fn sys_sigpending(set: *mut ctypes::sigset_t) -> c_int {
+    syscall_body!(sys_sigpending, {
+        if set.is_null() {
+            return Err(LinuxError::EFAULT);
+        }
+        write_sigset(set, current_signals().pending.load(Ordering::Acquire));
+        Ok(0)
+    })
+}
+
+/// Send a signal to a task or a process group.
+///
+/// Each task is a process of its own ID as returned by `getpid`. If `pid` is
+/// 0 or less than -1, the signal is sent to every task in the process group
+/// of the current task or of `-pid`. If `sig` is 0, only the existence of the
+/// target is checked.
+pub fn sys_kill(pid: c_int, sig: c_int) -> c_int {
+    debug!("sys_kill <= {} {}", pid, sig);
+    let ret = syscall_body!(sys_kill, {
+        if pid == 0 || pid < -1 {
+            let pgid = if pid == 0 {
+                super::session::current_pgid()
+            } else {
+                pid.unsigned_abs() as u64
+            };
+            super::session::kill_pgrp(pgid, sig)?;
+            return Ok(0);
+        }
+        if pid == -1 {
+            // no permission to signal every process
+            return Err(LinuxError::ESRCH);
+        }
+        if sig == 0 {
+            return if Pthread::exists(pid as u64) {
+                Ok(0)
+            } else {
+                Err(LinuxError::ESRCH)
+            };
+        }
+        send_signal(pid as u64, check_signal(sig)?)?;
+        Ok(0)
+    });
+    handle_signals();
+    ret
+}
+
+/// Send a signal to a thread.
+///
+/// As there's only one process, `tgid` is not checked.
+pub fn sys_tgkill(_tgid: c_int, tid: c_int, sig: c_int) -> c_int {
+    sys_kill(tid, sig)
+}
+
+/// Send a signal to a thread.
+pub fn sys_pthread_kill(thread: ctypes::pthread_t, sig: c_int) -> c_int {
+    debug!("sys_pthread_kill <= {:#x} {}", thread as usize, sig);
+    let ret = syscall_body!(sys_pthread_kill, {
+        // not dereferenced, it may be a thread already joined
+        let tid = Pthread::tid(thread).ok_or(LinuxError::ESRCH)?;
+        if sig != 0 {
+            send_signal(tid, check_signal(sig)?)?;
+        }
+        Ok(0)
+    });
+    handle_signals();
+    ret
+}
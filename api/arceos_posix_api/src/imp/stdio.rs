@@ -1,43 +1,19 @@
 use axerrno::AxResult;
-use axio::{BufReader, prelude::*};
+use axio::prelude::*;
 use axsync::Mutex;
 
+use super::tty::console;
+
 #[cfg(feature = "fd")]
 use {alloc::sync::Arc, axerrno::LinuxError, axerrno::LinuxResult, axio::PollState};
 
-fn console_read_bytes(buf: &mut [u8]) -> AxResult<usize> {
-    let len = axhal::console::read_bytes(buf);
-    for c in &mut buf[..len] {
-        if *c == b'\r' {
-            *c = b'\n';
-        }
-    }
-    Ok(len)
-}
-
 fn console_write_bytes(buf: &[u8]) -> AxResult<usize> {
     axhal::console::write_bytes(buf);
     Ok(buf.len())
 }
 
-struct StdinRaw;
 struct StdoutRaw;
 
-impl Read for StdinRaw {
-    // Non-blocking read, returns number of bytes read.
-    fn read(&mut self, buf: &mut [u8]) -> AxResult<usize> {
-        let mut read_len = 0;
-        while read_len < buf.len() {
-            let len = console_read_bytes(buf[read_len..].as_mut())?;
-            if len == 0 {
-                break;
-            }
-            read_len += len;
-        }
-        Ok(read_len)
-    }
-}
-
 impl Write for StdoutRaw {
     fn write(&mut self, buf: &[u8]) -> AxResult<usize> {
         console_write_bytes(buf)
@@ -48,31 +24,12 @@ impl Write for StdoutRaw {
     }
 }
 
-pub struct Stdin {
-    inner: &'static Mutex<BufReader<StdinRaw>>,
-}
-
-impl Stdin {
-    // Block until at least one byte is read.
-    fn read_blocked(&self, buf: &mut [u8]) -> AxResult<usize> {
-        let read_len = self.inner.lock().read(buf)?;
-        if buf.is_empty() || read_len > 0 {
-            return Ok(read_len);
-        }
-        // try again until we get something
-        loop {
-            let read_len = self.inner.lock().read(buf)?;
-            if read_len > 0 {
-                return Ok(read_len);
-            }
-            crate::sys_sched_yield();
-        }
-    }
-}
+/// The standard input, read through the line discipline of the console.
+pub struct Stdin;
 
 impl Read for Stdin {
     fn read(&mut self, buf: &mut [u8]) -> AxResult<usize> {
-        self.read_blocked(buf)
+        Ok(console().read(buf, false, || false)?)
     }
 }
 
@@ -92,8 +49,7 @@ impl Write for Stdout {
 
 /// Constructs a new handle to the standard input of the current process.
 pub fn stdin() -> Stdin {
-    static INSTANCE: Mutex<BufReader<StdinRaw>> = Mutex::new(BufReader::new(StdinRaw));
-    Stdin { inner: &INSTANCE }
+    Stdin
 }
 
 /// Constructs a new handle to the standard output of the current process.
@@ -105,7 +61,7 @@ pub fn stdout() -> Stdout {
 #[cfg(feature = "fd")]
 impl super::fd_ops::FileLike for Stdin {
     fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
-        Ok(self.read_blocked(buf)?)
+        console().read(buf, false, || false)
     }
 
     fn write(&self, _buf: &[u8]) -> LinuxResult<usize> {
@@ -128,7 +84,7 @@ impl super::fd_ops::FileLike for Stdin {
 
     fn poll(&self) -> LinuxResult<PollState> {
         Ok(PollState {
-            readable: true,
+            readable: console().readable(),
             writable: true,
         })
     }
@@ -136,6 +92,10 @@ impl super::fd_ops::FileLike for Stdin {
     fn set_nonblocking(&self, _nonblocking: bool) -> LinuxResult {
         Ok(())
     }
+
+    fn ioctl(&self, cmd: u32, arg: usize) -> LinuxResult<core::ffi::c_int> {
+        console().ioctl(cmd, arg)
+    }
 }
 
 #[cfg(feature = "fd")]
@@ -172,4 +132,8 @@ impl super::fd_ops::FileLike for Stdout {
     fn set_nonblocking(&self, _nonblocking: bool) -> LinuxResult {
         Ok(())
     }
+
+    fn ioctl(&self, cmd: u32, arg: usize) -> LinuxResult<core::ffi::c_int> {
+        console().ioctl(cmd, arg)
+    }
 }
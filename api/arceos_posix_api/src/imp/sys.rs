@@ -1,9 +1,31 @@
-use core::ffi::{c_int, c_long};
+use core::ffi::{c_int, c_long, c_uint, c_ulong, c_void};
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use axerrno::LinuxError;
 
 use crate::ctypes;
 
 const PAGE_SIZE_4K: usize = 4096;
 
+const PER_LINUX: u32 = 0;
+const PER_MASK: u32 = 0xff;
+/// Disables the ASLR.
+#[cfg(feature = "paging")]
+const ADDR_NO_RANDOMIZE: u32 = 0x0040000;
+/// Queries the personality without changing it.
+const PER_QUERY: c_ulong = 0xffff_ffff;
+
+/// Don't block if the entropy pool isn't initialized.
+const GRND_NONBLOCK: c_uint = 0x1;
+/// Draw from the blocking pool.
+const GRND_RANDOM: c_uint = 0x2;
+/// Don't wait for the entropy pool to be initialized.
+const GRND_INSECURE: c_uint = 0x4;
+
+/// The execution domain and flags set by `personality`, shared by all the
+/// tasks.
+static PERSONALITY: AtomicU32 = AtomicU32::new(PER_LINUX);
+
 /// Return system configuration infomation
 ///
 /// Notice: currently only support what unikraft covers
@@ -40,3 +62,59 @@ pub fn sys_sysconf(name: c_int) -> c_long {
         }
     })
 }
+
+/// Set the execution domain of the tasks, returns the previous one.
+///
+/// Only the native Linux domain, `PER_LINUX`, is supported, and `PER_LINUX32`
+/// fails with `EINVAL`. The application and the plugins run in the 64-bit
+/// kernel mode, with the ABI of the kernel: there's no user mode, and so no
+/// compat mode, to run 32-bit code in, and `axplugin` rejects the 32-bit
+/// objects.
+/// `ADDR_NO_RANDOMIZE` disables the ASLR of the places chosen afterwards (see
+/// `axmm::set_addr_no_randomize`), the other flags are kept, but have no
+/// effect. A `persona` of `0xffffffff` only queries the personality.
+pub fn sys_personality(persona: c_ulong) -> c_int {
+    debug!("sys_personality <= {:#x}", persona);
+    syscall_body!(sys_personality, {
+        if persona == PER_QUERY {
+            return Ok(PERSONALITY.load(Ordering::Relaxed));
+        }
+        let persona = u32::try_from(persona).map_err(|_| LinuxError::EINVAL)?;
+        if persona & PER_MASK != PER_LINUX {
+            return Err(LinuxError::EINVAL);
+        }
+        #[cfg(feature = "paging")]
+        axmm::set_addr_no_randomize(persona & ADDR_NO_RANDOMIZE != 0);
+        Ok(PERSONALITY.swap(persona, Ordering::Relaxed))
+    })
+}
+
+/// Fill the buffer with random bytes from the kernel CSPRNG.
+///
+/// The CSPRNG is seeded at boot before the application runs, so it never
+/// blocks and all the flags give the same bytes. The application calls it
+/// directly in the kernel, so there's no syscall to avoid by a vDSO.
+pub fn sys_getrandom(buf: *mut c_void, buflen: usize, flags: c_uint) -> ctypes::ssize_t {
+    debug!(
+        "sys_getrandom <= {:#x} {} {:#x}",
+        buf as usize, buflen, flags
+    );
+    syscall_body!(sys_getrandom, {
+        if flags & !(GRND_NONBLOCK | GRND_RANDOM | GRND_INSECURE) != 0
+            || flags & (GRND_RANDOM | GRND_INSECURE) == GRND_RANDOM | GRND_INSECURE
+        {
+            return Err(LinuxError::EINVAL);
+        }
+        if buflen == 0 {
+            return Ok(0);
+        }
+        if buf.is_null() {
+            return Err(LinuxError::EFAULT);
+        }
+        // at most `MAX_RW_COUNT` bytes at once, as Linux
+        let buflen = buflen.min(c_int::MAX as usize & !(PAGE_SIZE_4K - 1));
+        let buf = unsafe { core::slice::from_raw_parts_mut(buf as *mut u8, buflen) };
+        axhal::random::fill_bytes(buf);
+        Ok(buflen)
+    })
+}
@@ -1,4 +1,15 @@
-use core::ffi::c_int;
+use core::ffi::{c_int, c_uint};
+use core::sync::atomic::{AtomicBool, Ordering, fence};
+
+use axerrno::LinuxError;
+
+#[cfg(feature = "multitask")]
+use {
+    crate::ctypes,
+    crate::imp::pthread::Pthread,
+    axerrno::LinuxResult,
+    axtask::{AxCpuMask, AxTaskRef},
+};
 
 /// Relinquish the CPU, and switches to another task.
 ///
@@ -6,7 +17,10 @@ use core::ffi::c_int;
 /// relax the CPU and wait for incoming interrupts.
 pub fn sys_sched_yield() -> c_int {
     #[cfg(feature = "multitask")]
-    axtask::yield_now();
+    {
+        axtask::yield_now();
+        super::signal::handle_signals();
+    }
     #[cfg(not(feature = "multitask"))]
     if cfg!(feature = "irq") {
         axhal::arch::wait_for_irqs();
@@ -30,6 +44,21 @@ pub fn sys_getpid() -> c_int {
     )
 }
 
+/// Get the CPU and the NUMA node the current thread is running on.
+///
+/// There's a single NUMA node, numbered 0. Either pointer may be null.
+pub fn sys_getcpu(cpu: *mut c_uint, node: *mut c_uint) -> c_int {
+    syscall_body!(sys_getcpu, {
+        if !cpu.is_null() {
+            unsafe { *cpu = axhal::cpu::this_cpu_id() as c_uint };
+        }
+        if !node.is_null() {
+            unsafe { *node = 0 };
+        }
+        Ok(0)
+    })
+}
+
 /// Exit current task
 pub fn sys_exit(exit_code: c_int) -> ! {
     debug!("sys_exit <= {}", exit_code);
@@ -38,3 +67,201 @@ pub fn sys_exit(exit_code: c_int) -> ! {
     #[cfg(not(feature = "multitask"))]
     axhal::misc::terminate();
 }
+
+/// Checks that `which` and `who` of `getpriority`/`setpriority` name the
+/// current task, the only one whose priority can be queried or changed.
+#[cfg(feature = "multitask")]
+fn check_prio_target(which: c_int, who: c_int) -> LinuxResult {
+    match which as u32 {
+        ctypes::PRIO_PROCESS | ctypes::PRIO_PGRP | ctypes::PRIO_USER => {}
+        _ => return Err(LinuxError::EINVAL),
+    }
+    if who != 0 && who as u64 != axtask::current().id().as_u64() {
+        return Err(LinuxError::ESRCH);
+    }
+    Ok(())
+}
+
+/// Get the scheduling priority of the current task.
+///
+/// As with the Linux system call, it returns `20 - nice` to stay positive,
+/// i.e. a value from 1 (lowest priority) to 40 (highest priority).
+#[cfg(feature = "multitask")]
+pub fn sys_getpriority(which: c_int, who: c_int) -> c_int {
+    debug!("sys_getpriority <= {} {}", which, who);
+    syscall_body!(sys_getpriority, {
+        check_prio_target(which, who)?;
+        Ok(20 - axtask::current().priority() as c_int)
+    })
+}
+
+/// Set the scheduling priority (nice value) of the current task.
+///
+/// `prio` is clamped into the range -20..=19. It fails with `EPERM` if the
+/// scheduler has no priorities, e.g. without the `sched_cfs` feature.
+#[cfg(feature = "multitask")]
+pub fn sys_setpriority(which: c_int, who: c_int, prio: c_int) -> c_int {
+    debug!("sys_setpriority <= {} {} {}", which, who, prio);
+    syscall_body!(sys_setpriority, {
+        check_prio_target(which, who)?;
+        let prio = prio.clamp(-20, 19);
+        if !axtask::set_priority(prio as isize) {
+            return Err(LinuxError::EPERM);
+        }
+        Ok(0)
+    })
+}
+
+/// Add `inc` to the nice value of the current task.
+#[cfg(feature = "multitask")]
+pub fn sys_nice(inc: c_int) -> c_int {
+    debug!("sys_nice <= {}", inc);
+    let nice = axtask::current().priority() as c_int;
+    sys_setpriority(ctypes::PRIO_PROCESS as c_int, 0, nice.saturating_add(inc))
+}
+
+/// Returns the task of ID `pid`, or the current task if `pid` is 0.
+#[cfg(feature = "multitask")]
+fn task_of(pid: c_int) -> LinuxResult<AxTaskRef> {
+    if pid == 0 {
+        return Ok(axtask::current().as_task_ref().clone());
+    }
+    let tid = u64::try_from(pid).map_err(|_| LinuxError::ESRCH)?;
+    Pthread::task(tid).ok_or(LinuxError::ESRCH)
+}
+
+/// Set the CPU affinity mask of a task.
+///
+/// The CPUs that don't exist are ignored. A task running on another CPU moves
+/// to an allowed one when it's preempted or woken up.
+#[cfg(feature = "multitask")]
+pub fn sys_sched_setaffinity(
+    pid: c_int,
+    cpusetsize: usize,
+    mask: *const ctypes::cpu_set_t,
+) -> c_int {
+    debug!(
+        "sys_sched_setaffinity <= {} {} {:#x}",
+        pid, cpusetsize, mask as usize
+    );
+    syscall_body!(sys_sched_setaffinity, {
+        if mask.is_null() {
+            return Err(LinuxError::EFAULT);
+        }
+        let bits = unsafe { core::slice::from_raw_parts(mask as *const u8, cpusetsize) };
+        let mut cpumask = AxCpuMask::new();
+        for cpu in 0..axconfig::SMP.min(cpusetsize * 8) {
+            cpumask.set(cpu, bits[cpu / 8] & (1 << (cpu % 8)) != 0);
+        }
+        if !axtask::set_affinity(&task_of(pid)?, cpumask) {
+            return Err(LinuxError::EINVAL);
+        }
+        Ok(0)
+    })
+}
+
+/// Get the CPU affinity mask of a task.
+///
+/// `cpusetsize` must be large enough for all the CPUs.
+#[cfg(feature = "multitask")]
+pub fn sys_sched_getaffinity(pid: c_int, cpusetsize: usize, mask: *mut ctypes::cpu_set_t) -> c_int {
+    debug!(
+        "sys_sched_getaffinity <= {} {} {:#x}",
+        pid, cpusetsize, mask as usize
+    );
+    syscall_body!(sys_sched_getaffinity, {
+        if mask.is_null() {
+            return Err(LinuxError::EFAULT);
+        }
+        if cpusetsize * 8 < axconfig::SMP {
+            return Err(LinuxError::EINVAL);
+        }
+        let cpumask = task_of(pid)?.cpumask();
+        let bits = unsafe { core::slice::from_raw_parts_mut(mask as *mut u8, cpusetsize) };
+        bits.fill(0);
+        for cpu in (0..axconfig::SMP).filter(|&cpu| cpumask.get(cpu)) {
+            bits[cpu / 8] |= 1 << (cpu % 8);
+        }
+        Ok(0)
+    })
+}
+
+const MEMBARRIER_CMD_QUERY: c_int = 0;
+const MEMBARRIER_CMD_GLOBAL: c_int = 1 << 0;
+const MEMBARRIER_CMD_GLOBAL_EXPEDITED: c_int = 1 << 1;
+const MEMBARRIER_CMD_REGISTER_GLOBAL_EXPEDITED: c_int = 1 << 2;
+const MEMBARRIER_CMD_PRIVATE_EXPEDITED: c_int = 1 << 3;
+const MEMBARRIER_CMD_REGISTER_PRIVATE_EXPEDITED: c_int = 1 << 4;
+
+const MEMBARRIER_SUPPORTED: c_int = MEMBARRIER_CMD_GLOBAL
+    | MEMBARRIER_CMD_GLOBAL_EXPEDITED
+    | MEMBARRIER_CMD_REGISTER_GLOBAL_EXPEDITED
+    | MEMBARRIER_CMD_PRIVATE_EXPEDITED
+    | MEMBARRIER_CMD_REGISTER_PRIVATE_EXPEDITED;
+
+/// Whether `MEMBARRIER_CMD_REGISTER_PRIVATE_EXPEDITED` has been issued. All
+/// the tasks share the address space, so they register as one process.
+static MEMBARRIER_PRIVATE_REGISTERED: AtomicBool = AtomicBool::new(false);
+
+/// Whether the barriers reach all the CPUs: by IPIs, or there's only one.
+const MEMBARRIER_AVAILABLE: bool =
+    axconfig::SMP == 1 || cfg!(all(feature = "smp", feature = "irq"));
+
+/// Issue a memory barrier on all the CPUs running the tasks.
+///
+/// The other CPUs run the barrier in the handler of an IPI, and the call
+/// returns once they all have (see `axhal::irq::ipi`). Without IPIs on
+/// multiple CPUs, no command is supported. The expedited commands are the
+/// same as `MEMBARRIER_CMD_GLOBAL`, as all the tasks share the address space.
+/// The `SYNC_CORE` and `RSEQ` commands are not supported.
+pub fn sys_membarrier(cmd: c_int, flags: c_uint, cpu_id: c_int) -> c_int {
+    debug!("sys_membarrier <= {} {} {}", cmd, flags, cpu_id);
+    syscall_body!(sys_membarrier, {
+        if flags != 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        if cmd == MEMBARRIER_CMD_QUERY {
+            return Ok(if MEMBARRIER_AVAILABLE {
+                MEMBARRIER_SUPPORTED
+            } else {
+                0
+            });
+        }
+        if !MEMBARRIER_AVAILABLE {
+            return Err(LinuxError::EINVAL);
+        }
+        match cmd {
+            MEMBARRIER_CMD_REGISTER_GLOBAL_EXPEDITED => return Ok(0),
+            MEMBARRIER_CMD_REGISTER_PRIVATE_EXPEDITED => {
+                MEMBARRIER_PRIVATE_REGISTERED.store(true, Ordering::Relaxed);
+                return Ok(0);
+            }
+            MEMBARRIER_CMD_PRIVATE_EXPEDITED => {
+                if !MEMBARRIER_PRIVATE_REGISTERED.load(Ordering::Relaxed) {
+                    return Err(LinuxError::EPERM);
+                }
+            }
+            MEMBARRIER_CMD_GLOBAL | MEMBARRIER_CMD_GLOBAL_EXPEDITED => {}
+            _ => return Err(LinuxError::EINVAL),
+        }
+        fence(Ordering::SeqCst);
+        #[cfg(all(feature = "smp", feature = "irq"))]
+        axhal::irq::ipi::run_on_other_cpus(&|| fence(Ordering::SeqCst));
+        Ok(0)
+    })
+}
+
+/// Updates the file at `path` in the procfs if it's one of the task
+/// statistics, `/proc/groups` or `/proc/schedstat`, before it's opened.
+///
+/// Other paths are ignored.
+#[cfg(all(feature = "multitask", feature = "fs"))]
+pub(crate) fn update_proc_task_file(path: &str) {
+    let content = match path {
+        "/proc/groups" => axtask::proc_task_groups(),
+        "/proc/schedstat" => axtask::proc_schedstat(),
+        _ => return,
+    };
+    // fails if there's no procfs
+    axfs::api::write(path, content).ok();
+}
@@ -1,9 +1,14 @@
-use axerrno::LinuxError;
+use axerrno::{LinuxError, LinuxResult};
 use core::ffi::{c_int, c_long};
+#[cfg(feature = "alloc")]
+use core::sync::atomic::{AtomicI64, Ordering};
 use core::time::Duration;
 
 use crate::ctypes;
-use crate::ctypes::{CLOCK_MONOTONIC, CLOCK_REALTIME};
+use crate::ctypes::{
+    CLOCK_BOOTTIME, CLOCK_MONOTONIC, CLOCK_MONOTONIC_RAW, CLOCK_PROCESS_CPUTIME_ID, CLOCK_REALTIME,
+    CLOCK_THREAD_CPUTIME_ID,
+};
 
 impl From<ctypes::timespec> for Duration {
     fn from(ts: ctypes::timespec) -> Self {
@@ -35,29 +40,178 @@ impl From<Duration> for ctypes::timeval {
     }
 }
 
+/// Clock offsets of a time namespace.
+///
+/// The clocks read by the tasks in a namespace are shifted by its offsets, so
+/// that a workload can observe time jumps or a long uptime without affecting
+/// the rest of the system. Sleeping for a duration is not affected.
+#[cfg(feature = "alloc")]
+pub struct ClockOffsets {
+    monotonic: AtomicI64,
+    realtime: AtomicI64,
+}
+
+#[cfg(feature = "alloc")]
+impl ClockOffsets {
+    const fn new() -> Self {
+        Self {
+            monotonic: AtomicI64::new(0),
+            realtime: AtomicI64::new(0),
+        }
+    }
+
+    fn offset_of(&self, clk: u32) -> Option<&AtomicI64> {
+        match clk {
+            CLOCK_REALTIME => Some(&self.realtime),
+            CLOCK_MONOTONIC => Some(&self.monotonic),
+            _ => None,
+        }
+    }
+
+    /// Returns the offset of the clock `clk` in nanoseconds.
+    pub fn get(&self, clk: ctypes::clockid_t) -> LinuxResult<i64> {
+        let offset = self.offset_of(clk as u32).ok_or(LinuxError::EINVAL)?;
+        Ok(offset.load(Ordering::Relaxed))
+    }
+
+    /// Sets the offset of the clock `clk` in nanoseconds.
+    ///
+    /// Only `CLOCK_REALTIME` and `CLOCK_MONOTONIC` are supported. This is not
+    /// exposed to applications, and should be called by the one that sets up
+    /// the namespace, e.g. a test harness.
+    pub fn set(&self, clk: ctypes::clockid_t, offset_nanos: i64) -> LinuxResult {
+        let offset = self.offset_of(clk as u32).ok_or(LinuxError::EINVAL)?;
+        offset.store(offset_nanos, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "alloc")]
+axns::def_resource! {
+    pub static CLOCK_OFFSETS: ClockOffsets = ClockOffsets::new();
+}
+
+/// Returns the CPU time consumed by the current thread.
+fn thread_cpu_time() -> Duration {
+    #[cfg(feature = "multitask")]
+    {
+        axtask::current().cpu_time()
+    }
+    // the only task runs all the time
+    #[cfg(not(feature = "multitask"))]
+    {
+        axhal::time::monotonic_time()
+    }
+}
+
+/// Returns the CPU time consumed by all threads of the process.
+fn process_cpu_time() -> Duration {
+    #[cfg(feature = "multitask")]
+    {
+        super::pthread::Pthread::total_cpu_time()
+    }
+    #[cfg(not(feature = "multitask"))]
+    {
+        axhal::time::monotonic_time()
+    }
+}
+
+/// Returns the current time of the clock `clk`, as seen in the current time
+/// namespace.
+///
+/// The CPU-time clocks are not shifted by the namespace offsets. The time is
+/// never adjusted by NTP and the system never suspends, so
+/// `CLOCK_MONOTONIC_RAW` and `CLOCK_BOOTTIME` are the same as
+/// `CLOCK_MONOTONIC`.
+pub(crate) fn clock_now(clk: ctypes::clockid_t) -> LinuxResult<Duration> {
+    let (now, base) = match clk as u32 {
+        CLOCK_REALTIME => (axhal::time::wall_time(), CLOCK_REALTIME),
+        CLOCK_MONOTONIC | CLOCK_MONOTONIC_RAW | CLOCK_BOOTTIME => {
+            (axhal::time::monotonic_time(), CLOCK_MONOTONIC)
+        }
+        CLOCK_PROCESS_CPUTIME_ID => return Ok(process_cpu_time()),
+        CLOCK_THREAD_CPUTIME_ID => return Ok(thread_cpu_time()),
+        _ => return Err(LinuxError::EINVAL),
+    };
+    #[cfg(feature = "alloc")]
+    let now = {
+        let nanos = now.as_nanos() as i128 + CLOCK_OFFSETS.get(base as _)? as i128;
+        Duration::from_nanos(nanos.clamp(0, u64::MAX as i128) as u64)
+    };
+    #[cfg(not(feature = "alloc"))]
+    let _ = base;
+    Ok(now)
+}
+
 /// Get clock time since booting
 pub unsafe fn sys_clock_gettime(clk: ctypes::clockid_t, ts: *mut ctypes::timespec) -> c_int {
     syscall_body!(sys_clock_gettime, {
         if ts.is_null() {
             return Err(LinuxError::EFAULT);
         }
-        let now = match clk as u32 {
-            CLOCK_REALTIME => axhal::time::wall_time().into(),
-            CLOCK_MONOTONIC => axhal::time::monotonic_time().into(),
-            _ => {
-                warn!("Called sys_clock_gettime for unsupported clock {}", clk);
-                return Err(LinuxError::EINVAL);
-            }
-        };
+        let now: ctypes::timespec = clock_now(clk)
+            .inspect_err(|_| warn!("Called sys_clock_gettime for unsupported clock {}", clk))?
+            .into();
         unsafe { *ts = now };
         debug!("sys_clock_gettime: {}.{:09}s", now.tv_sec, now.tv_nsec);
         Ok(0)
     })
 }
 
+/// Set the time of a clock
+///
+/// Only `CLOCK_REALTIME` can be set. The time is set as seen in the current
+/// time namespace, and it's written back to the RTC if there is one. All tasks
+/// run with full privileges, so there's no `CAP_SYS_TIME` to check.
+pub unsafe fn sys_clock_settime(clk: ctypes::clockid_t, ts: *const ctypes::timespec) -> c_int {
+    syscall_body!(sys_clock_settime, {
+        if ts.is_null() {
+            return Err(LinuxError::EFAULT);
+        }
+        let ts = unsafe { *ts };
+        debug!(
+            "sys_clock_settime <= {} {}.{:09}s",
+            clk, ts.tv_sec, ts.tv_nsec
+        );
+        if clk as u32 != CLOCK_REALTIME
+            || ts.tv_sec < 0
+            || !(0..1_000_000_000).contains(&ts.tv_nsec)
+        {
+            return Err(LinuxError::EINVAL);
+        }
+        let time = Duration::from(ts);
+        #[cfg(feature = "alloc")]
+        let time = {
+            let nanos = time.as_nanos() as i128 - CLOCK_OFFSETS.get(clk)? as i128;
+            Duration::from_nanos(nanos.clamp(0, u64::MAX as i128) as u64)
+        };
+        if time < axhal::time::monotonic_time() {
+            // earlier than the boot time
+            return Err(LinuxError::EINVAL);
+        }
+        axhal::time::set_wall_time(time);
+        Ok(0)
+    })
+}
+
+/// Get the resolution of a clock
+///
+/// All clocks are read from the same hardware counter, so they have the same
+/// resolution, i.e., the duration of a tick. `res` may be null.
+pub unsafe fn sys_clock_getres(clk: ctypes::clockid_t, res: *mut ctypes::timespec) -> c_int {
+    syscall_body!(sys_clock_getres, {
+        clock_now(clk)?;
+        if !res.is_null() {
+            let nanos = axhal::time::ticks_to_nanos(1).max(1);
+            unsafe { *res = Duration::from_nanos(nanos).into() };
+        }
+        Ok(0)
+    })
+}
+
 /// Sleep some nanoseconds
 ///
-/// TODO: should be woken by signals, and set errno
+/// With the `multitask` feature, it's interrupted by signals.
 pub unsafe fn sys_nanosleep(req: *const ctypes::timespec, rem: *mut ctypes::timespec) -> c_int {
     syscall_body!(sys_nanosleep, {
         unsafe {
@@ -74,7 +228,9 @@ pub unsafe fn sys_nanosleep(req: *const ctypes::timespec, rem: *mut ctypes::time
         let now = axhal::time::monotonic_time();
 
         #[cfg(feature = "multitask")]
-        axtask::sleep(dur);
+        if super::signal::sleep_interruptible(dur) {
+            super::signal::handle_signals();
+        }
         #[cfg(not(feature = "multitask"))]
         axhal::time::busy_wait(dur);
 
@@ -94,7 +250,7 @@ pub unsafe fn sys_nanosleep(req: *const ctypes::timespec, rem: *mut ctypes::time
 /// Get current system time and store in specific struct
 pub unsafe fn sys_get_time_of_day(ts: *mut ctypes::timeval) -> c_int {
     syscall_body!(sys_get_time_of_day, {
-        let current_us = axhal::time::monotonic_time_nanos() as usize / 1000;
+        let current_us = clock_now(CLOCK_MONOTONIC as _)?.as_micros() as usize;
         unsafe {
             *ts = ctypes::timeval {
                 tv_sec: (current_us / 1_000_000) as i64,
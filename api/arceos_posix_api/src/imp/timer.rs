@@ -0,0 +1,503 @@
+//! Interval timers (`setitimer`) and POSIX timers (`timer_create`).
+//!
+//! All timers are served by a kernel task, which sleeps until the earliest
+//! deadline and sends the signals from the task context. `ITIMER_REAL` counts
+//! the monotonic time, while `ITIMER_VIRTUAL` and `ITIMER_PROF` both count the
+//! CPU time of the process, as applications run in the kernel and there's no
+//! difference between user and system time. The interval timers are shared by
+//! all tasks of the process, and their signals are sent to the task that
+//! armed them.
+//!
+//! A POSIX timer sends its signal to the task that created it, or to the one
+//! given by `SIGEV_THREAD_ID`. With `SIGEV_THREAD`, the notification function
+//! is called in a new kernel task.
+
+use alloc::{collections::BTreeMap, vec::Vec};
+use core::ffi::c_int;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::time::Duration;
+
+use axerrno::{LinuxError, LinuxResult};
+use axtask::WaitQueue;
+use spin::{Mutex, Once};
+
+use super::pthread::Pthread;
+use super::signal::{SigSource, check_signal, send_signal_from};
+use crate::ctypes;
+
+/// The minimum time to wait before checking a CPU-time clock again.
+const CPU_CLOCK_POLL: Duration = Duration::from_millis(1);
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Clock {
+    Monotonic,
+    Realtime,
+    ProcessCpu,
+    ThreadCpu(u64),
+}
+
+impl Clock {
+    /// Returns the current time of the clock in nanoseconds, or `None` if the
+    /// thread of a thread CPU-time clock has exited.
+    fn now(self) -> Option<u64> {
+        let now = match self {
+            Clock::Monotonic => return Some(axhal::time::monotonic_time_nanos()),
+            Clock::Realtime => return Some(axhal::time::wall_time_nanos()),
+            Clock::ProcessCpu => Pthread::total_cpu_time(),
+            Clock::ThreadCpu(tid) => Pthread::cpu_time(tid)?,
+        };
+        Some(now.as_nanos() as u64)
+    }
+
+    /// Returns how long to wait for the clock to advance by `nanos`.
+    fn wait_for(self, nanos: u64) -> Duration {
+        match self {
+            Clock::Monotonic | Clock::Realtime => Duration::from_nanos(nanos),
+            // the threads of the process may run on all CPUs at the same time
+            _ => Duration::from_nanos(nanos / axconfig::SMP as u64).max(CPU_CLOCK_POLL),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Notify {
+    None,
+    Signal {
+        tid: u64,
+        sig: u32,
+        value: usize,
+    },
+    Thread {
+        func: unsafe extern "C" fn(ctypes::sigval),
+        value: usize,
+    },
+}
+
+struct Timer {
+    clock: Clock,
+    /// The deadline in nanoseconds of the clock, or 0 if disarmed.
+    deadline: u64,
+    /// The interval in nanoseconds, or 0 for a one-shot timer.
+    interval: u64,
+    notify: Notify,
+    /// The number of expirations missed before the last notification.
+    overrun: i32,
+}
+
+impl Timer {
+    const fn new(clock: Clock) -> Self {
+        Self {
+            clock,
+            deadline: 0,
+            interval: 0,
+            notify: Notify::None,
+            overrun: 0,
+        }
+    }
+
+    /// Returns the interval and the time until the next expiration, which is
+    /// zero if the timer is disarmed.
+    fn get(&self) -> (Duration, Duration) {
+        let value = match self.deadline {
+            0 => 0,
+            deadline => {
+                let now = self.clock.now().unwrap_or(deadline);
+                deadline.saturating_sub(now).max(1)
+            }
+        };
+        (
+            Duration::from_nanos(self.interval),
+            Duration::from_nanos(value),
+        )
+    }
+
+    /// Arms the timer to expire after `value`, then every `interval`, or
+    /// disarms it if `value` is zero.
+    fn arm(&mut self, value: Duration, interval: Duration) -> LinuxResult {
+        self.overrun = 0;
+        if value.is_zero() {
+            self.deadline = 0;
+            self.interval = 0;
+            return Ok(());
+        }
+        let now = self.clock.now().ok_or(LinuxError::EINVAL)?;
+        self.deadline = now.saturating_add(value.as_nanos().min(u64::MAX as u128) as u64);
+        self.interval = interval.as_nanos().min(u64::MAX as u128) as u64;
+        Ok(())
+    }
+
+    /// Checks the timer at the time `now` of its clock. If it has expired, it's
+    /// rearmed or disarmed, and `true` is returned.
+    fn expire(&mut self, now: u64) -> bool {
+        if self.deadline == 0 || now < self.deadline {
+            return false;
+        }
+        if self.interval == 0 {
+            self.deadline = 0;
+            self.overrun = 0;
+        } else {
+            let missed = (now - self.deadline) / self.interval;
+            self.overrun = missed.min(i32::MAX as u64) as i32;
+            self.deadline = self
+                .deadline
+                .saturating_add((missed + 1).saturating_mul(self.interval));
+        }
+        true
+    }
+}
+
+struct Timers {
+    /// `ITIMER_REAL`, `ITIMER_VIRTUAL` and `ITIMER_PROF`.
+    itimers: [Timer; 3],
+    posix: BTreeMap<i32, Timer>,
+    next_id: i32,
+}
+
+static TIMERS: Mutex<Timers> = Mutex::new(Timers {
+    itimers: [
+        Timer::new(Clock::Monotonic),
+        Timer::new(Clock::ProcessCpu),
+        Timer::new(Clock::ProcessCpu),
+    ],
+    posix: BTreeMap::new(),
+    next_id: 0,
+});
+
+/// Set when a timer is armed, to wake up the timer task.
+static CHANGED: AtomicBool = AtomicBool::new(false);
+static TIMER_WQ: WaitQueue = WaitQueue::new();
+static TIMER_TASK: Once = Once::new();
+
+/// Wakes up the timer task, which is spawned on the first call.
+fn timers_changed() {
+    TIMER_TASK.call_once(|| {
+        axtask::spawn(timer_task);
+    });
+    CHANGED.store(true, Ordering::Release);
+    TIMER_WQ.notify_one(false);
+}
+
+fn timer_task() {
+    loop {
+        let mut fired = Vec::new();
+        let mut wait: Option<Duration> = None;
+        {
+            let mut timers = TIMERS.lock();
+            let Timers { itimers, posix, .. } = &mut *timers;
+            let all = itimers
+                .iter_mut()
+                .map(|timer| (None, timer))
+                .chain(posix.iter_mut().map(|(&id, timer)| (Some(id), timer)));
+            for (id, timer) in all {
+                if timer.deadline == 0 {
+                    continue;
+                }
+                let Some(now) = timer.clock.now() else {
+                    // the thread has exited
+                    timer.deadline = 0;
+                    continue;
+                };
+                if timer.expire(now) {
+                    let source = match (id, timer.notify) {
+                        (Some(id), Notify::Signal { value, .. }) => SigSource::Timer {
+                            id,
+                            overrun: timer.overrun,
+                            value,
+                        },
+                        _ => SigSource::Kernel,
+                    };
+                    fired.push((timer.notify, source));
+                }
+                if timer.deadline != 0 {
+                    let next = timer.clock.wait_for(timer.deadline.saturating_sub(now));
+                    wait = Some(wait.map_or(next, |wait| wait.min(next)));
+                }
+            }
+        }
+
+        for (notify, source) in fired {
+            match notify {
+                Notify::None => {}
+                Notify::Signal { tid, sig, .. } => {
+                    // the target may have exited
+                    send_signal_from(tid, sig, source).ok();
+                }
+                Notify::Thread { func, value } => {
+                    axtask::spawn(move || unsafe {
+                        func(ctypes::sigval {
+                            sival_ptr: value as _,
+                        })
+                    });
+                }
+            }
+        }
+
+        let changed = || CHANGED.swap(false, Ordering::AcqRel);
+        match wait {
+            #[cfg(feature = "irq")]
+            Some(wait) => {
+                TIMER_WQ.wait_timeout_until(wait, changed);
+            }
+            #[cfg(not(feature = "irq"))]
+            Some(wait) => axtask::sleep(wait.min(CPU_CLOCK_POLL)),
+            None => TIMER_WQ.wait_until(changed),
+        }
+    }
+}
+
+fn current_tid() -> u64 {
+    axtask::current().id().as_u64()
+}
+
+fn read_timeval(tv: ctypes::timeval) -> LinuxResult<Duration> {
+    if tv.tv_sec < 0 || !(0..1_000_000).contains(&tv.tv_usec) {
+        return Err(LinuxError::EINVAL);
+    }
+    Ok(tv.into())
+}
+
+fn read_timespec(ts: ctypes::timespec) -> LinuxResult<Duration> {
+    if ts.tv_sec < 0 || !(0..1_000_000_000).contains(&ts.tv_nsec) {
+        return Err(LinuxError::EINVAL);
+    }
+    Ok(ts.into())
+}
+
+fn itimer_index(which: c_int) -> LinuxResult<usize> {
+    match which as u32 {
+        ctypes::ITIMER_REAL | ctypes::ITIMER_VIRTUAL | ctypes::ITIMER_PROF => Ok(which as usize),
+        _ => Err(LinuxError::EINVAL),
+    }
+}
+
+/// Get the value of an interval timer.
+pub unsafe fn sys_getitimer(which: c_int, curr_value: *mut ctypes::itimerval) -> c_int {
+    syscall_body!(sys_getitimer, {
+        let idx = itimer_index(which)?;
+        if curr_value.is_null() {
+            return Err(LinuxError::EFAULT);
+        }
+        let (interval, value) = TIMERS.lock().itimers[idx].get();
+        unsafe {
+            *curr_value = ctypes::itimerval {
+                it_interval: interval.into(),
+                it_value: value.into(),
+            };
+        }
+        Ok(0)
+    })
+}
+
+/// Set the value of an interval timer.
+///
+/// On expiration, `SIGALRM`, `SIGVTALRM` or `SIGPROF` is sent to the current
+/// task. If `old_value` is not null, the previous value is stored in it.
+pub unsafe fn sys_setitimer(
+    which: c_int,
+    new_value: *const ctypes::itimerval,
+    old_value: *mut ctypes::itimerval,
+) -> c_int {
+    debug!(
+        "sys_setitimer <= {} {:#x} {:#x}",
+        which, new_value as usize, old_value as usize
+    );
+    syscall_body!(sys_setitimer, {
+        let idx = itimer_index(which)?;
+        if new_value.is_null() {
+            return Err(LinuxError::EFAULT);
+        }
+        let new_value = unsafe { *new_value };
+        let value = read_timeval(new_value.it_value)?;
+        let interval = read_timeval(new_value.it_interval)?;
+        let sig = [ctypes::SIGALRM, ctypes::SIGVTALRM, ctypes::SIGPROF][idx];
+
+        let mut timers = TIMERS.lock();
+        let timer = &mut timers.itimers[idx];
+        if !old_value.is_null() {
+            let (interval, value) = timer.get();
+            unsafe {
+                *old_value = ctypes::itimerval {
+                    it_interval: interval.into(),
+                    it_value: value.into(),
+                };
+            }
+        }
+        timer.notify = Notify::Signal {
+            tid: current_tid(),
+            sig,
+            value: 0,
+        };
+        timer.arm(value, interval)?;
+        drop(timers);
+        timers_changed();
+        Ok(0)
+    })
+}
+
+/// Create a POSIX timer on the clock `clockid`.
+///
+/// If `sevp` is null, `SIGALRM` is sent to the current task on expiration.
+/// The ID of the new timer is stored in `timerid`.
+pub unsafe fn sys_timer_create(
+    clockid: ctypes::clockid_t,
+    sevp: *const ctypes::sigevent,
+    timerid: *mut c_int,
+) -> c_int {
+    debug!(
+        "sys_timer_create <= {} {:#x} {:#x}",
+        clockid, sevp as usize, timerid as usize
+    );
+    syscall_body!(sys_timer_create, {
+        if timerid.is_null() {
+            return Err(LinuxError::EFAULT);
+        }
+        let tid = current_tid();
+        let clock = match clockid as u32 {
+            ctypes::CLOCK_REALTIME => Clock::Realtime,
+            ctypes::CLOCK_MONOTONIC | ctypes::CLOCK_BOOTTIME => Clock::Monotonic,
+            ctypes::CLOCK_PROCESS_CPUTIME_ID => Clock::ProcessCpu,
+            ctypes::CLOCK_THREAD_CPUTIME_ID => Clock::ThreadCpu(tid),
+            _ => return Err(LinuxError::EINVAL),
+        };
+
+        let mut timers = TIMERS.lock();
+        let id = timers.next_id;
+        let notify = if sevp.is_null() {
+            Notify::Signal {
+                tid,
+                sig: ctypes::SIGALRM,
+                value: id as usize,
+            }
+        } else {
+            let sev = unsafe { &*sevp };
+            let value = unsafe { sev.sigev_value.sival_ptr } as usize;
+            match sev.sigev_notify as u32 {
+                ctypes::SIGEV_NONE => Notify::None,
+                ctypes::SIGEV_SIGNAL => Notify::Signal {
+                    tid,
+                    sig: check_signal(sev.sigev_signo)?,
+                    value,
+                },
+                ctypes::SIGEV_THREAD_ID => {
+                    let target = unsafe { sev.__sev_fields.sigev_notify_thread_id };
+                    if target <= 0 || !Pthread::exists(target as u64) {
+                        return Err(LinuxError::EINVAL);
+                    }
+                    Notify::Signal {
+                        tid: target as u64,
+                        sig: check_signal(sev.sigev_signo)?,
+                        value,
+                    }
+                }
+                ctypes::SIGEV_THREAD => Notify::Thread {
+                    func: unsafe { sev.__sev_fields.__sev_thread.sigev_notify_function }
+                        .ok_or(LinuxError::EINVAL)?,
+                    value,
+                },
+                _ => return Err(LinuxError::EINVAL),
+            }
+        };
+        timers.next_id = id.checked_add(1).ok_or(LinuxError::EAGAIN)?;
+        timers.posix.insert(
+            id,
+            Timer {
+                notify,
+                ..Timer::new(clock)
+            },
+        );
+        unsafe { *timerid = id };
+        Ok(0)
+    })
+}
+
+/// Arm or disarm a POSIX timer.
+///
+/// With `TIMER_ABSTIME` in `flags`, the expiration time is an absolute time of
+/// the clock of the timer, and a time in the past makes it expire immediately.
+/// If `old_value` is not null, the previous value is stored in it.
+pub unsafe fn sys_timer_settime(
+    timerid: c_int,
+    flags: c_int,
+    new_value: *const ctypes::itimerspec,
+    old_value: *mut ctypes::itimerspec,
+) -> c_int {
+    debug!(
+        "sys_timer_settime <= {} {:#x} {:#x} {:#x}",
+        timerid, flags, new_value as usize, old_value as usize
+    );
+    syscall_body!(sys_timer_settime, {
+        if new_value.is_null() {
+            return Err(LinuxError::EFAULT);
+        }
+        let new_value = unsafe { *new_value };
+        let mut value = read_timespec(new_value.it_value)?;
+        let interval = read_timespec(new_value.it_interval)?;
+
+        let mut timers = TIMERS.lock();
+        let timer = timers.posix.get_mut(&timerid).ok_or(LinuxError::EINVAL)?;
+        if !old_value.is_null() {
+            let (interval, value) = timer.get();
+            unsafe {
+                *old_value = ctypes::itimerspec {
+                    it_interval: interval.into(),
+                    it_value: value.into(),
+                };
+            }
+        }
+        if flags as u32 & ctypes::TIMER_ABSTIME != 0 && !value.is_zero() {
+            // as seen in the current time namespace
+            let now = match timer.clock {
+                Clock::Realtime => super::time::clock_now(ctypes::CLOCK_REALTIME as _)?,
+                Clock::Monotonic => super::time::clock_now(ctypes::CLOCK_MONOTONIC as _)?,
+                clock => Duration::from_nanos(clock.now().ok_or(LinuxError::EINVAL)?),
+            };
+            value = value.saturating_sub(now).max(Duration::from_nanos(1));
+        }
+        timer.arm(value, interval)?;
+        drop(timers);
+        timers_changed();
+        Ok(0)
+    })
+}
+
+/// Get the time until the next expiration of a POSIX timer, and its interval.
+pub unsafe fn sys_timer_gettime(timerid: c_int, curr_value: *mut ctypes::itimerspec) -> c_int {
+    syscall_body!(sys_timer_gettime, {
+        if curr_value.is_null() {
+            return Err(LinuxError::EFAULT);
+        }
+        let timers = TIMERS.lock();
+        let timer = timers.posix.get(&timerid).ok_or(LinuxError::EINVAL)?;
+        let (interval, value) = timer.get();
+        unsafe {
+            *curr_value = ctypes::itimerspec {
+                it_interval: interval.into(),
+                it_value: value.into(),
+            };
+        }
+        Ok(0)
+    })
+}
+
+/// Get the number of expirations of a POSIX timer that were missed before
+/// its last notification.
+pub fn sys_timer_getoverrun(timerid: c_int) -> c_int {
+    syscall_body!(sys_timer_getoverrun, {
+        let timers = TIMERS.lock();
+        let timer = timers.posix.get(&timerid).ok_or(LinuxError::EINVAL)?;
+        Ok(timer.overrun)
+    })
+}
+
+/// Delete a POSIX timer.
+pub fn sys_timer_delete(timerid: c_int) -> c_int {
+    debug!("sys_timer_delete <= {}", timerid);
+    syscall_body!(sys_timer_delete, {
+        TIMERS
+            .lock()
+            .posix
+            .remove(&timerid)
+            .ok_or(LinuxError::EINVAL)?;
+        Ok(0)
+    })
+}
@@ -0,0 +1,125 @@
+//! The files of the event tracer, in `/sys/kernel/tracing` as ftrace.
+//!
+//! - `tracing_on`: `1` while the events are recorded, `0` otherwise. Writing
+//!   `1` or `0` to it turns tracing on or off.
+//! - `trace`: the events recorded, as text (see [`axtrace::dump_text`]).
+//!   Opening it with `O_TRUNC` (e.g. `echo > trace`) discards them.
+//! - `trace_raw`: the events recorded, in the binary format of
+//!   [`axtrace::dump_binary`].
+//!
+//! The events are read as they were when the file was opened. Besides the
+//! events of the other modules, the entry and exit of each syscall are traced,
+//! as `sys_*_enter` and `sys_*_exit` with its return value.
+
+use alloc::{string::String, sync::Arc, vec::Vec};
+use core::ffi::c_int;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use axerrno::{LinuxError, LinuxResult};
+use axio::PollState;
+
+use super::fd_ops::{FileLike, add_file_like};
+use crate::ctypes;
+
+/// The directory of the files of the tracer.
+const TRACING_DIR: &str = "/sys/kernel/tracing/";
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    TracingOn,
+    Trace,
+    TraceRaw,
+}
+
+struct TraceFile {
+    kind: Kind,
+    /// The content when it was opened.
+    content: Vec<u8>,
+    /// The offset of the next read.
+    pos: AtomicUsize,
+}
+
+impl FileLike for TraceFile {
+    fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        let pos = self.pos.load(Ordering::Acquire).min(self.content.len());
+        let len = buf.len().min(self.content.len() - pos);
+        buf[..len].copy_from_slice(&self.content[pos..pos + len]);
+        self.pos.fetch_add(len, Ordering::AcqRel);
+        Ok(len)
+    }
+
+    fn write(&self, buf: &[u8]) -> LinuxResult<usize> {
+        match self.kind {
+            Kind::TracingOn => match buf.trim_ascii() {
+                b"0" => axtrace::set_tracing_on(false),
+                b"1" => axtrace::set_tracing_on(true),
+                _ => return Err(LinuxError::EINVAL),
+            },
+            // the events are discarded by opening it with `O_TRUNC`
+            Kind::Trace => {}
+            Kind::TraceRaw => return Err(LinuxError::EBADF),
+        }
+        Ok(buf.len())
+    }
+
+    fn stat(&self) -> LinuxResult<ctypes::stat> {
+        let perm = match self.kind {
+            Kind::TraceRaw => 0o444, // r--r--r--
+            _ => 0o644,              // rw-r--r--
+        };
+        Ok(ctypes::stat {
+            st_ino: 1,
+            st_nlink: 1,
+            st_mode: 0o100000 | perm, // S_IFREG
+            st_size: self.content.len() as _,
+            st_blksize: 4096,
+            ..Default::default()
+        })
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn core::any::Any + Send + Sync> {
+        self
+    }
+
+    fn poll(&self) -> LinuxResult<PollState> {
+        Ok(PollState {
+            readable: true,
+            writable: self.kind != Kind::TraceRaw,
+        })
+    }
+
+    fn set_nonblocking(&self, _nonblocking: bool) -> LinuxResult {
+        Ok(())
+    }
+}
+
+/// Opens `path` if it's a file of the tracer, or returns `None` if it's not.
+pub(crate) fn open(path: &str, flags: c_int) -> Option<LinuxResult<c_int>> {
+    let kind = match path.strip_prefix(TRACING_DIR)? {
+        "tracing_on" => Kind::TracingOn,
+        "trace" => Kind::Trace,
+        "trace_raw" => Kind::TraceRaw,
+        _ => return None,
+    };
+    if kind == Kind::Trace && flags as u32 & ctypes::O_TRUNC != 0 {
+        axtrace::clear();
+    }
+    let content = match kind {
+        Kind::TracingOn => [b'0' + axtrace::tracing_on() as u8, b'\n'].into(),
+        Kind::Trace => {
+            let mut text = String::new();
+            axtrace::dump_text(&mut text).ok();
+            text.into_bytes()
+        }
+        Kind::TraceRaw => {
+            let mut data = Vec::new();
+            axtrace::dump_binary(|bytes| data.extend_from_slice(bytes));
+            data
+        }
+    };
+    Some(add_file_like(Arc::new(TraceFile {
+        kind,
+        content,
+        pos: AtomicUsize::new(0),
+    })))
+}
@@ -0,0 +1,700 @@
+//! The line discipline of the terminals: the console, and the slaves of the
+//! pseudo-terminals.
+//!
+//! The input typed on a terminal is processed as set by its `termios`. In
+//! canonical mode (`ICANON`), it's edited a line at a time with `VERASE`,
+//! `VWERASE` and `VKILL`, and a read returns at most one line. Otherwise, it's
+//! read as it's typed, with `VMIN` and `VTIME`. It's echoed if `ECHO` is set,
+//! and `VINTR`, `VQUIT` and `VSUSP` send signals to the foreground process
+//! group if `ISIG` is set (only with `multitask`).
+//!
+//! The flow control (`IXON`) and `VDISCARD` are not supported. The output of
+//! the console is processed by its driver, which always writes `\n` as
+//! `\r\n`. The output of a pseudo-terminal is only processed for `ONLCR`.
+//!
+//! The console and the slaves are read the same way ([`Terminal::read`]). The
+//! blocked tasks wait for a change of the terminal (input typed, output
+//! taken, hang-up), but the console has no input interrupts, so it's polled
+//! every [`CONSOLE_POLL_INTERVAL`] while a task reads it, and when a task
+//! polls it.
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+
+use axerrno::{LinuxError, LinuxResult};
+use axhal::time::monotonic_time;
+use spin::Mutex;
+
+#[cfg(feature = "multitask")]
+use super::session::JobControl;
+use crate::ctypes;
+#[cfg(feature = "fd")]
+use core::ffi::c_int;
+
+const INLCR: u32 = 0o100;
+const IGNCR: u32 = 0o200;
+const ICRNL: u32 = 0o400;
+const IUTF8: u32 = 0o40000;
+
+const OPOST: u32 = 0o1;
+const ONLCR: u32 = 0o4;
+
+const B115200: u32 = 0o10002;
+const CS8: u32 = 0o60;
+const CREAD: u32 = 0o200;
+
+const ISIG: u32 = 0o1;
+const ICANON: u32 = 0o2;
+const ECHO: u32 = 0o10;
+const ECHOE: u32 = 0o20;
+const ECHOK: u32 = 0o40;
+const ECHONL: u32 = 0o100;
+const NOFLSH: u32 = 0o200;
+const ECHOCTL: u32 = 0o1000;
+const ECHOKE: u32 = 0o4000;
+const IEXTEN: u32 = 0o100000;
+
+const VINTR: usize = 0;
+const VQUIT: usize = 1;
+const VERASE: usize = 2;
+const VKILL: usize = 3;
+const VEOF: usize = 4;
+const VTIME: usize = 5;
+const VMIN: usize = 6;
+const VSTART: usize = 8;
+const VSTOP: usize = 9;
+const VSUSP: usize = 10;
+const VEOL: usize = 11;
+const VREPRINT: usize = 12;
+const VDISCARD: usize = 13;
+const VWERASE: usize = 14;
+const VLNEXT: usize = 15;
+const VEOL2: usize = 16;
+
+/// The maximum length of a line in canonical mode, as on Linux.
+const MAX_LINE: usize = 4095;
+
+/// How often the console is polled while a task reads it.
+const CONSOLE_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The most bytes of input a pseudo-terminal holds before writing to its
+/// master blocks, as on Linux.
+#[cfg(feature = "pty")]
+const MAX_INPUT: usize = 4096;
+
+/// The settings of a terminal, as `struct termios` of Linux.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Termios {
+    c_iflag: u32,
+    c_oflag: u32,
+    c_cflag: u32,
+    c_lflag: u32,
+    c_line: u8,
+    c_cc: [u8; 19],
+}
+
+impl Termios {
+    /// Whether `c` is the control character `i`. A control character of 0 is
+    /// disabled.
+    fn is_cc(&self, c: u8, i: usize) -> bool {
+        self.c_cc[i] != 0 && self.c_cc[i] == c
+    }
+
+    fn lflag(&self, flag: u32) -> bool {
+        self.c_lflag & flag != 0
+    }
+}
+
+/// The size of a terminal window (`struct winsize`).
+#[cfg(feature = "fd")]
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq)]
+struct Winsize {
+    ws_row: u16,
+    ws_col: u16,
+    ws_xpixel: u16,
+    ws_ypixel: u16,
+}
+
+struct Tty {
+    termios: Termios,
+    /// The line being edited in canonical mode.
+    line: Vec<u8>,
+    /// The input ready to be read.
+    ready: VecDeque<u8>,
+    /// The lengths of the lines in `ready` in canonical mode, where an empty
+    /// one is an end of file. It's empty otherwise.
+    lines: VecDeque<usize>,
+    /// Whether the next character is taken literally (`VLNEXT`).
+    literal: bool,
+    /// When the last character was typed, for `VTIME`.
+    last_input: Duration,
+    /// The echo of the input, to be written to the terminal.
+    echo: Vec<u8>,
+}
+
+/// A terminal: its line discipline, window size and job control.
+pub(crate) struct Terminal {
+    tty: Mutex<Tty>,
+    #[cfg(feature = "fd")]
+    winsize: Mutex<Winsize>,
+    #[cfg(feature = "multitask")]
+    job: Mutex<JobControl>,
+    /// The number of changes so far, which the blocked tasks wait for.
+    events: AtomicU64,
+    #[cfg(feature = "multitask")]
+    wait_queue: axtask::WaitQueue,
+    /// Processes the input typed since the last call, if the input of the
+    /// terminal is polled.
+    poll_input: Option<fn()>,
+}
+
+lazy_static::lazy_static! {
+    /// The console, the controlling terminal of the session of the task that
+    /// first uses it.
+    static ref CONSOLE: Terminal = {
+        let mut console = Terminal::new();
+        console.poll_input = Some(receive_input);
+        #[cfg(feature = "multitask")]
+        {
+            *console.job.lock() = JobControl::current();
+        }
+        console
+    };
+}
+
+impl Tty {
+    /// A terminal with the default settings of Linux.
+    const fn new() -> Self {
+        Self {
+            termios: Termios {
+                c_iflag: ICRNL | IUTF8,
+                c_oflag: OPOST | ONLCR,
+                c_cflag: B115200 | CS8 | CREAD,
+                c_lflag: ISIG | ICANON | ECHO | ECHOE | ECHOK | ECHOCTL | ECHOKE | IEXTEN,
+                c_line: 0,
+                c_cc: {
+                    let mut cc = [0; 19];
+                    cc[VINTR] = 0x03; // ^C
+                    cc[VQUIT] = 0x1c; // ^\
+                    cc[VERASE] = 0x7f; // DEL
+                    cc[VKILL] = 0x15; // ^U
+                    cc[VEOF] = 0x04; // ^D
+                    cc[VMIN] = 1;
+                    cc[VSTART] = 0x11; // ^Q
+                    cc[VSTOP] = 0x13; // ^S
+                    cc[VSUSP] = 0x1a; // ^Z
+                    cc[VREPRINT] = 0x12; // ^R
+                    cc[VDISCARD] = 0x0f; // ^O
+                    cc[VWERASE] = 0x17; // ^W
+                    cc[VLNEXT] = 0x16; // ^V
+                    cc
+                },
+            },
+            line: Vec::new(),
+            ready: VecDeque::new(),
+            lines: VecDeque::new(),
+            literal: false,
+            last_input: Duration::ZERO,
+            echo: Vec::new(),
+        }
+    }
+
+    fn echo(&mut self, bytes: &[u8]) {
+        self.echo.extend_from_slice(bytes);
+    }
+
+    fn canonical(&self) -> bool {
+        self.termios.lflag(ICANON)
+    }
+
+    /// Echoes a character typed, as `^X` if it's a control one and `ECHOCTL`
+    /// is set.
+    fn echo_char(&mut self, c: u8) {
+        if !self.termios.lflag(ECHO) {
+            return;
+        }
+        if self.termios.lflag(ECHOCTL) && (c < b' ' || c == 0x7f) && c != b'\t' && c != b'\n' {
+            self.echo(&[b'^', c ^ 0x40]);
+        } else {
+            self.echo(&[c]);
+        }
+    }
+
+    /// The number of columns taken by the echo of `c`.
+    fn echo_width(&self, c: u8) -> usize {
+        if self.termios.lflag(ECHOCTL) && (c < b' ' || c == 0x7f) {
+            2
+        } else {
+            1
+        }
+    }
+
+    /// Erases the last character of the line, and returns it.
+    fn erase_char(&mut self) -> Option<u8> {
+        let mut c = self.line.pop()?;
+        if self.termios.c_iflag & IUTF8 != 0 {
+            // the continuation bytes of a UTF-8 character
+            while c & 0xc0 == 0x80 {
+                match self.line.pop() {
+                    Some(prev) => c = prev,
+                    None => break,
+                }
+            }
+        }
+        if self.termios.lflag(ECHO) && self.termios.lflag(ECHOE) {
+            for _ in 0..self.echo_width(c) {
+                self.echo(b"\x08 \x08");
+            }
+        }
+        Some(c)
+    }
+
+    /// Moves the line being edited to the input ready to be read.
+    fn complete_line(&mut self) {
+        self.lines.push_back(self.line.len());
+        self.ready.extend(self.line.drain(..));
+    }
+
+    fn flush_input(&mut self) {
+        self.line.clear();
+        self.ready.clear();
+        self.lines.clear();
+        self.literal = false;
+    }
+
+    /// Processes a character typed, and returns the signal it generates, if
+    /// any.
+    fn receive(&mut self, c: u8) -> Option<u32> {
+        self.last_input = monotonic_time();
+        let t = self.termios;
+        if self.literal {
+            self.literal = false;
+            if self.line.len() < MAX_LINE {
+                self.line.push(c);
+                self.echo_char(c);
+            }
+            return None;
+        }
+        let c = match c {
+            b'\r' if t.c_iflag & IGNCR != 0 => return None,
+            b'\r' if t.c_iflag & ICRNL != 0 => b'\n',
+            b'\n' if t.c_iflag & INLCR != 0 => b'\r',
+            c => c,
+        };
+        if t.lflag(ISIG) {
+            let sig = if t.is_cc(c, VINTR) {
+                Some(ctypes::SIGINT)
+            } else if t.is_cc(c, VQUIT) {
+                Some(ctypes::SIGQUIT)
+            } else if t.is_cc(c, VSUSP) {
+                Some(ctypes::SIGTSTP)
+            } else {
+                None
+            };
+            if sig.is_some() {
+                if !t.lflag(NOFLSH) {
+                    self.flush_input();
+                }
+                self.echo_char(c);
+                return sig;
+            }
+        }
+        if !self.canonical() {
+            self.ready.push_back(c);
+            self.echo_char(c);
+            return None;
+        }
+
+        if t.lflag(IEXTEN) && t.is_cc(c, VLNEXT) {
+            self.literal = true;
+            if t.lflag(ECHO) && t.lflag(ECHOCTL) {
+                self.echo(b"^\x08");
+            }
+        } else if t.is_cc(c, VERASE) {
+            self.erase_char();
+        } else if t.lflag(IEXTEN) && t.is_cc(c, VWERASE) {
+            while self.line.last().is_some_and(|c| c.is_ascii_whitespace()) {
+                self.erase_char();
+            }
+            while self.line.last().is_some_and(|c| !c.is_ascii_whitespace()) {
+                self.erase_char();
+            }
+        } else if t.is_cc(c, VKILL) {
+            if t.lflag(ECHO) && t.lflag(ECHOKE) && t.lflag(ECHOE) {
+                while self.erase_char().is_some() {}
+            } else {
+                self.line.clear();
+                self.echo_char(c);
+                if t.lflag(ECHO) && t.lflag(ECHOK) {
+                    self.echo(b"\n");
+                }
+            }
+        } else if t.lflag(IEXTEN) && t.is_cc(c, VREPRINT) {
+            if t.lflag(ECHO) {
+                self.echo_char(c);
+                self.echo(b"\n");
+                self.echo.extend_from_slice(&self.line);
+            }
+        } else if t.is_cc(c, VEOF) {
+            // the end of file isn't part of the line
+            self.complete_line();
+        } else if c == b'\n' || t.is_cc(c, VEOL) || t.is_cc(c, VEOL2) {
+            self.line.push(c);
+            if t.lflag(ECHO) || (c == b'\n' && t.lflag(ECHONL)) {
+                self.echo_char(c);
+            }
+            self.complete_line();
+        } else if self.line.len() < MAX_LINE {
+            self.line.push(c);
+            self.echo_char(c);
+        }
+        None
+    }
+
+    /// The number of bytes that can be read.
+    #[cfg(feature = "fd")]
+    fn available(&self) -> usize {
+        if self.canonical() {
+            self.lines.iter().sum()
+        } else {
+            self.ready.len()
+        }
+    }
+
+    /// Takes at most `buf.len()` bytes of the input, and at most one line in
+    /// canonical mode.
+    fn take(&mut self, buf: &mut [u8]) -> usize {
+        let mut len = buf.len().min(self.ready.len());
+        if self.canonical() {
+            let Some(line) = self.lines.front_mut() else {
+                return 0;
+            };
+            len = len.min(*line);
+            *line -= len;
+            if *line == 0 {
+                self.lines.pop_front();
+            }
+        }
+        for (dst, src) in buf.iter_mut().zip(self.ready.drain(..len)) {
+            *dst = src;
+        }
+        len
+    }
+
+    fn set_termios(&mut self, termios: Termios) {
+        let was_canonical = self.canonical();
+        self.termios = termios;
+        match (was_canonical, self.canonical()) {
+            // the line being edited becomes readable
+            (true, false) => {
+                self.ready.extend(self.line.drain(..));
+                self.lines.clear();
+            }
+            // the input typed is a line
+            (false, true) if !self.ready.is_empty() => self.lines.push_back(self.ready.len()),
+            _ => {}
+        }
+    }
+}
+
+impl Terminal {
+    /// A terminal with the default settings of Linux, controlling no session.
+    pub(crate) const fn new() -> Self {
+        Self {
+            tty: Mutex::new(Tty::new()),
+            // the size is unknown, it's the usual one until set by `TIOCSWINSZ`
+            #[cfg(feature = "fd")]
+            winsize: Mutex::new(Winsize {
+                ws_row: 24,
+                ws_col: 80,
+                ws_xpixel: 0,
+                ws_ypixel: 0,
+            }),
+            #[cfg(feature = "multitask")]
+            job: Mutex::new(JobControl::new()),
+            events: AtomicU64::new(0),
+            #[cfg(feature = "multitask")]
+            wait_queue: axtask::WaitQueue::new(),
+            poll_input: None,
+        }
+    }
+
+    /// Returns the number of changes so far, to be given to [`wait`] before
+    /// checking the state of the terminal.
+    ///
+    /// [`wait`]: Self::wait
+    pub(crate) fn events(&self) -> u64 {
+        self.events.load(Ordering::Acquire)
+    }
+
+    /// Wakes up the tasks waiting for a change of the terminal.
+    pub(crate) fn notify(&self) {
+        self.events.fetch_add(1, Ordering::AcqRel);
+        #[cfg(feature = "multitask")]
+        self.wait_queue.notify_all(false);
+    }
+
+    /// Blocks until a change after the first `seen` ones, or `timeout` if
+    /// any.
+    ///
+    /// It fails with `EINTR` if it's interrupted by a signal whose handler
+    /// wasn't installed with `SA_RESTART`.
+    pub(crate) fn wait(&self, seen: u64, timeout: Option<Duration>) -> LinuxResult {
+        #[cfg(feature = "multitask")]
+        {
+            use axtask::WaitResult;
+
+            let changed = || self.events() != seen;
+            let res = match timeout {
+                #[cfg(feature = "irq")]
+                Some(timeout) => self
+                    .wait_queue
+                    .wait_timeout_until_interruptible(timeout, changed),
+                // no timers to wake the task up
+                #[cfg(not(feature = "irq"))]
+                Some(_) => {
+                    crate::sys_sched_yield();
+                    WaitResult::TimedOut
+                }
+                None => self.wait_queue.wait_until_interruptible(changed),
+            };
+            if res == WaitResult::Interrupted {
+                super::signal::check_interrupt(true)?;
+            }
+        }
+        #[cfg(not(feature = "multitask"))]
+        {
+            // only the polled input can change it
+            let _ = (seen, timeout);
+            crate::sys_sched_yield();
+        }
+        Ok(())
+    }
+
+    /// Processes the characters typed, and returns their echo.
+    pub(crate) fn input(&self, chars: &[u8]) -> Vec<u8> {
+        let mut tty = self.tty.lock();
+        for &c in chars {
+            let sig = tty.receive(c);
+            #[cfg(feature = "multitask")]
+            if let Some(sig) = sig {
+                self.job.lock().signal(sig);
+            }
+            #[cfg(not(feature = "multitask"))]
+            let _ = sig;
+        }
+        let echo = core::mem::take(&mut tty.echo);
+        drop(tty);
+        if !chars.is_empty() {
+            self.notify();
+        }
+        echo
+    }
+
+    /// Reads from the terminal, blocking as set by its `termios` unless
+    /// `nonblocking`.
+    ///
+    /// It returns the end of file if there's nothing to read and `hung_up`
+    /// returns `true`, i.e. the other side of a pseudo-terminal is gone.
+    pub(crate) fn read(
+        &self,
+        buf: &mut [u8],
+        nonblocking: bool,
+        hung_up: impl Fn() -> bool,
+    ) -> LinuxResult<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let start = monotonic_time();
+        loop {
+            if let Some(poll_input) = self.poll_input {
+                poll_input();
+            }
+            let seen = self.events();
+            if let Some(len) = self.try_read(buf, start) {
+                // there's room for more input
+                self.notify();
+                return Ok(len);
+            }
+            if hung_up() {
+                return Ok(0);
+            }
+            if nonblocking {
+                return Err(LinuxError::EAGAIN);
+            }
+            // `VTIME` expires at most that long from now
+            let mut timeout = self.read_timeout();
+            if self.poll_input.is_some() {
+                timeout =
+                    Some(timeout.map_or(CONSOLE_POLL_INTERVAL, |t| t.min(CONSOLE_POLL_INTERVAL)));
+            }
+            self.wait(seen, timeout)?;
+        }
+    }
+
+    /// The `VTIME` of a noncanonical read, if it's set.
+    fn read_timeout(&self) -> Option<Duration> {
+        let tty = self.tty.lock();
+        let time = tty.termios.c_cc[VTIME] as u64 * 100;
+        (!tty.canonical() && time > 0).then(|| Duration::from_millis(time))
+    }
+
+    /// Reads the input if there is enough as set by the `termios`, where
+    /// `start` is when the read began, for `VTIME`.
+    fn try_read(&self, buf: &mut [u8], start: Duration) -> Option<usize> {
+        let mut tty = self.tty.lock();
+        if tty.canonical() {
+            return if tty.lines.is_empty() {
+                None
+            } else {
+                Some(tty.take(buf))
+            };
+        }
+        let min = tty.termios.c_cc[VMIN] as usize;
+        let time = Duration::from_millis(tty.termios.c_cc[VTIME] as u64 * 100);
+        let available = tty.ready.len();
+        let now = monotonic_time();
+        let done = if available >= min.clamp(1, buf.len()) || (min == 0 && time.is_zero()) {
+            true
+        } else if time.is_zero() {
+            false
+        } else if min == 0 {
+            // a timeout of the read
+            now >= start + time
+        } else {
+            // a timeout between the characters, once there is one
+            available > 0 && now >= tty.last_input + time
+        };
+        done.then(|| tty.take(buf))
+    }
+
+    /// Whether there is input to be read.
+    #[cfg(feature = "fd")]
+    pub(crate) fn readable(&self) -> bool {
+        if let Some(poll_input) = self.poll_input {
+            poll_input();
+        }
+        let tty = self.tty.lock();
+        if tty.canonical() {
+            !tty.lines.is_empty()
+        } else {
+            !tty.ready.is_empty() || tty.termios.c_cc[VMIN] == 0
+        }
+    }
+
+    /// The number of characters that can be typed before the input is full.
+    #[cfg(feature = "pty")]
+    pub(crate) fn room(&self) -> usize {
+        let tty = self.tty.lock();
+        MAX_INPUT.saturating_sub(tty.ready.len() + tty.line.len())
+    }
+
+    /// Processes the output written to the terminal as set by `ONLCR`, and
+    /// appends it to `out`.
+    #[cfg(feature = "pty")]
+    pub(crate) fn output(&self, buf: &[u8], out: &mut VecDeque<u8>) {
+        let oflag = self.tty.lock().termios.c_oflag;
+        if oflag & OPOST == 0 || oflag & ONLCR == 0 {
+            out.extend(buf);
+            return;
+        }
+        for &c in buf {
+            if c == b'\n' {
+                out.push_back(b'\r');
+            }
+            out.push_back(c);
+        }
+    }
+
+    /// Sends `SIGHUP` to the foreground process group, as the terminal is
+    /// gone.
+    #[cfg(feature = "pty")]
+    pub(crate) fn hang_up(&self) {
+        #[cfg(feature = "multitask")]
+        self.job.lock().signal(ctypes::SIGHUP);
+        self.notify();
+    }
+
+    /// Handles the terminal `ioctl`s: the settings and window size here, and
+    /// the job control ones in the session module.
+    #[cfg(feature = "fd")]
+    pub(crate) fn ioctl(&self, cmd: u32, arg: usize) -> LinuxResult<c_int> {
+        const TCGETS: u32 = 0x5401;
+        const TCSETS: u32 = 0x5402;
+        const TCSETSW: u32 = 0x5403;
+        const TCSETSF: u32 = 0x5404;
+        const TIOCGWINSZ: u32 = 0x5413;
+        const TIOCSWINSZ: u32 = 0x5414;
+
+        use super::fd_ops::check_ioctl_arg;
+
+        if let Some(poll_input) = self.poll_input {
+            poll_input();
+        }
+        match cmd {
+            TCGETS => {
+                check_ioctl_arg(arg, size_of::<Termios>(), true)?;
+                let termios = self.tty.lock().termios;
+                unsafe { (arg as *mut Termios).write_unaligned(termios) };
+            }
+            TCSETS | TCSETSW | TCSETSF => {
+                check_ioctl_arg(arg, size_of::<Termios>(), false)?;
+                let termios = unsafe { (arg as *const Termios).read_unaligned() };
+                let mut tty = self.tty.lock();
+                // the output is written synchronously, there's nothing to
+                // drain, but the pending input is discarded by `TCSETSF`
+                if cmd == TCSETSF {
+                    tty.flush_input();
+                }
+                tty.set_termios(termios);
+                drop(tty);
+                // the input may become readable, or the room for it larger
+                self.notify();
+            }
+            TIOCGWINSZ => {
+                check_ioctl_arg(arg, size_of::<Winsize>(), true)?;
+                let winsize = *self.winsize.lock();
+                unsafe { (arg as *mut Winsize).write_unaligned(winsize) };
+            }
+            TIOCSWINSZ => {
+                check_ioctl_arg(arg, size_of::<Winsize>(), false)?;
+                let winsize = unsafe { (arg as *const Winsize).read_unaligned() };
+                if core::mem::replace(&mut *self.winsize.lock(), winsize) != winsize {
+                    // tell the foreground process group to redraw
+                    #[cfg(feature = "multitask")]
+                    self.job.lock().signal(ctypes::SIGWINCH);
+                }
+            }
+            super::fd_ops::FIONREAD => {
+                return super::fd_ops::ioctl_write_int(arg, self.tty.lock().available());
+            }
+            #[cfg(feature = "multitask")]
+            _ => return self.job.lock().ioctl(cmd, arg),
+            #[cfg(not(feature = "multitask"))]
+            _ => return Err(LinuxError::ENOTTY),
+        }
+        Ok(0)
+    }
+}
+
+/// Processes the characters typed on the console since the last call.
+fn receive_input() {
+    let mut buf = [0; 32];
+    loop {
+        let len = axhal::console::read_bytes(&mut buf);
+        if len == 0 {
+            break;
+        }
+        let echo = CONSOLE.input(&buf[..len]);
+        axhal::console::write_bytes(&echo);
+    }
+}
+
+/// Returns the console, the terminal of the standard input and output.
+pub(crate) fn console() -> &'static Terminal {
+    &CONSOLE
+}
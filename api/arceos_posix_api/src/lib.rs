@@ -32,31 +32,48 @@ pub mod config {
 pub mod ctypes;
 
 pub use imp::io::{sys_read, sys_write, sys_writev};
+pub use imp::kmsg::sys_syslog;
 #[cfg(feature = "fs")]
 pub use imp::path_link::{AT_FDCWD, FilePath, HARDLINK_MANAGER, handle_file_path};
-pub use imp::resources::{sys_getrlimit, sys_setrlimit};
-pub use imp::sys::sys_sysconf;
-pub use imp::task::{sys_exit, sys_getpid, sys_sched_yield};
-pub use imp::time::{sys_clock_gettime, sys_get_time_of_day, sys_nanosleep};
+pub use imp::resources::{
+    charge_memory, sys_getrlimit, sys_prlimit64, sys_setrlimit, uncharge_memory,
+};
+pub use imp::sys::{sys_getrandom, sys_personality, sys_sysconf};
+pub use imp::task::{sys_exit, sys_getcpu, sys_getpid, sys_membarrier, sys_sched_yield};
+pub use imp::time::{
+    sys_clock_getres, sys_clock_gettime, sys_clock_settime, sys_get_time_of_day, sys_nanosleep,
+};
 
+#[cfg(feature = "alloc")]
+pub use imp::env::{sys_environ, sys_setenv, sys_unsetenv};
 #[cfg(feature = "fd")]
 pub use imp::fd_ops::{
-    FD_TABLE, add_file_like, get_file_like, sys_close, sys_dup, sys_dup2, sys_fcntl,
+    FD_TABLE, FdTable, FileTransfer, add_file_like, get_file_like, send_file_likes, sys_close,
+    sys_dup, sys_dup2, sys_fcntl, sys_ioctl,
 };
 #[cfg(feature = "fs")]
+pub use imp::file_lock::sys_flock;
+#[cfg(feature = "fs")]
 pub use imp::fs::{
-    Directory, File, sys_fstat, sys_getcwd, sys_lseek, sys_lstat, sys_open, sys_openat, sys_rename,
-    sys_stat,
+    Directory, File, sys_copy_file_range, sys_fgetxattr, sys_fstat, sys_getcwd, sys_getxattr,
+    sys_listxattr, sys_lseek, sys_lstat, sys_open, sys_openat, sys_removexattr, sys_rename,
+    sys_sendfile, sys_setxattr, sys_stat,
 };
+#[cfg(feature = "multitask")]
+pub use imp::futex::{sys_futex, sys_get_robust_list, sys_set_robust_list};
 #[cfg(feature = "select")]
 pub use imp::io_mpx::sys_select;
 #[cfg(feature = "epoll")]
 pub use imp::io_mpx::{sys_epoll_create, sys_epoll_ctl, sys_epoll_wait};
+#[cfg(feature = "kcov")]
+pub use imp::kcov::trace_edge;
+#[cfg(feature = "mmap")]
+pub use imp::mman::{sys_mmap, sys_munmap};
 #[cfg(feature = "net")]
 pub use imp::net::{
-    sys_accept, sys_bind, sys_connect, sys_freeaddrinfo, sys_getaddrinfo, sys_getpeername,
-    sys_getsockname, sys_listen, sys_recv, sys_recvfrom, sys_send, sys_sendto, sys_shutdown,
-    sys_socket,
+    sys_accept, sys_bind, sys_connect, sys_freeaddrinfo, sys_getaddrinfo, sys_getnameinfo,
+    sys_getpeername, sys_getsockname, sys_getsockopt, sys_listen, sys_recv, sys_recvfrom, sys_send,
+    sys_sendto, sys_setsockopt, sys_shutdown, sys_socket, sys_unshare,
 };
 #[cfg(feature = "pipe")]
 pub use imp::pipe::sys_pipe;
@@ -66,3 +83,21 @@ pub use imp::pthread::mutex::{
 };
 #[cfg(feature = "multitask")]
 pub use imp::pthread::{sys_pthread_create, sys_pthread_exit, sys_pthread_join, sys_pthread_self};
+#[cfg(feature = "multitask")]
+pub use imp::session::{sys_getpgid, sys_getsid, sys_setpgid, sys_setsid};
+#[cfg(feature = "multitask")]
+pub use imp::signal::{
+    sys_kill, sys_pthread_kill, sys_rt_sigaction, sys_rt_sigprocmask, sys_sigaltstack,
+    sys_sigpending, sys_tgkill,
+};
+#[cfg(feature = "multitask")]
+pub use imp::task::{
+    sys_getpriority, sys_nice, sys_sched_getaffinity, sys_sched_setaffinity, sys_setpriority,
+};
+#[cfg(feature = "alloc")]
+pub use imp::time::{CLOCK_OFFSETS, ClockOffsets};
+#[cfg(feature = "multitask")]
+pub use imp::timer::{
+    sys_getitimer, sys_setitimer, sys_timer_create, sys_timer_delete, sys_timer_getoverrun,
+    sys_timer_gettime, sys_timer_settime,
+};
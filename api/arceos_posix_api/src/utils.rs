@@ -16,6 +16,39 @@ pub fn char_ptr_to_str<'a>(str: *const c_char) -> LinuxResult<&'a str> {
     }
 }
 
+/// Marks the current task as in a system call until it's dropped, so that
+/// it's not diverted to the signal handlers while it holds kernel locks.
+#[cfg(feature = "multitask")]
+pub struct SyscallGuard;
+
+#[cfg(feature = "multitask")]
+impl SyscallGuard {
+    pub fn enter() -> Self {
+        axtask::set_syscall_depth(axtask::syscall_depth() + 1);
+        Self
+    }
+}
+
+#[cfg(feature = "multitask")]
+impl Drop for SyscallGuard {
+    fn drop(&mut self) {
+        axtask::set_syscall_depth(axtask::syscall_depth() - 1);
+    }
+}
+
+/// Initializes the scheduler for the tests, and serializes them, as they share
+/// the run queues and the current task.
+#[cfg(test)]
+pub fn test_init() -> std::sync::MutexGuard<'static, ()> {
+    use std::sync::{Mutex, Once};
+
+    static INIT: Once = Once::new();
+    static SERIAL: Mutex<()> = Mutex::new(());
+
+    INIT.call_once(axtask::init_scheduler);
+    SERIAL.lock().unwrap_or_else(|e| e.into_inner())
+}
+
 pub fn check_null_ptr<T>(ptr: *const T) -> LinuxResult {
     if ptr.is_null() {
         Err(LinuxError::EFAULT)
@@ -34,23 +67,32 @@ pub fn check_null_mut_ptr<T>(ptr: *mut T) -> LinuxResult {
 
 macro_rules! syscall_body {
     ($fn: ident, $($stmt: tt)*) => {{
+        #[cfg(feature = "multitask")]
+        let _guard = crate::utils::SyscallGuard::enter();
+        #[cfg(feature = "trace")]
+        axtrace::tracepoint!("syscalls", concat!(stringify!($fn), "_enter"));
         #[allow(clippy::redundant_closure_call)]
         let res = (|| -> axerrno::LinuxResult<_> { $($stmt)* })();
         match res {
             Ok(_) | Err(axerrno::LinuxError::EAGAIN) => debug!(concat!(stringify!($fn), " => {:?}"),  res),
             Err(_) => info!(concat!(stringify!($fn), " => {:?}"), res),
         }
-        match res {
+        let ret = match res {
             Ok(v) => v as _,
             Err(e) => {
                 -e.code() as _
             }
-        }
+        };
+        #[cfg(feature = "trace")]
+        axtrace::tracepoint!("syscalls", concat!(stringify!($fn), "_exit"), ret = ret);
+        ret
     }};
 }
 
 macro_rules! syscall_body_no_debug {
     ($($stmt: tt)*) => {{
+        #[cfg(feature = "multitask")]
+        let _guard = crate::utils::SyscallGuard::enter();
         #[allow(clippy::redundant_closure_call)]
         let res = (|| -> axerrno::LinuxResult<_> { $($stmt)* })();
         match res {
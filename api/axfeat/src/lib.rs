@@ -14,16 +14,27 @@
 //!     - `alloc-buddy`: Use the buddy system allocator.
 //!     - `paging`: Enable page table manipulation.
 //!     - `tls`: Enable thread-local storage.
+//!     - `iommu`: Isolate the DMA of the devices by the IOMMU (VT-d or SMMUv3).
 //! - Task management
 //!     - `multitask`: Enable multi-threading support.
 //!     - `sched_fifo`: Use the FIFO cooperative scheduler.
 //!     - `sched_rr`: Use the Round-robin preemptive scheduler.
 //!     - `sched_cfs`: Use the Completely Fair Scheduler (CFS) preemptive scheduler.
-//! - Upperlayer stacks (fs, net, display)
+//! - Upperlayer stacks (fs, net, display, console)
 //!     - `fs`: Enable file system support.
 //!     - `myfs`: Allow users to define their custom filesystems to override the default.
+//!     - `squashfs`: Use a read-only SquashFS image as the root filesystem.
+//!     - `ninep`: Mount the directories shared by the host through virtio-9p on
+//!       `/mnt/<mount tag>`.
+//!     - `overlay`: Allow mounting a writable directory over read-only ones.
+//!     - `initramfs`: Boot from the cpio archive loaded by the bootloader (e.g.
+//!       QEMU `-initrd`), unpacked into a RAM-backed root.
 //!     - `net`: Enable networking support.
 //!     - `display`: Enable graphics support.
+//!     - `console`: Enable console devices (`/dev/hvcN`), one of which can be
+//!       the primary console.
+//! - Plugins
+//!     - `plugin`: Enable loading relocatable kernel-space plugins at runtime.
 //! - Device drivers
 //!     - `bus-mmio`: Use device tree to probe all MMIO devices.
 //!     - `bus-pci`: Use PCI bus to probe all PCI devices.
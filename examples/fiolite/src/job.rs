@@ -0,0 +1,184 @@
+//! Job files, in the INI format of fio.
+//!
+//! Each section but `[global]` is a job named by the section, run in order.
+//! The options of `[global]` are the defaults of the jobs after it. The
+//! supported options are:
+//!
+//! - `filename`: the file to do I/O on, `/fio-<job>.dat` by default.
+//! - `rw`: `read`, `write`, `randread` or `randwrite`.
+//! - `bs`: the size of each I/O, 4k by default.
+//! - `size`: the number of bytes of the file to do I/O on, 4m by default.
+//! - `direct`: 1 to bypass the block cache (`O_DIRECT`).
+//! - `iodepth`: the number of I/Os in flight. Only 1 is supported until there
+//!   is asynchronous I/O.
+//! - `loops`: the number of times to run the job.
+//! - `randseed`: the seed of the random offsets.
+//!
+//! The sizes take a `k`, `m` or `g` suffix, in units of 1024.
+
+use std::string::{String, ToString};
+use std::vec::Vec;
+
+/// The access pattern of a job.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Pattern {
+    Read,
+    Write,
+    RandRead,
+    RandWrite,
+}
+
+impl Pattern {
+    pub fn is_write(self) -> bool {
+        matches!(self, Self::Write | Self::RandWrite)
+    }
+
+    pub fn is_random(self) -> bool {
+        matches!(self, Self::RandRead | Self::RandWrite)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Read => "read",
+            Self::Write => "write",
+            Self::RandRead => "randread",
+            Self::RandWrite => "randwrite",
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Job {
+    pub name: String,
+    pub filename: Option<String>,
+    pub rw: Pattern,
+    pub bs: usize,
+    pub size: u64,
+    pub direct: bool,
+    pub iodepth: usize,
+    pub loops: usize,
+    pub seed: u64,
+}
+
+impl Default for Job {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            filename: None,
+            rw: Pattern::Read,
+            bs: 4096,
+            size: 4 << 20,
+            direct: false,
+            iodepth: 1,
+            loops: 1,
+            seed: 0x8965_3fc7_0d3a_6d25,
+        }
+    }
+}
+
+impl Job {
+    /// The file to do I/O on.
+    pub fn filename(&self) -> String {
+        self.filename
+            .clone()
+            .unwrap_or_else(|| format!("/fio-{}.dat", self.name))
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        let bad_value = || format!("bad value of `{}`: {}", key, value);
+        match key {
+            "filename" => self.filename = Some(value.into()),
+            "rw" | "readwrite" => {
+                self.rw = match value {
+                    "read" => Pattern::Read,
+                    "write" => Pattern::Write,
+                    "randread" => Pattern::RandRead,
+                    "randwrite" => Pattern::RandWrite,
+                    _ => return Err(bad_value()),
+                }
+            }
+            "bs" | "blocksize" => {
+                self.bs = parse_size(value)
+                    .filter(|&bs| bs > 0)
+                    .ok_or_else(bad_value)? as usize
+            }
+            "size" => self.size = parse_size(value).ok_or_else(bad_value)?,
+            "direct" => self.direct = parse_bool(value).ok_or_else(bad_value)?,
+            "iodepth" => {
+                self.iodepth = value
+                    .parse()
+                    .ok()
+                    .filter(|&n| n > 0)
+                    .ok_or_else(bad_value)?
+            }
+            "loops" => self.loops = value.parse().map_err(|_| bad_value())?,
+            "randseed" => self.seed = value.parse().map_err(|_| bad_value())?,
+            _ => return Err(format!("unknown option `{}`", key)),
+        }
+        Ok(())
+    }
+}
+
+/// Parses a size with an optional `k`, `m` or `g` suffix.
+fn parse_size(s: &str) -> Option<u64> {
+    let s = s.to_ascii_lowercase();
+    let s = s.trim_end_matches('b');
+    let (digits, shift) = match s.as_bytes().last()? {
+        b'k' => (&s[..s.len() - 1], 10),
+        b'm' => (&s[..s.len() - 1], 20),
+        b'g' => (&s[..s.len() - 1], 30),
+        _ => (s, 0),
+    };
+    digits.parse::<u64>().ok()?.checked_mul(1 << shift)
+}
+
+fn parse_bool(s: &str) -> Option<bool> {
+    match s {
+        "1" | "true" => Some(true),
+        "0" | "false" => Some(false),
+        _ => None,
+    }
+}
+
+/// Parses a job file, and returns its jobs in order.
+pub fn parse(text: &str) -> Result<Vec<Job>, String> {
+    let mut global = Job::default();
+    let mut jobs: Vec<Job> = Vec::new();
+    let mut in_global = false;
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(';') || line.starts_with('#') {
+            continue;
+        }
+        let err = |msg: String| format!("line {}: {}", i + 1, msg);
+        if let Some(section) = line.strip_prefix('[') {
+            let name = section
+                .strip_suffix(']')
+                .ok_or_else(|| err("unclosed section".into()))?
+                .trim();
+            in_global = name == "global";
+            if !in_global {
+                jobs.push(Job {
+                    name: name.into(),
+                    ..global.clone()
+                });
+            }
+            continue;
+        }
+        // a bare option is a flag, e.g. `direct`
+        let (key, value) = match line.split_once('=') {
+            Some((key, value)) => (key.trim(), value.trim()),
+            None => (line, "1"),
+        };
+        let job = match jobs.last_mut() {
+            Some(job) if !in_global => job,
+            None if !in_global => return Err(err("option outside of a section".into())),
+            _ => &mut global,
+        };
+        job.set(key, value).map_err(err)?;
+    }
+    if jobs.is_empty() {
+        return Err("no jobs".to_string());
+    }
+    Ok(jobs)
+}
@@ -0,0 +1,70 @@
+//! A small storage benchmark in the spirit of fio.
+//!
+//! The jobs are read from [`JOB_FILE`] (see the [`job`] module for its
+//! format), or are [`DEFAULT_JOBS`] if it doesn't exist.
+
+#![cfg_attr(feature = "axstd", no_std)]
+#![cfg_attr(feature = "axstd", no_main)]
+
+#[macro_use]
+#[cfg(feature = "axstd")]
+extern crate axstd as std;
+
+mod job;
+mod run;
+
+/// The job file.
+const JOB_FILE: &str = "/fio.ini";
+
+/// The jobs run without a job file: sequential and random reads and writes,
+/// buffered and direct, on the same file.
+const DEFAULT_JOBS: &str = "\
+[global]
+filename=/fio.dat
+bs=4k
+size=4m
+
+[seq-write]
+rw=write
+
+[seq-read]
+rw=read
+
+[rand-read]
+rw=randread
+
+[rand-write]
+rw=randwrite
+
+[seq-read-direct]
+rw=read
+direct=1
+
+[rand-read-direct]
+rw=randread
+direct=1
+";
+
+#[cfg_attr(feature = "axstd", unsafe(no_mangle))]
+fn main() {
+    let text = match std::fs::read_to_string(JOB_FILE) {
+        Ok(text) => text,
+        Err(_) => {
+            println!("fio-lite: no {}, running the default jobs", JOB_FILE);
+            DEFAULT_JOBS.into()
+        }
+    };
+    let jobs = match job::parse(&text) {
+        Ok(jobs) => jobs,
+        Err(e) => {
+            println!("fio-lite: {}", e);
+            return;
+        }
+    };
+    for job in &jobs {
+        match run::run(job) {
+            Ok(report) => report.print(job),
+            Err(e) => println!("{}: {:?}", job.name, e),
+        }
+    }
+}
@@ -0,0 +1,189 @@
+//! Running the jobs, and reporting the bandwidth and the latencies.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, SeekFrom, prelude::*};
+use std::time::{Duration, Instant};
+use std::vec::Vec;
+
+use crate::job::Job;
+
+/// The alignment of the buffers, enough for direct I/O.
+const BUF_ALIGN: usize = 4096;
+
+/// The percentiles of the latencies reported, as fio does.
+const PERCENTILES: &[f64] = &[50.0, 90.0, 99.0, 99.9, 99.99];
+
+/// The result of a job.
+pub struct Report {
+    /// The number of bytes transferred.
+    bytes: u64,
+    /// The time spent doing I/O.
+    elapsed: Duration,
+    /// The latency of each I/O in nanoseconds, sorted.
+    lat: Vec<u64>,
+}
+
+/// A xorshift generator, for the random offsets to be the same on each run.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+/// A buffer of `len` bytes aligned to [`BUF_ALIGN`].
+struct AlignedBuf {
+    data: Vec<u8>,
+    offset: usize,
+    len: usize,
+}
+
+impl AlignedBuf {
+    fn new(len: usize) -> Self {
+        let data = vec![0xa5; len + BUF_ALIGN];
+        let offset = data.as_ptr().align_offset(BUF_ALIGN);
+        Self { data, offset, len }
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        &self.data[self.offset..self.offset + self.len]
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        &mut self.data[self.offset..self.offset + self.len]
+    }
+}
+
+/// Returns the offsets of the I/Os of a pass over the file: each block once,
+/// in order or shuffled.
+fn offsets(job: &Job, rng: &mut Rng) -> Vec<u64> {
+    let bs = job.bs as u64;
+    let mut offsets: Vec<u64> = (0..job.size / bs).map(|i| i * bs).collect();
+    if job.rw.is_random() {
+        for i in (1..offsets.len()).rev() {
+            let j = (rng.next() % (i as u64 + 1)) as usize;
+            offsets.swap(i, j);
+        }
+    }
+    offsets
+}
+
+/// Fills the file to the size of the job, for it to be read.
+fn lay_out(path: &str, size: u64) -> io::Result<()> {
+    if fs::metadata(path).is_ok_and(|meta| meta.len() >= size) {
+        return Ok(());
+    }
+    println!("  laying out {} ({} bytes)", path, size);
+    let mut file = File::create(path)?;
+    let chunk = vec![0xa5; 64 * 1024];
+    let mut left = size;
+    while left > 0 {
+        let len = left.min(chunk.len() as u64) as usize;
+        file.write_all(&chunk[..len])?;
+        left -= len as u64;
+    }
+    Ok(())
+}
+
+fn open(job: &Job, path: &str) -> io::Result<File> {
+    let mut opts = OpenOptions::new();
+    opts.read(true)
+        .write(job.rw.is_write())
+        .create(job.rw.is_write());
+    #[cfg(feature = "axstd")]
+    opts.direct(job.direct);
+    #[cfg(not(feature = "axstd"))]
+    if job.direct {
+        println!("  direct I/O is only supported on ArceOS, ignored");
+    }
+    opts.open(path)
+}
+
+/// Runs `job`, and returns its report.
+pub fn run(job: &Job) -> io::Result<Report> {
+    println!(
+        "{}: rw={}, bs={}, size={}, direct={}",
+        job.name,
+        job.rw.name(),
+        job.bs,
+        job.size,
+        job.direct as u8
+    );
+    let path = job.filename();
+    if !job.rw.is_write() {
+        lay_out(&path, job.size)?;
+    }
+    if job.iodepth > 1 {
+        println!(
+            "  iodepth={} needs asynchronous I/O, running with 1",
+            job.iodepth
+        );
+    }
+    let mut file = open(job, &path)?;
+    let mut buf = AlignedBuf::new(job.bs);
+    let mut rng = Rng(job.seed | 1);
+    let mut lat = Vec::new();
+    let mut elapsed = Duration::ZERO;
+    for _ in 0..job.loops {
+        for offset in offsets(job, &mut rng) {
+            let start = Instant::now();
+            file.seek(SeekFrom::Start(offset))?;
+            if job.rw.is_write() {
+                file.write_all(buf.as_slice())?;
+            } else {
+                file.read_exact(buf.as_mut_slice())?;
+            }
+            let time = start.elapsed();
+            elapsed += time;
+            lat.push(time.as_nanos() as u64);
+        }
+    }
+    lat.sort_unstable();
+    Ok(Report {
+        bytes: lat.len() as u64 * job.bs as u64,
+        elapsed,
+        lat,
+    })
+}
+
+impl Report {
+    /// Returns the latency at the percentile `p`, in nanoseconds.
+    fn percentile(&self, p: f64) -> u64 {
+        let rank = (p / 100.0 * self.lat.len() as f64) as usize;
+        self.lat[rank.min(self.lat.len() - 1)]
+    }
+
+    /// Prints the bandwidth and the latencies of `job`, as fio does.
+    pub fn print(&self, job: &Job) {
+        if self.lat.is_empty() {
+            println!("  no I/O done, the size is smaller than bs");
+            return;
+        }
+        let secs = self.elapsed.as_secs_f64().max(1e-9);
+        println!(
+            "  {}: IOPS={:.0}, BW={:.2}MiB/s ({} bytes/{}ms)",
+            job.rw.name(),
+            self.lat.len() as f64 / secs,
+            self.bytes as f64 / secs / (1 << 20) as f64,
+            self.bytes,
+            self.elapsed.as_millis()
+        );
+        let usec = |ns: u64| ns as f64 / 1000.0;
+        let avg = self.lat.iter().sum::<u64>() / self.lat.len() as u64;
+        println!(
+            "    lat (usec): min={:.2}, max={:.2}, avg={:.2}",
+            usec(self.lat[0]),
+            usec(self.lat[self.lat.len() - 1]),
+            usec(avg)
+        );
+        print!("    lat percentiles (usec):");
+        for &p in PERCENTILES {
+            print!(" {:.2}th=[{:.2}]", p, usec(self.percentile(p)));
+        }
+        println!();
+    }
+}
@@ -0,0 +1 @@
+app-objs := fuzz.o
@@ -0,0 +1,398 @@
+#include <errno.h>
+#include <fcntl.h>
+#include <pthread.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/stat.h>
+#include <time.h>
+#include <unistd.h>
+
+#ifdef AX_CONFIG_KCOV
+#include <linux/kcov.h>
+#include <sys/ioctl.h>
+#endif
+
+/*
+ * A syscall fuzzer for the POSIX API.
+ *
+ * Programs are short sequences of typed calls on a few file descriptor slots
+ * and paths. When the kernel is built with `KCOV=y`, programs that cover new
+ * edges are kept in a corpus and mutated, otherwise every program is random.
+ *
+ * Each program runs in a new thread. If it doesn't finish in time, it's
+ * reported as a hang and minimized by removing calls as long as the rest
+ * still hangs. The program being run is also saved to `STATE_PATH`, so if it
+ * brings the kernel down, it's reported on the next boot.
+ */
+
+#define MAX_CALLS       16
+#define NR_SLOTS        8
+#define CORPUS_SIZE     256
+#define ITERATIONS      100000
+#define HANG_TIMEOUT_MS 2000
+#define COVER_SIZE      (64 << 10)
+#define SEEN_SIZE       (1 << 18)
+#define STATE_PATH      "/fuzz.last"
+
+enum {
+    OP_OPEN,
+    OP_CLOSE,
+    OP_READ,
+    OP_WRITE,
+    OP_LSEEK,
+    OP_DUP,
+    OP_DUP2,
+    OP_PIPE,
+    OP_FSTAT,
+    OP_STAT,
+    OP_RENAME,
+    NR_OPS,
+};
+
+static const char *const op_names[NR_OPS] = {
+    "open", "close", "read", "write", "lseek", "dup", "dup2", "pipe", "fstat", "stat", "rename",
+};
+
+static const char *const paths[] = {
+    "/fuzz0", "/fuzz1", "/fuzz2", "/", "/fuzz0/x", "", "/dev/null", "/dev/zero",
+};
+#define NR_PATHS (sizeof(paths) / sizeof(paths[0]))
+
+static const int open_flags[] = {
+    O_RDONLY,
+    O_WRONLY | O_CREAT,
+    O_RDWR | O_CREAT | O_TRUNC,
+    O_RDWR | O_APPEND,
+    O_RDONLY | O_DIRECTORY,
+    O_RDWR | O_CREAT | O_EXCL,
+};
+#define NR_OPEN_FLAGS (sizeof(open_flags) / sizeof(open_flags[0]))
+
+struct call {
+    int op;
+    int a, b; // slots or indices into `paths` and `open_flags`
+    long c;   // a length, an offset or `whence`
+};
+
+struct prog {
+    int len;
+    struct call calls[MAX_CALLS];
+};
+
+struct run {
+    const struct prog *prog;
+    volatile int done;
+    int nr_cover;
+};
+
+static uint64_t rng_state;
+
+static uint64_t rnd(void)
+{
+    // xorshift64*
+    rng_state ^= rng_state >> 12;
+    rng_state ^= rng_state << 25;
+    rng_state ^= rng_state >> 27;
+    return rng_state * 0x2545f4914f6cdd1dULL;
+}
+
+static long now_ms(void)
+{
+    struct timespec ts;
+    clock_gettime(CLOCK_MONOTONIC, &ts);
+    return ts.tv_sec * 1000 + ts.tv_nsec / 1000000;
+}
+
+static void random_call(struct call *c)
+{
+    static const long lengths[] = {0, 1, 7, 512, 4096, 65536};
+    c->op = rnd() % NR_OPS;
+    c->a = rnd() % NR_SLOTS;
+    c->b = rnd() % NR_SLOTS;
+    switch (rnd() % 3) {
+    case 0:
+        c->c = lengths[rnd() % (sizeof(lengths) / sizeof(lengths[0]))];
+        break;
+    case 1:
+        c->c = rnd() % 3; // SEEK_SET, SEEK_CUR or SEEK_END
+        break;
+    default:
+        c->c = (long)(rnd() % 8192) - 4096;
+    }
+}
+
+static void print_prog(const char *title, const struct prog *p)
+{
+    printf("%s (%d calls):\n", title, p->len);
+    for (int i = 0; i < p->len; i++) {
+        const struct call *c = &p->calls[i];
+        printf("  %s(%d, %d, %ld)\n", op_names[c->op], c->a, c->b, c->c);
+    }
+}
+
+static char io_buf[65536];
+
+#ifdef AX_CONFIG_KCOV
+static uint64_t cover[COVER_SIZE];
+#endif
+
+static void exec_call(const struct call *c, int *fds)
+{
+    int fd = fds[c->a];
+    size_t len = (size_t)c->c % sizeof(io_buf);
+    struct stat st;
+    int ret, pfd[2];
+
+    switch (c->op) {
+    case OP_OPEN:
+        ret = open(paths[c->b % NR_PATHS], open_flags[c->c % NR_OPEN_FLAGS], 0644);
+        if (ret >= 0)
+            fds[c->a] = ret;
+        break;
+    case OP_CLOSE:
+        if (fd > 2 && close(fd) == 0)
+            fds[c->a] = -1;
+        break;
+    case OP_READ:
+        read(fd, io_buf, len);
+        break;
+    case OP_WRITE:
+        if (fd > 2)
+            write(fd, io_buf, len);
+        break;
+    case OP_LSEEK:
+        lseek(fd, c->c, c->b % 3);
+        break;
+    case OP_DUP:
+        ret = dup(fd);
+        if (ret >= 0)
+            fds[c->b] = ret;
+        break;
+    case OP_DUP2:
+        if (fds[c->b] > 2)
+            dup2(fd, fds[c->b]);
+        break;
+    case OP_PIPE:
+        if (pipe(pfd) == 0) {
+            // reads on an empty pipe must not block, or it's reported as a hang
+            fcntl(pfd[0], F_SETFL, O_NONBLOCK);
+            fcntl(pfd[1], F_SETFL, O_NONBLOCK);
+            fds[c->a] = pfd[0];
+            fds[c->b] = pfd[1];
+        }
+        break;
+    case OP_FSTAT:
+        fstat(fd, &st);
+        break;
+    case OP_STAT:
+        stat(paths[c->b % NR_PATHS], &st);
+        break;
+    case OP_RENAME:
+        rename(paths[c->a % NR_PATHS], paths[c->b % NR_PATHS]);
+        break;
+    }
+}
+
+static void *run_prog(void *arg)
+{
+    struct run *run = arg;
+    int fds[NR_SLOTS];
+
+    for (int i = 0; i < NR_SLOTS; i++)
+        fds[i] = -1;
+
+#ifdef AX_CONFIG_KCOV
+    int kcov = open("/dev/kcov", O_RDWR);
+    if (kcov < 0 || ioctl(kcov, KCOV_INIT_TRACE, COVER_SIZE) < 0 ||
+        ioctl(kcov, KCOV_ENABLE, KCOV_TRACE_PC) < 0) {
+        perror("kcov");
+        exit(1);
+    }
+#endif
+
+    for (int i = 0; i < run->prog->len; i++)
+        exec_call(&run->prog->calls[i], fds);
+
+#ifdef AX_CONFIG_KCOV
+    ioctl(kcov, KCOV_DISABLE, 0);
+    if (read(kcov, cover, sizeof(cover)) >= (ssize_t)sizeof(uint64_t))
+        run->nr_cover = (int)cover[0];
+    close(kcov);
+#endif
+
+    for (int i = 0; i < NR_SLOTS; i++) {
+        if (fds[i] > 2) {
+            close(fds[i]);
+            for (int j = i + 1; j < NR_SLOTS; j++) {
+                if (fds[j] == fds[i])
+                    fds[j] = -1;
+            }
+        }
+    }
+    run->done = 1;
+    return NULL;
+}
+
+static void save_state(const struct prog *p)
+{
+    int fd = open(STATE_PATH, O_WRONLY | O_CREAT | O_TRUNC, 0644);
+    if (fd >= 0) {
+        if (p)
+            write(fd, p, sizeof(*p));
+        close(fd);
+    }
+}
+
+static void check_last_run(void)
+{
+    struct prog p;
+    int fd = open(STATE_PATH, O_RDONLY);
+    if (fd < 0)
+        return;
+    if (read(fd, &p, sizeof(p)) == sizeof(p) && p.len > 0 && p.len <= MAX_CALLS)
+        print_prog("the previous run crashed the kernel running", &p);
+    close(fd);
+}
+
+/*
+ * Runs `p` in a new thread. Returns -1 on a hang, or the number of edges it
+ * covered.
+ */
+static int exec_prog(const struct prog *p, struct run *run)
+{
+    pthread_t t;
+
+    run->prog = p;
+    run->done = 0;
+    run->nr_cover = 0;
+    save_state(p);
+    if (pthread_create(&t, NULL, run_prog, run) != 0) {
+        perror("pthread_create");
+        exit(1);
+    }
+    long deadline = now_ms() + HANG_TIMEOUT_MS;
+    while (!run->done) {
+        if (now_ms() > deadline)
+            return -1; // the thread is leaked
+        usleep(1000);
+    }
+    pthread_join(t, NULL);
+    return run->nr_cover;
+}
+
+static int hangs(const struct prog *p)
+{
+    // the run is leaked together with the thread if it hangs
+    struct run *run = malloc(sizeof(*run));
+    int ret = exec_prog(p, run);
+    if (ret >= 0)
+        free(run);
+    return ret < 0;
+}
+
+static void minimize(struct prog *p)
+{
+    for (int i = p->len - 1; i >= 0; i--) {
+        struct prog q = *p;
+        memmove(&q.calls[i], &q.calls[i + 1], (q.len - i - 1) * sizeof(q.calls[0]));
+        q.len--;
+        if (hangs(&q))
+            *p = q;
+    }
+}
+
+#ifdef AX_CONFIG_KCOV
+static uint64_t seen[SEEN_SIZE];
+
+/* Returns the number of edges in the last trace that were never seen. */
+static int new_edges(int nr_cover)
+{
+    int fresh = 0;
+
+    for (int i = 1; i <= nr_cover && i < COVER_SIZE; i++) {
+        uint64_t edge = cover[i];
+        uint64_t h = (edge * 0x9e3779b97f4a7c15ULL) % SEEN_SIZE;
+        while (seen[h] && seen[h] != edge)
+            h = (h + 1) % SEEN_SIZE;
+        if (!seen[h]) {
+            seen[h] = edge;
+            fresh++;
+        }
+    }
+    return fresh;
+}
+#endif
+
+static struct prog corpus[CORPUS_SIZE];
+static int corpus_len;
+
+static void mutate(struct prog *p)
+{
+    if (corpus_len == 0 || rnd() % 4 == 0) {
+        p->len = 1 + rnd() % MAX_CALLS;
+        for (int i = 0; i < p->len; i++)
+            random_call(&p->calls[i]);
+        return;
+    }
+    *p = corpus[rnd() % corpus_len];
+    int i = rnd() % p->len;
+    switch (rnd() % 3) {
+    case 0:
+        if (p->len < MAX_CALLS) {
+            memmove(&p->calls[i + 1], &p->calls[i], (p->len - i) * sizeof(p->calls[0]));
+            p->len++;
+        }
+        random_call(&p->calls[i]);
+        break;
+    case 1:
+        if (p->len > 1) {
+            memmove(&p->calls[i], &p->calls[i + 1], (p->len - i - 1) * sizeof(p->calls[0]));
+            p->len--;
+        }
+        break;
+    default: {
+        struct call c;
+        random_call(&c);
+        p->calls[i].b = c.b;
+        p->calls[i].c = c.c;
+    }
+    }
+}
+
+int main(void)
+{
+    struct prog p;
+    struct run run;
+    const char *seed = getenv("FUZZ_SEED");
+
+    rng_state = seed ? strtoull(seed, NULL, 0) : (uint64_t)now_ms() | 1;
+    printf("fuzzing with seed %#llx\n", (unsigned long long)rng_state);
+    check_last_run();
+
+    for (int iter = 0; iter < ITERATIONS; iter++) {
+        mutate(&p);
+        int nr_cover = exec_prog(&p, &run);
+        if (nr_cover < 0) {
+            print_prog("hang", &p);
+            minimize(&p);
+            print_prog("minimized reproducer", &p);
+            save_state(NULL);
+            return 1;
+        }
+#ifdef AX_CONFIG_KCOV
+        if (new_edges(nr_cover) > 0) {
+            if (corpus_len < CORPUS_SIZE)
+                corpus[corpus_len++] = p;
+            else
+                corpus[rnd() % CORPUS_SIZE] = p;
+        }
+#endif
+        if (iter % 1000 == 0)
+            printf("%d programs run, corpus size %d\n", iter, corpus_len);
+    }
+    save_state(NULL);
+    printf("done\n");
+    return 0;
+}
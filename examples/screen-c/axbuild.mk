@@ -0,0 +1 @@
+app-objs := screen.o
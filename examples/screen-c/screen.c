@@ -0,0 +1,413 @@
+#include <pthread.h>
+#include <pty.h>
+#include <signal.h>
+#include <stdarg.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/ioctl.h>
+#include <termios.h>
+#include <time.h>
+#include <unistd.h>
+
+/*
+ * screen-lite, a terminal multiplexer on the console.
+ *
+ * Each window is a pseudo-terminal, with a small shell running on its slave
+ * in a thread of its own. The shell leads a new session whose controlling
+ * terminal is the slave, so `^C` and `^\` typed in a window only interrupt
+ * the command of that window.
+ *
+ * The console is put in raw mode: what's typed goes to the master of the
+ * current window, and the output of every window is kept in its scrollback
+ * buffer, printed as well if it's the current window. Switching windows
+ * redraws the end of the scrollback of the new one.
+ *
+ * The commands are typed after `^A`:
+ *
+ *   c      create a window
+ *   n, p   switch to the next or previous window
+ *   0-9    switch to the window of that number
+ *   w      list the windows
+ *   [      print the whole scrollback of the window, to read it back in the
+ *          scrollback of the terminal on the other side of the console
+ *   a      send a literal `^A`
+ *   q      quit
+ */
+
+#define MAX_WINDOWS     10
+#define SCROLLBACK_SIZE (16 << 10)
+#define REDRAW_SIZE     2048
+#define CTRL_A          0x01
+
+enum {
+    WINDOW_FREE,
+    WINDOW_ALIVE,
+    WINDOW_DEAD, // its threads have to be joined, and its master closed
+};
+
+struct window {
+    int state;
+    int master, slave;
+    char name[32];
+    pthread_t shell, pump;
+    char scrollback[SCROLLBACK_SIZE];
+    size_t written; // bytes written to the scrollback, which keeps the last ones
+};
+
+static struct window windows[MAX_WINDOWS];
+static int current = -1;
+static pthread_mutex_t lock;
+static struct winsize console_size;
+static int has_console_size;
+
+static void write_all(int fd, const char *buf, size_t len)
+{
+    while (len > 0) {
+        ssize_t n = write(fd, buf, len);
+        if (n <= 0)
+            return;
+        buf += n;
+        len -= n;
+    }
+}
+
+static void out(int fd, const char *fmt, ...)
+{
+    char buf[256];
+    va_list ap;
+    va_start(ap, fmt);
+    int len = vsnprintf(buf, sizeof(buf), fmt, ap);
+    va_end(ap);
+    if (len > 0)
+        write_all(fd, buf, len < (int)sizeof(buf) ? (size_t)len : sizeof(buf) - 1);
+}
+
+static void on_interrupt(int sig)
+{
+    // nothing to do: the blocked call of the shell fails with `EINTR`
+    (void)sig;
+}
+
+static int sleep_ms(long ms)
+{
+    struct timespec ts = {.tv_sec = ms / 1000, .tv_nsec = (ms % 1000) * 1000000};
+    return nanosleep(&ts, NULL);
+}
+
+static void shell_help(int fd)
+{
+    out(fd, "commands:\n"
+            "  help       show this help\n"
+            "  echo ARGS  print ARGS\n"
+            "  count [N]  count to N (10 by default) every second, ^C to stop\n"
+            "  info       show the session of this window\n"
+            "  exit       close this window (or ^D)\n");
+}
+
+/* A shell on the slave `fd`, until `exit` or the end of file. */
+static void shell(int fd, int index)
+{
+    char line[128];
+
+    out(fd, "window %d, type `help` for the commands\n", index);
+    for (;;) {
+        out(fd, "[%d]$ ", index);
+        ssize_t n = read(fd, line, sizeof(line) - 1);
+        if (n < 0) {
+            // interrupted by `^C`
+            out(fd, "\n");
+            continue;
+        }
+        if (n == 0)
+            break;
+        line[n] = '\0';
+        line[strcspn(line, "\n")] = '\0';
+
+        char *cmd = line + strspn(line, " ");
+        char *args = cmd + strcspn(cmd, " ");
+        if (*args)
+            *args++ = '\0';
+
+        if (!*cmd) {
+            continue;
+        } else if (!strcmp(cmd, "help")) {
+            shell_help(fd);
+        } else if (!strcmp(cmd, "echo")) {
+            out(fd, "%s\n", args);
+        } else if (!strcmp(cmd, "count")) {
+            long limit = *args ? strtol(args, NULL, 10) : 10;
+            for (long i = 1; i <= limit; i++) {
+                out(fd, "%ld\n", i);
+                if (i < limit && sleep_ms(1000) < 0) {
+                    out(fd, "interrupted\n");
+                    break;
+                }
+            }
+        } else if (!strcmp(cmd, "info")) {
+            out(fd, "session %d, process group %d\n", (int)getsid(0), (int)getpgid(0));
+        } else if (!strcmp(cmd, "exit")) {
+            break;
+        } else {
+            out(fd, "%s: command not found\n", cmd);
+        }
+    }
+}
+
+static void *window_main(void *arg)
+{
+    struct window *w = arg;
+    int index = w - windows;
+
+    // lead a session controlled by the slave, for `^C` to interrupt it
+    if (setsid() < 0 || ioctl(w->slave, TIOCSCTTY, 0) < 0)
+        out(w->slave, "no controlling terminal, ^C won't interrupt the commands\n");
+    shell(w->slave, index);
+    close(w->slave);
+    return NULL;
+}
+
+static void record(struct window *w, const char *buf, size_t len)
+{
+    for (size_t i = 0; i < len; i++) w->scrollback[w->written++ % SCROLLBACK_SIZE] = buf[i];
+}
+
+/* Prints the last `len` bytes of the scrollback of `w`. */
+static void replay(const struct window *w, size_t len)
+{
+    if (len > SCROLLBACK_SIZE)
+        len = SCROLLBACK_SIZE;
+    size_t i = w->written > len ? w->written - len : 0;
+    while (i < w->written) {
+        size_t off = i % SCROLLBACK_SIZE;
+        size_t n = SCROLLBACK_SIZE - off;
+        if (n > w->written - i)
+            n = w->written - i;
+        write_all(STDOUT_FILENO, w->scrollback + off, n);
+        i += n;
+    }
+}
+
+/* Clears the console and redraws the current window. Called with `lock`. */
+static void redraw(size_t len)
+{
+    struct window *w = &windows[current];
+    out(STDOUT_FILENO, "\033[2J\033[H-- window %d (%s) --\r\n", current, w->name);
+    replay(w, len);
+}
+
+/* Switches to the window `index`, if it's alive. Called with `lock`. */
+static void switch_to(int index)
+{
+    if (index < 0 || index >= MAX_WINDOWS || windows[index].state != WINDOW_ALIVE) {
+        out(STDOUT_FILENO, "\r\n[no window %d]\r\n", index);
+        return;
+    }
+    current = index;
+    redraw(REDRAW_SIZE);
+}
+
+/*
+ * Switches to the next alive window after the current one in direction `dir`,
+ * or to none. Called with `lock`.
+ */
+static void switch_next(int dir)
+{
+    int start = current < 0 ? 0 : current;
+    for (int i = 1; i <= MAX_WINDOWS; i++) {
+        int index = (start + dir * i + MAX_WINDOWS) % MAX_WINDOWS;
+        if (windows[index].state == WINDOW_ALIVE) {
+            switch_to(index);
+            return;
+        }
+    }
+    current = -1;
+    out(STDOUT_FILENO, "\r\n[no windows, ^A c to create one, ^A q to quit]\r\n");
+}
+
+/* Copies the output of a window to its scrollback and the console. */
+static void *pump(void *arg)
+{
+    struct window *w = arg;
+    int index = w - windows;
+    char buf[256];
+    ssize_t n;
+
+    // fails with `EIO` once the shell has closed the slave
+    while ((n = read(w->master, buf, sizeof(buf))) > 0) {
+        pthread_mutex_lock(&lock);
+        record(w, buf, n);
+        if (current == index)
+            write_all(STDOUT_FILENO, buf, n);
+        pthread_mutex_unlock(&lock);
+    }
+
+    pthread_join(w->shell, NULL);
+    pthread_mutex_lock(&lock);
+    w->state = WINDOW_DEAD;
+    if (current == index)
+        switch_next(1);
+    pthread_mutex_unlock(&lock);
+    return NULL;
+}
+
+/*
+ * Creates a window and returns its number, or -1. Called with `lock`.
+ *
+ * Only the main thread closes the masters, so it can write to the master of
+ * the current window without `lock`, which would block the pumps if the
+ * input of the slave is full.
+ */
+static int open_window(void)
+{
+    for (int i = 0; i < MAX_WINDOWS; i++) {
+        struct window *w = &windows[i];
+        if (w->state == WINDOW_ALIVE)
+            continue;
+        if (w->state == WINDOW_DEAD) {
+            pthread_join(w->pump, NULL);
+            close(w->master);
+            w->state = WINDOW_FREE;
+        }
+
+        if (openpty(&w->master, &w->slave, w->name, NULL,
+                    has_console_size ? &console_size : NULL) < 0) {
+            perror("openpty");
+            return -1;
+        }
+        w->written = 0;
+        if (pthread_create(&w->shell, NULL, window_main, w) != 0) {
+            close(w->master);
+            close(w->slave);
+            return -1;
+        }
+        // the shell owns the slave from now on
+        if (pthread_create(&w->pump, NULL, pump, w) != 0) {
+            close(w->master); // hangs the shell up
+            pthread_join(w->shell, NULL);
+            return -1;
+        }
+        w->state = WINDOW_ALIVE;
+        return i;
+    }
+    return -1;
+}
+
+static void list_windows(void)
+{
+    out(STDOUT_FILENO, "\r\n");
+    for (int i = 0; i < MAX_WINDOWS; i++) {
+        if (windows[i].state == WINDOW_ALIVE)
+            out(STDOUT_FILENO, "%c %d %s\r\n", i == current ? '*' : ' ', i, windows[i].name);
+    }
+}
+
+/* Returns the master of the current window, or -1. */
+static int current_master(void)
+{
+    pthread_mutex_lock(&lock);
+    int master = current >= 0 ? windows[current].master : -1;
+    pthread_mutex_unlock(&lock);
+    return master;
+}
+
+/* Runs the command `c` typed after `^A`, and returns 0 to quit. */
+static int command(char c)
+{
+    int index;
+
+    pthread_mutex_lock(&lock);
+    switch (c) {
+    case 'c':
+        index = open_window();
+        if (index < 0)
+            out(STDOUT_FILENO, "\r\n[can't create a window]\r\n");
+        else
+            switch_to(index);
+        break;
+    case 'n':
+        switch_next(1);
+        break;
+    case 'p':
+        switch_next(-1);
+        break;
+    case 'w':
+        list_windows();
+        break;
+    case '[':
+        if (current >= 0)
+            redraw(SCROLLBACK_SIZE);
+        break;
+    case 'q':
+        pthread_mutex_unlock(&lock);
+        return 0;
+    default:
+        if (c >= '0' && c <= '9')
+            switch_to(c - '0');
+    }
+    pthread_mutex_unlock(&lock);
+    return 1;
+}
+
+int main(void)
+{
+    struct termios saved, raw;
+    struct sigaction sa;
+    char c;
+    int prefix = 0;
+
+    if (tcgetattr(STDIN_FILENO, &saved) < 0) {
+        perror("tcgetattr");
+        return 1;
+    }
+    has_console_size = ioctl(STDIN_FILENO, TIOCGWINSZ, &console_size) == 0;
+    pthread_mutex_init(&lock, NULL);
+
+    // The dispositions are shared by all threads. `^C` and `^\` only reach
+    // the shell of their window, and the stop signals would stop them all.
+    memset(&sa, 0, sizeof(sa));
+    sa.sa_handler = on_interrupt;
+    sigaction(SIGINT, &sa, NULL);
+    sigaction(SIGQUIT, &sa, NULL);
+    sa.sa_handler = SIG_IGN;
+    sigaction(SIGTSTP, &sa, NULL);
+    sigaction(SIGTTIN, &sa, NULL);
+    sigaction(SIGTTOU, &sa, NULL);
+    sigaction(SIGHUP, &sa, NULL);
+
+    raw = saved;
+    cfmakeraw(&raw);
+    tcsetattr(STDIN_FILENO, TCSAFLUSH, &raw);
+
+    pthread_mutex_lock(&lock);
+    if (open_window() < 0) {
+        pthread_mutex_unlock(&lock);
+        tcsetattr(STDIN_FILENO, TCSAFLUSH, &saved);
+        fprintf(stderr, "screen-lite: can't create a window\n");
+        return 1;
+    }
+    switch_to(0);
+    pthread_mutex_unlock(&lock);
+
+    while (read(STDIN_FILENO, &c, 1) == 1) {
+        if (prefix) {
+            prefix = 0;
+            if (c != 'a') {
+                if (!command(c))
+                    break;
+                continue;
+            }
+            c = CTRL_A; // a literal `^A`
+        } else if (c == CTRL_A) {
+            prefix = 1;
+            continue;
+        }
+        int master = current_master();
+        if (master >= 0)
+            write_all(master, &c, 1);
+    }
+
+    tcsetattr(STDIN_FILENO, TCSAFLUSH, &saved);
+    printf("\n[screen-lite is terminating]\n");
+    return 0;
+}
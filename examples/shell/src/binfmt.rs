@@ -0,0 +1,117 @@
+//! Binary format handlers, to run a file as a command.
+//!
+//! A command whose name contains a `/` is the path of a file to run. The
+//! first bytes of the file are given to each of the [`HANDLERS`] in turn, and
+//! the first one recognizing the format returns the command that runs it. A
+//! `#!` script is run by its interpreter, with the path of the script
+//! appended to the arguments of the interpreter.
+//!
+//! The interpreters are the commands of the shell, named by the base name of
+//! their path if the file doesn't exist, e.g. `#!/bin/sh` runs `sh`.
+
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::string::{String, ToString};
+use std::vec::Vec;
+
+/// A handler of a format: given the path of the file, its first bytes and
+/// its arguments, returns the command that runs it (the command name first),
+/// or `None` if the file isn't in its format.
+pub type Handler = fn(path: &str, header: &[u8], args: &[String]) -> Option<Vec<String>>;
+
+/// The handlers of the formats, tried in order. New formats (e.g. wasm) are
+/// added here.
+pub const HANDLERS: &[Handler] = &[shebang];
+
+/// The number of bytes of the file given to the handlers.
+const HEADER_LEN: usize = 256;
+
+/// The maximum number of nested interpreters, as on Linux.
+const MAX_DEPTH: usize = 4;
+
+/// The exit status of a command that can't be run.
+pub const STATUS_NOT_EXEC: i32 = 126;
+
+/// Why a file can't be run.
+pub enum ExecError {
+    /// The file doesn't exist, or can't be opened.
+    NotFound,
+    /// No handler recognizes the format of the file.
+    Format,
+    /// Too many nested interpreters.
+    TooDeep,
+}
+
+impl fmt::Display for ExecError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::NotFound => "No such file or directory",
+            Self::Format => "Exec format error",
+            Self::TooDeep => "Too many levels of interpreters",
+        })
+    }
+}
+
+/// Reads the first bytes of the file at `path`, or returns `None` if it
+/// can't be opened.
+fn read_header(path: &str) -> Option<Vec<u8>> {
+    let mut file = File::open(path).ok()?;
+    let mut header = [0; HEADER_LEN];
+    let mut len = 0;
+    while len < HEADER_LEN {
+        match file.read(&mut header[len..]) {
+            Ok(0) | Err(_) => break,
+            Ok(n) => len += n,
+        }
+    }
+    Some(header[..len].to_vec())
+}
+
+/// Returns the command that runs the file at `path` with `args`.
+pub fn resolve(path: &str, args: &[String]) -> Result<Vec<String>, ExecError> {
+    let mut argv = [&[path.to_string()][..], args].concat();
+    for depth in 0..=MAX_DEPTH {
+        if !argv[0].contains('/') {
+            return Ok(argv);
+        }
+        let Some(header) = read_header(&argv[0]) else {
+            if depth == 0 {
+                return Err(ExecError::NotFound);
+            }
+            // an interpreter that isn't a file is a command
+            let name = argv[0].rsplit('/').next().unwrap_or_default().to_string();
+            argv[0] = name;
+            return Ok(argv);
+        };
+        argv = HANDLERS
+            .iter()
+            .find_map(|handler| handler(&argv[0], &header, &argv[1..]))
+            .ok_or(ExecError::Format)?;
+    }
+    Err(ExecError::TooDeep)
+}
+
+/// Runs a `#!` script: the rest of the first line is the path of the
+/// interpreter, and an optional argument.
+fn shebang(path: &str, header: &[u8], args: &[String]) -> Option<Vec<String>> {
+    let line = header.strip_prefix(b"#!")?;
+    let line = line.split(|&b| b == b'\n').next()?;
+    let line = core::str::from_utf8(line).ok()?.trim();
+    let (interp, arg) = match line.split_once(char::is_whitespace) {
+        Some((interp, arg)) => (interp, Some(arg.trim())),
+        None => (line, None),
+    };
+    if interp.is_empty() {
+        return None;
+    }
+    let mut argv = Vec::new();
+    match arg {
+        // `#!/usr/bin/env sh`: the interpreter is the argument
+        Some(arg) if interp.rsplit('/').next() == Some("env") => argv.push(arg.into()),
+        _ => argv.extend([interp.to_string()].into_iter().chain(arg.map(Into::into))),
+    }
+    argv.push(path.into());
+    argv.extend_from_slice(args);
+    Some(argv)
+}
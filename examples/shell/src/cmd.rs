@@ -1,3 +1,4 @@
+use core::sync::atomic::{AtomicI32, Ordering};
 use std::fs::{self, File, FileType};
 use std::io::{self, prelude::*};
 use std::{string::String, vec::Vec};
@@ -5,13 +6,18 @@ use std::{string::String, vec::Vec};
 #[cfg(all(not(feature = "axstd"), unix))]
 use std::os::unix::fs::{FileTypeExt, PermissionsExt};
 
-use crate::path_to_str;
+use crate::{path_to_str, script};
+
+/// The exit status of the running command, set to 1 by [`print_err!`].
+static STATUS: AtomicI32 = AtomicI32::new(0);
 
 macro_rules! print_err {
     ($cmd: literal, $msg: expr) => {
+        STATUS.store(1, Ordering::Relaxed);
         println!("{}: {}", $cmd, $msg);
     };
     ($cmd: literal, $arg: expr, $err: expr) => {
+        STATUS.store(1, Ordering::Relaxed);
         println!("{}: {}: {}", $cmd, $arg, $err);
     };
 }
@@ -19,15 +25,24 @@ macro_rules! print_err {
 type CmdHandler = fn(&str);
 
 const CMD_TABLE: &[(&str, CmdHandler)] = &[
+    ("allocs", do_allocs),
     ("cat", do_cat),
     ("cd", do_cd),
+    ("df", do_df),
+    ("dmesg", do_dmesg),
     ("echo", do_echo),
     ("exit", do_exit),
     ("help", do_help),
+    ("logs", do_logs),
+    ("loglevel", do_loglevel),
+    ("iptables", do_iptables),
     ("ls", do_ls),
     ("mkdir", do_mkdir),
+    ("prof", do_prof),
     ("pwd", do_pwd),
     ("rm", do_rm),
+    ("services", do_services),
+    ("trace", do_trace),
     ("uname", do_uname),
 ];
 
@@ -240,6 +255,462 @@ fn do_cd(mut args: &str) {
     }
 }
 
+#[cfg(feature = "axstd")]
+fn do_df(args: &str) {
+    fn print_usage(name: &str, usage: &fs::DiskUsage) {
+        let percent = if usage.total == 0 {
+            0
+        } else {
+            (usage.used() * 100).div_ceil(usage.total)
+        };
+        println!(
+            "{:>12} {:>12} {:>12} {:>4}% {}",
+            usage.total / 1024,
+            usage.used() / 1024,
+            usage.available / 1024,
+            percent,
+            name
+        );
+    }
+
+    println!(
+        "{:>12} {:>12} {:>12} {:>5} Mounted on",
+        "1K-blocks", "Used", "Available", "Use%"
+    );
+    if args.is_empty() {
+        match fs::mounts() {
+            Ok(mounts) => {
+                for (path, usage) in mounts {
+                    print_usage(&path, &usage);
+                }
+            }
+            Err(e) => print_err!("df", e),
+        }
+    } else {
+        for path in args.split_whitespace() {
+            match fs::disk_usage(path) {
+                Ok(usage) => print_usage(path, &usage),
+                Err(e) => print_err!("df", path, e),
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "axstd"))]
+fn do_df(_args: &str) {
+    print_err!("df", "not supported on this platform");
+}
+
+#[cfg(feature = "axstd")]
+fn do_dmesg(args: &str) {
+    use std::os::arceos::modules::axlog::{self, LevelFilter};
+
+    // only the records of this level or more severe ones are shown
+    let level = if args.is_empty() {
+        LevelFilter::Trace
+    } else {
+        match args.trim().parse::<LevelFilter>() {
+            Ok(level) => level,
+            Err(e) => {
+                print_err!("dmesg", args, e);
+                return;
+            }
+        }
+    };
+    let mut seq = 0;
+    while let Some(record) = axlog::kmsg_read(seq) {
+        if record.level <= level {
+            println!("{}", record);
+        }
+        seq = record.seq + 1;
+    }
+}
+
+#[cfg(not(feature = "axstd"))]
+fn do_dmesg(_args: &str) {
+    print_err!("dmesg", "not supported on this platform");
+}
+
+#[cfg(feature = "axstd")]
+fn do_loglevel(args: &str) {
+    use std::os::arceos::modules::axlog;
+
+    // e.g. `loglevel arceos_posix_api=off,axfs=debug`
+    let args = args.trim();
+    if !args.is_empty() {
+        if let Err(e) = axlog::update_log_filter(args) {
+            print_err!("loglevel", args, e);
+            return;
+        }
+    }
+    println!("{}", axlog::log_filter());
+}
+
+#[cfg(not(feature = "axstd"))]
+fn do_loglevel(_args: &str) {
+    print_err!("loglevel", "not supported on this platform");
+}
+
+#[cfg(feature = "alloc-track")]
+fn do_allocs(args: &str) {
+    use std::os::arceos::modules::axalloc;
+
+    let limit = if args.is_empty() {
+        20
+    } else {
+        match args.trim().parse::<usize>() {
+            Ok(n) => n,
+            Err(e) => {
+                print_err!("allocs", args, e);
+                return;
+            }
+        }
+    };
+    let sites = axalloc::live_allocations();
+    let (count, bytes) = sites
+        .iter()
+        .fold((0, 0), |(c, b), s| (c + s.count, b + s.bytes));
+    println!(
+        "{} live allocations, {} bytes, from {} call sites",
+        count,
+        bytes,
+        sites.len()
+    );
+    println!("{:>8} {:>12} Backtrace", "Count", "Bytes");
+    for site in sites.iter().take(limit) {
+        print!("{:>8} {:>12}", site.count, site.bytes);
+        for ra in site.backtrace.iter().take_while(|&&ra| ra != 0) {
+            print!(" {:#x}", ra);
+        }
+        println!();
+    }
+}
+
+#[cfg(not(feature = "alloc-track"))]
+fn do_allocs(_args: &str) {
+    print_err!(
+        "allocs",
+        "not supported, rebuild with the alloc-track feature"
+    );
+}
+
+#[cfg(feature = "trace")]
+fn do_trace(args: &str) {
+    use std::os::arceos::modules::axtrace;
+
+    match args.trim() {
+        "on" => axtrace::set_tracing_on(true),
+        "off" => axtrace::set_tracing_on(false),
+        "clear" => axtrace::clear(),
+        "" => {
+            let mut text = String::new();
+            axtrace::dump_text(&mut text).ok();
+            print!("{}", text);
+        }
+        args => print_err!("trace", args, "usage: trace [on|off|clear]"),
+    }
+}
+
+#[cfg(not(feature = "trace"))]
+fn do_trace(_args: &str) {
+    print_err!("trace", "not supported, rebuild with the trace feature");
+}
+
+#[cfg(feature = "prof")]
+fn do_prof(args: &str) {
+    use std::os::arceos::modules::axprof::{self, SampleMode};
+
+    let mut args = args.split_whitespace();
+    let mode = match args.next() {
+        None => {
+            let mut report = String::new();
+            axprof::report_folded(&mut report).ok();
+            print!("{}", report);
+            println!(
+                "# {} samples, {} lost",
+                axprof::sample_count(),
+                axprof::lost_count()
+            );
+            return;
+        }
+        Some("stop") => {
+            axprof::stop();
+            return;
+        }
+        Some("start") => match args.next() {
+            None | Some("pc") => SampleMode::Pc,
+            Some("fp") => SampleMode::FramePointer,
+            Some(arg) => {
+                print_err!("prof", arg, "usage: prof [start [pc|fp] [samples]|stop]");
+                return;
+            }
+        },
+        Some(arg) => {
+            print_err!("prof", arg, "usage: prof [start [pc|fp] [samples]|stop]");
+            return;
+        }
+    };
+    let capacity = match args.next().map(str::parse) {
+        None => axprof::DEFAULT_CAPACITY,
+        Some(Ok(n)) => n,
+        Some(Err(e)) => {
+            print_err!("prof", e);
+            return;
+        }
+    };
+    axprof::start(mode, capacity);
+}
+
+#[cfg(not(feature = "prof"))]
+fn do_prof(_args: &str) {
+    print_err!("prof", "not supported, rebuild with the prof feature");
+}
+
+#[cfg(feature = "net")]
+fn do_iptables(args: &str) {
+    use std::net::IpAddr;
+    use std::os::arceos::modules::axnet::{self, FilterAction, FilterHook, FilterRule};
+    use std::string::ToString;
+
+    const USAGE: &str = "usage: iptables -L|-F [INPUT|OUTPUT]\n\
+        \x20      iptables -D INPUT|OUTPUT <rulenum>\n\
+        \x20      iptables -A INPUT|OUTPUT [-p tcp|udp|icmp] [-s addr[/len]] [-d addr[/len]]\n\
+        \x20                [--sport port] [--dport port] -j ACCEPT|DROP|LOG";
+
+    fn parse_hook(name: &str) -> Result<FilterHook, String> {
+        match name {
+            "INPUT" => Ok(FilterHook::Ingress),
+            "OUTPUT" => Ok(FilterHook::Egress),
+            _ => Err(format!("no chain named {}", name)),
+        }
+    }
+
+    fn parse_network(arg: &str) -> Result<(IpAddr, u8), String> {
+        let (addr, len) = arg.split_once('/').unwrap_or((arg, "32"));
+        let addr = addr
+            .parse()
+            .map_err(|_| format!("invalid address {}", arg))?;
+        let len = len.parse().map_err(|_| format!("invalid prefix {}", arg))?;
+        Ok((addr, len))
+    }
+
+    fn parse_rule(args: &[&str]) -> Result<FilterRule, String> {
+        let mut rule = FilterRule {
+            protocol: None,
+            src: None,
+            dst: None,
+            src_port: None,
+            dst_port: None,
+            action: FilterAction::Accept,
+        };
+        let mut action = None;
+        let mut iter = args.iter();
+        while let Some(&opt) = iter.next() {
+            let val = *iter
+                .next()
+                .ok_or_else(|| format!("{} needs a value", opt))?;
+            let port = || val.parse().map_err(|_| format!("invalid port {}", val));
+            match opt {
+                "-p" => {
+                    rule.protocol = Some(match val {
+                        "icmp" => 1,
+                        "tcp" => 6,
+                        "udp" => 17,
+                        _ => val
+                            .parse()
+                            .map_err(|_| format!("unknown protocol {}", val))?,
+                    })
+                }
+                "-s" => rule.src = Some(parse_network(val)?),
+                "-d" => rule.dst = Some(parse_network(val)?),
+                "--sport" => rule.src_port = Some(port()?),
+                "--dport" => rule.dst_port = Some(port()?),
+                "-j" => {
+                    action = Some(match val {
+                        "ACCEPT" => FilterAction::Accept,
+                        "DROP" => FilterAction::Drop,
+                        "LOG" => FilterAction::Log,
+                        _ => return Err(format!("unknown target {}", val)),
+                    })
+                }
+                _ => return Err(format!("unknown option {}", opt)),
+            }
+        }
+        rule.action = action.ok_or("no target given")?;
+        Ok(rule)
+    }
+
+    fn print_chain(name: &str, hook: FilterHook) {
+        println!("Chain {} (policy ACCEPT)", name);
+        println!(
+            "{:>4} {:>8} {:<6} {:<5} {:<18} {:<18} Ports",
+            "Num", "Pkts", "Target", "Prot", "Source", "Destination"
+        );
+        for (i, (rule, packets)) in axnet::filter_rules(hook).iter().enumerate() {
+            let net = |net: Option<(IpAddr, u8)>| match net {
+                Some((addr, len)) => format!("{}/{}", addr, len),
+                None => "anywhere".into(),
+            };
+            let prot = match rule.protocol {
+                None => "all".into(),
+                Some(1) => "icmp".into(),
+                Some(6) => "tcp".into(),
+                Some(17) => "udp".into(),
+                Some(p) => format!("{}", p),
+            };
+            let target = match rule.action {
+                FilterAction::Accept => "ACCEPT",
+                FilterAction::Drop => "DROP",
+                FilterAction::Log => "LOG",
+            };
+            print!(
+                "{:>4} {:>8} {:<6} {:<5} {:<18} {:<18}",
+                i + 1,
+                packets,
+                target,
+                prot,
+                net(rule.src),
+                net(rule.dst)
+            );
+            if let Some(port) = rule.src_port {
+                print!(" spt:{}", port);
+            }
+            if let Some(port) = rule.dst_port {
+                print!(" dpt:{}", port);
+            }
+            println!();
+        }
+    }
+
+    let args = args.split_whitespace().collect::<Vec<_>>();
+    let chains = match args.get(1) {
+        Some(&name) => match parse_hook(name) {
+            Ok(hook) => vec![(name, hook)],
+            Err(e) => {
+                print_err!("iptables", e);
+                return;
+            }
+        },
+        None => vec![
+            ("INPUT", FilterHook::Ingress),
+            ("OUTPUT", FilterHook::Egress),
+        ],
+    };
+    let result = match args.first().copied() {
+        Some("-L") if args.len() <= 2 => {
+            for (name, hook) in chains {
+                print_chain(name, hook);
+            }
+            Ok(())
+        }
+        Some("-F") if args.len() <= 2 => {
+            for (_, hook) in chains {
+                axnet::flush_filter_rules(hook);
+            }
+            Ok(())
+        }
+        Some("-A") if args.len() > 2 => parse_rule(&args[2..])
+            .and_then(|rule| axnet::add_filter_rule(chains[0].1, rule).map_err(|e| e.to_string())),
+        Some("-D") if args.len() == 3 => match args[2].parse::<usize>() {
+            Ok(num) if num > 0 => {
+                axnet::del_filter_rule(chains[0].1, num - 1).map_err(|e| e.to_string())
+            }
+            _ => Err(format!("invalid rule number {}", args[2])),
+        },
+        _ => Err(USAGE.into()),
+    };
+    if let Err(e) = result {
+        print_err!("iptables", e);
+    }
+}
+
+#[cfg(not(feature = "net"))]
+fn do_iptables(_args: &str) {
+    print_err!("iptables", "not supported, rebuild with the net feature");
+}
+
+#[cfg(feature = "init")]
+static SERVICES: std::sync::Mutex<Option<std::process::service::ServiceManager>> =
+    std::sync::Mutex::new(None);
+
+/// Starts the services defined in `/etc/services`, if any.
+#[cfg(feature = "init")]
+pub fn start_services() {
+    use std::process::service;
+
+    if let Ok(configs) = service::load_dir(service::SERVICE_DIR) {
+        *SERVICES.lock() = Some(service::start(configs));
+    }
+}
+
+#[cfg(feature = "init")]
+fn do_services(_args: &str) {
+    use std::string::ToString;
+
+    match SERVICES.lock().as_ref() {
+        Some(services) => {
+            println!(
+                "{:<16} {:<24} {:>8} {:>8} {:>5} {:>10} {:>10}",
+                "NAME", "STATE", "CPU(ms)", "HEAP(KB)", "FDS", "RX", "TX"
+            );
+            for (name, state) in services.states() {
+                let usage = services.usage(&name).unwrap_or_default();
+                println!(
+                    "{:<16} {:<24} {:>8} {:>8} {:>5} {:>10} {:>10}",
+                    name,
+                    state.to_string(),
+                    usage.cpu_time.as_millis(),
+                    usage.heap_bytes / 1024,
+                    usage.open_files,
+                    usage.net_rx_bytes,
+                    usage.net_tx_bytes,
+                );
+            }
+        }
+        None => print_err!("services", "no services defined"),
+    }
+}
+
+#[cfg(not(feature = "init"))]
+fn do_services(_args: &str) {
+    print_err!("services", "not supported, rebuild with the init feature");
+}
+
+#[cfg(feature = "init")]
+fn do_logs(args: &str) {
+    const DEFAULT_LINES: usize = 10;
+
+    let (name, lines) = split_whitespace(args);
+    if name.is_empty() {
+        print_err!("logs", "no service specified");
+        return;
+    }
+    let lines = if lines.is_empty() {
+        DEFAULT_LINES
+    } else if let Ok(n) = lines.parse() {
+        n
+    } else {
+        print_err!("logs", lines, "invalid number of lines");
+        return;
+    };
+
+    let path = std::process::service::log_path(name);
+    match fs::read_to_string(&path) {
+        Ok(text) => {
+            let all = text.lines().collect::<Vec<_>>();
+            for line in &all[all.len().saturating_sub(lines)..] {
+                println!("{}", line);
+            }
+        }
+        Err(e) => print_err!("logs", path, e),
+    }
+}
+
+#[cfg(not(feature = "init"))]
+fn do_logs(_args: &str) {
+    print_err!("logs", "not supported, rebuild with the init feature");
+}
+
 fn do_pwd(_args: &str) {
     let pwd = std::env::current_dir().unwrap();
     println!("{}", path_to_str(&pwd));
@@ -264,7 +735,9 @@ fn do_uname(_args: &str) {
 
 fn do_help(_args: &str) {
     println!("Available commands:");
-    for (name, _) in CMD_TABLE {
+    let mut names = command_names().collect::<Vec<_>>();
+    names.sort();
+    for name in names {
         println!("  {}", name);
     }
 }
@@ -274,18 +747,23 @@ fn do_exit(_args: &str) {
     std::process::exit(0);
 }
 
-pub fn run_cmd(line: &[u8]) {
+/// Returns the names of the commands, with the builtins of the interpreter.
+pub fn command_names() -> impl Iterator<Item = &'static str> {
+    CMD_TABLE
+        .iter()
+        .map(|(name, _)| *name)
+        .chain(script::BUILTINS.iter().copied())
+}
+
+/// Runs a command, and returns its exit status, or `None` if it doesn't
+/// exist.
+pub fn run_cmd(line: &[u8]) -> Option<i32> {
     let line_str = unsafe { core::str::from_utf8_unchecked(line) };
     let (cmd, args) = split_whitespace(line_str);
-    if !cmd.is_empty() {
-        for (name, func) in CMD_TABLE {
-            if cmd == *name {
-                func(args);
-                return;
-            }
-        }
-        println!("{}: command not found", cmd);
-    }
+    let (_, func) = CMD_TABLE.iter().find(|(name, _)| cmd == *name)?;
+    STATUS.store(0, Ordering::Relaxed);
+    func(args);
+    Some(STATUS.load(Ordering::Relaxed))
 }
 
 fn split_whitespace(str: &str) -> (&str, &str) {
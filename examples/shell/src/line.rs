@@ -0,0 +1,367 @@
+//! A line editor with the usual key bindings of readline.
+//!
+//! The cursor is moved with the arrows and `Home`/`End` (or `^A`/`^E`), the
+//! history is browsed with the up and down arrows, and `Tab` completes the
+//! commands and the paths. The terminal is expected to send each key as it's
+//! typed, without echoing it, as the console of ArceOS does.
+//!
+//! The history is appended to [`HISTORY_FILE`], so that it's kept across the
+//! sessions when the root filesystem is on a disk.
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, prelude::*};
+use std::{string::String, vec::Vec};
+
+use crate::{cmd, path_to_str, print_prompt};
+
+const LF: u8 = b'\n';
+const CR: u8 = b'\r';
+const DL: u8 = b'\x7f';
+const BS: u8 = b'\x08';
+const ESC: u8 = b'\x1b';
+const TAB: u8 = b'\t';
+const SPACE: u8 = b' ';
+
+const fn ctrl(c: u8) -> u8 {
+    c & 0x1f
+}
+
+const MAX_CMD_LEN: usize = 256;
+const MAX_HISTORY: usize = 100;
+
+const HISTORY_FILE: &str = "/.shell_history";
+
+/// A key, with the escape sequences of the terminal decoded.
+enum Key {
+    Char(u8),
+    Enter,
+    Backspace,
+    Delete,
+    Tab,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    /// `^K`, which deletes from the cursor to the end of the line.
+    KillToEnd,
+    /// `^U`, which deletes from the start of the line to the cursor.
+    KillToStart,
+    /// `^C`, which discards the line.
+    Cancel,
+    Ignored,
+}
+
+pub struct LineEditor {
+    stdin: io::Stdin,
+    stdout: io::Stdout,
+    line: Vec<u8>,
+    cursor: usize,
+    history: Vec<String>,
+    /// The entry of the history being shown, `history.len()` for the line
+    /// being typed.
+    hist_pos: usize,
+    /// The line being typed, kept while the history is browsed.
+    pending: Vec<u8>,
+}
+
+impl LineEditor {
+    pub fn new() -> Self {
+        let history = load_history();
+        Self {
+            stdin: io::stdin(),
+            stdout: io::stdout(),
+            line: Vec::new(),
+            cursor: 0,
+            hist_pos: history.len(),
+            history,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Reads a line, after the prompt is printed, and adds it to the history.
+    pub fn read_line(&mut self) -> String {
+        self.line.clear();
+        self.cursor = 0;
+        self.hist_pos = self.history.len();
+        loop {
+            match self.read_key() {
+                Key::Char(c) => self.insert(&[c]),
+                Key::Enter => break,
+                Key::Backspace => {
+                    if self.cursor > 0 {
+                        self.move_left(1);
+                        self.delete(1);
+                    }
+                }
+                Key::Delete => self.delete(1),
+                Key::Tab => self.complete(),
+                Key::Left => self.move_left(1),
+                Key::Right => self.move_right(1),
+                Key::Up => self.browse_history(-1),
+                Key::Down => self.browse_history(1),
+                Key::Home => self.move_left(self.cursor),
+                Key::End => self.move_right(self.line.len() - self.cursor),
+                Key::KillToEnd => self.delete(self.line.len() - self.cursor),
+                Key::KillToStart => {
+                    let n = self.cursor;
+                    self.move_left(n);
+                    self.delete(n);
+                }
+                Key::Cancel => {
+                    self.write(b"^C");
+                    self.line.clear();
+                    break;
+                }
+                Key::Ignored => {}
+            }
+            self.stdout.flush().unwrap();
+        }
+        self.write(b"\n");
+
+        let line: String = String::from_utf8_lossy(&self.line).trim().into();
+        self.add_history(&line);
+        line
+    }
+
+    fn read_byte(&mut self) -> u8 {
+        let mut c = [0];
+        while self.stdin.read(&mut c).ok() != Some(1) {}
+        c[0]
+    }
+
+    fn read_key(&mut self) -> Key {
+        match self.read_byte() {
+            CR | LF => Key::Enter,
+            BS | DL => Key::Backspace,
+            TAB => Key::Tab,
+            ESC => self.read_escape(),
+            c if c == ctrl(b'A') => Key::Home,
+            c if c == ctrl(b'E') => Key::End,
+            c if c == ctrl(b'B') => Key::Left,
+            c if c == ctrl(b'F') => Key::Right,
+            c if c == ctrl(b'P') => Key::Up,
+            c if c == ctrl(b'N') => Key::Down,
+            c if c == ctrl(b'D') => Key::Delete,
+            c if c == ctrl(b'K') => Key::KillToEnd,
+            c if c == ctrl(b'U') => Key::KillToStart,
+            c if c == ctrl(b'C') => Key::Cancel,
+            0..=31 => Key::Ignored,
+            c => Key::Char(c),
+        }
+    }
+
+    /// Decodes the rest of an escape sequence, e.g. `ESC [ A` or `ESC [ 3 ~`.
+    fn read_escape(&mut self) -> Key {
+        if !matches!(self.read_byte(), b'[' | b'O') {
+            return Key::Ignored;
+        }
+        match self.read_byte() {
+            b'A' => Key::Up,
+            b'B' => Key::Down,
+            b'C' => Key::Right,
+            b'D' => Key::Left,
+            b'H' => Key::Home,
+            b'F' => Key::End,
+            n @ b'0'..=b'9' => {
+                // the parameters end with `~`
+                let mut c = n;
+                while c.is_ascii_digit() || c == b';' {
+                    c = self.read_byte();
+                }
+                match (n, c) {
+                    (b'1' | b'7', b'~') => Key::Home,
+                    (b'4' | b'8', b'~') => Key::End,
+                    (b'3', b'~') => Key::Delete,
+                    _ => Key::Ignored,
+                }
+            }
+            _ => Key::Ignored,
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        self.stdout.write_all(bytes).unwrap();
+    }
+
+    /// Moves the cursor of the terminal `n` columns to the left.
+    fn back(&mut self, n: usize) {
+        for _ in 0..n {
+            self.write(&[BS]);
+        }
+    }
+
+    /// Redraws the line from the cursor, erasing the `erased` columns after
+    /// its end, and puts the cursor back.
+    fn redraw_tail(&mut self, erased: usize) {
+        let tail = self.line[self.cursor..].to_vec();
+        self.write(&tail);
+        for _ in 0..erased {
+            self.write(&[SPACE]);
+        }
+        self.back(tail.len() + erased);
+    }
+
+    fn insert(&mut self, bytes: &[u8]) {
+        let bytes = &bytes[..bytes.len().min(MAX_CMD_LEN - 1 - self.line.len())];
+        self.line
+            .splice(self.cursor..self.cursor, bytes.iter().copied());
+        self.write(bytes);
+        self.cursor += bytes.len();
+        self.redraw_tail(0);
+    }
+
+    /// Deletes `n` characters at the cursor.
+    fn delete(&mut self, n: usize) {
+        let n = n.min(self.line.len() - self.cursor);
+        if n > 0 {
+            self.line.drain(self.cursor..self.cursor + n);
+            self.redraw_tail(n);
+        }
+    }
+
+    fn move_left(&mut self, n: usize) {
+        let n = n.min(self.cursor);
+        self.back(n);
+        self.cursor -= n;
+    }
+
+    fn move_right(&mut self, n: usize) {
+        let n = n.min(self.line.len() - self.cursor);
+        let moved = self.line[self.cursor..self.cursor + n].to_vec();
+        self.write(&moved);
+        self.cursor += n;
+    }
+
+    /// Replaces the whole line, with the cursor at its end.
+    fn set_line(&mut self, line: Vec<u8>) {
+        self.move_left(self.cursor);
+        let old_len = self.line.len();
+        self.line = line;
+        self.redraw_tail(old_len.saturating_sub(self.line.len()));
+        self.move_right(self.line.len());
+    }
+
+    /// Shows the previous (`dir < 0`) or the next entry of the history.
+    fn browse_history(&mut self, dir: isize) {
+        let Some(pos) = self.hist_pos.checked_add_signed(dir) else {
+            return;
+        };
+        if pos > self.history.len() {
+            return;
+        }
+        if self.hist_pos == self.history.len() {
+            self.pending = self.line.clone();
+        }
+        self.hist_pos = pos;
+        let line = match self.history.get(pos) {
+            Some(line) => line.as_bytes().to_vec(),
+            None => core::mem::take(&mut self.pending),
+        };
+        self.set_line(line);
+    }
+
+    fn add_history(&mut self, line: &str) {
+        if line.is_empty() || self.history.last().is_some_and(|last| last == line) {
+            return;
+        }
+        if self.history.len() == MAX_HISTORY {
+            self.history.remove(0);
+        }
+        self.history.push(line.into());
+        let file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(HISTORY_FILE);
+        if let Ok(mut file) = file {
+            let _ = file.write_all(line.as_bytes());
+            let _ = file.write_all(b"\n");
+        }
+    }
+
+    /// Completes the word before the cursor: the name of a command for the
+    /// first word, and a path otherwise.
+    ///
+    /// A single match is inserted, otherwise their common prefix is, and
+    /// they're listed if there's none.
+    fn complete(&mut self) {
+        let line = String::from_utf8_lossy(&self.line[..self.cursor]).into_owned();
+        let start = line.rfind(' ').map_or(0, |i| i + 1);
+        let word = &line[start..];
+        let is_cmd = line[..start].trim().is_empty();
+        // the candidates replace the part after the last `/`
+        let prefix = &word[word.rfind('/').map_or(0, |i| i + 1)..];
+        let mut candidates = if is_cmd && !word.contains('/') {
+            cmd::command_names()
+                .filter(|name| name.starts_with(prefix))
+                .map(|name| String::from(name) + " ")
+                .collect()
+        } else {
+            complete_path(word, prefix)
+        };
+        candidates.sort();
+
+        let Some(first) = candidates.first() else {
+            return;
+        };
+        let common = candidates.iter().fold(first.len(), |len, c| {
+            first
+                .bytes()
+                .zip(c.bytes())
+                .take(len)
+                .take_while(|(a, b)| a == b)
+                .count()
+        });
+        if common > prefix.len() {
+            let added = first.as_bytes()[prefix.len()..common].to_vec();
+            self.insert(&added);
+        } else if candidates.len() > 1 {
+            self.write(b"\n");
+            for c in &candidates {
+                self.write(c.trim_end().as_bytes());
+                self.write(b"  ");
+            }
+            self.write(b"\n");
+            print_prompt();
+            let line = self.line.clone();
+            self.write(&line);
+            self.back(self.line.len() - self.cursor);
+        }
+    }
+}
+
+/// Returns the entries of the directory of `word` that start with `prefix`,
+/// followed by `/` for directories and a space otherwise.
+fn complete_path(word: &str, prefix: &str) -> Vec<String> {
+    let dir = &word[..word.len() - prefix.len()];
+    let Ok(entries) = fs::read_dir(if dir.is_empty() { "." } else { dir }) else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(|e| e.ok())
+        .filter_map(|e| {
+            let name = e.file_name();
+            let name = path_to_str(&name);
+            if !name.starts_with(prefix) || name == "." || name == ".." {
+                return None;
+            }
+            let path = String::from(dir) + name;
+            let is_dir = fs::metadata(&path).is_ok_and(|m| m.is_dir());
+            Some(String::from(name) + if is_dir { "/" } else { " " })
+        })
+        .collect()
+}
+
+fn load_history() -> Vec<String> {
+    let Ok(text) = fs::read_to_string(HISTORY_FILE) else {
+        return Vec::new();
+    };
+    let mut history = text
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(String::from)
+        .collect::<Vec<_>>();
+    history.drain(..history.len().saturating_sub(MAX_HISTORY));
+    history
+}
@@ -15,21 +15,16 @@ fn path_to_str(path: &str) -> &str {
     path
 }
 
+mod binfmt;
 mod cmd;
+mod line;
+mod script;
 
 #[cfg(feature = "use-ramfs")]
 mod ramfs;
 
 use std::io::prelude::*;
 
-const LF: u8 = b'\n';
-const CR: u8 = b'\r';
-const DL: u8 = b'\x7f';
-const BS: u8 = b'\x08';
-const SPACE: u8 = b' ';
-
-const MAX_CMD_LEN: usize = 256;
-
 fn print_prompt() {
     print!(
         "arceos:{}$ ",
@@ -40,43 +35,19 @@ fn print_prompt() {
 
 #[cfg_attr(feature = "axstd", unsafe(no_mangle))]
 fn main() {
-    let mut stdin = std::io::stdin();
-    let mut stdout = std::io::stdout();
+    #[cfg(feature = "init")]
+    cmd::start_services();
+    if std::fs::metadata(script::INIT_SCRIPT).is_ok() {
+        script::run_file(script::INIT_SCRIPT, &[script::INIT_SCRIPT.into()]);
+    }
 
-    let mut buf = [0; MAX_CMD_LEN];
-    let mut cursor = 0;
+    let mut editor = line::LineEditor::new();
+    let mut shell = script::Shell::new_interactive();
     cmd::run_cmd("help".as_bytes());
-    print_prompt();
 
     loop {
-        if stdin.read(&mut buf[cursor..cursor + 1]).ok() != Some(1) {
-            continue;
-        }
-        if buf[cursor] == b'\x1b' {
-            buf[cursor] = b'^';
-        }
-        match buf[cursor] {
-            CR | LF => {
-                println!();
-                if cursor > 0 {
-                    cmd::run_cmd(&buf[..cursor]);
-                    cursor = 0;
-                }
-                print_prompt();
-            }
-            BS | DL => {
-                if cursor > 0 {
-                    stdout.write_all(&[BS, SPACE, BS]).unwrap();
-                    cursor -= 1;
-                }
-            }
-            0..=31 => {}
-            c => {
-                if cursor < MAX_CMD_LEN - 1 {
-                    stdout.write_all(&[c]).unwrap();
-                    cursor += 1;
-                }
-            }
-        }
+        print_prompt();
+        let line = editor.read_line();
+        shell.run_line(&line);
     }
 }
@@ -0,0 +1,584 @@
+//! A small script interpreter, after the POSIX shell.
+//!
+//! The commands are separated by newlines or `;`, and chained with `&&` and
+//! `||`. It has `if`/`elif`/`else`, `for ... in` and `while` loops,
+//! variables (`NAME=value`, `$NAME`, `${NAME}`), the arguments of the script
+//! (`$0`-`$9`, `$#`), and the exit status of the last command (`$?`). Words
+//! are quoted with `'...'` and `"..."`, and `#` starts a comment.
+//!
+//! The scripts are run with `sh <file> [args...]`, or by their paths if they
+//! start with `#!` (see [`binfmt`](crate::binfmt)). [`INIT_SCRIPT`] is run at
+//! startup if it exists, to set up the system without rebuilding it.
+
+use std::boxed::Box;
+use std::collections::BTreeMap;
+use std::fs;
+use std::string::{String, ToString};
+use std::vec::Vec;
+
+use crate::binfmt::{self, ExecError};
+use crate::cmd;
+
+/// The script run at startup.
+pub const INIT_SCRIPT: &str = "/etc/init.sh";
+
+/// The commands handled by the interpreter itself.
+pub const BUILTINS: &[&str] = &["[", "exit", "false", "sh", "test", "true"];
+
+/// The exit status of a command that doesn't exist.
+const STATUS_NOT_FOUND: i32 = 127;
+/// The exit status of a misused builtin.
+const STATUS_USAGE: i32 = 2;
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Word(String),
+    /// A newline or `;`.
+    Sep,
+    And,
+    Or,
+}
+
+enum Node {
+    /// The words of a command, not expanded yet.
+    Command(Vec<String>),
+    And(Box<Node>, Box<Node>),
+    Or(Box<Node>, Box<Node>),
+    If {
+        /// The conditions and the bodies of `if` and of the `elif`s.
+        branches: Vec<(Vec<Node>, Vec<Node>)>,
+        otherwise: Vec<Node>,
+    },
+    For {
+        var: String,
+        words: Vec<String>,
+        body: Vec<Node>,
+    },
+    While {
+        cond: Vec<Node>,
+        body: Vec<Node>,
+    },
+}
+
+fn tokenize(src: &str) -> Result<Vec<Token>, &'static str> {
+    let mut tokens = Vec::new();
+    let mut chars = src.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            '\n' | ';' => {
+                chars.next();
+                tokens.push(Token::Sep);
+            }
+            '#' => while chars.next_if(|&c| c != '\n').is_some() {},
+            '&' | '|' => {
+                chars.next();
+                if chars.next_if_eq(&c).is_none() {
+                    return Err("only `&&` and `||` are supported");
+                }
+                tokens.push(if c == '&' { Token::And } else { Token::Or });
+            }
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            _ => {
+                // the quotes are kept, and removed when the word is expanded
+                let mut word = String::new();
+                let mut quote = None;
+                while let Some(&c) = chars.peek() {
+                    match quote {
+                        None if c.is_whitespace() || matches!(c, ';' | '&' | '|') => break,
+                        None if c == '\'' || c == '"' => quote = Some(c),
+                        Some(q) if c == q => quote = None,
+                        _ => {}
+                    }
+                    word.push(c);
+                    chars.next();
+                    if c == '\\' && quote != Some('\'') {
+                        word.extend(chars.next());
+                    }
+                }
+                if quote.is_some() {
+                    return Err("unterminated quote");
+                }
+                tokens.push(Token::Word(word));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek_word(&self) -> Option<&str> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Word(w)) => Some(w),
+            _ => None,
+        }
+    }
+
+    fn skip_seps(&mut self) {
+        while self.tokens.get(self.pos) == Some(&Token::Sep) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, keyword: &'static str) -> Result<(), String> {
+        if self.peek_word() == Some(keyword) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(String::from("expected `") + keyword + "`")
+        }
+    }
+
+    /// Parses the commands up to one of the keywords `until`, or to the end
+    /// if it's empty.
+    fn parse_list(&mut self, until: &[&str]) -> Result<Vec<Node>, String> {
+        let mut list = Vec::new();
+        loop {
+            self.skip_seps();
+            match self.peek_word() {
+                Some(w) if until.contains(&w) => return Ok(list),
+                None if self.pos == self.tokens.len() => {
+                    return match until.last() {
+                        Some(keyword) => Err(String::from("expected `") + keyword + "`"),
+                        None => Ok(list),
+                    };
+                }
+                _ => list.push(self.parse_and_or()?),
+            }
+        }
+    }
+
+    fn parse_and_or(&mut self) -> Result<Node, String> {
+        let mut node = self.parse_command()?;
+        loop {
+            let and = match self.tokens.get(self.pos) {
+                Some(Token::And) => true,
+                Some(Token::Or) => false,
+                _ => return Ok(node),
+            };
+            self.pos += 1;
+            self.skip_seps();
+            let rhs = Box::new(self.parse_command()?);
+            node = if and {
+                Node::And(Box::new(node), rhs)
+            } else {
+                Node::Or(Box::new(node), rhs)
+            };
+        }
+    }
+
+    fn parse_command(&mut self) -> Result<Node, String> {
+        match self.peek_word() {
+            Some("if") => {
+                self.pos += 1;
+                let mut branches = Vec::new();
+                let mut otherwise = Vec::new();
+                loop {
+                    let cond = self.parse_list(&["then"])?;
+                    self.expect("then")?;
+                    let body = self.parse_list(&["elif", "else", "fi"])?;
+                    branches.push((cond, body));
+                    match self.peek_word() {
+                        Some("elif") => self.pos += 1,
+                        Some("else") => {
+                            self.pos += 1;
+                            otherwise = self.parse_list(&["fi"])?;
+                            break;
+                        }
+                        _ => break,
+                    }
+                }
+                self.expect("fi")?;
+                Ok(Node::If {
+                    branches,
+                    otherwise,
+                })
+            }
+            Some("for") => {
+                self.pos += 1;
+                let var = self.peek_word().ok_or("expected a variable")?.into();
+                self.pos += 1;
+                self.expect("in")?;
+                let mut words = Vec::new();
+                while let Some(w) = self.peek_word() {
+                    words.push(w.into());
+                    self.pos += 1;
+                }
+                self.skip_seps();
+                self.expect("do")?;
+                let body = self.parse_list(&["done"])?;
+                self.expect("done")?;
+                Ok(Node::For { var, words, body })
+            }
+            Some("while") => {
+                self.pos += 1;
+                let cond = self.parse_list(&["do"])?;
+                self.expect("do")?;
+                let body = self.parse_list(&["done"])?;
+                self.expect("done")?;
+                Ok(Node::While { cond, body })
+            }
+            Some(_) => {
+                let mut words = Vec::new();
+                while let Some(w) = self.peek_word() {
+                    words.push(w.into());
+                    self.pos += 1;
+                }
+                Ok(Node::Command(words))
+            }
+            None => Err("unexpected `&&` or `||`".into()),
+        }
+    }
+}
+
+fn parse(src: &str) -> Result<Vec<Node>, String> {
+    let tokens = tokenize(src)?;
+    Parser { tokens, pos: 0 }.parse_list(&[])
+}
+
+fn is_var_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// The state of a shell: the variables, the arguments, and the exit status
+/// of the last command.
+pub struct Shell {
+    vars: BTreeMap<String, String>,
+    /// The name of the script followed by its arguments.
+    args: Vec<String>,
+    status: i32,
+    interactive: bool,
+}
+
+impl Shell {
+    /// Creates the shell of the command line.
+    pub fn new_interactive() -> Self {
+        Self {
+            vars: BTreeMap::new(),
+            args: Vec::from([String::from("sh")]),
+            status: 0,
+            interactive: true,
+        }
+    }
+
+    /// Runs a line typed in the command line.
+    pub fn run_line(&mut self, line: &str) {
+        match parse(line) {
+            Ok(list) => {
+                let _ = self.exec_list(&list);
+            }
+            Err(e) => {
+                println!("sh: syntax error: {}", e);
+                self.status = STATUS_USAGE;
+            }
+        }
+    }
+
+    /// Executes the commands, and returns `Err` with the exit status if the
+    /// script exits.
+    fn exec_list(&mut self, list: &[Node]) -> Result<(), i32> {
+        for node in list {
+            self.exec(node)?;
+        }
+        Ok(())
+    }
+
+    fn exec(&mut self, node: &Node) -> Result<(), i32> {
+        match node {
+            Node::Command(words) => {
+                let words = words
+                    .iter()
+                    .flat_map(|w| self.expand(w))
+                    .collect::<Vec<_>>();
+                self.status = self.exec_command(&words)?;
+            }
+            Node::And(lhs, rhs) => {
+                self.exec(lhs)?;
+                if self.status == 0 {
+                    self.exec(rhs)?;
+                }
+            }
+            Node::Or(lhs, rhs) => {
+                self.exec(lhs)?;
+                if self.status != 0 {
+                    self.exec(rhs)?;
+                }
+            }
+            Node::If {
+                branches,
+                otherwise,
+            } => {
+                for (cond, body) in branches {
+                    self.exec_list(cond)?;
+                    if self.status == 0 {
+                        return self.exec_list(body);
+                    }
+                }
+                self.status = 0;
+                self.exec_list(otherwise)?;
+            }
+            Node::For { var, words, body } => {
+                let values = words
+                    .iter()
+                    .flat_map(|w| self.expand(w))
+                    .collect::<Vec<_>>();
+                self.status = 0;
+                for value in values {
+                    self.vars.insert(var.clone(), value);
+                    self.exec_list(body)?;
+                }
+            }
+            Node::While { cond, body } => {
+                let mut status = 0;
+                loop {
+                    self.exec_list(cond)?;
+                    if self.status != 0 {
+                        break;
+                    }
+                    self.exec_list(body)?;
+                    status = self.status;
+                }
+                self.status = status;
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs an expanded command, and returns its exit status.
+    fn exec_command(&mut self, words: &[String]) -> Result<i32, i32> {
+        let Some((name, args)) = words.split_first() else {
+            return Ok(0);
+        };
+        match name.split_once('=') {
+            Some((var, value)) if is_var_name(var) && args.is_empty() => {
+                self.vars.insert(var.into(), value.into());
+                return Ok(0);
+            }
+            _ => {}
+        }
+        match name.as_str() {
+            "true" => Ok(0),
+            "false" => Ok(1),
+            "test" => Ok(test(args)),
+            "[" => match args.split_last() {
+                Some((last, args)) if last == "]" => Ok(test(args)),
+                _ => {
+                    println!("[: missing `]`");
+                    Ok(STATUS_USAGE)
+                }
+            },
+            "sh" => match args.split_first() {
+                Some((path, _)) => Ok(run_file(path, args)),
+                None => {
+                    println!("sh: no script specified");
+                    Ok(STATUS_USAGE)
+                }
+            },
+            "exit" if !self.interactive => match args.first().map(|s| s.parse()) {
+                None => Err(self.status),
+                Some(Ok(status)) => Err(status),
+                Some(Err(_)) => {
+                    println!("exit: numeric argument required");
+                    Err(STATUS_USAGE)
+                }
+            },
+            _ if name.contains('/') => match binfmt::resolve(name, args) {
+                Ok(argv) => self.exec_command(&argv),
+                Err(e) => {
+                    println!("{}: {}", name, e);
+                    Ok(match e {
+                        ExecError::NotFound => STATUS_NOT_FOUND,
+                        _ => binfmt::STATUS_NOT_EXEC,
+                    })
+                }
+            },
+            _ => {
+                let line = words.join(" ");
+                Ok(cmd::run_cmd(line.as_bytes()).unwrap_or_else(|| {
+                    println!("{}: command not found", name);
+                    STATUS_NOT_FOUND
+                }))
+            }
+        }
+    }
+
+    fn var(&self, name: &str) -> String {
+        match name {
+            "?" => self.status.to_string(),
+            "#" => self.args.len().saturating_sub(1).to_string(),
+            _ => match name.parse::<usize>() {
+                Ok(i) => self.args.get(i).cloned().unwrap_or_default(),
+                Err(_) => self.vars.get(name).cloned().unwrap_or_default(),
+            },
+        }
+    }
+
+    /// Expands the variables of a word and removes its quotes. Outside of
+    /// the quotes, the values of the variables are split into words.
+    fn expand(&self, word: &str) -> Vec<String> {
+        let mut fields = Vec::new();
+        let mut cur = String::new();
+        // whether `cur` is a word, even if it's empty (e.g. `""`)
+        let mut has_cur = false;
+        let mut in_double = false;
+        let mut chars = word.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\'' if !in_double => {
+                    while let Some(c) = chars.next_if(|&c| c != '\'') {
+                        cur.push(c);
+                    }
+                    chars.next();
+                    has_cur = true;
+                }
+                '"' => {
+                    in_double = !in_double;
+                    has_cur = true;
+                }
+                '\\' => {
+                    cur.extend(chars.next());
+                    has_cur = true;
+                }
+                '$' => {
+                    let name = match chars.peek() {
+                        Some('{') => {
+                            chars.next();
+                            let mut name = String::new();
+                            while let Some(c) = chars.next_if(|&c| c != '}') {
+                                name.push(c);
+                            }
+                            chars.next();
+                            name
+                        }
+                        Some(&c) if matches!(c, '?' | '#') || c.is_ascii_digit() => {
+                            chars.next();
+                            c.to_string()
+                        }
+                        _ => {
+                            let mut name = String::new();
+                            while let Some(c) =
+                                chars.next_if(|&c| c.is_ascii_alphanumeric() || c == '_')
+                            {
+                                name.push(c);
+                            }
+                            name
+                        }
+                    };
+                    if name.is_empty() {
+                        cur.push('$');
+                        has_cur = true;
+                    } else if in_double {
+                        cur.push_str(&self.var(&name));
+                        has_cur = true;
+                    } else {
+                        for (i, piece) in self.var(&name).split_whitespace().enumerate() {
+                            if i > 0 {
+                                fields.push(core::mem::take(&mut cur));
+                            }
+                            cur.push_str(piece);
+                            has_cur = true;
+                        }
+                    }
+                }
+                c => {
+                    cur.push(c);
+                    has_cur = true;
+                }
+            }
+        }
+        if has_cur {
+            fields.push(cur);
+        }
+        fields
+    }
+}
+
+/// Runs the script at `path` with the arguments `args` (`args[0]` being the
+/// name of the script), and returns its exit status.
+pub fn run_file(path: &str, args: &[String]) -> i32 {
+    let src = match fs::read_to_string(path) {
+        Ok(src) => src,
+        Err(e) => {
+            println!("sh: {}: {}", path, e);
+            return STATUS_NOT_FOUND;
+        }
+    };
+    let list = match parse(&src) {
+        Ok(list) => list,
+        Err(e) => {
+            println!("sh: {}: syntax error: {}", path, e);
+            return STATUS_USAGE;
+        }
+    };
+    let mut shell = Shell {
+        vars: BTreeMap::new(),
+        args: args.to_vec(),
+        status: 0,
+        interactive: false,
+    };
+    match shell.exec_list(&list) {
+        Ok(()) => shell.status,
+        Err(status) => status,
+    }
+}
+
+/// Evaluates the expression of `test`, and returns its exit status.
+fn test(args: &[String]) -> i32 {
+    fn is_dir(path: &str) -> bool {
+        fs::metadata(path).is_ok_and(|m| m.is_dir())
+    }
+    fn is_file(path: &str) -> bool {
+        fs::metadata(path).is_ok_and(|m| m.is_file())
+    }
+    fn compare(a: &str, op: &str, b: &str) -> Option<bool> {
+        if op == "=" || op == "==" {
+            return Some(a == b);
+        } else if op == "!=" {
+            return Some(a != b);
+        }
+        let (a, b) = (a.parse::<i64>().ok()?, b.parse::<i64>().ok()?);
+        match op {
+            "-eq" => Some(a == b),
+            "-ne" => Some(a != b),
+            "-lt" => Some(a < b),
+            "-le" => Some(a <= b),
+            "-gt" => Some(a > b),
+            "-ge" => Some(a >= b),
+            _ => None,
+        }
+    }
+    fn eval(args: &[&str]) -> Option<bool> {
+        match *args {
+            [] => Some(false),
+            ["!", ref rest @ ..] => eval(rest).map(|b| !b),
+            [s] => Some(!s.is_empty()),
+            ["-n", s] => Some(!s.is_empty()),
+            ["-z", s] => Some(s.is_empty()),
+            ["-e", path] => Some(fs::metadata(path).is_ok()),
+            ["-d", path] => Some(is_dir(path)),
+            ["-f", path] => Some(is_file(path)),
+            [a, op, b] => compare(a, op, b),
+            _ => None,
+        }
+    }
+
+    let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+    match eval(&args) {
+        Some(true) => 0,
+        Some(false) => 1,
+        None => {
+            println!("test: invalid expression: {}", args.join(" "));
+            STATUS_USAGE
+        }
+    }
+}
@@ -0,0 +1,205 @@
+//! A heap checker for debugging, similar to a simplified KASAN.
+//!
+//! Every allocation is surrounded by redzones filled with a known pattern,
+//! and the header in the left redzone records the backtraces of its
+//! allocation and deallocation. Freed memory is poisoned and kept in a
+//! quarantine for a while before it's given back to the byte allocator. The
+//! following errors are caught and reported with the recorded backtraces:
+//!
+//! - Writes out of bounds, when the allocation is freed.
+//! - Writes after free, when the allocation leaves the quarantine.
+//! - Double free and free of a pointer that was never allocated.
+//!
+//! Reads are not checked, as there is no shadow memory.
+//!
+//! The backtraces are collected by walking the frame pointers, so the kernel
+//! should be built with `-C force-frame-pointers=yes` to get useful ones,
+//! and with `KSYMS=y` to show the function names.
+
+use core::alloc::Layout;
+use core::ptr::NonNull;
+
+use allocator::AllocResult;
+use kspin::SpinNoIrq;
+
+use crate::GlobalAllocator;
+
+/// The size of the redzone after the allocation, and the minimum size of
+/// the one before the allocation, besides the header.
+const REDZONE_SIZE: usize = 32;
+/// The total size of the freed allocations kept in the quarantine.
+const QUARANTINE_SIZE: usize = 1 << 20;
+pub(crate) const BACKTRACE_DEPTH: usize = 8;
+
+const MAGIC_LIVE: usize = 0x4845_4150_4c49_5645; // "HEAPLIVE"
+const MAGIC_FREED: usize = 0x4845_4150_4652_4545; // "HEAPFREE"
+
+const POISON_REDZONE: u8 = 0xfa;
+const POISON_FREED: u8 = 0xfd;
+const POISON_UNINIT: u8 = 0xbe;
+
+pub(crate) type Backtrace = [usize; BACKTRACE_DEPTH];
+
+/// Placed at the start of the left redzone.
+#[repr(C)]
+struct Header {
+    magic: usize,
+    size: usize,
+    align: usize,
+    /// The next allocation in the quarantine.
+    next: usize,
+    alloc_bt: Backtrace,
+    free_bt: Backtrace,
+    /// The call site in the allocation tracker.
+    #[cfg(feature = "track")]
+    site: usize,
+}
+
+struct Quarantine {
+    head: usize,
+    tail: usize,
+    bytes: usize,
+}
+
+static QUARANTINE: SpinNoIrq<Quarantine> = SpinNoIrq::new(Quarantine {
+    head: 0,
+    tail: 0,
+    bytes: 0,
+});
+
+/// The size of the left redzone, including the header.
+fn left_size(layout: Layout) -> usize {
+    (size_of::<Header>() + REDZONE_SIZE).next_multiple_of(layout.align())
+}
+
+fn raw_layout(layout: Layout) -> Layout {
+    let align = layout.align().max(align_of::<Header>());
+    Layout::from_size_align(left_size(layout) + layout.size() + REDZONE_SIZE, align).unwrap()
+}
+
+#[inline(always)]
+fn backtrace() -> Backtrace {
+    let mut bt = [0; BACKTRACE_DEPTH];
+    axksyms::backtrace(&mut bt);
+    bt
+}
+
+fn report_backtrace(what: &str, bt: &Backtrace) {
+    error!("{}:", what);
+    for &ra in bt.iter().take_while(|&&ra| ra != 0) {
+        error!("  {}", axksyms::Symbolized(ra));
+    }
+}
+
+fn report(msg: &str, ptr: usize, header: &Header) -> ! {
+    error!("heap check: {} at {:#x} (size {})", msg, ptr, header.size);
+    report_backtrace("allocated at", &header.alloc_bt);
+    if header.magic == MAGIC_FREED {
+        report_backtrace("freed at", &header.free_bt);
+    }
+    panic!("heap check: {} at {:#x}", msg, ptr);
+}
+
+fn is_filled(start: usize, len: usize, byte: u8) -> bool {
+    let data = unsafe { core::slice::from_raw_parts(start as *const u8, len) };
+    data.iter().all(|&b| b == byte)
+}
+
+fn fill(start: usize, len: usize, byte: u8) {
+    unsafe { core::ptr::write_bytes(start as *mut u8, byte, len) };
+}
+
+pub(crate) fn alloc(allocator: &GlobalAllocator, layout: Layout) -> AllocResult<NonNull<u8>> {
+    let raw = allocator.alloc_bytes(raw_layout(layout))?.as_ptr() as usize;
+    let left = left_size(layout);
+    let ptr = raw + left;
+    let header = unsafe { &mut *(raw as *mut Header) };
+    let alloc_bt = backtrace();
+    *header = Header {
+        magic: MAGIC_LIVE,
+        size: layout.size(),
+        align: layout.align(),
+        next: 0,
+        alloc_bt,
+        free_bt: [0; BACKTRACE_DEPTH],
+        #[cfg(feature = "track")]
+        site: crate::track::add(&alloc_bt, layout.size()),
+    };
+    fill(
+        raw + size_of::<Header>(),
+        left - size_of::<Header>(),
+        POISON_REDZONE,
+    );
+    fill(ptr, layout.size(), POISON_UNINIT);
+    fill(ptr + layout.size(), REDZONE_SIZE, POISON_REDZONE);
+    Ok(unsafe { NonNull::new_unchecked(ptr as *mut u8) })
+}
+
+pub(crate) fn dealloc(allocator: &GlobalAllocator, pos: NonNull<u8>, layout: Layout) {
+    let ptr = pos.as_ptr() as usize;
+    let left = left_size(layout);
+    let raw = ptr - left;
+    let header = unsafe { &mut *(raw as *mut Header) };
+    match header.magic {
+        MAGIC_LIVE => {}
+        MAGIC_FREED => report("double free", ptr, header),
+        _ => panic!("heap check: invalid free at {:#x}", ptr),
+    }
+    if header.size != layout.size() {
+        report("free with a wrong layout", ptr, header);
+    }
+    if !is_filled(
+        raw + size_of::<Header>(),
+        left - size_of::<Header>(),
+        POISON_REDZONE,
+    ) || !is_filled(ptr + layout.size(), REDZONE_SIZE, POISON_REDZONE)
+    {
+        report("out-of-bounds write", ptr, header);
+    }
+    header.magic = MAGIC_FREED;
+    header.free_bt = backtrace();
+    #[cfg(feature = "track")]
+    crate::track::remove(header.site, header.size);
+    fill(ptr, layout.size(), POISON_FREED);
+
+    // Put it into the quarantine, and evict the oldest ones if it's full.
+    let mut evicted = 0;
+    {
+        let mut q = QUARANTINE.lock();
+        if q.tail == 0 {
+            q.head = raw;
+        } else {
+            unsafe { (*(q.tail as *mut Header)).next = raw };
+        }
+        q.tail = raw;
+        q.bytes += layout.size();
+        while q.bytes > QUARANTINE_SIZE && q.head != raw {
+            let old = q.head;
+            let old_header = unsafe { &mut *(old as *mut Header) };
+            q.head = old_header.next;
+            q.bytes -= old_header.size;
+            old_header.next = evicted;
+            evicted = old;
+        }
+    }
+    while evicted != 0 {
+        let header = unsafe { &*(evicted as *const Header) };
+        let next = header.next;
+        release(allocator, evicted, header);
+        evicted = next;
+    }
+}
+
+/// Checks a freed allocation leaving the quarantine, and gives it back to
+/// the byte allocator.
+fn release(allocator: &GlobalAllocator, raw: usize, header: &Header) {
+    let layout = Layout::from_size_align(header.size, header.align).unwrap();
+    let ptr = raw + left_size(layout);
+    if !is_filled(ptr, header.size, POISON_FREED) {
+        report("use-after-free write", ptr, header);
+    }
+    allocator.dealloc_bytes(
+        unsafe { NonNull::new_unchecked(raw as *mut u8) },
+        raw_layout(layout),
+    );
+}
@@ -0,0 +1,94 @@
+//! The contiguous memory allocator (CMA), for large physically contiguous
+//! buffers such as framebuffers and DMA buffers.
+//!
+//! It manages a region reserved at boot, apart from the global allocator, so
+//! that large buffers can still be allocated once the rest of the memory is
+//! fragmented. Unlike Linux, the region is not lent to other allocations
+//! while it's free, as there are no movable pages to migrate out of it when
+//! a buffer is needed: what's in the region is always a CMA buffer.
+
+use core::ops::Range;
+
+use allocator::{AllocError, AllocResult, BaseAllocator, BitmapPageAllocator, PageAllocator};
+use kspin::SpinNoIrq;
+
+use crate::{PAGE_SIZE, global_allocator};
+
+/// What to do when the CMA region can't satisfy an allocation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CmaPolicy {
+    /// Fail with [`AllocError::NoMemory`].
+    Strict,
+    /// Allocate contiguous pages from the global allocator instead, which
+    /// may fail if the memory is fragmented.
+    Fallback,
+}
+
+struct Cma {
+    range: Range<usize>,
+    palloc: BitmapPageAllocator<PAGE_SIZE>,
+}
+
+static CMA: SpinNoIrq<Cma> = SpinNoIrq::new(Cma {
+    range: 0..0,
+    palloc: BitmapPageAllocator::new(),
+});
+
+/// Initializes the CMA with the given region, which must not be in the
+/// global allocator.
+pub fn cma_init(start_vaddr: usize, size: usize) {
+    info!(
+        "initialize CMA at: [{:#x}, {:#x})",
+        start_vaddr,
+        start_vaddr + size
+    );
+    let mut cma = CMA.lock();
+    cma.range = start_vaddr..start_vaddr + size;
+    cma.palloc.init(start_vaddr, size);
+}
+
+/// Allocates `num_pages` contiguous pages from the CMA region, aligned to
+/// `align_pow2`, returns the virtual address of the first page.
+///
+/// If the region is full or there is no region, `policy` decides whether to
+/// allocate them from the global allocator instead.
+pub fn cma_alloc_pages(
+    num_pages: usize,
+    align_pow2: usize,
+    policy: CmaPolicy,
+) -> AllocResult<usize> {
+    let res = {
+        let mut cma = CMA.lock();
+        if cma.range.is_empty() {
+            Err(AllocError::NoMemory)
+        } else {
+            cma.palloc.alloc_pages(num_pages, align_pow2)
+        }
+    };
+    match (res, policy) {
+        (Err(AllocError::NoMemory), CmaPolicy::Fallback) => {
+            debug!("CMA is full, fall back to the global allocator");
+            global_allocator().alloc_pages(num_pages, align_pow2)
+        }
+        (res, _) => res,
+    }
+}
+
+/// Gives back the pages allocated by [`cma_alloc_pages`], whether they are in
+/// the CMA region or were allocated from the global allocator.
+pub fn cma_dealloc_pages(pos: usize, num_pages: usize) {
+    let mut cma = CMA.lock();
+    if cma.range.contains(&pos) {
+        cma.palloc.dealloc_pages(pos, num_pages);
+    } else {
+        drop(cma);
+        global_allocator().dealloc_pages(pos, num_pages);
+    }
+}
+
+/// Returns the number of used pages and the total number of pages of the CMA
+/// region.
+pub fn cma_pages() -> (usize, usize) {
+    let cma = CMA.lock();
+    (cma.palloc.used_pages(), cma.palloc.total_pages())
+}
@@ -0,0 +1,141 @@
+//! Memory regions added to the page allocator after boot (memory hotplug).
+//!
+//! Each region is managed by its own [`BitmapPageAllocator`], which is placed
+//! in the first pages of the region itself, so that adding memory doesn't
+//! cost any memory of the kernel image, whatever the size of the bitmap is.
+//! Regions larger than a bitmap can manage are split.
+
+use allocator::{AllocError, AllocResult, BaseAllocator, BitmapPageAllocator, PageAllocator};
+use memory_addr::{align_down, align_up};
+
+use crate::PAGE_SIZE;
+
+/// The maximum number of regions that can be added.
+const MAX_REGIONS: usize = 64;
+
+cfg_if::cfg_if! {
+    if #[cfg(feature = "page-alloc-64g")] {
+        const MAX_REGION_SIZE: usize = 64 << 30;
+    } else if #[cfg(feature = "page-alloc-4g")] {
+        const MAX_REGION_SIZE: usize = 4 << 30;
+    } else {
+        const MAX_REGION_SIZE: usize = 256 << 20;
+    }
+}
+
+/// The size of the [`Region`] at the start of each region.
+const META_SIZE: usize = align_up(size_of::<Region>(), PAGE_SIZE);
+
+struct Region {
+    /// The start of the pages, after the metadata.
+    start: usize,
+    end: usize,
+    palloc: BitmapPageAllocator<PAGE_SIZE>,
+}
+
+impl Region {
+    fn contains(&self, pos: usize) -> bool {
+        (self.start..self.end).contains(&pos)
+    }
+}
+
+/// The page allocators of the hotplugged memory regions.
+pub(crate) struct HotplugPages {
+    regions: [Option<&'static mut Region>; MAX_REGIONS],
+}
+
+impl HotplugPages {
+    pub const fn new() -> Self {
+        Self {
+            regions: [const { None }; MAX_REGIONS],
+        }
+    }
+
+    fn regions(&self) -> impl Iterator<Item = &Region> {
+        self.regions.iter().flatten().map(|r| &**r)
+    }
+
+    /// Adds the pages in `[start_vaddr, start_vaddr + size)`.
+    ///
+    /// Either the whole region is added, or nothing.
+    pub fn add_memory(&mut self, start_vaddr: usize, size: usize) -> AllocResult {
+        let start = align_up(start_vaddr, PAGE_SIZE);
+        let end = align_down(start_vaddr + size, PAGE_SIZE);
+        if start >= end || end - start <= META_SIZE {
+            return Err(AllocError::InvalidParam);
+        }
+        if self
+            .regions()
+            .any(|r| r.start - META_SIZE < end && start < r.end)
+        {
+            return Err(AllocError::MemoryOverlap);
+        }
+        let chunk_size = META_SIZE + MAX_REGION_SIZE;
+        let num_chunks = (end - start).div_ceil(chunk_size);
+        let used = self.regions().count();
+        if used + num_chunks > MAX_REGIONS {
+            return Err(AllocError::NoMemory);
+        }
+
+        let mut chunk_start = start;
+        for slot in &mut self.regions[used..used + num_chunks] {
+            let chunk_end = end.min(chunk_start + chunk_size);
+            if chunk_end - chunk_start <= META_SIZE {
+                // too small for any page, only the tail of the region
+                break;
+            }
+            let region = chunk_start as *mut Region;
+            // SAFETY: the memory is given to us by the caller. All the fields
+            // of `Region` are integers, and an empty `BitmapPageAllocator` is
+            // all zeros, the same as `BitmapPageAllocator::new()`, which may
+            // be too large to be built on the stack.
+            let region = unsafe {
+                region.write_bytes(0, 1);
+                &mut *region
+            };
+            region.start = chunk_start + META_SIZE;
+            region.end = chunk_end;
+            region.palloc.init(region.start, chunk_end - region.start);
+            debug!(
+                "add hotplug pages: [{:#x}, {:#x})",
+                region.start, region.end
+            );
+            *slot = Some(region);
+            chunk_start = chunk_end;
+        }
+        Ok(())
+    }
+
+    pub fn alloc_pages(&mut self, num_pages: usize, align_pow2: usize) -> AllocResult<usize> {
+        self.regions
+            .iter_mut()
+            .flatten()
+            .find_map(|r| r.palloc.alloc_pages(num_pages, align_pow2).ok())
+            .ok_or(AllocError::NoMemory)
+    }
+
+    /// Returns `false` if the pages are not in any hotplugged region.
+    pub fn dealloc_pages(&mut self, pos: usize, num_pages: usize) -> bool {
+        match self.regions.iter_mut().flatten().find(|r| r.contains(pos)) {
+            Some(r) => {
+                r.palloc.dealloc_pages(pos, num_pages);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn used_pages(&self) -> usize {
+        self.regions().map(|r| r.palloc.used_pages()).sum()
+    }
+
+    pub fn available_pages(&self) -> usize {
+        self.regions().map(|r| r.palloc.available_pages()).sum()
+    }
+
+    /// Returns the total size of the hotplugged regions in bytes, including
+    /// their metadata.
+    pub fn total_bytes(&self) -> usize {
+        self.regions().map(|r| r.end - r.start + META_SIZE).sum()
+    }
+}
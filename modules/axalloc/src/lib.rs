@@ -11,17 +11,31 @@
 extern crate log;
 extern crate alloc;
 
+#[cfg(feature = "heap-check")]
+mod check;
+mod cma;
+mod hotplug;
 mod page;
+#[cfg(feature = "track")]
+mod track;
 
-use allocator::{AllocResult, BaseAllocator, BitmapPageAllocator, ByteAllocator, PageAllocator};
+use allocator::{
+    AllocError, AllocResult, BaseAllocator, BitmapPageAllocator, ByteAllocator, PageAllocator,
+};
 use core::alloc::{GlobalAlloc, Layout};
 use core::ptr::NonNull;
+use core::sync::atomic::{AtomicPtr, Ordering};
 use kspin::SpinNoIrq;
 
+use self::hotplug::HotplugPages;
+
 const PAGE_SIZE: usize = 0x1000;
 const MIN_HEAP_SIZE: usize = 0x8000; // 32 K
 
+pub use cma::{CmaPolicy, cma_alloc_pages, cma_dealloc_pages, cma_init, cma_pages};
 pub use page::GlobalPage;
+#[cfg(feature = "track")]
+pub use track::{AllocSite, live_allocations};
 
 cfg_if::cfg_if! {
     if #[cfg(feature = "slab")] {
@@ -46,10 +60,14 @@ cfg_if::cfg_if! {
 /// Currently, [`TlsfByteAllocator`] is used as the byte allocator, while
 /// [`BitmapPageAllocator`] is used as the page allocator.
 ///
+/// Memory discovered after boot can be added to the page allocator with
+/// [`add_hotplug_memory`](Self::add_hotplug_memory).
+///
 /// [`TlsfByteAllocator`]: allocator::TlsfByteAllocator
 pub struct GlobalAllocator {
     balloc: SpinNoIrq<DefaultByteAllocator>,
     palloc: SpinNoIrq<BitmapPageAllocator<PAGE_SIZE>>,
+    hotplug: SpinNoIrq<HotplugPages>,
 }
 
 impl GlobalAllocator {
@@ -58,6 +76,7 @@ impl GlobalAllocator {
         Self {
             balloc: SpinNoIrq::new(DefaultByteAllocator::new()),
             palloc: SpinNoIrq::new(BitmapPageAllocator::new()),
+            hotplug: SpinNoIrq::new(HotplugPages::new()),
         }
     }
 
@@ -96,13 +115,36 @@ impl GlobalAllocator {
         self.balloc.lock().add_memory(start_vaddr, size)
     }
 
+    /// Adds the given region to the page allocator, after [`init`].
+    ///
+    /// It's used for memory hotplug, when more memory is discovered at
+    /// runtime. The region is managed by its own page allocator, whose
+    /// bitmap is placed in the first pages of the region. It can't be
+    /// removed later.
+    ///
+    /// [`init`]: GlobalAllocator::init
+    pub fn add_hotplug_memory(&self, start_vaddr: usize, size: usize) -> AllocResult {
+        self.hotplug.lock().add_memory(start_vaddr, size)
+    }
+
     /// Allocate arbitrary number of bytes. Returns the left bound of the
     /// allocated region.
     ///
     /// It firstly tries to allocate from the byte allocator. If there is no
     /// memory, it asks the page allocator for more memory and adds it to the
     /// byte allocator.
+    ///
+    /// With the `heap-check` feature, allocations are surrounded by redzones
+    /// and quarantined after being freed, so that out-of-bounds writes,
+    /// writes after free and double frees are caught.
     pub fn alloc(&self, layout: Layout) -> AllocResult<NonNull<u8>> {
+        #[cfg(feature = "heap-check")]
+        return check::alloc(self, layout);
+        #[cfg(not(feature = "heap-check"))]
+        self.alloc_bytes(layout)
+    }
+
+    fn alloc_bytes(&self, layout: Layout) -> AllocResult<NonNull<u8>> {
         // simple two-level allocator: if no heap memory, allocate from the page allocator.
         let mut balloc = self.balloc.lock();
         loop {
@@ -133,6 +175,13 @@ impl GlobalAllocator {
     ///
     /// [`alloc`]: GlobalAllocator::alloc
     pub fn dealloc(&self, pos: NonNull<u8>, layout: Layout) {
+        #[cfg(feature = "heap-check")]
+        check::dealloc(self, pos, layout);
+        #[cfg(not(feature = "heap-check"))]
+        self.dealloc_bytes(pos, layout)
+    }
+
+    fn dealloc_bytes(&self, pos: NonNull<u8>, layout: Layout) {
         self.balloc.lock().dealloc(pos, layout)
     }
 
@@ -142,8 +191,14 @@ impl GlobalAllocator {
     ///
     /// `align_pow2` must be a power of 2, and the returned region bound will be
     /// aligned to it.
+    ///
+    /// The hotplugged memory is used only when the initial region is full.
     pub fn alloc_pages(&self, num_pages: usize, align_pow2: usize) -> AllocResult<usize> {
-        self.palloc.lock().alloc_pages(num_pages, align_pow2)
+        let res = self.palloc.lock().alloc_pages(num_pages, align_pow2);
+        match res {
+            Err(AllocError::NoMemory) => self.hotplug.lock().alloc_pages(num_pages, align_pow2),
+            res => res,
+        }
     }
 
     /// Gives back the allocated pages starts from `pos` to the page allocator.
@@ -154,7 +209,9 @@ impl GlobalAllocator {
     ///
     /// [`alloc_pages`]: GlobalAllocator::alloc_pages
     pub fn dealloc_pages(&self, pos: usize, num_pages: usize) {
-        self.palloc.lock().dealloc_pages(pos, num_pages)
+        if !self.hotplug.lock().dealloc_pages(pos, num_pages) {
+            self.palloc.lock().dealloc_pages(pos, num_pages)
+        }
     }
 
     /// Returns the number of allocated bytes in the byte allocator.
@@ -169,18 +226,46 @@ impl GlobalAllocator {
 
     /// Returns the number of allocated pages in the page allocator.
     pub fn used_pages(&self) -> usize {
-        self.palloc.lock().used_pages()
+        self.palloc.lock().used_pages() + self.hotplug.lock().used_pages()
     }
 
     /// Returns the number of available pages in the page allocator.
     pub fn available_pages(&self) -> usize {
-        self.palloc.lock().available_pages()
+        self.palloc.lock().available_pages() + self.hotplug.lock().available_pages()
+    }
+
+    /// Returns the total size of the hotplugged memory in bytes.
+    pub fn hotplugged_bytes(&self) -> usize {
+        self.hotplug.lock().total_bytes()
+    }
+}
+
+/// A function called with the size of each allocation made through
+/// [`GlobalAlloc`], negated when it's freed, see [`set_usage_hook`].
+pub type UsageHook = fn(isize);
+
+static USAGE_HOOK: AtomicPtr<()> = AtomicPtr::new(core::ptr::null_mut());
+
+/// Sets the function called at each allocation and deallocation made through
+/// [`GlobalAlloc`], e.g. to account the memory used by each task.
+///
+/// The hook must not allocate memory.
+pub fn set_usage_hook(hook: UsageHook) {
+    USAGE_HOOK.store(hook as *mut (), Ordering::Release);
+}
+
+fn call_usage_hook(delta: isize) {
+    let hook = USAGE_HOOK.load(Ordering::Acquire);
+    if !hook.is_null() {
+        let hook: UsageHook = unsafe { core::mem::transmute(hook) };
+        hook(delta);
     }
 }
 
 unsafe impl GlobalAlloc for GlobalAllocator {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
         if let Ok(ptr) = GlobalAllocator::alloc(self, layout) {
+            call_usage_hook(layout.size() as isize);
             ptr.as_ptr()
         } else {
             alloc::alloc::handle_alloc_error(layout)
@@ -188,7 +273,8 @@ unsafe impl GlobalAlloc for GlobalAllocator {
     }
 
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
-        GlobalAllocator::dealloc(self, NonNull::new(ptr).expect("dealloc null ptr"), layout)
+        GlobalAllocator::dealloc(self, NonNull::new(ptr).expect("dealloc null ptr"), layout);
+        call_usage_hook(-(layout.size() as isize));
     }
 }
 
@@ -231,3 +317,19 @@ pub fn global_add_memory(start_vaddr: usize, size: usize) -> AllocResult {
     );
     GLOBAL_ALLOCATOR.add_memory(start_vaddr, size)
 }
+
+/// Adds the given memory region, discovered after boot, to the global
+/// allocator.
+///
+/// Unlike [`global_add_memory`], the region is added to the page allocator,
+/// so it can be used for page allocations as well as for the heap. It must
+/// be mapped, and users should ensure that it's valid and not being used by
+/// others.
+pub fn global_add_hotplug_memory(start_vaddr: usize, size: usize) -> AllocResult {
+    info!(
+        "add a hotplugged memory region to global allocator: [{:#x}, {:#x})",
+        start_vaddr,
+        start_vaddr + size
+    );
+    GLOBAL_ALLOCATOR.add_hotplug_memory(start_vaddr, size)
+}
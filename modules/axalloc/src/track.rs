@@ -0,0 +1,97 @@
+//! Tracking of live allocations by call site, to find memory leaks.
+//!
+//! It's built on the heap checker: the backtrace recorded when an allocation
+//! is made identifies its call site, and the index of the site is kept in the
+//! header of the allocation, so that it can be accounted when it's freed. A
+//! site that keeps growing on a long-running system is likely to leak.
+
+use alloc::vec::Vec;
+
+use kspin::SpinNoIrq;
+
+use crate::check::{BACKTRACE_DEPTH, Backtrace};
+
+/// The maximum number of distinct call sites that can be tracked.
+const MAX_SITES: usize = 1024;
+
+/// Live allocations made from the same call site.
+#[derive(Debug, Clone, Copy)]
+pub struct AllocSite {
+    /// The return addresses of the call site, innermost first.
+    pub backtrace: [usize; BACKTRACE_DEPTH],
+    /// The number of live allocations.
+    pub count: usize,
+    /// The total size of the live allocations in bytes.
+    pub bytes: usize,
+}
+
+#[derive(Clone, Copy)]
+struct Entry {
+    used: bool,
+    site: AllocSite,
+}
+
+const EMPTY: Entry = Entry {
+    used: false,
+    site: AllocSite {
+        backtrace: [0; BACKTRACE_DEPTH],
+        count: 0,
+        bytes: 0,
+    },
+};
+
+/// An open-addressing hash table. Entries are never removed, so a site with
+/// no live allocations is still found by later lookups.
+static SITES: SpinNoIrq<[Entry; MAX_SITES]> = SpinNoIrq::new([EMPTY; MAX_SITES]);
+
+fn hash(bt: &Backtrace) -> usize {
+    bt.iter().fold(0, |h: usize, &ra| {
+        (h.rotate_left(5) ^ ra).wrapping_mul(0x9e37_79b9)
+    })
+}
+
+/// Accounts a new allocation. Returns the index of its site plus one, or 0 if
+/// the table is full.
+pub(crate) fn add(bt: &Backtrace, size: usize) -> usize {
+    let mut sites = SITES.lock();
+    let start = hash(bt) % MAX_SITES;
+    for i in 0..MAX_SITES {
+        let idx = (start + i) % MAX_SITES;
+        let entry = &mut sites[idx];
+        if !entry.used {
+            entry.used = true;
+            entry.site.backtrace = *bt;
+        } else if entry.site.backtrace != *bt {
+            continue;
+        }
+        entry.site.count += 1;
+        entry.site.bytes += size;
+        return idx + 1;
+    }
+    0
+}
+
+/// Accounts a freed allocation with the site returned by [`add`].
+pub(crate) fn remove(site: usize, size: usize) {
+    if site != 0 {
+        let entry = &mut SITES.lock()[site - 1];
+        entry.site.count -= 1;
+        entry.site.bytes -= size;
+    }
+}
+
+/// Returns the call sites with live allocations, the ones holding the most
+/// bytes first.
+pub fn live_allocations() -> Vec<AllocSite> {
+    // The vector can't be allocated with the lock held, as the allocation
+    // is tracked too. Leave some room for the sites added in between.
+    let nr_sites = SITES.lock().iter().filter(|e| e.site.count > 0).count();
+    let mut result = Vec::with_capacity(nr_sites + 16);
+    for entry in SITES.lock().iter() {
+        if entry.site.count > 0 && result.len() < result.capacity() {
+            result.push(entry.site);
+        }
+    }
+    result.sort_unstable_by(|a, b| b.bytes.cmp(&a.bytes));
+    result
+}
@@ -1,6 +1,7 @@
 //! [ArceOS](https://github.com/arceos-org/arceos) graphics module.
 //!
-//! Currently only supports direct writing to the framebuffer.
+//! Currently only supports direct writing to the framebuffer, whose resolution
+//! can be changed.
 
 #![no_std]
 
@@ -11,6 +12,7 @@ extern crate log;
 pub use axdriver_display::DisplayInfo;
 
 use axdriver::{AxDeviceContainer, prelude::*};
+use axerrno::{AxError, AxResult};
 use axsync::Mutex;
 use lazyinit::LazyInit;
 
@@ -34,3 +36,31 @@ pub fn framebuffer_info() -> DisplayInfo {
 pub fn framebuffer_flush() {
     MAIN_DISPLAY.lock().flush().unwrap();
 }
+
+/// Gets the resolution the display prefers, e.g. the size of the window.
+pub fn preferred_resolution() -> AxResult<(u32, u32)> {
+    MAIN_DISPLAY
+        .lock()
+        .preferred_resolution()
+        .map_err(as_ax_err)
+}
+
+/// Changes the resolution of the display, and returns the new framebuffer
+/// information.
+///
+/// The framebuffer is reallocated, so the old one must not be used anymore.
+pub fn framebuffer_set_resolution(width: u32, height: u32) -> AxResult<DisplayInfo> {
+    let mut display = MAIN_DISPLAY.lock();
+    display.set_resolution(width, height).map_err(as_ax_err)?;
+    Ok(display.info())
+}
+
+fn as_ax_err(err: DevError) -> AxError {
+    match err {
+        DevError::InvalidParam => AxError::InvalidInput,
+        DevError::NoMemory => AxError::NoMemory,
+        DevError::Unsupported => AxError::Unsupported,
+        DevError::BadState => AxError::BadState,
+        _ => AxError::Io,
+    }
+}
@@ -1,100 +1,57 @@
 //! [ArceOS](https://github.com/arceos-org/arceos) global DMA allocator.
+//!
+//! It's a wrapper of [`axhal::dma`] for the users allocating the DMA memory
+//! by [`Layout`]s, e.g. the drivers outside ArceOS.
 
 #![no_std]
 
-extern crate alloc;
-
-mod dma;
-
 use core::{alloc::Layout, ptr::NonNull};
 
-use allocator::AllocResult;
-use memory_addr::PhysAddr;
-
-use self::dma::ALLOCATOR;
+use allocator::{AllocError, AllocResult};
+use axhal::dma::DmaBuffer;
+use memory_addr::PAGE_SIZE_4K;
 
-/// Converts a physical address to a bus address.
-///
-/// It assumes that there is a linear mapping with the offset
-/// [`axconfig::plat::PHYS_BUS_OFFSET`], that maps all the physical memory
-/// to the virtual space at the address plus the offset. So we have
-/// `baddr = paddr + PHYS_BUS_OFFSET`.
-#[inline]
-pub const fn phys_to_bus(paddr: PhysAddr) -> BusAddr {
-    BusAddr::new((paddr.as_usize() + axconfig::plat::PHYS_BUS_OFFSET) as u64)
-}
+pub use axhal::dma::{BusAddr, phys_to_bus};
 
 /// Allocates **coherent** memory that meets Direct Memory Access (DMA)
 /// requirements.
 ///
-/// This function allocates a block of memory through the global allocator. The
-/// memory pages must be contiguous, undivided, and have consistent read and
-/// write access.
+/// The memory is physically contiguous whole pages, filled with zero, see
+/// [`axhal::dma::alloc_coherent`].
 ///
 /// - `layout`: The memory layout, which describes the size and alignment
-///   requirements of the requested memory.
+///   requirements of the requested memory. The alignment is at most 4K.
 ///
 /// Returns an [`DMAInfo`] structure containing details about the allocated
 /// memory, such as the starting address and size. If it's not possible to
-/// allocate memory meeting the criteria, returns [`None`].
+/// allocate memory meeting the criteria, returns an error.
 ///
 /// # Safety
 ///
-/// This function is unsafe because it directly interacts with the global
-/// allocator, which can potentially cause memory leaks or other issues if not
-/// used correctly.
+/// The memory must be freed by [`dealloc_coherent`] with the same `layout`.
 pub unsafe fn alloc_coherent(layout: Layout) -> AllocResult<DMAInfo> {
-    ALLOCATOR.lock().alloc_coherent(layout)
+    if layout.align() > PAGE_SIZE_4K {
+        return Err(AllocError::InvalidParam);
+    }
+    let buf = axhal::dma::alloc_coherent(layout.size()).ok_or(AllocError::NoMemory)?;
+    let bus_addr = buf.bus_addr();
+    Ok(DMAInfo {
+        cpu_addr: buf.into_raw(),
+        bus_addr,
+    })
 }
 
 /// Frees coherent memory previously allocated.
 ///
-/// This function releases the memory block that was previously allocated and
-/// marked as coherent. It ensures proper deallocation and management of resources
-/// associated with the memory block.
-///
 /// - `dma_info`: An instance of [`DMAInfo`] containing the details of the memory
 ///   block to be freed, such as its starting address and size.
 ///
 /// # Safety
 ///
-/// This function is unsafe because it directly interacts with the global allocator,
-/// which can potentially cause memory leaks or other issues if not used correctly.
+/// `dma` must be allocated by [`alloc_coherent`] with the same `layout`, and
+/// be freed only once.
 pub unsafe fn dealloc_coherent(dma: DMAInfo, layout: Layout) {
-    ALLOCATOR.lock().dealloc_coherent(dma, layout)
-}
-
-/// A bus memory address.
-///
-/// It's a wrapper type around an [`u64`].
-#[repr(transparent)]
-#[derive(Copy, Clone, Default, Ord, PartialOrd, Eq, PartialEq)]
-pub struct BusAddr(u64);
-
-impl BusAddr {
-    /// Converts an [`u64`] to a physical address.
-    pub const fn new(addr: u64) -> Self {
-        Self(addr)
-    }
-
-    /// Converts the address to an [`u64`].
-    pub const fn as_u64(self) -> u64 {
-        self.0
-    }
-}
-
-impl From<u64> for BusAddr {
-    fn from(value: u64) -> Self {
-        Self::new(value)
-    }
-}
-
-impl core::fmt::Debug for BusAddr {
-    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        f.debug_tuple("BusAddr")
-            .field(&format_args!("{:#X}", self.0))
-            .finish()
-    }
+    drop(unsafe { DmaBuffer::from_raw(dma.cpu_addr, layout.size(), None) });
 }
 
 /// Represents information related to a DMA operation.
@@ -1,6 +1,8 @@
 const NET_DEV_FEATURES: &[&str] = &["fxmac", "ixgbe", "virtio-net"];
 const BLOCK_DEV_FEATURES: &[&str] = &["ramdisk", "bcm2835-sdhci", "virtio-blk"];
 const DISPLAY_DEV_FEATURES: &[&str] = &["virtio-gpu"];
+const CONSOLE_DEV_FEATURES: &[&str] = &["virtio-console"];
+const NINEP_DEV_FEATURES: &[&str] = &["virtio-9p"];
 
 fn make_cfg_values(str_list: &[&str]) -> String {
     str_list
@@ -36,6 +38,8 @@ fn main() {
         ("net", NET_DEV_FEATURES),
         ("block", BLOCK_DEV_FEATURES),
         ("display", DISPLAY_DEV_FEATURES),
+        ("console", CONSOLE_DEV_FEATURES),
+        ("ninep", NINEP_DEV_FEATURES),
     ] {
         if !has_feature(dev_kind) {
             continue;
@@ -72,4 +76,12 @@ fn main() {
         "cargo::rustc-check-cfg=cfg(display_dev, values({}, \"dummy\"))",
         make_cfg_values(DISPLAY_DEV_FEATURES)
     );
+    println!(
+        "cargo::rustc-check-cfg=cfg(console_dev, values({}, \"dummy\"))",
+        make_cfg_values(CONSOLE_DEV_FEATURES)
+    );
+    println!(
+        "cargo::rustc-check-cfg=cfg(ninep_dev, values({}, \"dummy\"))",
+        make_cfg_values(NINEP_DEV_FEATURES)
+    );
 }
@@ -2,3 +2,5 @@
 mod mmio;
 #[cfg(bus = "pci")]
 mod pci;
+#[cfg(all(bus = "pci", feature = "irq"))]
+pub(crate) mod msi;
@@ -0,0 +1,133 @@
+//! MSI and MSI-X of PCI devices, see [`axhal::irq::msi`].
+
+use axdriver_base::{DevError, DevResult};
+use axdriver_pci::{BarInfo, DeviceFunction, PciRoot};
+use axhal::irq::{IrqHandler, msi};
+use axhal::mem::phys_to_virt;
+
+const PCI_CAP_ID_MSI: u8 = 0x05;
+const PCI_CAP_ID_MSIX: u8 = 0x11;
+
+// MSI capability, message control (upper half of the first dword).
+const MSI_CTRL_ENABLE: u32 = 1 << 16;
+const MSI_CTRL_MULTI_ENABLE_MASK: u32 = 0b111 << 20;
+const MSI_CTRL_64BIT: u32 = 1 << 23;
+
+// MSI-X capability, message control (upper half of the first dword).
+const MSIX_CTRL_TABLE_SIZE_MASK: u32 = 0x7ff << 16;
+const MSIX_CTRL_FUNCTION_MASK: u32 = 1 << 30;
+const MSIX_CTRL_ENABLE: u32 = 1 << 31;
+const MSIX_TABLE_BIR_MASK: u32 = 0b111;
+
+const MSIX_ENTRY_SIZE: usize = 16;
+const MSIX_ENTRY_VECTOR_CTRL_MASKED: u32 = 1;
+
+fn find_capability(root: &PciRoot, bdf: DeviceFunction, id: u8) -> Option<u8> {
+    root.capabilities(bdf)
+        .find(|cap| cap.id == id)
+        .map(|cap| cap.offset)
+}
+
+/// Allocates an IRQ handled by `handler` on the CPU `cpu_id`, and returns it
+/// with its message.
+fn alloc_irq(handler: IrqHandler, cpu_id: usize) -> DevResult<(usize, msi::MsiMessage)> {
+    let irq = msi::alloc_irq().ok_or(DevError::NoMemory)?;
+    if !axhal::irq::register_handler(irq, handler) {
+        msi::free_irq(irq);
+        return Err(DevError::BadState);
+    }
+    Ok((irq, msi::message_to(irq, cpu_id)))
+}
+
+/// Returns the number of vectors in the MSI-X table of the device, or `None`
+/// if it has no MSI-X capability.
+pub(crate) fn msix_table_size(root: &PciRoot, bdf: DeviceFunction) -> Option<u16> {
+    let cap = find_capability(root, bdf, PCI_CAP_ID_MSIX)?;
+    let ctrl = root.config_read_word(bdf, cap);
+    Some(((ctrl & MSIX_CTRL_TABLE_SIZE_MASK) >> 16) as u16 + 1)
+}
+
+/// Enables MSI-X on the device, with the first `irqs.len()` vectors of its
+/// table.
+///
+/// Each vector `i` is given an IRQ handled by `handler` on the CPU
+/// `cpu_of(i)`, stored in `irqs`. The IRQs stay allocated as long as the
+/// device lives.
+pub(crate) fn enable_msix(
+    root: &mut PciRoot,
+    bdf: DeviceFunction,
+    irqs: &mut [usize],
+    handler: IrqHandler,
+    cpu_of: impl Fn(usize) -> usize,
+) -> DevResult {
+    let cap = find_capability(root, bdf, PCI_CAP_ID_MSIX).ok_or(DevError::Unsupported)?;
+    let ctrl = root.config_read_word(bdf, cap);
+    let table_size = ((ctrl & MSIX_CTRL_TABLE_SIZE_MASK) >> 16) as usize + 1;
+    if irqs.is_empty() || irqs.len() > table_size {
+        return Err(DevError::InvalidParam);
+    }
+
+    // The table is in the memory space of a BAR, mapped with the others.
+    let table = root.config_read_word(bdf, cap + 4);
+    let bir = (table & MSIX_TABLE_BIR_MASK) as u8;
+    let table_offset = (table & !MSIX_TABLE_BIR_MASK) as usize;
+    let bar_addr = match root.bar_info(bdf, bir) {
+        Ok(BarInfo::Memory { address, .. }) if address != 0 => address as usize,
+        _ => return Err(DevError::BadState),
+    };
+    let table_vaddr = phys_to_virt((bar_addr + table_offset).into()).as_usize();
+
+    // Mask the whole function while the table is written.
+    root.config_write_word(bdf, cap, ctrl | MSIX_CTRL_ENABLE | MSIX_CTRL_FUNCTION_MASK);
+    for (i, irq) in irqs.iter_mut().enumerate() {
+        let (new_irq, message) = alloc_irq(handler, cpu_of(i))?;
+        *irq = new_irq;
+        let entry = (table_vaddr + i * MSIX_ENTRY_SIZE) as *mut u32;
+        unsafe {
+            entry.write_volatile(message.address as u32);
+            entry.add(1).write_volatile((message.address >> 32) as u32);
+            entry.add(2).write_volatile(message.data);
+            let vector_ctrl = entry.add(3).read_volatile();
+            entry
+                .add(3)
+                .write_volatile(vector_ctrl & !MSIX_ENTRY_VECTOR_CTRL_MASKED);
+        }
+        debug!("  MSI-X vector {}: IRQ {} on CPU {}", i, new_irq, cpu_of(i));
+    }
+    root.config_write_word(
+        bdf,
+        cap,
+        (ctrl | MSIX_CTRL_ENABLE) & !MSIX_CTRL_FUNCTION_MASK,
+    );
+    Ok(())
+}
+
+/// Enables MSI on the device, with a single message, and returns its IRQ,
+/// handled by `handler`.
+///
+/// The IRQ stays allocated as long as the device lives.
+#[allow(dead_code)]
+pub(crate) fn enable_msi(
+    root: &mut PciRoot,
+    bdf: DeviceFunction,
+    handler: IrqHandler,
+) -> DevResult<usize> {
+    let cap = find_capability(root, bdf, PCI_CAP_ID_MSI).ok_or(DevError::Unsupported)?;
+    let ctrl = root.config_read_word(bdf, cap);
+    let (irq, message) = alloc_irq(handler, 0)?;
+    if ctrl & MSI_CTRL_64BIT != 0 {
+        root.config_write_word(bdf, cap + 4, message.address as u32);
+        root.config_write_word(bdf, cap + 8, (message.address >> 32) as u32);
+        root.config_write_word(bdf, cap + 12, message.data);
+    } else {
+        root.config_write_word(bdf, cap + 4, message.address as u32);
+        root.config_write_word(bdf, cap + 8, message.data);
+    }
+    root.config_write_word(
+        bdf,
+        cap,
+        (ctrl & !MSI_CTRL_MULTI_ENABLE_MASK) | MSI_CTRL_ENABLE,
+    );
+    debug!("  MSI: IRQ {}", irq);
+    Ok(irq)
+}
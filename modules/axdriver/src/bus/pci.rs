@@ -82,6 +82,18 @@ fn config_pci_device(
     Ok(())
 }
 
+/// Attaches the device to the kernel domain of the IOMMU, so that its DMA to
+/// the memory shared by the drivers is allowed.
+#[cfg(feature = "iommu")]
+fn attach_iommu(bdf: DeviceFunction) {
+    use axhal::iommu::DeviceId;
+
+    let dev = DeviceId::pci(bdf.bus, bdf.device, bdf.function);
+    if let Err(e) = axhal::iommu::attach_kernel(dev) {
+        warn!("failed to attach PCI device at {} to the IOMMU: {}", bdf, e);
+    }
+}
+
 impl AllDevices {
     pub(crate) fn probe_bus_devices(&mut self) {
         let base_vaddr = phys_to_virt(axconfig::devices::PCI_ECAM_BASE.into());
@@ -99,18 +111,22 @@ impl AllDevices {
                     continue;
                 }
                 match config_pci_device(&mut root, bdf, &mut allocator) {
-                    Ok(_) => for_each_drivers!(type Driver, {
-                        if let Some(dev) = Driver::probe_pci(&mut root, bdf, &dev_info) {
-                            info!(
-                                "registered a new {:?} device at {}: {:?}",
-                                dev.device_type(),
-                                bdf,
-                                dev.device_name(),
-                            );
-                            self.add_device(dev);
-                            continue; // skip to the next device
-                        }
-                    }),
+                    Ok(_) => {
+                        #[cfg(feature = "iommu")]
+                        attach_iommu(bdf);
+                        for_each_drivers!(type Driver, {
+                            if let Some(dev) = Driver::probe_pci(&mut root, bdf, &dev_info) {
+                                info!(
+                                    "registered a new {:?} device at {}: {:?}",
+                                    dev.device_type(),
+                                    bdf,
+                                    dev.device_name(),
+                                );
+                                self.add_device(dev);
+                                continue; // skip to the next device
+                            }
+                        })
+                    }
                     Err(e) => warn!(
                         "failed to enable PCI device at {}({}): {:?}",
                         bdf, dev_info, e
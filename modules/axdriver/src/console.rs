@@ -0,0 +1,30 @@
+//! Common traits and types for console device drivers.
+
+use axdriver_base::{BaseDriverOps, DevResult};
+
+/// Operations that require a console device driver to implement.
+///
+/// A console has one or more ports, each being a separate stream of bytes
+/// (e.g. the ports of a VirtIO console with multiport support). Port 0 always
+/// exists.
+pub trait ConsoleDriverOps: BaseDriverOps {
+    /// Returns the number of ports, that are numbered from 0.
+    fn num_ports(&self) -> usize;
+
+    /// Returns whether the port `port` has been added by the device, and can
+    /// be read and written.
+    fn port_present(&mut self, port: usize) -> bool;
+
+    /// Reads the received bytes of `port` into `buf`.
+    ///
+    /// Returns [`DevError::Again`](axdriver_base::DevError::Again) if nothing
+    /// has been received.
+    fn read(&mut self, port: usize, buf: &mut [u8]) -> DevResult<usize>;
+
+    /// Queues the bytes in `buf` to be sent to `port`, and returns how many
+    /// were queued.
+    ///
+    /// Returns [`DevError::Again`](axdriver_base::DevError::Again) if the
+    /// queue is full.
+    fn write(&mut self, port: usize, buf: &[u8]) -> DevResult<usize>;
+}
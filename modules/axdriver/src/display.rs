@@ -0,0 +1,20 @@
+//! Mode setting of graphics display devices.
+
+use axdriver_base::DevResult;
+use axdriver_display::DisplayDriverOps;
+
+/// Mode setting operations of a display device, on top of the
+/// [`DisplayDriverOps`].
+///
+/// The framebuffer is linear, with 32-bit pixels in the BGRA order.
+pub trait DisplayModeOps: DisplayDriverOps {
+    /// Returns the resolution the display prefers, e.g. the size of the
+    /// window of QEMU.
+    fn preferred_resolution(&mut self) -> DevResult<(u32, u32)>;
+
+    /// Changes the resolution to `width` x `height`.
+    ///
+    /// The framebuffer is reallocated: the one returned by
+    /// [`DisplayDriverOps::info`] before is no longer valid.
+    fn set_resolution(&mut self, width: u32, height: u32) -> DevResult;
+}
@@ -51,6 +51,18 @@ register_display_driver!(
     <virtio::VirtIoGpu as VirtIoDevMeta>::Device
 );
 
+#[cfg(console_dev = "virtio-console")]
+register_console_driver!(
+    <virtio::VirtIoConsole as VirtIoDevMeta>::Driver,
+    <virtio::VirtIoConsole as VirtIoDevMeta>::Device
+);
+
+#[cfg(ninep_dev = "virtio-9p")]
+register_ninep_driver!(
+    <virtio::VirtIo9p as VirtIoDevMeta>::Driver,
+    <virtio::VirtIo9p as VirtIoDevMeta>::Device
+);
+
 cfg_if::cfg_if! {
     if #[cfg(block_dev = "ramdisk")] {
         pub struct RamDiskDriver;
@@ -131,7 +143,9 @@ cfg_if::cfg_if! {
 
 cfg_if::cfg_if! {
     if #[cfg(net_dev = "fxmac")]{
-        use axalloc::global_allocator;
+        use core::ptr::NonNull;
+
+        use axhal::dma::{self, DmaBuffer};
         use axhal::mem::PAGE_SIZE_4K;
 
         #[crate_interface::impl_interface]
@@ -145,17 +159,20 @@ cfg_if::cfg_if! {
             }
 
             fn dma_alloc_coherent(pages: usize) -> (usize, usize) {
-                let Ok(vaddr) = global_allocator().alloc_pages(pages, PAGE_SIZE_4K) else {
+                let Some(buf) = dma::alloc_coherent(pages * PAGE_SIZE_4K) else {
                     error!("failed to alloc pages");
                     return (0, 0);
                 };
-                let paddr = axhal::mem::virt_to_phys((vaddr).into());
+                let paddr = buf.phys_addr();
+                let vaddr = buf.into_raw().as_ptr() as usize;
                 debug!("alloc pages @ vaddr={:#x}, paddr={:#x}", vaddr, paddr);
                 (vaddr, paddr.as_usize())
             }
 
             fn dma_free_coherent(vaddr: usize, pages: usize) {
-                global_allocator().dealloc_pages(vaddr, pages);
+                if let Some(ptr) = NonNull::new(vaddr as *mut u8) {
+                    drop(unsafe { DmaBuffer::from_raw(ptr, pages * PAGE_SIZE_4K, None) });
+                }
             }
 
             fn dma_request_irq(_irq: usize, _handler: fn()) {
@@ -66,6 +66,15 @@ cfg_if! {
                 Err(DevError::Unsupported)
             }
         }
+
+        impl DisplayModeOps for DummyDisplayDev {
+            fn preferred_resolution(&mut self) -> DevResult<(u32, u32)> {
+                Err(DevError::Unsupported)
+            }
+            fn set_resolution(&mut self, _width: u32, _height: u32) -> DevResult {
+                Err(DevError::Unsupported)
+            }
+        }
     }
 }
 
@@ -100,3 +109,64 @@ cfg_if! {
         }
     }
 }
+
+cfg_if! {
+    if #[cfg(console_dev = "dummy")] {
+        pub struct DummyConsoleDev;
+        pub struct DummyConsoleDriver;
+        register_console_driver!(DummyConsoleDriver, DummyConsoleDev);
+
+        impl BaseDriverOps for DummyConsoleDev {
+            fn device_type(&self) -> DeviceType {
+                DeviceType::Char
+            }
+            fn device_name(&self) -> &str {
+                "dummy-console"
+            }
+        }
+
+        impl ConsoleDriverOps for DummyConsoleDev {
+            fn num_ports(&self) -> usize {
+                0
+            }
+            fn port_present(&mut self, _: usize) -> bool {
+                false
+            }
+            fn read(&mut self, _: usize, _: &mut [u8]) -> DevResult<usize> {
+                Err(DevError::Unsupported)
+            }
+            fn write(&mut self, _: usize, _: &[u8]) -> DevResult<usize> {
+                Err(DevError::Unsupported)
+            }
+        }
+    }
+}
+
+cfg_if! {
+    if #[cfg(ninep_dev = "dummy")] {
+        pub struct DummyNinePDev;
+        pub struct DummyNinePDriver;
+        register_ninep_driver!(DummyNinePDriver, DummyNinePDev);
+
+        impl BaseDriverOps for DummyNinePDev {
+            fn device_type(&self) -> DeviceType {
+                DeviceType::Char
+            }
+            fn device_name(&self) -> &str {
+                "dummy-9p"
+            }
+        }
+
+        impl NinePDriverOps for DummyNinePDev {
+            fn mount_tag(&self) -> &str {
+                ""
+            }
+            fn max_msg_size(&self) -> usize {
+                0
+            }
+            fn request(&mut self, _: &[u8], _: &mut [u8]) -> DevResult<usize> {
+                Err(DevError::Unsupported)
+            }
+        }
+    }
+}
@@ -8,8 +8,9 @@
 //! driver they want.
 //!
 //! For each device category (i.e., net, block, display, etc.), an unified type
-//! is used to represent all devices in that category. Currently, there are 3
-//! categories: [`AxNetDevice`], [`AxBlockDevice`], and [`AxDisplayDevice`].
+//! is used to represent all devices in that category. Currently, there are 5
+//! categories: [`AxNetDevice`], [`AxBlockDevice`], [`AxDisplayDevice`],
+//! [`AxConsoleDevice`], and [`AxNinePDevice`].
 //!
 //! # Concepts
 //!
@@ -17,7 +18,7 @@
 //!
 //! - **Static**: The type of all devices is static, it is determined at compile
 //!   time by corresponding cargo features. For example, [`AxNetDevice`] will be
-//!   an alias of [`VirtioNetDev`] if the `virtio-net` feature is enabled. This
+//!   an alias of `VirtIoNetDev` if the `virtio-net` feature is enabled. This
 //!   model provides the best performance as it avoids dynamic dispatch. But on
 //!   limitation, only one device instance is supported for each device category.
 //! - **Dynamic**: All device instance is using [trait objects] and wrapped in a
@@ -33,7 +34,9 @@
 //! | Block | `ramdisk` | A RAM disk that stores data in a vector |
 //! | Block | `virtio-blk` | VirtIO block device |
 //! | Network | `virtio-net` | VirtIO network device |
-//! | Display | `virtio-gpu` | VirtIO graphics device |
+//! | Display | `virtio-gpu` | VirtIO graphics device, with mode setting |
+//! | Console | `virtio-console` | VirtIO console device, with multiple ports |
+//! | 9P | `virtio-9p` | VirtIO 9P transport, to share a directory of the host |
 //!
 //! # Other Cargo Features
 //!
@@ -42,14 +45,20 @@
 //! - `bus-pci`: use PCI bus to probe all PCI devices. This feature is
 //!    enabeld by default.
 //! - `virtio`: use VirtIO devices. This is enabled if any of `virtio-blk`,
-//!   `virtio-net` or `virtio-gpu` is enabled.
+//!   `virtio-net`, `virtio-gpu`, `virtio-console` or `virtio-9p` is enabled.
 //! - `net`: use network devices. This is enabled if any feature of network
 //!    devices is selected. If this feature is enabled without any network device
 //!    features, a dummy struct is used for [`AxNetDevice`].
 //! - `block`: use block storage devices. Similar to the `net` feature.
 //! - `display`: use graphics display devices. Similar to the `net` feature.
+//! - `console`: use console devices. Similar to the `net` feature.
+//! - `ninep`: use 9P transports. Similar to the `net` feature.
+//! - `irq`: give PCI devices MSI or MSI-X vectors. VirtIO devices get one
+//!   per queue, which wake up the CPU waiting for IRQs. The VirtIO NICs also
+//!   tell the network stack when they receive frames (see [`net_irq`]).
+//! - `iommu`: attach the PCI devices to the kernel domain of the IOMMU, and
+//!   map the memory shared with the VirtIO devices in it.
 //!
-//! [`VirtioNetDev`]: axdriver_virtio::VirtIoNetDev
 //! [`Box<dyn NetDriverOps>`]: axdriver_net::NetDriverOps
 //! [trait objects]: https://doc.rust-lang.org/book/ch17-02-trait-objects.html
 //! [dyn]: https://doc.rust-lang.org/std/keyword.dyn.html
@@ -68,8 +77,16 @@ extern crate alloc;
 mod macros;
 
 mod bus;
+#[cfg(feature = "console")]
+pub mod console;
+#[cfg(feature = "display")]
+pub mod display;
 mod drivers;
 mod dummy;
+#[cfg(feature = "net")]
+pub mod net_irq;
+#[cfg(feature = "ninep")]
+pub mod ninep;
 mod structs;
 
 #[cfg(feature = "virtio")]
@@ -77,6 +94,21 @@ mod virtio;
 
 #[cfg(feature = "ixgbe")]
 mod ixgbe;
+#[cfg(feature = "virtio-9p")]
+mod virtio_9p;
+#[cfg(feature = "virtio-console")]
+mod virtio_console;
+#[cfg(feature = "virtio-gpu")]
+mod virtio_gpu;
+#[cfg(feature = "virtio-net")]
+mod virtio_net;
+#[cfg(any(
+    feature = "virtio-console",
+    feature = "virtio-gpu",
+    feature = "virtio-net",
+    feature = "virtio-9p"
+))]
+mod virtqueue;
 
 pub mod prelude;
 
@@ -86,10 +118,14 @@ pub use self::structs::{AxDeviceContainer, AxDeviceEnum};
 
 #[cfg(feature = "block")]
 pub use self::structs::AxBlockDevice;
+#[cfg(feature = "console")]
+pub use self::structs::AxConsoleDevice;
 #[cfg(feature = "display")]
 pub use self::structs::AxDisplayDevice;
 #[cfg(feature = "net")]
 pub use self::structs::AxNetDevice;
+#[cfg(feature = "ninep")]
+pub use self::structs::AxNinePDevice;
 
 /// A structure that contains all device drivers, organized by their category.
 #[derive(Default)]
@@ -103,6 +139,12 @@ pub struct AllDevices {
     /// All graphics device drivers.
     #[cfg(feature = "display")]
     pub display: AxDeviceContainer<AxDisplayDevice>,
+    /// All console device drivers.
+    #[cfg(feature = "console")]
+    pub console: AxDeviceContainer<AxConsoleDevice>,
+    /// All 9P transport drivers.
+    #[cfg(feature = "ninep")]
+    pub ninep: AxDeviceContainer<AxNinePDevice>,
 }
 
 impl AllDevices {
@@ -143,6 +185,10 @@ impl AllDevices {
             AxDeviceEnum::Block(dev) => self.block.push(dev),
             #[cfg(feature = "display")]
             AxDeviceEnum::Display(dev) => self.display.push(dev),
+            #[cfg(feature = "console")]
+            AxDeviceEnum::Console(dev) => self.console.push(dev),
+            #[cfg(feature = "ninep")]
+            AxDeviceEnum::NineP(dev) => self.ninep.push(dev),
         }
     }
 }
@@ -179,6 +225,32 @@ pub fn init_drivers() -> AllDevices {
             debug!("  graphics device {}: {:?}", i, dev.device_name());
         }
     }
+    #[cfg(feature = "console")]
+    {
+        debug!("number of console devices: {}", all_devs.console.len());
+        for (i, dev) in all_devs.console.iter().enumerate() {
+            assert_eq!(dev.device_type(), DeviceType::Char);
+            debug!(
+                "  console device {}: {:?}, {} ports",
+                i,
+                dev.device_name(),
+                dev.num_ports()
+            );
+        }
+    }
+    #[cfg(feature = "ninep")]
+    {
+        debug!("number of 9P transports: {}", all_devs.ninep.len());
+        for (i, dev) in all_devs.ninep.iter().enumerate() {
+            assert_eq!(dev.device_type(), DeviceType::Char);
+            debug!(
+                "  9P transport {}: {:?}, tag {:?}",
+                i,
+                dev.device_name(),
+                dev.mount_tag()
+            );
+        }
+    }
 
     all_devs
 }
@@ -0,0 +1,45 @@
+//! Notifications of the frames received by the NICs, sent from their IRQ
+//! handlers to the network stack.
+//!
+//! The NICs whose RX queues interrupt the CPU are counted, so that the network
+//! stack only waits for the notifications if all of them do, and polls them
+//! otherwise.
+
+use core::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+
+/// The function called when frames are received, a `fn()`.
+static NOTIFIER: AtomicPtr<()> = AtomicPtr::new(core::ptr::null_mut());
+/// The number of NICs whose RX queues interrupt the CPU.
+static RX_IRQ_NICS: AtomicUsize = AtomicUsize::new(0);
+
+/// Sets the function called from the IRQ handlers when a NIC receives frames.
+///
+/// It runs on the CPU the receive queue interrupts, which is the CPU polling
+/// the queue first if the NIC has multiple ones. It runs with IRQs disabled,
+/// so it should be short and must not block.
+pub fn set_rx_notifier(f: fn()) {
+    NOTIFIER.store(f as *mut (), Ordering::Release);
+}
+
+/// Returns the number of NICs which interrupt the CPU when they receive
+/// frames. The others must be polled.
+pub fn num_rx_irq_nics() -> usize {
+    RX_IRQ_NICS.load(Ordering::Acquire)
+}
+
+/// Counts a NIC whose RX queues interrupt the CPU.
+#[allow(dead_code)]
+pub(crate) fn add_rx_irq_nic() {
+    RX_IRQ_NICS.fetch_add(1, Ordering::AcqRel);
+}
+
+/// Calls the function set by [`set_rx_notifier`], if any.
+#[allow(dead_code)]
+pub(crate) fn notify_rx() {
+    let f = NOTIFIER.load(Ordering::Acquire);
+    if !f.is_null() {
+        // Safety: only `fn()`s are stored.
+        let f = unsafe { core::mem::transmute::<*mut (), fn()>(f) };
+        f();
+    }
+}
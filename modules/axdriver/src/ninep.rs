@@ -0,0 +1,23 @@
+//! Common traits and types for 9P transport drivers.
+
+use axdriver_base::{BaseDriverOps, DevResult};
+
+/// Operations that require a 9P transport driver to implement.
+///
+/// The transport only carries the messages of the 9P protocol: a request is
+/// sent to the server (e.g. the host under QEMU), which sends back a response.
+pub trait NinePDriverOps: BaseDriverOps {
+    /// Returns the tag that the server exports the filesystem under, which
+    /// identifies it when mounting.
+    fn mount_tag(&self) -> &str;
+
+    /// Returns the maximum size of a message, either a request or a
+    /// response, that the transport can carry.
+    fn max_msg_size(&self) -> usize;
+
+    /// Sends the request `req` and waits for its response, which is written
+    /// into `resp`.
+    ///
+    /// Returns the size of the response.
+    fn request(&mut self, req: &[u8], resp: &mut [u8]) -> DevResult<usize>;
+}
@@ -2,9 +2,16 @@
 
 pub use axdriver_base::{BaseDriverOps, DevError, DevResult, DeviceType};
 
+#[cfg(feature = "console")]
+pub use {crate::console::ConsoleDriverOps, crate::structs::AxConsoleDevice};
+#[cfg(feature = "display")]
+pub use {
+    crate::display::DisplayModeOps, crate::structs::AxDisplayDevice,
+    axdriver_display::DisplayDriverOps,
+};
+#[cfg(feature = "ninep")]
+pub use {crate::ninep::NinePDriverOps, crate::structs::AxNinePDevice};
 #[cfg(feature = "block")]
 pub use {crate::structs::AxBlockDevice, axdriver_block::BlockDriverOps};
-#[cfg(feature = "display")]
-pub use {crate::structs::AxDisplayDevice, axdriver_display::DisplayDriverOps};
 #[cfg(feature = "net")]
 pub use {crate::structs::AxNetDevice, axdriver_net::NetDriverOps};
@@ -11,7 +11,13 @@ pub type AxNetDevice = Box<dyn NetDriverOps>;
 pub type AxBlockDevice = Box<dyn BlockDriverOps>;
 /// The unified type of the graphics display devices.
 #[cfg(feature = "display")]
-pub type AxDisplayDevice = Box<dyn DisplayDriverOps>;
+pub type AxDisplayDevice = Box<dyn DisplayModeOps>;
+/// The unified type of the console devices.
+#[cfg(feature = "console")]
+pub type AxConsoleDevice = Box<dyn ConsoleDriverOps>;
+/// The unified type of the 9P transports.
+#[cfg(feature = "ninep")]
+pub type AxNinePDevice = Box<dyn NinePDriverOps>;
 
 impl super::AxDeviceEnum {
     /// Constructs a network device.
@@ -28,9 +34,21 @@ impl super::AxDeviceEnum {
 
     /// Constructs a display device.
     #[cfg(feature = "display")]
-    pub fn from_display(dev: impl DisplayDriverOps + 'static) -> Self {
+    pub fn from_display(dev: impl DisplayModeOps + 'static) -> Self {
         Self::Display(Box::new(dev))
     }
+
+    /// Constructs a console device.
+    #[cfg(feature = "console")]
+    pub fn from_console(dev: impl ConsoleDriverOps + 'static) -> Self {
+        Self::Console(Box::new(dev))
+    }
+
+    /// Constructs a 9P transport.
+    #[cfg(feature = "ninep")]
+    pub fn from_ninep(dev: impl NinePDriverOps + 'static) -> Self {
+        Self::NineP(Box::new(dev))
+    }
 }
 
 /// A structure that contains all device drivers of a certain category.
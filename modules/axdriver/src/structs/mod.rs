@@ -18,6 +18,12 @@ pub enum AxDeviceEnum {
     /// Graphic display device.
     #[cfg(feature = "display")]
     Display(AxDisplayDevice),
+    /// Console device.
+    #[cfg(feature = "console")]
+    Console(AxConsoleDevice),
+    /// 9P transport.
+    #[cfg(feature = "ninep")]
+    NineP(AxNinePDevice),
 }
 
 impl BaseDriverOps for AxDeviceEnum {
@@ -31,6 +37,10 @@ impl BaseDriverOps for AxDeviceEnum {
             Self::Block(_) => DeviceType::Block,
             #[cfg(feature = "display")]
             Self::Display(_) => DeviceType::Display,
+            #[cfg(feature = "console")]
+            Self::Console(_) => DeviceType::Char,
+            #[cfg(feature = "ninep")]
+            Self::NineP(_) => DeviceType::Char,
             _ => unreachable!(),
         }
     }
@@ -45,6 +55,10 @@ impl BaseDriverOps for AxDeviceEnum {
             Self::Block(dev) => dev.device_name(),
             #[cfg(feature = "display")]
             Self::Display(dev) => dev.device_name(),
+            #[cfg(feature = "console")]
+            Self::Console(dev) => dev.device_name(),
+            #[cfg(feature = "ninep")]
+            Self::NineP(dev) => dev.device_name(),
             _ => unreachable!(),
         }
     }
@@ -1,9 +1,13 @@
 #[cfg(feature = "block")]
 pub use crate::drivers::AxBlockDevice;
+#[cfg(feature = "console")]
+pub use crate::drivers::AxConsoleDevice;
 #[cfg(feature = "display")]
 pub use crate::drivers::AxDisplayDevice;
 #[cfg(feature = "net")]
 pub use crate::drivers::AxNetDevice;
+#[cfg(feature = "ninep")]
+pub use crate::drivers::AxNinePDevice;
 
 impl super::AxDeviceEnum {
     /// Constructs a network device.
@@ -23,6 +27,18 @@ impl super::AxDeviceEnum {
     pub const fn from_display(dev: AxDisplayDevice) -> Self {
         Self::Display(dev)
     }
+
+    /// Constructs a console device.
+    #[cfg(feature = "console")]
+    pub const fn from_console(dev: AxConsoleDevice) -> Self {
+        Self::Console(dev)
+    }
+
+    /// Constructs a 9P transport.
+    #[cfg(feature = "ninep")]
+    pub const fn from_ninep(dev: AxNinePDevice) -> Self {
+        Self::NineP(dev)
+    }
 }
 
 /// A structure that contains all device drivers of a certain category.
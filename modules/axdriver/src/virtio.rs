@@ -1,11 +1,12 @@
 use core::marker::PhantomData;
 use core::ptr::NonNull;
 
-use axalloc::global_allocator;
 use axdriver_base::{BaseDriverOps, DevResult, DeviceType};
 use axdriver_virtio::{BufferDirection, PhysAddr, VirtIoHal};
-use axhal::mem::{phys_to_virt, virt_to_phys};
+use axhal::dma::{self, DmaBuffer, DmaDirection};
+use axhal::mem::{PAGE_SIZE_4K, VirtAddr, phys_to_virt, virt_to_phys};
 use cfg_if::cfg_if;
+use virtio_drivers::transport::DeviceType as VirtIoDevType;
 
 use crate::{AxDeviceEnum, drivers::DriverProbe};
 
@@ -21,6 +22,9 @@ cfg_if! {
 /// A trait for VirtIO device meta information.
 pub trait VirtIoDevMeta {
     const DEVICE_TYPE: DeviceType;
+    /// The VirtIO device type, for the devices that `axdriver_virtio` doesn't
+    /// probe, whose transports are created here instead.
+    const VIRTIO_TYPE: Option<VirtIoDevType> = None;
 
     type Device: BaseDriverOps;
     type Driver = VirtIoDriver<Self>;
@@ -34,7 +38,8 @@ cfg_if! {
 
         impl VirtIoDevMeta for VirtIoNet {
             const DEVICE_TYPE: DeviceType = DeviceType::Net;
-            type Device = axdriver_virtio::VirtIoNetDev<VirtIoHalImpl, VirtIoTransport, 64>;
+            const VIRTIO_TYPE: Option<VirtIoDevType> = Some(VirtIoDevType::Network);
+            type Device = crate::virtio_net::VirtIoNetDev<VirtIoHalImpl, VirtIoTransport>;
 
             fn try_new(transport: VirtIoTransport) -> DevResult<AxDeviceEnum> {
                 Ok(AxDeviceEnum::from_net(Self::Device::try_new(transport)?))
@@ -64,7 +69,7 @@ cfg_if! {
 
         impl VirtIoDevMeta for VirtIoGpu {
             const DEVICE_TYPE: DeviceType = DeviceType::Display;
-            type Device = axdriver_virtio::VirtIoGpuDev<VirtIoHalImpl, VirtIoTransport>;
+            type Device = crate::virtio_gpu::VirtIoGpuDev<VirtIoHalImpl, VirtIoTransport>;
 
             fn try_new(transport: VirtIoTransport) -> DevResult<AxDeviceEnum> {
                 Ok(AxDeviceEnum::from_display(Self::Device::try_new(transport)?))
@@ -73,6 +78,58 @@ cfg_if! {
     }
 }
 
+cfg_if! {
+    if #[cfg(console_dev = "virtio-console")] {
+        pub struct VirtIoConsole;
+
+        impl VirtIoDevMeta for VirtIoConsole {
+            const DEVICE_TYPE: DeviceType = DeviceType::Char;
+            const VIRTIO_TYPE: Option<VirtIoDevType> = Some(VirtIoDevType::Console);
+            type Device = crate::virtio_console::VirtIoConsoleDev<VirtIoHalImpl, VirtIoTransport>;
+
+            fn try_new(transport: VirtIoTransport) -> DevResult<AxDeviceEnum> {
+                Ok(AxDeviceEnum::from_console(Self::Device::try_new(transport)?))
+            }
+        }
+    }
+}
+
+cfg_if! {
+    if #[cfg(ninep_dev = "virtio-9p")] {
+        pub struct VirtIo9p;
+
+        impl VirtIoDevMeta for VirtIo9p {
+            // there is no device type for 9P transports
+            const DEVICE_TYPE: DeviceType = DeviceType::Char;
+            const VIRTIO_TYPE: Option<VirtIoDevType> = Some(VirtIoDevType::_9P);
+            type Device = crate::virtio_9p::VirtIo9pDev<VirtIoHalImpl, VirtIoTransport>;
+
+            fn try_new(transport: VirtIoTransport) -> DevResult<AxDeviceEnum> {
+                Ok(AxDeviceEnum::from_ninep(Self::Device::try_new(transport)?))
+            }
+        }
+    }
+}
+
+/// Creates the transport of the device at `base_vaddr`, if its type is
+/// `virtio_type`.
+///
+/// `axdriver_virtio` only probes the device types it has drivers for.
+#[cfg(bus = "mmio")]
+fn probe_mmio_transport(
+    base_vaddr: *mut u8,
+    virtio_type: VirtIoDevType,
+) -> Option<VirtIoTransport> {
+    use virtio_drivers::transport::{Transport, mmio::VirtIOHeader};
+
+    let header = NonNull::new(base_vaddr as *mut VirtIOHeader)?;
+    let transport = unsafe { VirtIoTransport::new(header) }.ok()?;
+    if transport.device_type() != virtio_type {
+        return None;
+    }
+    Some(transport)
+}
+
 /// A common driver for all VirtIO devices that implements [`DriverProbe`].
 pub struct VirtIoDriver<D: VirtIoDevMeta + ?Sized>(PhantomData<D>);
 
@@ -80,9 +137,12 @@ impl<D: VirtIoDevMeta> DriverProbe for VirtIoDriver<D> {
     #[cfg(bus = "mmio")]
     fn probe_mmio(mmio_base: usize, mmio_size: usize) -> Option<AxDeviceEnum> {
         let base_vaddr = phys_to_virt(mmio_base.into());
-        if let Some((ty, transport)) =
-            axdriver_virtio::probe_mmio_device(base_vaddr.as_mut_ptr(), mmio_size)
-        {
+        let probed = match D::VIRTIO_TYPE {
+            Some(virtio_type) => probe_mmio_transport(base_vaddr.as_mut_ptr(), virtio_type)
+                .map(|transport| (D::DEVICE_TYPE, transport)),
+            None => axdriver_virtio::probe_mmio_device(base_vaddr.as_mut_ptr(), mmio_size),
+        };
+        if let Some((ty, transport)) = probed {
             if ty == D::DEVICE_TYPE {
                 match D::try_new(transport) {
                     Ok(dev) => return Some(dev),
@@ -110,19 +170,41 @@ impl<D: VirtIoDevMeta> DriverProbe for VirtIoDriver<D> {
         if dev_info.vendor_id != 0x1af4 {
             return None;
         }
-        match (D::DEVICE_TYPE, dev_info.device_id) {
-            (DeviceType::Net, 0x1000) | (DeviceType::Net, 0x1041) => {}
-            (DeviceType::Block, 0x1001) | (DeviceType::Block, 0x1042) => {}
-            (DeviceType::Display, 0x1050) => {}
+        match (D::VIRTIO_TYPE, D::DEVICE_TYPE, dev_info.device_id) {
+            (None, DeviceType::Block, 0x1001) | (None, DeviceType::Block, 0x1042) => {}
+            (None, DeviceType::Display, 0x1050) => {}
+            (Some(VirtIoDevType::Network), _, 0x1000 | 0x1041) => {}
+            (Some(VirtIoDevType::Console), _, 0x1003 | 0x1043) => {}
+            (Some(VirtIoDevType::_9P), _, 0x1009 | 0x1049) => {}
             _ => return None,
         }
 
-        if let Some((ty, transport)) =
-            axdriver_virtio::probe_pci_device::<VirtIoHalImpl>(root, bdf, dev_info)
-        {
+        let probed = match D::VIRTIO_TYPE {
+            // `axdriver_virtio` only probes the device types it has drivers for
+            Some(_) => VirtIoTransport::new::<VirtIoHalImpl>(root, bdf)
+                .ok()
+                .map(|transport| (D::DEVICE_TYPE, transport)),
+            None => axdriver_virtio::probe_pci_device::<VirtIoHalImpl>(root, bdf, dev_info),
+        };
+        if let Some((ty, transport)) = probed {
             if ty == D::DEVICE_TYPE {
                 match D::try_new(transport) {
-                    Ok(dev) => return Some(dev),
+                    Ok(dev) => {
+                        #[cfg(feature = "irq")]
+                        {
+                            #[cfg(feature = "net")]
+                            if D::DEVICE_TYPE == DeviceType::Net {
+                                // each queue pair interrupts the CPU polling it
+                                let pair_cpu = |queue: usize| queue / 2 % axconfig::SMP;
+                                if setup_msix(root, bdf, msix::handle_net_irq, pair_cpu) {
+                                    crate::net_irq::add_rx_irq_nic();
+                                }
+                                return Some(dev);
+                            }
+                            setup_msix(root, bdf, msix::handle_irq, |_| 0);
+                        }
+                        return Some(dev);
+                    }
                     Err(e) => {
                         warn!(
                             "failed to initialize PCI device at {}({}): {:?}",
@@ -137,22 +219,151 @@ impl<D: VirtIoDevMeta> DriverProbe for VirtIoDriver<D> {
     }
 }
 
+#[cfg(all(bus = "pci", feature = "irq"))]
+mod msix {
+    use axdriver_pci::{BarInfo, DeviceFunction, PciRoot};
+    use axhal::mem::phys_to_virt;
+
+    use crate::bus::msi;
+
+    const PCI_CAP_ID_VNDR: u8 = 0x09;
+    const VIRTIO_PCI_CAP_COMMON_CFG: u8 = 1;
+
+    // Offsets in the common configuration structure.
+    const MSIX_CONFIG: usize = 0x10;
+    const NUM_QUEUES: usize = 0x12;
+    const QUEUE_SELECT: usize = 0x16;
+    const QUEUE_MSIX_VECTOR: usize = 0x1a;
+
+    const VIRTIO_MSI_NO_VECTOR: u16 = 0xffff;
+
+    /// The maximum number of vectors of a device.
+    const MAX_VECTORS: usize = 16;
+
+    /// Returns the virtual address of the common configuration structure.
+    fn common_cfg(root: &PciRoot, bdf: DeviceFunction) -> Option<usize> {
+        root.capabilities(bdf)
+            .filter(|cap| cap.id == PCI_CAP_ID_VNDR)
+            .find_map(|cap| {
+                let cfg_type = (root.config_read_word(bdf, cap.offset) >> 24) as u8;
+                if cfg_type != VIRTIO_PCI_CAP_COMMON_CFG {
+                    return None;
+                }
+                let bar = root.config_read_word(bdf, cap.offset + 4) as u8;
+                let offset = root.config_read_word(bdf, cap.offset + 8) as usize;
+                match root.bar_info(bdf, bar) {
+                    Ok(BarInfo::Memory { address, .. }) if address != 0 => {
+                        Some(phys_to_virt((address as usize + offset).into()).as_usize())
+                    }
+                    _ => None,
+                }
+            })
+    }
+
+    fn read_u16(common_cfg: usize, offset: usize) -> u16 {
+        unsafe { ((common_cfg + offset) as *const u16).read_volatile() }
+    }
+
+    fn write_u16(common_cfg: usize, offset: usize, value: u16) {
+        unsafe { ((common_cfg + offset) as *mut u16).write_volatile(value) }
+    }
+
+    /// The drivers poll the queues, so the IRQs only wake up the CPU waiting
+    /// for them.
+    pub(super) fn handle_irq() {
+        trace!("VirtIO IRQ");
+    }
+
+    /// Tells the network stack that frames are received, on the CPU of the
+    /// queue.
+    #[cfg(feature = "net")]
+    pub(super) fn handle_net_irq() {
+        trace!("VirtIO net IRQ");
+        crate::net_irq::notify_rx();
+    }
+
+    /// Gives an MSI-X vector to the configuration changes, and one to each
+    /// queue as long as there are enough, rather than sharing the legacy INTx
+    /// line.
+    ///
+    /// The IRQs are handled by `handler`, the ones of the queue `i` on the CPU
+    /// `queue_cpu(i)` and the others on the primary CPU. Returns whether the
+    /// queues have vectors.
+    ///
+    /// It is done once the device is initialized, as a reset unmaps the
+    /// vectors.
+    pub(super) fn setup_msix(
+        root: &mut PciRoot,
+        bdf: DeviceFunction,
+        handler: fn(),
+        queue_cpu: impl Fn(usize) -> usize,
+    ) -> bool {
+        let Some(common_cfg) = common_cfg(root, bdf) else {
+            return false;
+        };
+        let Some(table_size) = msi::msix_table_size(root, bdf) else {
+            return false;
+        };
+        let num_queues = read_u16(common_cfg, NUM_QUEUES);
+        let num_vectors = (num_queues as usize + 1)
+            .min(table_size as usize)
+            .min(MAX_VECTORS);
+        let mut irqs = [0; MAX_VECTORS];
+        // the vector `i + 1` is the one of the queue `i`, or of the last ones
+        let vector_cpu = |vector: usize| match vector {
+            0 => 0,
+            _ => queue_cpu(vector - 1),
+        };
+        if let Err(e) = msi::enable_msix(root, bdf, &mut irqs[..num_vectors], handler, vector_cpu) {
+            warn!("failed to enable MSI-X for PCI device at {}: {:?}", bdf, e);
+            return false;
+        }
+
+        write_u16(common_cfg, MSIX_CONFIG, 0);
+        if read_u16(common_cfg, MSIX_CONFIG) == VIRTIO_MSI_NO_VECTOR {
+            warn!("PCI device at {}: no MSI-X vector for config changes", bdf);
+        }
+        let mut all_queues = true;
+        for queue in 0..num_queues {
+            let vector = (queue as usize + 1).min(num_vectors - 1) as u16;
+            write_u16(common_cfg, QUEUE_SELECT, queue);
+            write_u16(common_cfg, QUEUE_MSIX_VECTOR, vector);
+            if read_u16(common_cfg, QUEUE_MSIX_VECTOR) == VIRTIO_MSI_NO_VECTOR {
+                warn!("PCI device at {}: no MSI-X vector for queue {}", bdf, queue);
+                all_queues = false;
+            }
+        }
+        info!(
+            "PCI device at {}: {} MSI-X vectors for {} queues",
+            bdf, num_vectors, num_queues
+        );
+        all_queues
+    }
+}
+
+#[cfg(all(bus = "pci", feature = "irq"))]
+use self::msix::setup_msix;
+
+const fn dma_direction(direction: BufferDirection) -> DmaDirection {
+    match direction {
+        BufferDirection::DriverToDevice => DmaDirection::ToDevice,
+        BufferDirection::DeviceToDriver => DmaDirection::FromDevice,
+        BufferDirection::Both => DmaDirection::Bidirectional,
+    }
+}
+
 pub struct VirtIoHalImpl;
 
 unsafe impl VirtIoHal for VirtIoHalImpl {
     fn dma_alloc(pages: usize, _direction: BufferDirection) -> (PhysAddr, NonNull<u8>) {
-        let vaddr = if let Ok(vaddr) = global_allocator().alloc_pages(pages, 0x1000) {
-            vaddr
-        } else {
-            return (0, NonNull::dangling());
-        };
-        let paddr = virt_to_phys(vaddr.into());
-        let ptr = NonNull::new(vaddr as _).unwrap();
-        (paddr.as_usize(), ptr)
+        match dma::alloc_coherent(pages * PAGE_SIZE_4K) {
+            Some(buf) => (buf.phys_addr().as_usize(), buf.into_raw()),
+            None => (0, NonNull::dangling()),
+        }
     }
 
     unsafe fn dma_dealloc(_paddr: PhysAddr, vaddr: NonNull<u8>, pages: usize) -> i32 {
-        global_allocator().dealloc_pages(vaddr.as_ptr() as usize, pages);
+        drop(unsafe { DmaBuffer::from_raw(vaddr, pages * PAGE_SIZE_4K, None) });
         0
     }
 
@@ -162,11 +373,23 @@ unsafe impl VirtIoHal for VirtIoHalImpl {
     }
 
     #[inline]
-    unsafe fn share(buffer: NonNull<[u8]>, _direction: BufferDirection) -> PhysAddr {
-        let vaddr = buffer.as_ptr() as *mut u8 as usize;
-        virt_to_phys(vaddr.into()).into()
+    unsafe fn share(buffer: NonNull<[u8]>, direction: BufferDirection) -> PhysAddr {
+        let vaddr = VirtAddr::from(buffer.as_ptr() as *mut u8 as usize);
+        dma::sync_for_device(vaddr, buffer.len(), dma_direction(direction));
+        // `virtio-drivers` can't fail here, and the device would access an
+        // unmapped buffer silently
+        #[cfg(feature = "iommu")]
+        if let Err(e) = axhal::iommu::map_dma(virt_to_phys(vaddr), buffer.len()) {
+            panic!("failed to map a VirtIO buffer in the IOMMU: {}", e);
+        }
+        virt_to_phys(vaddr).into()
     }
 
     #[inline]
-    unsafe fn unshare(_paddr: PhysAddr, _buffer: NonNull<[u8]>, _direction: BufferDirection) {}
+    unsafe fn unshare(_paddr: PhysAddr, buffer: NonNull<[u8]>, direction: BufferDirection) {
+        let vaddr = VirtAddr::from(buffer.as_ptr() as *mut u8 as usize);
+        #[cfg(feature = "iommu")]
+        axhal::iommu::unmap_dma(virt_to_phys(vaddr), buffer.len());
+        dma::sync_for_cpu(vaddr, buffer.len(), dma_direction(direction));
+    }
 }
@@ -0,0 +1,130 @@
+//! VirtIO 9P transport driver, to share a directory of the host (e.g. QEMU's
+//! `-device virtio-9p-*,mount_tag=...`).
+//!
+//! `axdriver_virtio` has no 9P driver, so the driver sets up its own
+//! [`VirtQueue`]. The device has a single queue, on which each request is
+//! sent followed by a buffer for its response; the requests are sent one at
+//! a time.
+
+use core::mem::size_of;
+use core::ptr::addr_of;
+
+use axdriver_base::{BaseDriverOps, DevError, DevResult, DeviceType};
+use virtio_drivers::transport::{DeviceStatus, Transport};
+use virtio_drivers::{Hal, PAGE_SIZE};
+
+use crate::ninep::NinePDriverOps;
+use crate::virtqueue::VirtQueue;
+
+/// The size of the buffer of each descriptor, which is the maximum size of a
+/// message.
+const BUF_SIZE: usize = 16384;
+/// The maximum length of the mount tag, longer ones are truncated.
+const MAX_TAG_LEN: usize = 64;
+
+const VIRTIO_9P_MOUNT_TAG: u64 = 1 << 0;
+const VIRTIO_F_VERSION_1: u64 = 1 << 32;
+
+const REQUEST_QUEUE: u16 = 0;
+
+#[repr(C)]
+struct NinePConfig {
+    tag_len: u16,
+    // followed by `tag_len` bytes of the tag, not NUL-terminated
+}
+
+/// The VirtIO 9P transport driver.
+pub struct VirtIo9pDev<H: Hal, T: Transport> {
+    transport: T,
+    queue: VirtQueue<H, BUF_SIZE>,
+    tag: [u8; MAX_TAG_LEN],
+    tag_len: usize,
+}
+
+impl<H: Hal, T: Transport> VirtIo9pDev<H, T> {
+    /// Initializes the device, and reads its mount tag.
+    pub fn try_new(mut transport: T) -> DevResult<Self> {
+        transport.set_status(DeviceStatus::empty());
+        transport.set_status(DeviceStatus::ACKNOWLEDGE | DeviceStatus::DRIVER);
+        let features =
+            transport.read_device_features() & (VIRTIO_9P_MOUNT_TAG | VIRTIO_F_VERSION_1);
+        transport.write_driver_features(features);
+        transport.set_status(
+            DeviceStatus::ACKNOWLEDGE | DeviceStatus::DRIVER | DeviceStatus::FEATURES_OK,
+        );
+        transport.set_guest_page_size(PAGE_SIZE as u32);
+
+        let mut tag = [0; MAX_TAG_LEN];
+        let mut tag_len = 0;
+        if features & VIRTIO_9P_MOUNT_TAG != 0 {
+            let config = transport
+                .config_space::<NinePConfig>()
+                .map_err(|_| DevError::Unsupported)?;
+            let len = unsafe { addr_of!((*config.as_ptr()).tag_len).read_volatile() };
+            tag_len = (len as usize).min(MAX_TAG_LEN);
+            let tag_ptr = unsafe { (config.as_ptr() as *const u8).add(size_of::<NinePConfig>()) };
+            for (i, b) in tag[..tag_len].iter_mut().enumerate() {
+                *b = unsafe { tag_ptr.add(i).read_volatile() };
+            }
+        }
+
+        let queue = VirtQueue::new(&mut transport, REQUEST_QUEUE)?;
+        transport.finish_init();
+        Ok(Self {
+            transport,
+            queue,
+            tag,
+            tag_len,
+        })
+    }
+}
+
+impl<H: Hal, T: Transport> BaseDriverOps for VirtIo9pDev<H, T> {
+    fn device_name(&self) -> &str {
+        "virtio-9p"
+    }
+
+    fn device_type(&self) -> DeviceType {
+        DeviceType::Char
+    }
+}
+
+impl<H: Hal, T: Transport> NinePDriverOps for VirtIo9pDev<H, T> {
+    fn mount_tag(&self) -> &str {
+        core::str::from_utf8(&self.tag[..self.tag_len]).unwrap_or_default()
+    }
+
+    fn max_msg_size(&self) -> usize {
+        BUF_SIZE
+    }
+
+    fn request(&mut self, req: &[u8], resp: &mut [u8]) -> DevResult<usize> {
+        if req.len() > BUF_SIZE {
+            return Err(DevError::InvalidParam);
+        }
+        // the requests are sent one at a time, so all the descriptors are free
+        let queue = &mut self.queue;
+        let req_id = queue.alloc().ok_or(DevError::BadState)?;
+        let resp_id = queue.alloc().ok_or(DevError::BadState)?;
+        unsafe {
+            queue
+                .buf(req_id)
+                .copy_from_nonoverlapping(req.as_ptr(), req.len())
+        };
+        queue.push_chain(&[(req_id, req.len(), false), (resp_id, BUF_SIZE, true)]);
+        self.transport.notify(queue.index);
+        let len = loop {
+            if let Some((_, len)) = queue.pop_used() {
+                break len.min(resp.len());
+            }
+            core::hint::spin_loop();
+        };
+        unsafe {
+            queue
+                .buf(resp_id)
+                .copy_to_nonoverlapping(resp.as_mut_ptr(), len)
+        };
+        queue.free(req_id);
+        Ok(len)
+    }
+}
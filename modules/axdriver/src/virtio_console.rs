@@ -0,0 +1,344 @@
+//! VirtIO console driver, with multiple ports (`VIRTIO_CONSOLE_F_MULTIPORT`).
+//!
+//! `axdriver_virtio` has no console driver, so the driver sets up its own
+//! [`VirtQueue`]s.
+//!
+//! Port 0 uses the queues 0 (receive) and 1 (transmit). With multiple ports,
+//! the queues 2 and 3 carry the control messages, and port `n > 0` uses the
+//! queues `2n + 2` and `2n + 3`. The device adds the ports with control
+//! messages once the driver is ready.
+
+use core::mem::size_of;
+use core::ptr::addr_of;
+use core::time::Duration;
+
+use axdriver_base::{BaseDriverOps, DevError, DevResult, DeviceType};
+use virtio_drivers::transport::{DeviceStatus, Transport};
+use virtio_drivers::{Hal, PAGE_SIZE};
+
+use crate::console::ConsoleDriverOps;
+use crate::virtqueue::VirtQueue;
+
+/// The maximum number of ports, the others are refused.
+const MAX_PORTS: usize = 8;
+/// The size of the buffer of each descriptor.
+const BUF_SIZE: usize = 256;
+
+const VIRTIO_CONSOLE_F_MULTIPORT: u64 = 1 << 1;
+const VIRTIO_F_VERSION_1: u64 = 1 << 32;
+
+// Events of the control messages.
+const VIRTIO_CONSOLE_DEVICE_READY: u16 = 0;
+const VIRTIO_CONSOLE_DEVICE_ADD: u16 = 1;
+const VIRTIO_CONSOLE_DEVICE_REMOVE: u16 = 2;
+const VIRTIO_CONSOLE_PORT_READY: u16 = 3;
+const VIRTIO_CONSOLE_PORT_OPEN: u16 = 6;
+
+/// How long the device has to add the ports during the initialization, after
+/// its last control message.
+const PORT_ADD_TIMEOUT: Duration = Duration::from_millis(10);
+/// How long the initialization waits for the ports at most.
+const INIT_TIMEOUT: Duration = Duration::from_millis(100);
+
+#[repr(C)]
+#[allow(dead_code)]
+struct ConsoleConfig {
+    cols: u16,
+    rows: u16,
+    max_nr_ports: u32,
+    emerg_wr: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct ControlMsg {
+    id: u32,
+    event: u16,
+    value: u16,
+}
+
+struct Port<H: Hal> {
+    rx: VirtQueue<H, BUF_SIZE>,
+    tx: VirtQueue<H, BUF_SIZE>,
+    /// The received buffer being read: its descriptor, and the range of the
+    /// bytes not read yet.
+    rx_pending: Option<(u16, usize, usize)>,
+    present: bool,
+}
+
+struct Control<H: Hal> {
+    rx: VirtQueue<H, BUF_SIZE>,
+    tx: VirtQueue<H, BUF_SIZE>,
+}
+
+/// The VirtIO console device driver.
+pub struct VirtIoConsoleDev<H: Hal, T: Transport> {
+    transport: T,
+    ports: [Option<Port<H>>; MAX_PORTS],
+    num_ports: usize,
+    /// The number of ports the device supports, maybe more than `num_ports`.
+    max_nr_ports: usize,
+    /// The control queues, with multiple ports.
+    control: Option<Control<H>>,
+}
+
+impl<H: Hal, T: Transport> VirtIoConsoleDev<H, T> {
+    /// Initializes the device, and waits for it to add its ports.
+    pub fn try_new(mut transport: T) -> DevResult<Self> {
+        transport.set_status(DeviceStatus::empty());
+        transport.set_status(DeviceStatus::ACKNOWLEDGE | DeviceStatus::DRIVER);
+        let features =
+            transport.read_device_features() & (VIRTIO_CONSOLE_F_MULTIPORT | VIRTIO_F_VERSION_1);
+        transport.write_driver_features(features);
+        transport.set_status(
+            DeviceStatus::ACKNOWLEDGE | DeviceStatus::DRIVER | DeviceStatus::FEATURES_OK,
+        );
+        transport.set_guest_page_size(PAGE_SIZE as u32);
+
+        let multiport = features & VIRTIO_CONSOLE_F_MULTIPORT != 0;
+        let max_nr_ports = if multiport {
+            let config = transport
+                .config_space::<ConsoleConfig>()
+                .map_err(|_| DevError::Unsupported)?;
+            let max_nr_ports = unsafe { addr_of!((*config.as_ptr()).max_nr_ports).read_volatile() };
+            max_nr_ports as usize
+        } else {
+            1
+        };
+        let num_ports = max_nr_ports.clamp(1, MAX_PORTS);
+
+        let mut ports: [Option<Port<H>>; MAX_PORTS] = Default::default();
+        for (i, port) in ports.iter_mut().take(num_ports).enumerate() {
+            let (rx, tx) = match i {
+                0 => (0, 1),
+                _ => (2 * i as u16 + 2, 2 * i as u16 + 3),
+            };
+            let mut rx = VirtQueue::new(&mut transport, rx)?;
+            rx.fill();
+            *port = Some(Port {
+                rx,
+                tx: VirtQueue::new(&mut transport, tx)?,
+                rx_pending: None,
+                // port 0 is there without multiple ports
+                present: !multiport,
+            });
+        }
+        let control = if multiport {
+            let mut rx = VirtQueue::new(&mut transport, 2)?;
+            rx.fill();
+            Some(Control {
+                rx,
+                tx: VirtQueue::new(&mut transport, 3)?,
+            })
+        } else {
+            None
+        };
+        transport.finish_init();
+
+        let mut dev = Self {
+            transport,
+            ports,
+            num_ports,
+            max_nr_ports,
+            control,
+        };
+        for port in dev.ports.iter().flatten() {
+            dev.transport.notify(port.rx.index);
+        }
+        if let Some(queue) = dev.control.as_ref().map(|control| control.rx.index) {
+            dev.transport.notify(queue);
+            dev.send_control(0, VIRTIO_CONSOLE_DEVICE_READY, 1);
+            dev.wait_for_ports();
+        }
+        Ok(dev)
+    }
+
+    /// Handles the control messages until the device has been quiet for a
+    /// while.
+    fn wait_for_ports(&mut self) {
+        let start = axhal::time::monotonic_time();
+        let mut last = start;
+        loop {
+            let now = axhal::time::monotonic_time();
+            if self.poll_control() {
+                last = now;
+            } else if now - last >= PORT_ADD_TIMEOUT || now - start >= INIT_TIMEOUT {
+                break;
+            }
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Handles the control messages received, and returns whether there
+    /// were any.
+    fn poll_control(&mut self) -> bool {
+        let mut handled = false;
+        while let Some(msg) = self.recv_control() {
+            self.handle_control(msg);
+            handled = true;
+        }
+        handled
+    }
+
+    fn recv_control(&mut self) -> Option<ControlMsg> {
+        let control = self.control.as_mut()?;
+        loop {
+            let (id, len) = control.rx.pop_used()?;
+            let msg = (len >= size_of::<ControlMsg>())
+                .then(|| unsafe { (control.rx.buf(id) as *const ControlMsg).read_unaligned() });
+            control.rx.push(id, BUF_SIZE, true);
+            self.transport.notify(control.rx.index);
+            if msg.is_some() {
+                return msg;
+            }
+        }
+    }
+
+    fn handle_control(&mut self, msg: ControlMsg) {
+        let id = msg.id as usize;
+        // the port ID comes from the device, so it's checked
+        let port = self.ports.get_mut(id).and_then(Option::as_mut);
+        match (msg.event, port) {
+            (VIRTIO_CONSOLE_DEVICE_ADD, Some(port)) => {
+                debug!("virtio-console: port {} added", id);
+                port.present = true;
+                self.send_control(id, VIRTIO_CONSOLE_PORT_READY, 1);
+                // all the ports are used as consoles
+                self.send_control(id, VIRTIO_CONSOLE_PORT_OPEN, 1);
+            }
+            (VIRTIO_CONSOLE_DEVICE_ADD, None) if id < self.max_nr_ports => {
+                warn!("virtio-console: too many ports, port {} refused", id);
+                self.send_control(id, VIRTIO_CONSOLE_PORT_READY, 0);
+            }
+            (VIRTIO_CONSOLE_DEVICE_REMOVE, Some(port)) => {
+                debug!("virtio-console: port {} removed", id);
+                port.present = false;
+            }
+            (event, None) => warn!(
+                "virtio-console: event {} of invalid port {} ignored",
+                event, id
+            ),
+            (event, Some(_)) => trace!("virtio-console: ignored event {} of port {}", event, id),
+        }
+    }
+
+    fn send_control(&mut self, id: usize, event: u16, value: u16) {
+        let Some(control) = self.control.as_mut() else {
+            return;
+        };
+        control.tx.reclaim();
+        let Some(desc) = control.tx.alloc() else {
+            warn!(
+                "virtio-console: control queue full, event {} dropped",
+                event
+            );
+            return;
+        };
+        let msg = ControlMsg {
+            id: id as u32,
+            event,
+            value,
+        };
+        unsafe { (control.tx.buf(desc) as *mut ControlMsg).write_unaligned(msg) };
+        control.tx.push(desc, size_of::<ControlMsg>(), false);
+        self.transport.notify(control.tx.index);
+    }
+
+    fn port_mut(ports: &mut [Option<Port<H>>], port: usize) -> DevResult<&mut Port<H>> {
+        match ports.get_mut(port) {
+            Some(Some(port)) if port.present => Ok(port),
+            Some(Some(_)) => Err(DevError::BadState),
+            _ => Err(DevError::InvalidParam),
+        }
+    }
+}
+
+impl<H: Hal, T: Transport> Drop for VirtIoConsoleDev<H, T> {
+    fn drop(&mut self) {
+        // stop the device before the queues are freed
+        self.transport.set_status(DeviceStatus::empty());
+    }
+}
+
+impl<H: Hal, T: Transport> BaseDriverOps for VirtIoConsoleDev<H, T> {
+    fn device_name(&self) -> &str {
+        "virtio-console"
+    }
+
+    fn device_type(&self) -> DeviceType {
+        DeviceType::Char
+    }
+}
+
+impl<H: Hal, T: Transport> ConsoleDriverOps for VirtIoConsoleDev<H, T> {
+    fn num_ports(&self) -> usize {
+        self.num_ports
+    }
+
+    fn port_present(&mut self, port: usize) -> bool {
+        self.poll_control();
+        Self::port_mut(&mut self.ports, port).is_ok()
+    }
+
+    fn read(&mut self, port: usize, buf: &mut [u8]) -> DevResult<usize> {
+        self.poll_control();
+        let port = Self::port_mut(&mut self.ports, port)?;
+        let mut read = 0;
+        let mut reposted = false;
+        while read < buf.len() {
+            let (id, start, end) = match port.rx_pending.take() {
+                Some(pending) => pending,
+                None => match port.rx.pop_used() {
+                    Some((id, len)) => (id, 0, len),
+                    None => break,
+                },
+            };
+            let len = (end - start).min(buf.len() - read);
+            unsafe {
+                port.rx
+                    .buf(id)
+                    .add(start)
+                    .copy_to_nonoverlapping(buf[read..].as_mut_ptr(), len)
+            };
+            read += len;
+            if start + len < end {
+                port.rx_pending = Some((id, start + len, end));
+            } else {
+                port.rx.push(id, BUF_SIZE, true);
+                reposted = true;
+            }
+        }
+        if reposted {
+            self.transport.notify(port.rx.index);
+        }
+        if read == 0 && !buf.is_empty() {
+            return Err(DevError::Again);
+        }
+        Ok(read)
+    }
+
+    fn write(&mut self, port: usize, buf: &[u8]) -> DevResult<usize> {
+        self.poll_control();
+        let port = Self::port_mut(&mut self.ports, port)?;
+        port.tx.reclaim();
+        let mut written = 0;
+        while written < buf.len() {
+            let Some(id) = port.tx.alloc() else {
+                break;
+            };
+            let len = (buf.len() - written).min(BUF_SIZE);
+            unsafe {
+                port.tx
+                    .buf(id)
+                    .copy_from_nonoverlapping(buf[written..].as_ptr(), len)
+            };
+            port.tx.push(id, len, false);
+            written += len;
+        }
+        if written == 0 && !buf.is_empty() {
+            return Err(DevError::Again);
+        }
+        self.transport.notify(port.tx.index);
+        Ok(written)
+    }
+}
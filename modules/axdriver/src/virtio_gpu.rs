@@ -0,0 +1,422 @@
+//! VirtIO GPU driver, with a linear framebuffer of which the resolution can
+//! be changed.
+//!
+//! The GPU of `axdriver_virtio` sets up its framebuffer once, at the
+//! resolution of the display, so the driver sends the 2D commands itself
+//! through a [`VirtQueue`]. Only the first scanout is used, and there is no
+//! cursor.
+
+use core::mem::size_of;
+
+use axdriver_base::{BaseDriverOps, DevError, DevResult, DeviceType};
+use axdriver_display::{DisplayDriverOps, DisplayInfo, FrameBuffer};
+use virtio_drivers::transport::{DeviceStatus, Transport};
+use virtio_drivers::{BufferDirection, Hal, PAGE_SIZE, PhysAddr};
+
+use crate::display::DisplayModeOps;
+use crate::virtqueue::VirtQueue;
+
+/// The size of the buffer of each descriptor, enough for the display info.
+const BUF_SIZE: usize = 512;
+
+/// The resolution used if the display doesn't tell its own.
+const DEFAULT_RESOLUTION: (u32, u32) = (1280, 800);
+/// The maximum width and height.
+const MAX_RESOLUTION: u32 = 8192;
+
+const VIRTIO_F_VERSION_1: u64 = 1 << 32;
+
+const CONTROL_QUEUE: u16 = 0;
+const SCANOUT_ID: u32 = 0;
+const RESOURCE_ID: u32 = 1;
+const VIRTIO_GPU_FORMAT_B8G8R8A8_UNORM: u32 = 1;
+const VIRTIO_GPU_MAX_SCANOUTS: usize = 16;
+
+// Types of the commands and the responses.
+const VIRTIO_GPU_CMD_GET_DISPLAY_INFO: u32 = 0x100;
+const VIRTIO_GPU_CMD_RESOURCE_CREATE_2D: u32 = 0x101;
+const VIRTIO_GPU_CMD_RESOURCE_UNREF: u32 = 0x102;
+const VIRTIO_GPU_CMD_SET_SCANOUT: u32 = 0x103;
+const VIRTIO_GPU_CMD_RESOURCE_FLUSH: u32 = 0x104;
+const VIRTIO_GPU_CMD_TRANSFER_TO_HOST_2D: u32 = 0x105;
+const VIRTIO_GPU_CMD_RESOURCE_ATTACH_BACKING: u32 = 0x106;
+const VIRTIO_GPU_CMD_RESOURCE_DETACH_BACKING: u32 = 0x107;
+const VIRTIO_GPU_RESP_OK_NODATA: u32 = 0x1100;
+const VIRTIO_GPU_RESP_OK_DISPLAY_INFO: u32 = 0x1101;
+const VIRTIO_GPU_RESP_ERR_OUT_OF_MEMORY: u32 = 0x1201;
+const VIRTIO_GPU_RESP_ERR_INVALID_SCANOUT_ID: u32 = 0x1202;
+const VIRTIO_GPU_RESP_ERR_INVALID_PARAMETER: u32 = 0x1205;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct CtrlHeader {
+    hdr_type: u32,
+    flags: u32,
+    fence_id: u64,
+    ctx_id: u32,
+    padding: u32,
+}
+
+impl CtrlHeader {
+    const fn new(hdr_type: u32) -> Self {
+        Self {
+            hdr_type,
+            flags: 0,
+            fence_id: 0,
+            ctx_id: 0,
+            padding: 0,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct Rect {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+impl Rect {
+    const fn new(width: u32, height: u32) -> Self {
+        Self {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct DisplayOne {
+    rect: Rect,
+    enabled: u32,
+    flags: u32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct RespDisplayInfo {
+    header: CtrlHeader,
+    pmodes: [DisplayOne; VIRTIO_GPU_MAX_SCANOUTS],
+}
+
+#[repr(C)]
+struct ResourceCreate2D {
+    header: CtrlHeader,
+    resource_id: u32,
+    format: u32,
+    width: u32,
+    height: u32,
+}
+
+/// `RESOURCE_UNREF` and `RESOURCE_DETACH_BACKING`.
+#[repr(C)]
+struct ResourceCmd {
+    header: CtrlHeader,
+    resource_id: u32,
+    padding: u32,
+}
+
+/// `RESOURCE_ATTACH_BACKING`, with a single entry.
+#[repr(C)]
+struct ResourceAttachBacking {
+    header: CtrlHeader,
+    resource_id: u32,
+    nr_entries: u32,
+    addr: u64,
+    length: u32,
+    padding: u32,
+}
+
+#[repr(C)]
+struct SetScanout {
+    header: CtrlHeader,
+    rect: Rect,
+    scanout_id: u32,
+    resource_id: u32,
+}
+
+#[repr(C)]
+struct TransferToHost2D {
+    header: CtrlHeader,
+    rect: Rect,
+    offset: u64,
+    resource_id: u32,
+    padding: u32,
+}
+
+#[repr(C)]
+struct ResourceFlush {
+    header: CtrlHeader,
+    rect: Rect,
+    resource_id: u32,
+    padding: u32,
+}
+
+/// The framebuffer, attached to the resource [`RESOURCE_ID`].
+struct Framebuffer {
+    paddr: PhysAddr,
+    vaddr: usize,
+    pages: usize,
+    width: u32,
+    height: u32,
+}
+
+impl Framebuffer {
+    fn size(&self) -> usize {
+        self.width as usize * self.height as usize * 4
+    }
+}
+
+/// The VirtIO GPU device driver.
+pub struct VirtIoGpuDev<H: Hal, T: Transport> {
+    transport: T,
+    control: VirtQueue<H, BUF_SIZE>,
+    fb: Option<Framebuffer>,
+}
+
+impl<H: Hal, T: Transport> VirtIoGpuDev<H, T> {
+    /// Initializes the device, with a framebuffer at the preferred
+    /// resolution.
+    pub fn try_new(mut transport: T) -> DevResult<Self> {
+        transport.set_status(DeviceStatus::empty());
+        transport.set_status(DeviceStatus::ACKNOWLEDGE | DeviceStatus::DRIVER);
+        let features = transport.read_device_features() & VIRTIO_F_VERSION_1;
+        transport.write_driver_features(features);
+        transport.set_status(
+            DeviceStatus::ACKNOWLEDGE | DeviceStatus::DRIVER | DeviceStatus::FEATURES_OK,
+        );
+        transport.set_guest_page_size(PAGE_SIZE as u32);
+        let control = VirtQueue::new(&mut transport, CONTROL_QUEUE)?;
+        transport.finish_init();
+
+        let mut dev = Self {
+            transport,
+            control,
+            fb: None,
+        };
+        let (width, height) = dev.preferred_resolution()?;
+        dev.set_resolution(width, height)?;
+        Ok(dev)
+    }
+
+    /// Sends the command `req`, and waits for the response.
+    fn request<Req, Resp>(&mut self, req: Req) -> DevResult<Resp> {
+        const { assert!(size_of::<Req>() <= BUF_SIZE && size_of::<Resp>() <= BUF_SIZE) };
+        // the commands are sent one at a time, so all the descriptors are free
+        let queue = &mut self.control;
+        let req_id = queue.alloc().ok_or(DevError::BadState)?;
+        let resp_id = queue.alloc().ok_or(DevError::BadState)?;
+        unsafe { (queue.buf(req_id) as *mut Req).write_unaligned(req) };
+        queue.push_chain(&[
+            (req_id, size_of::<Req>(), false),
+            (resp_id, size_of::<Resp>(), true),
+        ]);
+        self.transport.notify(queue.index);
+        while queue.pop_used().is_none() {
+            core::hint::spin_loop();
+        }
+        let resp = unsafe { (queue.buf(resp_id) as *const Resp).read_unaligned() };
+        queue.free(req_id);
+        Ok(resp)
+    }
+
+    /// Sends a command of which the response has no data.
+    fn request_nodata<Req>(&mut self, req: Req) -> DevResult {
+        let resp: CtrlHeader = self.request(req)?;
+        check_response(resp, VIRTIO_GPU_RESP_OK_NODATA)
+    }
+
+    /// Allocates a framebuffer of `width` x `height` and shows it.
+    fn create_framebuffer(&mut self, width: u32, height: u32) -> DevResult<Framebuffer> {
+        let pages = (width as usize * height as usize * 4).div_ceil(PAGE_SIZE);
+        let (paddr, vaddr) = H::dma_alloc(pages, BufferDirection::DriverToDevice);
+        if paddr == 0 {
+            return Err(DevError::NoMemory);
+        }
+        unsafe { vaddr.as_ptr().write_bytes(0, pages * PAGE_SIZE) };
+        let fb = Framebuffer {
+            paddr,
+            vaddr: vaddr.as_ptr() as usize,
+            pages,
+            width,
+            height,
+        };
+        if let Err(e) = self.show_framebuffer(&fb) {
+            self.destroy_framebuffer(fb);
+            return Err(e);
+        }
+        Ok(fb)
+    }
+
+    fn show_framebuffer(&mut self, fb: &Framebuffer) -> DevResult {
+        self.request_nodata(ResourceCreate2D {
+            header: CtrlHeader::new(VIRTIO_GPU_CMD_RESOURCE_CREATE_2D),
+            resource_id: RESOURCE_ID,
+            format: VIRTIO_GPU_FORMAT_B8G8R8A8_UNORM,
+            width: fb.width,
+            height: fb.height,
+        })?;
+        self.request_nodata(ResourceAttachBacking {
+            header: CtrlHeader::new(VIRTIO_GPU_CMD_RESOURCE_ATTACH_BACKING),
+            resource_id: RESOURCE_ID,
+            nr_entries: 1,
+            addr: fb.paddr as u64,
+            length: fb.size() as u32,
+            padding: 0,
+        })?;
+        self.request_nodata(SetScanout {
+            header: CtrlHeader::new(VIRTIO_GPU_CMD_SET_SCANOUT),
+            rect: Rect::new(fb.width, fb.height),
+            scanout_id: SCANOUT_ID,
+            resource_id: RESOURCE_ID,
+        })
+    }
+
+    /// Stops showing the framebuffer, and frees it.
+    fn destroy_framebuffer(&mut self, fb: Framebuffer) {
+        // fails for the steps not done if the creation failed
+        self.request_nodata(SetScanout {
+            header: CtrlHeader::new(VIRTIO_GPU_CMD_SET_SCANOUT),
+            rect: Rect::new(0, 0),
+            scanout_id: SCANOUT_ID,
+            resource_id: 0,
+        })
+        .ok();
+        self.request_nodata(ResourceCmd {
+            header: CtrlHeader::new(VIRTIO_GPU_CMD_RESOURCE_DETACH_BACKING),
+            resource_id: RESOURCE_ID,
+            padding: 0,
+        })
+        .ok();
+        self.request_nodata(ResourceCmd {
+            header: CtrlHeader::new(VIRTIO_GPU_CMD_RESOURCE_UNREF),
+            resource_id: RESOURCE_ID,
+            padding: 0,
+        })
+        .ok();
+        free_framebuffer::<H>(fb);
+    }
+}
+
+fn free_framebuffer<H: Hal>(fb: Framebuffer) {
+    let vaddr = core::ptr::NonNull::new(fb.vaddr as *mut u8).unwrap();
+    unsafe { H::dma_dealloc(fb.paddr, vaddr, fb.pages) };
+}
+
+fn check_response(header: CtrlHeader, expected: u32) -> DevResult {
+    match header.hdr_type {
+        t if t == expected => Ok(()),
+        VIRTIO_GPU_RESP_ERR_OUT_OF_MEMORY => Err(DevError::NoMemory),
+        VIRTIO_GPU_RESP_ERR_INVALID_SCANOUT_ID..=VIRTIO_GPU_RESP_ERR_INVALID_PARAMETER => {
+            Err(DevError::InvalidParam)
+        }
+        t => {
+            warn!("virtio-gpu: unexpected response {:#x}", t);
+            Err(DevError::Io)
+        }
+    }
+}
+
+impl<H: Hal, T: Transport> Drop for VirtIoGpuDev<H, T> {
+    fn drop(&mut self) {
+        // stop the device before the memory is freed
+        self.transport.set_status(DeviceStatus::empty());
+        if let Some(fb) = self.fb.take() {
+            free_framebuffer::<H>(fb);
+        }
+    }
+}
+
+impl<H: Hal, T: Transport> BaseDriverOps for VirtIoGpuDev<H, T> {
+    fn device_name(&self) -> &str {
+        "virtio-gpu"
+    }
+
+    fn device_type(&self) -> DeviceType {
+        DeviceType::Display
+    }
+}
+
+impl<H: Hal, T: Transport> DisplayDriverOps for VirtIoGpuDev<H, T> {
+    fn info(&self) -> DisplayInfo {
+        match &self.fb {
+            Some(fb) => DisplayInfo {
+                width: fb.width,
+                height: fb.height,
+                fb_base_vaddr: fb.vaddr,
+                fb_size: fb.size(),
+            },
+            None => DisplayInfo {
+                width: 0,
+                height: 0,
+                fb_base_vaddr: 0,
+                fb_size: 0,
+            },
+        }
+    }
+
+    fn fb(&self) -> FrameBuffer {
+        match &self.fb {
+            Some(fb) => unsafe { FrameBuffer::from_raw_parts_mut(fb.vaddr as *mut u8, fb.size()) },
+            None => FrameBuffer::from_slice(&mut []),
+        }
+    }
+
+    fn need_flush(&self) -> bool {
+        true
+    }
+
+    fn flush(&mut self) -> DevResult {
+        let Some(fb) = &self.fb else {
+            return Err(DevError::BadState);
+        };
+        let rect = Rect::new(fb.width, fb.height);
+        self.request_nodata(TransferToHost2D {
+            header: CtrlHeader::new(VIRTIO_GPU_CMD_TRANSFER_TO_HOST_2D),
+            rect,
+            offset: 0,
+            resource_id: RESOURCE_ID,
+            padding: 0,
+        })?;
+        self.request_nodata(ResourceFlush {
+            header: CtrlHeader::new(VIRTIO_GPU_CMD_RESOURCE_FLUSH),
+            rect,
+            resource_id: RESOURCE_ID,
+            padding: 0,
+        })
+    }
+}
+
+impl<H: Hal, T: Transport> DisplayModeOps for VirtIoGpuDev<H, T> {
+    fn preferred_resolution(&mut self) -> DevResult<(u32, u32)> {
+        let resp: RespDisplayInfo =
+            self.request(CtrlHeader::new(VIRTIO_GPU_CMD_GET_DISPLAY_INFO))?;
+        check_response(resp.header, VIRTIO_GPU_RESP_OK_DISPLAY_INFO)?;
+        let mode = resp.pmodes[SCANOUT_ID as usize];
+        if mode.enabled == 0 || mode.rect.width == 0 || mode.rect.height == 0 {
+            return Ok(DEFAULT_RESOLUTION);
+        }
+        Ok((
+            mode.rect.width.min(MAX_RESOLUTION),
+            mode.rect.height.min(MAX_RESOLUTION),
+        ))
+    }
+
+    fn set_resolution(&mut self, width: u32, height: u32) -> DevResult {
+        if !(1..=MAX_RESOLUTION).contains(&width) || !(1..=MAX_RESOLUTION).contains(&height) {
+            return Err(DevError::InvalidParam);
+        }
+        if let Some(fb) = self.fb.take() {
+            self.destroy_framebuffer(fb);
+        }
+        let fb = self.create_framebuffer(width, height)?;
+        info!("virtio-gpu: resolution {}x{}", width, height);
+        self.fb = Some(fb);
+        self.flush()
+    }
+}
@@ -0,0 +1,434 @@
+//! VirtIO network driver, with multiple queue pairs (`VIRTIO_NET_F_MQ`).
+//!
+//! The driver of `axdriver_virtio` has a single queue pair, so this one sets
+//! up its own [`VirtQueue`]s. The queue pair `n` is made of the queues `2n`
+//! (receive) and `2n + 1` (transmit), and the control queue follows the last
+//! pair the device supports.
+//!
+//! Each CPU transmits on the queue pair of its index (modulo the number of
+//! pairs), and receives from its own pair first. With `VIRTIO_NET_F_RSS`, the
+//! device steers the flows to the pairs by the hash of their addresses and
+//! ports, with a random key, so that all the frames of a flow are received by
+//! the same pair, and handled by the same CPU as its queues interrupt the CPU
+//! of the pair. Otherwise the device spreads the flows its own way.
+//!
+//! The RX queues interrupt the CPU when frames are received (see
+//! [`net_irq`](crate::net_irq)). As NAPI does, the interrupts of a queue are
+//! suppressed once the network stack starts taking its frames, and enabled
+//! again once it's empty, so that a burst of frames is handled in a batch
+//! with a single interrupt.
+
+use core::ptr::{NonNull, addr_of};
+use core::time::Duration;
+
+use axdriver_base::{BaseDriverOps, DevError, DevResult, DeviceType};
+use axdriver_net::{EthernetAddress, NetBufPtr, NetDriverOps};
+use virtio_drivers::transport::{DeviceStatus, Transport};
+use virtio_drivers::{Hal, PAGE_SIZE};
+
+use crate::virtqueue::VirtQueue;
+
+/// The maximum number of queue pairs used.
+const MAX_QUEUE_PAIRS: usize = 8;
+/// The number of descriptors of the RX and TX queues.
+const QUEUE_SIZE: usize = 64;
+/// The size of the buffer of each descriptor, for a frame of the standard MTU
+/// with its header.
+const BUF_SIZE: usize = 2048;
+/// The size of the buffers of the control queue, for the RSS configuration.
+const CTRL_BUF_SIZE: usize = 512;
+/// The maximum length of the RSS indirection table.
+const MAX_RSS_TABLE_LEN: usize = 128;
+/// The maximum length of the RSS key.
+const MAX_RSS_KEY_LEN: usize = 40;
+
+const VIRTIO_NET_F_MAC: u64 = 1 << 5;
+const VIRTIO_NET_F_CTRL_VQ: u64 = 1 << 17;
+const VIRTIO_NET_F_MQ: u64 = 1 << 22;
+const VIRTIO_F_VERSION_1: u64 = 1 << 32;
+const VIRTIO_NET_F_RSS: u64 = 1 << 60;
+
+/// The length of `virtio_net_hdr`, without and with `VIRTIO_F_VERSION_1`.
+const LEGACY_HDR_LEN: usize = 10;
+const HDR_LEN: usize = 12;
+
+const VIRTIO_NET_CTRL_MQ: u8 = 4;
+const VIRTIO_NET_CTRL_MQ_VQ_PAIRS_SET: u8 = 0;
+const VIRTIO_NET_CTRL_MQ_RSS_CONFIG: u8 = 1;
+const VIRTIO_NET_OK: u8 = 0;
+
+/// The flows hashed by RSS: IPv4, with the ports of TCP and UDP.
+const VIRTIO_NET_RSS_HASH_TYPES: u32 = 0b111;
+
+/// How long the device has to complete a control command.
+const CTRL_TIMEOUT: Duration = Duration::from_millis(100);
+
+#[repr(C)]
+#[allow(dead_code)]
+struct NetConfig {
+    mac: [u8; 6],
+    status: u16,
+    max_virtqueue_pairs: u16,
+    mtu: u16,
+    speed: u32,
+    duplex: u8,
+    rss_max_key_size: u8,
+    rss_max_indirection_table_length: u16,
+    supported_hash_types: u32,
+}
+
+/// The RSS parameters of the device.
+struct RssLimits {
+    max_key_size: u8,
+    max_table_len: u16,
+    hash_types: u32,
+}
+
+struct QueuePair<H: Hal> {
+    rx: VirtQueue<H, BUF_SIZE, QUEUE_SIZE>,
+    tx: VirtQueue<H, BUF_SIZE, QUEUE_SIZE>,
+    /// Whether the interrupts of `rx` are suppressed, while its frames are
+    /// taken.
+    rx_polling: bool,
+}
+
+impl<H: Hal> QueuePair<H> {
+    /// Takes the next frame received, and suppresses the interrupts of the
+    /// queue until it's empty.
+    fn pop_rx(&mut self, hdr_len: usize) -> Option<NetBufPtr> {
+        let (id, len) = self.rx.pop_used()?;
+        if !self.rx_polling {
+            self.rx.set_interrupts(false);
+            self.rx_polling = true;
+        }
+        let buf = self.rx.buf(id);
+        // a frame shorter than its header is given empty, and dropped
+        let packet = unsafe { buf.add(hdr_len) };
+        Some(NetBufPtr::new(
+            NonNull::new(buf)?,
+            NonNull::new(packet)?,
+            len.saturating_sub(hdr_len),
+        ))
+    }
+}
+
+/// The VirtIO network device driver.
+pub struct VirtIoNetDev<H: Hal, T: Transport> {
+    transport: T,
+    mac: [u8; 6],
+    hdr_len: usize,
+    pairs: [Option<QueuePair<H>>; MAX_QUEUE_PAIRS],
+    num_pairs: usize,
+    ctrl: Option<VirtQueue<H, CTRL_BUF_SIZE>>,
+}
+
+impl<H: Hal, T: Transport> VirtIoNetDev<H, T> {
+    /// Initializes the device, with as many queue pairs as it supports up to
+    /// the number of CPUs.
+    pub fn try_new(mut transport: T) -> DevResult<Self> {
+        transport.set_status(DeviceStatus::empty());
+        transport.set_status(DeviceStatus::ACKNOWLEDGE | DeviceStatus::DRIVER);
+        let mut features = transport.read_device_features()
+            & (VIRTIO_NET_F_MAC
+                | VIRTIO_NET_F_CTRL_VQ
+                | VIRTIO_NET_F_MQ
+                | VIRTIO_F_VERSION_1
+                | VIRTIO_NET_F_RSS);
+        if features & VIRTIO_NET_F_CTRL_VQ == 0 {
+            // the number of pairs and RSS are set with control commands
+            features &= !(VIRTIO_NET_F_MQ | VIRTIO_NET_F_RSS);
+        }
+        transport.write_driver_features(features);
+        transport.set_status(
+            DeviceStatus::ACKNOWLEDGE | DeviceStatus::DRIVER | DeviceStatus::FEATURES_OK,
+        );
+        transport.set_guest_page_size(PAGE_SIZE as u32);
+
+        let config = transport
+            .config_space::<NetConfig>()
+            .map_err(|_| DevError::Unsupported)?;
+        let mac = if features & VIRTIO_NET_F_MAC != 0 {
+            unsafe { addr_of!((*config.as_ptr()).mac).read_volatile() }
+        } else {
+            // a random locally administered address
+            let bytes = axhal::random::random_u64().to_ne_bytes();
+            [0x02, bytes[0], bytes[1], bytes[2], bytes[3], bytes[4]]
+        };
+        let max_pairs = if features & (VIRTIO_NET_F_MQ | VIRTIO_NET_F_RSS) != 0 {
+            unsafe { addr_of!((*config.as_ptr()).max_virtqueue_pairs).read_volatile() }.max(1)
+        } else {
+            1
+        };
+        let rss = (features & VIRTIO_NET_F_RSS != 0).then(|| unsafe {
+            RssLimits {
+                max_key_size: addr_of!((*config.as_ptr()).rss_max_key_size).read_volatile(),
+                max_table_len: addr_of!((*config.as_ptr()).rss_max_indirection_table_length)
+                    .read_volatile(),
+                hash_types: addr_of!((*config.as_ptr()).supported_hash_types).read_volatile(),
+            }
+        });
+        let num_pairs = (max_pairs as usize).min(MAX_QUEUE_PAIRS.min(axconfig::SMP));
+
+        let mut pairs: [Option<QueuePair<H>>; MAX_QUEUE_PAIRS] = Default::default();
+        for (i, pair) in pairs.iter_mut().take(num_pairs).enumerate() {
+            let mut rx = VirtQueue::new(&mut transport, 2 * i as u16)?;
+            rx.fill();
+            *pair = Some(QueuePair {
+                rx,
+                tx: VirtQueue::new(&mut transport, 2 * i as u16 + 1)?,
+                rx_polling: false,
+            });
+        }
+        let ctrl = if features & VIRTIO_NET_F_CTRL_VQ != 0 {
+            Some(VirtQueue::new(&mut transport, 2 * max_pairs)?)
+        } else {
+            None
+        };
+        transport.finish_init();
+
+        let mut dev = Self {
+            transport,
+            mac,
+            hdr_len: if features & VIRTIO_F_VERSION_1 != 0 {
+                HDR_LEN
+            } else {
+                LEGACY_HDR_LEN
+            },
+            pairs,
+            num_pairs,
+            ctrl,
+        };
+        for pair in dev.pairs.iter().flatten() {
+            dev.transport.notify(pair.rx.index);
+        }
+        if num_pairs > 1 {
+            let rss = rss.map(|rss| dev.configure_rss(&rss));
+            if let Some(Err(e)) = rss {
+                warn!("virtio-net: failed to configure RSS: {:?}", e);
+            }
+            if !matches!(rss, Some(Ok(()))) {
+                let data = (num_pairs as u16).to_le_bytes();
+                if let Err(e) =
+                    dev.ctrl_command(VIRTIO_NET_CTRL_MQ, VIRTIO_NET_CTRL_MQ_VQ_PAIRS_SET, &data)
+                {
+                    // the device only uses the first pair until told otherwise
+                    warn!(
+                        "virtio-net: failed to use {} queue pairs: {:?}",
+                        num_pairs, e
+                    );
+                    dev.num_pairs = 1;
+                }
+            }
+        }
+        info!("virtio-net: {} queue pairs", dev.num_pairs);
+        Ok(dev)
+    }
+
+    /// Steers the flows received to the queue pairs by their hash, and uses
+    /// all the pairs to transmit.
+    fn configure_rss(&mut self, rss: &RssLimits) -> DevResult {
+        let hash_types = rss.hash_types & VIRTIO_NET_RSS_HASH_TYPES;
+        // the length of the table is a power of two
+        let table_len = (rss.max_table_len as usize).min(MAX_RSS_TABLE_LEN);
+        if hash_types == 0 || table_len == 0 {
+            return Err(DevError::Unsupported);
+        }
+        let table_len = 1 << table_len.ilog2();
+        let key_len = (rss.max_key_size as usize).min(MAX_RSS_KEY_LEN);
+
+        // `struct virtio_net_rss_config`
+        let mut data = [0; CTRL_BUF_SIZE - 2];
+        data[..4].copy_from_slice(&hash_types.to_le_bytes());
+        data[4..6].copy_from_slice(&(table_len as u16 - 1).to_le_bytes());
+        // the unclassified frames go to the first pair
+        data[6..8].copy_from_slice(&0u16.to_le_bytes());
+        for i in 0..table_len {
+            let pair = (i % self.num_pairs) as u16;
+            data[8 + 2 * i..10 + 2 * i].copy_from_slice(&pair.to_le_bytes());
+        }
+        let pos = 8 + 2 * table_len;
+        data[pos..pos + 2].copy_from_slice(&(self.num_pairs as u16).to_le_bytes());
+        data[pos + 2] = key_len as u8;
+        let end = pos + 3 + key_len;
+        axhal::random::fill_bytes(&mut data[pos + 3..end]);
+        self.ctrl_command(
+            VIRTIO_NET_CTRL_MQ,
+            VIRTIO_NET_CTRL_MQ_RSS_CONFIG,
+            &data[..end],
+        )?;
+        debug!(
+            "virtio-net: RSS over {} queue pairs, hash types {:#x}",
+            self.num_pairs, hash_types
+        );
+        Ok(())
+    }
+
+    /// Sends a control command, and waits for the device to complete it.
+    fn ctrl_command(&mut self, class: u8, command: u8, data: &[u8]) -> DevResult {
+        let ctrl = self.ctrl.as_mut().ok_or(DevError::Unsupported)?;
+        if 2 + data.len() > CTRL_BUF_SIZE {
+            return Err(DevError::InvalidParam);
+        }
+        // the descriptors of a command that timed out are taken back once
+        // the device completes it
+        ctrl.reclaim();
+        if ctrl.num_free() < 2 {
+            return Err(DevError::Again);
+        }
+        let (out, ack) = (ctrl.alloc().unwrap(), ctrl.alloc().unwrap());
+        unsafe {
+            let buf = ctrl.buf(out);
+            buf.write(class);
+            buf.add(1).write(command);
+            buf.add(2)
+                .copy_from_nonoverlapping(data.as_ptr(), data.len());
+            ctrl.buf(ack).write_volatile(!VIRTIO_NET_OK);
+        }
+        ctrl.push_chain(&[(out, 2 + data.len(), false), (ack, 1, true)]);
+        self.transport.notify(ctrl.index);
+
+        let start = axhal::time::monotonic_time();
+        loop {
+            while let Some((id, _)) = ctrl.pop_used() {
+                ctrl.free(id);
+                if id != out {
+                    // a command that timed out
+                    continue;
+                }
+                return match unsafe { ctrl.buf(ack).read_volatile() } {
+                    VIRTIO_NET_OK => Ok(()),
+                    _ => Err(DevError::Unsupported),
+                };
+            }
+            if axhal::time::monotonic_time() - start >= CTRL_TIMEOUT {
+                return Err(DevError::Again);
+            }
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Returns the queue pair of the current CPU.
+    fn cpu_pair(&mut self) -> &mut QueuePair<H> {
+        let index = axhal::cpu::this_cpu_id() % self.num_pairs;
+        // the pairs used are the first ones
+        self.pairs[index].as_mut().unwrap()
+    }
+}
+
+impl<H: Hal, T: Transport> Drop for VirtIoNetDev<H, T> {
+    fn drop(&mut self) {
+        // stop the device before the queues are freed
+        self.transport.set_status(DeviceStatus::empty());
+    }
+}
+
+impl<H: Hal, T: Transport> BaseDriverOps for VirtIoNetDev<H, T> {
+    fn device_name(&self) -> &str {
+        "virtio-net"
+    }
+
+    fn device_type(&self) -> DeviceType {
+        DeviceType::Net
+    }
+}
+
+impl<H: Hal, T: Transport> NetDriverOps for VirtIoNetDev<H, T> {
+    fn mac_address(&self) -> EthernetAddress {
+        EthernetAddress(self.mac)
+    }
+
+    fn can_transmit(&self) -> bool {
+        let index = axhal::cpu::this_cpu_id() % self.num_pairs;
+        self.pairs[index]
+            .as_ref()
+            .is_some_and(|pair| pair.tx.num_free() > 0 || pair.tx.has_used())
+    }
+
+    fn can_receive(&self) -> bool {
+        self.pairs.iter().flatten().any(|pair| pair.rx.has_used())
+    }
+
+    fn rx_queue_size(&self) -> usize {
+        QUEUE_SIZE
+    }
+
+    fn tx_queue_size(&self) -> usize {
+        QUEUE_SIZE
+    }
+
+    fn recycle_rx_buffer(&mut self, rx_buf: NetBufPtr) -> DevResult {
+        let ptr = rx_buf.raw_ptr::<u8>();
+        for pair in self.pairs.iter_mut().flatten() {
+            if let Some(id) = pair.rx.desc_of(ptr) {
+                pair.rx.push(id, BUF_SIZE, true);
+                self.transport.notify(pair.rx.index);
+                return Ok(());
+            }
+        }
+        Err(DevError::InvalidParam)
+    }
+
+    fn recycle_tx_buffers(&mut self) -> DevResult {
+        for pair in self.pairs.iter_mut().flatten() {
+            pair.tx.reclaim();
+        }
+        Ok(())
+    }
+
+    fn transmit(&mut self, tx_buf: NetBufPtr) -> DevResult {
+        let ptr = tx_buf.raw_ptr::<u8>();
+        let len = self.hdr_len + tx_buf.packet_len();
+        // the buffer may have been allocated on another CPU
+        for pair in self.pairs.iter_mut().flatten() {
+            if let Some(id) = pair.tx.desc_of(ptr) {
+                pair.tx.push(id, len, false);
+                self.transport.notify(pair.tx.index);
+                return Ok(());
+            }
+        }
+        Err(DevError::InvalidParam)
+    }
+
+    fn receive(&mut self) -> DevResult<NetBufPtr> {
+        let hdr_len = self.hdr_len;
+        let first = axhal::cpu::this_cpu_id() % self.num_pairs;
+        for i in 0..self.num_pairs {
+            let index = (first + i) % self.num_pairs;
+            if let Some(buf) = self.pairs[index]
+                .as_mut()
+                .and_then(|pair| pair.pop_rx(hdr_len))
+            {
+                return Ok(buf);
+            }
+        }
+        // all empty: interrupt again, and take the frames received meanwhile
+        for pair in self.pairs.iter_mut().flatten() {
+            if pair.rx_polling {
+                pair.rx.set_interrupts(true);
+                pair.rx_polling = false;
+                if let Some(buf) = pair.pop_rx(hdr_len) {
+                    return Ok(buf);
+                }
+            }
+        }
+        Err(DevError::Again)
+    }
+
+    fn alloc_tx_buffer(&mut self, size: usize) -> DevResult<NetBufPtr> {
+        let hdr_len = self.hdr_len;
+        if size > BUF_SIZE - hdr_len {
+            return Err(DevError::InvalidParam);
+        }
+        let pair = self.cpu_pair();
+        pair.tx.reclaim();
+        let id = pair.tx.alloc().ok_or(DevError::NoMemory)?;
+        let buf = pair.tx.buf(id);
+        // no offloads
+        unsafe { buf.write_bytes(0, hdr_len) };
+        let packet = unsafe { buf.add(hdr_len) };
+        match (NonNull::new(buf), NonNull::new(packet)) {
+            (Some(buf), Some(packet)) => Ok(NetBufPtr::new(buf, packet, size)),
+            _ => Err(DevError::BadState),
+        }
+    }
+}
@@ -0,0 +1,244 @@
+//! Split virtqueues for the VirtIO drivers of this crate.
+//!
+//! The virtqueues of `virtio-drivers` are private, so the drivers that
+//! `axdriver_virtio` doesn't have set up their own ones through the
+//! [`Transport`] of `virtio-drivers`.
+
+use core::marker::PhantomData;
+use core::mem::size_of;
+use core::ptr::{NonNull, addr_of, addr_of_mut};
+use core::sync::atomic::{Ordering, fence};
+
+use axdriver_base::{DevError, DevResult};
+use virtio_drivers::transport::Transport;
+use virtio_drivers::{BufferDirection, Hal, PAGE_SIZE, PhysAddr};
+
+/// The default number of descriptors of a queue.
+pub(crate) const QUEUE_SIZE: usize = 16;
+
+// The descriptor table and the available ring are in the first page, the used
+// ring in the second one (as the legacy layout requires), and the buffers
+// after them.
+const USED_OFFSET: usize = PAGE_SIZE;
+const BUF_OFFSET: usize = 2 * PAGE_SIZE;
+
+const VIRTQ_DESC_F_NEXT: u16 = 1;
+const VIRTQ_DESC_F_WRITE: u16 = 2;
+/// Asks the device not to interrupt when it uses descriptors.
+const VIRTQ_AVAIL_F_NO_INTERRUPT: u16 = 1;
+
+#[repr(C)]
+#[allow(dead_code)]
+struct Descriptor {
+    addr: u64,
+    len: u32,
+    flags: u16,
+    next: u16,
+}
+
+/// A split virtqueue of `SIZE` descriptors, which each have a buffer of
+/// `BUF_SIZE` bytes.
+pub(crate) struct VirtQueue<H: Hal, const BUF_SIZE: usize, const SIZE: usize = QUEUE_SIZE> {
+    pub index: u16,
+    paddr: PhysAddr,
+    vaddr: NonNull<u8>,
+    /// The descriptors not given to the device.
+    free: [u16; SIZE],
+    num_free: usize,
+    avail_idx: u16,
+    last_used_idx: u16,
+    _phantom: PhantomData<H>,
+}
+
+unsafe impl<H: Hal, const BUF_SIZE: usize, const SIZE: usize> Send
+    for VirtQueue<H, BUF_SIZE, SIZE>
+{
+}
+unsafe impl<H: Hal, const BUF_SIZE: usize, const SIZE: usize> Sync
+    for VirtQueue<H, BUF_SIZE, SIZE>
+{
+}
+
+impl<H: Hal, const BUF_SIZE: usize, const SIZE: usize> VirtQueue<H, BUF_SIZE, SIZE> {
+    const PAGES: usize = 2 + (BUF_SIZE * SIZE).div_ceil(PAGE_SIZE);
+    const AVAIL_OFFSET: usize = size_of::<Descriptor>() * SIZE;
+
+    /// Allocates the queue `index` of the device, and tells the device where
+    /// it is.
+    pub fn new<T: Transport>(transport: &mut T, index: u16) -> DevResult<Self> {
+        // the rings must fit in their pages
+        const { assert!(Self::AVAIL_OFFSET + 6 + 2 * SIZE <= PAGE_SIZE && 6 + 8 * SIZE <= PAGE_SIZE) };
+        if (transport.max_queue_size(index) as usize) < SIZE {
+            return Err(DevError::Unsupported);
+        }
+        let (paddr, vaddr) = H::dma_alloc(Self::PAGES, BufferDirection::Both);
+        if paddr == 0 {
+            return Err(DevError::NoMemory);
+        }
+        unsafe { vaddr.as_ptr().write_bytes(0, Self::PAGES * PAGE_SIZE) };
+        let queue = Self {
+            index,
+            paddr,
+            vaddr,
+            free: core::array::from_fn(|i| i as u16),
+            num_free: SIZE,
+            avail_idx: 0,
+            last_used_idx: 0,
+            _phantom: PhantomData,
+        };
+        for id in 0..SIZE as u16 {
+            let addr = (paddr + BUF_OFFSET + id as usize * BUF_SIZE) as u64;
+            unsafe { addr_of_mut!((*queue.desc(id)).addr).write_volatile(addr) };
+        }
+        transport.queue_set(
+            index,
+            SIZE as u32,
+            paddr,
+            paddr + Self::AVAIL_OFFSET,
+            paddr + USED_OFFSET,
+        );
+        Ok(queue)
+    }
+
+    fn ptr(&self, offset: usize) -> *mut u8 {
+        unsafe { self.vaddr.as_ptr().add(offset) }
+    }
+
+    fn desc(&self, id: u16) -> *mut Descriptor {
+        self.ptr(id as usize * size_of::<Descriptor>()) as _
+    }
+
+    /// Returns the buffer of the descriptor `id`.
+    pub fn buf(&self, id: u16) -> *mut u8 {
+        self.ptr(BUF_OFFSET + id as usize * BUF_SIZE)
+    }
+
+    /// Returns the descriptor whose buffer holds `ptr`, if any.
+    pub fn desc_of(&self, ptr: *const u8) -> Option<u16> {
+        let offset = (ptr as usize).checked_sub(self.buf(0) as usize)?;
+        let id = offset / BUF_SIZE;
+        (id < SIZE).then_some(id as u16)
+    }
+
+    /// Returns the number of descriptors not given to the device.
+    pub fn num_free(&self) -> usize {
+        self.num_free
+    }
+
+    /// Takes a descriptor not given to the device.
+    pub fn alloc(&mut self) -> Option<u16> {
+        if self.num_free == 0 {
+            return None;
+        }
+        self.num_free -= 1;
+        Some(self.free[self.num_free])
+    }
+
+    /// Gives the descriptor `id` to the device, with `len` bytes of its
+    /// buffer to be read, or written if `device_writes`.
+    pub fn push(&mut self, id: u16, len: usize, device_writes: bool) {
+        self.push_chain(&[(id, len, device_writes)]);
+    }
+
+    /// Gives the descriptors to the device as a chain, each one as in
+    /// [`push`](Self::push). The device reads the buffers before writing
+    /// any.
+    pub fn push_chain(&mut self, chain: &[(u16, usize, bool)]) {
+        for (i, &(id, len, device_writes)) in chain.iter().enumerate() {
+            let mut flags = if device_writes { VIRTQ_DESC_F_WRITE } else { 0 };
+            let next = match chain.get(i + 1) {
+                Some(&(next, ..)) => {
+                    flags |= VIRTQ_DESC_F_NEXT;
+                    next
+                }
+                None => 0,
+            };
+            unsafe {
+                let desc = self.desc(id);
+                addr_of_mut!((*desc).len).write_volatile(len.min(BUF_SIZE) as u32);
+                addr_of_mut!((*desc).flags).write_volatile(flags);
+                addr_of_mut!((*desc).next).write_volatile(next);
+            }
+        }
+        unsafe {
+            // flags: u16, idx: u16, ring: [u16; SIZE]
+            let ring = self.ptr(Self::AVAIL_OFFSET + 4) as *mut u16;
+            ring.add(self.avail_idx as usize % SIZE)
+                .write_volatile(chain[0].0);
+            fence(Ordering::SeqCst);
+            self.avail_idx = self.avail_idx.wrapping_add(1);
+            (self.ptr(Self::AVAIL_OFFSET + 2) as *mut u16).write_volatile(self.avail_idx);
+        }
+        fence(Ordering::SeqCst);
+    }
+
+    /// Whether the device has used descriptors not taken yet.
+    pub fn has_used(&self) -> bool {
+        fence(Ordering::SeqCst);
+        // flags: u16, idx: u16, ring: [(id: u32, len: u32); SIZE]
+        let used_idx = unsafe { (self.ptr(USED_OFFSET + 2) as *const u16).read_volatile() };
+        used_idx != self.last_used_idx
+    }
+
+    /// Takes the next (head) descriptor used by the device, and the number of
+    /// bytes it wrote.
+    pub fn pop_used(&mut self) -> Option<(u16, usize)> {
+        if !self.has_used() {
+            return None;
+        }
+        fence(Ordering::SeqCst);
+        let slot = self.last_used_idx as usize % SIZE;
+        let elem = self.ptr(USED_OFFSET + 4 + slot * 8) as *const u32;
+        let (id, len) = unsafe { (elem.read_volatile(), elem.add(1).read_volatile()) };
+        self.last_used_idx = self.last_used_idx.wrapping_add(1);
+        Some((id as u16, (len as usize).min(BUF_SIZE)))
+    }
+
+    /// Frees the descriptor chain starting at `id`, once used by the device.
+    pub fn free(&mut self, mut id: u16) {
+        loop {
+            self.free[self.num_free] = id;
+            self.num_free += 1;
+            let desc = self.desc(id);
+            let flags = unsafe { addr_of!((*desc).flags).read_volatile() };
+            if flags & VIRTQ_DESC_F_NEXT == 0 {
+                break;
+            }
+            id = unsafe { addr_of!((*desc).next).read_volatile() };
+        }
+    }
+
+    /// Takes back the descriptors sent by the device.
+    pub fn reclaim(&mut self) {
+        while let Some((id, _)) = self.pop_used() {
+            self.free(id);
+        }
+    }
+
+    /// Lets the device interrupt when it uses descriptors, or asks it not to.
+    ///
+    /// The device may still interrupt after being asked not to, so the
+    /// interrupts are only suppressed as an optimization.
+    pub fn set_interrupts(&mut self, enabled: bool) {
+        let flags = if enabled {
+            0
+        } else {
+            VIRTQ_AVAIL_F_NO_INTERRUPT
+        };
+        unsafe { (self.ptr(Self::AVAIL_OFFSET) as *mut u16).write_volatile(flags) };
+        fence(Ordering::SeqCst);
+    }
+
+    /// Gives all the descriptors to the device to be written.
+    pub fn fill(&mut self) {
+        while let Some(id) = self.alloc() {
+            self.push(id, BUF_SIZE, true);
+        }
+    }
+}
+
+impl<H: Hal, const BUF_SIZE: usize, const SIZE: usize> Drop for VirtQueue<H, BUF_SIZE, SIZE> {
+    fn drop(&mut self) {
+        unsafe { H::dma_dealloc(self.paddr, self.vaddr, Self::PAGES) };
+    }
+}
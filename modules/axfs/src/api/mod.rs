@@ -5,6 +5,7 @@ mod file;
 
 pub use self::dir::{DirBuilder, DirEntry, ReadDir};
 pub use self::file::{File, FileType, Metadata, OpenOptions, Permissions};
+pub use crate::xattr::XattrSetMode;
 
 use alloc::{string::String, vec::Vec};
 use axio::{self as io, prelude::*};
@@ -53,12 +54,78 @@ pub fn write<C: AsRef<[u8]>>(path: &str, contents: C) -> io::Result<()> {
     File::create(path)?.write_all(contents.as_ref())
 }
 
+/// Disk space usage of a filesystem, in bytes.
+///
+/// Filesystems that live in memory (e.g. ramfs, devfs), and the ones whose
+/// usage can't be told (e.g. 9P), report zero for all fields, as ramfs does on
+/// Linux.
+///
+/// There is a single user, so there are no per-user quotas: the space
+/// available is the free space, less the space the filesystem reserves if
+/// any. A read-only filesystem has none.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DiskUsage {
+    /// The allocation unit of the filesystem.
+    pub block_size: u64,
+    /// The total capacity.
+    pub total: u64,
+    /// The free space.
+    pub free: u64,
+    /// The free space the application can use.
+    pub available: u64,
+}
+
+impl DiskUsage {
+    /// Returns the used space.
+    pub const fn used(&self) -> u64 {
+        self.total - self.free
+    }
+}
+
+/// Returns the disk space usage of the filesystem containing `path`.
+pub fn disk_usage(path: &str) -> io::Result<DiskUsage> {
+    let path = crate::root::absolute_path(path)?;
+    crate::root::lookup(None, &path)?;
+    crate::root::disk_usage(&path)
+}
+
+/// Returns all mount points with the disk space usage of their filesystems,
+/// starting with the root filesystem.
+pub fn mounts() -> io::Result<Vec<(String, DiskUsage)>> {
+    crate::root::mounts()
+}
+
 /// Given a path, query the file system to get information about a file,
 /// directory, etc.
 pub fn metadata(path: &str) -> io::Result<Metadata> {
     crate::root::lookup(None, path)?.get_attr().map(Metadata)
 }
 
+/// Returns the size of the value of the extended attribute `name` of a file
+/// or directory, which is read into `buf` only if it fits.
+///
+/// See [`crate::xattr`] for the filesystems that support them.
+pub fn get_xattr(path: &str, name: &str, buf: &mut [u8]) -> io::Result<usize> {
+    crate::xattr::get(&crate::root::lookup(None, path)?, name, buf)
+}
+
+/// Sets the value of the extended attribute `name` of a file or directory.
+pub fn set_xattr(path: &str, name: &str, value: &[u8], mode: XattrSetMode) -> io::Result<()> {
+    crate::xattr::set(&crate::root::lookup(None, path)?, name, value, mode)
+}
+
+/// Returns the size of the list of the names of the extended attributes of
+/// a file or directory, each followed by a NUL, which is read into `buf`
+/// only if it fits.
+pub fn list_xattr(path: &str, buf: &mut [u8]) -> io::Result<usize> {
+    crate::xattr::list(&crate::root::lookup(None, path)?, buf)
+}
+
+/// Removes the extended attribute `name` of a file or directory.
+pub fn remove_xattr(path: &str, name: &str) -> io::Result<()> {
+    crate::xattr::remove(&crate::root::lookup(None, path)?, name)
+}
+
 /// Creates a new, empty directory at the provided path.
 pub fn create_dir(path: &str) -> io::Result<()> {
     DirBuilder::new().create(path)
@@ -0,0 +1,331 @@
+//! The block cache, and the controls of the filesystem caches.
+//!
+//! The blocks read from the disk are kept in memory, so that the metadata and
+//! the files read again don't go to the device. The cache is write-through:
+//! its blocks are always clean, and can be dropped at any time, either all at
+//! once with [`drop_caches`] (`/proc/sys/vm/drop_caches`), or the least
+//! recently used ones when the cache is full.
+//!
+//! How many blocks the cache holds is governed by [`set_cache_pressure`]
+//! (`/proc/sys/vm/vfs_cache_pressure`): the higher the pressure, the smaller
+//! the cache.
+//!
+//! The I/Os of the files opened with
+//! [`direct`](crate::fops::OpenOptions::direct) I/O (`O_DIRECT`) bypass the
+//! cache, see [`direct_io`]. The bypass is a flag of the request, i.e., of the
+//! task running it, handed down to [`BlockCache`] by the disk: the I/Os of the
+//! other tasks meanwhile are still cached.
+//!
+//! The I/Os going to the disks are traced as the events `block_rq_issue` and
+//! `block_rq_complete` with the `trace` feature.
+//!
+//! axfs keeps no dentries or inodes of its own, the filesystems look their
+//! directories up in the cached blocks. So there's nothing else to drop.
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+#[cfg(feature = "multitask")]
+use alloc::collections::BTreeSet;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+#[cfg(not(feature = "multitask"))]
+use core::sync::atomic::AtomicBool;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use axdriver::prelude::*;
+use axsync::Mutex;
+
+use crate::dev::BLOCK_SIZE;
+
+/// Number of blocks cached at the default pressure (1 MiB).
+const DEFAULT_CAPACITY: usize = 2048;
+
+/// The default cache pressure, as on Linux.
+const DEFAULT_PRESSURE: u32 = 100;
+
+static PRESSURE: AtomicU32 = AtomicU32::new(DEFAULT_PRESSURE);
+
+/// The IDs of the tasks running a direct I/O, see [`direct_io`].
+#[cfg(feature = "multitask")]
+static DIRECT_IO_TASKS: Mutex<BTreeSet<u64>> = Mutex::new(BTreeSet::new());
+
+/// Whether a direct I/O is running, with a single task.
+#[cfg(not(feature = "multitask"))]
+static DIRECT_IO: AtomicBool = AtomicBool::new(false);
+
+/// The caches of all the disks, for [`drop_caches`].
+static CACHES: Mutex<Vec<Weak<BlockCache>>> = Mutex::new(Vec::new());
+
+/// Statistics of the block cache, returned by [`stats`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CacheStats {
+    /// The number of blocks in the cache.
+    pub blocks: usize,
+    /// The number of blocks read from the cache.
+    pub hits: u64,
+    /// The number of blocks read from the disk.
+    pub misses: u64,
+}
+
+struct CachedBlock {
+    /// When the block was last used, the key in [`CacheInner::lru`].
+    stamp: u64,
+    data: Box<[u8; BLOCK_SIZE]>,
+}
+
+#[derive(Default)]
+struct CacheInner {
+    blocks: BTreeMap<u64, CachedBlock>,
+    /// The block IDs by their last use, oldest first.
+    lru: BTreeMap<u64, u64>,
+    next_stamp: u64,
+    hits: u64,
+    misses: u64,
+}
+
+impl CacheInner {
+    fn touch(&mut self, block_id: u64) -> Option<&[u8; BLOCK_SIZE]> {
+        let stamp = self.next_stamp;
+        let block = self.blocks.get_mut(&block_id)?;
+        self.lru.remove(&block.stamp);
+        self.lru.insert(stamp, block_id);
+        block.stamp = stamp;
+        self.next_stamp += 1;
+        Some(&block.data)
+    }
+
+    fn insert(&mut self, block_id: u64, data: &[u8]) {
+        if let Some(block) = self.blocks.get_mut(&block_id) {
+            block.data.copy_from_slice(data);
+            self.touch(block_id);
+            return;
+        }
+        let mut block = CachedBlock {
+            stamp: self.next_stamp,
+            data: Box::new([0; BLOCK_SIZE]),
+        };
+        block.data.copy_from_slice(data);
+        self.lru.insert(block.stamp, block_id);
+        self.blocks.insert(block_id, block);
+        self.next_stamp += 1;
+        self.shrink_to(capacity());
+    }
+
+    fn remove(&mut self, block_id: u64) {
+        if let Some(block) = self.blocks.remove(&block_id) {
+            self.lru.remove(&block.stamp);
+        }
+    }
+
+    /// Drops the least recently used blocks until at most `capacity` are
+    /// left, and returns how many were dropped.
+    fn shrink_to(&mut self, capacity: usize) -> usize {
+        let mut dropped = 0;
+        while self.blocks.len() > capacity {
+            let (_, block_id) = self.lru.pop_first().unwrap();
+            self.blocks.remove(&block_id);
+            dropped += 1;
+        }
+        dropped
+    }
+}
+
+/// The cache of the blocks of a disk.
+pub(crate) struct BlockCache {
+    inner: Mutex<CacheInner>,
+}
+
+impl BlockCache {
+    /// Creates an empty cache, and registers it for [`drop_caches`].
+    pub(crate) fn new() -> Arc<Self> {
+        let cache = Arc::new(Self {
+            inner: Mutex::new(CacheInner::default()),
+        });
+        let mut caches = CACHES.lock();
+        caches.retain(|cache| cache.strong_count() > 0);
+        caches.push(Arc::downgrade(&cache));
+        cache
+    }
+
+    /// Reads the block `block_id` of `dev`, from the cache if it's there and
+    /// not `bypass`ed.
+    pub(crate) fn read_block(
+        &self,
+        dev: &mut AxBlockDevice,
+        block_id: u64,
+        buf: &mut [u8; BLOCK_SIZE],
+        bypass: bool,
+    ) -> DevResult {
+        if bypass {
+            // the cached copy, if any, is the same as on the disk
+            return disk_io(block_id, false, || dev.read_block(block_id, buf));
+        }
+        let mut inner = self.inner.lock();
+        if let Some(data) = inner.touch(block_id) {
+            buf.copy_from_slice(data);
+            inner.hits += 1;
+            return Ok(());
+        }
+        inner.misses += 1;
+        disk_io(block_id, false, || dev.read_block(block_id, buf))?;
+        inner.insert(block_id, buf);
+        Ok(())
+    }
+
+    /// Writes the block `block_id` of `dev` through the cache, or drops it
+    /// from the cache if `bypass`ed.
+    pub(crate) fn write_block(
+        &self,
+        dev: &mut AxBlockDevice,
+        block_id: u64,
+        buf: &[u8],
+        bypass: bool,
+    ) -> DevResult {
+        let mut inner = self.inner.lock();
+        match disk_io(block_id, true, || dev.write_block(block_id, buf)) {
+            Ok(()) if !bypass => {
+                inner.insert(block_id, buf);
+                Ok(())
+            }
+            res => {
+                // the content of the block on the disk is unknown, or mustn't
+                // be cached
+                inner.remove(block_id);
+                res
+            }
+        }
+    }
+}
+
+/// Runs `io`, an I/O of the block `block_id` on the disk.
+#[cfg_attr(not(feature = "trace"), allow(unused_variables))]
+fn disk_io(block_id: u64, write: bool, io: impl FnOnce() -> DevResult) -> DevResult {
+    #[cfg(feature = "trace")]
+    axtrace::tracepoint!("block", "block_rq_issue", block = block_id, write = write);
+    let res = io();
+    #[cfg(feature = "trace")]
+    axtrace::tracepoint!(
+        "block",
+        "block_rq_complete",
+        block = block_id,
+        write = write,
+        error = res.is_err(),
+    );
+    res
+}
+
+/// Runs `f`, a direct I/O of a file by the current task, with the block cache
+/// bypassed: the blocks are read from the disks and written to them without
+/// being cached.
+///
+/// Only the I/Os of the current task until `f` returns bypass the cache,
+/// including the metadata read by the filesystem for this request. The I/Os
+/// of the other tasks meanwhile are cached as usual.
+pub(crate) fn direct_io<T>(f: impl FnOnce() -> T) -> T {
+    #[cfg(feature = "multitask")]
+    {
+        let Some(id) = current_task_id() else {
+            return f();
+        };
+        // a task runs a single request at a time
+        DIRECT_IO_TASKS.lock().insert(id);
+        let res = f();
+        DIRECT_IO_TASKS.lock().remove(&id);
+        res
+    }
+    #[cfg(not(feature = "multitask"))]
+    {
+        DIRECT_IO.store(true, Ordering::Relaxed);
+        let res = f();
+        DIRECT_IO.store(false, Ordering::Relaxed);
+        res
+    }
+}
+
+#[cfg(feature = "multitask")]
+fn current_task_id() -> Option<u64> {
+    axtask::current_may_uninit().map(|task| task.id().as_u64())
+}
+
+/// Whether the I/O requested by the current task bypasses the cache, handed
+/// down to [`BlockCache`] by the disk.
+pub(crate) fn bypassed() -> bool {
+    #[cfg(feature = "multitask")]
+    {
+        current_task_id().is_some_and(|id| DIRECT_IO_TASKS.lock().contains(&id))
+    }
+    #[cfg(not(feature = "multitask"))]
+    {
+        DIRECT_IO.load(Ordering::Relaxed)
+    }
+}
+
+/// Returns the maximum number of blocks in the cache of each disk.
+fn capacity() -> usize {
+    match PRESSURE.load(Ordering::Relaxed) {
+        0 => usize::MAX,
+        pressure => (DEFAULT_CAPACITY * DEFAULT_PRESSURE as usize / pressure as usize).max(1),
+    }
+}
+
+fn caches() -> Vec<Arc<BlockCache>> {
+    CACHES
+        .lock()
+        .iter()
+        .filter_map(|cache| cache.upgrade())
+        .collect()
+}
+
+/// Drops all the cached blocks (`echo 3 > /proc/sys/vm/drop_caches`), and
+/// returns how many were dropped.
+///
+/// The blocks are never dirty, so nothing is written back. This is mostly
+/// useful to measure the performance of cold reads.
+pub fn drop_caches() -> usize {
+    let dropped = caches()
+        .iter()
+        .map(|cache| cache.inner.lock().shrink_to(0))
+        .sum();
+    debug!("dropped {} cached blocks", dropped);
+    dropped
+}
+
+/// Returns the cache pressure (`vfs_cache_pressure`).
+pub fn cache_pressure() -> u32 {
+    PRESSURE.load(Ordering::Relaxed)
+}
+
+/// Sets the cache pressure (`vfs_cache_pressure`), 100 by default.
+///
+/// The cache holds 1 MiB of blocks per disk at the default pressure, and
+/// proportionally less (more) at a higher (lower) pressure. A pressure of 0
+/// means that the blocks are never reclaimed, which may exhaust the memory.
+///
+/// The caches over the new size are shrunk right away.
+pub fn set_cache_pressure(pressure: u32) {
+    PRESSURE.store(pressure, Ordering::Relaxed);
+    let capacity = capacity();
+    for cache in caches() {
+        cache.inner.lock().shrink_to(capacity);
+    }
+    // fails if procfs isn't mounted, e.g. with `myfs`
+    #[cfg(feature = "procfs")]
+    crate::api::write(
+        "/proc/sys/vm/vfs_cache_pressure",
+        alloc::format!("{}\n", pressure),
+    )
+    .ok();
+}
+
+/// Returns the statistics of the block caches of all the disks.
+pub fn stats() -> CacheStats {
+    caches()
+        .iter()
+        .fold(CacheStats::default(), |mut stats, cache| {
+            let inner = cache.inner.lock();
+            stats.blocks += inner.blocks.len();
+            stats.hits += inner.hits;
+            stats.misses += inner.misses;
+            stats
+        })
+}
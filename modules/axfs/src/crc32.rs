@@ -0,0 +1,36 @@
+//! CRC-32 (IEEE 802.3), as used by zip archives.
+
+pub(crate) const CRC_INIT: u32 = !0;
+
+static CRC_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// Updates the (inverted) CRC-32 with `data`.
+pub(crate) fn crc32_update(crc: u32, data: &[u8]) -> u32 {
+    data.iter().fold(crc, |crc, &b| {
+        CRC_TABLE[((crc ^ b as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+/// Computes the CRC-32 of `data`.
+#[cfg(feature = "kv")]
+pub(crate) fn crc32(data: &[u8]) -> u32 {
+    !crc32_update(CRC_INIT, data)
+}
@@ -1,12 +1,19 @@
+use alloc::sync::Arc;
 use axdriver::prelude::*;
 
-const BLOCK_SIZE: usize = 512;
+use crate::cache::BlockCache;
+
+pub(crate) const BLOCK_SIZE: usize = 512;
 
 /// A disk device with a cursor.
+///
+/// The blocks are read and written through a [`BlockCache`], bypassed by the
+/// direct I/Os (see `cache::direct_io`).
 pub struct Disk {
     block_id: u64,
     offset: usize,
     dev: AxBlockDevice,
+    cache: Arc<BlockCache>,
 }
 
 impl Disk {
@@ -17,6 +24,7 @@ impl Disk {
             block_id: 0,
             offset: 0,
             dev,
+            cache: BlockCache::new(),
         }
     }
 
@@ -38,10 +46,12 @@ impl Disk {
 
     /// Read within one block, returns the number of bytes read.
     pub fn read_one(&mut self, buf: &mut [u8]) -> DevResult<usize> {
+        let bypass = crate::cache::bypassed();
         let read_size = if self.offset == 0 && buf.len() >= BLOCK_SIZE {
             // whole block
             let mut data = [0u8; BLOCK_SIZE];
-            self.dev.read_block(self.block_id, &mut data)?;
+            self.cache
+                .read_block(&mut self.dev, self.block_id, &mut data, bypass)?;
             buf[0..BLOCK_SIZE].copy_from_slice(&data);
             // self.dev
             //     .read_block(self.block_id, &mut buf[0..BLOCK_SIZE])?;
@@ -53,7 +63,8 @@ impl Disk {
             let start = self.offset;
             let count = buf.len().min(BLOCK_SIZE - self.offset);
 
-            self.dev.read_block(self.block_id, &mut data)?;
+            self.cache
+                .read_block(&mut self.dev, self.block_id, &mut data, bypass)?;
             buf[..count].copy_from_slice(&data[start..start + count]);
 
             self.offset += count;
@@ -68,9 +79,11 @@ impl Disk {
 
     /// Write within one block, returns the number of bytes written.
     pub fn write_one(&mut self, buf: &[u8]) -> DevResult<usize> {
+        let bypass = crate::cache::bypassed();
         let write_size = if self.offset == 0 && buf.len() >= BLOCK_SIZE {
             // whole block
-            self.dev.write_block(self.block_id, &buf[0..BLOCK_SIZE])?;
+            self.cache
+                .write_block(&mut self.dev, self.block_id, &buf[0..BLOCK_SIZE], bypass)?;
             self.block_id += 1;
             BLOCK_SIZE
         } else {
@@ -79,9 +92,11 @@ impl Disk {
             let start = self.offset;
             let count = buf.len().min(BLOCK_SIZE - self.offset);
 
-            self.dev.read_block(self.block_id, &mut data)?;
+            self.cache
+                .read_block(&mut self.dev, self.block_id, &mut data, bypass)?;
             data[start..start + count].copy_from_slice(&buf[..count]);
-            self.dev.write_block(self.block_id, &data)?;
+            self.cache
+                .write_block(&mut self.dev, self.block_id, &data, bypass)?;
 
             self.offset += count;
             if self.offset >= BLOCK_SIZE {
@@ -96,10 +111,11 @@ impl Disk {
     /// Read a single block starting from the specified offset.
     #[allow(unused)]
     pub fn read_offset(&mut self, offset: usize) -> [u8; BLOCK_SIZE] {
+        let bypass = crate::cache::bypassed();
         let block_id = offset / BLOCK_SIZE;
         let mut block_data = [0u8; BLOCK_SIZE];
-        self.dev
-            .read_block(block_id as u64, &mut block_data)
+        self.cache
+            .read_block(&mut self.dev, block_id as u64, &mut block_data, bypass)
             .unwrap();
         block_data
     }
@@ -112,8 +128,11 @@ impl Disk {
             "Buffer length must be equal to BLOCK_SIZE"
         );
         assert!(offset % BLOCK_SIZE == 0);
+        let bypass = crate::cache::bypassed();
         let block_id = offset / BLOCK_SIZE;
-        self.dev.write_block(block_id as u64, buf).unwrap();
+        self.cache
+            .write_block(&mut self.dev, block_id as u64, buf, bypass)
+            .unwrap();
         Ok(buf.len())
     }
 }
@@ -0,0 +1,73 @@
+//! Conversions between filesystem errors and Linux error numbers.
+//!
+//! [`VfsError`] is the same type as [`AxError`], which `axerrno` already
+//! converts into a [`LinuxError`] with `LinuxError::from`. The other
+//! direction, which `axerrno` lacks, is for the filesystem drivers that report
+//! errno values (e.g. lwext4), converted with [`from_errno`].
+
+use axerrno::{AxError, LinuxError};
+use axfs_vfs::VfsError;
+
+/// The [`AxError`] of each Linux error `axerrno` converts an [`AxError`] into.
+///
+/// When several errors are converted into the same Linux error, the most
+/// general one is given back.
+const LINUX_ERRORS: &[(LinuxError, AxError)] = &[
+    (LinuxError::EADDRINUSE, AxError::AddrInUse),
+    (LinuxError::EEXIST, AxError::AlreadyExists),
+    (LinuxError::EFAULT, AxError::BadAddress),
+    (LinuxError::EINVAL, AxError::InvalidInput),
+    (LinuxError::ECONNREFUSED, AxError::ConnectionRefused),
+    (LinuxError::ECONNRESET, AxError::ConnectionReset),
+    (LinuxError::ENOTEMPTY, AxError::DirectoryNotEmpty),
+    (LinuxError::EIO, AxError::Io),
+    (LinuxError::EISDIR, AxError::IsADirectory),
+    (LinuxError::ENOMEM, AxError::NoMemory),
+    (LinuxError::ENOTDIR, AxError::NotADirectory),
+    (LinuxError::ENOTCONN, AxError::NotConnected),
+    (LinuxError::ENOENT, AxError::NotFound),
+    (LinuxError::EACCES, AxError::PermissionDenied),
+    (LinuxError::EBUSY, AxError::ResourceBusy),
+    (LinuxError::ENOSPC, AxError::StorageFull),
+    (LinuxError::ENOSYS, AxError::Unsupported),
+    (LinuxError::EAGAIN, AxError::WouldBlock),
+];
+
+/// Linux errors without an exact counterpart, and the closest [`AxError`].
+const LINUX_ALIASES: &[(LinuxError, AxError)] = &[
+    (LinuxError::EPERM, AxError::PermissionDenied),
+    (LinuxError::EROFS, AxError::PermissionDenied),
+    (LinuxError::ENXIO, AxError::NotFound),
+    (LinuxError::ENODEV, AxError::NotFound),
+    (LinuxError::ENODATA, AxError::NotFound),
+    (LinuxError::ENAMETOOLONG, AxError::InvalidInput),
+    (LinuxError::ELOOP, AxError::InvalidInput),
+    (LinuxError::EFBIG, AxError::StorageFull),
+    (LinuxError::EDQUOT, AxError::StorageFull),
+    (LinuxError::EOPNOTSUPP, AxError::Unsupported),
+    (LinuxError::EXDEV, AxError::Unsupported),
+    (LinuxError::EBADF, AxError::BadState),
+];
+
+/// Converts a [`LinuxError`] into a [`VfsError`].
+///
+/// Errors that have no counterpart are reported as [`VfsError::Io`].
+pub fn from_linux_error(err: LinuxError) -> VfsError {
+    LINUX_ERRORS
+        .iter()
+        .chain(LINUX_ALIASES)
+        .find(|(l, _)| *l == err)
+        .map_or(VfsError::Io, |&(_, e)| e)
+}
+
+/// Converts an errno value, either positive or negated, into a [`VfsError`].
+///
+/// Unknown values are reported as [`VfsError::Io`].
+pub fn from_errno(errno: i32) -> VfsError {
+    let code = errno.saturating_abs();
+    LINUX_ERRORS
+        .iter()
+        .chain(LINUX_ALIASES)
+        .find(|(l, _)| l.code() == code)
+        .map_or(VfsError::Io, |&(_, e)| e)
+}
@@ -1,11 +1,14 @@
 //! Low-level filesystem operations.
 
+use alloc::sync::Arc;
 use axerrno::{AxError, AxResult, ax_err, ax_err_type};
 use axfs_vfs::{VfsError, VfsNodeRef};
 use axio::SeekFrom;
 use cap_access::{Cap, WithCap};
 use core::fmt;
 
+use crate::dev::BLOCK_SIZE;
+
 #[cfg(feature = "myfs")]
 pub use crate::dev::Disk;
 #[cfg(feature = "myfs")]
@@ -20,10 +23,43 @@ pub type FileAttr = axfs_vfs::VfsNodeAttr;
 /// Alias of [`axfs_vfs::VfsNodePerm`].
 pub type FilePerm = axfs_vfs::VfsNodePerm;
 
+/// The identity of the node of an opened file, compared by address.
+///
+/// It keeps the node alive, so its address isn't reused while it exists.
+#[derive(Clone)]
+pub struct NodeId(VfsNodeRef);
+
+impl NodeId {
+    fn addr(&self) -> usize {
+        Arc::as_ptr(&self.0) as *const () as usize
+    }
+}
+
+impl PartialEq for NodeId {
+    fn eq(&self, other: &Self) -> bool {
+        self.addr() == other.addr()
+    }
+}
+
+impl Eq for NodeId {}
+
+impl PartialOrd for NodeId {
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for NodeId {
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        self.addr().cmp(&other.addr())
+    }
+}
+
 /// An opened file object, with open permissions and a cursor.
 pub struct File {
     node: WithCap<VfsNodeRef>,
     is_append: bool,
+    is_direct: bool,
     offset: u64,
 }
 
@@ -46,6 +82,7 @@ pub struct OpenOptions {
     create: bool,
     create_new: bool,
     directory: bool,
+    direct: bool,
     // system-specific
     _custom_flags: i32,
     _mode: u32,
@@ -64,6 +101,7 @@ impl OpenOptions {
             create: false,
             create_new: false,
             directory: false,
+            direct: false,
             // system-specific
             _custom_flags: 0,
             _mode: 0o666,
@@ -101,6 +139,14 @@ impl OpenOptions {
     pub fn directory(&mut self, directory: bool) {
         self.directory = directory;
     }
+    /// Sets the option for direct I/O (`O_DIRECT`), bypassing the block cache.
+    ///
+    /// The reads and writes must then be aligned to the block size (512
+    /// bytes): their offsets, lengths and buffers. The filesystems with caches
+    /// of their own (ext4) still use them.
+    pub fn direct(&mut self, direct: bool) {
+        self.direct = direct;
+    }
     /// check whether contains directory.
     pub fn has_directory(&self) -> bool {
         self.directory
@@ -151,6 +197,28 @@ impl File {
         self.node.access_or_err(cap, AxError::PermissionDenied)
     }
 
+    /// Runs `io`, a read or write of `len` bytes at `offset` from or to the
+    /// buffer at `buf_addr`, with the block cache bypassed if the file is
+    /// opened for direct I/O.
+    ///
+    /// A direct I/O fails with `InvalidInput` if it isn't aligned to the
+    /// block size.
+    fn do_io<T>(
+        &self,
+        offset: u64,
+        buf_addr: usize,
+        len: usize,
+        io: impl FnOnce() -> AxResult<T>,
+    ) -> AxResult<T> {
+        if !self.is_direct {
+            return io();
+        }
+        if offset % BLOCK_SIZE as u64 != 0 || len % BLOCK_SIZE != 0 || buf_addr % BLOCK_SIZE != 0 {
+            return ax_err!(InvalidInput);
+        }
+        crate::cache::direct_io(io)
+    }
+
     fn _open_at(dir: Option<&VfsNodeRef>, path: &str, opts: &OpenOptions) -> AxResult<Self> {
         debug!("open file: {} {:?}", path, opts);
         if !opts.is_valid() {
@@ -192,6 +260,7 @@ impl File {
         Ok(Self {
             node: WithCap::new(node, access_cap),
             is_append: opts.append,
+            is_direct: opts.direct,
             offset: 0,
         })
     }
@@ -214,7 +283,10 @@ impl File {
     /// After the read, the cursor will be advanced by the number of bytes read.
     pub fn read(&mut self, buf: &mut [u8]) -> AxResult<usize> {
         let node = self.access_node(Cap::READ)?;
-        let read_len = node.read_at(self.offset, buf)?;
+        let offset = self.offset;
+        let read_len = self.do_io(offset, buf.as_ptr() as usize, buf.len(), || {
+            node.read_at(offset, buf)
+        })?;
         self.offset += read_len as u64;
         Ok(read_len)
     }
@@ -224,7 +296,9 @@ impl File {
     /// It does not update the file cursor.
     pub fn read_at(&self, offset: u64, buf: &mut [u8]) -> AxResult<usize> {
         let node = self.access_node(Cap::READ)?;
-        let read_len = node.read_at(offset, buf)?;
+        let read_len = self.do_io(offset, buf.as_ptr() as usize, buf.len(), || {
+            node.read_at(offset, buf)
+        })?;
         Ok(read_len)
     }
 
@@ -240,7 +314,9 @@ impl File {
             self.offset
         };
         let node = self.access_node(Cap::WRITE)?;
-        let write_len = node.write_at(offset, buf)?;
+        let write_len = self.do_io(offset, buf.as_ptr() as usize, buf.len(), || {
+            node.write_at(offset, buf)
+        })?;
         self.offset = offset + write_len as u64;
         Ok(write_len)
     }
@@ -251,7 +327,9 @@ impl File {
     /// It does not update the file cursor.
     pub fn write_at(&self, offset: u64, buf: &[u8]) -> AxResult<usize> {
         let node = self.access_node(Cap::WRITE)?;
-        let write_len = node.write_at(offset, buf)?;
+        let write_len = self.do_io(offset, buf.as_ptr() as usize, buf.len(), || {
+            node.write_at(offset, buf)
+        })?;
         Ok(write_len)
     }
 
@@ -279,6 +357,15 @@ impl File {
     pub fn get_attr(&self) -> AxResult<FileAttr> {
         self.access_node(Cap::empty())?.get_attr()
     }
+
+    /// Returns the identity of the node of the file.
+    ///
+    /// The files opened on the same node have the same identity, but whether
+    /// the opens of a file share a node depends on the filesystem.
+    pub fn node_id(&self) -> NodeId {
+        // Safety: the empty capability is always granted
+        NodeId(unsafe { self.node.access_unchecked() }.clone())
+    }
 }
 
 impl Directory {
@@ -417,6 +504,7 @@ impl fmt::Debug for OpenOptions {
         fmt_opt!(truncate, "TRUNC");
         fmt_opt!(create, "CREATE");
         fmt_opt!(create_new, "CREATE_NEW");
+        fmt_opt!(direct, "DIRECT");
         Ok(())
     }
 }
@@ -1,3 +1,4 @@
+use alloc::format;
 use alloc::sync::Arc;
 use core::cell::UnsafeCell;
 
@@ -6,6 +7,7 @@ use axfs_vfs::{VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsNodeType, VfsOps};
 use axsync::Mutex;
 use fatfs::{Dir, File, LossyOemCpConverter, NullTimeProvider, Read, Seek, SeekFrom, Write};
 
+use crate::api::DiskUsage;
 use crate::dev::Disk;
 
 const BLOCK_SIZE: usize = 512;
@@ -55,6 +57,18 @@ impl FatFileSystem {
         unsafe { *self.root_dir.get() = Some(Self::new_dir(self.inner.root_dir())) }
     }
 
+    pub fn disk_usage(&self) -> VfsResult<DiskUsage> {
+        let stats = self.inner.stats().map_err(as_vfs_err)?;
+        let block_size = stats.cluster_size() as u64;
+        let free = stats.free_clusters() as u64 * block_size;
+        Ok(DiskUsage {
+            block_size,
+            total: stats.total_clusters() as u64 * block_size,
+            free,
+            available: free,
+        })
+    }
+
     fn new_file<IO: IoTrait>(
         file: File<'_, IO, NullTimeProvider, LossyOemCpConverter>,
     ) -> Arc<FileWrapper<IO>> {
@@ -198,6 +212,21 @@ impl<IO: IoTrait> VfsNodeOps for DirWrapper<'static, IO> {
             src_path, dst_path
         );
 
+        if src_path != dst_path && super::eq_ignore_case(src_path, dst_path) {
+            // the destination is found as the source itself, which fatfs
+            // leaves as it is, so the case is changed through another name
+            let tmp_path = format!("{}~", src_path.trim_end_matches('/'));
+            self.0
+                .rename(src_path, &self.0, &tmp_path)
+                .map_err(as_vfs_err)?;
+            return self
+                .0
+                .rename(&tmp_path, &self.0, dst_path)
+                .inspect_err(|_| {
+                    let _ = self.0.rename(&tmp_path, &self.0, src_path);
+                })
+                .map_err(as_vfs_err);
+        }
         self.0
             .rename(src_path, &self.0, dst_path)
             .map_err(as_vfs_err)
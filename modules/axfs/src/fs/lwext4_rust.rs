@@ -1,17 +1,120 @@
 use crate::alloc::string::String;
 use alloc::sync::Arc;
-use axerrno::AxError;
+use alloc::vec;
 use axfs_vfs::{VfsDirEntry, VfsError, VfsNodePerm, VfsResult};
 use axfs_vfs::{VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsNodeType, VfsOps};
 use axsync::Mutex;
+use core::sync::atomic::{AtomicBool, Ordering};
 use lwext4_rust::bindings::{
-    O_CREAT, O_RDONLY, O_RDWR, O_TRUNC, O_WRONLY, SEEK_CUR, SEEK_END, SEEK_SET,
+    O_CREAT, O_RDONLY, O_RDWR, O_TRUNC, O_WRONLY, SEEK_CUR, SEEK_END, SEEK_SET, ext4_getxattr,
+    ext4_listxattr, ext4_removexattr, ext4_setxattr,
 };
 use lwext4_rust::{Ext4BlockWrapper, Ext4File, InodeTypes, KernelDevOp};
 
 use crate::dev::Disk;
+use crate::error::from_errno;
+use crate::xattr::{XATTR_LIST_MAX, XATTR_SIZE_MAX, XattrOps};
 pub const BLOCK_SIZE: usize = 512;
 
+/// Set when the filesystem may be inconsistent, after which all the writes
+/// fail with [`VfsError::PermissionDenied`] (`EROFS`).
+static READ_ONLY: AtomicBool = AtomicBool::new(false);
+
+fn check_writable() -> VfsResult {
+    if READ_ONLY.load(Ordering::Relaxed) {
+        return Err(VfsError::PermissionDenied);
+    }
+    Ok(())
+}
+
+const SUPER_BLOCK_OFFSET: u64 = 1024;
+const SUPER_BLOCK_SIZE: usize = 1024;
+const EXT4_SUPER_MAGIC: u16 = 0xef53;
+const EXT4_STATE_VALID_FS: u16 = 0x1;
+const EXT4_FEATURE_COMPAT_HAS_JOURNAL: u32 = 0x4;
+const EXT4_FEATURE_INCOMPAT_RECOVER: u32 = 0x4;
+
+/// What the superblock tells of how the filesystem was left, read before it
+/// is mounted.
+struct SuperBlockState {
+    /// Whether it was unmounted cleanly.
+    clean: bool,
+    /// The number of errors recorded by the previous mounts.
+    error_count: u32,
+    has_journal: bool,
+    /// Whether the journal has transactions to replay.
+    needs_recovery: bool,
+    /// The first inode of the orphan list, which are the inodes unlinked
+    /// while still open, or being truncated.
+    last_orphan: u32,
+}
+
+impl SuperBlockState {
+    fn read(disk: &mut Disk) -> Option<Self> {
+        let mut sb = [0u8; SUPER_BLOCK_SIZE];
+        disk.set_position(SUPER_BLOCK_OFFSET);
+        let mut read_len = 0;
+        while read_len < sb.len() {
+            match disk.read_one(&mut sb[read_len..]) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => read_len += n,
+            }
+        }
+        disk.set_position(0);
+        if read_len < sb.len() {
+            return None;
+        }
+
+        let u16_at = |off: usize| u16::from_le_bytes([sb[off], sb[off + 1]]);
+        let u32_at = |off: usize| u32::from_le_bytes(sb[off..off + 4].try_into().unwrap());
+        if u16_at(0x38) != EXT4_SUPER_MAGIC {
+            return None;
+        }
+        Some(Self {
+            clean: u16_at(0x3a) == EXT4_STATE_VALID_FS,
+            error_count: u32_at(0x194),
+            has_journal: u32_at(0x5c) & EXT4_FEATURE_COMPAT_HAS_JOURNAL != 0,
+            needs_recovery: u32_at(0x60) & EXT4_FEATURE_INCOMPAT_RECOVER != 0,
+            last_orphan: u32_at(0xe8),
+        })
+    }
+
+    /// Reports how the filesystem was left, and returns `true` if it may be
+    /// inconsistent, so that it should only be read.
+    ///
+    /// The journal itself is replayed when the filesystem is mounted, as
+    /// `Ext4BlockWrapper` calls `ext4_recover` before it starts the journal.
+    fn check(&self) -> bool {
+        let mut read_only = false;
+        if self.needs_recovery && self.has_journal {
+            info!("ext4: replaying the journal");
+        } else if self.needs_recovery {
+            warn!("ext4: a journal recovery is needed, but there is no journal");
+            read_only = true;
+        } else if !self.clean && !self.has_journal {
+            // lwext4 marks the filesystem as having errors while it's
+            // mounted, so this is common and doesn't mean it's corrupted
+            warn!("ext4: not cleanly unmounted, and has no journal to replay");
+        }
+        if self.error_count > 0 {
+            warn!(
+                "ext4: {} errors recorded by the previous mounts",
+                self.error_count
+            );
+            read_only = true;
+        }
+        if self.last_orphan != 0 {
+            // lwext4 doesn't process the orphan list, so their blocks stay
+            // allocated until the filesystem is checked
+            warn!(
+                "ext4: orphan inodes from inode {} are left to e2fsck",
+                self.last_orphan
+            );
+        }
+        read_only
+    }
+}
+
 #[allow(dead_code)]
 pub struct Ext4FileSystem {
     inner: Ext4BlockWrapper<Disk>,
@@ -28,14 +131,19 @@ impl Ext4FileSystem {
     }
 
     #[cfg(not(feature = "use-ramdisk"))]
-    pub fn new(disk: Disk) -> Self {
+    pub fn new(mut disk: Disk) -> Self {
         info!(
             "Got Disk size:{}, position:{}",
             disk.size(),
             disk.position()
         );
-        let inner =
-            Ext4BlockWrapper::<Disk>::new(disk).expect("failed to initialize EXT4 filesystem");
+        if SuperBlockState::read(&mut disk).is_some_and(|sb| sb.check()) {
+            warn!("ext4: mounted read-only, run e2fsck on the image");
+            READ_ONLY.store(true, Ordering::Relaxed);
+        }
+        let inner = Ext4BlockWrapper::<Disk>::new(disk).unwrap_or_else(|e| {
+            panic!("failed to initialize EXT4 filesystem: {:?}", from_errno(e))
+        });
         let root = Arc::new(FileWrapper::new("/", InodeTypes::EXT4_DE_DIR));
         Self { inner, root }
     }
@@ -119,8 +227,7 @@ impl VfsNodeOps for FileWrapper {
         let size = if vtype == VfsNodeType::File {
             let path = file.get_path();
             let path = path.to_str().unwrap();
-            file.file_open(path, O_RDONLY)
-                .map_err(|e| <i32 as TryInto<AxError>>::try_into(e).unwrap())?;
+            file.file_open(path, O_RDONLY).map_err(from_errno)?;
             let fsize = file.file_size();
             let _ = file.file_close();
             fsize
@@ -142,6 +249,7 @@ impl VfsNodeOps for FileWrapper {
 
     fn create(&self, path: &str, ty: VfsNodeType) -> VfsResult {
         info!("create {:?} on Ext4fs: {}", ty, path);
+        check_writable()?;
         let fpath = self.path_deal_with(path);
         let fpath = fpath.as_str();
         if fpath.is_empty() {
@@ -163,21 +271,18 @@ impl VfsNodeOps for FileWrapper {
             Ok(())
         } else {
             if types == InodeTypes::EXT4_DE_DIR {
-                file.dir_mk(fpath)
-                    .map(|_v| ())
-                    .map_err(|e| e.try_into().unwrap())
+                file.dir_mk(fpath).map(|_v| ()).map_err(from_errno)
             } else {
                 file.file_open(fpath, O_WRONLY | O_CREAT | O_TRUNC)
                     .expect("create file failed");
-                file.file_close()
-                    .map(|_v| ())
-                    .map_err(|e| e.try_into().unwrap())
+                file.file_close().map(|_v| ()).map_err(from_errno)
             }
         }
     }
 
     fn remove(&self, path: &str) -> VfsResult {
         info!("remove ext4fs: {}", path);
+        check_writable()?;
         let fpath = self.path_deal_with(path);
         let fpath = fpath.as_str();
 
@@ -186,13 +291,9 @@ impl VfsNodeOps for FileWrapper {
         let mut file = self.0.lock();
         if file.check_inode_exist(fpath, InodeTypes::EXT4_DE_DIR) {
             // Recursive directory remove
-            file.dir_rm(fpath)
-                .map(|_v| ())
-                .map_err(|e| e.try_into().unwrap())
+            file.dir_rm(fpath).map(|_v| ()).map_err(from_errno)
         } else {
-            file.file_remove(fpath)
-                .map(|_v| ())
-                .map_err(|e| e.try_into().unwrap())
+            file.file_remove(fpath).map(|_v| ()).map_err(from_errno)
         }
     }
 
@@ -275,50 +376,51 @@ impl VfsNodeOps for FileWrapper {
         let mut file = self.0.lock();
         let path = file.get_path();
         let path = path.to_str().unwrap();
-        file.file_open(path, O_RDONLY)
-            .map_err(|e| <i32 as TryInto<AxError>>::try_into(e).unwrap())?;
+        file.file_open(path, O_RDONLY).map_err(from_errno)?;
 
         file.file_seek(offset as i64, SEEK_SET)
-            .map_err(|e| <i32 as TryInto<AxError>>::try_into(e).unwrap())?;
+            .map_err(from_errno)?;
         let r = file.file_read(buf);
 
         let _ = file.file_close();
-        r.map_err(|e| e.try_into().unwrap())
+        r.map_err(from_errno)
     }
 
     fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        check_writable()?;
         let mut file = self.0.lock();
         let path = file.get_path();
         let path = path.to_str().unwrap();
-        file.file_open(path, O_RDWR)
-            .map_err(|e| <i32 as TryInto<AxError>>::try_into(e).unwrap())?;
+        file.file_open(path, O_RDWR).map_err(from_errno)?;
 
         file.file_seek(offset as i64, SEEK_SET)
-            .map_err(|e| <i32 as TryInto<AxError>>::try_into(e).unwrap())?;
+            .map_err(from_errno)?;
         let r = file.file_write(buf);
 
         let _ = file.file_close();
-        r.map_err(|e| e.try_into().unwrap())
+        r.map_err(from_errno)
     }
 
     fn truncate(&self, size: u64) -> VfsResult {
+        check_writable()?;
         let mut file = self.0.lock();
         let path = file.get_path();
         let path = path.to_str().unwrap();
         file.file_open(path, O_RDWR | O_CREAT | O_TRUNC)
-            .map_err(|e| <i32 as TryInto<AxError>>::try_into(e).unwrap())?;
+            .map_err(from_errno)?;
 
         let t = file.file_truncate(size);
 
         let _ = file.file_close();
-        t.map(|_v| ()).map_err(|e| e.try_into().unwrap())
+        t.map(|_v| ()).map_err(from_errno)
     }
 
     fn rename(&self, src_path: &str, dst_path: &str) -> VfsResult {
+        check_writable()?;
         let mut file = self.0.lock();
         file.file_rename(src_path, dst_path)
             .map(|_v| ())
-            .map_err(|e| e.try_into().unwrap())
+            .map_err(from_errno)
     }
 
     fn as_any(&self) -> &dyn core::any::Any {
@@ -326,6 +428,82 @@ impl VfsNodeOps for FileWrapper {
     }
 }
 
+/// The extended attributes are read whole into a buffer of the maximum
+/// size, as lwext4 fails if the value doesn't fit.
+impl XattrOps for FileWrapper {
+    fn get_xattr(&self, name: &str, buf: &mut [u8]) -> VfsResult<usize> {
+        let path = self.0.lock().get_path();
+        let mut value = vec![0u8; XATTR_SIZE_MAX];
+        let mut size = 0;
+        let ret = unsafe {
+            ext4_getxattr(
+                path.as_ptr(),
+                name.as_ptr().cast(),
+                name.len(),
+                value.as_mut_ptr().cast(),
+                value.len(),
+                &mut size,
+            )
+        };
+        if ret != 0 {
+            return Err(from_errno(ret));
+        }
+        if let Some(dst) = buf.get_mut(..size) {
+            dst.copy_from_slice(&value[..size]);
+        }
+        Ok(size)
+    }
+
+    fn set_xattr(&self, name: &str, value: &[u8]) -> VfsResult {
+        check_writable()?;
+        let path = self.0.lock().get_path();
+        let ret = unsafe {
+            ext4_setxattr(
+                path.as_ptr(),
+                name.as_ptr().cast(),
+                name.len(),
+                value.as_ptr().cast(),
+                value.len(),
+            )
+        };
+        if ret != 0 {
+            return Err(from_errno(ret));
+        }
+        Ok(())
+    }
+
+    fn list_xattr(&self, buf: &mut [u8]) -> VfsResult<usize> {
+        let path = self.0.lock().get_path();
+        let mut list = vec![0u8; XATTR_LIST_MAX];
+        let mut size = 0;
+        let ret = unsafe {
+            ext4_listxattr(
+                path.as_ptr(),
+                list.as_mut_ptr().cast(),
+                list.len(),
+                &mut size,
+            )
+        };
+        if ret != 0 {
+            return Err(from_errno(ret));
+        }
+        if let Some(dst) = buf.get_mut(..size) {
+            dst.copy_from_slice(&list[..size]);
+        }
+        Ok(size)
+    }
+
+    fn remove_xattr(&self, name: &str) -> VfsResult {
+        check_writable()?;
+        let path = self.0.lock().get_path();
+        let ret = unsafe { ext4_removexattr(path.as_ptr(), name.as_ptr().cast(), name.len()) };
+        if ret != 0 {
+            return Err(from_errno(ret));
+        }
+        Ok(())
+    }
+}
+
 impl Drop for FileWrapper {
     fn drop(&mut self) {
         let mut file = self.0.lock();
@@ -3,14 +3,29 @@ cfg_if::cfg_if! {
         pub mod myfs;
     } else if #[cfg(feature = "lwext4_rs")] {
         pub mod lwext4_rust;
+    } else if #[cfg(feature = "squashfs")] {
+        pub mod squashfs;
     } else if #[cfg(feature = "fatfs")] {
         pub mod fatfs;
     }
 
 }
 
+#[cfg(feature = "ninep")]
+pub mod ninep;
+
+#[cfg(feature = "overlay")]
+pub mod overlay;
+
 #[cfg(feature = "devfs")]
 pub use axfs_devfs as devfs;
 
 #[cfg(feature = "ramfs")]
 pub use axfs_ramfs as ramfs;
+
+/// Compares two names as the filesystems that ignore the case do (e.g. FAT).
+pub(crate) fn eq_ignore_case(a: &str, b: &str) -> bool {
+    a.chars()
+        .flat_map(char::to_lowercase)
+        .eq(b.chars().flat_map(char::to_lowercase))
+}
@@ -0,0 +1,720 @@
+//! A client of the 9P2000.L protocol, to mount a directory shared by the
+//! host (e.g. with QEMU's `-fsdev local,...`) through a 9P transport.
+//!
+//! Each node holds a fid walked to its path, and another one opened by
+//! [`VfsNodeOps::open`] for reading and writing, which are both clunked when
+//! the node is dropped. Nothing is cached: every operation is a request to
+//! the server, so the changes made by the host are seen at once.
+
+use alloc::{string::String, sync::Arc, vec, vec::Vec};
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use axdriver::prelude::*;
+use axfs_vfs::{VfsDirEntry, VfsError, VfsNodePerm, VfsResult};
+use axfs_vfs::{VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsNodeType, VfsOps};
+use axsync::Mutex;
+
+const VERSION: &str = "9P2000.L";
+const NOTAG: u16 = !0;
+const NOFID: u32 = !0;
+/// The tag of the requests, which are sent one at a time.
+const TAG: u16 = 1;
+/// The maximum number of names in a `Twalk`.
+const MAX_WALK_NAMES: usize = 16;
+
+// Types of the messages, the response of each request being the next one.
+const RLERROR: u8 = 7;
+const TLOPEN: u8 = 12;
+const TLCREATE: u8 = 14;
+const TGETATTR: u8 = 24;
+const TSETATTR: u8 = 26;
+const TREADDIR: u8 = 40;
+const TFSYNC: u8 = 50;
+const TMKDIR: u8 = 72;
+const TRENAMEAT: u8 = 74;
+const TUNLINKAT: u8 = 76;
+const TVERSION: u8 = 100;
+const TATTACH: u8 = 104;
+const TWALK: u8 = 110;
+const TREAD: u8 = 116;
+const TWRITE: u8 = 118;
+const TCLUNK: u8 = 120;
+
+const QTDIR: u8 = 0x80;
+
+const O_RDONLY: u32 = 0;
+const O_RDWR: u32 = 2;
+const O_DIRECTORY: u32 = 0o200000;
+const AT_REMOVEDIR: u32 = 0x200;
+const P9_GETATTR_BASIC: u64 = 0x7ff;
+const P9_SETATTR_SIZE: u32 = 0x8;
+
+/// The size of the header of `Rread`, before the data.
+const READ_HEADER_SIZE: usize = 11;
+/// The size of the header of `Twrite`, before the data.
+const WRITE_HEADER_SIZE: usize = 23;
+
+/// A request being built.
+struct Request(Vec<u8>);
+
+impl Request {
+    fn new(ty: u8) -> Self {
+        Self::with_tag(ty, TAG)
+    }
+
+    fn with_tag(ty: u8, tag: u16) -> Self {
+        let mut buf = Vec::with_capacity(64);
+        buf.extend_from_slice(&[0; 4]); // size, filled when sent
+        buf.push(ty);
+        buf.extend_from_slice(&tag.to_le_bytes());
+        Self(buf)
+    }
+
+    fn u16(mut self, v: u16) -> Self {
+        self.0.extend_from_slice(&v.to_le_bytes());
+        self
+    }
+
+    fn u32(mut self, v: u32) -> Self {
+        self.0.extend_from_slice(&v.to_le_bytes());
+        self
+    }
+
+    fn u64(mut self, v: u64) -> Self {
+        self.0.extend_from_slice(&v.to_le_bytes());
+        self
+    }
+
+    fn str(self, s: &str) -> Self {
+        let mut req = self.u16(s.len() as u16);
+        req.0.extend_from_slice(s.as_bytes());
+        req
+    }
+
+    fn data(mut self, data: &[u8]) -> Self {
+        self.0.extend_from_slice(data);
+        self
+    }
+
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+}
+
+/// A response being read, after its header.
+struct Response<'a>(&'a [u8]);
+
+impl<'a> Response<'a> {
+    fn take(&mut self, n: usize) -> VfsResult<&'a [u8]> {
+        if self.0.len() < n {
+            return Err(VfsError::InvalidData);
+        }
+        let (head, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> VfsResult<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> VfsResult<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> VfsResult<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> VfsResult<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn str(&mut self) -> VfsResult<&'a str> {
+        let len = self.u16()? as usize;
+        core::str::from_utf8(self.take(len)?).map_err(|_| VfsError::InvalidData)
+    }
+
+    /// Reads a qid, returns its type.
+    fn qid(&mut self) -> VfsResult<u8> {
+        let ty = self.u8()?;
+        self.take(12)?; // version, path
+        Ok(ty)
+    }
+}
+
+/// Sends `req` through `dev`, returns the body of the response written into
+/// `resp`.
+fn transact<'a>(
+    dev: &mut AxNinePDevice,
+    mut req: Request,
+    resp: &'a mut [u8],
+) -> VfsResult<Response<'a>> {
+    let ty = req.0[4];
+    let size = req.len() as u32;
+    req.0[..4].copy_from_slice(&size.to_le_bytes());
+    let len = dev.request(&req.0, resp).map_err(|e| {
+        warn!("9P request failed: {:?}", e);
+        VfsError::Io
+    })?;
+    let resp = &resp[..len];
+    if resp.len() < 7 {
+        return Err(VfsError::InvalidData);
+    }
+    let mut body = Response(&resp[7..]);
+    match resp[4] {
+        t if t == ty + 1 => Ok(body),
+        RLERROR => Err(errno_to_vfs_err(body.u32()?)),
+        t => {
+            warn!("unexpected 9P response type {} to {}", t, ty);
+            Err(VfsError::InvalidData)
+        }
+    }
+}
+
+struct Transport {
+    dev: AxNinePDevice,
+    resp: Vec<u8>,
+}
+
+/// The connection to the server.
+struct Client {
+    transport: Mutex<Transport>,
+    msize: usize,
+    next_fid: AtomicU32,
+}
+
+impl Client {
+    /// Sends `req`, and calls `f` on the response.
+    fn rpc<T>(&self, req: Request, f: impl FnOnce(Response) -> VfsResult<T>) -> VfsResult<T> {
+        if req.len() > self.msize {
+            return Err(VfsError::InvalidInput);
+        }
+        let mut transport = self.transport.lock();
+        let Transport { dev, resp } = &mut *transport;
+        f(transact(dev, req, resp)?)
+    }
+
+    fn alloc_fid(&self) -> u32 {
+        self.next_fid.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Walks from `fid` along `names` to a new fid, returns it and the type
+    /// of the qid it ends at (`None` if `names` is empty).
+    fn walk(&self, fid: u32, names: &[&str]) -> VfsResult<(u32, Option<u8>)> {
+        let newfid = self.alloc_fid();
+        let mut from = fid;
+        let mut qid_type = None;
+        let mut chunks = names.chunks(MAX_WALK_NAMES);
+        // walk at least once, to clone `fid` if there is no name
+        let mut chunk = chunks.next().unwrap_or(&[]);
+        loop {
+            let mut req = Request::new(TWALK)
+                .u32(from)
+                .u32(newfid)
+                .u16(chunk.len() as u16);
+            for name in chunk {
+                req = req.str(name);
+            }
+            let res = self.rpc(req, |mut resp| {
+                let nwqid = resp.u16()? as usize;
+                let mut last = None;
+                for _ in 0..nwqid {
+                    last = Some(resp.qid()?);
+                }
+                Ok((nwqid, last))
+            });
+            match res {
+                Ok((nwqid, last)) if nwqid == chunk.len() => qid_type = last.or(qid_type),
+                res => {
+                    // `newfid` is only created if the whole walk succeeds,
+                    // but it's still there if walking from itself
+                    if from == newfid {
+                        self.clunk(newfid);
+                    }
+                    return Err(res.err().unwrap_or(VfsError::NotFound));
+                }
+            }
+            from = newfid;
+            match chunks.next() {
+                Some(next) => chunk = next,
+                None => return Ok((newfid, qid_type)),
+            }
+        }
+    }
+
+    fn clunk(&self, fid: u32) {
+        if let Err(e) = self.rpc(Request::new(TCLUNK).u32(fid), |_| Ok(())) {
+            warn!("failed to clunk 9P fid {}: {:?}", fid, e);
+        }
+    }
+
+    /// Opens `fid` with `flags`, returns the maximum size of an I/O.
+    fn lopen(&self, fid: u32, flags: u32) -> VfsResult<usize> {
+        self.rpc(Request::new(TLOPEN).u32(fid).u32(flags), |mut resp| {
+            resp.qid()?;
+            Ok(resp.u32()? as usize)
+        })
+    }
+}
+
+/// Splits `path` into the names to walk along.
+fn split_path(path: &str) -> Vec<&str> {
+    path.split('/')
+        .filter(|name| !name.is_empty() && *name != ".")
+        .collect()
+}
+
+/// Splits `path` into the names of its parent, and its last name.
+fn split_parent(path: &str) -> VfsResult<(Vec<&str>, &str)> {
+    let mut names = split_path(path);
+    match names.pop() {
+        Some(name) if name != ".." => Ok((names, name)),
+        _ => Err(VfsError::InvalidInput),
+    }
+}
+
+fn errno_to_vfs_err(errno: u32) -> VfsError {
+    match errno {
+        1 | 13 | 30 => VfsError::PermissionDenied, // EPERM, EACCES, EROFS
+        2 => VfsError::NotFound,
+        12 => VfsError::NoMemory,
+        17 => VfsError::AlreadyExists,
+        20 => VfsError::NotADirectory,
+        21 => VfsError::IsADirectory,
+        22 | 36 => VfsError::InvalidInput, // EINVAL, ENAMETOOLONG
+        28 => VfsError::StorageFull,
+        39 => VfsError::DirectoryNotEmpty,
+        95 => VfsError::Unsupported,
+        _ => VfsError::Io,
+    }
+}
+
+fn mode_to_node_type(mode: u32) -> VfsNodeType {
+    match mode & 0o170000 {
+        0o010000 => VfsNodeType::Fifo,
+        0o020000 => VfsNodeType::CharDevice,
+        0o040000 => VfsNodeType::Dir,
+        0o060000 => VfsNodeType::BlockDevice,
+        0o120000 => VfsNodeType::SymLink,
+        0o140000 => VfsNodeType::Socket,
+        _ => VfsNodeType::File,
+    }
+}
+
+fn dirent_type_to_node_type(ty: u8) -> VfsNodeType {
+    match ty {
+        1 => VfsNodeType::Fifo,
+        2 => VfsNodeType::CharDevice,
+        4 => VfsNodeType::Dir,
+        6 => VfsNodeType::BlockDevice,
+        10 => VfsNodeType::SymLink,
+        12 => VfsNodeType::Socket,
+        _ => VfsNodeType::File,
+    }
+}
+
+/// A fid opened for I/O.
+#[derive(Clone, Copy)]
+struct OpenFid {
+    fid: u32,
+    iounit: usize,
+}
+
+/// A file or directory on the server.
+pub struct NinePNode {
+    client: Arc<Client>,
+    fid: u32,
+    is_dir: bool,
+    opened: Mutex<Option<OpenFid>>,
+    /// The index of the next entry of `read_dir`, and its offset.
+    dir_pos: Mutex<(usize, u64)>,
+    /// Where the filesystem is mounted, to turn the absolute paths given to
+    /// `rename` into its own.
+    mount_path: Arc<Mutex<String>>,
+}
+
+impl NinePNode {
+    fn new(
+        client: Arc<Client>,
+        mount_path: Arc<Mutex<String>>,
+        fid: u32,
+        is_dir: bool,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            client,
+            fid,
+            is_dir,
+            opened: Mutex::new(None),
+            dir_pos: Mutex::new((0, 0)),
+            mount_path,
+        })
+    }
+
+    fn new_child(&self, fid: u32, is_dir: bool) -> Arc<Self> {
+        Self::new(self.client.clone(), self.mount_path.clone(), fid, is_dir)
+    }
+
+    /// Returns the opened fid, opens one if [`open`](VfsNodeOps::open)
+    /// hasn't been called.
+    fn opened(&self) -> VfsResult<OpenFid> {
+        let mut opened = self.opened.lock();
+        if let Some(open_fid) = *opened {
+            return Ok(open_fid);
+        }
+        let (fid, _) = self.client.walk(self.fid, &[])?;
+        let res = if self.is_dir {
+            self.client.lopen(fid, O_RDONLY | O_DIRECTORY)
+        } else {
+            // fall back to read-only for the files that can't be written
+            self.client
+                .lopen(fid, O_RDWR)
+                .or_else(|_| self.client.lopen(fid, O_RDONLY))
+        };
+        let iounit = match res {
+            Ok(iounit) => iounit,
+            Err(e) => {
+                self.client.clunk(fid);
+                return Err(e);
+            }
+        };
+        let open_fid = OpenFid { fid, iounit };
+        *opened = Some(open_fid);
+        Ok(open_fid)
+    }
+
+    /// Returns the maximum size of the data of a read or write.
+    fn io_size(&self, open_fid: OpenFid, header_size: usize) -> usize {
+        let max = self.client.msize - header_size;
+        match open_fid.iounit {
+            0 => max,
+            iounit => iounit.min(max),
+        }
+    }
+
+    /// Walks to the parent of `path` and calls `f` with its fid and the last
+    /// name of `path`.
+    fn with_parent<T>(
+        &self,
+        path: &str,
+        f: impl FnOnce(u32, &str) -> VfsResult<T>,
+    ) -> VfsResult<T> {
+        let (names, name) = split_parent(path)?;
+        let (dfid, _) = self.client.walk(self.fid, &names)?;
+        let res = f(dfid, name);
+        self.client.clunk(dfid);
+        res
+    }
+}
+
+impl Drop for NinePNode {
+    fn drop(&mut self) {
+        if let Some(open_fid) = self.opened.get_mut().take() {
+            self.client.clunk(open_fid.fid);
+        }
+        self.client.clunk(self.fid);
+    }
+}
+
+impl VfsNodeOps for NinePNode {
+    fn open(&self) -> VfsResult {
+        self.opened().map(|_| ())
+    }
+
+    fn release(&self) -> VfsResult {
+        if let Some(open_fid) = self.opened.lock().take() {
+            self.client.clunk(open_fid.fid);
+        }
+        Ok(())
+    }
+
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        let req = Request::new(TGETATTR).u32(self.fid).u64(P9_GETATTR_BASIC);
+        self.client.rpc(req, |mut resp| {
+            resp.u64()?; // valid
+            resp.qid()?;
+            let mode = resp.u32()?;
+            resp.take(4 + 4 + 8 + 8)?; // uid, gid, nlink, rdev
+            let size = resp.u64()?;
+            resp.u64()?; // blksize
+            let blocks = resp.u64()?;
+            let perm = VfsNodePerm::from_bits_truncate((mode & 0o777) as u16);
+            Ok(VfsNodeAttr::new(
+                perm,
+                mode_to_node_type(mode),
+                size,
+                blocks,
+            ))
+        })
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        if self.is_dir {
+            return Err(VfsError::IsADirectory);
+        }
+        let open_fid = self.opened()?;
+        let max = self.io_size(open_fid, READ_HEADER_SIZE);
+        let mut read = 0;
+        while read < buf.len() {
+            let count = (buf.len() - read).min(max);
+            let req = Request::new(TREAD)
+                .u32(open_fid.fid)
+                .u64(offset + read as u64)
+                .u32(count as u32);
+            let n = self.client.rpc(req, |mut resp| {
+                let n = (resp.u32()? as usize).min(count);
+                buf[read..read + n].copy_from_slice(resp.take(n)?);
+                Ok(n)
+            })?;
+            read += n;
+            if n < count {
+                break;
+            }
+        }
+        Ok(read)
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        if self.is_dir {
+            return Err(VfsError::IsADirectory);
+        }
+        let open_fid = self.opened()?;
+        let max = self.io_size(open_fid, WRITE_HEADER_SIZE);
+        let mut written = 0;
+        while written < buf.len() {
+            let data = &buf[written..buf.len().min(written + max)];
+            let req = Request::new(TWRITE)
+                .u32(open_fid.fid)
+                .u64(offset + written as u64)
+                .u32(data.len() as u32)
+                .data(data);
+            let n = self.client.rpc(req, |mut resp| Ok(resp.u32()? as usize))?;
+            written += n;
+            if n < data.len() {
+                break;
+            }
+        }
+        Ok(written)
+    }
+
+    fn fsync(&self) -> VfsResult {
+        match *self.opened.lock() {
+            Some(open_fid) => {
+                let req = Request::new(TFSYNC).u32(open_fid.fid).u32(0);
+                self.client.rpc(req, |_| Ok(()))
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn truncate(&self, size: u64) -> VfsResult {
+        if self.is_dir {
+            return Err(VfsError::IsADirectory);
+        }
+        let req = Request::new(TSETATTR)
+            .u32(self.fid)
+            .u32(P9_SETATTR_SIZE)
+            .u32(0) // mode
+            .u32(0) // uid
+            .u32(0) // gid
+            .u64(size)
+            .u64(0) // atime
+            .u64(0)
+            .u64(0) // mtime
+            .u64(0);
+        self.client.rpc(req, |_| Ok(()))
+    }
+
+    fn parent(&self) -> Option<VfsNodeRef> {
+        let (fid, _) = self.client.walk(self.fid, &[".."]).ok()?;
+        Some(self.new_child(fid, true))
+    }
+
+    fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
+        debug!("lookup at 9p: {}", path);
+        if !self.is_dir {
+            return Err(VfsError::NotADirectory);
+        }
+        let names = split_path(path);
+        if names.is_empty() {
+            return Ok(self.clone());
+        }
+        let (fid, qid_type) = self.client.walk(self.fid, &names)?;
+        let is_dir = qid_type.is_some_and(|ty| ty & QTDIR != 0);
+        Ok(self.new_child(fid, is_dir))
+    }
+
+    fn create(&self, path: &str, ty: VfsNodeType) -> VfsResult {
+        debug!("create {:?} at 9p: {}", ty, path);
+        if split_path(path).is_empty() {
+            return Ok(());
+        }
+        self.with_parent(path, |dfid, name| match ty {
+            VfsNodeType::File => {
+                // `Tlcreate` turns the fid of the directory into the new file
+                let (fid, _) = self.client.walk(dfid, &[])?;
+                let req = Request::new(TLCREATE)
+                    .u32(fid)
+                    .str(name)
+                    .u32(O_RDWR)
+                    .u32(0o644)
+                    .u32(0);
+                let res = self.client.rpc(req, |_| Ok(()));
+                self.client.clunk(fid);
+                res
+            }
+            VfsNodeType::Dir => {
+                let req = Request::new(TMKDIR).u32(dfid).str(name).u32(0o755).u32(0);
+                self.client.rpc(req, |_| Ok(()))
+            }
+            _ => Err(VfsError::Unsupported),
+        })
+    }
+
+    fn remove(&self, path: &str) -> VfsResult {
+        debug!("remove at 9p: {}", path);
+        let names = split_path(path);
+        if names.is_empty() {
+            return Err(VfsError::InvalidInput);
+        }
+        let (fid, qid_type) = self.client.walk(self.fid, &names)?;
+        self.client.clunk(fid);
+        let flags = match qid_type {
+            Some(ty) if ty & QTDIR != 0 => AT_REMOVEDIR,
+            _ => 0,
+        };
+        self.with_parent(path, |dfid, name| {
+            let req = Request::new(TUNLINKAT).u32(dfid).str(name).u32(flags);
+            self.client.rpc(req, |_| Ok(()))
+        })
+    }
+
+    fn read_dir(&self, start_idx: usize, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
+        if !self.is_dir {
+            return Err(VfsError::NotADirectory);
+        }
+        let open_fid = self.opened()?;
+        let mut dir_pos = self.dir_pos.lock();
+        if dir_pos.0 > start_idx {
+            // rewind, the entries can only be read from an offset
+            *dir_pos = (0, 0);
+        }
+        let count = self.io_size(open_fid, READ_HEADER_SIZE) as u32;
+        let mut filled = 0;
+        while filled < dirents.len() {
+            let req = Request::new(TREADDIR)
+                .u32(open_fid.fid)
+                .u64(dir_pos.1)
+                .u32(count);
+            let end = self.client.rpc(req, |mut resp| {
+                let len = resp.u32()? as usize;
+                let mut entries = Response(resp.take(len)?);
+                if entries.0.is_empty() {
+                    return Ok(true);
+                }
+                while !entries.0.is_empty() && filled < dirents.len() {
+                    entries.qid()?;
+                    let offset = entries.u64()?;
+                    let ty = entries.u8()?;
+                    let name = entries.str()?;
+                    if dir_pos.0 >= start_idx {
+                        dirents[filled] = VfsDirEntry::new(name, dirent_type_to_node_type(ty));
+                        filled += 1;
+                    }
+                    *dir_pos = (dir_pos.0 + 1, offset);
+                }
+                Ok(false)
+            })?;
+            if end {
+                break;
+            }
+        }
+        Ok(filled)
+    }
+
+    fn rename(&self, src_path: &str, dst_path: &str) -> VfsResult {
+        debug!(
+            "rename at 9p, src_path: {}, dst_path: {}",
+            src_path, dst_path
+        );
+        let dst_path = {
+            let mount_path = self.mount_path.lock();
+            let dst = dst_path.trim_start_matches('/');
+            let mount = mount_path.trim_start_matches('/');
+            match dst.strip_prefix(mount) {
+                Some(rest) if !mount.is_empty() && (rest.is_empty() || rest.starts_with('/')) => {
+                    String::from(rest)
+                }
+                _ => String::from(dst_path),
+            }
+        };
+        self.with_parent(src_path, |old_dfid, old_name| {
+            self.with_parent(&dst_path, |new_dfid, new_name| {
+                let req = Request::new(TRENAMEAT)
+                    .u32(old_dfid)
+                    .str(old_name)
+                    .u32(new_dfid)
+                    .str(new_name);
+                self.client.rpc(req, |_| Ok(()))
+            })
+        })
+    }
+
+    fn as_any(&self) -> &dyn core::any::Any {
+        self
+    }
+}
+
+/// A filesystem shared by a 9P server.
+pub struct NinePFileSystem {
+    root: Arc<NinePNode>,
+}
+
+impl NinePFileSystem {
+    /// Connects to the server through `dev`, and attaches to its filesystem.
+    pub fn new(mut dev: AxNinePDevice) -> VfsResult<Self> {
+        let max_msg_size = dev.max_msg_size();
+        let mut resp = vec![0; max_msg_size];
+        // the version is negotiated before any other request
+        let req = Request::with_tag(TVERSION, NOTAG)
+            .u32(max_msg_size as u32)
+            .str(VERSION);
+        let (msize, version) = {
+            let mut body = transact(&mut dev, req, &mut resp)?;
+            (body.u32()? as usize, String::from(body.str()?))
+        };
+        if version != VERSION {
+            warn!("9P server only supports {:?}", version);
+            return Err(VfsError::Unsupported);
+        }
+
+        let client = Arc::new(Client {
+            transport: Mutex::new(Transport { dev, resp }),
+            msize: msize.min(max_msg_size),
+            next_fid: AtomicU32::new(0),
+        });
+        let root_fid = client.alloc_fid();
+        let req = Request::new(TATTACH)
+            .u32(root_fid)
+            .u32(NOFID)
+            .str("root")
+            .str("")
+            .u32(0);
+        client.rpc(req, |mut resp| resp.qid().map(|_| ()))?;
+        let mount_path = Arc::new(Mutex::new(String::new()));
+        Ok(Self {
+            root: NinePNode::new(client, mount_path, root_fid, true),
+        })
+    }
+}
+
+impl VfsOps for NinePFileSystem {
+    fn mount(&self, path: &str, _mount_point: VfsNodeRef) -> VfsResult {
+        *self.root.mount_path.lock() = String::from(path);
+        Ok(())
+    }
+
+    fn root_dir(&self) -> VfsNodeRef {
+        self.root.clone()
+    }
+}
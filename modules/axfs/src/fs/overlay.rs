@@ -0,0 +1,447 @@
+//! An overlay filesystem, which merges a writable upper directory with
+//! read-only lower ones, e.g. a ramfs over a SquashFS image.
+//!
+//! A path is looked up in the upper directory, then in each lower one in
+//! order, and the entries of a directory are merged from all of them. The
+//! lower directories are never modified:
+//!
+//! - A file is copied up to the upper directory, with its parents, before it
+//!   is written.
+//! - A name removed from the lower directories is hidden by a whiteout, an
+//!   empty file named `.wh.<name>` in the upper directory.
+//! - A directory created where a lower one was removed is made opaque by a
+//!   `.wh..wh..opq` file in it, so that the lower one isn't merged again.
+//!
+//! The files are copied up without their permissions, and renaming a
+//! directory that has a lower part fails with `Unsupported`.
+
+use alloc::{format, string::String, sync::Arc, vec, vec::Vec};
+
+use axfs_vfs::{VfsDirEntry, VfsError, VfsResult};
+use axfs_vfs::{VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsNodeType, VfsOps};
+use axsync::Mutex;
+
+/// The prefix of the name of a whiteout.
+const WHITEOUT_PREFIX: &str = ".wh.";
+/// The file making a directory of the upper layer opaque.
+const OPAQUE: &str = ".wh..wh..opq";
+/// The size of the chunks in which files are copied up.
+const COPY_CHUNK_SIZE: usize = 4096;
+
+/// Returns the path of `name` in `dir`, both relative to the root.
+fn join(dir: &str, name: &str) -> String {
+    if dir.is_empty() {
+        String::from(name)
+    } else {
+        format!("{}/{}", dir, name)
+    }
+}
+
+/// Returns the path of the whiteout hiding `path`.
+fn whiteout_of(path: &str) -> String {
+    let (parent, name) = split_parent(path);
+    join(parent, &format!("{}{}", WHITEOUT_PREFIX, name))
+}
+
+/// Splits a path relative to the root into its parent and its name.
+fn split_parent(path: &str) -> (&str, &str) {
+    path.rsplit_once('/').unwrap_or(("", path))
+}
+
+fn lookup_in(dir: &VfsNodeRef, path: &str) -> VfsResult<VfsNodeRef> {
+    if path.is_empty() {
+        Ok(dir.clone())
+    } else {
+        dir.clone().lookup(path)
+    }
+}
+
+/// Returns the names and the types of the entries of a directory, without
+/// `.` and `..`.
+fn read_entries(dir: &VfsNodeRef) -> VfsResult<Vec<(String, VfsNodeType)>> {
+    const EMPTY: VfsDirEntry = VfsDirEntry::default();
+    let mut dirents = [EMPTY; 16];
+    let mut entries = Vec::new();
+    let mut start = 0;
+    loop {
+        let n = dir.read_dir(start, &mut dirents)?;
+        if n == 0 {
+            return Ok(entries);
+        }
+        for dirent in &dirents[..n] {
+            let name = String::from_utf8_lossy(dirent.name_as_bytes());
+            if name != "." && name != ".." {
+                entries.push((name.into_owned(), dirent.entry_type()));
+            }
+        }
+        start += n;
+    }
+}
+
+/// The layers, shared by the nodes.
+struct Layers {
+    upper: VfsNodeRef,
+    lowers: Vec<VfsNodeRef>,
+    /// Where it's mounted, to find the destination of a rename.
+    mount_path: Mutex<String>,
+}
+
+impl Layers {
+    fn upper_lookup(&self, path: &str) -> Option<VfsNodeRef> {
+        lookup_in(&self.upper, path).ok()
+    }
+
+    /// Returns `true` if the lower entry at `path` is hidden by a whiteout,
+    /// or by an opaque directory above it.
+    fn lower_hidden(&self, path: &str) -> bool {
+        let mut parent = String::new();
+        for name in path.split('/') {
+            // without the parent, there are no whiteouts deeper
+            let Some(dir) = self.upper_lookup(&parent) else {
+                return false;
+            };
+            let whiteout = format!("{}{}", WHITEOUT_PREFIX, name);
+            if dir.clone().lookup(OPAQUE).is_ok() || dir.lookup(&whiteout).is_ok() {
+                return true;
+            }
+            parent = join(&parent, name);
+        }
+        false
+    }
+
+    fn lower_lookup(&self, path: &str) -> Option<VfsNodeRef> {
+        if self.lower_hidden(path) {
+            return None;
+        }
+        self.lowers
+            .iter()
+            .find_map(|lower| lookup_in(lower, path).ok())
+    }
+
+    /// Returns the node seen at `path`, from the upper layer if it's there.
+    fn resolve(&self, path: &str) -> VfsResult<VfsNodeRef> {
+        if path
+            .split('/')
+            .any(|name| name.starts_with(WHITEOUT_PREFIX))
+        {
+            return Err(VfsError::NotFound);
+        }
+        self.upper_lookup(path)
+            .or_else(|| self.lower_lookup(path))
+            .ok_or(VfsError::NotFound)
+    }
+
+    /// Returns the node at `path` in the upper layer, after copying it and
+    /// its parents from the lower ones if needed.
+    fn copy_up(&self, path: &str) -> VfsResult<VfsNodeRef> {
+        if let Some(node) = self.upper_lookup(path) {
+            return Ok(node);
+        }
+        let lower = self.resolve(path)?;
+        let (parent, _) = split_parent(path);
+        self.copy_up(parent)?;
+
+        let attr = lower.get_attr()?;
+        if attr.is_dir() {
+            self.upper.create(path, VfsNodeType::Dir)?;
+            return self.upper.clone().lookup(path);
+        } else if !attr.is_file() {
+            return Err(VfsError::Unsupported);
+        }
+        self.upper.create(path, VfsNodeType::File)?;
+        let node = self.upper.clone().lookup(path)?;
+        if let Err(e) = copy_data(&lower, &node) {
+            self.upper.remove(path).ok();
+            return Err(e);
+        }
+        Ok(node)
+    }
+
+    /// Returns the merged entries of the directory at `path`.
+    fn merged_entries(&self, path: &str) -> VfsResult<Vec<(String, VfsNodeType)>> {
+        let mut entries = Vec::new();
+        let mut whiteouts = Vec::new();
+        let mut opaque = false;
+        if let Some(dir) = self.upper_lookup(path) {
+            for (name, ty) in read_entries(&dir)? {
+                if name == OPAQUE {
+                    opaque = true;
+                } else if let Some(hidden) = name.strip_prefix(WHITEOUT_PREFIX) {
+                    whiteouts.push(String::from(hidden));
+                } else {
+                    entries.push((name, ty));
+                }
+            }
+        }
+        if opaque || self.lower_hidden(path) {
+            return Ok(entries);
+        }
+        for lower in &self.lowers {
+            let Ok(dir) = lookup_in(lower, path) else {
+                continue;
+            };
+            if !dir.get_attr()?.is_dir() {
+                continue;
+            }
+            for (name, ty) in read_entries(&dir)? {
+                if !whiteouts.contains(&name) && !entries.iter().any(|(n, _)| *n == name) {
+                    entries.push((name, ty));
+                }
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Creates `path` in the upper layer, where its parent is copied up.
+    fn create(&self, path: &str, ty: VfsNodeType) -> VfsResult {
+        if path.is_empty() || self.resolve(path).is_ok() {
+            return Ok(()); // already exists
+        }
+        let (parent, name) = split_parent(path);
+        if name.starts_with(WHITEOUT_PREFIX) {
+            return Err(VfsError::InvalidInput);
+        }
+        if !self.resolve(parent)?.get_attr()?.is_dir() {
+            return Err(VfsError::NotADirectory);
+        }
+        self.copy_up(parent)?;
+
+        let whiteout = whiteout_of(path);
+        let replaces_lower = self.upper_lookup(&whiteout).is_some();
+        self.upper.create(path, ty)?;
+        if replaces_lower {
+            if ty == VfsNodeType::Dir {
+                self.upper.create(&join(path, OPAQUE), VfsNodeType::File)?;
+            }
+            self.upper.remove(&whiteout)?;
+        }
+        Ok(())
+    }
+
+    /// Removes `path` from the upper layer, and hides it in the lower ones.
+    fn remove(&self, path: &str) -> VfsResult {
+        if path.is_empty() {
+            return Err(VfsError::PermissionDenied);
+        }
+        let node = self.resolve(path)?;
+        if node.get_attr()?.is_dir() && !self.merged_entries(path)?.is_empty() {
+            return Err(VfsError::DirectoryNotEmpty);
+        }
+        let in_lower = self.lower_lookup(path).is_some();
+        if let Some(node) = self.upper_lookup(path) {
+            if node.get_attr()?.is_dir() {
+                // only whiteouts are left
+                for (name, _) in read_entries(&node)? {
+                    self.upper.remove(&join(path, &name))?;
+                }
+            }
+            self.upper.remove(path)?;
+        }
+        if in_lower {
+            let (parent, _) = split_parent(path);
+            self.copy_up(parent)?;
+            self.upper.create(&whiteout_of(path), VfsNodeType::File)?;
+        }
+        Ok(())
+    }
+
+    /// Renames a file by copying it, as the upper layer may not support
+    /// renames, and a directory that is only in the upper layer.
+    fn rename(&self, src: &str, dst: &str) -> VfsResult {
+        if src.is_empty() || dst.is_empty() {
+            return Err(VfsError::PermissionDenied);
+        }
+        if src == dst {
+            return Ok(());
+        }
+        let src_node = self.resolve(src)?;
+        let (dst_parent, _) = split_parent(dst);
+        if !self.resolve(dst_parent)?.get_attr()?.is_dir() {
+            return Err(VfsError::NotADirectory);
+        }
+        if !src_node.get_attr()?.is_dir() {
+            self.create(dst, VfsNodeType::File)?;
+            let dst_node = self.copy_up(dst)?;
+            dst_node.truncate(0)?;
+            copy_data(&src_node, &dst_node)?;
+            return self.remove(src);
+        }
+
+        if self.lower_lookup(src).is_some() {
+            return Err(VfsError::Unsupported);
+        }
+        self.copy_up(dst_parent)?;
+        let whiteout = whiteout_of(dst);
+        let replaces_lower = self.upper_lookup(&whiteout).is_some();
+        self.upper.rename(src, dst)?;
+        if replaces_lower {
+            self.upper.create(&join(dst, OPAQUE), VfsNodeType::File)?;
+            self.upper.remove(&whiteout)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the path relative to the root of a destination of a rename,
+    /// which is given from the root of all filesystems.
+    fn dst_path(&self, dst_path: &str) -> String {
+        let mount_path = self.mount_path.lock();
+        let dst = dst_path.trim_start_matches('/');
+        let mount = mount_path.trim_start_matches('/');
+        let dst = match dst.strip_prefix(mount) {
+            Some(rest) if !mount.is_empty() && (rest.is_empty() || rest.starts_with('/')) => rest,
+            _ => dst,
+        };
+        canonicalize(dst)
+    }
+}
+
+fn copy_data(src: &VfsNodeRef, dst: &VfsNodeRef) -> VfsResult {
+    let mut buf = vec![0; COPY_CHUNK_SIZE];
+    let mut offset = 0;
+    loop {
+        let n = src.read_at(offset, &mut buf)?;
+        if n == 0 {
+            return Ok(());
+        }
+        let mut written = 0;
+        while written < n {
+            match dst.write_at(offset + written as u64, &buf[written..n])? {
+                0 => return Err(VfsError::WriteZero),
+                m => written += m,
+            }
+        }
+        offset += n as u64;
+    }
+}
+
+/// Resolves the `.` and `..` of a path relative to the root.
+fn canonicalize(path: &str) -> String {
+    let path = axfs_vfs::path::canonicalize(&format!("/{}", path));
+    String::from(path.trim_matches('/'))
+}
+
+/// A node of the overlay, found again in the layers by its path at each
+/// operation, as it can be copied up or hidden in the meantime.
+pub struct OverlayNode {
+    layers: Arc<Layers>,
+    /// The path from the root, without leading `/`.
+    path: String,
+}
+
+impl OverlayNode {
+    fn child(&self, path: &str) -> String {
+        canonicalize(&join(&self.path, path))
+    }
+}
+
+impl VfsNodeOps for OverlayNode {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        self.layers.resolve(&self.path)?.get_attr()
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        self.layers.resolve(&self.path)?.read_at(offset, buf)
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        self.layers.copy_up(&self.path)?.write_at(offset, buf)
+    }
+
+    fn truncate(&self, size: u64) -> VfsResult {
+        self.layers.copy_up(&self.path)?.truncate(size)
+    }
+
+    fn fsync(&self) -> VfsResult {
+        match self.layers.upper_lookup(&self.path) {
+            Some(node) => node.fsync(),
+            None => Ok(()),
+        }
+    }
+
+    fn parent(&self) -> Option<VfsNodeRef> {
+        if self.path.is_empty() {
+            return None;
+        }
+        let (parent, _) = split_parent(&self.path);
+        Some(Arc::new(OverlayNode {
+            layers: self.layers.clone(),
+            path: String::from(parent),
+        }))
+    }
+
+    fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
+        debug!("lookup at overlay: {}", path);
+        let path = self.child(path);
+        self.layers.resolve(&path)?;
+        Ok(Arc::new(OverlayNode {
+            layers: self.layers.clone(),
+            path,
+        }))
+    }
+
+    fn read_dir(&self, start_idx: usize, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
+        if !self.get_attr()?.is_dir() {
+            return Err(VfsError::NotADirectory);
+        }
+        let dots = [".", ".."].map(|name| (String::from(name), VfsNodeType::Dir));
+        let entries = dots
+            .into_iter()
+            .chain(self.layers.merged_entries(&self.path)?);
+        let mut filled = 0;
+        for (dirent, (name, ty)) in dirents.iter_mut().zip(entries.skip(start_idx)) {
+            *dirent = VfsDirEntry::new(&name, ty);
+            filled += 1;
+        }
+        Ok(filled)
+    }
+
+    fn create(&self, path: &str, ty: VfsNodeType) -> VfsResult {
+        debug!("create {:?} at overlay: {}", ty, path);
+        self.layers.create(&self.child(path), ty)
+    }
+
+    fn remove(&self, path: &str) -> VfsResult {
+        debug!("remove at overlay: {}", path);
+        self.layers.remove(&self.child(path))
+    }
+
+    fn rename(&self, src_path: &str, dst_path: &str) -> VfsResult {
+        debug!("rename at overlay: {} -> {}", src_path, dst_path);
+        let dst = self.layers.dst_path(dst_path);
+        self.layers.rename(&self.child(src_path), &dst)
+    }
+}
+
+/// An overlay of an upper directory over lower ones.
+pub struct OverlayFileSystem {
+    root: Arc<OverlayNode>,
+}
+
+impl OverlayFileSystem {
+    /// Creates an overlay of `upper` over `lowers`, the first one being on
+    /// top. The upper directory must be writable.
+    pub fn new(upper: VfsNodeRef, lowers: Vec<VfsNodeRef>) -> Self {
+        let layers = Arc::new(Layers {
+            upper,
+            lowers,
+            mount_path: Mutex::new(String::new()),
+        });
+        Self {
+            root: Arc::new(OverlayNode {
+                layers,
+                path: String::new(),
+            }),
+        }
+    }
+}
+
+impl VfsOps for OverlayFileSystem {
+    fn mount(&self, path: &str, _mount_point: VfsNodeRef) -> VfsResult {
+        *self.root.layers.mount_path.lock() = String::from(path);
+        Ok(())
+    }
+
+    fn root_dir(&self) -> VfsNodeRef {
+        self.root.clone()
+    }
+}
@@ -0,0 +1,661 @@
+//! A read-only [SquashFS] (version 4.0), to boot from a small compressed
+//! image that is never modified, e.g. the root filesystem of an appliance.
+//! The writable paths are other filesystems mounted on it, e.g. the ramfs on
+//! `/tmp`.
+//!
+//! The blocks can be stored or compressed with gzip, which is the default of
+//! `mksquashfs`. Images compressed with another algorithm are rejected, as
+//! there is no decompressor for them here.
+//!
+//! The symbolic links are listed, and reading one returns its target, but
+//! they are not followed when a path is looked up. Extended attributes, the
+//! owners and the times of the files are ignored.
+//!
+//! [SquashFS]: https://dr-emann.github.io/squashfs/
+
+use alloc::{sync::Arc, vec, vec::Vec};
+
+use axfs_vfs::{VfsDirEntry, VfsError, VfsNodePerm, VfsResult};
+use axfs_vfs::{VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsNodeType, VfsOps};
+use axsync::Mutex;
+use miniz_oxide::inflate::decompress_to_vec_zlib_with_limit;
+
+use crate::api::DiskUsage;
+use crate::dev::Disk;
+
+const MAGIC: u32 = 0x7371_7368; // "hsqs"
+const SUPER_BLOCK_SIZE: usize = 96;
+const COMPRESSOR_GZIP: u16 = 1;
+
+/// The size of the metadata blocks once decompressed.
+const METADATA_SIZE: usize = 8192;
+/// Set in the header of a metadata block that is stored.
+const METADATA_UNCOMPRESSED: u16 = 0x8000;
+/// Set in the size of a data block that is stored.
+const BLOCK_UNCOMPRESSED: u32 = 1 << 24;
+/// The fragment index of a file whose tail isn't in a fragment.
+const NO_FRAGMENT: u32 = !0;
+/// The number of entries in a metadata block of the fragment table.
+const FRAGMENTS_PER_BLOCK: u32 = (METADATA_SIZE / 16) as u32;
+/// The maximum number of entries after a directory header.
+const MAX_DIR_ENTRIES: u32 = 256;
+
+// Types of the inodes, the extended ones have more fields.
+const INODE_DIR: u16 = 1;
+const INODE_FILE: u16 = 2;
+const INODE_SYMLINK: u16 = 3;
+const INODE_BLOCK_DEV: u16 = 4;
+const INODE_CHAR_DEV: u16 = 5;
+const INODE_FIFO: u16 = 6;
+const INODE_SOCKET: u16 = 7;
+const INODE_EXT_DIR: u16 = 8;
+const INODE_EXT_FILE: u16 = 9;
+const INODE_EXT_SYMLINK: u16 = 10;
+const INODE_EXT_BLOCK_DEV: u16 = 11;
+const INODE_EXT_CHAR_DEV: u16 = 12;
+const INODE_EXT_FIFO: u16 = 13;
+const INODE_EXT_SOCKET: u16 = 14;
+
+/// The fields of the superblock that are used.
+struct SuperBlock {
+    block_size: u32,
+    fragment_count: u32,
+    root_inode: u64,
+    bytes_used: u64,
+    inode_table: u64,
+    dir_table: u64,
+    fragment_table: u64,
+}
+
+impl SuperBlock {
+    fn read(disk: &mut Disk) -> VfsResult<Self> {
+        let mut buf = [0; SUPER_BLOCK_SIZE];
+        read_disk(disk, 0, &mut buf)?;
+        let u16_at = |off: usize| u16::from_le_bytes([buf[off], buf[off + 1]]);
+        let u32_at = |off: usize| u32::from_le_bytes(buf[off..off + 4].try_into().unwrap());
+        let u64_at = |off: usize| u64::from_le_bytes(buf[off..off + 8].try_into().unwrap());
+
+        if u32_at(0) != MAGIC {
+            warn!("squashfs: bad magic");
+            return Err(VfsError::InvalidData);
+        }
+        let version = (u16_at(28), u16_at(30));
+        if version != (4, 0) {
+            warn!("squashfs: unsupported version {}.{}", version.0, version.1);
+            return Err(VfsError::Unsupported);
+        }
+        let compressor = u16_at(20);
+        if compressor != COMPRESSOR_GZIP {
+            warn!("squashfs: unsupported compressor {}", compressor);
+            return Err(VfsError::Unsupported);
+        }
+        let sb = Self {
+            block_size: u32_at(12),
+            fragment_count: u32_at(16),
+            root_inode: u64_at(32),
+            bytes_used: u64_at(40),
+            inode_table: u64_at(64),
+            dir_table: u64_at(72),
+            fragment_table: u64_at(80),
+        };
+        if !sb.block_size.is_power_of_two() || sb.bytes_used > disk.size() {
+            return Err(VfsError::InvalidData);
+        }
+        Ok(sb)
+    }
+}
+
+fn read_disk(disk: &mut Disk, pos: u64, buf: &mut [u8]) -> VfsResult {
+    disk.set_position(pos);
+    let mut read = 0;
+    while read < buf.len() {
+        match disk.read_one(&mut buf[read..]) {
+            Ok(0) | Err(_) => return Err(VfsError::Io),
+            Ok(n) => read += n,
+        }
+    }
+    Ok(())
+}
+
+/// The image, shared by the nodes.
+struct Image {
+    disk: Mutex<Disk>,
+    sb: SuperBlock,
+    /// The positions of the metadata blocks of the fragment table.
+    fragment_blocks: Vec<u64>,
+}
+
+impl Image {
+    fn read(&self, pos: u64, buf: &mut [u8]) -> VfsResult {
+        if pos + buf.len() as u64 > self.sb.bytes_used {
+            return Err(VfsError::InvalidData);
+        }
+        read_disk(&mut self.disk.lock(), pos, buf)
+    }
+
+    /// Reads `size` bytes at `pos`, and decompresses them if `compressed`,
+    /// into at most `limit` bytes.
+    fn read_block(
+        &self,
+        pos: u64,
+        size: usize,
+        compressed: bool,
+        limit: usize,
+    ) -> VfsResult<Vec<u8>> {
+        if size > limit {
+            return Err(VfsError::InvalidData);
+        }
+        let mut data = vec![0; size];
+        self.read(pos, &mut data)?;
+        if compressed {
+            decompress_to_vec_zlib_with_limit(&data, limit).map_err(|_| VfsError::InvalidData)
+        } else {
+            Ok(data)
+        }
+    }
+
+    /// Reads the metadata block at `pos`, returns its content and the
+    /// position of the next one.
+    fn read_metadata(&self, pos: u64) -> VfsResult<(Vec<u8>, u64)> {
+        let mut header = [0; 2];
+        self.read(pos, &mut header)?;
+        let header = u16::from_le_bytes(header);
+        let size = (header & !METADATA_UNCOMPRESSED) as usize;
+        let compressed = header & METADATA_UNCOMPRESSED == 0;
+        let data = self.read_block(pos + 2, size, compressed, METADATA_SIZE)?;
+        Ok((data, pos + 2 + size as u64))
+    }
+
+    /// Returns the position and the size of the fragment `index`.
+    fn fragment(&self, index: u32) -> VfsResult<(u64, u32)> {
+        let block = *self
+            .fragment_blocks
+            .get((index / FRAGMENTS_PER_BLOCK) as usize)
+            .ok_or(VfsError::InvalidData)?;
+        let offset = (index % FRAGMENTS_PER_BLOCK) as usize * 16;
+        let mut r = MetadataReader::new(self, 0, block, offset)?;
+        Ok((r.u64()?, r.u32()?))
+    }
+}
+
+/// Reads the metadata from a position in a table, across its blocks.
+struct MetadataReader<'a> {
+    image: &'a Image,
+    data: Vec<u8>,
+    offset: usize,
+    next: u64,
+}
+
+impl<'a> MetadataReader<'a> {
+    /// Starts at `offset` in the block at `block` from the start of `table`.
+    fn new(image: &'a Image, table: u64, block: u64, offset: usize) -> VfsResult<Self> {
+        let (data, next) = image.read_metadata(table + block)?;
+        if offset > data.len() {
+            return Err(VfsError::InvalidData);
+        }
+        Ok(Self {
+            image,
+            data,
+            offset,
+            next,
+        })
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> VfsResult {
+        let mut read = 0;
+        while read < buf.len() {
+            if self.offset == self.data.len() {
+                (self.data, self.next) = self.image.read_metadata(self.next)?;
+                self.offset = 0;
+                if self.data.is_empty() {
+                    return Err(VfsError::InvalidData);
+                }
+            }
+            let n = (buf.len() - read).min(self.data.len() - self.offset);
+            buf[read..read + n].copy_from_slice(&self.data[self.offset..self.offset + n]);
+            self.offset += n;
+            read += n;
+        }
+        Ok(())
+    }
+
+    fn skip(&mut self, n: usize) -> VfsResult {
+        self.read(&mut vec![0; n])
+    }
+
+    fn u16(&mut self) -> VfsResult<u16> {
+        let mut buf = [0; 2];
+        self.read(&mut buf)?;
+        Ok(u16::from_le_bytes(buf))
+    }
+
+    fn u32(&mut self) -> VfsResult<u32> {
+        let mut buf = [0; 4];
+        self.read(&mut buf)?;
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    fn u64(&mut self) -> VfsResult<u64> {
+        let mut buf = [0; 8];
+        self.read(&mut buf)?;
+        Ok(u64::from_le_bytes(buf))
+    }
+}
+
+/// What is needed to read a node, besides its attributes.
+enum InodeData {
+    Dir {
+        /// The position of the entries in the directory table.
+        block: u32,
+        offset: u16,
+    },
+    File {
+        /// The position and the size on the disk of each full block.
+        blocks: Vec<(u64, u32)>,
+        /// The fragment holding the tail, and its position in it.
+        fragment: u32,
+        fragment_offset: u32,
+    },
+    Symlink(Vec<u8>),
+    Special,
+}
+
+struct Inode {
+    ty: VfsNodeType,
+    perm: VfsNodePerm,
+    size: u64,
+    data: InodeData,
+}
+
+impl Inode {
+    /// Reads the inode referred to by `inode_ref`, the position of its
+    /// metadata block in the inode table and its offset in it.
+    fn read(image: &Image, inode_ref: u64) -> VfsResult<Self> {
+        let mut r = MetadataReader::new(
+            image,
+            image.sb.inode_table,
+            inode_ref >> 16,
+            (inode_ref & 0xffff) as usize,
+        )?;
+        let inode_type = r.u16()?;
+        let perm = VfsNodePerm::from_bits_truncate(r.u16()? & 0o777);
+        r.skip(12)?; // uid, gid, mtime, inode number
+
+        let (ty, size, data) = match inode_type {
+            INODE_DIR => {
+                let block = r.u32()?;
+                r.skip(4)?; // link count
+                let size = r.u16()? as u64;
+                let offset = r.u16()?;
+                (VfsNodeType::Dir, size, InodeData::Dir { block, offset })
+            }
+            INODE_EXT_DIR => {
+                r.skip(4)?; // link count
+                let size = r.u32()? as u64;
+                let block = r.u32()?;
+                r.skip(6)?; // parent inode number, index count
+                let offset = r.u16()?;
+                (VfsNodeType::Dir, size, InodeData::Dir { block, offset })
+            }
+            INODE_FILE | INODE_EXT_FILE => {
+                let (start, fragment, fragment_offset, size) = if inode_type == INODE_FILE {
+                    let start = r.u32()? as u64;
+                    let fragment = r.u32()?;
+                    let fragment_offset = r.u32()?;
+                    (start, fragment, fragment_offset, r.u32()? as u64)
+                } else {
+                    let start = r.u64()?;
+                    let size = r.u64()?;
+                    r.skip(12)?; // sparse bytes, link count
+                    let fragment = r.u32()?;
+                    let fragment_offset = r.u32()?;
+                    r.skip(4)?; // xattr index
+                    (start, fragment, fragment_offset, size)
+                };
+                let block_size = image.sb.block_size as u64;
+                let count = if fragment == NO_FRAGMENT {
+                    size.div_ceil(block_size)
+                } else {
+                    size / block_size
+                };
+                let mut blocks = Vec::new();
+                let mut pos = start;
+                for _ in 0..count {
+                    let disk_size = r.u32()?;
+                    blocks.push((pos, disk_size));
+                    pos += (disk_size & !BLOCK_UNCOMPRESSED) as u64;
+                }
+                let data = InodeData::File {
+                    blocks,
+                    fragment,
+                    fragment_offset,
+                };
+                (VfsNodeType::File, size, data)
+            }
+            INODE_SYMLINK | INODE_EXT_SYMLINK => {
+                r.skip(4)?; // link count
+                let mut target = vec![0; r.u32()? as usize];
+                r.read(&mut target)?;
+                let size = target.len() as u64;
+                (VfsNodeType::SymLink, size, InodeData::Symlink(target))
+            }
+            INODE_BLOCK_DEV | INODE_EXT_BLOCK_DEV => {
+                (VfsNodeType::BlockDevice, 0, InodeData::Special)
+            }
+            INODE_CHAR_DEV | INODE_EXT_CHAR_DEV => (VfsNodeType::CharDevice, 0, InodeData::Special),
+            INODE_FIFO | INODE_EXT_FIFO => (VfsNodeType::Fifo, 0, InodeData::Special),
+            INODE_SOCKET | INODE_EXT_SOCKET => (VfsNodeType::Socket, 0, InodeData::Special),
+            _ => return Err(VfsError::InvalidData),
+        };
+        Ok(Self {
+            ty,
+            perm,
+            size,
+            data,
+        })
+    }
+}
+
+fn inode_type_to_node_type(ty: u16) -> VfsResult<VfsNodeType> {
+    Ok(match ty {
+        INODE_DIR | INODE_EXT_DIR => VfsNodeType::Dir,
+        INODE_FILE | INODE_EXT_FILE => VfsNodeType::File,
+        INODE_SYMLINK | INODE_EXT_SYMLINK => VfsNodeType::SymLink,
+        INODE_BLOCK_DEV | INODE_EXT_BLOCK_DEV => VfsNodeType::BlockDevice,
+        INODE_CHAR_DEV | INODE_EXT_CHAR_DEV => VfsNodeType::CharDevice,
+        INODE_FIFO | INODE_EXT_FIFO => VfsNodeType::Fifo,
+        INODE_SOCKET | INODE_EXT_SOCKET => VfsNodeType::Socket,
+        _ => return Err(VfsError::InvalidData),
+    })
+}
+
+/// An entry of a directory.
+struct DirEntry {
+    name: Vec<u8>,
+    ty: VfsNodeType,
+    inode_ref: u64,
+}
+
+/// A node of the image, which can't be modified.
+pub struct SquashNode {
+    image: Arc<Image>,
+    inode: Inode,
+    /// The parent of a directory, `None` for the root.
+    parent: Option<VfsNodeRef>,
+    /// The last data block read from a file, as it's usually read in smaller
+    /// chunks.
+    cached_block: Mutex<Option<(u64, Vec<u8>)>>,
+}
+
+impl SquashNode {
+    fn new(image: Arc<Image>, inode_ref: u64, parent: Option<VfsNodeRef>) -> VfsResult<Arc<Self>> {
+        let inode = Inode::read(&image, inode_ref)?;
+        Ok(Arc::new(Self {
+            image,
+            inode,
+            parent,
+            cached_block: Mutex::new(None),
+        }))
+    }
+
+    fn entries(&self) -> VfsResult<Vec<DirEntry>> {
+        let InodeData::Dir { block, offset } = self.inode.data else {
+            return Err(VfsError::NotADirectory);
+        };
+        let mut r = MetadataReader::new(
+            &self.image,
+            self.image.sb.dir_table,
+            block as u64,
+            offset as usize,
+        )?;
+        // the size counts `.` and `..`, which aren't stored
+        let mut remaining = self.inode.size.saturating_sub(3);
+        let mut entries = Vec::new();
+        while remaining > 0 {
+            let count = r.u32()? + 1;
+            let start = r.u32()? as u64;
+            r.skip(4)?; // inode number
+            remaining = remaining.checked_sub(12).ok_or(VfsError::InvalidData)?;
+            if count > MAX_DIR_ENTRIES {
+                return Err(VfsError::InvalidData);
+            }
+            for _ in 0..count {
+                let offset = r.u16()? as u64;
+                r.skip(2)?; // inode number, from the one of the header
+                let ty = inode_type_to_node_type(r.u16()?)?;
+                let mut name = vec![0; r.u16()? as usize + 1];
+                r.read(&mut name)?;
+                remaining = remaining
+                    .checked_sub(8 + name.len() as u64)
+                    .ok_or(VfsError::InvalidData)?;
+                entries.push(DirEntry {
+                    name,
+                    ty,
+                    inode_ref: (start << 16) | offset,
+                });
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Returns whether `path` exists, without going up with `..`.
+    fn exists(&self, path: &str) -> VfsResult<bool> {
+        let mut node: Option<Arc<SquashNode>> = None;
+        for name in path.split('/').filter(|n| !n.is_empty() && *n != ".") {
+            let entries = node.as_deref().unwrap_or(self).entries()?;
+            let Some(entry) = entries.into_iter().find(|e| e.name == name.as_bytes()) else {
+                return Ok(false);
+            };
+            node = Some(SquashNode::new(self.image.clone(), entry.inode_ref, None)?);
+        }
+        Ok(true)
+    }
+
+    /// Reads the data block `index` of a file, which is shorter than the
+    /// block size if it's the tail of the file.
+    fn read_data_block(&self, index: u64) -> VfsResult<Vec<u8>> {
+        let InodeData::File {
+            ref blocks,
+            fragment,
+            fragment_offset,
+        } = self.inode.data
+        else {
+            return Err(VfsError::IsADirectory);
+        };
+        let block_size = self.image.sb.block_size as usize;
+        if let Some(&(pos, disk_size)) = blocks.get(index as usize) {
+            let size = (disk_size & !BLOCK_UNCOMPRESSED) as usize;
+            if size == 0 {
+                // a sparse block
+                return Ok(vec![0; block_size]);
+            }
+            let compressed = disk_size & BLOCK_UNCOMPRESSED == 0;
+            return self.image.read_block(pos, size, compressed, block_size);
+        }
+        if fragment == NO_FRAGMENT {
+            return Err(VfsError::InvalidData);
+        }
+        let (pos, disk_size) = self.image.fragment(fragment)?;
+        let size = (disk_size & !BLOCK_UNCOMPRESSED) as usize;
+        let compressed = disk_size & BLOCK_UNCOMPRESSED == 0;
+        let data = self.image.read_block(pos, size, compressed, block_size)?;
+        let start = fragment_offset as usize;
+        let len = (self.inode.size % block_size as u64) as usize;
+        data.get(start..start + len)
+            .map(<[u8]>::to_vec)
+            .ok_or(VfsError::InvalidData)
+    }
+}
+
+impl VfsNodeOps for SquashNode {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        let blocks = self.inode.size.div_ceil(512);
+        Ok(VfsNodeAttr::new(
+            self.inode.perm,
+            self.inode.ty,
+            self.inode.size,
+            blocks,
+        ))
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        match &self.inode.data {
+            InodeData::File { .. } => {}
+            InodeData::Symlink(target) => {
+                let start = (offset as usize).min(target.len());
+                let len = buf.len().min(target.len() - start);
+                buf[..len].copy_from_slice(&target[start..start + len]);
+                return Ok(len);
+            }
+            InodeData::Dir { .. } => return Err(VfsError::IsADirectory),
+            InodeData::Special => return Err(VfsError::Unsupported),
+        }
+        let block_size = self.image.sb.block_size as u64;
+        let end = self.inode.size.min(offset + buf.len() as u64);
+        let mut pos = offset;
+        let mut cached = self.cached_block.lock();
+        while pos < end {
+            let index = pos / block_size;
+            if cached.as_ref().is_none_or(|(i, _)| *i != index) {
+                *cached = Some((index, self.read_data_block(index)?));
+            }
+            let data = &cached.as_ref().unwrap().1;
+            let start = (pos % block_size) as usize;
+            let len = ((end - pos) as usize).min(block_size as usize - start);
+            let src = data.get(start..start + len).ok_or(VfsError::InvalidData)?;
+            let dst = (pos - offset) as usize;
+            buf[dst..dst + len].copy_from_slice(src);
+            pos += len as u64;
+        }
+        Ok(pos.saturating_sub(offset) as usize)
+    }
+
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> VfsResult<usize> {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn truncate(&self, _size: u64) -> VfsResult {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn parent(&self) -> Option<VfsNodeRef> {
+        self.parent.clone()
+    }
+
+    fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
+        debug!("lookup at squashfs: {}", path);
+        let path = path.trim_matches('/');
+        if path.is_empty() || path == "." {
+            return Ok(self);
+        }
+        if let Some(rest) = path.strip_prefix("./") {
+            return self.lookup(rest);
+        }
+        let (name, rest) = path.split_once('/').unwrap_or((path, ""));
+        if name == ".." {
+            let parent: VfsNodeRef = match self.parent.clone() {
+                Some(parent) => parent,
+                None => self,
+            };
+            return parent.lookup(rest);
+        }
+        let entry = self
+            .entries()?
+            .into_iter()
+            .find(|e| e.name == name.as_bytes())
+            .ok_or(VfsError::NotFound)?;
+        let node = SquashNode::new(self.image.clone(), entry.inode_ref, Some(self))?;
+        if rest.is_empty() {
+            Ok(node)
+        } else if entry.ty == VfsNodeType::Dir {
+            node.lookup(rest)
+        } else {
+            Err(VfsError::NotADirectory)
+        }
+    }
+
+    fn read_dir(&self, start_idx: usize, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
+        let entries = self.entries()?;
+        let dots = [".", ".."].map(|name| VfsDirEntry::new(name, VfsNodeType::Dir));
+        let entries = entries.iter().map(|e| {
+            let name = core::str::from_utf8(&e.name).unwrap_or("?");
+            VfsDirEntry::new(name, e.ty)
+        });
+        let mut filled = 0;
+        for (dirent, entry) in dirents
+            .iter_mut()
+            .zip(dots.into_iter().chain(entries).skip(start_idx))
+        {
+            *dirent = entry;
+            filled += 1;
+        }
+        Ok(filled)
+    }
+
+    fn create(&self, path: &str, _ty: VfsNodeType) -> VfsResult {
+        // succeeds if it exists, e.g. a mount point
+        if self.exists(path)? {
+            Ok(())
+        } else {
+            Err(VfsError::PermissionDenied)
+        }
+    }
+
+    fn remove(&self, _path: &str) -> VfsResult {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn rename(&self, _src_path: &str, _dst_path: &str) -> VfsResult {
+        Err(VfsError::PermissionDenied)
+    }
+}
+
+/// A SquashFS image on a disk.
+pub struct SquashFileSystem {
+    image: Arc<Image>,
+    root: VfsNodeRef,
+}
+
+impl SquashFileSystem {
+    pub fn new(mut disk: Disk) -> VfsResult<Self> {
+        let sb = SuperBlock::read(&mut disk)?;
+        let mut fragment_blocks = Vec::new();
+        let table_len = sb.fragment_count.div_ceil(FRAGMENTS_PER_BLOCK);
+        for i in 0..table_len as u64 {
+            let mut pos = [0; 8];
+            read_disk(&mut disk, sb.fragment_table + i * 8, &mut pos)?;
+            fragment_blocks.push(u64::from_le_bytes(pos));
+        }
+        info!(
+            "squashfs: {} bytes, blocks of {} bytes",
+            sb.bytes_used, sb.block_size
+        );
+        let root_inode = sb.root_inode;
+        let image = Arc::new(Image {
+            disk: Mutex::new(disk),
+            sb,
+            fragment_blocks,
+        });
+        let root = SquashNode::new(image.clone(), root_inode, None)?;
+        if root.inode.ty != VfsNodeType::Dir {
+            return Err(VfsError::InvalidData);
+        }
+        Ok(Self { image, root })
+    }
+
+    /// Returns the size of the image, which has no free space.
+    pub fn disk_usage(&self) -> VfsResult<DiskUsage> {
+        Ok(DiskUsage {
+            block_size: self.image.sb.block_size as u64,
+            total: self.image.sb.bytes_used,
+            free: 0,
+            available: 0,
+        })
+    }
+}
+
+impl VfsOps for SquashFileSystem {
+    fn root_dir(&self) -> VfsNodeRef {
+        self.root.clone()
+    }
+}
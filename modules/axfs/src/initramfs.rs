@@ -0,0 +1,117 @@
+//! Unpacking of the initial RAM filesystem, a cpio archive in the `newc`
+//! format as made by `cpio -H newc` (e.g. for Linux).
+//!
+//! Only the directories and the regular files are unpacked. The symbolic
+//! links and the special files are skipped, as the ramfs doesn't have them,
+//! and the hard links are copies of the file.
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+use axfs_vfs::{VfsError, VfsNodeRef, VfsNodeType, VfsResult};
+
+const MAGIC: &[u8] = b"070701";
+/// The magic of the archives with the checksums of the files, which are not
+/// verified.
+const MAGIC_CRC: &[u8] = b"070702";
+const HEADER_LEN: usize = 110;
+const TRAILER: &[u8] = b"TRAILER!!!";
+
+const S_IFMT: usize = 0o170000;
+const S_IFDIR: usize = 0o040000;
+const S_IFREG: usize = 0o100000;
+
+/// Reads the `i`-th field of the header, 8 hexadecimal digits.
+fn field(header: &[u8], i: usize) -> VfsResult<usize> {
+    let digits = &header[6 + i * 8..6 + (i + 1) * 8];
+    core::str::from_utf8(digits)
+        .ok()
+        .and_then(|s| usize::from_str_radix(s, 16).ok())
+        .ok_or(VfsError::InvalidData)
+}
+
+/// Creates `path` in `root` if it doesn't exist, with its parents.
+fn create_all(root: &VfsNodeRef, path: &str, ty: VfsNodeType) -> VfsResult {
+    let parents = path.match_indices('/').map(|(i, _)| &path[..i]);
+    for (parent, ty) in parents
+        .map(|parent| (parent, VfsNodeType::Dir))
+        .chain([(path, ty)])
+    {
+        match root.create(parent, ty) {
+            Ok(()) | Err(VfsError::AlreadyExists) => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+fn write_file(root: &VfsNodeRef, path: &str, data: &[u8]) -> VfsResult {
+    let file = root.clone().lookup(path)?;
+    file.truncate(0)?;
+    file.write_at(0, data)?;
+    Ok(())
+}
+
+/// Unpacks the archive `cpio` in the directory `root`, and returns the
+/// number of entries unpacked.
+pub(crate) fn unpack(cpio: &[u8], root: &VfsNodeRef) -> VfsResult<usize> {
+    let mut off = 0;
+    let mut count = 0;
+    // The names of the files with several links, by inode. Only the last
+    // entry of a file has its data.
+    let mut links = BTreeMap::<usize, Vec<&str>>::new();
+    loop {
+        let header = cpio
+            .get(off..off + HEADER_LEN)
+            .ok_or(VfsError::InvalidData)?;
+        if !header.starts_with(MAGIC) && !header.starts_with(MAGIC_CRC) {
+            return Err(VfsError::InvalidData);
+        }
+        let ino = field(header, 0)?;
+        let mode = field(header, 1)?;
+        let nlink = field(header, 4)?;
+        let file_size = field(header, 6)?;
+        let name_size = field(header, 11)?;
+
+        let name_off = off + HEADER_LEN;
+        let data_off = (name_off + name_size).next_multiple_of(4);
+        off = (data_off + file_size).next_multiple_of(4);
+        // the name ends with a NUL
+        let name = cpio
+            .get(name_off..name_off + name_size.saturating_sub(1))
+            .ok_or(VfsError::InvalidData)?;
+        let data = cpio
+            .get(data_off..data_off + file_size)
+            .ok_or(VfsError::InvalidData)?;
+        if name == TRAILER {
+            return Ok(count);
+        }
+
+        let name = core::str::from_utf8(name).map_err(|_| VfsError::InvalidData)?;
+        let path = name.trim_start_matches("./").trim_matches('/');
+        if path.is_empty() || path == "." {
+            continue;
+        }
+        match mode & S_IFMT {
+            S_IFDIR => create_all(root, path, VfsNodeType::Dir)?,
+            S_IFREG => {
+                create_all(root, path, VfsNodeType::File)?;
+                write_file(root, path, data)?;
+                if nlink > 1 {
+                    let names = links.entry(ino).or_default();
+                    if !data.is_empty() {
+                        for name in names.iter() {
+                            write_file(root, name, data)?;
+                        }
+                    }
+                    names.push(path);
+                }
+            }
+            _ => {
+                warn!("initramfs: {}: unsupported file type, skipped", path);
+                continue;
+            }
+        }
+        count += 1;
+    }
+}
@@ -0,0 +1,429 @@
+//! A persistent key-value store for system configuration.
+//!
+//! The store is an append-only log of records in a file, each protected by a
+//! CRC-32 checksum, and the whole content is kept in memory. A record is
+//! appended and flushed on every change, so a crash loses at most the change
+//! in progress: a torn record at the end of the log is discarded when the
+//! store is opened again.
+//!
+//! When the log has grown mostly obsolete, it's compacted by writing the live
+//! entries to `<path>.compact` and renaming it over the log. If a crash
+//! happens in between, either the old log or the complete new one survives.
+
+use alloc::{borrow::ToOwned, collections::BTreeMap, format, string::String, vec, vec::Vec};
+use core::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use axerrno::{AxResult, ax_err};
+use axio::{SeekFrom, prelude::*};
+
+use crate::api::{self, File};
+use crate::crc32::crc32;
+
+const MAGIC: &[u8; 4] = b"AXKV";
+
+const OP_SET: u8 = 1;
+const OP_REMOVE: u8 = 2;
+
+/// crc32 (4), op (1), key length (2), value length (4).
+const RECORD_HEADER_LEN: usize = 11;
+
+/// The maximum length of a key in bytes.
+pub const MAX_KEY_LEN: usize = 255;
+/// The maximum length of a value in bytes.
+pub const MAX_VALUE_LEN: usize = 64 * 1024;
+
+/// The log is not compacted until it's larger than this.
+const COMPACT_THRESHOLD: u64 = 4096;
+
+/// A value that can be written to a [`KvStore`].
+pub trait ToKvValue {
+    /// Encodes the value into bytes.
+    fn to_kv_value(&self) -> Vec<u8>;
+}
+
+/// A value that can be read from a [`KvStore`].
+pub trait FromKvValue: Sized {
+    /// Decodes the value from bytes, or returns `None` if they are not a valid
+    /// encoding.
+    fn from_kv_value(bytes: &[u8]) -> Option<Self>;
+}
+
+impl ToKvValue for [u8] {
+    fn to_kv_value(&self) -> Vec<u8> {
+        self.to_vec()
+    }
+}
+
+impl ToKvValue for Vec<u8> {
+    fn to_kv_value(&self) -> Vec<u8> {
+        self.clone()
+    }
+}
+
+impl FromKvValue for Vec<u8> {
+    fn from_kv_value(bytes: &[u8]) -> Option<Self> {
+        Some(bytes.to_vec())
+    }
+}
+
+impl ToKvValue for str {
+    fn to_kv_value(&self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+}
+
+impl ToKvValue for String {
+    fn to_kv_value(&self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+}
+
+impl FromKvValue for String {
+    fn from_kv_value(bytes: &[u8]) -> Option<Self> {
+        core::str::from_utf8(bytes).ok().map(ToOwned::to_owned)
+    }
+}
+
+impl ToKvValue for bool {
+    fn to_kv_value(&self) -> Vec<u8> {
+        vec![*self as u8]
+    }
+}
+
+impl FromKvValue for bool {
+    fn from_kv_value(bytes: &[u8]) -> Option<Self> {
+        match bytes {
+            [0] => Some(false),
+            [1] => Some(true),
+            _ => None,
+        }
+    }
+}
+
+macro_rules! impl_kv_value_int {
+    ($($ty:ty),*) => {$(
+        impl ToKvValue for $ty {
+            fn to_kv_value(&self) -> Vec<u8> {
+                self.to_le_bytes().to_vec()
+            }
+        }
+
+        impl FromKvValue for $ty {
+            fn from_kv_value(bytes: &[u8]) -> Option<Self> {
+                bytes.try_into().ok().map(<$ty>::from_le_bytes)
+            }
+        }
+    )*};
+}
+
+impl_kv_value_int!(u8, u16, u32, u64, i8, i16, i32, i64);
+
+macro_rules! impl_kv_value_text {
+    ($($ty:ty),*) => {$(
+        impl ToKvValue for $ty {
+            fn to_kv_value(&self) -> Vec<u8> {
+                format!("{}", self).into_bytes()
+            }
+        }
+
+        impl FromKvValue for $ty {
+            fn from_kv_value(bytes: &[u8]) -> Option<Self> {
+                core::str::from_utf8(bytes).ok()?.parse().ok()
+            }
+        }
+    )*};
+}
+
+impl_kv_value_text!(IpAddr, Ipv4Addr, Ipv6Addr);
+
+/// Returns whether `key` can be used in a [`KvStore`].
+///
+/// A key must be non-empty, at most [`MAX_KEY_LEN`] bytes long, and must not
+/// contain `=`, newlines or NUL.
+pub fn is_valid_key(key: &str) -> bool {
+    !key.is_empty() && key.len() <= MAX_KEY_LEN && !key.contains(['=', '\n', '\0'])
+}
+
+fn encode_record(op: u8, key: &str, value: &[u8]) -> Vec<u8> {
+    let mut record = Vec::with_capacity(RECORD_HEADER_LEN + key.len() + value.len());
+    record.extend_from_slice(&[0; 4]);
+    record.push(op);
+    record.extend_from_slice(&(key.len() as u16).to_le_bytes());
+    record.extend_from_slice(&(value.len() as u32).to_le_bytes());
+    record.extend_from_slice(key.as_bytes());
+    record.extend_from_slice(value);
+    let crc = crc32(&record[4..]);
+    record[..4].copy_from_slice(&crc.to_le_bytes());
+    record
+}
+
+/// Decodes the record at the start of `buf`, returning the operation, key,
+/// value and length of the record, or `None` if it's torn or corrupted.
+fn decode_record(buf: &[u8]) -> Option<(u8, &str, &[u8], usize)> {
+    let header = buf.get(..RECORD_HEADER_LEN)?;
+    let op = header[4];
+    let key_len = u16::from_le_bytes([header[5], header[6]]) as usize;
+    let value_len = u32::from_le_bytes(header[7..11].try_into().unwrap()) as usize;
+    let len = RECORD_HEADER_LEN + key_len + value_len;
+    let record = buf.get(..len)?;
+    if crc32(&record[4..]) != u32::from_le_bytes(header[..4].try_into().unwrap()) {
+        return None;
+    }
+    let key = core::str::from_utf8(&record[RECORD_HEADER_LEN..][..key_len]).ok()?;
+    let value = &record[RECORD_HEADER_LEN + key_len..];
+    Some((op, key, value, len))
+}
+
+/// A persistent key-value store backed by a log file.
+///
+/// # Examples
+///
+/// ```no_run
+/// use axfs::kv::KvStore;
+///
+/// let mut store = KvStore::open("/etc/config.kv")?;
+/// store.set("net.hostname", "arceos")?;
+/// store.set("boot.count", &(store.get::<u32>("boot.count")?.unwrap_or(0) + 1))?;
+/// assert_eq!(store.get::<String>("net.hostname")?.as_deref(), Some("arceos"));
+/// # Ok::<(), axio::Error>(())
+/// ```
+pub struct KvStore {
+    path: String,
+    file: File,
+    entries: BTreeMap<String, Vec<u8>>,
+    /// The length of the log in bytes.
+    log_len: u64,
+    /// The length of the records that are still live.
+    live_len: u64,
+    #[cfg(feature = "procfs")]
+    proc_path: Option<String>,
+}
+
+impl KvStore {
+    /// Opens the store at `path`, creating it if it doesn't exist.
+    pub fn open(path: &str) -> AxResult<Self> {
+        let compact_path = format!("{}.compact", path);
+        if api::metadata(&compact_path).is_ok() {
+            if api::metadata(path).is_ok() {
+                // the compaction didn't finish, and the old log is intact
+                api::remove_file(&compact_path)?;
+            } else {
+                api::rename(&compact_path, path)?;
+            }
+        }
+
+        let mut file = File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open(path)?;
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)?;
+        if data.is_empty() {
+            file.write_all(MAGIC)?;
+            file.flush()?;
+            data.extend_from_slice(MAGIC);
+        } else if !data.starts_with(MAGIC) {
+            return ax_err!(InvalidData, "kv: bad magic");
+        }
+
+        let mut store = Self {
+            path: path.into(),
+            file,
+            entries: BTreeMap::new(),
+            log_len: MAGIC.len() as u64,
+            live_len: MAGIC.len() as u64,
+            #[cfg(feature = "procfs")]
+            proc_path: None,
+        };
+        let mut pos = MAGIC.len();
+        while pos < data.len() {
+            let Some((op, key, value, len)) = decode_record(&data[pos..]) else {
+                warn!(
+                    "kv: discarding {} bytes of corrupted log in {}",
+                    data.len() - pos,
+                    path
+                );
+                store.file.set_len(pos as u64)?;
+                break;
+            };
+            match op {
+                OP_SET => store.apply_set(key, value.to_vec(), len as u64),
+                OP_REMOVE => store.apply_remove(key),
+                _ => return ax_err!(InvalidData, "kv: unknown record type"),
+            }
+            store.log_len += len as u64;
+            pos += len;
+        }
+        store.file.seek(SeekFrom::Start(store.log_len))?;
+        Ok(store)
+    }
+
+    fn apply_set(&mut self, key: &str, value: Vec<u8>, len: u64) {
+        self.apply_remove(key);
+        self.entries.insert(key.into(), value);
+        self.live_len += len;
+    }
+
+    fn apply_remove(&mut self, key: &str) {
+        if let Some(old) = self.entries.remove(key) {
+            self.live_len -= (RECORD_HEADER_LEN + key.len() + old.len()) as u64;
+        }
+    }
+
+    /// Returns the number of entries.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns whether the store has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns whether the store has an entry of `key`.
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    /// Returns an iterator over the keys in sorted order.
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.entries.keys().map(String::as_str)
+    }
+
+    /// Returns the raw bytes of the value of `key`.
+    pub fn get_raw(&self, key: &str) -> Option<&[u8]> {
+        self.entries.get(key).map(Vec::as_slice)
+    }
+
+    /// Returns the value of `key`, or `None` if it doesn't exist.
+    ///
+    /// Returns [`InvalidData`](axerrno::AxError::InvalidData) if the value
+    /// is not of type `T`.
+    pub fn get<T: FromKvValue>(&self, key: &str) -> AxResult<Option<T>> {
+        match self.entries.get(key) {
+            Some(bytes) => match T::from_kv_value(bytes) {
+                Some(value) => Ok(Some(value)),
+                None => ax_err!(InvalidData, "kv: value of a different type"),
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Sets the value of `key`, and writes it to the log.
+    pub fn set<T: ToKvValue + ?Sized>(&mut self, key: &str, value: &T) -> AxResult {
+        self.set_raw(key, &value.to_kv_value())
+    }
+
+    /// Sets the value of `key` to raw bytes, and writes it to the log.
+    pub fn set_raw(&mut self, key: &str, value: &[u8]) -> AxResult {
+        if !is_valid_key(key) {
+            return ax_err!(InvalidInput, "kv: invalid key");
+        }
+        if value.len() > MAX_VALUE_LEN {
+            return ax_err!(InvalidInput, "kv: value too long");
+        }
+        if self.get_raw(key) == Some(value) {
+            return Ok(());
+        }
+        let record = encode_record(OP_SET, key, value);
+        self.append(&record)?;
+        self.apply_set(key, value.to_vec(), record.len() as u64);
+        self.changed()
+    }
+
+    /// Removes `key` from the store, and writes the removal to the log.
+    ///
+    /// Returns whether the key existed.
+    pub fn remove(&mut self, key: &str) -> AxResult<bool> {
+        if !self.entries.contains_key(key) {
+            return Ok(false);
+        }
+        self.append(&encode_record(OP_REMOVE, key, &[]))?;
+        self.apply_remove(key);
+        self.changed()?;
+        Ok(true)
+    }
+
+    fn append(&mut self, record: &[u8]) -> AxResult {
+        if let Err(e) = self.file.write_all(record).and_then(|_| self.file.flush()) {
+            // drop the partial record, so that later ones can be read back
+            self.file.set_len(self.log_len)?;
+            self.file.seek(SeekFrom::Start(self.log_len))?;
+            return Err(e);
+        }
+        self.log_len += record.len() as u64;
+        Ok(())
+    }
+
+    fn changed(&mut self) -> AxResult {
+        if self.log_len > COMPACT_THRESHOLD && self.log_len > 2 * self.live_len {
+            self.compact()?;
+        }
+        #[cfg(feature = "procfs")]
+        self.update_proc()?;
+        Ok(())
+    }
+
+    /// Rewrites the log with only the live entries.
+    ///
+    /// It's done automatically when most of the log is obsolete.
+    pub fn compact(&mut self) -> AxResult {
+        let compact_path = format!("{}.compact", self.path);
+        let mut log = Vec::with_capacity(self.live_len as usize);
+        log.extend_from_slice(MAGIC);
+        for (key, value) in &self.entries {
+            log.extend_from_slice(&encode_record(OP_SET, key, value));
+        }
+        let mut file = File::create(&compact_path)?;
+        file.write_all(&log)?;
+        file.flush()?;
+        drop(file);
+        api::rename(&compact_path, &self.path)?;
+
+        self.file = File::options().read(true).write(true).open(&self.path)?;
+        self.file.seek(SeekFrom::End(0))?;
+        self.log_len = log.len() as u64;
+        self.live_len = self.log_len;
+        debug!("kv: compacted {} to {} bytes", self.path, self.log_len);
+        Ok(())
+    }
+
+    /// Exposes the entries in `/proc/kv/<name>`, one `key=value` per line.
+    ///
+    /// The file is kept up to date with the store. Values that are not
+    /// printable text are shown in hex.
+    #[cfg(feature = "procfs")]
+    pub fn expose_in_procfs(&mut self, name: &str) -> AxResult {
+        if name.is_empty() || name.contains('/') {
+            return ax_err!(InvalidInput, "kv: invalid name");
+        }
+        self.proc_path = Some(format!("/proc/kv/{}", name));
+        self.update_proc()
+    }
+
+    #[cfg(feature = "procfs")]
+    fn update_proc(&self) -> AxResult {
+        use core::fmt::Write;
+
+        let Some(path) = &self.proc_path else {
+            return Ok(());
+        };
+        let mut content = String::new();
+        for (key, value) in &self.entries {
+            match core::str::from_utf8(value) {
+                Ok(text) if !text.contains(|c: char| c.is_control()) => {
+                    writeln!(content, "{}={}", key, text).unwrap()
+                }
+                _ => {
+                    write!(content, "{}=0x", key).unwrap();
+                    for b in value {
+                        write!(content, "{:02x}", b).unwrap();
+                    }
+                    content.push('\n');
+                }
+            }
+        }
+        api::write(path, content)
+    }
+}
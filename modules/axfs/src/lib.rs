@@ -15,8 +15,29 @@
 //!    to create and initialize other filesystems. This feature is **disabled** by
 //!    by default, but it will override other filesystem selection features if
 //!    both are enabled.
+//! - `squashfs`: Use a read-only [SquashFS] image compressed with gzip as the
+//!    main filesystem instead of FAT, with a ramfs on `/var` for the data that
+//!    changes, or over all of it with the `overlay` feature. The image must
+//!    have the directories where the other filesystems are mounted (e.g.
+//!    `/dev`, `/tmp` and `/var`). This feature is **disabled** by default.
+//! - `zip`: Enable reading zip archives with [`zip::ZipArchive`]. This feature
+//!    is **disabled** by default.
+//! - `kv`: Enable the persistent key-value store [`kv::KvStore`]. This feature
+//!    is **disabled** by default.
+//! - `ninep`: Mount the directories shared by the host through 9P transports
+//!    on `/mnt/<mount tag>`, see [`init_ninep`]. This feature is **disabled**
+//!    by default.
+//! - `overlay`: Enable mounting a writable directory over read-only ones with
+//!    [`mount_overlay`]. This feature is **disabled** by default.
+//! - `initramfs`: Enable booting from an initial RAM filesystem, a cpio
+//!    archive unpacked into a ramfs used as the root, see [`init_initramfs`].
+//!    This feature is **disabled** by default.
+//!
+//! The blocks of the disk are cached in memory, see [`cache`] for how to
+//! control the cache.
 //!
 //! [FAT]: https://en.wikipedia.org/wiki/File_Allocation_Table
+//! [SquashFS]: https://en.wikipedia.org/wiki/SquashFS
 //! [`MyFileSystemIf`]: fops::MyFileSystemIf
 
 #![cfg_attr(all(not(test), not(doc)), no_std)]
@@ -26,13 +47,24 @@
 extern crate log;
 extern crate alloc;
 
+#[cfg(any(feature = "zip", feature = "kv"))]
+mod crc32;
 mod dev;
 mod fs;
+#[cfg(feature = "initramfs")]
+mod initramfs;
 mod mounts;
 mod root;
 
 pub mod api;
+pub mod cache;
+pub mod error;
 pub mod fops;
+#[cfg(feature = "kv")]
+pub mod kv;
+pub mod xattr;
+#[cfg(feature = "zip")]
+pub mod zip;
 pub use root::{CURRENT_DIR, CURRENT_DIR_PATH};
 
 use axdriver::{AxDeviceContainer, prelude::*};
@@ -45,3 +77,82 @@ pub fn init_filesystems(mut blk_devs: AxDeviceContainer<AxBlockDevice>) {
     info!("  use block device 0: {:?}", dev.device_name());
     self::root::init_rootfs(self::dev::Disk::new(dev));
 }
+
+/// Initializes filesystems with the initial RAM filesystem `cpio`, a cpio
+/// archive in the `newc` format, e.g. loaded by the bootloader.
+///
+/// The archive is unpacked into a ramfs used as the root, so that no disk is
+/// needed. The filesystem of the first block device, if any, is mounted on
+/// `/mnt`.
+#[cfg(feature = "initramfs")]
+pub fn init_initramfs(cpio: &[u8], mut blk_devs: AxDeviceContainer<AxBlockDevice>) {
+    info!("Initialize filesystems from the initramfs...");
+
+    let disk = blk_devs.take_one().map(|dev| {
+        info!("  use block device 0: {:?}", dev.device_name());
+        self::dev::Disk::new(dev)
+    });
+    self::root::init_initramfs(cpio, disk);
+}
+
+/// Mounts the filesystem of each 9P transport on `/mnt/<mount tag>` (or
+/// `/mnt/9p<n>` if it has no tag), e.g. the directories shared by the host.
+///
+/// It must be called after [`init_filesystems`].
+#[cfg(feature = "ninep")]
+pub fn init_ninep(mut ninep_devs: AxDeviceContainer<AxNinePDevice>) {
+    let mut i = 0;
+    while let Some(dev) = ninep_devs.take_one() {
+        let path = match dev.mount_tag() {
+            "" => alloc::format!("/mnt/9p{i}"),
+            tag => alloc::format!("/mnt/{tag}"),
+        };
+        i += 1;
+        let res = fs::ninep::NinePFileSystem::new(dev)
+            .and_then(|fs| self::root::mount(path.clone().leak(), alloc::sync::Arc::new(fs)));
+        match res {
+            Ok(()) => info!("  mounted 9P filesystem at {}", path),
+            Err(e) => warn!("failed to mount 9P filesystem at {}: {:?}", path, e),
+        }
+    }
+}
+
+/// Mounts on `path` the overlay of the directory `upper` over the directories
+/// `lowers`, the first one being on top: their entries are merged, and the
+/// changes are only written to `upper`, copying the files up as needed.
+///
+/// The deleted entries of the lower directories are hidden by whiteouts in
+/// `upper`, the files named `.wh.<name>`.
+#[cfg(feature = "overlay")]
+pub fn mount_overlay(path: &str, upper: &str, lowers: &[&str]) -> axerrno::AxResult {
+    let lookup_dir = |path: &str| {
+        let node = self::root::lookup(None, path)?;
+        if node.get_attr()?.is_dir() {
+            Ok(node)
+        } else {
+            axerrno::ax_err!(NotADirectory)
+        }
+    };
+    let upper = lookup_dir(upper)?;
+    let lowers = lowers
+        .iter()
+        .map(|path| lookup_dir(path))
+        .collect::<axerrno::AxResult<_>>()?;
+    let fs = fs::overlay::OverlayFileSystem::new(upper, lowers);
+    let path = self::root::absolute_path(path)?;
+    self::root::mount(path.leak(), alloc::sync::Arc::new(fs))
+}
+
+/// Adds the device file `/dev/<name>`, e.g. for a device found by a driver.
+///
+/// Fails with [`Unsupported`](axerrno::AxError::Unsupported) without the
+/// `devfs` feature, or before [`init_filesystems`].
+pub fn add_device(name: &'static str, dev: axfs_vfs::VfsNodeRef) -> axerrno::AxResult {
+    #[cfg(feature = "devfs")]
+    if let Some(devfs) = mounts::DEVFS.get() {
+        devfs.add(name, dev);
+        return Ok(());
+    }
+    let _ = (name, dev);
+    axerrno::ax_err!(Unsupported, "devfs not mounted")
+}
@@ -3,6 +3,11 @@ use axfs_vfs::{VfsNodeType, VfsOps, VfsResult};
 
 use crate::fs;
 
+/// The devfs mounted on `/dev`, for [`add_device`](crate::add_device).
+#[cfg(feature = "devfs")]
+pub(crate) static DEVFS: lazyinit::LazyInit<Arc<fs::devfs::DeviceFileSystem>> =
+    lazyinit::LazyInit::new();
+
 #[cfg(feature = "devfs")]
 pub(crate) fn devfs() -> Arc<fs::devfs::DeviceFileSystem> {
     let null = fs::devfs::NullDev;
@@ -13,7 +18,8 @@ pub(crate) fn devfs() -> Arc<fs::devfs::DeviceFileSystem> {
     devfs.add("null", Arc::new(null));
     devfs.add("zero", Arc::new(zero));
     foo_dir.add("bar", Arc::new(bar));
-    Arc::new(devfs)
+    DEVFS.init_once(Arc::new(devfs));
+    DEVFS.clone()
 }
 
 #[cfg(feature = "ramfs")]
@@ -40,10 +46,46 @@ pub(crate) fn procfs() -> VfsResult<Arc<fs::ramfs::RamFileSystem>> {
     let file_over = proc_root.clone().lookup("./sys/vm/overcommit_memory")?;
     file_over.write_at(0, b"0\n")?;
 
+    // Create /proc/sys/vm/drop_caches and /proc/sys/vm/vfs_cache_pressure, the
+    // writes are handled by the POSIX API (see `crate::cache`)
+    proc_root.create("sys/vm/drop_caches", VfsNodeType::File)?;
+    proc_root.create("sys/vm/vfs_cache_pressure", VfsNodeType::File)?;
+    let file_pressure = proc_root.clone().lookup("./sys/vm/vfs_cache_pressure")?;
+    file_pressure.write_at(0, b"100\n")?;
+
+    // Create /proc/sys/kernel/randomize_va_space, the writes are handled by
+    // the POSIX API (see `axmm::set_randomize_va_space`)
+    proc_root.create("sys/kernel", VfsNodeType::Dir)?;
+    proc_root.create("sys/kernel/randomize_va_space", VfsNodeType::File)?;
+    let file_aslr = proc_root
+        .clone()
+        .lookup("./sys/kernel/randomize_va_space")?;
+    file_aslr.write_at(0, b"2\n")?;
+
     // Create /proc/self/stat
     proc_root.create("self", VfsNodeType::Dir)?;
     proc_root.create("self/stat", VfsNodeType::File)?;
 
+    // Create /proc/self/environ, updated by `axruntime::env`
+    proc_root.create("self/environ", VfsNodeType::File)?;
+
+    // Create /proc/net/route and /proc/net/tcp, updated by the POSIX API when
+    // they're opened
+    proc_root.create("net", VfsNodeType::Dir)?;
+    proc_root.create("net/route", VfsNodeType::File)?;
+    proc_root.create("net/tcp", VfsNodeType::File)?;
+
+    // Create /proc/groups and /proc/schedstat, updated by the POSIX API when
+    // they're opened
+    proc_root.create("groups", VfsNodeType::File)?;
+    proc_root.create("schedstat", VfsNodeType::File)?;
+
+    // Create /proc/iomem, written by `axruntime` at boot
+    proc_root.create("iomem", VfsNodeType::File)?;
+
+    // Create /proc/kv, for `axfs::kv::KvStore::expose_in_procfs`
+    proc_root.create("kv", VfsNodeType::Dir)?;
+
     Ok(Arc::new(procfs))
 }
 
@@ -4,14 +4,13 @@
 
 use alloc::{string::String, sync::Arc, vec::Vec};
 use axerrno::{AxError, AxResult, ax_err};
-use axfs_vfs::{VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsNodeType, VfsOps, VfsResult};
+use axfs_vfs::{VfsDirEntry, VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsNodeType, VfsOps, VfsResult};
 use axns::{ResArc, def_resource};
-use axsync::Mutex;
+use axsync::{Mutex, RwLock};
 use lazyinit::LazyInit;
-use spin::RwLock;
 
 use crate::{
-    api::FileType,
+    api::{DiskUsage, FileType},
     fs::{self},
     mounts,
 };
@@ -38,18 +37,27 @@ impl CURRENT_DIR {
 struct MountPoint {
     path: &'static str,
     fs: Arc<dyn VfsOps>,
+    usage: Option<DiskUsageFn>,
 }
 
+/// Gets the disk space usage of a filesystem.
+type DiskUsageFn = fn() -> AxResult<DiskUsage>;
+
 struct RootDirectory {
     main_fs: Arc<dyn VfsOps>,
+    main_fs_usage: Option<DiskUsageFn>,
     mounts: RwLock<Vec<MountPoint>>,
 }
 
 static ROOT_DIR: LazyInit<Arc<RootDirectory>> = LazyInit::new();
 
 impl MountPoint {
-    pub fn new(path: &'static str, fs: Arc<dyn VfsOps>) -> Self {
-        Self { path, fs }
+    pub fn new(path: &'static str, fs: Arc<dyn VfsOps>, usage: Option<DiskUsageFn>) -> Self {
+        Self { path, fs, usage }
+    }
+
+    fn usage(&self) -> AxResult<DiskUsage> {
+        self.usage.map_or(Ok(DiskUsage::default()), |f| f())
     }
 }
 
@@ -60,14 +68,22 @@ impl Drop for MountPoint {
 }
 
 impl RootDirectory {
-    pub const fn new(main_fs: Arc<dyn VfsOps>) -> Self {
+    pub const fn new(main_fs: Arc<dyn VfsOps>, main_fs_usage: Option<DiskUsageFn>) -> Self {
         Self {
             main_fs,
+            main_fs_usage,
             mounts: RwLock::new(Vec::new()),
         }
     }
 
-    pub fn mount(&self, path: &'static str, fs: Arc<dyn VfsOps>) -> AxResult {
+    /// Mounts `fs` on `path`, where `usage` gets its disk usage if it's on a
+    /// disk.
+    pub fn mount(
+        &self,
+        path: &'static str,
+        fs: Arc<dyn VfsOps>,
+        usage: Option<DiskUsageFn>,
+    ) -> AxResult {
         if path == "/" {
             return ax_err!(InvalidInput, "cannot mount root filesystem");
         }
@@ -77,10 +93,19 @@ impl RootDirectory {
         if self.mounts.read().iter().any(|mp| mp.path == path) {
             return ax_err!(InvalidInput, "mount point already exists");
         }
-        // create the mount point in the main filesystem if it does not exist
-        self.main_fs.root_dir().create(path, FileType::Dir)?;
-        fs.mount(path, self.main_fs.root_dir().lookup(path)?)?;
-        self.mounts.write().push(MountPoint::new(path, fs));
+        // create the mount point in the main filesystem if it does not exist,
+        // with its parents
+        let main_root = self.main_fs.root_dir();
+        let create_dir = |path| match main_root.create(path, FileType::Dir) {
+            Err(AxError::AlreadyExists) => Ok(()),
+            res => res,
+        };
+        for (i, _) in path.match_indices('/').skip(1) {
+            create_dir(&path[..i])?;
+        }
+        create_dir(path)?;
+        fs.mount(path, main_root.lookup(path)?)?;
+        self.mounts.write().push(MountPoint::new(path, fs, usage));
         Ok(())
     }
 
@@ -92,6 +117,25 @@ impl RootDirectory {
         self.mounts.read().iter().any(|mp| mp.path == path)
     }
 
+    /// Returns the disk space usage of the filesystem containing `path`.
+    pub fn disk_usage(&self, path: &str) -> AxResult<DiskUsage> {
+        self.lookup_mounted_fs(path, |fs, _| {
+            if Arc::ptr_eq(&fs, &self.main_fs) {
+                return self.main_fs_usage();
+            }
+            let mounts = self.mounts.read();
+            match mounts.iter().find(|mp| Arc::ptr_eq(&mp.fs, &fs)) {
+                Some(mp) => mp.usage(),
+                // unmounted meanwhile
+                None => ax_err!(NotFound),
+            }
+        })
+    }
+
+    fn main_fs_usage(&self) -> AxResult<DiskUsage> {
+        self.main_fs_usage.map_or(Ok(DiskUsage::default()), |f| f())
+    }
+
     fn lookup_mounted_fs<F, T>(&self, path: &str, f: F) -> AxResult<T>
     where
         F: FnOnce(Arc<dyn VfsOps>, &str) -> AxResult<T>,
@@ -102,24 +146,23 @@ impl RootDirectory {
             return self.lookup_mounted_fs(rest, f);
         }
 
-        let mut idx = 0;
+        let mut fs = &self.main_fs; // not matched any mount point
         let mut max_len = 0;
 
         // Find the filesystem that has the longest mounted path match
         // TODO: more efficient, e.g. trie
-        for (i, mp) in self.mounts.read().iter().enumerate() {
+        let mounts = self.mounts.read();
+        for mp in mounts.iter() {
             // skip the first '/'
             if path.starts_with(&mp.path[1..]) && mp.path.len() - 1 > max_len {
                 max_len = mp.path.len() - 1;
-                idx = i;
+                fs = &mp.fs;
             }
         }
-
-        if max_len == 0 {
-            f(self.main_fs.clone(), path) // not matched any mount point
-        } else {
-            f(self.mounts.read()[idx].fs.clone(), &path[max_len..]) // matched at `idx`
-        }
+        // don't block mounting while `f` runs
+        let fs = fs.clone();
+        drop(mounts);
+        f(fs, &path[max_len..])
     }
 }
 
@@ -165,44 +208,107 @@ impl VfsNodeOps for RootDirectory {
     }
 }
 
-pub(crate) fn init_rootfs(disk: crate::dev::Disk) {
+/// Returns the main filesystem on `disk`, and the function to get its disk
+/// usage.
+fn disk_fs(disk: crate::dev::Disk) -> (Arc<dyn VfsOps>, Option<DiskUsageFn>) {
     cfg_if::cfg_if! {
         if #[cfg(feature = "myfs")] { // override the default filesystem
             let main_fs = fs::myfs::new_myfs(disk);
+            let main_fs_usage = None;
         } else if #[cfg(feature = "lwext4_rs")] {
             static EXT4_FS: LazyInit<Arc<fs::lwext4_rust::Ext4FileSystem>> = LazyInit::new();
             EXT4_FS.init_once(Arc::new(fs::lwext4_rust::Ext4FileSystem::new(disk)));
             let main_fs = EXT4_FS.clone();
+            let main_fs_usage = None;
+        } else if #[cfg(feature = "squashfs")] {
+            static SQUASH_FS: LazyInit<Arc<fs::squashfs::SquashFileSystem>> = LazyInit::new();
+            let squash_fs = fs::squashfs::SquashFileSystem::new(disk)
+                .expect("failed to initialize SquashFS filesystem");
+            SQUASH_FS.init_once(Arc::new(squash_fs));
+            let main_fs = SQUASH_FS.clone();
+            let main_fs_usage: Option<DiskUsageFn> = Some(|| SQUASH_FS.disk_usage());
         } else if #[cfg(feature = "fatfs")] {
             static FAT_FS: LazyInit<Arc<fs::fatfs::FatFileSystem>> = LazyInit::new();
             FAT_FS.init_once(Arc::new(fs::fatfs::FatFileSystem::new(disk)));
             FAT_FS.init();
             let main_fs = FAT_FS.clone();
+            let main_fs_usage: Option<DiskUsageFn> = Some(|| FAT_FS.disk_usage());
+        }
+    }
+
+    // Keep the changes to the SquashFS root in memory, over the image.
+    #[cfg(all(
+        feature = "squashfs",
+        feature = "overlay",
+        feature = "ramfs",
+        not(any(feature = "myfs", feature = "lwext4_rs"))
+    ))]
+    let main_fs: Arc<dyn VfsOps> = Arc::new(fs::overlay::OverlayFileSystem::new(
+        mounts::ramfs().root_dir(),
+        alloc::vec![main_fs.root_dir()],
+    ));
+
+    (main_fs, main_fs_usage)
+}
+
+pub(crate) fn init_rootfs(disk: crate::dev::Disk) {
+    let (main_fs, main_fs_usage) = disk_fs(disk);
+    init_root_dir(main_fs, main_fs_usage);
+}
+
+/// Uses a ramfs with the content of the cpio archive `cpio` as the root, and
+/// mounts the filesystem on `disk`, if any, on `/mnt`.
+#[cfg(feature = "initramfs")]
+pub(crate) fn init_initramfs(cpio: &[u8], disk: Option<crate::dev::Disk>) {
+    let ramfs = mounts::ramfs();
+    match crate::initramfs::unpack(cpio, &ramfs.root_dir()) {
+        Ok(count) => info!("  unpacked {} entries from the initramfs", count),
+        Err(e) => warn!("failed to unpack the initramfs: {:?}", e),
+    }
+    init_root_dir(ramfs, None);
+
+    if let Some(disk) = disk {
+        let (disk_fs, usage) = disk_fs(disk);
+        if let Err(e) = ROOT_DIR.mount("/mnt", disk_fs, usage) {
+            warn!("failed to mount the disk at /mnt: {:?}", e);
         }
     }
+}
 
-    let root_dir = RootDirectory::new(main_fs);
+fn init_root_dir(main_fs: Arc<dyn VfsOps>, main_fs_usage: Option<DiskUsageFn>) {
+    let root_dir = RootDirectory::new(main_fs, main_fs_usage);
 
     #[cfg(feature = "devfs")]
     root_dir
-        .mount("/dev", mounts::devfs())
+        .mount("/dev", mounts::devfs(), None)
         .expect("failed to mount devfs at /dev");
 
     #[cfg(feature = "ramfs")]
     root_dir
-        .mount("/tmp", mounts::ramfs())
+        .mount("/tmp", mounts::ramfs(), None)
         .expect("failed to mount ramfs at /tmp");
 
+    // The SquashFS root is read-only without an overlay, keep the variable
+    // data in memory. The image must have the mount points.
+    #[cfg(all(
+        feature = "squashfs",
+        feature = "ramfs",
+        not(any(feature = "myfs", feature = "lwext4_rs", feature = "overlay"))
+    ))]
+    if let Err(e) = root_dir.mount("/var", mounts::ramfs(), None) {
+        warn!("failed to mount ramfs at /var: {:?}", e);
+    }
+
     // Mount another ramfs as procfs
     #[cfg(feature = "procfs")]
     root_dir // should not fail
-        .mount("/proc", mounts::procfs().unwrap())
+        .mount("/proc", mounts::procfs().unwrap(), None)
         .expect("fail to mount procfs at /proc");
 
     // Mount another ramfs as sysfs
     #[cfg(feature = "sysfs")]
     root_dir // should not fail
-        .mount("/sys", mounts::sysfs().unwrap())
+        .mount("/sys", mounts::sysfs().unwrap(), None)
         .expect("fail to mount sysfs at /sys");
 
     ROOT_DIR.init_once(Arc::new(root_dir));
@@ -212,6 +318,12 @@ pub(crate) fn init_rootfs(disk: crate::dev::Disk) {
     CURRENT_DIR_PATH.init_new(Mutex::new("/".into()));
 }
 
+/// Mounts `fs` on `path`, once the root filesystem is initialized.
+#[cfg(any(feature = "ninep", feature = "overlay"))]
+pub(crate) fn mount(path: &'static str, fs: Arc<dyn VfsOps>) -> AxResult {
+    ROOT_DIR.mount(path, fs, None)
+}
+
 fn parent_node_of(dir: Option<&VfsNodeRef>, path: &str) -> VfsNodeRef {
     if path.starts_with('/') {
         ROOT_DIR.clone()
@@ -301,6 +413,19 @@ pub(crate) fn remove_dir(dir: Option<&VfsNodeRef>, path: &str) -> AxResult {
     }
 }
 
+pub(crate) fn disk_usage(abs_path: &str) -> AxResult<DiskUsage> {
+    ROOT_DIR.disk_usage(abs_path)
+}
+
+pub(crate) fn mounts() -> AxResult<Vec<(String, DiskUsage)>> {
+    let mut mounts = Vec::new();
+    mounts.push(("/".into(), ROOT_DIR.main_fs_usage()?));
+    for mp in ROOT_DIR.mounts.read().iter() {
+        mounts.push((mp.path.into(), mp.usage()?));
+    }
+    Ok(mounts)
+}
+
 pub(crate) fn current_dir() -> AxResult<String> {
     Ok(CURRENT_DIR_PATH.lock().clone())
 }
@@ -329,8 +454,44 @@ pub(crate) fn set_current_dir(path: &str) -> AxResult {
     }
 }
 
+/// Returns `true` if renaming `old` to `new` only changes the case of its
+/// name in a filesystem that ignores it (e.g. FAT), where `new` is found as
+/// `old` itself, and must not be removed.
+fn is_case_rename(old: &str, new: &str) -> bool {
+    if old == new || !fs::eq_ignore_case(old, new) {
+        return false;
+    }
+    let new = new.trim_end_matches('/');
+    let (dir, name) = match new.rsplit_once('/') {
+        Some(("", name)) => ("/", name),
+        Some((dir, name)) => (dir, name),
+        None => (".", new),
+    };
+    let Ok(dir) = lookup(None, dir) else {
+        return false;
+    };
+    // a filesystem that doesn't ignore the case has an entry named `new`
+    const EMPTY: VfsDirEntry = VfsDirEntry::default();
+    let mut entries = [EMPTY; 16];
+    let mut start = 0;
+    loop {
+        match dir.read_dir(start, &mut entries) {
+            Ok(0) => return true,
+            Ok(n)
+                if entries[..n]
+                    .iter()
+                    .any(|e| e.name_as_bytes() == name.as_bytes()) =>
+            {
+                return false;
+            }
+            Ok(n) => start += n,
+            Err(_) => return false,
+        }
+    }
+}
+
 pub(crate) fn rename(old: &str, new: &str) -> AxResult {
-    if parent_node_of(None, new).lookup(new).is_ok() {
+    if parent_node_of(None, new).lookup(new).is_ok() && !is_case_rename(old, new) {
         warn!("dst file already exist, now remove it");
         remove_file(None, new)?;
     }
@@ -0,0 +1,104 @@
+//! Extended attributes (xattrs) of files and directories.
+//!
+//! [`VfsNodeOps`] has no extended attributes, so the nodes of filesystems
+//! that support them implement [`XattrOps`] as well. Access control lists
+//! are the `system.posix_acl_access` and `system.posix_acl_default`
+//! attributes, whose values are passed through as they are.
+//!
+//! [`VfsNodeOps`]: axfs_vfs::VfsNodeOps
+
+use axfs_vfs::{VfsError, VfsNodeRef, VfsResult};
+
+/// The maximum length of the name of an attribute.
+pub const XATTR_NAME_MAX: usize = 255;
+/// The maximum size of the value of an attribute.
+pub const XATTR_SIZE_MAX: usize = 65536;
+/// The maximum size of the list of the names of the attributes.
+pub const XATTR_LIST_MAX: usize = 65536;
+
+/// How [`set_xattr`](crate::api::set_xattr) treats an existing attribute.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum XattrSetMode {
+    /// Creates the attribute, or replaces its value.
+    Any,
+    /// Fails with [`VfsError::AlreadyExists`] if the attribute exists.
+    Create,
+    /// Fails with [`VfsError::NotFound`] if the attribute doesn't exist.
+    Replace,
+}
+
+/// Operations on the extended attributes of a node.
+///
+/// The names are full names with the namespace, e.g. `user.mime_type`. A
+/// missing attribute is reported as [`VfsError::NotFound`].
+pub trait XattrOps {
+    /// Returns the size of the value of the attribute `name`, which is read
+    /// into `buf` only if it fits.
+    fn get_xattr(&self, name: &str, buf: &mut [u8]) -> VfsResult<usize>;
+
+    /// Sets the value of the attribute `name`, creating it if needed.
+    fn set_xattr(&self, name: &str, value: &[u8]) -> VfsResult;
+
+    /// Returns the size of the list of the names of the attributes, each
+    /// followed by a NUL, which is read into `buf` only if it fits.
+    fn list_xattr(&self, buf: &mut [u8]) -> VfsResult<usize>;
+
+    /// Removes the attribute `name`.
+    fn remove_xattr(&self, name: &str) -> VfsResult;
+}
+
+/// Returns the extended attribute operations of `node`, or
+/// [`VfsError::Unsupported`] if its filesystem has none.
+fn xattr_ops(node: &VfsNodeRef) -> VfsResult<&dyn XattrOps> {
+    #[cfg(all(feature = "lwext4_rs", not(feature = "myfs")))]
+    if let Some(node) = node
+        .as_any()
+        .downcast_ref::<crate::fs::lwext4_rust::FileWrapper>()
+    {
+        return Ok(node);
+    }
+    let _ = node;
+    Err(VfsError::Unsupported)
+}
+
+fn check_name(name: &str) -> VfsResult {
+    if name.is_empty() || name.len() > XATTR_NAME_MAX || !name.contains('.') {
+        return Err(VfsError::InvalidInput);
+    }
+    Ok(())
+}
+
+pub(crate) fn get(node: &VfsNodeRef, name: &str, buf: &mut [u8]) -> VfsResult<usize> {
+    check_name(name)?;
+    xattr_ops(node)?.get_xattr(name, buf)
+}
+
+pub(crate) fn set(node: &VfsNodeRef, name: &str, value: &[u8], mode: XattrSetMode) -> VfsResult {
+    check_name(name)?;
+    if value.len() > XATTR_SIZE_MAX {
+        return Err(VfsError::InvalidInput);
+    }
+    let ops = xattr_ops(node)?;
+    if mode != XattrSetMode::Any {
+        let exists = match ops.get_xattr(name, &mut []) {
+            Ok(_) => true,
+            Err(VfsError::NotFound) => false,
+            Err(e) => return Err(e),
+        };
+        match (mode, exists) {
+            (XattrSetMode::Create, true) => return Err(VfsError::AlreadyExists),
+            (XattrSetMode::Replace, false) => return Err(VfsError::NotFound),
+            _ => {}
+        }
+    }
+    ops.set_xattr(name, value)
+}
+
+pub(crate) fn list(node: &VfsNodeRef, buf: &mut [u8]) -> VfsResult<usize> {
+    xattr_ops(node)?.list_xattr(buf)
+}
+
+pub(crate) fn remove(node: &VfsNodeRef, name: &str) -> VfsResult {
+    check_name(name)?;
+    xattr_ops(node)?.remove_xattr(name)
+}
@@ -0,0 +1,412 @@
+//! Read-only access to zip archives.
+//!
+//! Only the central directory is parsed when an archive is opened, and each
+//! entry is decompressed on the fly when it's read. Entries can be stored or
+//! compressed with deflate. ZIP64, encryption and multi-disk archives are not
+//! supported.
+
+use alloc::{boxed::Box, string::String, vec, vec::Vec};
+
+use axerrno::{ax_err, ax_err_type};
+use axio::{self as io, SeekFrom, prelude::*};
+use miniz_oxide::inflate::stream::{InflateState, inflate};
+use miniz_oxide::{DataFormat, MZError, MZFlush, MZStatus};
+
+use crate::api::File;
+use crate::crc32::{CRC_INIT, crc32_update};
+
+const EOCD_SIGNATURE: u32 = 0x0605_4b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x0201_4b50;
+const LOCAL_HEADER_SIGNATURE: u32 = 0x0403_4b50;
+
+const EOCD_LEN: usize = 22;
+const CENTRAL_HEADER_LEN: usize = 46;
+const LOCAL_HEADER_LEN: usize = 30;
+const MAX_COMMENT_LEN: usize = u16::MAX as usize;
+
+const FLAG_ENCRYPTED: u16 = 1 << 0;
+
+const INPUT_BUF_LEN: usize = 4096;
+
+/// The compression method of a zip entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompressionMethod {
+    /// The entry is stored as is.
+    Stored,
+    /// The entry is compressed with deflate.
+    Deflated,
+}
+
+/// Metadata of an entry in a zip archive.
+#[derive(Debug, Clone)]
+pub struct ZipEntry {
+    name: String,
+    method: CompressionMethod,
+    crc32: u32,
+    compressed_size: u64,
+    size: u64,
+    header_offset: u64,
+}
+
+impl ZipEntry {
+    /// Returns the path of the entry within the archive.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns whether the entry is a directory, whose name ends with `/`.
+    pub fn is_dir(&self) -> bool {
+        self.name.ends_with('/')
+    }
+
+    /// Returns the compression method of the entry.
+    pub fn compression(&self) -> CompressionMethod {
+        self.method
+    }
+
+    /// Returns the uncompressed size of the entry.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Returns the compressed size of the entry.
+    pub fn compressed_size(&self) -> u64 {
+        self.compressed_size
+    }
+
+    /// Returns the CRC-32 checksum of the uncompressed data.
+    pub fn crc32(&self) -> u32 {
+        self.crc32
+    }
+}
+
+/// A zip archive opened for reading.
+pub struct ZipArchive<R> {
+    reader: R,
+    entries: Vec<ZipEntry>,
+}
+
+impl ZipArchive<File> {
+    /// Opens the zip archive at the given path.
+    pub fn open(path: &str) -> io::Result<Self> {
+        Self::new(File::open(path)?)
+    }
+}
+
+impl<R: Read + Seek> ZipArchive<R> {
+    /// Reads the central directory of the zip archive in `reader`.
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let (count, cd_offset) = find_central_directory(&mut reader)?;
+        reader.seek(SeekFrom::Start(cd_offset))?;
+
+        let mut entries = Vec::with_capacity(count);
+        for _ in 0..count {
+            let mut header = [0; CENTRAL_HEADER_LEN];
+            reader.read_exact(&mut header)?;
+            if le_u32(&header, 0) != CENTRAL_HEADER_SIGNATURE {
+                return ax_err!(InvalidData, "zip: bad central directory header");
+            }
+            let name_len = le_u16(&header, 28) as usize;
+            let extra_len = le_u16(&header, 30) as i64;
+            let comment_len = le_u16(&header, 32) as i64;
+
+            let mut name = vec![0; name_len];
+            reader.read_exact(&mut name)?;
+            reader.seek(SeekFrom::Current(extra_len + comment_len))?;
+
+            let method = match le_u16(&header, 10) {
+                0 => CompressionMethod::Stored,
+                8 => CompressionMethod::Deflated,
+                _ => return ax_err!(Unsupported, "zip: unsupported compression method"),
+            };
+            if le_u16(&header, 8) & FLAG_ENCRYPTED != 0 {
+                return ax_err!(Unsupported, "zip: encrypted entry");
+            }
+            let compressed_size = le_u32(&header, 20);
+            let size = le_u32(&header, 24);
+            let header_offset = le_u32(&header, 42);
+            if [compressed_size, size, header_offset].contains(&u32::MAX) {
+                return ax_err!(Unsupported, "zip: ZIP64 not supported");
+            }
+            entries.push(ZipEntry {
+                name: String::from_utf8(name)
+                    .map_err(|_| ax_err_type!(InvalidData, "zip: entry name is not UTF-8"))?,
+                method,
+                crc32: le_u32(&header, 16),
+                compressed_size: compressed_size as u64,
+                size: size as u64,
+                header_offset: header_offset as u64,
+            });
+        }
+        Ok(Self { reader, entries })
+    }
+
+    /// Returns the number of entries in the archive.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns whether the archive has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns all entries in the archive, in the order of the central
+    /// directory.
+    pub fn entries(&self) -> &[ZipEntry] {
+        &self.entries
+    }
+
+    /// Returns the entry with the given name.
+    pub fn entry(&self, name: &str) -> Option<&ZipEntry> {
+        self.entries.iter().find(|e| e.name == name)
+    }
+
+    /// Opens the entry at the given index for reading.
+    pub fn by_index(&mut self, index: usize) -> io::Result<ZipFile<'_, R>> {
+        let entry = self
+            .entries
+            .get(index)
+            .ok_or_else(|| ax_err_type!(NotFound, "zip: no such entry"))?;
+        ZipFile::new(&mut self.reader, entry)
+    }
+
+    /// Opens the entry with the given name for reading.
+    pub fn by_name(&mut self, name: &str) -> io::Result<ZipFile<'_, R>> {
+        let index = self
+            .entries
+            .iter()
+            .position(|e| e.name == name)
+            .ok_or_else(|| ax_err_type!(NotFound, "zip: no such entry"))?;
+        self.by_index(index)
+    }
+
+    /// Consumes the archive, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+/// Decompression state of a deflated entry.
+struct Inflater {
+    state: Box<InflateState>,
+    input: Box<[u8]>,
+    input_pos: usize,
+    input_len: usize,
+    /// Number of compressed bytes read from the archive.
+    consumed: u64,
+    finished: bool,
+}
+
+/// A read-only file of a zip archive entry, returned by
+/// [`ZipArchive::by_name`] and [`ZipArchive::by_index`].
+///
+/// Seeking forward in a deflated entry decompresses the skipped data, and
+/// seeking backward restarts decompression from the beginning.
+pub struct ZipFile<'a, R> {
+    reader: &'a mut R,
+    entry: &'a ZipEntry,
+    data_offset: u64,
+    /// Position in the uncompressed data.
+    pos: u64,
+    inflater: Option<Inflater>,
+    /// CRC-32 of the data read so far, if it has been read sequentially from
+    /// the start.
+    crc: Option<u32>,
+}
+
+impl<'a, R: Read + Seek> ZipFile<'a, R> {
+    fn new(reader: &'a mut R, entry: &'a ZipEntry) -> io::Result<Self> {
+        let mut header = [0; LOCAL_HEADER_LEN];
+        reader.seek(SeekFrom::Start(entry.header_offset))?;
+        reader.read_exact(&mut header)?;
+        if le_u32(&header, 0) != LOCAL_HEADER_SIGNATURE {
+            return ax_err!(InvalidData, "zip: bad local file header");
+        }
+        let data_offset = entry.header_offset
+            + LOCAL_HEADER_LEN as u64
+            + le_u16(&header, 26) as u64
+            + le_u16(&header, 28) as u64;
+
+        let inflater = match entry.method {
+            CompressionMethod::Stored => None,
+            CompressionMethod::Deflated => Some(Inflater {
+                state: InflateState::new_boxed(DataFormat::Raw),
+                input: vec![0; INPUT_BUF_LEN].into_boxed_slice(),
+                input_pos: 0,
+                input_len: 0,
+                consumed: 0,
+                finished: false,
+            }),
+        };
+        Ok(Self {
+            reader,
+            entry,
+            data_offset,
+            pos: 0,
+            inflater,
+            crc: Some(CRC_INIT),
+        })
+    }
+
+    /// Returns the metadata of the entry.
+    pub fn entry(&self) -> &ZipEntry {
+        self.entry
+    }
+
+    /// Returns the uncompressed size of the entry.
+    pub fn size(&self) -> u64 {
+        self.entry.size
+    }
+
+    fn read_stored(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = buf.len().min((self.entry.size - self.pos) as usize);
+        self.reader
+            .seek(SeekFrom::Start(self.data_offset + self.pos))?;
+        self.reader.read_exact(&mut buf[..len])?;
+        Ok(len)
+    }
+
+    fn read_deflated(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let inflater = self.inflater.as_mut().unwrap();
+        loop {
+            if inflater.finished {
+                return Ok(0);
+            }
+            if inflater.input_pos == inflater.input_len {
+                let remaining = self.entry.compressed_size - inflater.consumed;
+                let len = inflater.input.len().min(remaining as usize);
+                self.reader
+                    .seek(SeekFrom::Start(self.data_offset + inflater.consumed))?;
+                self.reader.read_exact(&mut inflater.input[..len])?;
+                inflater.consumed += len as u64;
+                inflater.input_pos = 0;
+                inflater.input_len = len;
+            }
+
+            let input = &inflater.input[inflater.input_pos..inflater.input_len];
+            let res = inflate(&mut inflater.state, input, buf, MZFlush::None);
+            inflater.input_pos += res.bytes_consumed;
+            match res.status {
+                Ok(MZStatus::StreamEnd) => inflater.finished = true,
+                Ok(MZStatus::Ok) => {}
+                // no progress is possible with the given input
+                Err(MZError::Buf) if inflater.consumed < self.entry.compressed_size => {}
+                Err(MZError::Buf) => return ax_err!(UnexpectedEof, "zip: truncated entry"),
+                _ => return ax_err!(InvalidData, "zip: corrupted entry"),
+            }
+            if res.bytes_written > 0 || buf.is_empty() {
+                return Ok(res.bytes_written);
+            }
+        }
+    }
+
+    /// Rewinds the decompression to the beginning of the entry.
+    fn rewind(&mut self) {
+        if let Some(inflater) = &mut self.inflater {
+            inflater.state.reset(DataFormat::Raw);
+            inflater.input_pos = 0;
+            inflater.input_len = 0;
+            inflater.consumed = 0;
+            inflater.finished = false;
+        }
+        self.pos = 0;
+        self.crc = Some(CRC_INIT);
+    }
+}
+
+impl<R: Read + Seek> Read for ZipFile<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos >= self.entry.size {
+            return Ok(0);
+        }
+        let buf_len = buf.len().min((self.entry.size - self.pos) as usize);
+        let buf = &mut buf[..buf_len];
+        let len = match self.inflater {
+            None => self.read_stored(buf)?,
+            Some(_) => self.read_deflated(buf)?,
+        };
+        if len == 0 && !buf.is_empty() {
+            return ax_err!(UnexpectedEof, "zip: entry is shorter than expected");
+        }
+        self.pos += len as u64;
+
+        if let Some(crc) = &mut self.crc {
+            *crc = crc32_update(*crc, &buf[..len]);
+            if self.pos == self.entry.size && !*crc != self.entry.crc32 {
+                return ax_err!(InvalidData, "zip: CRC mismatch");
+            }
+        }
+        Ok(len)
+    }
+}
+
+impl<R: Read + Seek> Seek for ZipFile<'_, R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let new_pos = match pos {
+            SeekFrom::Start(pos) => Some(pos),
+            SeekFrom::Current(off) => self.pos.checked_add_signed(off),
+            SeekFrom::End(off) => self.entry.size.checked_add_signed(off),
+        }
+        .ok_or_else(|| ax_err_type!(InvalidInput, "zip: invalid seek position"))?
+        .min(self.entry.size);
+
+        if self.inflater.is_none() {
+            if new_pos != self.pos {
+                self.crc = None;
+            }
+            self.pos = new_pos;
+            return Ok(new_pos);
+        }
+
+        if new_pos < self.pos {
+            self.rewind();
+        }
+        let mut scratch = [0; 512];
+        while self.pos < new_pos {
+            let len = scratch.len().min((new_pos - self.pos) as usize);
+            self.read(&mut scratch[..len])?;
+        }
+        Ok(self.pos)
+    }
+}
+
+/// Finds the end of central directory record, and returns the number of
+/// entries and the offset of the central directory.
+fn find_central_directory<R: Read + Seek>(reader: &mut R) -> io::Result<(usize, u64)> {
+    let file_len = reader.seek(SeekFrom::End(0))?;
+    let tail_len = file_len.min((EOCD_LEN + MAX_COMMENT_LEN) as u64);
+    if tail_len < EOCD_LEN as u64 {
+        return ax_err!(InvalidData, "zip: file too short");
+    }
+    let mut tail = vec![0; tail_len as usize];
+    reader.seek(SeekFrom::Start(file_len - tail_len))?;
+    reader.read_exact(&mut tail)?;
+
+    // the record is followed by a comment of variable length
+    let pos = (0..=tail.len() - EOCD_LEN)
+        .rev()
+        .find(|&pos| {
+            le_u32(&tail, pos) == EOCD_SIGNATURE
+                && pos + EOCD_LEN + le_u16(&tail, pos + 20) as usize == tail.len()
+        })
+        .ok_or_else(|| ax_err_type!(InvalidData, "zip: end of central directory not found"))?;
+    let eocd = &tail[pos..];
+    if le_u16(eocd, 4) != 0 || le_u16(eocd, 6) != 0 || le_u16(eocd, 8) != le_u16(eocd, 10) {
+        return ax_err!(Unsupported, "zip: multi-disk archive");
+    }
+    let count = le_u16(eocd, 10);
+    let cd_offset = le_u32(eocd, 16);
+    if count == u16::MAX || cd_offset == u32::MAX {
+        return ax_err!(Unsupported, "zip: ZIP64 not supported");
+    }
+    Ok((count as usize, cd_offset as u64))
+}
+
+fn le_u16(buf: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([buf[offset], buf[offset + 1]])
+}
+
+fn le_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
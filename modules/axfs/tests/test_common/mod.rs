@@ -246,6 +246,10 @@ fn test_devfs_ramfs() -> Result<()> {
     assert_eq!(fs::remove_dir("tmp/dir/.././dir///"), Ok(()));
     assert_eq!(fs::read_dir("tmp").unwrap().count(), 0);
 
+    // ramfs has no extended attributes
+    assert_err!(fs::get_xattr("/tmp", "user.test", &mut []), Unsupported);
+    assert_err!(fs::list_xattr("/tmp/none", &mut []), NotFound);
+
     println!("test_devfs_ramfs() OK!");
     Ok(())
 }
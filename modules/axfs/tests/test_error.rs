@@ -0,0 +1,80 @@
+use axerrno::{AxError, LinuxError};
+use axfs::error::{from_errno, from_linux_error};
+
+const ALL_ERRORS: &[AxError] = &[
+    AxError::AddrInUse,
+    AxError::AlreadyExists,
+    AxError::BadAddress,
+    AxError::BadState,
+    AxError::ConnectionRefused,
+    AxError::ConnectionReset,
+    AxError::DirectoryNotEmpty,
+    AxError::InvalidData,
+    AxError::InvalidInput,
+    AxError::Io,
+    AxError::IsADirectory,
+    AxError::NoMemory,
+    AxError::NotADirectory,
+    AxError::NotConnected,
+    AxError::NotFound,
+    AxError::PermissionDenied,
+    AxError::ResourceBusy,
+    AxError::StorageFull,
+    AxError::UnexpectedEof,
+    AxError::Unsupported,
+    AxError::WouldBlock,
+    AxError::WriteZero,
+];
+
+#[test]
+fn test_round_trip() {
+    for &err in ALL_ERRORS {
+        let linux_err = LinuxError::from(err);
+        let back = from_linux_error(linux_err);
+        // several errors share a Linux error, so only the Linux side is stable
+        assert_eq!(LinuxError::from(back), linux_err, "{:?}", err);
+        if back == err {
+            continue;
+        }
+        assert!(
+            matches!(
+                err,
+                AxError::BadState
+                    | AxError::InvalidData
+                    | AxError::UnexpectedEof
+                    | AxError::WriteZero
+            ),
+            "{:?} is mapped back to {:?}",
+            err,
+            back
+        );
+    }
+}
+
+#[test]
+fn test_errno() {
+    for &err in ALL_ERRORS {
+        let linux_err = LinuxError::from(err);
+        let expected = from_linux_error(linux_err);
+        assert_eq!(from_errno(linux_err.code()), expected, "{:?}", err);
+        assert_eq!(from_errno(-linux_err.code()), expected, "{:?}", err);
+    }
+    assert_eq!(
+        from_errno(LinuxError::EROFS.code()),
+        AxError::PermissionDenied
+    );
+    assert_eq!(
+        from_errno(LinuxError::ENAMETOOLONG.code()),
+        AxError::InvalidInput
+    );
+    assert_eq!(from_errno(LinuxError::EDQUOT.code()), AxError::StorageFull);
+    assert_eq!(from_errno(LinuxError::ENODATA.code()), AxError::NotFound);
+}
+
+#[test]
+fn test_unknown_errors() {
+    assert_eq!(from_errno(0), AxError::Io);
+    assert_eq!(from_errno(i32::MAX), AxError::Io);
+    assert_eq!(from_errno(i32::MIN), AxError::Io);
+    assert_eq!(from_linux_error(LinuxError::ETIMEDOUT), AxError::Io);
+}
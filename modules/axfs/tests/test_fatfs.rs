@@ -24,4 +24,77 @@ fn test_fatfs() {
     axfs::init_filesystems(AxDeviceContainer::from_one(disk));
 
     test_common::test_all();
+    test_case_preserving();
+    test_drop_caches();
+}
+
+fn entry_names(dir: &str) -> Vec<String> {
+    axfs::api::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect()
+}
+
+fn test_case_preserving() {
+    println!("test case preserving ...");
+    axfs::api::write("/MixedCase.Txt", "mixed case\n").unwrap();
+    axfs::api::create_dir("/CaseDir").unwrap();
+    let names = entry_names("/");
+    assert!(names.iter().any(|name| name == "MixedCase.Txt"));
+    assert!(names.iter().any(|name| name == "CaseDir"));
+
+    // the names are looked up ignoring the case
+    assert_eq!(
+        axfs::api::read_to_string("/mixedcase.txt").unwrap(),
+        "mixed case\n"
+    );
+    assert!(axfs::api::metadata("/CASEDIR").unwrap().is_dir());
+    assert_eq!(
+        axfs::api::create_dir("/casedir").err(),
+        Some(axio::Error::AlreadyExists)
+    );
+
+    // renaming to another case changes the name, and keeps the file
+    axfs::api::rename("/MixedCase.Txt", "/mixedCASE.txt").unwrap();
+    axfs::api::rename("/CaseDir", "/casedir").unwrap();
+    let names = entry_names("/");
+    assert!(names.iter().any(|name| name == "mixedCASE.txt"));
+    assert!(!names.iter().any(|name| name == "MixedCase.Txt"));
+    assert!(names.iter().any(|name| name == "casedir"));
+    assert!(!names.iter().any(|name| name == "CaseDir"));
+    assert_eq!(
+        axfs::api::read_to_string("/MIXEDCASE.TXT").unwrap(),
+        "mixed case\n"
+    );
+
+    axfs::api::remove_file("/mixedcase.txt").unwrap();
+    axfs::api::remove_dir("/CaseDir").unwrap();
+    assert!(
+        !entry_names("/")
+            .iter()
+            .any(|name| name.eq_ignore_ascii_case("mixedcase.txt"))
+    );
+    println!("test_case_preserving() OK!");
+}
+
+fn test_drop_caches() {
+    println!("test drop caches ...");
+    let contents = axfs::api::read("/short.txt").unwrap();
+    let stats = axfs::cache::stats();
+    assert!(stats.blocks > 0 && stats.hits > 0);
+
+    assert_eq!(axfs::cache::drop_caches(), stats.blocks);
+    assert_eq!(axfs::cache::stats().blocks, 0);
+    assert_eq!(axfs::api::read("/short.txt").unwrap(), contents);
+    assert!(axfs::cache::stats().misses > stats.misses);
+
+    axfs::cache::set_cache_pressure(100 * 2048);
+    assert_eq!(axfs::cache::stats().blocks, 1);
+    axfs::cache::set_cache_pressure(100);
+    #[cfg(feature = "procfs")]
+    assert_eq!(
+        axfs::api::read_to_string("/proc/sys/vm/vfs_cache_pressure").unwrap(),
+        "100\n"
+    );
+    println!("test_drop_caches() OK!");
 }
@@ -0,0 +1,78 @@
+#![cfg(all(feature = "initramfs", not(feature = "myfs")))]
+
+use axdriver::AxDeviceContainer;
+use axdriver_block::ramdisk::RamDisk;
+use axfs::api as fs;
+use axio::{Error, Result};
+
+const IMG_PATH: &str = "resources/fat16.img";
+
+fn make_disk() -> std::io::Result<RamDisk> {
+    let path = std::env::current_dir()?.join(IMG_PATH);
+    let data = std::fs::read(path)?;
+    Ok(RamDisk::from(&data))
+}
+
+/// Appends an entry to a `newc` cpio archive.
+fn push_entry(cpio: &mut Vec<u8>, ino: u32, mode: u32, nlink: u32, name: &str, data: &[u8]) {
+    let fields = [ino, mode, 0, 0, nlink, 0, data.len() as u32, 0, 0, 0, 0];
+    cpio.extend_from_slice(b"070701");
+    for field in fields.iter().chain(&[name.len() as u32 + 1, 0]) {
+        cpio.extend_from_slice(format!("{field:08x}").as_bytes());
+    }
+    cpio.extend_from_slice(name.as_bytes());
+    cpio.push(0);
+    cpio.resize(cpio.len().next_multiple_of(4), 0);
+    cpio.extend_from_slice(data);
+    cpio.resize(cpio.len().next_multiple_of(4), 0);
+}
+
+fn make_cpio() -> Vec<u8> {
+    let mut cpio = Vec::new();
+    push_entry(&mut cpio, 1, 0o040755, 2, ".", b"");
+    push_entry(&mut cpio, 2, 0o040755, 2, "etc", b"");
+    push_entry(&mut cpio, 3, 0o100644, 1, "etc/hostname", b"arceos\n");
+    // the parents of an entry may be missing
+    push_entry(&mut cpio, 4, 0o100755, 1, "./bin/init", b"init");
+    // the data of a hard link is in its last entry
+    push_entry(&mut cpio, 5, 0o100644, 2, "a.txt", b"");
+    push_entry(&mut cpio, 5, 0o100644, 2, "b.txt", b"linked");
+    push_entry(&mut cpio, 6, 0o120777, 1, "sh", b"bin/init");
+    push_entry(&mut cpio, 0, 0, 1, "TRAILER!!!", b"");
+    cpio
+}
+
+fn test_unpacked() -> Result<()> {
+    assert_eq!(fs::read_to_string("/etc/hostname")?, "arceos\n");
+    assert_eq!(fs::read_to_string("/bin/init")?, "init");
+    assert_eq!(fs::read_to_string("/a.txt")?, "linked");
+    assert_eq!(fs::read_to_string("/b.txt")?, "linked");
+    // symbolic links are skipped
+    assert_eq!(fs::metadata("/sh").err(), Some(Error::NotFound));
+    Ok(())
+}
+
+fn test_disk() -> Result<()> {
+    // the disk is mounted on `/mnt`
+    assert!(fs::metadata("/mnt/short.txt")?.is_file());
+    // with the usage of the disk, not of the root
+    let usage = fs::disk_usage("/mnt/short.txt")?;
+    assert!(usage.total > 0 && usage.free <= usage.total);
+    let mounts = fs::mounts()?;
+    let (_, mnt) = mounts.iter().find(|(path, _)| path == "/mnt").unwrap();
+    assert_eq!(mnt.total, usage.total);
+    // and the root is writable
+    fs::write("/etc/hostname", "test\n")?;
+    assert_eq!(fs::read_to_string("/etc/hostname")?, "test\n");
+    Ok(())
+}
+
+#[test]
+fn test_initramfs() {
+    let disk = make_disk().expect("failed to load disk image");
+    axtask::init_scheduler(); // call this to use `axsync::Mutex`.
+    axfs::init_initramfs(&make_cpio(), AxDeviceContainer::from_one(disk));
+
+    test_unpacked().expect("test_unpacked() failed");
+    test_disk().expect("test_disk() failed");
+}
@@ -0,0 +1,109 @@
+#![cfg(all(feature = "kv", not(feature = "myfs")))]
+
+use std::net::{IpAddr, Ipv4Addr};
+
+use axdriver::AxDeviceContainer;
+use axdriver_block::ramdisk::RamDisk;
+use axfs::api::{self as fs, OpenOptions};
+use axfs::kv::KvStore;
+use axio::{Error, Result, Write};
+
+const IMG_PATH: &str = "resources/fat16.img";
+
+fn make_disk() -> std::io::Result<RamDisk> {
+    let path = std::env::current_dir()?.join(IMG_PATH);
+    let data = std::fs::read(path)?;
+    Ok(RamDisk::from(&data))
+}
+
+fn test_typed_values() -> Result<()> {
+    let mut store = KvStore::open("/typed.kv")?;
+    store.set("hostname", "arceos")?;
+    store.set("boot.count", &42u32)?;
+    store.set("net.dhcp", &true)?;
+    store.set("net.ip", &IpAddr::V4(Ipv4Addr::new(10, 0, 2, 15)))?;
+    store.set("serial", &[0xde, 0xad, 0xbe, 0xef][..])?;
+
+    assert_eq!(store.get::<String>("hostname")?.as_deref(), Some("arceos"));
+    assert_eq!(store.get::<u32>("boot.count")?, Some(42));
+    assert_eq!(store.get::<bool>("net.dhcp")?, Some(true));
+    assert_eq!(
+        store.get::<IpAddr>("net.ip")?,
+        Some(IpAddr::V4(Ipv4Addr::new(10, 0, 2, 15)))
+    );
+    assert_eq!(store.get_raw("serial"), Some(&[0xde, 0xad, 0xbe, 0xef][..]));
+    assert_eq!(
+        store.get::<u64>("boot.count").err(),
+        Some(Error::InvalidData)
+    );
+    assert_eq!(store.get::<u32>("missing")?, None);
+    assert_eq!(store.set("a=b", "c").err(), Some(Error::InvalidInput));
+    Ok(())
+}
+
+fn test_persistence() -> Result<()> {
+    let mut store = KvStore::open("/persist.kv")?;
+    store.set("a", "1")?;
+    store.set("b", "2")?;
+    store.set("a", "3")?;
+    assert!(store.remove("b")?);
+    assert!(!store.remove("b")?);
+    drop(store);
+
+    let store = KvStore::open("/persist.kv")?;
+    assert_eq!(store.keys().collect::<Vec<_>>(), ["a"]);
+    assert_eq!(store.get::<String>("a")?.as_deref(), Some("3"));
+    Ok(())
+}
+
+fn test_torn_record() -> Result<()> {
+    let mut store = KvStore::open("/torn.kv")?;
+    store.set("a", "1")?;
+    drop(store);
+    let len = fs::metadata("/torn.kv")?.len();
+
+    // an incomplete record written before a crash
+    let mut file = OpenOptions::new().append(true).open("/torn.kv")?;
+    file.write_all(&[0x12, 0x34, 0x56, 0x78, 1, 1, 0])?;
+    drop(file);
+
+    let mut store = KvStore::open("/torn.kv")?;
+    assert_eq!(fs::metadata("/torn.kv")?.len(), len);
+    assert_eq!(store.get::<String>("a")?.as_deref(), Some("1"));
+    store.set("b", "2")?;
+    drop(store);
+
+    let store = KvStore::open("/torn.kv")?;
+    assert_eq!(store.len(), 2);
+    Ok(())
+}
+
+fn test_compaction() -> Result<()> {
+    let mut store = KvStore::open("/compact.kv")?;
+    for i in 0..1000u32 {
+        store.set("counter", &i)?;
+    }
+    store.set("name", "arceos")?;
+    assert!(fs::metadata("/compact.kv")?.len() < 8192);
+    drop(store);
+
+    // an interrupted compaction leaves the old log intact
+    fs::write("/compact.kv.compact", "garbage")?;
+    let store = KvStore::open("/compact.kv")?;
+    assert!(fs::metadata("/compact.kv.compact").is_err());
+    assert_eq!(store.get::<u32>("counter")?, Some(999));
+    assert_eq!(store.get::<String>("name")?.as_deref(), Some("arceos"));
+    Ok(())
+}
+
+#[test]
+fn test_kv() {
+    let disk = make_disk().expect("failed to load disk image");
+    axtask::init_scheduler(); // call this to use `axsync::Mutex`.
+    axfs::init_filesystems(AxDeviceContainer::from_one(disk));
+
+    test_typed_values().expect("test_typed_values() failed");
+    test_persistence().expect("test_persistence() failed");
+    test_torn_record().expect("test_torn_record() failed");
+    test_compaction().expect("test_compaction() failed");
+}
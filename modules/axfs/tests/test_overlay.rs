@@ -0,0 +1,97 @@
+#![cfg(all(feature = "overlay", not(feature = "myfs")))]
+
+use axdriver::AxDeviceContainer;
+use axdriver_block::ramdisk::RamDisk;
+use axfs::api as fs;
+use axio::{Error, Result};
+
+const IMG_PATH: &str = "resources/fat16.img";
+
+fn make_disk() -> std::io::Result<RamDisk> {
+    let path = std::env::current_dir()?.join(IMG_PATH);
+    let data = std::fs::read(path)?;
+    Ok(RamDisk::from(&data))
+}
+
+fn entry_names(dir: &str) -> Vec<String> {
+    let mut names = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect::<Vec<_>>();
+    names.sort();
+    names
+}
+
+fn test_copy_up() -> Result<()> {
+    assert_eq!(fs::read_to_string("/merged/a.txt")?, "lower a");
+    assert_eq!(entry_names("/merged"), ["a.txt", "dir"]);
+
+    // the lower file is copied up when written
+    fs::write("/merged/a.txt", "upper a")?;
+    assert_eq!(fs::read_to_string("/merged/a.txt")?, "upper a");
+    assert_eq!(fs::read_to_string("/tmp/upper/a.txt")?, "upper a");
+    assert_eq!(fs::read_to_string("/lower/a.txt")?, "lower a");
+
+    // a new file is created in the upper directory, with its parents
+    fs::write("/merged/dir/c.txt", "upper c")?;
+    assert!(fs::metadata("/tmp/upper/dir").unwrap().is_dir());
+    assert!(fs::metadata("/lower/dir/c.txt").is_err());
+    assert_eq!(entry_names("/merged/dir"), ["b.txt", "c.txt"]);
+    Ok(())
+}
+
+fn test_whiteouts() -> Result<()> {
+    // a lower file is hidden when removed
+    fs::remove_file("/merged/dir/b.txt")?;
+    assert_eq!(
+        fs::metadata("/merged/dir/b.txt").err(),
+        Some(Error::NotFound)
+    );
+    assert_eq!(entry_names("/merged/dir"), ["c.txt"]);
+    assert_eq!(fs::read_to_string("/lower/dir/b.txt")?, "lower b");
+
+    // and replaced when created again
+    fs::write("/merged/dir/b.txt", "upper b")?;
+    assert_eq!(fs::read_to_string("/merged/dir/b.txt")?, "upper b");
+    assert_eq!(entry_names("/merged/dir"), ["b.txt", "c.txt"]);
+
+    // a directory created where a lower one was removed is empty
+    assert_eq!(
+        fs::remove_dir("/merged/dir").err(),
+        Some(Error::DirectoryNotEmpty)
+    );
+    fs::remove_file("/merged/dir/b.txt")?;
+    fs::remove_file("/merged/dir/c.txt")?;
+    fs::remove_dir("/merged/dir")?;
+    assert_eq!(entry_names("/merged"), ["a.txt"]);
+    fs::create_dir("/merged/dir")?;
+    assert!(entry_names("/merged/dir").is_empty());
+    assert_eq!(entry_names("/lower/dir"), ["b.txt"]);
+    Ok(())
+}
+
+fn test_rename() -> Result<()> {
+    fs::write("/lower/d.txt", "lower d")?;
+    fs::rename("/merged/d.txt", "/merged/dir/e.txt")?;
+    assert_eq!(fs::read_to_string("/merged/dir/e.txt")?, "lower d");
+    assert_eq!(fs::metadata("/merged/d.txt").err(), Some(Error::NotFound));
+    assert_eq!(fs::read_to_string("/lower/d.txt")?, "lower d");
+    Ok(())
+}
+
+#[test]
+fn test_overlay() {
+    let disk = make_disk().expect("failed to load disk image");
+    axtask::init_scheduler(); // call this to use `axsync::Mutex`.
+    axfs::init_filesystems(AxDeviceContainer::from_one(disk));
+
+    fs::create_dir_all("/lower/dir").unwrap();
+    fs::write("/lower/a.txt", "lower a").unwrap();
+    fs::write("/lower/dir/b.txt", "lower b").unwrap();
+    fs::create_dir("/tmp/upper").unwrap();
+    axfs::mount_overlay("/merged", "/tmp/upper", &["/lower"]).unwrap();
+
+    test_copy_up().expect("test_copy_up() failed");
+    test_whiteouts().expect("test_whiteouts() failed");
+    test_rename().expect("test_rename() failed");
+}
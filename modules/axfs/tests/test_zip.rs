@@ -0,0 +1,171 @@
+#![cfg(feature = "zip")]
+
+use axerrno::AxError;
+use axfs::zip::{CompressionMethod, ZipArchive};
+use axio::{Read, Result, Seek, SeekFrom};
+
+/// "hello" compressed with raw deflate.
+const HELLO_DEFLATED: &[u8] = &[0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x07, 0x00];
+const HELLO_CRC: u32 = 0x3610_a686;
+
+struct MemFile {
+    data: Vec<u8>,
+    pos: u64,
+}
+
+impl Read for MemFile {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let start = (self.pos as usize).min(self.data.len());
+        let len = buf.len().min(self.data.len() - start);
+        buf[..len].copy_from_slice(&self.data[start..start + len]);
+        self.pos += len as u64;
+        Ok(len)
+    }
+}
+
+impl Seek for MemFile {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        self.pos = match pos {
+            SeekFrom::Start(pos) => pos,
+            SeekFrom::Current(off) => self.pos.checked_add_signed(off).unwrap(),
+            SeekFrom::End(off) => (self.data.len() as u64).checked_add_signed(off).unwrap(),
+        };
+        Ok(self.pos)
+    }
+}
+
+/// (name, method, data as stored in the archive, uncompressed size, crc)
+type Entry<'a> = (&'a str, u16, &'a [u8], u32, u32);
+
+fn build_zip(entries: &[Entry], comment: &[u8]) -> MemFile {
+    let mut data = Vec::new();
+    let mut central = Vec::new();
+    for &(name, method, content, size, crc) in entries {
+        let offset = data.len() as u32;
+        let mut fields = Vec::new();
+        fields.extend_from_slice(&20u16.to_le_bytes()); // version needed
+        fields.extend_from_slice(&0u16.to_le_bytes()); // flags
+        fields.extend_from_slice(&method.to_le_bytes());
+        fields.extend_from_slice(&[0; 4]); // time and date
+        fields.extend_from_slice(&crc.to_le_bytes());
+        fields.extend_from_slice(&(content.len() as u32).to_le_bytes());
+        fields.extend_from_slice(&size.to_le_bytes());
+        fields.extend_from_slice(&(name.len() as u16).to_le_bytes());
+
+        data.extend_from_slice(&0x0403_4b50u32.to_le_bytes());
+        data.extend_from_slice(&fields);
+        data.extend_from_slice(&0u16.to_le_bytes()); // extra length
+        data.extend_from_slice(name.as_bytes());
+        data.extend_from_slice(content);
+
+        central.extend_from_slice(&0x0201_4b50u32.to_le_bytes());
+        central.extend_from_slice(&20u16.to_le_bytes()); // version made by
+        central.extend_from_slice(&fields);
+        central.extend_from_slice(&[0; 12]); // extra, comment, disk, attributes
+        central.extend_from_slice(&offset.to_le_bytes());
+        central.extend_from_slice(name.as_bytes());
+    }
+    let cd_offset = data.len() as u32;
+    data.extend_from_slice(&central);
+    data.extend_from_slice(&0x0605_4b50u32.to_le_bytes());
+    data.extend_from_slice(&[0; 4]); // disk numbers
+    data.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    data.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    data.extend_from_slice(&(central.len() as u32).to_le_bytes());
+    data.extend_from_slice(&cd_offset.to_le_bytes());
+    data.extend_from_slice(&(comment.len() as u16).to_le_bytes());
+    data.extend_from_slice(comment);
+    MemFile { data, pos: 0 }
+}
+
+fn read_all(archive: &mut ZipArchive<MemFile>, name: &str) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    archive.by_name(name)?.read_to_end(&mut buf)?;
+    Ok(buf)
+}
+
+#[test]
+fn test_entries() -> Result<()> {
+    let zip = build_zip(
+        &[
+            ("dir/", 0, b"", 0, 0),
+            ("dir/hello.txt", 0, b"hello", 5, HELLO_CRC),
+            ("hello.z", 8, HELLO_DEFLATED, 5, HELLO_CRC),
+        ],
+        b"a comment",
+    );
+    let archive = ZipArchive::new(zip)?;
+    assert_eq!(archive.len(), 3);
+
+    let names: Vec<_> = archive.entries().iter().map(|e| e.name()).collect();
+    assert_eq!(names, ["dir/", "dir/hello.txt", "hello.z"]);
+    assert!(archive.entry("dir/").unwrap().is_dir());
+
+    let entry = archive.entry("hello.z").unwrap();
+    assert!(!entry.is_dir());
+    assert_eq!(entry.compression(), CompressionMethod::Deflated);
+    assert_eq!(entry.size(), 5);
+    assert_eq!(entry.compressed_size(), HELLO_DEFLATED.len() as u64);
+    assert!(archive.entry("missing").is_none());
+    Ok(())
+}
+
+#[test]
+fn test_read() -> Result<()> {
+    let zip = build_zip(
+        &[
+            ("stored", 0, b"hello", 5, HELLO_CRC),
+            ("deflated", 8, HELLO_DEFLATED, 5, HELLO_CRC),
+        ],
+        b"",
+    );
+    let mut archive = ZipArchive::new(zip)?;
+    assert_eq!(read_all(&mut archive, "stored")?, b"hello");
+    assert_eq!(read_all(&mut archive, "deflated")?, b"hello");
+    assert_eq!(archive.by_name("missing").err(), Some(AxError::NotFound));
+    Ok(())
+}
+
+#[test]
+fn test_seek() -> Result<()> {
+    let zip = build_zip(
+        &[
+            ("stored", 0, b"hello", 5, HELLO_CRC),
+            ("deflated", 8, HELLO_DEFLATED, 5, HELLO_CRC),
+        ],
+        b"",
+    );
+    let mut archive = ZipArchive::new(zip)?;
+    for name in ["stored", "deflated"] {
+        let mut file = archive.by_name(name)?;
+        let mut buf = [0; 3];
+        assert_eq!(file.seek(SeekFrom::Start(2))?, 2);
+        file.read_exact(&mut buf)?;
+        assert_eq!(&buf, b"llo");
+
+        assert_eq!(file.seek(SeekFrom::End(-4))?, 1);
+        file.read_exact(&mut buf)?;
+        assert_eq!(&buf, b"ell");
+        assert_eq!(file.read(&mut buf)?, 1);
+        assert_eq!(file.read(&mut buf)?, 0);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_corrupted() -> Result<()> {
+    let zip = build_zip(&[("bad", 0, b"hellO", 5, HELLO_CRC)], b"");
+    let mut archive = ZipArchive::new(zip)?;
+    assert_eq!(
+        read_all(&mut archive, "bad").err(),
+        Some(AxError::InvalidData)
+    );
+
+    let zip = build_zip(&[("bad", 1, b"hello", 5, HELLO_CRC)], b"");
+    assert_eq!(ZipArchive::new(zip).err(), Some(AxError::Unsupported));
+
+    let mut zip = build_zip(&[("hello", 0, b"hello", 5, HELLO_CRC)], b"");
+    zip.data.truncate(zip.data.len() - 1);
+    assert_eq!(ZipArchive::new(zip).err(), Some(AxError::InvalidData));
+    Ok(())
+}
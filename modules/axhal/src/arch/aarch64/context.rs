@@ -98,6 +98,11 @@ impl TrapFrame {
         self.usp = sp as _;
     }
 
+    /// Gets the frame pointer.
+    pub const fn fp(&self) -> usize {
+        self.r[29] as _
+    }
+
     /// Gets the return value register.
     pub const fn retval(&self) -> usize {
         self.r[0] as _
@@ -253,6 +258,17 @@ impl FpState {
     }
 }
 
+/// Calls `f(arg)`, and restores the FP/SIMD registers it may change, for the
+/// upcalls run in the middle of the code using them.
+#[cfg(feature = "fp_simd")]
+pub(crate) fn call_preserving_fp(f: extern "C" fn(usize), arg: usize) {
+    let mut saved = FpState::default();
+    let mut scratch = FpState::default();
+    saved.switch_to(&scratch);
+    f(arg);
+    scratch.switch_to(&saved);
+}
+
 /// Saved hardware states of a task.
 ///
 /// The context usually includes:
@@ -11,6 +11,8 @@ use tock_registers::interfaces::{Readable, Writeable};
 
 #[cfg(feature = "uspace")]
 pub use self::context::UspaceContext;
+#[cfg(feature = "fp_simd")]
+pub(crate) use self::context::call_preserving_fp;
 pub use self::context::{FpState, TaskContext, TrapFrame};
 
 /// Allows the current CPU to respond to interrupts.
@@ -120,6 +122,60 @@ pub fn flush_dcache_line(vaddr: VirtAddr) {
     unsafe { asm!("dc ivac, {0:x}; dsb sy; isb", in(reg) vaddr.as_usize()) };
 }
 
+/// Returns the size of the smallest data cache line in bytes (from
+/// `CTR_EL0.DminLine`).
+#[inline]
+fn dcache_line_size() -> usize {
+    let ctr: usize;
+    unsafe { asm!("mrs {}, ctr_el0", out(reg) ctr) };
+    4 << ((ctr >> 16) & 0xf)
+}
+
+/// Applies the data cache maintenance instruction `dc $op` on each line of
+/// `[vaddr, vaddr + size)`, then waits for them to complete.
+macro_rules! dcache_range_op {
+    ($op:literal, $vaddr:expr, $size:expr) => {{
+        let line = dcache_line_size();
+        let start = $vaddr.as_usize() & !(line - 1);
+        for addr in (start..$vaddr.as_usize() + $size).step_by(line) {
+            unsafe { asm!(concat!("dc ", $op, ", {0:x}"), in(reg) addr) };
+        }
+        unsafe { asm!("dsb sy") };
+    }};
+}
+
+/// Writes back the data cache lines of `[vaddr, vaddr + size)` to the memory,
+/// e.g. before a device reads it.
+#[inline]
+pub fn clean_dcache_range(vaddr: VirtAddr, size: usize) {
+    dcache_range_op!("cvac", vaddr, size);
+}
+
+/// Discards the data cache lines of `[vaddr, vaddr + size)` without writing
+/// them back, e.g. after a device writes the memory.
+///
+/// The lines partially in the range are written back first, as the other data
+/// in them may be dirty.
+#[inline]
+pub fn invalidate_dcache_range(vaddr: VirtAddr, size: usize) {
+    let line = dcache_line_size();
+    let (start, end) = (vaddr.as_usize(), vaddr.as_usize() + size);
+    for addr in (start & !(line - 1)..end).step_by(line) {
+        if addr < start || addr + line > end {
+            unsafe { asm!("dc civac, {0:x}", in(reg) addr) };
+        } else {
+            unsafe { asm!("dc ivac, {0:x}", in(reg) addr) };
+        }
+    }
+    unsafe { asm!("dsb sy") };
+}
+
+/// Writes back then discards the data cache lines of `[vaddr, vaddr + size)`.
+#[inline]
+pub fn flush_dcache_range(vaddr: VirtAddr, size: usize) {
+    dcache_range_op!("civac", vaddr, size);
+}
+
 /// Reads the thread pointer of the current CPU.
 ///
 /// It is used to implement TLS (Thread Local Storage).
@@ -140,6 +196,45 @@ pub unsafe fn write_thread_pointer(tpidr_el0: usize) {
     TPIDR_EL0.set(tpidr_el0 as _)
 }
 
+/// Calls `f(arg)` with the stack pointer switched to `stack_top`, and
+/// switches back to the current stack when it returns.
+///
+/// It is used to run signal handlers on an alternate stack.
+///
+/// # Safety
+///
+/// `stack_top` must be the top of a valid stack that is large enough for `f`.
+pub unsafe fn call_on_stack(stack_top: usize, f: unsafe extern "C" fn(usize), arg: usize) {
+    unsafe {
+        asm!(
+            "mov x20, sp",
+            "mov sp, {stack}",
+            "blr {f}",
+            "mov sp, x20",
+            stack = in(reg) stack_top & !0xf,
+            f = in(reg) f,
+            in("x0") arg,
+            out("x20") _,
+            clobber_abi("C"),
+        )
+    }
+}
+
+// The trampoline of the upcalls (see `crate::trap::set_upcall`): it calls
+// `entry(func, arg)`, then raises an exception at `ax_upcall_return` to
+// restore the interrupted context.
+core::arch::global_asm!(
+    ".global ax_upcall_trampoline",
+    "ax_upcall_trampoline:",
+    "mov x9, x0",
+    "mov x0, x1",
+    "mov x1, x2",
+    "blr x9",
+    ".global ax_upcall_return",
+    "ax_upcall_return:",
+    "udf #0",
+);
+
 /// Initializes CPU states on the current CPU.
 ///
 /// On AArch64, it sets the exception vector base address (`VBAR_EL1`) and `TTBR0_EL1`.
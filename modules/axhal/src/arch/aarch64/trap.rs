@@ -5,6 +5,7 @@ use page_table_entry::MappingFlags;
 use tock_registers::interfaces::Readable;
 
 use super::TrapFrame;
+use crate::trap::{ExceptionKind, handle_exception};
 
 global_asm!(
     include_str!("trap.S"),
@@ -47,11 +48,11 @@ fn invalid_exception(tf: &TrapFrame, kind: TrapKind, source: TrapSource) {
 
 #[unsafe(no_mangle)]
 fn handle_irq_exception(tf: &mut TrapFrame, source: TrapSource) {
-    handle_trap!(IRQ, 0);
+    crate::trap::handle_irq(tf, 0);
     crate::trap::post_trap_callback(tf, source.is_from_user());
 }
 
-fn handle_instruction_abort(tf: &TrapFrame, iss: u64, is_user: bool) {
+fn handle_instruction_abort(tf: &mut TrapFrame, iss: u64, is_user: bool) {
     let mut access_flags = MappingFlags::EXECUTE;
     if is_user {
         access_flags |= MappingFlags::USER;
@@ -61,6 +62,7 @@ fn handle_instruction_abort(tf: &TrapFrame, iss: u64, is_user: bool) {
     // Only handle Translation fault and Permission fault
     if !matches!(iss & 0b111100, 0b0100 | 0b1100) // IFSC or DFSC bits
         || !handle_trap!(PAGE_FAULT, vaddr, access_flags, is_user)
+            && !handle_exception(tf, ExceptionKind::PageFault, vaddr.as_usize(), is_user)
     {
         panic!(
             "Unhandled {} Instruction Abort @ {:#x}, fault_vaddr={:#x}, ISS={:#x} ({:?}):\n{:#x?}",
@@ -74,7 +76,7 @@ fn handle_instruction_abort(tf: &TrapFrame, iss: u64, is_user: bool) {
     }
 }
 
-fn handle_data_abort(tf: &TrapFrame, iss: u64, is_user: bool) {
+fn handle_data_abort(tf: &mut TrapFrame, iss: u64, is_user: bool) {
     let wnr = (iss & (1 << 6)) != 0; // WnR: Write not Read
     let cm = (iss & (1 << 8)) != 0; // CM: Cache maintenance
     let mut access_flags = if wnr & !cm {
@@ -87,10 +89,17 @@ fn handle_data_abort(tf: &TrapFrame, iss: u64, is_user: bool) {
     }
     let vaddr = va!(FAR_EL1.get() as usize);
 
-    // Only handle Translation fault and Permission fault
-    if !matches!(iss & 0b111100, 0b0100 | 0b1100) // IFSC or DFSC bits
-        || !handle_trap!(PAGE_FAULT, vaddr, access_flags, is_user)
-    {
+    // Only handle Translation fault, Permission fault and Alignment fault
+    let handled = if matches!(iss & 0b111100, 0b0100 | 0b1100) {
+        // DFSC bits
+        handle_trap!(PAGE_FAULT, vaddr, access_flags, is_user)
+            || handle_exception(tf, ExceptionKind::PageFault, vaddr.as_usize(), is_user)
+    } else if iss & 0b111111 == 0b100001 {
+        handle_exception(tf, ExceptionKind::Misaligned, vaddr.as_usize(), is_user)
+    } else {
+        false
+    };
+    if !handled {
         panic!(
             "Unhandled {} Data Abort @ {:#x}, fault_vaddr={:#x}, ISS=0b{:08b} ({:?}):\n{:#x?}",
             if is_user { "EL0" } else { "EL1" },
@@ -103,6 +112,12 @@ fn handle_data_abort(tf: &TrapFrame, iss: u64, is_user: bool) {
     }
 }
 
+/// Calls the exception handler for a fault at the current instruction.
+fn handle_fault(tf: &mut TrapFrame, kind: ExceptionKind, is_user: bool) -> bool {
+    let elr = tf.elr as usize;
+    handle_exception(tf, kind, elr, is_user)
+}
+
 #[unsafe(no_mangle)]
 fn handle_sync_exception(tf: &mut TrapFrame, source: TrapSource) {
     let esr = ESR_EL1.extract();
@@ -123,6 +138,10 @@ fn handle_sync_exception(tf: &mut TrapFrame, source: TrapSource) {
             debug!("BRK #{:#x} @ {:#x} ", iss, tf.elr);
             tf.elr += 4;
         }
+        Some(ESR_EL1::EC::Value::Unknown)
+            if handle_fault(tf, ExceptionKind::IllegalInstruction, source.is_from_user()) => {}
+        Some(ESR_EL1::EC::Value::PCAlignmentFault | ESR_EL1::EC::Value::SPAlignmentFault)
+            if handle_fault(tf, ExceptionKind::Misaligned, source.is_from_user()) => {}
         _ => {
             panic!(
                 "Unhandled synchronous exception @ {:#x}: ESR={:#x} (EC {:#08b}, ISS {:#x})",
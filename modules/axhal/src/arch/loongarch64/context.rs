@@ -148,6 +148,11 @@ impl TrapFrame {
         self.regs.sp = sp;
     }
 
+    /// Gets the frame pointer.
+    pub const fn fp(&self) -> usize {
+        self.regs.fp
+    }
+
     /// Gets the return value register.
     pub const fn retval(&self) -> usize {
         self.regs.a0
@@ -374,6 +379,16 @@ unsafe extern "C" fn restore_fp_registers(fp_status: &FpStatus) {
     )
 }
 
+/// Calls `f(arg)`, and restores the FP/SIMD registers it may change, for the
+/// upcalls run in the middle of the code using them.
+#[cfg(feature = "fp_simd")]
+pub(crate) fn call_preserving_fp(f: extern "C" fn(usize), arg: usize) {
+    let mut saved = FpStatus::default();
+    unsafe { save_fp_registers(&mut saved) };
+    f(arg);
+    unsafe { restore_fp_registers(&saved) };
+}
+
 #[naked]
 unsafe extern "C" fn context_switch(_current_task: &mut TaskContext, _next_task: &TaskContext) {
     unsafe {
@@ -9,6 +9,8 @@ use loongArch64::register::{crmd, ecfg, eentry, pgdh, pgdl, stlbps, tlbidx, tlbr
 use memory_addr::{PhysAddr, VirtAddr};
 use page_table_multiarch::loongarch64::LA64MetaData;
 
+#[cfg(feature = "fp_simd")]
+pub(crate) use self::context::call_preserving_fp;
 pub use self::context::{TaskContext, TrapFrame};
 
 #[cfg(feature = "uspace")]
@@ -127,6 +129,39 @@ pub fn flush_tlb(vaddr: Option<VirtAddr>) {
     }
 }
 
+/// Flushes the entire instruction cache.
+#[inline]
+pub fn flush_icache_all() {
+    unsafe { asm!("ibar 0") };
+}
+
+/// Writes back the data cache lines of `[vaddr, vaddr + size)` to the memory,
+/// e.g. before a device reads it.
+///
+/// The caches are kept coherent with the DMA by the hardware, so it only
+/// orders the accesses to the memory with the ones to the devices.
+#[inline]
+pub fn clean_dcache_range(_vaddr: VirtAddr, _size: usize) {
+    unsafe { asm!("dbar 0") };
+}
+
+/// Discards the data cache lines of `[vaddr, vaddr + size)` without writing
+/// them back, e.g. after a device writes the memory.
+///
+/// As [`clean_dcache_range`], it only orders the accesses.
+#[inline]
+pub fn invalidate_dcache_range(_vaddr: VirtAddr, _size: usize) {
+    unsafe { asm!("dbar 0") };
+}
+
+/// Writes back then discards the data cache lines of `[vaddr, vaddr + size)`.
+///
+/// As [`clean_dcache_range`], it only orders the accesses.
+#[inline]
+pub fn flush_dcache_range(_vaddr: VirtAddr, _size: usize) {
+    unsafe { asm!("dbar 0") };
+}
+
 /// Writes Exception Entry Base Address Register (`eentry`).
 ///
 /// - ECFG: <https://loongson.github.io/LoongArch-Documentation/LoongArch-Vol1-EN.html#exception-configuration>
@@ -200,6 +235,45 @@ pub unsafe fn write_thread_pointer(tp: usize) {
     unsafe { asm!("move $tp, {}", in(reg) tp) }
 }
 
+/// Calls `f(arg)` with the stack pointer switched to `stack_top`, and
+/// switches back to the current stack when it returns.
+///
+/// It is used to run signal handlers on an alternate stack.
+///
+/// # Safety
+///
+/// `stack_top` must be the top of a valid stack that is large enough for `f`.
+pub unsafe fn call_on_stack(stack_top: usize, f: unsafe extern "C" fn(usize), arg: usize) {
+    unsafe {
+        asm!(
+            "move $s1, $sp",
+            "move $sp, {stack}",
+            "jirl $ra, {f}, 0",
+            "move $sp, $s1",
+            stack = in(reg) stack_top & !0xf,
+            f = in(reg) f,
+            in("$a0") arg,
+            out("$s1") _,
+            clobber_abi("C"),
+        )
+    }
+}
+
+// The trampoline of the upcalls (see `crate::trap::set_upcall`): it calls
+// `entry(func, arg)`, then raises an exception (a reserved instruction) at `ax_upcall_return` to
+// restore the interrupted context.
+core::arch::global_asm!(
+    ".global ax_upcall_trampoline",
+    "ax_upcall_trampoline:",
+    "move $t0, $a0",
+    "move $a0, $a1",
+    "move $a1, $a2",
+    "jirl $ra, $t0, 0",
+    ".global ax_upcall_return",
+    "ax_upcall_return:",
+    ".word 0",
+);
+
 /// Initializes CPU states on the current CPU.
 pub fn cpu_init() {
     #[cfg(feature = "fp_simd")]
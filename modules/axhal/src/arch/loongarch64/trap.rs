@@ -1,4 +1,5 @@
 use super::context::TrapFrame;
+use crate::trap::{ExceptionKind, handle_exception};
 use loongArch64::register::{
     badv,
     estat::{self, Exception, Trap},
@@ -16,12 +17,14 @@ fn handle_breakpoint(era: &mut usize) {
     *era += 4;
 }
 
-fn handle_page_fault(tf: &TrapFrame, mut access_flags: MappingFlags, is_user: bool) {
+fn handle_page_fault(tf: &mut TrapFrame, mut access_flags: MappingFlags, is_user: bool) {
     if is_user {
         access_flags |= MappingFlags::USER;
     }
     let vaddr = va!(badv::read().raw());
-    if !handle_trap!(PAGE_FAULT, vaddr, access_flags, is_user) {
+    if !handle_trap!(PAGE_FAULT, vaddr, access_flags, is_user)
+        && !handle_exception(tf, ExceptionKind::PageFault, vaddr.as_usize(), is_user)
+    {
         panic!(
             "Unhandled {} Page Fault @ {:#x}, fault_vaddr={:#x} ({:?}):\n{:#x?}",
             if is_user { "PLV3" } else { "PLV0" },
@@ -33,6 +36,11 @@ fn handle_page_fault(tf: &TrapFrame, mut access_flags: MappingFlags, is_user: bo
     }
 }
 
+/// Calls the exception handler for a fault reported in `badv`.
+fn handle_fault(tf: &mut TrapFrame, kind: ExceptionKind, is_user: bool) -> bool {
+    handle_exception(tf, kind, badv::read().raw(), is_user)
+}
+
 #[unsafe(no_mangle)]
 fn loongarch64_trap_handler(tf: &mut TrapFrame, from_user: bool) {
     let estat = estat::read();
@@ -61,9 +69,14 @@ fn loongarch64_trap_handler(tf: &mut TrapFrame, from_user: bool) {
             handle_page_fault(tf, MappingFlags::EXECUTE, from_user);
         }
         Trap::Exception(Exception::Breakpoint) => handle_breakpoint(&mut tf.era),
+        Trap::Exception(Exception::InstructionNotExist)
+        | Trap::Exception(Exception::InstructionPrivilegeIllegal)
+            if handle_fault(tf, ExceptionKind::IllegalInstruction, from_user) => {}
+        Trap::Exception(Exception::AddressNotAligned)
+            if handle_fault(tf, ExceptionKind::Misaligned, from_user) => {}
         Trap::Interrupt(_) => {
             let irq_num: usize = estat.is().trailing_zeros() as usize;
-            handle_trap!(IRQ, irq_num);
+            crate::trap::handle_irq(tf, irq_num);
         }
         _ => {
             panic!(
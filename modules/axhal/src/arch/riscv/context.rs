@@ -156,6 +156,11 @@ impl TrapFrame {
         self.regs.sp = sp;
     }
 
+    /// Gets the frame pointer.
+    pub const fn fp(&self) -> usize {
+        self.regs.s0
+    }
+
     /// Gets the return value register.
     pub const fn retval(&self) -> usize {
         self.regs.a0
@@ -463,6 +468,21 @@ unsafe extern "C" fn clear_fp_registers() {
     )
 }
 
+/// Calls `f(arg)`, and restores the FP/SIMD registers it may change, for the
+/// upcalls run in the middle of the code using them.
+#[cfg(feature = "fp_simd")]
+pub(crate) fn call_preserving_fp(f: extern "C" fn(usize), arg: usize) {
+    use riscv::register::sstatus::{self, FS};
+    // `fcsr` is saved after the registers
+    let mut saved = FpStatus::default();
+    unsafe { save_fp_registers(&mut saved.fp) };
+    f(arg);
+    unsafe {
+        restore_fp_registers(&saved.fp);
+        sstatus::set_fs(FS::Dirty);
+    }
+}
+
 #[naked]
 unsafe extern "C" fn context_switch(_current_task: &mut TaskContext, _next_task: &TaskContext) {
     naked_asm!(
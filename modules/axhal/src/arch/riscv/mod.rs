@@ -10,6 +10,8 @@ use riscv::register::{satp, sstatus, stvec};
 
 #[cfg(feature = "uspace")]
 pub use self::context::UspaceContext;
+#[cfg(feature = "fp_simd")]
+pub(crate) use self::context::call_preserving_fp;
 pub use self::context::{GeneralRegisters, TaskContext, TrapFrame};
 
 /// Allows the current CPU to respond to interrupts.
@@ -82,6 +84,39 @@ pub fn flush_tlb(vaddr: Option<VirtAddr>) {
     }
 }
 
+/// Flushes the entire instruction cache.
+#[inline]
+pub fn flush_icache_all() {
+    unsafe { core::arch::asm!("fence.i") };
+}
+
+/// Writes back the data cache lines of `[vaddr, vaddr + size)` to the memory,
+/// e.g. before a device reads it.
+///
+/// The DMA of the supported platforms is coherent with the caches, so it only
+/// orders the accesses to the memory with the ones to the devices.
+#[inline]
+pub fn clean_dcache_range(_vaddr: VirtAddr, _size: usize) {
+    unsafe { core::arch::asm!("fence iorw, iorw") };
+}
+
+/// Discards the data cache lines of `[vaddr, vaddr + size)` without writing
+/// them back, e.g. after a device writes the memory.
+///
+/// As [`clean_dcache_range`], it only orders the accesses.
+#[inline]
+pub fn invalidate_dcache_range(_vaddr: VirtAddr, _size: usize) {
+    unsafe { core::arch::asm!("fence iorw, iorw") };
+}
+
+/// Writes back then discards the data cache lines of `[vaddr, vaddr + size)`.
+///
+/// As [`clean_dcache_range`], it only orders the accesses.
+#[inline]
+pub fn flush_dcache_range(_vaddr: VirtAddr, _size: usize) {
+    unsafe { core::arch::asm!("fence iorw, iorw") };
+}
+
 /// Writes Supervisor Trap Vector Base Address Register (`stvec`).
 #[inline]
 pub fn set_trap_vector_base(stvec: usize) {
@@ -110,6 +145,45 @@ pub unsafe fn write_thread_pointer(tp: usize) {
     core::arch::asm!("mv tp, {}", in(reg) tp)
 }
 
+/// Calls `f(arg)` with the stack pointer switched to `stack_top`, and
+/// switches back to the current stack when it returns.
+///
+/// It is used to run signal handlers on an alternate stack.
+///
+/// # Safety
+///
+/// `stack_top` must be the top of a valid stack that is large enough for `f`.
+pub unsafe fn call_on_stack(stack_top: usize, f: unsafe extern "C" fn(usize), arg: usize) {
+    unsafe {
+        core::arch::asm!(
+            "mv s2, sp",
+            "mv sp, {stack}",
+            "jalr {f}",
+            "mv sp, s2",
+            stack = in(reg) stack_top & !0xf,
+            f = in(reg) f,
+            in("a0") arg,
+            out("s2") _,
+            clobber_abi("C"),
+        )
+    }
+}
+
+// The trampoline of the upcalls (see `crate::trap::set_upcall`): it calls
+// `entry(func, arg)`, then raises an exception at `ax_upcall_return` to
+// restore the interrupted context.
+core::arch::global_asm!(
+    ".global ax_upcall_trampoline",
+    "ax_upcall_trampoline:",
+    "mv t0, a0",
+    "mv a0, a1",
+    "mv a1, a2",
+    "jalr t0",
+    ".global ax_upcall_return",
+    "ax_upcall_return:",
+    "unimp",
+);
+
 /// Initializes CPU states on the current CPU.
 ///
 /// On RISC-V, it sets the trap vector base address.
@@ -5,6 +5,7 @@ use riscv::interrupt::supervisor::{Exception as E, Interrupt as I};
 use riscv::register::{scause, stval};
 
 use super::TrapFrame;
+use crate::trap::{ExceptionKind, handle_exception};
 
 core::arch::global_asm!(
     include_asm_macros!(),
@@ -18,7 +19,7 @@ fn handle_breakpoint(sepc: &mut usize) {
 }
 
 fn handle_page_fault(
-    tf: &TrapFrame,
+    tf: &mut TrapFrame,
     vaddr: VirtAddr,
     mut access_flags: MappingFlags,
     is_user: bool,
@@ -26,7 +27,9 @@ fn handle_page_fault(
     if is_user {
         access_flags |= MappingFlags::USER;
     }
-    if !handle_trap!(PAGE_FAULT, vaddr, access_flags, is_user) {
+    if !handle_trap!(PAGE_FAULT, vaddr, access_flags, is_user)
+        && !handle_exception(tf, ExceptionKind::PageFault, vaddr.as_usize(), is_user)
+    {
         panic!(
             "Unhandled {} Page Fault @ {:#x}, fault_vaddr={:#x} ({:?}):\n{:#x?}",
             if is_user { "User" } else { "Supervisor" },
@@ -38,6 +41,15 @@ fn handle_page_fault(
     }
 }
 
+fn handle_fault(tf: &mut TrapFrame, kind: ExceptionKind, fault_addr: usize, is_user: bool) {
+    if !handle_exception(tf, kind, fault_addr, is_user) {
+        panic!(
+            "Unhandled {:?} @ {:#x}, fault_addr={:#x}:\n{:#x?}",
+            kind, tf.sepc, fault_addr, tf
+        );
+    }
+}
+
 #[unsafe(no_mangle)]
 fn riscv_trap_handler(tf: &mut TrapFrame, from_user: bool) {
     let scause = scause::read();
@@ -65,7 +77,17 @@ fn riscv_trap_handler(tf: &mut TrapFrame, from_user: bool) {
             }
             Trap::Exception(E::Breakpoint) => handle_breakpoint(&mut tf.sepc),
             Trap::Interrupt(_) => {
-                handle_trap!(IRQ, scause.bits());
+                crate::trap::handle_irq(tf, scause.bits());
+            }
+            Trap::Exception(E::IllegalInstruction) => {
+                let sepc = tf.sepc;
+                handle_fault(tf, ExceptionKind::IllegalInstruction, sepc, from_user)
+            }
+            Trap::Exception(E::LoadMisaligned | E::StoreMisaligned) => {
+                handle_fault(tf, ExceptionKind::Misaligned, vaddr.as_usize(), from_user)
+            }
+            Trap::Exception(E::LoadFault | E::StoreFault) => {
+                handle_fault(tf, ExceptionKind::PageFault, vaddr.as_usize(), from_user)
             }
             _ => {
                 panic!("Unhandled trap {:?} @ {:#x}:\n{:#x?}", cause, tf.sepc, tf);
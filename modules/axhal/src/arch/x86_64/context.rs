@@ -123,6 +123,11 @@ impl TrapFrame {
         self.rsp = rsp as _;
     }
 
+    /// Gets the frame pointer.
+    pub const fn fp(&self) -> usize {
+        self.rbp as _
+    }
+
     /// Gets the return value register.
     pub const fn retval(&self) -> usize {
         self.rax as _
@@ -316,6 +321,16 @@ impl ExtendedState {
     }
 }
 
+/// Calls `f(arg)`, and restores the FP/SIMD registers it may change, for the
+/// upcalls run in the middle of the code using them.
+#[cfg(feature = "fp_simd")]
+pub(crate) fn call_preserving_fp(f: extern "C" fn(usize), arg: usize) {
+    let mut state = ExtendedState::default();
+    state.save();
+    f(arg);
+    state.restore();
+}
+
 impl fmt::Debug for ExtendedState {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("ExtendedState")
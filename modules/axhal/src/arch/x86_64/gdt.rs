@@ -14,6 +14,20 @@ static TSS: TaskStateSegment = TaskStateSegment::new();
 #[percpu::def_percpu]
 static GDT: LazyInit<GdtStruct> = LazyInit::new();
 
+/// The index of the stack of the double faults in the interrupt stack table.
+pub(super) const DOUBLE_FAULT_IST_INDEX: u16 = 0;
+
+const DOUBLE_FAULT_STACK_SIZE: usize = 0x4000;
+
+/// The stack of the double faults, e.g. caused by a page fault when the
+/// kernel stack has overflowed into its guard area, where nothing can be
+/// pushed anymore.
+#[repr(align(16))]
+struct DoubleFaultStack([u8; DOUBLE_FAULT_STACK_SIZE]);
+
+#[percpu::def_percpu]
+static DOUBLE_FAULT_STACK: DoubleFaultStack = DoubleFaultStack([0; DOUBLE_FAULT_STACK_SIZE]);
+
 /// A wrapper of the Global Descriptor Table (GDT) with maximum 16 entries.
 #[repr(align(16))]
 pub struct GdtStruct {
@@ -101,8 +115,13 @@ impl fmt::Debug for GdtStruct {
 
 /// Initializes the per-CPU TSS and GDT structures and loads them into the
 /// current CPU.
+///
+/// The double faults are handled on a stack of their own.
 pub fn init_gdt() {
     unsafe {
+        let stack_top = DOUBLE_FAULT_STACK.current_ref_raw().0.as_ptr_range().end;
+        TSS.current_ref_mut_raw().interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] =
+            VirtAddr::new(stack_top as u64);
         let gdt = GDT.current_ref_raw();
         gdt.init_once(GdtStruct::new(TSS.current_ref_raw()));
         gdt.load();
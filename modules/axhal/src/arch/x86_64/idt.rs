@@ -1,10 +1,13 @@
 use core::fmt;
 
 use lazyinit::LazyInit;
+use x86::irq::DOUBLE_FAULT_VECTOR;
 use x86_64::addr::VirtAddr;
 use x86_64::structures::DescriptorTablePointer;
 use x86_64::structures::idt::{Entry, HandlerFunc, InterruptDescriptorTable};
 
+use super::gdt::DOUBLE_FAULT_IST_INDEX;
+
 const NUM_INT: usize = 256;
 
 static IDT: LazyInit<IdtStruct> = LazyInit::new();
@@ -41,6 +44,10 @@ impl IdtStruct {
                 // enable user space breakpoints and legacy int 0x80 syscall
                 opt.set_privilege_level(x86_64::PrivilegeLevel::Ring3);
             }
+            if i == DOUBLE_FAULT_VECTOR as usize {
+                // the kernel stack may have overflowed
+                unsafe { opt.set_stack_index(DOUBLE_FAULT_IST_INDEX) };
+            }
         }
         idt
     }
@@ -17,6 +17,8 @@ use memory_addr::{MemoryAddr, PhysAddr, VirtAddr};
 use x86::{controlregs, msr, tlb};
 use x86_64::instructions::interrupts;
 
+#[cfg(feature = "fp_simd")]
+pub(crate) use self::context::call_preserving_fp;
 pub use self::context::{ExtendedState, FxsaveArea, TaskContext, TrapFrame};
 pub use self::gdt::{GdtStruct, init_gdt, tss_get_rsp0, tss_set_rsp0};
 pub use self::idt::{IdtStruct, init_idt};
@@ -105,6 +107,40 @@ pub fn flush_tlb(vaddr: Option<VirtAddr>) {
     }
 }
 
+/// Flushes the entire instruction cache.
+///
+/// It's a no-op on x86_64, as the instruction cache is coherent with the data
+/// cache.
+#[inline]
+pub fn flush_icache_all() {}
+
+/// Writes back the data cache lines of `[vaddr, vaddr + size)` to the memory,
+/// e.g. before a device reads it.
+///
+/// The caches are coherent with the DMA on x86_64, so it only orders the
+/// accesses to the memory with the ones to the devices.
+#[inline]
+pub fn clean_dcache_range(_vaddr: VirtAddr, _size: usize) {
+    unsafe { asm!("mfence") };
+}
+
+/// Discards the data cache lines of `[vaddr, vaddr + size)` without writing
+/// them back, e.g. after a device writes the memory.
+///
+/// As [`clean_dcache_range`], it only orders the accesses.
+#[inline]
+pub fn invalidate_dcache_range(_vaddr: VirtAddr, _size: usize) {
+    unsafe { asm!("mfence") };
+}
+
+/// Writes back then discards the data cache lines of `[vaddr, vaddr + size)`.
+///
+/// As [`clean_dcache_range`], it only orders the accesses.
+#[inline]
+pub fn flush_dcache_range(_vaddr: VirtAddr, _size: usize) {
+    unsafe { asm!("mfence") };
+}
+
 /// Reads the thread pointer of the current CPU.
 ///
 /// It is used to implement TLS (Thread Local Storage).
@@ -125,6 +161,46 @@ pub unsafe fn write_thread_pointer(fs_base: usize) {
     unsafe { msr::wrmsr(msr::IA32_FS_BASE, fs_base as u64) }
 }
 
+/// Calls `f(arg)` with the stack pointer switched to `stack_top`, and
+/// switches back to the current stack when it returns.
+///
+/// It is used to run signal handlers on an alternate stack.
+///
+/// # Safety
+///
+/// `stack_top` must be the top of a valid stack that is large enough for `f`.
+pub unsafe fn call_on_stack(stack_top: usize, f: unsafe extern "C" fn(usize), arg: usize) {
+    unsafe {
+        asm!(
+            "mov r12, rsp",
+            "mov rsp, {stack}",
+            "call {f}",
+            "mov rsp, r12",
+            stack = in(reg) stack_top & !0xf,
+            f = in(reg) f,
+            in("rdi") arg,
+            out("r12") _,
+            clobber_abi("C"),
+        )
+    }
+}
+
+// The trampoline of the upcalls (see `crate::trap::set_upcall`): it calls
+// `entry(func, arg)` with the stack aligned, then raises an exception at `ax_upcall_return` to
+// restore the interrupted context.
+core::arch::global_asm!(
+    ".global ax_upcall_trampoline",
+    "ax_upcall_trampoline:",
+    "and rsp, -16",
+    "mov rax, rdi",
+    "mov rdi, rsi",
+    "mov rsi, rdx",
+    "call rax",
+    ".global ax_upcall_return",
+    "ax_upcall_return:",
+    "ud2",
+);
+
 /// Initializes CPU states on the current CPU.
 ///
 /// In detail, it initializes the GDT, IDT on x86_64 platforms. If the `uspace`
@@ -3,6 +3,7 @@ use x86::{controlregs::cr2, irq::*};
 use x86_64::structures::idt::PageFaultErrorCode;
 
 use super::context::TrapFrame;
+use crate::trap::{ExceptionKind, handle_exception};
 
 core::arch::global_asm!(include_str!("trap.S"));
 
@@ -12,11 +13,14 @@ const LEGACY_SYSCALL_VECTOR: u8 = 0x80;
 const IRQ_VECTOR_START: u8 = 0x20;
 const IRQ_VECTOR_END: u8 = 0xff;
 
-fn handle_page_fault(tf: &TrapFrame) {
+fn handle_page_fault(tf: &mut TrapFrame) {
     let access_flags = err_code_to_flags(tf.error_code)
         .unwrap_or_else(|e| panic!("Invalid #PF error code: {:#x}", e));
     let vaddr = va!(unsafe { cr2() });
-    if !handle_trap!(PAGE_FAULT, vaddr, access_flags, tf.is_user()) {
+    let is_user = tf.is_user();
+    if !handle_trap!(PAGE_FAULT, vaddr, access_flags, is_user)
+        && !handle_exception(tf, ExceptionKind::PageFault, vaddr.as_usize(), is_user)
+    {
         panic!(
             "Unhandled {} #PF @ {:#x}, fault_vaddr={:#x}, error_code={:#x} ({:?}):\n{:#x?}",
             if tf.is_user() { "user" } else { "kernel" },
@@ -29,16 +33,42 @@ fn handle_page_fault(tf: &TrapFrame) {
     }
 }
 
+fn handle_fault(tf: &mut TrapFrame, kind: ExceptionKind) {
+    let (rip, is_user) = (tf.rip as usize, tf.is_user());
+    if !handle_exception(tf, kind, rip, is_user) {
+        panic!(
+            "Unhandled exception {} ({}, error_code={:#x}) @ {:#x}:\n{:#x?}",
+            tf.vector,
+            vec_to_str(tf.vector),
+            tf.error_code,
+            tf.rip,
+            tf
+        );
+    }
+}
+
 #[unsafe(no_mangle)]
 fn x86_trap_handler(tf: &mut TrapFrame) {
     #[cfg(feature = "uspace")]
     super::tls::switch_to_kernel_fs_base(tf);
-    if !matches!(tf.vector as u8, IRQ_VECTOR_START..=IRQ_VECTOR_END) {
+    if !matches!(
+        tf.vector as u8,
+        IRQ_VECTOR_START..=IRQ_VECTOR_END | DOUBLE_FAULT_VECTOR
+    ) {
         unmask_interrupts_for_exception(tf);
     }
     match tf.vector as u8 {
         PAGE_FAULT_VECTOR => handle_page_fault(tf),
         BREAKPOINT_VECTOR => debug!("#BP @ {:#x} ", tf.rip),
+        DIVIDE_ERROR_VECTOR => handle_fault(tf, ExceptionKind::DivideError),
+        INVALID_OPCODE_VECTOR => handle_fault(tf, ExceptionKind::IllegalInstruction),
+        ALIGNMENT_CHECK_VECTOR => handle_fault(tf, ExceptionKind::Misaligned),
+        DOUBLE_FAULT_VECTOR => {
+            panic!(
+                "#DF @ {:#x}, rsp={:#x}, likely a kernel stack overflow:\n{:#x?}",
+                tf.rip, tf.rsp, tf
+            );
+        }
         GENERAL_PROTECTION_FAULT_VECTOR => {
             panic!(
                 "#GP @ {:#x}, error_code={:#x}:\n{:#x?}",
@@ -48,7 +78,7 @@ fn x86_trap_handler(tf: &mut TrapFrame) {
         #[cfg(feature = "uspace")]
         LEGACY_SYSCALL_VECTOR => super::syscall::handle_syscall(tf),
         IRQ_VECTOR_START..=IRQ_VECTOR_END => {
-            handle_trap!(IRQ, tf.vector as _);
+            crate::trap::handle_irq(tf, tf.vector as _);
         }
         _ => {
             panic!(
@@ -0,0 +1,51 @@
+//! Console input and output.
+//!
+//! The console of the platform (usually a UART) is used, unless another
+//! device (e.g. a VirtIO console) is made the primary console with
+//! [`set_primary`]. The platform console still gets the output the primary
+//! console can't take, e.g. on a panic while it's in use.
+
+use lazyinit::LazyInit;
+
+pub use super::platform::console::*;
+
+/// A console that can replace the one of the platform, see [`set_primary`].
+pub trait PrimaryConsole: Send + Sync {
+    /// Writes `bytes` to the console.
+    ///
+    /// Returns `false` if the console is in use, then the bytes are written
+    /// to the platform console instead.
+    fn write_bytes(&self, bytes: &[u8]) -> bool;
+
+    /// Reads the received bytes into `bytes`, and returns how many were read.
+    fn read_bytes(&self, bytes: &mut [u8]) -> usize;
+}
+
+static PRIMARY: LazyInit<&'static dyn PrimaryConsole> = LazyInit::new();
+
+/// Makes `console` the primary console, used by [`write_bytes`] and
+/// [`read_bytes`] from now on.
+///
+/// It can only be set once.
+pub fn set_primary(console: &'static dyn PrimaryConsole) {
+    PRIMARY.init_once(console);
+}
+
+/// Writes bytes to the console from input u8 slice.
+pub fn write_bytes(bytes: &[u8]) {
+    if let Some(console) = PRIMARY.get() {
+        if console.write_bytes(bytes) {
+            return;
+        }
+    }
+    super::platform::console::write_bytes(bytes);
+}
+
+/// Reads bytes from the console into the given mutable slice.
+/// Returns the number of bytes read.
+pub fn read_bytes(bytes: &mut [u8]) -> usize {
+    match PRIMARY.get() {
+        Some(console) => console.read_bytes(bytes),
+        None => super::platform::console::read_bytes(bytes),
+    }
+}
@@ -0,0 +1,290 @@
+//! Memory shared with the devices by DMA (direct memory access).
+//!
+//! The devices access the memory by bus addresses, which are the physical
+//! addresses plus [`axconfig::plat::PHYS_BUS_OFFSET`]. A buffer is either:
+//!
+//! - coherent ([`alloc_coherent`]), shared with the device during its whole
+//!   life, e.g. a descriptor ring. Its cache lines are written back and
+//!   discarded once allocated, then the accesses need no cache maintenance,
+//!   as the DMA of the supported platforms is coherent with the caches;
+//! - or streaming ([`alloc_streaming`]), owned either by the CPU or by the
+//!   device in turn in a [`DmaDirection`], e.g. a packet. The caches are
+//!   maintained when the ownership is given to the device
+//!   ([`DmaBuffer::sync_for_device`]) and back to the CPU
+//!   ([`DmaBuffer::sync_for_cpu`]).
+//!
+//! The memory not allocated here, e.g. given by the users, is synchronized
+//! with [`sync_for_device`] and [`sync_for_cpu`].
+//!
+//! With the `iommu` feature, the buffers are mapped in the kernel domain of
+//! the IOMMU while they're allocated, but the other memory must be mapped
+//! while it's shared ([`crate::iommu::map_dma`]).
+
+use core::ptr::NonNull;
+
+use axalloc::{CmaPolicy, cma_alloc_pages, cma_dealloc_pages, global_allocator};
+use memory_addr::{PhysAddr, VirtAddr, align_up_4k};
+
+use crate::arch::{clean_dcache_range, flush_dcache_range, invalidate_dcache_range};
+use crate::mem::{PAGE_SIZE_4K, phys_to_virt, virt_to_phys};
+
+/// Buffers of at least this number of pages are allocated from the CMA region.
+const CMA_MIN_PAGES: usize = 16;
+
+/// A bus memory address.
+///
+/// It's a wrapper type around an [`u64`].
+#[repr(transparent)]
+#[derive(Copy, Clone, Default, Ord, PartialOrd, Eq, PartialEq)]
+pub struct BusAddr(u64);
+
+impl BusAddr {
+    /// Converts an [`u64`] to a bus address.
+    pub const fn new(addr: u64) -> Self {
+        Self(addr)
+    }
+
+    /// Converts the address to an [`u64`].
+    pub const fn as_u64(self) -> u64 {
+        self.0
+    }
+}
+
+impl From<u64> for BusAddr {
+    fn from(value: u64) -> Self {
+        Self::new(value)
+    }
+}
+
+impl core::fmt::Debug for BusAddr {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_tuple("BusAddr")
+            .field(&format_args!("{:#X}", self.0))
+            .finish()
+    }
+}
+
+/// Converts a physical address to a bus address.
+///
+/// It assumes that there is a linear mapping with the offset
+/// [`axconfig::plat::PHYS_BUS_OFFSET`], that maps all the physical memory
+/// to the bus at the address plus the offset. So we have
+/// `baddr = paddr + PHYS_BUS_OFFSET`.
+#[inline]
+pub const fn phys_to_bus(paddr: PhysAddr) -> BusAddr {
+    BusAddr::new((paddr.as_usize() + axconfig::plat::PHYS_BUS_OFFSET) as u64)
+}
+
+/// Converts a bus address to a physical address, the reverse of
+/// [`phys_to_bus`].
+#[inline]
+pub const fn bus_to_phys(baddr: BusAddr) -> PhysAddr {
+    PhysAddr::from_usize(baddr.as_u64() as usize - axconfig::plat::PHYS_BUS_OFFSET)
+}
+
+/// Converts a virtual address in the linear mapping to a bus address.
+#[inline]
+pub const fn virt_to_bus(vaddr: VirtAddr) -> BusAddr {
+    phys_to_bus(virt_to_phys(vaddr))
+}
+
+/// Returns the address in the linear mapping of the memory at the bus
+/// address `baddr`, e.g. given by a device.
+#[inline]
+pub const fn bus_to_virt(baddr: BusAddr) -> VirtAddr {
+    phys_to_virt(bus_to_phys(baddr))
+}
+
+/// The direction of the data of a streaming DMA buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DmaDirection {
+    /// Written by the CPU, read by the device.
+    ToDevice,
+    /// Written by the device, read by the CPU.
+    FromDevice,
+    /// Written and read by both.
+    Bidirectional,
+}
+
+/// Gives the memory in `[vaddr, vaddr + size)` to the device, after the CPU
+/// has accessed it.
+///
+/// The data written by the CPU is written back for the device to read, and
+/// the cache lines are discarded if the device writes, so that none of them is
+/// written back over its data later.
+pub fn sync_for_device(vaddr: VirtAddr, size: usize, dir: DmaDirection) {
+    match dir {
+        DmaDirection::ToDevice => clean_dcache_range(vaddr, size),
+        DmaDirection::FromDevice => invalidate_dcache_range(vaddr, size),
+        DmaDirection::Bidirectional => flush_dcache_range(vaddr, size),
+    }
+}
+
+/// Gives the memory in `[vaddr, vaddr + size)` back to the CPU, after the
+/// device has accessed it.
+///
+/// The cache lines are discarded if the device writes, as the CPU may have
+/// loaded them speculatively in the meantime.
+pub fn sync_for_cpu(vaddr: VirtAddr, size: usize, dir: DmaDirection) {
+    match dir {
+        DmaDirection::ToDevice => {}
+        DmaDirection::FromDevice | DmaDirection::Bidirectional => {
+            invalidate_dcache_range(vaddr, size)
+        }
+    }
+}
+
+/// Physically contiguous pages shared with a device, given back when it's
+/// dropped.
+#[derive(Debug)]
+pub struct DmaBuffer {
+    vaddr: VirtAddr,
+    size: usize,
+    /// `None` if it's coherent.
+    dir: Option<DmaDirection>,
+}
+
+impl DmaBuffer {
+    fn alloc(size: usize, dir: Option<DmaDirection>) -> Option<Self> {
+        let num_pages = align_up_4k(size.max(1)) / PAGE_SIZE_4K;
+        // large buffers, e.g. framebuffers, from the CMA region
+        let vaddr = if num_pages >= CMA_MIN_PAGES {
+            cma_alloc_pages(num_pages, PAGE_SIZE_4K, CmaPolicy::Fallback)
+        } else {
+            global_allocator().alloc_pages(num_pages, PAGE_SIZE_4K)
+        }
+        .ok()?;
+        #[cfg(feature = "iommu")]
+        if crate::iommu::map_dma(virt_to_phys(vaddr.into()), num_pages * PAGE_SIZE_4K).is_err() {
+            cma_dealloc_pages(vaddr, num_pages);
+            return None;
+        }
+        let buf = Self {
+            vaddr: VirtAddr::from(vaddr),
+            size,
+            dir,
+        };
+        // none of the stale cache lines is written back over the device's data
+        unsafe { core::ptr::write_bytes(buf.vaddr.as_mut_ptr(), 0, buf.alloc_size()) };
+        flush_dcache_range(buf.vaddr, buf.alloc_size());
+        Some(buf)
+    }
+
+    /// Rebuilds a buffer from its address in the linear mapping, returned by
+    /// [`DmaBuffer::into_raw`].
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be returned by [`DmaBuffer::into_raw`] on a buffer of `size`
+    /// bytes in the direction `dir` (`None` if it's coherent), and be rebuilt
+    /// only once.
+    pub unsafe fn from_raw(ptr: NonNull<u8>, size: usize, dir: Option<DmaDirection>) -> Self {
+        Self {
+            vaddr: VirtAddr::from(ptr.as_ptr() as usize),
+            size,
+            dir,
+        }
+    }
+
+    /// Gives up the ownership of the buffer, returning its address in the
+    /// linear mapping, to be given back with [`DmaBuffer::from_raw`].
+    pub fn into_raw(self) -> NonNull<u8> {
+        let ptr = self.cpu_addr();
+        core::mem::forget(self);
+        ptr
+    }
+
+    /// The size of the pages of the buffer.
+    fn alloc_size(&self) -> usize {
+        align_up_4k(self.size.max(1))
+    }
+
+    /// Returns the address the CPU accesses the buffer by, in the linear
+    /// mapping.
+    pub fn cpu_addr(&self) -> NonNull<u8> {
+        NonNull::new(self.vaddr.as_mut_ptr()).unwrap()
+    }
+
+    /// Returns the physical address of the buffer.
+    pub fn phys_addr(&self) -> PhysAddr {
+        virt_to_phys(self.vaddr)
+    }
+
+    /// Returns the address the device accesses the buffer by.
+    pub fn bus_addr(&self) -> BusAddr {
+        virt_to_bus(self.vaddr)
+    }
+
+    /// Returns the size of the buffer in bytes.
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    /// Whether the size of the buffer is 0.
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    /// Returns the direction of the data of a streaming buffer, or `None` if
+    /// it's coherent.
+    pub fn direction(&self) -> Option<DmaDirection> {
+        self.dir
+    }
+
+    /// Forms a slice of the buffer, to be read by the CPU.
+    ///
+    /// The data written by the device of a streaming buffer is read only
+    /// after [`DmaBuffer::sync_for_cpu`].
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self.vaddr.as_ptr(), self.size) }
+    }
+
+    /// Forms a mutable slice of the buffer, to be written by the CPU.
+    ///
+    /// The data written by the CPU to a streaming buffer is read by the device
+    /// only after [`DmaBuffer::sync_for_device`].
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        unsafe { core::slice::from_raw_parts_mut(self.vaddr.as_mut_ptr(), self.size) }
+    }
+
+    /// Gives a streaming buffer to the device, see [`sync_for_device`].
+    ///
+    /// It does nothing on a coherent buffer.
+    pub fn sync_for_device(&self) {
+        if let Some(dir) = self.dir {
+            sync_for_device(self.vaddr, self.alloc_size(), dir);
+        }
+    }
+
+    /// Gives a streaming buffer back to the CPU, see [`sync_for_cpu`].
+    ///
+    /// It does nothing on a coherent buffer.
+    pub fn sync_for_cpu(&self) {
+        if let Some(dir) = self.dir {
+            sync_for_cpu(self.vaddr, self.alloc_size(), dir);
+        }
+    }
+}
+
+impl Drop for DmaBuffer {
+    fn drop(&mut self) {
+        #[cfg(feature = "iommu")]
+        crate::iommu::unmap_dma(self.phys_addr(), self.alloc_size());
+        // whether they are in the CMA region or not
+        cma_dealloc_pages(self.vaddr.as_usize(), self.alloc_size() / PAGE_SIZE_4K);
+    }
+}
+
+/// Allocates a coherent DMA buffer of `size` bytes, filled with zero, or
+/// returns `None` if there's no memory.
+pub fn alloc_coherent(size: usize) -> Option<DmaBuffer> {
+    DmaBuffer::alloc(size, None)
+}
+
+/// Allocates a streaming DMA buffer of `size` bytes in the direction `dir`,
+/// filled with zero, or returns `None` if there's no memory.
+///
+/// It's owned by the CPU until [`DmaBuffer::sync_for_device`].
+pub fn alloc_streaming(size: usize, dir: DmaDirection) -> Option<DmaBuffer> {
+    DmaBuffer::alloc(size, Some(dir))
+}
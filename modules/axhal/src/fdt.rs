@@ -0,0 +1,244 @@
+//! A minimal reader of the flattened device tree (FDT), only for what's
+//! needed before the memory allocator is initialized: the memory reservation
+//! block, the `/reserved-memory` node and the initial RAM disk in `/chosen`.
+
+const FDT_MAGIC: u32 = 0xd00d_feed;
+
+const FDT_BEGIN_NODE: u32 = 1;
+const FDT_END_NODE: u32 = 2;
+const FDT_PROP: u32 = 3;
+const FDT_NOP: u32 = 4;
+const FDT_END: u32 = 9;
+
+/// A child of the `/reserved-memory` node.
+#[derive(Debug, Default)]
+pub(crate) struct ReservedNode {
+    /// The first entry of `reg`, for a static region.
+    pub reg: Option<(usize, usize)>,
+    /// `size`, for a dynamically allocated region.
+    pub size: Option<usize>,
+    /// `alignment` of a dynamically allocated region.
+    pub alignment: Option<usize>,
+    /// `compatible` contains `shared-dma-pool`.
+    pub shared_dma_pool: bool,
+    /// `reusable`: the memory may be used by the OS, i.e. a CMA region.
+    pub reusable: bool,
+    /// `linux,cma-default`: the default CMA region.
+    pub cma_default: bool,
+}
+
+impl ReservedNode {
+    /// Returns `true` if it's a contiguous memory allocator (CMA) region.
+    pub fn is_cma(&self) -> bool {
+        self.shared_dma_pool && (self.reusable || self.cma_default)
+    }
+}
+
+/// A flattened device tree blob.
+pub(crate) struct Fdt {
+    data: &'static [u8],
+    off_struct: usize,
+    off_strings: usize,
+    off_mem_rsvmap: usize,
+}
+
+impl Fdt {
+    /// Parses the header of the blob at `ptr`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must point to a valid device tree blob, or to readable memory at
+    /// least as large as the header.
+    pub unsafe fn from_ptr(ptr: *const u8) -> Option<Self> {
+        let header = unsafe { core::slice::from_raw_parts(ptr, 40) };
+        let be32 = |off: usize| u32::from_be_bytes(header[off..off + 4].try_into().unwrap());
+        if be32(0) != FDT_MAGIC {
+            return None;
+        }
+        let total_size = be32(4) as usize;
+        let fdt = Self {
+            data: unsafe { core::slice::from_raw_parts(ptr, total_size) },
+            off_struct: be32(8) as usize,
+            off_strings: be32(12) as usize,
+            off_mem_rsvmap: be32(16) as usize,
+        };
+        Some(fdt)
+    }
+
+    /// Returns the size of the blob in bytes.
+    pub fn total_size(&self) -> usize {
+        self.data.len()
+    }
+
+    fn be32(&self, off: usize) -> Option<u32> {
+        let bytes = self.data.get(off..off + 4)?;
+        Some(u32::from_be_bytes(bytes.try_into().unwrap()))
+    }
+
+    fn be64(&self, off: usize) -> Option<u64> {
+        let bytes = self.data.get(off..off + 8)?;
+        Some(u64::from_be_bytes(bytes.try_into().unwrap()))
+    }
+
+    /// Returns the NUL-terminated string at `off`.
+    fn str_at(&self, off: usize) -> Option<&'static [u8]> {
+        let bytes = self.data.get(off..)?;
+        let len = bytes.iter().position(|&b| b == 0)?;
+        Some(&bytes[..len])
+    }
+
+    /// Returns the entries `(address, size)` of the memory reservation block
+    /// (`/memreserve/`).
+    pub fn mem_reservations(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        (0..)
+            .map_while(move |i| {
+                let off = self.off_mem_rsvmap + i * 16;
+                Some((self.be64(off)?, self.be64(off + 8)?))
+            })
+            .take_while(|&(addr, size)| addr != 0 || size != 0)
+            .map(|(addr, size)| (addr as usize, size as usize))
+    }
+
+    /// Calls `f` on each child of the `/reserved-memory` node.
+    pub fn reserved_memory(&self, mut f: impl FnMut(&ReservedNode)) {
+        let mut off = self.off_struct;
+        let mut depth = 0;
+        let mut in_reserved = false;
+        // `#address-cells` and `#size-cells` of `/reserved-memory`
+        let mut cells = (2, 1);
+        let mut node = ReservedNode::default();
+        loop {
+            let Some(token) = self.be32(off) else {
+                return;
+            };
+            off += 4;
+            match token {
+                FDT_BEGIN_NODE => {
+                    let Some(name) = self.str_at(off) else {
+                        return;
+                    };
+                    off = (off + name.len() + 1).next_multiple_of(4);
+                    depth += 1;
+                    if depth == 2 && name == b"reserved-memory" {
+                        in_reserved = true;
+                    } else if in_reserved && depth == 3 {
+                        node = ReservedNode::default();
+                    }
+                }
+                FDT_END_NODE => {
+                    if in_reserved && depth == 3 {
+                        f(&node);
+                    } else if depth == 2 {
+                        in_reserved = false;
+                    }
+                    depth -= 1;
+                }
+                FDT_PROP => {
+                    let (Some(len), Some(name_off)) = (self.be32(off), self.be32(off + 4)) else {
+                        return;
+                    };
+                    let value_off = off + 8;
+                    off = (value_off + len as usize).next_multiple_of(4);
+                    let (Some(name), Some(value)) = (
+                        self.str_at(self.off_strings + name_off as usize),
+                        self.data.get(value_off..value_off + len as usize),
+                    ) else {
+                        return;
+                    };
+                    if in_reserved && depth == 2 {
+                        match name {
+                            b"#address-cells" => cells.0 = read_cells(value, 1).unwrap_or(2),
+                            b"#size-cells" => cells.1 = read_cells(value, 1).unwrap_or(1),
+                            _ => {}
+                        }
+                    } else if in_reserved && depth == 3 {
+                        parse_reserved_prop(&mut node, name, value, cells);
+                    }
+                }
+                FDT_NOP => {}
+                FDT_END => return,
+                // malformed
+                _ => return,
+            }
+        }
+    }
+
+    /// Returns the physical address range `[start, end)` of the initial RAM
+    /// disk loaded by the bootloader, from the `linux,initrd-start` and
+    /// `linux,initrd-end` properties of the `/chosen` node.
+    pub fn initrd(&self) -> Option<(usize, usize)> {
+        let mut off = self.off_struct;
+        let mut depth = 0;
+        let mut in_chosen = false;
+        let (mut start, mut end) = (None, None);
+        loop {
+            let token = self.be32(off)?;
+            off += 4;
+            match token {
+                FDT_BEGIN_NODE => {
+                    let name = self.str_at(off)?;
+                    off = (off + name.len() + 1).next_multiple_of(4);
+                    depth += 1;
+                    if depth == 2 {
+                        in_chosen = name == b"chosen";
+                    }
+                }
+                FDT_END_NODE => {
+                    if in_chosen && depth == 2 {
+                        break;
+                    }
+                    depth -= 1;
+                }
+                FDT_PROP => {
+                    let (len, name_off) = (self.be32(off)? as usize, self.be32(off + 4)?);
+                    let value_off = off + 8;
+                    off = (value_off + len).next_multiple_of(4);
+                    if in_chosen && depth == 2 {
+                        let name = self.str_at(self.off_strings + name_off as usize)?;
+                        let value = self.data.get(value_off..value_off + len)?;
+                        // either one or two cells
+                        match name {
+                            b"linux,initrd-start" => start = read_cells(value, len / 4),
+                            b"linux,initrd-end" => end = read_cells(value, len / 4),
+                            _ => {}
+                        }
+                    }
+                }
+                FDT_NOP => {}
+                // the end, or malformed
+                _ => break,
+            }
+        }
+        start.zip(end).filter(|(start, end)| start < end)
+    }
+}
+
+fn parse_reserved_prop(node: &mut ReservedNode, name: &[u8], value: &[u8], cells: (usize, usize)) {
+    let (addr_cells, size_cells) = cells;
+    match name {
+        b"reg" => {
+            node.reg = read_cells(value, addr_cells).zip(
+                value
+                    .get(addr_cells * 4..)
+                    .and_then(|v| read_cells(v, size_cells)),
+            );
+        }
+        b"size" => node.size = read_cells(value, size_cells),
+        b"alignment" => node.alignment = read_cells(value, size_cells),
+        b"compatible" => {
+            node.shared_dma_pool = value.split(|&b| b == 0).any(|s| s == b"shared-dma-pool");
+        }
+        b"reusable" => node.reusable = true,
+        b"linux,cma-default" => node.cma_default = true,
+        _ => {}
+    }
+}
+
+/// Reads a number of `cells` big-endian 32-bit cells.
+fn read_cells(value: &[u8], cells: usize) -> Option<usize> {
+    let bytes = value.get(..cells * 4)?;
+    let n = bytes.chunks_exact(4).fold(0u64, |n, c| {
+        (n << 32) | u32::from_be_bytes(c.try_into().unwrap()) as u64
+    });
+    Some(n as usize)
+}
@@ -0,0 +1,197 @@
+//! The formats of the entries of the tables and the commands of the supported
+//! IOMMUs, kept apart from their drivers to be tested on any platform.
+
+/// Intel VT-d, with the legacy mode translation tables.
+#[cfg(any(test, all(target_arch = "x86_64", platform_family = "x86-pc")))]
+pub(crate) mod vtd {
+    use memory_addr::PhysAddr;
+
+    use crate::iommu::{DeviceId, IommuFlags};
+
+    /// Present bit of the root and context entries.
+    pub(crate) const ENTRY_PRESENT: u64 = 1 << 0;
+    /// The second-level page table of 3 levels, for 39-bit addresses.
+    const CONTEXT_AW_39: u64 = 1;
+    /// The second-level page table of 4 levels, for 48-bit addresses.
+    const CONTEXT_AW_48: u64 = 2;
+
+    /// Read permission of the second-level entries.
+    const SL_READ: u64 = 1 << 0;
+    /// Write permission of the second-level entries.
+    const SL_WRITE: u64 = 1 << 1;
+
+    /// Returns the indexes of the root entry and the context entry of `dev`,
+    /// its bus and its device and function numbers, or `None` if it's not a
+    /// PCI requester ID.
+    pub(crate) const fn context_index(dev: DeviceId) -> Option<(usize, usize)> {
+        if dev.0 > 0xffff {
+            return None;
+        }
+        Some(((dev.0 >> 8) as usize, (dev.0 & 0xff) as usize))
+    }
+
+    /// Returns the lower half of the root entry pointing to the context table
+    /// at `paddr`.
+    pub(crate) const fn root_entry(paddr: PhysAddr) -> u64 {
+        paddr.as_usize() as u64 | ENTRY_PRESENT
+    }
+
+    /// Returns the context entry of the domain `domain_id`, whose page table
+    /// of `levels` levels is at `root`.
+    ///
+    /// The untranslated requests are translated by the second-level page
+    /// table (translation type 0).
+    pub(crate) const fn context_entry(root: PhysAddr, domain_id: u16, levels: usize) -> [u64; 2] {
+        let aw = if levels == 4 {
+            CONTEXT_AW_48
+        } else {
+            CONTEXT_AW_39
+        };
+        [
+            root.as_usize() as u64 | ENTRY_PRESENT,
+            aw | ((domain_id as u64) << 8),
+        ]
+    }
+
+    /// Returns the second-level entry pointing to the next level table at
+    /// `paddr`.
+    pub(crate) const fn table_pte(paddr: PhysAddr) -> u64 {
+        paddr.as_usize() as u64 | SL_READ | SL_WRITE
+    }
+
+    /// Returns the second-level entry mapping the 4K page at `paddr`.
+    pub(crate) fn page_pte(paddr: PhysAddr, flags: IommuFlags) -> u64 {
+        let mut pte = paddr.as_usize() as u64;
+        if flags.contains(IommuFlags::READ) {
+            pte |= SL_READ;
+        }
+        if flags.contains(IommuFlags::WRITE) {
+            pte |= SL_WRITE;
+        }
+        pte
+    }
+
+    /// Whether the second-level entry `pte` is present.
+    pub(crate) const fn is_present(pte: u64) -> bool {
+        pte & (SL_READ | SL_WRITE) != 0
+    }
+}
+
+/// Arm SMMUv3, with the stage 2 translation.
+#[cfg(any(
+    test,
+    all(target_arch = "aarch64", platform_family = "aarch64-qemu-virt")
+))]
+pub(crate) mod smmuv3 {
+    use memory_addr::PhysAddr;
+
+    use crate::iommu::IommuFlags;
+
+    pub(crate) const STE_VALID: u64 = 1 << 0;
+    /// Stage 1 bypassed, stage 2 translated.
+    const STE_CONFIG_S2: u64 = 0b110 << 1;
+    /// Use the incoming shareability.
+    const STE_SHCFG_INCOMING: u64 = 1 << 44;
+
+    /// The stage 2 page tables of 3 levels from level 1, for 39-bit IPAs.
+    pub(crate) const S2_LEVELS: usize = 3;
+    const STE_S2T0SZ: u64 = 25 << 32;
+    const STE_S2SL0_LEVEL1: u64 = 1 << 38;
+    /// The walks are inner and outer write-back cacheable, and inner
+    /// shareable.
+    const STE_S2_WBACK_ISH: u64 = (1 << 40) | (1 << 42) | (3 << 44);
+    const STE_S2PS_SHIFT: u32 = 48;
+    const STE_S2AA64: u64 = 1 << 51;
+    const STE_S2R: u64 = 1 << 58;
+
+    const CMD_CFGI_STE: u64 = 0x03;
+    const CMD_CFGI_ALL: u64 = 0x04;
+    const CMD_TLBI_S12_VMALL: u64 = 0x28;
+    const CMD_TLBI_NSNH_ALL: u64 = 0x30;
+    const CMD_SYNC: u64 = 0x46;
+
+    /// Table or page descriptor.
+    const DESC_VALID_TABLE: u64 = 0b11;
+    /// Normal memory, inner and outer write-back cacheable.
+    const DESC_S2_MEMATTR_WB: u64 = 0b1111 << 2;
+    const DESC_S2AP_READ: u64 = 1 << 6;
+    const DESC_S2AP_WRITE: u64 = 1 << 7;
+    const DESC_SH_INNER: u64 = 0b11 << 8;
+    const DESC_AF: u64 = 1 << 10;
+
+    /// Returns the STE translating the streams by the stage 2 page table at
+    /// `root` of the domain `domain_id`, used as the VMID, with the output
+    /// address size `s2ps` (in the encoding of `S2PS`).
+    pub(crate) const fn ste(domain_id: u16, root: PhysAddr, s2ps: u64) -> [u64; 8] {
+        [
+            STE_VALID | STE_CONFIG_S2,
+            STE_SHCFG_INCOMING,
+            domain_id as u64
+                | STE_S2T0SZ
+                | STE_S2SL0_LEVEL1
+                | STE_S2_WBACK_ISH
+                | (s2ps << STE_S2PS_SHIFT)
+                | STE_S2AA64
+                | STE_S2R,
+            root.as_usize() as u64,
+            0,
+            0,
+            0,
+            0,
+        ]
+    }
+
+    /// Returns the command invalidating the cached STE of the stream `sid`.
+    pub(crate) const fn cmd_cfgi_ste(sid: u32) -> [u64; 2] {
+        // leaf, only the STE and not the context descriptors of stage 1
+        [CMD_CFGI_STE | ((sid as u64) << 32), 1]
+    }
+
+    /// Returns the command invalidating all the cached configurations.
+    pub(crate) const fn cmd_cfgi_all() -> [u64; 2] {
+        // the range of all the stream IDs
+        [CMD_CFGI_ALL, 31]
+    }
+
+    /// Returns the command invalidating the TLB entries of the VMID `vmid`.
+    pub(crate) const fn cmd_tlbi_s12_vmall(vmid: u16) -> [u64; 2] {
+        [CMD_TLBI_S12_VMALL | ((vmid as u64) << 32), 0]
+    }
+
+    /// Returns the command invalidating all the non-secure TLB entries.
+    pub(crate) const fn cmd_tlbi_nsnh_all() -> [u64; 2] {
+        [CMD_TLBI_NSNH_ALL, 0]
+    }
+
+    /// Returns the command completed once the commands before it are.
+    pub(crate) const fn cmd_sync() -> [u64; 2] {
+        [CMD_SYNC, 0]
+    }
+
+    /// Returns the stage 2 descriptor pointing to the next level table at
+    /// `paddr`.
+    pub(crate) const fn table_pte(paddr: PhysAddr) -> u64 {
+        paddr.as_usize() as u64 | DESC_VALID_TABLE
+    }
+
+    /// Returns the stage 2 descriptor mapping the 4K page at `paddr`.
+    pub(crate) fn page_pte(paddr: PhysAddr, flags: IommuFlags) -> u64 {
+        let mut pte = paddr.as_usize() as u64
+            | DESC_VALID_TABLE
+            | DESC_S2_MEMATTR_WB
+            | DESC_SH_INNER
+            | DESC_AF;
+        if flags.contains(IommuFlags::READ) {
+            pte |= DESC_S2AP_READ;
+        }
+        if flags.contains(IommuFlags::WRITE) {
+            pte |= DESC_S2AP_WRITE;
+        }
+        pte
+    }
+
+    /// Whether the stage 2 descriptor `pte` is valid.
+    pub(crate) const fn is_present(pte: u64) -> bool {
+        pte & 1 != 0
+    }
+}
@@ -0,0 +1,407 @@
+//! IOMMU (I/O memory management unit) support, to isolate the devices.
+//!
+//! With an IOMMU, the DMA of a device is translated from I/O virtual
+//! addresses (IOVAs) to physical addresses by the page table of the
+//! [`IommuDomain`] it's attached to, and any access out of the mappings of the
+//! domain is blocked. A device not attached to any domain can't access the
+//! memory at all.
+//!
+//! The devices used by the kernel are attached to the kernel domain
+//! ([`attach_kernel`]), where the memory is mapped at its physical address
+//! only while it's shared with the devices ([`map_dma`]), so that the drivers
+//! give the same addresses to the devices with or without an IOMMU. The other
+//! domains map any IOVAs, e.g. to pass a device through to a guest, with the
+//! memory of the guest mapped at its guest physical addresses.
+//!
+//! The supported IOMMUs are Intel VT-d on `x86-pc` and Arm SMMUv3 (stage 2
+//! translation) on `aarch64-qemu-virt`, found at the `iommu-paddr` of the
+//! platform configuration. On the other platforms, or if there is no IOMMU
+//! found, the devices access the physical memory directly, and the
+//! operations here do nothing.
+
+extern crate alloc;
+
+pub(crate) mod format;
+mod table;
+
+#[cfg(test)]
+mod tests;
+
+use alloc::collections::BTreeMap;
+use core::fmt;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use kspin::SpinNoIrq;
+use lazyinit::LazyInit;
+use memory_addr::{PhysAddr, VirtAddr, align_down_4k, align_up_4k, is_aligned_4k};
+
+use self::table::IoPageTable;
+use crate::mem::PAGE_SIZE_4K;
+
+pub(crate) use self::table::{alloc_frames, dealloc_frames};
+
+/// The error type of the IOMMU operations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IommuError {
+    /// There is no IOMMU.
+    NotPresent,
+    /// There is no memory for the page tables.
+    NoMemory,
+    /// The address, size or device is invalid.
+    InvalidParam,
+    /// The IOVA is already mapped.
+    AlreadyMapped,
+    /// The IOVA is not mapped.
+    NotMapped,
+    /// All the IDs of the domains are used.
+    NoDomainId,
+}
+
+impl fmt::Display for IommuError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let msg = match self {
+            Self::NotPresent => "no IOMMU",
+            Self::NoMemory => "no memory",
+            Self::InvalidParam => "invalid parameter",
+            Self::AlreadyMapped => "already mapped",
+            Self::NotMapped => "not mapped",
+            Self::NoDomainId => "no domain ID left",
+        };
+        f.write_str(msg)
+    }
+}
+
+/// The result type of the IOMMU operations.
+pub type IommuResult<T = ()> = Result<T, IommuError>;
+
+bitflags::bitflags! {
+    /// The accesses of the devices allowed to a mapping.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct IommuFlags: u8 {
+        /// The devices may read the memory.
+        const READ = 1 << 0;
+        /// The devices may write the memory.
+        const WRITE = 1 << 1;
+    }
+}
+
+/// The ID of a device behind the IOMMU, the requester ID of a PCI function
+/// for VT-d, or its stream ID for SMMUv3.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DeviceId(pub u32);
+
+impl DeviceId {
+    /// Returns the ID of the PCI function at `bus:device.function`.
+    pub const fn pci(bus: u8, device: u8, function: u8) -> Self {
+        Self(((bus as u32) << 8) | ((device as u32) << 3) | function as u32)
+    }
+}
+
+impl fmt::Display for DeviceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:02x}:{:02x}.{}",
+            self.0 >> 8,
+            (self.0 >> 3) & 0x1f,
+            self.0 & 0x7
+        )
+    }
+}
+
+/// The operations of an IOMMU, implemented by the platforms.
+pub(crate) trait IommuHw: Sync {
+    /// Returns the name of the IOMMU.
+    fn name(&self) -> &'static str;
+
+    /// Returns the number of the levels of the page tables, of 512 entries
+    /// each.
+    fn levels(&self) -> usize;
+
+    /// Returns the number of the IDs of the domains.
+    fn max_domains(&self) -> usize;
+
+    /// Returns the entry pointing to the next level table at `paddr`.
+    fn table_pte(&self, paddr: PhysAddr) -> u64;
+
+    /// Returns the entry mapping the 4K page at `paddr`.
+    fn page_pte(&self, paddr: PhysAddr, flags: IommuFlags) -> u64;
+
+    /// Whether the entry `pte` is present.
+    fn is_present(&self, pte: u64) -> bool;
+
+    /// Makes the tables in `[vaddr, vaddr + size)` written by the CPU visible
+    /// to the IOMMU.
+    fn sync_table(&self, vaddr: VirtAddr, size: usize);
+
+    /// Attaches the device `dev` to the domain `domain_id`, whose page table
+    /// is at `root`.
+    fn attach(&self, dev: DeviceId, domain_id: u16, root: PhysAddr) -> IommuResult;
+
+    /// Detaches the device `dev` from its domain, blocking its accesses.
+    fn detach(&self, dev: DeviceId) -> IommuResult;
+
+    /// Invalidates the translations of the domain `domain_id` cached by the
+    /// IOMMU.
+    fn flush_domain(&self, domain_id: u16);
+}
+
+static IOMMU: LazyInit<&'static dyn IommuHw> = LazyInit::new();
+
+static KERNEL_DOMAIN: LazyInit<KernelDomain> = LazyInit::new();
+
+/// The next ID of a domain, the ID 0 is reserved by VT-d.
+static NEXT_DOMAIN_ID: AtomicUsize = AtomicUsize::new(1);
+
+cfg_if::cfg_if! {
+    if #[cfg(any(
+        all(target_arch = "x86_64", platform_family = "x86-pc"),
+        all(target_arch = "aarch64", platform_family = "aarch64-qemu-virt"),
+    ))] {
+        use crate::platform::iommu::probe;
+    } else {
+        fn probe() -> Option<&'static dyn IommuHw> {
+            None
+        }
+    }
+}
+
+fn hw() -> IommuResult<&'static dyn IommuHw> {
+    IOMMU.get().copied().ok_or(IommuError::NotPresent)
+}
+
+/// Returns the range of the 4K pages covering `[addr, addr + size)`.
+fn page_range(addr: usize, size: usize) -> IommuResult<(usize, usize)> {
+    let end = addr.checked_add(size).ok_or(IommuError::InvalidParam)?;
+    Ok((align_down_4k(addr), align_up_4k(end)))
+}
+
+/// A domain of the IOMMU, the I/O address space of the devices attached to
+/// it.
+///
+/// The devices attached must be detached before it's dropped. Its ID is never
+/// reused.
+pub struct IommuDomain {
+    id: u16,
+    table: SpinNoIrq<IoPageTable>,
+}
+
+impl IommuDomain {
+    /// Creates an empty domain.
+    ///
+    /// It fails with [`IommuError::NotPresent`] if there is no IOMMU.
+    pub fn new() -> IommuResult<Self> {
+        let hw = hw()?;
+        let id = NEXT_DOMAIN_ID
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |id| {
+                (id < hw.max_domains()).then_some(id + 1)
+            })
+            .map_err(|_| IommuError::NoDomainId)?;
+        Ok(Self {
+            id: id as u16,
+            table: SpinNoIrq::new(IoPageTable::new(hw)?),
+        })
+    }
+
+    /// Returns the ID of the domain.
+    pub fn id(&self) -> u16 {
+        self.id
+    }
+
+    /// Returns the size of the I/O address space, the IOVAs are below it.
+    pub fn iova_limit(&self) -> usize {
+        self.table.lock().iova_limit()
+    }
+
+    /// Maps the IOVAs in `[iova, iova + size)` to the physical memory at
+    /// `paddr`, with the accesses `flags` allowed.
+    ///
+    /// The addresses and the size must be aligned to 4K. Nothing is mapped if
+    /// any of the IOVAs is already mapped.
+    pub fn map(&self, iova: usize, paddr: PhysAddr, size: usize, flags: IommuFlags) -> IommuResult {
+        if !is_aligned_4k(iova) || !is_aligned_4k(paddr.as_usize()) || !is_aligned_4k(size) {
+            return Err(IommuError::InvalidParam);
+        }
+        let hw = hw()?;
+        let mut table = self.table.lock();
+        table.map_range(hw, iova, paddr, size, flags)?;
+        hw.flush_domain(self.id);
+        Ok(())
+    }
+
+    /// Unmaps the IOVAs in `[iova, iova + size)`.
+    ///
+    /// The address and the size must be aligned to 4K. It fails with
+    /// [`IommuError::NotMapped`] if any of the IOVAs is not mapped, after
+    /// unmapping the others.
+    pub fn unmap(&self, iova: usize, size: usize) -> IommuResult {
+        if !is_aligned_4k(iova) || !is_aligned_4k(size) {
+            return Err(IommuError::InvalidParam);
+        }
+        let hw = hw()?;
+        let mut table = self.table.lock();
+        let res = table.unmap_range(hw, iova, size);
+        hw.flush_domain(self.id);
+        res
+    }
+
+    /// Returns the physical address the IOVA `iova` is mapped to.
+    pub fn translate(&self, iova: usize) -> Option<PhysAddr> {
+        self.table.lock().translate(hw().ok()?, iova)
+    }
+
+    /// Attaches the device `dev` to the domain, detaching it from its
+    /// previous one.
+    pub fn attach(&self, dev: DeviceId) -> IommuResult {
+        let hw = hw()?;
+        let root = self.table.lock().root_paddr();
+        hw.attach(dev, self.id, root)
+    }
+}
+
+impl fmt::Debug for IommuDomain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IommuDomain").field("id", &self.id).finish()
+    }
+}
+
+/// The domain of the devices used by the kernel, with the memory shared with
+/// them mapped at its physical addresses.
+struct KernelDomain {
+    domain: IommuDomain,
+    /// The number of the shares of each page mapped, by its address.
+    refs: SpinNoIrq<BTreeMap<usize, usize>>,
+}
+
+impl KernelDomain {
+    /// Unmaps the pages in `[start, end)`, which are shared once less.
+    fn unshare(
+        &self,
+        refs: &mut BTreeMap<usize, usize>,
+        table: &mut IoPageTable,
+        start: usize,
+        end: usize,
+    ) {
+        let Ok(hw) = hw() else {
+            return;
+        };
+        for page in (start..end).step_by(PAGE_SIZE_4K) {
+            let Some(count) = refs.get_mut(&page) else {
+                warn!("IOMMU: unmapping a page not mapped: {:#x}", page);
+                continue;
+            };
+            *count -= 1;
+            if *count == 0 {
+                refs.remove(&page);
+                table.unmap_range(hw, page, PAGE_SIZE_4K).ok();
+            }
+        }
+    }
+}
+
+/// Probes the IOMMU of the platform and enables it.
+///
+/// It's called once by the runtime after the memory allocator is initialized,
+/// before the drivers probe the devices.
+pub fn init() {
+    let Some(hw) = probe() else {
+        return;
+    };
+    IOMMU.init_once(hw);
+    match IommuDomain::new() {
+        Ok(domain) => {
+            KERNEL_DOMAIN.init_once(KernelDomain {
+                domain,
+                refs: SpinNoIrq::new(BTreeMap::new()),
+            });
+            info!("IOMMU: {} enabled", hw.name());
+        }
+        Err(e) => warn!("IOMMU: failed to create the kernel domain: {}", e),
+    }
+}
+
+/// Returns the name of the IOMMU, or `None` if there is none.
+pub fn name() -> Option<&'static str> {
+    hw().ok().map(|hw| hw.name())
+}
+
+/// Whether there is an IOMMU.
+pub fn is_present() -> bool {
+    IOMMU.is_inited()
+}
+
+/// Detaches the device `dev` from its domain, blocking its accesses.
+pub fn detach(dev: DeviceId) -> IommuResult {
+    hw()?.detach(dev)
+}
+
+/// Attaches the device `dev` to the kernel domain, to be used by a driver of
+/// the kernel.
+///
+/// It does nothing if there is no IOMMU.
+pub fn attach_kernel(dev: DeviceId) -> IommuResult {
+    match KERNEL_DOMAIN.get() {
+        Some(kernel) => kernel.domain.attach(dev),
+        None => Ok(()),
+    }
+}
+
+/// Maps the physical memory in `[paddr, paddr + size)` at the same IOVAs in
+/// the kernel domain, while it's shared with the devices.
+///
+/// The whole pages covering it are mapped, so the memory around it in the
+/// same pages is exposed too. The memory may be shared more than once, and
+/// it's unmapped after as many [`unmap_dma`]. It does nothing if there is no
+/// IOMMU.
+pub fn map_dma(paddr: PhysAddr, size: usize) -> IommuResult {
+    let Some(kernel) = KERNEL_DOMAIN.get() else {
+        return Ok(());
+    };
+    let hw = hw()?;
+    let (start, end) = page_range(paddr.as_usize(), size)?;
+    let mut refs = kernel.refs.lock();
+    let mut table = kernel.domain.table.lock();
+    if end > table.iova_limit() {
+        return Err(IommuError::InvalidParam);
+    }
+    for page in (start..end).step_by(PAGE_SIZE_4K) {
+        let count = refs.entry(page).or_default();
+        *count += 1;
+        if *count == 1 {
+            let res = table.map_range(
+                hw,
+                page,
+                PhysAddr::from(page),
+                PAGE_SIZE_4K,
+                IommuFlags::READ | IommuFlags::WRITE,
+            );
+            if let Err(e) = res {
+                refs.remove(&page);
+                kernel.unshare(&mut refs, &mut table, start, page);
+                return Err(e);
+            }
+        }
+    }
+    // the IOMMUs in the caching mode cache the entries not present
+    hw.flush_domain(kernel.domain.id);
+    Ok(())
+}
+
+/// Unmaps the physical memory in `[paddr, paddr + size)` from the kernel
+/// domain, shared by [`map_dma`].
+///
+/// It does nothing if there is no IOMMU.
+pub fn unmap_dma(paddr: PhysAddr, size: usize) {
+    let Some(kernel) = KERNEL_DOMAIN.get() else {
+        return;
+    };
+    let Ok((start, end)) = page_range(paddr.as_usize(), size) else {
+        return;
+    };
+    let mut refs = kernel.refs.lock();
+    let mut table = kernel.domain.table.lock();
+    kernel.unshare(&mut refs, &mut table, start, end);
+    if let Ok(hw) = hw() {
+        hw.flush_domain(kernel.domain.id);
+    }
+}
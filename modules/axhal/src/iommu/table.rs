@@ -0,0 +1,188 @@
+//! The I/O page tables of the domains.
+//!
+//! VT-d and SMMUv3 (stage 2) walk the same radix tree of 512 entries per
+//! table as the CPU, with the 4K pages mapped at the last level, only the
+//! bits of the entries differ ([`IommuHw::table_pte`] and
+//! [`IommuHw::page_pte`]). The tables emptied by the unmappings are kept until
+//! the whole page table is dropped.
+
+use axalloc::global_allocator;
+use memory_addr::{PhysAddr, VirtAddr};
+
+use super::{IommuError, IommuFlags, IommuHw, IommuResult};
+use crate::mem::{PAGE_SIZE_4K, phys_to_virt, virt_to_phys};
+
+const ENTRY_COUNT: usize = 512;
+
+/// The bits of the next level table or the page in an entry.
+pub(super) const PTE_ADDR_MASK: u64 = 0x000f_ffff_ffff_f000;
+
+/// Allocates `num_frames` contiguous frames filled with zero, for the tables
+/// of the IOMMU.
+pub(crate) fn alloc_frames(num_frames: usize) -> IommuResult<PhysAddr> {
+    let vaddr = global_allocator()
+        .alloc_pages(num_frames, PAGE_SIZE_4K)
+        .map_err(|_| IommuError::NoMemory)?;
+    unsafe { core::ptr::write_bytes(vaddr as *mut u8, 0, num_frames * PAGE_SIZE_4K) };
+    Ok(virt_to_phys(VirtAddr::from(vaddr)))
+}
+
+/// Gives back the frames allocated by [`alloc_frames`].
+pub(crate) fn dealloc_frames(paddr: PhysAddr, num_frames: usize) {
+    global_allocator().dealloc_pages(phys_to_virt(paddr).as_usize(), num_frames);
+}
+
+fn table_of<'a>(paddr: PhysAddr) -> &'a mut [u64; ENTRY_COUNT] {
+    unsafe { &mut *phys_to_virt(paddr).as_mut_ptr().cast() }
+}
+
+fn entry_vaddr(entry: &u64) -> VirtAddr {
+    VirtAddr::from(entry as *const u64 as usize)
+}
+
+pub(super) const fn pte_addr(pte: u64) -> PhysAddr {
+    PhysAddr::from_usize((pte & PTE_ADDR_MASK) as usize)
+}
+
+/// Returns the index of the entry of `iova` in the table at `level`, the
+/// level of the pages being 0.
+pub(super) const fn pte_index(iova: usize, level: usize) -> usize {
+    (iova >> (12 + 9 * level)) & (ENTRY_COUNT - 1)
+}
+
+/// Gives back the table at `paddr` and the ones below it.
+fn free_table(paddr: PhysAddr, level: usize) {
+    if level > 0 {
+        // the entries of the tables are never cleared once set
+        for &pte in table_of(paddr).iter().filter(|&&pte| pte != 0) {
+            free_table(pte_addr(pte), level - 1);
+        }
+    }
+    dealloc_frames(paddr, 1);
+}
+
+/// The page table of a domain.
+pub(super) struct IoPageTable {
+    root: PhysAddr,
+    levels: usize,
+}
+
+impl IoPageTable {
+    /// Creates an empty page table in the format of the IOMMU `hw`.
+    pub fn new(hw: &dyn IommuHw) -> IommuResult<Self> {
+        let root = alloc_frames(1)?;
+        hw.sync_table(phys_to_virt(root), PAGE_SIZE_4K);
+        Ok(Self {
+            root,
+            levels: hw.levels(),
+        })
+    }
+
+    /// Returns the physical address of the root table.
+    pub fn root_paddr(&self) -> PhysAddr {
+        self.root
+    }
+
+    /// Returns the size of the address space mapped by the table.
+    pub fn iova_limit(&self) -> usize {
+        1 << (12 + 9 * self.levels)
+    }
+
+    /// Returns the entry of the page of `iova`, the missing tables on the way
+    /// are created if `create`.
+    fn page_entry(&mut self, hw: &dyn IommuHw, iova: usize, create: bool) -> IommuResult<&mut u64> {
+        if iova >= self.iova_limit() {
+            return Err(IommuError::InvalidParam);
+        }
+        let mut table = table_of(self.root);
+        for level in (1..self.levels).rev() {
+            let entry = &mut table[pte_index(iova, level)];
+            if !hw.is_present(*entry) {
+                if !create {
+                    return Err(IommuError::NotMapped);
+                }
+                let next = alloc_frames(1)?;
+                hw.sync_table(phys_to_virt(next), PAGE_SIZE_4K);
+                *entry = hw.table_pte(next);
+                hw.sync_table(entry_vaddr(entry), size_of::<u64>());
+            }
+            table = table_of(pte_addr(*entry));
+        }
+        Ok(&mut table[pte_index(iova, 0)])
+    }
+
+    fn map_page(
+        &mut self,
+        hw: &dyn IommuHw,
+        iova: usize,
+        paddr: PhysAddr,
+        flags: IommuFlags,
+    ) -> IommuResult {
+        let entry = self.page_entry(hw, iova, true)?;
+        if hw.is_present(*entry) {
+            return Err(IommuError::AlreadyMapped);
+        }
+        *entry = hw.page_pte(paddr, flags);
+        hw.sync_table(entry_vaddr(entry), size_of::<u64>());
+        Ok(())
+    }
+
+    /// Maps the 4K pages in `[iova, iova + size)` to the physical memory at
+    /// `paddr`, nothing is mapped on errors.
+    ///
+    /// The translations cached by the IOMMU are not invalidated.
+    pub fn map_range(
+        &mut self,
+        hw: &dyn IommuHw,
+        iova: usize,
+        paddr: PhysAddr,
+        size: usize,
+        flags: IommuFlags,
+    ) -> IommuResult {
+        if flags.is_empty()
+            || iova
+                .checked_add(size)
+                .is_none_or(|end| end > self.iova_limit())
+        {
+            return Err(IommuError::InvalidParam);
+        }
+        for offset in (0..size).step_by(PAGE_SIZE_4K) {
+            if let Err(e) = self.map_page(hw, iova + offset, paddr + offset, flags) {
+                self.unmap_range(hw, iova, offset).ok();
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Unmaps the 4K pages in `[iova, iova + size)`, it fails with
+    /// [`IommuError::NotMapped`] if any of them is not mapped.
+    ///
+    /// The translations cached by the IOMMU are not invalidated.
+    pub fn unmap_range(&mut self, hw: &dyn IommuHw, iova: usize, size: usize) -> IommuResult {
+        let mut res = Ok(());
+        for offset in (0..size).step_by(PAGE_SIZE_4K) {
+            match self.page_entry(hw, iova + offset, false) {
+                Ok(entry) if hw.is_present(*entry) => {
+                    *entry = 0;
+                    hw.sync_table(entry_vaddr(entry), size_of::<u64>());
+                }
+                _ => res = Err(IommuError::NotMapped),
+            }
+        }
+        res
+    }
+
+    /// Returns the physical address `iova` is mapped to.
+    pub fn translate(&mut self, hw: &dyn IommuHw, iova: usize) -> Option<PhysAddr> {
+        let entry = *self.page_entry(hw, iova, false).ok()?;
+        hw.is_present(entry)
+            .then(|| pte_addr(entry) + (iova & (PAGE_SIZE_4K - 1)))
+    }
+}
+
+impl Drop for IoPageTable {
+    fn drop(&mut self) {
+        free_table(self.root, self.levels - 1);
+    }
+}
@@ -0,0 +1,107 @@
+use memory_addr::pa;
+
+use super::format::{smmuv3, vtd};
+use super::table::{PTE_ADDR_MASK, pte_addr, pte_index};
+use super::{DeviceId, IommuFlags};
+
+const RW: IommuFlags = IommuFlags::READ.union(IommuFlags::WRITE);
+
+#[test]
+fn test_pte_index() {
+    let iova = (0x12 << 30) | (0x34 << 21) | (0x56 << 12) | 0x789;
+    assert_eq!(pte_index(iova, 0), 0x56);
+    assert_eq!(pte_index(iova, 1), 0x34);
+    assert_eq!(pte_index(iova, 2), 0x12);
+    assert_eq!(pte_index(iova, 3), 0);
+    assert_eq!(pte_index(usize::MAX, 3), 511);
+
+    // the attributes around the address are dropped
+    assert_eq!(pte_addr(0xfff0_1234_5678_9fff), pa!(0x1234_5678_9000));
+    assert_eq!(PTE_ADDR_MASK.count_ones(), 40);
+}
+
+#[test]
+fn test_vtd_entries() {
+    let paddr = pa!(0x1_2345_6000);
+    assert_eq!(vtd::table_pte(paddr), 0x1_2345_6003);
+    assert_eq!(vtd::page_pte(paddr, IommuFlags::READ), 0x1_2345_6001);
+    assert_eq!(vtd::page_pte(paddr, IommuFlags::WRITE), 0x1_2345_6002);
+    assert_eq!(vtd::page_pte(paddr, RW), 0x1_2345_6003);
+    assert_eq!(pte_addr(vtd::page_pte(paddr, RW)), paddr);
+
+    // present with any of the permissions
+    assert!(vtd::is_present(vtd::page_pte(paddr, IommuFlags::WRITE)));
+    assert!(vtd::is_present(vtd::table_pte(paddr)));
+    assert!(!vtd::is_present(0x1_2345_6000));
+    assert!(!vtd::is_present(0));
+}
+
+#[test]
+fn test_vtd_context() {
+    let dev = DeviceId::pci(0x3a, 0x1f, 7);
+    assert_eq!(vtd::context_index(dev), Some((0x3a, 0xff)));
+    assert_eq!(vtd::context_index(DeviceId::pci(0, 1, 0)), Some((0, 0x08)));
+    assert_eq!(vtd::context_index(DeviceId(0x1_0000)), None);
+    assert_eq!(vtd::root_entry(pa!(0x8000)), 0x8001);
+
+    // AW in the bits 2:0 of the upper half, the domain ID from the bit 8
+    let root = pa!(0xabc_d000);
+    assert_eq!(vtd::context_entry(root, 5, 4), [0xabc_d001, 0x502]);
+    assert_eq!(vtd::context_entry(root, 0xffff, 3), [0xabc_d001, 0xff_ff01]);
+}
+
+#[test]
+fn test_smmuv3_descriptors() {
+    let paddr = pa!(0x4_0000_1000);
+    // valid page, normal write-back memory, inner shareable, accessed
+    let attrs = 0b11 | (0b1111 << 2) | (0b11 << 8) | (1 << 10);
+    assert_eq!(smmuv3::table_pte(paddr), 0x4_0000_1003);
+    assert_eq!(
+        smmuv3::page_pte(paddr, IommuFlags::READ),
+        0x4_0000_1000 | attrs | (1 << 6)
+    );
+    assert_eq!(
+        smmuv3::page_pte(paddr, IommuFlags::WRITE),
+        0x4_0000_1000 | attrs | (1 << 7)
+    );
+    assert_eq!(
+        smmuv3::page_pte(paddr, RW),
+        0x4_0000_1000 | attrs | (0b11 << 6)
+    );
+    assert_eq!(pte_addr(smmuv3::page_pte(paddr, RW)), paddr);
+
+    assert!(smmuv3::is_present(smmuv3::table_pte(paddr)));
+    assert!(!smmuv3::is_present(0x4_0000_1002));
+    assert!(!smmuv3::is_present(0));
+}
+
+#[test]
+fn test_smmuv3_ste() {
+    let ste = smmuv3::ste(0x42, pa!(0x8765_4000), 5);
+    // valid, stage 1 bypassed and stage 2 translated
+    assert_eq!(ste[0], 0b1101);
+    assert_eq!(ste[1], 1 << 44);
+    // VMID, T0SZ, SL0, the attributes of the walks, PS, AA64 and S2R
+    assert_eq!(ste[2] & 0xffff, 0x42);
+    assert_eq!((ste[2] >> 32) & 0x3f, 25);
+    assert_eq!((ste[2] >> 38) & 0b11, 1);
+    assert_eq!((ste[2] >> 40) & 0xff, 0b11_01_01);
+    assert_eq!((ste[2] >> 48) & 0b111, 5);
+    assert_eq!(ste[2] & ((1 << 51) | (1 << 58)), (1 << 51) | (1 << 58));
+    assert_eq!(ste[3], 0x8765_4000);
+    assert!(ste[4..].iter().all(|&word| word == 0));
+    assert_eq!(
+        1 << (12 + 9 * smmuv3::S2_LEVELS),
+        1usize << (64 - 25),
+        "T0SZ doesn't match the levels"
+    );
+}
+
+#[test]
+fn test_smmuv3_commands() {
+    assert_eq!(smmuv3::cmd_cfgi_ste(0x1234), [0x1234_0000_0003, 1]);
+    assert_eq!(smmuv3::cmd_cfgi_all(), [0x04, 31]);
+    assert_eq!(smmuv3::cmd_tlbi_s12_vmall(7), [0x7_0000_0028, 0]);
+    assert_eq!(smmuv3::cmd_tlbi_nsnh_all(), [0x30, 0]);
+    assert_eq!(smmuv3::cmd_sync(), [0x46, 0]);
+}
@@ -1,4 +1,13 @@
 //! Interrupt management.
+//!
+//! The IRQ handlers should be kept short, and leave the rest of the work to
+//! a [softirq](softirq). PCI devices may signal IRQs of their own with
+//! [MSIs](msi), and the CPUs interrupt each other with [IPIs](ipi).
+
+#[cfg(feature = "smp")]
+pub mod ipi;
+pub mod msi;
+pub mod softirq;
 
 use handler_table::HandlerTable;
 
@@ -38,7 +47,12 @@ pub(crate) fn register_handler_common(irq_num: usize, handler: IrqHandler) -> bo
 #[register_trap_handler(IRQ)]
 fn handler_irq(irq_num: usize) -> bool {
     let guard = kernel_guard::NoPreempt::new();
+    #[cfg(feature = "trace")]
+    axtrace::tracepoint!("irq", "irq_handler_entry", irq = irq_num);
     dispatch_irq(irq_num);
+    #[cfg(feature = "trace")]
+    axtrace::tracepoint!("irq", "irq_handler_exit", irq = irq_num);
+    softirq::irq_exit();
     drop(guard); // rescheduling may occur when preemption is re-enabled.
     true
 }
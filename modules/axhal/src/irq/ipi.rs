@@ -0,0 +1,134 @@
+//! Inter-processor interrupts (IPIs), to run a function on the other CPUs.
+//!
+//! [`run_on_other_cpus`] runs a function on every other online CPU from its
+//! IPI handler, and returns once they all have run it: e.g. to flush their
+//! TLB after a mapping is removed, or to order their memory accesses.
+//!
+//! A single cross-CPU call is in flight at a time. A CPU waiting for its turn
+//! runs the calls sent to it meanwhile, so that two CPUs calling each other
+//! with IRQs disabled don't wait for each other forever.
+//!
+//! [`wake_up_cpu`] only interrupts a CPU, e.g. to wake it up from
+//! [`wait_for_irqs`](crate::arch::wait_for_irqs), without waiting for it.
+
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+
+use kspin::SpinNoIrq;
+
+use crate::cpu::this_cpu_id;
+use crate::platform::irq::{IPI_IRQ_NUM, send_ipi};
+
+static_assertions::const_assert!(axconfig::SMP <= usize::BITS as usize);
+
+/// The bitmask of the CPUs handling the IPIs.
+static ONLINE: AtomicUsize = AtomicUsize::new(0);
+/// The bitmask of the CPUs that haven't run the call in flight yet.
+static PENDING: AtomicUsize = AtomicUsize::new(0);
+/// Whether a call is in flight.
+static CALL_LOCK: AtomicBool = AtomicBool::new(false);
+/// The function of the call in flight, borrowed from [`run_on_other_cpus`]
+/// until all the CPUs have run it.
+static CALL_FN: SpinNoIrq<Option<&'static (dyn Fn() + Sync)>> = SpinNoIrq::new(None);
+/// The bitmask of the CPUs woken up by [`wake_up_cpu`] since their last IPI.
+static WAKEUP: AtomicUsize = AtomicUsize::new(0);
+/// The function run by the CPUs woken up, a `fn()`.
+static WAKEUP_HANDLER: AtomicPtr<()> = AtomicPtr::new(core::ptr::null_mut());
+
+fn handle_ipi() {
+    let mask = 1 << this_cpu_id();
+    if WAKEUP.fetch_and(!mask, Ordering::AcqRel) & mask != 0 {
+        let f = WAKEUP_HANDLER.load(Ordering::Acquire);
+        if !f.is_null() {
+            // Safety: only `fn()`s are stored.
+            let f = unsafe { core::mem::transmute::<*mut (), fn()>(f) };
+            f();
+        }
+    }
+    run_pending_call();
+}
+
+/// Runs the call in flight if it's pending on the current CPU.
+fn run_pending_call() {
+    let mask = 1 << this_cpu_id();
+    if PENDING.load(Ordering::Acquire) & mask == 0 {
+        return;
+    }
+    // the caller waits for the CPU to clear its bit before giving the
+    // function up
+    let func = *CALL_FN.lock();
+    if let Some(func) = func {
+        func();
+    }
+    PENDING.fetch_and(!mask, Ordering::Release);
+}
+
+/// Runs `f` on every other online CPU, and waits until they all have run it.
+///
+/// `f` runs in the IPI handler, with IRQs disabled, so it should be short and
+/// must not block. It doesn't run on the current CPU.
+pub fn run_on_other_cpus(f: &(dyn Fn() + Sync)) {
+    let _guard = kernel_guard::NoPreempt::new();
+    let targets = ONLINE.load(Ordering::Acquire) & !(1 << this_cpu_id());
+    if targets == 0 {
+        return;
+    }
+    while CALL_LOCK
+        .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+        .is_err()
+    {
+        run_pending_call();
+        core::hint::spin_loop();
+    }
+
+    // Safety: `f` is only used until the pending bits are cleared, before
+    // this function returns.
+    let func = unsafe { core::mem::transmute::<&(dyn Fn() + Sync), &'static (dyn Fn() + Sync)>(f) };
+    *CALL_FN.lock() = Some(func);
+    PENDING.store(targets, Ordering::Release);
+    for cpu_id in 0..axconfig::SMP {
+        if targets & (1 << cpu_id) != 0 {
+            send_ipi(cpu_id);
+        }
+    }
+    while PENDING.load(Ordering::Acquire) != 0 {
+        core::hint::spin_loop();
+    }
+    *CALL_FN.lock() = None;
+
+    CALL_LOCK.store(false, Ordering::Release);
+}
+
+/// Sets the function run by the CPUs woken up by [`wake_up_cpu`].
+///
+/// It runs in the IPI handler, with IRQs disabled, so it should be short and
+/// must not block.
+pub fn set_wakeup_handler(f: fn()) {
+    WAKEUP_HANDLER.store(f as *mut (), Ordering::Release);
+}
+
+/// Interrupts the CPU `cpu_id` if it's online, and runs the function set by
+/// [`set_wakeup_handler`] on it. It returns at once.
+///
+/// The wake-ups sent to a CPU before it handles the first one are merged.
+pub fn wake_up_cpu(cpu_id: usize) {
+    let mask = 1 << cpu_id;
+    if cpu_id == this_cpu_id() || ONLINE.load(Ordering::Acquire) & mask == 0 {
+        return;
+    }
+    if WAKEUP.fetch_or(mask, Ordering::AcqRel) & mask == 0 {
+        send_ipi(cpu_id);
+    }
+}
+
+/// Registers the IPI handler, and puts the primary CPU online.
+///
+/// It's called once the IRQs of the primary CPU are set up.
+pub fn init() {
+    crate::irq::register_handler(IPI_IRQ_NUM, handle_ipi);
+    ONLINE.fetch_or(1 << this_cpu_id(), Ordering::Release);
+}
+
+/// Puts a secondary CPU online, once its IRQs are enabled.
+pub fn init_secondary() {
+    ONLINE.fetch_or(1 << this_cpu_id(), Ordering::Release);
+}
@@ -0,0 +1,70 @@
+//! Message signaled interrupts (MSI and MSI-X) of PCI devices.
+//!
+//! A device signals an MSI by writing a message to an address, which the
+//! interrupt controller turns into an IRQ. Each MSI is given an IRQ of its
+//! own with [`alloc_irq`], so a device can have one per queue rather than
+//! sharing a legacy INTx line with others. The IRQ handler is registered
+//! with [`register_handler`](super::register_handler) as usual.
+//!
+//! The messages of [`message`] are delivered to the primary CPU, and those of
+//! [`message_to`] to the given one, e.g. the CPU which polls the queue of the
+//! device. MSIs are supported on:
+//!
+//! - x86_64: the messages are sent to the local APIC, with the vectors
+//!   `0x40..0xf0`.
+//! - aarch64: the messages are sent to the GICv2m frame (`gicv2m-paddr` in
+//!   the platform config), which turns them into SPIs. There's no support for
+//!   the GICv3 ITS, as only the GICv2 is supported.
+//!
+//! There are no IRQs for MSIs on other platforms.
+
+use kspin::SpinNoIrq;
+
+use crate::platform::irq::{MAX_IRQ_COUNT, msi_irqs};
+
+/// An MSI message: the device writes `data` to `address` to raise the IRQ.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MsiMessage {
+    /// The address written to.
+    pub address: u64,
+    /// The data written.
+    pub data: u32,
+}
+
+static ALLOCATED: SpinNoIrq<[bool; MAX_IRQ_COUNT]> = SpinNoIrq::new([false; MAX_IRQ_COUNT]);
+
+/// Allocates an IRQ for an MSI.
+///
+/// It returns `None` if all are used, or the platform has none.
+pub fn alloc_irq() -> Option<usize> {
+    let mut allocated = ALLOCATED.lock();
+    let irq = msi_irqs().find(|&irq| !allocated[irq])?;
+    allocated[irq] = true;
+    trace!("MSI IRQ {} allocated", irq);
+    Some(irq)
+}
+
+/// Frees an IRQ allocated with [`alloc_irq`].
+///
+/// Its handler must have been unregistered, and the device must no longer
+/// send its message.
+pub fn free_irq(irq: usize) {
+    assert!(msi_irqs().contains(&irq), "not an MSI IRQ: {}", irq);
+    ALLOCATED.lock()[irq] = false;
+}
+
+/// Returns the message raising `irq`, an IRQ allocated with [`alloc_irq`].
+pub fn message(irq: usize) -> MsiMessage {
+    message_to(irq, 0)
+}
+
+/// Returns the message raising `irq`, an IRQ allocated with [`alloc_irq`], on
+/// the CPU `cpu_id`.
+///
+/// On aarch64, the IRQ itself is routed to the CPU, so the messages given
+/// before for other CPUs raise it on this one too.
+pub fn message_to(irq: usize, cpu_id: usize) -> MsiMessage {
+    assert!(msi_irqs().contains(&irq), "not an MSI IRQ: {}", irq);
+    assert!(cpu_id < axconfig::SMP, "invalid CPU ID: {}", cpu_id);
+    crate::platform::irq::msi_message(irq, cpu_id)
+}
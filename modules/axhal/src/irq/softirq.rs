@@ -0,0 +1,158 @@
+//! Softirqs, the bottom halves of the IRQ handlers.
+//!
+//! An IRQ handler (the top half) runs with IRQs disabled, so it should only
+//! acknowledge the device and [`raise`] a softirq for the rest of the work.
+//! The pending softirqs run on the same CPU when the outermost IRQ handler
+//! returns, with IRQs enabled but preemption still disabled, in the order of
+//! their numbers. A softirq raised again while they run is run again, for at
+//! most [`MAX_RESTART`] rounds; the remaining ones wait for the next IRQ.
+//!
+//! Softirqs don't nest: an IRQ arriving while they run only raises its own
+//! softirq, which is picked up by the running round. Task code sharing data
+//! with a softirq handler keeps it from running with a [`NoSoftirq`] guard.
+
+use handler_table::HandlerTable;
+
+use crate::arch::{disable_irqs, enable_irqs, irqs_enabled};
+
+/// The maximum number of softirqs.
+pub const MAX_SOFTIRQ_COUNT: usize = 8;
+
+/// The softirq of the timers.
+pub const TIMER_SOFTIRQ: usize = 0;
+/// The softirq of the network transmit completions.
+pub const NET_TX_SOFTIRQ: usize = 1;
+/// The softirq of the received network packets.
+pub const NET_RX_SOFTIRQ: usize = 2;
+/// The softirq of the block I/O completions.
+pub const BLOCK_SOFTIRQ: usize = 3;
+
+/// The maximum number of rounds of softirqs run when an IRQ handler returns.
+pub const MAX_RESTART: usize = 10;
+
+/// The type of a softirq handler.
+pub type SoftirqHandler = handler_table::Handler;
+
+static SOFTIRQ_HANDLER_TABLE: HandlerTable<MAX_SOFTIRQ_COUNT> = HandlerTable::new();
+
+/// The bitmask of the softirqs raised on the CPU.
+#[percpu::def_percpu]
+static PENDING: u32 = 0;
+
+/// Whether the CPU is running the softirqs.
+#[percpu::def_percpu]
+static IN_SOFTIRQ: bool = false;
+
+/// The number of [`NoSoftirq`] guards alive on the CPU.
+#[percpu::def_percpu]
+static DISABLE_COUNT: usize = 0;
+
+/// Registers a handler for the softirq `nr`.
+///
+/// It returns `false` if the number is out of range or already has a handler.
+pub fn register_handler(nr: usize, handler: SoftirqHandler) -> bool {
+    if nr < MAX_SOFTIRQ_COUNT && SOFTIRQ_HANDLER_TABLE.register_handler(nr, handler) {
+        return true;
+    }
+    warn!("register handler for softirq {} failed", nr);
+    false
+}
+
+/// Marks the softirq `nr` as pending on the current CPU.
+///
+/// It is usually called by IRQ handlers. Called from task code with IRQs
+/// enabled, the softirq runs right away, unless it is disabled by a
+/// [`NoSoftirq`] guard.
+pub fn raise(nr: usize) {
+    assert!(nr < MAX_SOFTIRQ_COUNT, "invalid softirq {}", nr);
+    let irqs_enabled = irqs_enabled();
+    let _guard = kernel_guard::IrqSave::new();
+    unsafe {
+        PENDING.write_current_raw(PENDING.read_current_raw() | 1 << nr);
+        if irqs_enabled && can_run() {
+            do_softirq();
+        }
+    }
+}
+
+/// Returns whether the current CPU is running the softirqs.
+pub fn in_softirq() -> bool {
+    let _guard = kernel_guard::IrqSave::new();
+    unsafe { IN_SOFTIRQ.read_current_raw() }
+}
+
+/// A guard that keeps the softirqs from running on the current CPU while
+/// alive.
+///
+/// The softirqs raised meanwhile run when the last guard is dropped, if IRQs
+/// are enabled then, or otherwise when the next IRQ handler returns.
+/// Preemption should be disabled too, so that the guard is dropped on the CPU
+/// it was created on.
+pub struct NoSoftirq(());
+
+impl NoSoftirq {
+    /// Disables the softirqs on the current CPU.
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        let _guard = kernel_guard::IrqSave::new();
+        unsafe { DISABLE_COUNT.write_current_raw(DISABLE_COUNT.read_current_raw() + 1) };
+        Self(())
+    }
+}
+
+impl Drop for NoSoftirq {
+    fn drop(&mut self) {
+        let irqs_enabled = irqs_enabled();
+        let _guard = kernel_guard::IrqSave::new();
+        unsafe {
+            DISABLE_COUNT.write_current_raw(DISABLE_COUNT.read_current_raw() - 1);
+            if irqs_enabled && can_run() {
+                do_softirq();
+            }
+        }
+    }
+}
+
+/// Called when leaving an IRQ handler, with IRQs and preemption disabled.
+///
+/// Runs the pending softirqs unless the IRQ interrupted them.
+pub(super) fn irq_exit() {
+    unsafe {
+        if can_run() {
+            do_softirq();
+        }
+    }
+}
+
+/// Whether softirqs are pending and may run. IRQs must be disabled.
+unsafe fn can_run() -> bool {
+    unsafe {
+        PENDING.read_current_raw() != 0
+            && !IN_SOFTIRQ.read_current_raw()
+            && DISABLE_COUNT.read_current_raw() == 0
+    }
+}
+
+/// Runs the pending softirqs. IRQs must be disabled, and stay disabled on
+/// return.
+unsafe fn do_softirq() {
+    let _guard = kernel_guard::NoPreempt::new();
+    unsafe {
+        IN_SOFTIRQ.write_current_raw(true);
+        for _ in 0..MAX_RESTART {
+            let pending = PENDING.read_current_raw();
+            if pending == 0 {
+                break;
+            }
+            PENDING.write_current_raw(0);
+            enable_irqs();
+            for nr in 0..MAX_SOFTIRQ_COUNT {
+                if pending & 1 << nr != 0 && !SOFTIRQ_HANDLER_TABLE.handle(nr) {
+                    warn!("Unhandled softirq {}", nr);
+                }
+            }
+            disable_irqs();
+        }
+        IN_SOFTIRQ.write_current_raw(false);
+    }
+}
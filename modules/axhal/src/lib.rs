@@ -20,6 +20,8 @@
 //! - `fp_simd`: Enable floating-point and SIMD support.
 //! - `paging`: Enable page table manipulation.
 //! - `irq`: Enable interrupt handling support.
+//! - `dma`: Enable the allocation of DMA buffers and their cache maintenance.
+//! - `iommu`: Enable the IOMMU, isolating the DMA of the devices.
 //!
 //! [ArceOS]: https://github.com/arceos-org/arceos
 //! [cargo test]: https://doc.rust-lang.org/cargo/guide/tests.html
@@ -37,14 +39,17 @@ extern crate log;
 #[macro_use]
 extern crate memory_addr;
 
+mod fdt;
 mod platform;
 
 #[macro_use]
 pub mod trap;
 
 pub mod arch;
+pub mod console;
 pub mod cpu;
 pub mod mem;
+pub mod random;
 pub mod time;
 
 #[cfg(feature = "tls")]
@@ -56,10 +61,11 @@ pub mod irq;
 #[cfg(feature = "paging")]
 pub mod paging;
 
-/// Console input and output.
-pub mod console {
-    pub use super::platform::console::*;
-}
+#[cfg(feature = "dma")]
+pub mod dma;
+
+#[cfg(feature = "iommu")]
+pub mod iommu;
 
 /// Miscellaneous operation, e.g. terminate the system.
 pub mod misc {
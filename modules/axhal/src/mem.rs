@@ -3,6 +3,12 @@
 use core::fmt;
 
 use axconfig::plat::{PHYS_MEMORY_BASE, PHYS_MEMORY_SIZE, PHYS_VIRT_OFFSET};
+use kspin::SpinNoIrq;
+
+use self::memblock::MemBlock;
+use crate::fdt::Fdt;
+
+mod memblock;
 
 #[doc(no_inline)]
 pub use memory_addr::{MemoryAddr, PAGE_SIZE_4K, PhysAddr, VirtAddr};
@@ -46,6 +52,40 @@ pub struct MemRegion {
     pub name: &'static str,
 }
 
+/// The size of each region of [`dma_zone_region`] unless it's set at build
+/// time.
+#[cfg(feature = "paging")]
+const DEFAULT_DMA_ZONE_SIZE: usize = 1 << 20;
+
+/// The maximum number of memory regions added after boot.
+const MAX_HOTPLUG_REGIONS: usize = 16;
+
+static HOTPLUG_REGIONS: SpinNoIrq<[Option<(PhysAddr, usize)>; MAX_HOTPLUG_REGIONS]> =
+    SpinNoIrq::new([None; MAX_HOTPLUG_REGIONS]);
+
+/// The boot-time memory manager, see [`init_memblock`].
+static MEMBLOCK: SpinNoIrq<MemBlock> = SpinNoIrq::new(MemBlock::new());
+
+/// The region of the contiguous memory allocator (CMA), one of the reserved
+/// regions.
+static CMA_REGION: SpinNoIrq<Option<(PhysAddr, usize)>> = SpinNoIrq::new(None);
+
+/// The end of the physical memory the devices with 24-bit DMA addresses (e.g.
+/// ISA ones) can access.
+pub const DMA_ZONE_LIMIT: usize = 16 << 20;
+
+/// The end of the physical memory the devices with 32-bit DMA addresses can
+/// access.
+pub const DMA32_ZONE_LIMIT: usize = 4 << 30;
+
+/// The regions kept for the allocations below [`DMA_ZONE_LIMIT`] and
+/// [`DMA32_ZONE_LIMIT`], two of the reserved regions.
+#[cfg(feature = "paging")]
+static DMA_ZONE_REGIONS: SpinNoIrq<[Option<(PhysAddr, usize)>; 2]> = SpinNoIrq::new([None; 2]);
+
+/// The initial RAM disk loaded by the bootloader, one of the reserved regions.
+static INITRD: SpinNoIrq<Option<(PhysAddr, usize)>> = SpinNoIrq::new(None);
+
 /// Converts a virtual address to a physical address.
 ///
 /// It assumes that there is a linear mapping with the offset
@@ -69,8 +109,308 @@ pub const fn phys_to_virt(paddr: PhysAddr) -> VirtAddr {
 }
 
 /// Returns an iterator over all physical memory regions.
+///
+/// The free memory is the RAM found by [`init_memblock`] without the
+/// reservations, which are listed as reserved regions, and the regions
+/// registered by [`add_hotplug_region`] come last.
 pub fn memory_regions() -> impl Iterator<Item = MemRegion> {
-    kernel_image_regions().chain(crate::platform::mem::platform_regions())
+    let mb = *MEMBLOCK.lock();
+    let free = mb.free_ranges().into_iter().flatten().map(|b| MemRegion {
+        paddr: pa!(b.base),
+        size: b.end - b.base,
+        flags: MemRegionFlags::FREE | MemRegionFlags::READ | MemRegionFlags::WRITE,
+        name: b.name,
+    });
+    let reserved = mb
+        .reserved_ranges()
+        .into_iter()
+        .flatten()
+        .map(|b| MemRegion {
+            paddr: pa!(b.base),
+            size: b.end - b.base,
+            flags: MemRegionFlags::RESERVED | MemRegionFlags::READ | MemRegionFlags::WRITE,
+            name: b.name,
+        });
+    kernel_image_regions()
+        .chain(free)
+        .chain(device_regions())
+        .chain(reserved)
+        .chain(hotplug_regions())
+}
+
+/// Initializes the boot-time memory manager, which tracks the RAM and the
+/// reservations in it until the global allocator is initialized, and sets up
+/// the region of the contiguous memory allocator (CMA).
+///
+/// The RAM is the kernel image and the free regions of the platform. The
+/// reservations are the kernel image, the device tree blob at `dtb` (a
+/// physical address, or 0 if there is none) itself, the entries of its
+/// memory reservation block, the children of its `/reserved-memory` node
+/// with a `reg` property, and the initial RAM disk given in its `/chosen`
+/// node, see [`initrd`]. Overlaps between them are reported, as well as
+/// device regions in the RAM.
+///
+/// The CMA region is the reusable `shared-dma-pool` child of
+/// `/reserved-memory`, either static or dynamically allocated with `size`
+/// and `alignment`. If there is none, a region of `AX_CMA_SIZE` bytes (set
+/// at build time, e.g. `16M`) is allocated at the end of the free memory.
+/// With the `paging` feature, the regions of [`dma_zone_region`] are then
+/// allocated at the end of the free memory below their limits.
+///
+/// It must be called before [`memory_regions`] and [`early_alloc`], usually
+/// at the very beginning of the boot.
+pub fn init_memblock(dtb: usize) {
+    let mut mb = MEMBLOCK.lock();
+    let kernel_start = virt_to_phys((_skernel as usize).into()).as_usize();
+    let kernel_end = virt_to_phys((_ekernel as usize).into()).as_usize();
+    mb.add_memory(kernel_start, kernel_end, memblock::KERNEL);
+    mb.reserve(kernel_start, kernel_end, memblock::KERNEL);
+    for r in crate::platform::mem::platform_regions() {
+        if r.flags.contains(MemRegionFlags::FREE) {
+            mb.add_memory(r.paddr.as_usize(), (r.paddr + r.size).as_usize(), r.name);
+        }
+    }
+    for r in device_regions() {
+        let (start, end) = (r.paddr.as_usize(), (r.paddr + r.size).as_usize());
+        for (base, ram_end) in mb.ram_ranges().filter(|&(b, e)| b < end && start < e) {
+            warn!(
+                "memblock: {} [{:#x}, {:#x}) overlaps RAM [{:#x}, {:#x})",
+                r.name, start, end, base, ram_end
+            );
+        }
+    }
+
+    let mut cma_size = option_env!("AX_CMA_SIZE")
+        .and_then(parse_size)
+        .map(|size| (size, PAGE_SIZE_4K));
+    if let Some(fdt) = early_fdt(dtb) {
+        mb.reserve(dtb, dtb + fdt.total_size(), "dtb");
+        for (paddr, size) in fdt.mem_reservations() {
+            mb.reserve(paddr, paddr + size, "memreserve");
+        }
+        if let Some((start, end)) = fdt.initrd() {
+            mb.reserve(start, end, "initrd");
+            *INITRD.lock() = Some((pa!(start), end - start));
+        }
+        fdt.reserved_memory(|node| match node.reg {
+            Some((paddr, size)) if node.is_cma() && CMA_REGION.lock().is_none() => {
+                if mb.is_free(paddr, paddr + size) {
+                    mb.reserve(paddr, paddr + size, "cma");
+                    *CMA_REGION.lock() = Some((pa!(paddr), size));
+                } else {
+                    warn!("CMA region [{:#x}, {:#x}) is not free", paddr, paddr + size);
+                }
+            }
+            Some((paddr, size)) => {
+                mb.reserve(paddr, paddr + size, "reserved-memory");
+            }
+            None if node.is_cma() => {
+                if let Some(size) = node.size {
+                    cma_size = Some((size, node.alignment.unwrap_or(PAGE_SIZE_4K)));
+                }
+            }
+            // dynamically allocated regions for other drivers are not supported
+            None => {}
+        });
+    }
+
+    let cma = *CMA_REGION.lock();
+    if let (None, Some((size, align))) = (cma, cma_size) {
+        let size = size.align_up_4k();
+        match mb.alloc(size, align.next_power_of_two(), "cma") {
+            Some(paddr) => *CMA_REGION.lock() = Some((pa!(paddr), size)),
+            None => warn!("no free memory for a CMA region of {:#x} bytes", size),
+        }
+    }
+
+    // the low memory first, as the DMA32 region may be taken from it
+    #[cfg(feature = "paging")]
+    for (i, limit, size, name) in [
+        (
+            0,
+            DMA_ZONE_LIMIT,
+            option_env!("AX_DMA_ZONE_SIZE"),
+            "dma-zone",
+        ),
+        (
+            1,
+            DMA32_ZONE_LIMIT,
+            option_env!("AX_DMA32_ZONE_SIZE"),
+            "dma32-zone",
+        ),
+    ] {
+        let size = size.map_or(Some(DEFAULT_DMA_ZONE_SIZE), parse_size);
+        if let Some(size) = size.filter(|&size| size > 0).map(|size| size.align_up_4k()) {
+            match mb.alloc_below(size, PAGE_SIZE_4K, limit, name) {
+                Some(paddr) => DMA_ZONE_REGIONS.lock()[i] = Some((pa!(paddr), size)),
+                None => debug!("no free memory below {:#x} for {}", limit, name),
+            }
+        }
+    }
+}
+
+/// Allocates `size` bytes of physical memory aligned to `align`, before the
+/// global allocator is initialized (e.g. for the page tables or buffers that
+/// are needed early).
+///
+/// The memory is taken at the end of the free memory, and is reserved as
+/// `name`, so it's never given to the global allocator. Returns `None` if
+/// there is not enough memory, or after [`finish_early_alloc`].
+pub fn early_alloc(size: usize, align: usize, name: &'static str) -> Option<PhysAddr> {
+    MEMBLOCK.lock().alloc(size, align, name).map(PhysAddr::from)
+}
+
+/// Ends the early allocations by [`early_alloc`], called before the free
+/// memory is given to the global allocator.
+pub fn finish_early_alloc() {
+    MEMBLOCK.lock().freeze();
+}
+
+/// Writes the memory map in the format of `/proc/iomem` on Linux: the RAM
+/// and the device regions, each followed by the reservations in it.
+pub fn write_iomem(w: &mut dyn fmt::Write) -> fmt::Result {
+    let mb = *MEMBLOCK.lock();
+    let ram = "System RAM";
+    let top_regions = || {
+        mb.ram_ranges()
+            .map(move |(base, end)| (base, end, ram))
+            .chain(
+                hotplug_regions()
+                    .map(move |r| (r.paddr.as_usize(), (r.paddr + r.size).as_usize(), ram)),
+            )
+            .chain(
+                device_regions()
+                    .map(|r| (r.paddr.as_usize(), (r.paddr + r.size).as_usize(), r.name)),
+            )
+    };
+    let mut last = None;
+    while let Some(region) = top_regions().filter(|r| last.is_none_or(|l| *r > l)).min() {
+        let (base, end, name) = region;
+        writeln!(w, "{:08x}-{:08x} : {}", base, end - 1, name)?;
+        if name == ram {
+            for r in mb.reserved().filter(|r| r.base < end && base < r.end) {
+                writeln!(
+                    w,
+                    "  {:08x}-{:08x} : {}",
+                    r.base.max(base),
+                    r.end.min(end) - 1,
+                    r.name
+                )?;
+            }
+        }
+        last = Some(region);
+    }
+    Ok(())
+}
+
+/// Returns the region of the contiguous memory allocator (CMA), if any.
+///
+/// It's one of the reserved regions, to be given to the CMA allocator rather
+/// than to the global allocator.
+pub fn cma_region() -> Option<(PhysAddr, usize)> {
+    *CMA_REGION.lock()
+}
+
+/// Returns the region kept for the allocations of the physical memory below
+/// `limit`, [`DMA_ZONE_LIMIT`] or [`DMA32_ZONE_LIMIT`], if any.
+///
+/// It's one of the reserved regions, to be given to the allocator of the
+/// frames of the zone rather than to the global allocator, so that the
+/// devices which only access the low memory always find some. Its size is
+/// `AX_DMA_ZONE_SIZE` or `AX_DMA32_ZONE_SIZE` (set at build time, e.g. `1M`,
+/// and 1 MiB by default), and there is none if there is not enough free
+/// memory below `limit`.
+#[cfg(feature = "paging")]
+pub fn dma_zone_region(limit: usize) -> Option<(PhysAddr, usize)> {
+    match limit {
+        DMA_ZONE_LIMIT => DMA_ZONE_REGIONS.lock()[0],
+        DMA32_ZONE_LIMIT => DMA_ZONE_REGIONS.lock()[1],
+        _ => None,
+    }
+}
+
+/// Returns the initial RAM disk loaded by the bootloader (e.g. by QEMU with
+/// `-initrd`), as its physical address and size, if any.
+///
+/// It's found in the device tree by [`init_memblock`], and reserved so that
+/// it's kept intact.
+pub fn initrd() -> Option<(PhysAddr, usize)> {
+    *INITRD.lock()
+}
+
+/// Returns the device tree at the physical address `dtb`, if it's in the
+/// physical memory, which is mapped by the boot page table.
+fn early_fdt(dtb: usize) -> Option<Fdt> {
+    let mem = PHYS_MEMORY_BASE..PHYS_MEMORY_BASE + PHYS_MEMORY_SIZE;
+    if dtb == 0 || !mem.contains(&dtb) || !mem.contains(&(dtb + 64)) {
+        return None;
+    }
+    let fdt = unsafe { Fdt::from_ptr(phys_to_virt(pa!(dtb)).as_ptr()) }?;
+    mem.contains(&(dtb + fdt.total_size() - 1)).then_some(fdt)
+}
+
+/// Parses a size like `16M`, `0x1000` or `65536`.
+fn parse_size(s: &str) -> Option<usize> {
+    let (num, shift) = match s.as_bytes().last()? {
+        b'K' | b'k' => (&s[..s.len() - 1], 10),
+        b'M' | b'm' => (&s[..s.len() - 1], 20),
+        b'G' | b'g' => (&s[..s.len() - 1], 30),
+        _ => (s, 0),
+    };
+    let n = match num.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16).ok()?,
+        None => num.parse().ok()?,
+    };
+    Some(n << shift)
+}
+
+/// Returns the regions of the platform that are not free memory, e.g. the
+/// MMIO regions.
+fn device_regions() -> impl Iterator<Item = MemRegion> {
+    crate::platform::mem::platform_regions().filter(|r| !r.flags.contains(MemRegionFlags::FREE))
+}
+
+/// Registers a region of RAM discovered after boot (memory hotplug, e.g.
+/// by virtio-mem or a device tree overlay), so that it's listed by
+/// [`memory_regions`].
+///
+/// It only records the region: mapping it and giving it to the allocator is
+/// up to the caller. Returns `false` if too many regions were registered.
+pub fn add_hotplug_region(paddr: PhysAddr, size: usize) -> bool {
+    let mut regions = HOTPLUG_REGIONS.lock();
+    match regions.iter_mut().find(|r| r.is_none()) {
+        Some(slot) => {
+            *slot = Some((paddr, size));
+            true
+        }
+        None => false,
+    }
+}
+
+/// Unregisters the region starting at `paddr` registered by
+/// [`add_hotplug_region`].
+pub fn remove_hotplug_region(paddr: PhysAddr) {
+    let mut regions = HOTPLUG_REGIONS.lock();
+    if let Some(slot) = regions
+        .iter_mut()
+        .find(|r| matches!(r, Some((start, _)) if *start == paddr))
+    {
+        *slot = None;
+    }
+}
+
+/// Returns the memory regions registered after boot.
+fn hotplug_regions() -> impl Iterator<Item = MemRegion> {
+    let regions = *HOTPLUG_REGIONS.lock();
+    regions
+        .into_iter()
+        .flatten()
+        .map(|(paddr, size)| MemRegion {
+            paddr,
+            size,
+            flags: MemRegionFlags::FREE | MemRegionFlags::READ | MemRegionFlags::WRITE,
+            name: "hotplug memory",
+        })
 }
 
 /// Returns the memory regions of the kernel image (code and data sections).
@@ -147,6 +487,7 @@ pub(crate) fn clear_bss() {
 }
 
 unsafe extern "C" {
+    fn _skernel();
     fn _stext();
     fn _etext();
     fn _srodata();
@@ -0,0 +1,293 @@
+//! The boot-time memory manager, after Linux's memblock.
+//!
+//! Before the global allocator is initialized, it tracks the RAM, and the
+//! reservations in it: the kernel image, the device tree, the firmware
+//! regions, and the early allocations. The reservations are kept as they
+//! are given, and only rounded to pages when the free memory is computed.
+//!
+//! Overlaps are reported as they're found, as they are usually mistakes in
+//! the memory map that corrupt memory later: RAM regions given twice, a
+//! reservation in the kernel image, or two reservations of the same memory.
+
+use memory_addr::{PAGE_SIZE_4K, align_down_4k, align_up_4k};
+
+/// The maximum number of blocks of RAM, and of reservations.
+const MAX_BLOCKS: usize = 32;
+/// The maximum number of free ranges.
+pub(super) const MAX_FREE_RANGES: usize = 2 * MAX_BLOCKS;
+
+/// The name of the reservation of the kernel image.
+pub(super) const KERNEL: &str = "kernel";
+
+/// A range of physical memory `[base, end)`.
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Block {
+    pub base: usize,
+    pub end: usize,
+    pub name: &'static str,
+}
+
+impl Block {
+    const EMPTY: Self = Self {
+        base: 0,
+        end: 0,
+        name: "",
+    };
+
+    fn overlaps(&self, base: usize, end: usize) -> bool {
+        self.base < end && base < self.end
+    }
+}
+
+/// Blocks sorted by their base address.
+#[derive(Clone, Copy)]
+struct Blocks {
+    blocks: [Block; MAX_BLOCKS],
+    len: usize,
+}
+
+impl Blocks {
+    const fn new() -> Self {
+        Self {
+            blocks: [Block::EMPTY; MAX_BLOCKS],
+            len: 0,
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &Block> {
+        self.blocks[..self.len].iter()
+    }
+
+    fn insert(&mut self, block: Block) -> bool {
+        if self.len == MAX_BLOCKS {
+            warn!(
+                "memblock: too many blocks, {} [{:#x}, {:#x}) ignored",
+                block.name, block.base, block.end
+            );
+            return false;
+        }
+        let pos = self.iter().position(|b| b.base > block.base);
+        let pos = pos.unwrap_or(self.len);
+        self.blocks.copy_within(pos..self.len, pos + 1);
+        self.blocks[pos] = block;
+        self.len += 1;
+        true
+    }
+}
+
+/// The RAM and the reservations in it.
+#[derive(Clone, Copy)]
+pub(super) struct MemBlock {
+    memory: Blocks,
+    reserved: Blocks,
+    /// Set once the free memory is given to the global allocator, after
+    /// which nothing can be allocated here.
+    frozen: bool,
+}
+
+impl MemBlock {
+    pub const fn new() -> Self {
+        Self {
+            memory: Blocks::new(),
+            reserved: Blocks::new(),
+            frozen: false,
+        }
+    }
+
+    /// Returns the blocks of RAM.
+    pub fn memory(&self) -> impl Iterator<Item = &Block> {
+        self.memory.iter()
+    }
+
+    /// Returns the ranges of RAM `(base, end)`, with the adjacent blocks
+    /// merged.
+    pub fn ram_ranges(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let mut blocks = self.memory().peekable();
+        core::iter::from_fn(move || {
+            let first = blocks.next()?;
+            let mut end = first.end;
+            while let Some(b) = blocks.next_if(|b| b.base == end) {
+                end = b.end;
+            }
+            Some((first.base, end))
+        })
+    }
+
+    /// Returns the reservations.
+    pub fn reserved(&self) -> impl Iterator<Item = &Block> {
+        self.reserved.iter()
+    }
+
+    /// Forbids the early allocations, once the free memory is given to the
+    /// global allocator.
+    pub fn freeze(&mut self) {
+        self.frozen = true;
+    }
+
+    /// Adds the RAM `[base, end)`. The parts already added are reported and
+    /// ignored, so that no memory is given twice to the allocator.
+    pub fn add_memory(&mut self, base: usize, end: usize, name: &'static str) {
+        let mut cur = base;
+        let old = self.memory;
+        for b in old.iter().filter(|b| b.overlaps(base, end)) {
+            warn!(
+                "memblock: RAM {} [{:#x}, {:#x}) overlaps {} [{:#x}, {:#x})",
+                name, base, end, b.name, b.base, b.end
+            );
+            if cur < b.base {
+                self.memory.insert(Block {
+                    base: cur,
+                    end: b.base,
+                    name,
+                });
+            }
+            cur = cur.max(b.end);
+        }
+        if cur < end {
+            self.memory.insert(Block {
+                base: cur,
+                end,
+                name,
+            });
+        }
+    }
+
+    /// Reserves `[base, end)`, reporting the reservations it overlaps.
+    pub fn reserve(&mut self, base: usize, end: usize, name: &'static str) -> bool {
+        if base >= end {
+            return false;
+        }
+        for b in self.reserved.iter().filter(|b| b.overlaps(base, end)) {
+            if b.name == KERNEL || name == KERNEL {
+                error!(
+                    "memblock: {} [{:#x}, {:#x}) overlaps the kernel image [{:#x}, {:#x})",
+                    name, base, end, b.base, b.end
+                );
+            } else {
+                warn!(
+                    "memblock: {} [{:#x}, {:#x}) overlaps {} [{:#x}, {:#x})",
+                    name, base, end, b.name, b.base, b.end
+                );
+            }
+        }
+        debug!("memblock: reserve {} [{:#x}, {:#x})", name, base, end);
+        self.reserved.insert(Block { base, end, name })
+    }
+
+    /// Returns `true` if `[base, end)` is in the RAM, and not reserved.
+    pub fn is_free(&self, base: usize, end: usize) -> bool {
+        self.free_ranges()
+            .iter()
+            .flatten()
+            .any(|r| r.base <= base && end <= r.end)
+    }
+
+    /// Allocates `size` bytes aligned to `align` (a power of two), at the
+    /// highest free address, and reserves them as `name`.
+    pub fn alloc(&mut self, size: usize, align: usize, name: &'static str) -> Option<usize> {
+        self.alloc_below(size, align, usize::MAX, name)
+    }
+
+    /// Allocates `size` bytes aligned to `align` (a power of two), at the
+    /// highest free address below `limit`, and reserves them as `name`.
+    pub fn alloc_below(
+        &mut self,
+        size: usize,
+        align: usize,
+        limit: usize,
+        name: &'static str,
+    ) -> Option<usize> {
+        if self.frozen {
+            warn!("memblock: {} allocated after the allocator is up", name);
+            return None;
+        }
+        let size = align_up_4k(size);
+        let align = align.max(PAGE_SIZE_4K);
+        let base = self
+            .free_ranges()
+            .iter()
+            .flatten()
+            .filter_map(|r| {
+                let base = r.end.min(limit).checked_sub(size)? & !(align - 1);
+                (base >= r.base).then_some(base)
+            })
+            .max()?;
+        self.reserve(base, base + size, name);
+        Some(base)
+    }
+
+    /// Returns the free memory: the RAM without the reservations rounded to
+    /// pages, sorted by address.
+    pub fn free_ranges(&self) -> [Option<Block>; MAX_FREE_RANGES] {
+        let mut ranges = [None; MAX_FREE_RANGES];
+        let mut n = 0;
+        for m in self.memory() {
+            let mut cur = align_up_4k(m.base);
+            let end = align_down_4k(m.end);
+            for r in self.reserved() {
+                let (r_base, r_end) = (align_down_4k(r.base), align_up_4k(r.end));
+                if r_base >= end || r_end <= cur {
+                    continue;
+                }
+                if r_base > cur && n < MAX_FREE_RANGES {
+                    ranges[n] = Some(Block {
+                        base: cur,
+                        end: r_base,
+                        name: m.name,
+                    });
+                    n += 1;
+                }
+                cur = cur.max(r_end);
+            }
+            if cur < end && n < MAX_FREE_RANGES {
+                ranges[n] = Some(Block {
+                    base: cur,
+                    end,
+                    name: m.name,
+                });
+                n += 1;
+            }
+        }
+        ranges
+    }
+
+    /// Returns the reservations in the RAM rounded to pages, without the
+    /// kernel image, and merged so that they don't overlap.
+    pub fn reserved_ranges(&self) -> [Option<Block>; MAX_FREE_RANGES] {
+        let mut ranges = [None; MAX_FREE_RANGES];
+        let mut n = 0;
+        let kernel = self
+            .reserved()
+            .find(|b| b.name == KERNEL)
+            .map(|b| (align_down_4k(b.base), align_up_4k(b.end)));
+        let mut covered = 0;
+        for r in self.reserved().filter(|b| b.name != KERNEL) {
+            let base = align_down_4k(r.base).max(covered);
+            let end = align_up_4k(r.end);
+            if base >= end {
+                continue;
+            }
+            covered = end;
+            // the kernel image is mapped by sections
+            let parts = match kernel {
+                Some((k_base, k_end)) if k_base < end && base < k_end => {
+                    [(base, k_base.max(base)), (k_end.min(end), end)]
+                }
+                _ => [(base, end), (end, end)],
+            };
+            for (base, end) in parts.into_iter().filter(|(base, end)| base < end) {
+                for m in self.memory().filter(|m| m.overlaps(base, end)) {
+                    if n < MAX_FREE_RANGES {
+                        ranges[n] = Some(Block {
+                            base: base.max(align_up_4k(m.base)),
+                            end: end.min(align_down_4k(m.end)),
+                            name: r.name,
+                        });
+                        n += 1;
+                    }
+                }
+            }
+        }
+        ranges
+    }
+}
@@ -32,6 +32,11 @@ pub fn epochoffset_nanos() -> u64 {
     unsafe { RTC_EPOCHOFFSET_NANOS }
 }
 
+/// Sets the epoch offset in nanoseconds.
+pub fn set_epochoffset_nanos(nanos: u64) {
+    unsafe { RTC_EPOCHOFFSET_NANOS = nanos }
+}
+
 /// Set a one-shot timer.
 ///
 /// A timer interrupt will be triggered at the specified monotonic time deadline (in nanoseconds).
@@ -76,6 +81,62 @@ pub(crate) fn init_early() {
     }
 }
 
+/// Sets the RTC alarm to go off at the given wall time, in seconds since the
+/// epoch. The RTC raises its interrupt when the alarm goes off.
+///
+/// Returns `false` if there's no PL031 RTC on the platform.
+#[cfg(feature = "rtc")]
+pub fn set_rtc_alarm(epoch_secs: u64) -> bool {
+    if axconfig::devices::RTC_PADDR == 0 {
+        return false;
+    }
+    unsafe {
+        pl031_write(PL031_ICR, 1);
+        pl031_write(PL031_MR, epoch_secs as u32);
+        pl031_write(PL031_IMSC, 1);
+    }
+    true
+}
+
+/// Writes the given wall time, in seconds since the epoch, to the RTC.
+///
+/// Returns `false` if there's no PL031 RTC on the platform.
+#[cfg(feature = "rtc")]
+pub fn set_rtc_time(epoch_secs: u64) -> bool {
+    if axconfig::devices::RTC_PADDR == 0 {
+        return false;
+    }
+    unsafe { pl031_write(PL031_LR, epoch_secs as u32) };
+    true
+}
+
+/// Disarms the RTC alarm, and acknowledges its interrupt.
+#[cfg(feature = "rtc")]
+pub fn clear_rtc_alarm() {
+    if axconfig::devices::RTC_PADDR != 0 {
+        unsafe {
+            pl031_write(PL031_IMSC, 0);
+            pl031_write(PL031_ICR, 1);
+        }
+    }
+}
+
+#[cfg(feature = "rtc")]
+const PL031_MR: usize = 0x04; // Match register
+#[cfg(feature = "rtc")]
+const PL031_LR: usize = 0x08; // Load register
+#[cfg(feature = "rtc")]
+const PL031_IMSC: usize = 0x10; // Interrupt mask set and clear register
+#[cfg(feature = "rtc")]
+const PL031_ICR: usize = 0x1c; // Interrupt clear register
+
+#[cfg(feature = "rtc")]
+unsafe fn pl031_write(offset: usize, value: u32) {
+    use crate::mem::phys_to_virt;
+    let base = phys_to_virt(pa!(axconfig::devices::RTC_PADDR)).as_usize();
+    unsafe { core::ptr::write_volatile((base + offset) as *mut u32, value) };
+}
+
 pub(crate) fn init_percpu() {
     #[cfg(feature = "irq")]
     {
@@ -1,7 +1,9 @@
 use crate::{irq::IrqHandler, mem::phys_to_virt};
-use arm_gicv2::{GicCpuInterface, GicDistributor, InterruptType, translate_irq};
-use axconfig::devices::{GICC_PADDR, GICD_PADDR, UART_IRQ};
+use arm_gicv2::{GicCpuInterface, GicDistributor, InterruptType, TriggerMode, translate_irq};
+use axconfig::devices::{GICC_PADDR, GICD_PADDR, GICV2M_PADDR, UART_IRQ};
+use core::ops::Range;
 use kspin::SpinNoIrq;
+use lazyinit::LazyInit;
 use memory_addr::PhysAddr;
 
 /// The maximum number of IRQs.
@@ -10,11 +12,29 @@ pub const MAX_IRQ_COUNT: usize = 1024;
 /// The timer IRQ number.
 pub const TIMER_IRQ_NUM: usize = translate_irq(14, InterruptType::PPI).unwrap();
 
+/// The IRQ number of the IPIs, the SGI 1.
+#[cfg(feature = "smp")]
+pub(crate) const IPI_IRQ_NUM: usize = translate_irq(1, InterruptType::SGI).unwrap();
+
 /// The UART IRQ number.
 pub const UART_IRQ_NUM: usize = translate_irq(UART_IRQ, InterruptType::SPI).unwrap();
 
 const GICD_BASE: PhysAddr = pa!(GICD_PADDR);
 const GICC_BASE: PhysAddr = pa!(GICC_PADDR);
+const GICV2M_BASE: PhysAddr = pa!(GICV2M_PADDR);
+
+/// The register of the distributor generating the SGIs.
+#[cfg(feature = "smp")]
+const GICD_SGIR: usize = 0xf00;
+
+/// The registers of the distributor routing the SPIs to the CPUs, a byte per
+/// IRQ.
+const GICD_ITARGETSR: usize = 0x800;
+
+/// The register of the GICv2m frame reporting its SPIs.
+const V2M_MSI_TYPER: usize = 0x008;
+/// The register of the GICv2m frame the MSIs are written to.
+const V2M_MSI_SETSPI_NS: usize = 0x040;
 
 static GICD: SpinNoIrq<GicDistributor> =
     SpinNoIrq::new(GicDistributor::new(phys_to_virt(GICD_BASE).as_mut_ptr()));
@@ -22,6 +42,9 @@ static GICD: SpinNoIrq<GicDistributor> =
 // per-CPU, no lock
 static GICC: GicCpuInterface = GicCpuInterface::new(phys_to_virt(GICC_BASE).as_mut_ptr());
 
+/// The SPIs of the GICv2m frame, turned into IRQ numbers.
+static MSI_IRQS: LazyInit<Range<usize>> = LazyInit::new();
+
 /// Enables or disables the given IRQ.
 pub fn set_enable(irq_num: usize, enabled: bool) {
     trace!("GICD set enable: {} {}", irq_num, enabled);
@@ -37,6 +60,38 @@ pub fn register_handler(irq_num: usize, handler: IrqHandler) -> bool {
     crate::irq::register_handler_common(irq_num, handler)
 }
 
+/// Sends an IPI to the CPU `cpu_id` (its CPU interface number).
+#[cfg(feature = "smp")]
+pub(crate) fn send_ipi(cpu_id: usize) {
+    let sgir_ptr = (phys_to_virt(GICD_BASE) + GICD_SGIR).as_mut_ptr() as *mut u32;
+    // the target list is in bits 16..24
+    let sgir = (1 << (16 + cpu_id)) | IPI_IRQ_NUM as u32;
+    unsafe {
+        // make the data of the IPI visible before it's sent
+        core::arch::asm!("dsb ishst");
+        sgir_ptr.write_volatile(sgir);
+    }
+}
+
+/// Returns the IRQs allocatable to MSIs, the SPIs of the GICv2m frame.
+pub(crate) fn msi_irqs() -> Range<usize> {
+    MSI_IRQS.start..MSI_IRQS.end
+}
+
+/// Returns the MSI message raising `irq`: its number written to the GICv2m
+/// frame.
+///
+/// The SPI is routed to the CPU `cpu_id` (its CPU interface number) by the
+/// distributor, the message is the same for all CPUs.
+pub(crate) fn msi_message(irq: usize, cpu_id: usize) -> crate::irq::msi::MsiMessage {
+    let target_ptr = (phys_to_virt(GICD_BASE) + GICD_ITARGETSR + irq).as_mut_ptr();
+    unsafe { target_ptr.write_volatile(1 << cpu_id) };
+    crate::irq::msi::MsiMessage {
+        address: (GICV2M_PADDR + V2M_MSI_SETSPI_NS) as u64,
+        data: irq as u32,
+    }
+}
+
 /// Dispatches the IRQ.
 ///
 /// This function is called by the common interrupt handler. It looks
@@ -51,10 +106,32 @@ pub(crate) fn init_primary() {
     info!("Initialize GICv2...");
     GICD.lock().init();
     GICC.init();
+    init_v2m();
+}
+
+/// Finds the SPIs of the GICv2m frame, if any, and makes them edge-triggered
+/// as MSIs are.
+fn init_v2m() {
+    if GICV2M_PADDR == 0 {
+        MSI_IRQS.init_once(0..0);
+        return;
+    }
+    let typer_ptr = (phys_to_virt(GICV2M_BASE) + V2M_MSI_TYPER).as_usize() as *const u32;
+    let typer = unsafe { typer_ptr.read_volatile() } as usize;
+    let base = (typer >> 16) & 0x3ff;
+    let count = typer & 0x3ff;
+    info!("GICv2m: {} SPIs for MSIs from {}", count, base);
+    let mut gicd = GICD.lock();
+    for irq in base..base + count {
+        gicd.configure_interrupt(irq, TriggerMode::Edge);
+    }
+    MSI_IRQS.init_once(base..base + count);
 }
 
 /// Initializes GICC on secondary CPUs.
 #[cfg(feature = "smp")]
 pub(crate) fn init_secondary() {
     GICC.init();
+    // the enable bits of the SGIs are banked per CPU
+    GICD.lock().set_enable(IPI_IRQ_NUM, true);
 }
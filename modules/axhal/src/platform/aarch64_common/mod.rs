@@ -7,5 +7,8 @@ pub mod psci;
 #[cfg(feature = "irq")]
 pub mod gic;
 
+#[cfg(all(feature = "iommu", platform_family = "aarch64-qemu-virt"))]
+pub mod smmuv3;
+
 #[cfg(not(platform_family = "aarch64-bsta1000b"))]
 pub mod pl011;
@@ -0,0 +1,271 @@
+//! Arm SMMUv3 (System Memory Management Unit), translating the DMA by stage 2
+//! page tables.
+//!
+//! The stream IDs index a linear stream table, whose entries (STEs) give the
+//! VMID, the ID of the domain, and the stage 2 page table of the devices. The
+//! configuration and the TLBs are invalidated by the commands written to the
+//! command queue. There is no event queue, the faults are not reported.
+
+use core::arch::asm;
+
+use kspin::SpinNoIrq;
+use lazyinit::LazyInit;
+use memory_addr::{PhysAddr, VirtAddr};
+
+use crate::arch::clean_dcache_range;
+use crate::iommu::format::smmuv3::{self, S2_LEVELS, STE_VALID};
+use crate::iommu::{DeviceId, IommuError, IommuFlags, IommuHw, IommuResult, alloc_frames};
+use crate::mem::{PAGE_SIZE_4K, phys_to_virt};
+
+const IDR0: usize = 0x00;
+const IDR1: usize = 0x04;
+const IDR5: usize = 0x14;
+const CR0: usize = 0x20;
+const CR0ACK: usize = 0x24;
+const CR1: usize = 0x28;
+const STRTAB_BASE: usize = 0x80;
+const STRTAB_BASE_CFG: usize = 0x88;
+const CMDQ_BASE: usize = 0x90;
+const CMDQ_PROD: usize = 0x98;
+const CMDQ_CONS: usize = 0x9c;
+
+const IDR0_S2P: u32 = 1 << 0;
+const IDR0_COHACC: u32 = 1 << 4;
+const IDR0_VMID16: u32 = 1 << 18;
+const IDR5_GRAN4K: u32 = 1 << 4;
+
+const CR0_SMMUEN: u32 = 1 << 0;
+const CR0_CMDQEN: u32 = 1 << 3;
+/// The tables and the queues are inner shareable and write-back cacheable.
+const CR1_WBACK_ISH: u32 = 0b11_01_01_11_01_01;
+
+/// Read-allocate hint of the table and queue bases.
+const BASE_RA: u64 = 1 << 62;
+
+/// The maximum log2 of the number of the STEs, 4096 STEs of 64 bytes for the
+/// PCI functions on the first 16 buses.
+const MAX_STRTAB_LOG2: u32 = 12;
+/// The maximum log2 of the number of the commands, 256 commands of 16 bytes.
+const MAX_CMDQ_LOG2: u32 = 8;
+
+struct CmdQueue {
+    base: VirtAddr,
+    log2: u32,
+    /// The index of the next command, with the wrap bit above it.
+    prod: u32,
+}
+
+struct Smmu {
+    regs: VirtAddr,
+    /// Whether the accesses to the tables and the queues are coherent with
+    /// the caches.
+    coherent: bool,
+    /// The output address size of stage 2, in the encoding of `S2PS`.
+    s2ps: u64,
+    max_domains: usize,
+    strtab: VirtAddr,
+    strtab_log2: u32,
+    /// The command queue, locked while the STEs are changed.
+    cmdq: SpinNoIrq<CmdQueue>,
+}
+
+static SMMU: LazyInit<Smmu> = LazyInit::new();
+
+impl Smmu {
+    fn read32(&self, reg: usize) -> u32 {
+        unsafe { ((self.regs.as_usize() + reg) as *const u32).read_volatile() }
+    }
+
+    fn write32(&self, reg: usize, value: u32) {
+        unsafe { ((self.regs.as_usize() + reg) as *mut u32).write_volatile(value) }
+    }
+
+    fn write64(&self, reg: usize, value: u64) {
+        unsafe { ((self.regs.as_usize() + reg) as *mut u64).write_volatile(value) }
+    }
+
+    fn write_cr0(&self, value: u32) {
+        self.write32(CR0, value);
+        while self.read32(CR0ACK) != value {
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Writes the command `cmd` to the queue and lets the SMMU consume it.
+    fn submit(&self, q: &mut CmdQueue, cmd: [u64; 2]) {
+        let size = 1 << q.log2;
+        let mask = (size << 1) - 1;
+        // full if the indexes are the same but the wrap bits differ
+        while (q.prod ^ (self.read32(CMDQ_CONS) & mask)) == size {
+            core::hint::spin_loop();
+        }
+        let entry = (q.base.as_usize() + (q.prod & (size - 1)) as usize * 16) as *mut [u64; 2];
+        unsafe { entry.write_volatile(cmd) };
+        self.sync_table(VirtAddr::from(entry as usize), 16);
+        q.prod = (q.prod + 1) & mask;
+        self.write32(CMDQ_PROD, q.prod);
+    }
+
+    /// Waits for the commands submitted to be completed.
+    fn sync(&self, q: &mut CmdQueue) {
+        self.submit(q, smmuv3::cmd_sync());
+        let mask = (1 << (q.log2 + 1)) - 1;
+        while self.read32(CMDQ_CONS) & mask != q.prod {
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Writes the STE of the stream `sid`, with the lock of the command queue
+    /// held.
+    fn write_ste(&self, q: &mut CmdQueue, sid: u32, ste: [u64; 8]) -> IommuResult {
+        if sid >= 1 << self.strtab_log2 {
+            return Err(IommuError::InvalidParam);
+        }
+        let entry = (self.strtab.as_usize() + sid as usize * 64) as *mut u64;
+        let cfgi_ste = smmuv3::cmd_cfgi_ste(sid);
+        // invalidate it first, as it's not written at once
+        if unsafe { entry.read_volatile() } & STE_VALID != 0 {
+            unsafe { entry.write_volatile(0) };
+            self.sync_table(VirtAddr::from(entry as usize), 8);
+            self.submit(q, cfgi_ste);
+            self.sync(q);
+        }
+        for (i, &word) in ste.iter().enumerate().skip(1) {
+            unsafe { entry.add(i).write_volatile(word) };
+        }
+        self.sync_table(VirtAddr::from(entry as usize), 64);
+        unsafe { entry.write_volatile(ste[0]) };
+        self.sync_table(VirtAddr::from(entry as usize), 8);
+        self.submit(q, cfgi_ste);
+        self.sync(q);
+        Ok(())
+    }
+
+    fn enable(&self, strtab: PhysAddr, cmdq: PhysAddr) {
+        self.write_cr0(0);
+        self.write32(CR1, CR1_WBACK_ISH);
+        self.write64(STRTAB_BASE, strtab.as_usize() as u64 | BASE_RA);
+        // the linear format
+        self.write32(STRTAB_BASE_CFG, self.strtab_log2);
+
+        let mut q = self.cmdq.lock();
+        self.write64(CMDQ_BASE, cmdq.as_usize() as u64 | BASE_RA | q.log2 as u64);
+        self.write32(CMDQ_PROD, 0);
+        self.write32(CMDQ_CONS, 0);
+        self.write_cr0(CR0_CMDQEN);
+        self.submit(&mut q, smmuv3::cmd_cfgi_all());
+        self.submit(&mut q, smmuv3::cmd_tlbi_nsnh_all());
+        self.sync(&mut q);
+        self.write_cr0(CR0_CMDQEN | CR0_SMMUEN);
+    }
+}
+
+impl IommuHw for Smmu {
+    fn name(&self) -> &'static str {
+        "Arm SMMUv3"
+    }
+
+    fn levels(&self) -> usize {
+        S2_LEVELS
+    }
+
+    fn max_domains(&self) -> usize {
+        self.max_domains
+    }
+
+    fn table_pte(&self, paddr: PhysAddr) -> u64 {
+        smmuv3::table_pte(paddr)
+    }
+
+    fn page_pte(&self, paddr: PhysAddr, flags: IommuFlags) -> u64 {
+        smmuv3::page_pte(paddr, flags)
+    }
+
+    fn is_present(&self, pte: u64) -> bool {
+        smmuv3::is_present(pte)
+    }
+
+    fn sync_table(&self, vaddr: VirtAddr, size: usize) {
+        if self.coherent {
+            unsafe { asm!("dsb ishst") };
+        } else {
+            clean_dcache_range(vaddr, size);
+        }
+    }
+
+    fn attach(&self, dev: DeviceId, domain_id: u16, root: PhysAddr) -> IommuResult {
+        let ste = smmuv3::ste(domain_id, root, self.s2ps);
+        let mut q = self.cmdq.lock();
+        self.write_ste(&mut q, dev.0, ste)?;
+        // the TLB entries of the previous domain of the same VMID
+        self.submit(&mut q, smmuv3::cmd_tlbi_s12_vmall(domain_id));
+        self.sync(&mut q);
+        Ok(())
+    }
+
+    fn detach(&self, dev: DeviceId) -> IommuResult {
+        // an invalid STE aborts the transactions
+        let mut q = self.cmdq.lock();
+        self.write_ste(&mut q, dev.0, [0; 8])
+    }
+
+    fn flush_domain(&self, domain_id: u16) {
+        let mut q = self.cmdq.lock();
+        self.submit(&mut q, smmuv3::cmd_tlbi_s12_vmall(domain_id));
+        self.sync(&mut q);
+    }
+}
+
+/// Probes the SMMUv3 at `iommu-paddr` and enables it, aborting the DMA of
+/// all the devices.
+pub(crate) fn probe() -> Option<&'static dyn IommuHw> {
+    let paddr = axconfig::devices::IOMMU_PADDR;
+    if paddr == 0 {
+        return None;
+    }
+    let regs = phys_to_virt(PhysAddr::from(paddr));
+    let read32 = |reg: usize| unsafe { ((regs.as_usize() + reg) as *const u32).read_volatile() };
+    let (idr0, idr1, idr5) = (read32(IDR0), read32(IDR1), read32(IDR5));
+    // AArch64 translation tables of 4K granule, of at least 40-bit outputs
+    let oas = idr5 as u64 & 0x7;
+    let ttf_aarch64 = (idr0 >> 2) & 0b10 != 0;
+    if idr0 & IDR0_S2P == 0 || !ttf_aarch64 || idr5 & IDR5_GRAN4K == 0 || oas < 2 {
+        if idr0 != 0 {
+            warn!(
+                "SMMUv3: unsupported features: IDR0 = {:#x}, IDR5 = {:#x}",
+                idr0, idr5
+            );
+        }
+        return None;
+    }
+
+    let strtab_log2 = (idr1 & 0x3f).min(MAX_STRTAB_LOG2);
+    let cmdq_log2 = ((idr1 >> 21) & 0x1f).min(MAX_CMDQ_LOG2);
+    let strtab = alloc_frames(((64 << strtab_log2) as usize).div_ceil(PAGE_SIZE_4K)).ok()?;
+    let cmdq = alloc_frames(((16 << cmdq_log2) as usize).div_ceil(PAGE_SIZE_4K)).ok()?;
+    let smmu = SMMU.init_once(Smmu {
+        regs,
+        coherent: idr0 & IDR0_COHACC != 0,
+        // the page tables map at most 48-bit addresses
+        s2ps: oas.min(5),
+        max_domains: if idr0 & IDR0_VMID16 != 0 {
+            1 << 16
+        } else {
+            1 << 8
+        },
+        strtab: phys_to_virt(strtab),
+        strtab_log2,
+        cmdq: SpinNoIrq::new(CmdQueue {
+            base: phys_to_virt(cmdq),
+            log2: cmdq_log2,
+            prod: 0,
+        }),
+    });
+    smmu.sync_table(smmu.strtab, 64 << strtab_log2);
+    debug!(
+        "SMMUv3 at {:#x}: IDR0 = {:#x}, IDR1 = {:#x}, IDR5 = {:#x}",
+        paddr, idr0, idr1, idr5
+    );
+    smmu.enable(strtab, cmdq);
+    Some(smmu)
+}
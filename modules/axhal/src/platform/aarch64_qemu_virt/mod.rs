@@ -20,6 +20,11 @@ pub mod misc {
     pub use crate::platform::aarch64_common::psci::system_off as terminate;
 }
 
+#[cfg(feature = "iommu")]
+pub(crate) mod iommu {
+    pub(crate) use crate::platform::aarch64_common::smmuv3::probe;
+}
+
 unsafe extern "C" {
     fn rust_main(cpu_id: usize, dtb: usize);
     #[cfg(feature = "smp")]
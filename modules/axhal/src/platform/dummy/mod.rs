@@ -59,6 +59,24 @@ pub mod time {
     pub fn epochoffset_nanos() -> u64 {
         0
     }
+
+    /// Sets the epoch offset in nanoseconds.
+    pub fn set_epochoffset_nanos(nanos: u64) {}
+
+    /// Sets the RTC alarm to go off at the given wall time, in seconds since
+    /// the epoch. Returns `false` if there's no RTC alarm.
+    pub fn set_rtc_alarm(epoch_secs: u64) -> bool {
+        false
+    }
+
+    /// Disarms the RTC alarm, and acknowledges its interrupt.
+    pub fn clear_rtc_alarm() {}
+
+    /// Writes the given wall time, in seconds since the epoch, to the RTC.
+    /// Returns `false` if there's no RTC.
+    pub fn set_rtc_time(epoch_secs: u64) -> bool {
+        false
+    }
 }
 
 #[cfg(feature = "irq")]
@@ -69,6 +87,10 @@ pub mod irq {
     /// The timer IRQ number.
     pub const TIMER_IRQ_NUM: usize = 0;
 
+    /// The IRQ number of the IPIs.
+    #[cfg(feature = "smp")]
+    pub(crate) const IPI_IRQ_NUM: usize = 1;
+
     /// Enables or disables the given IRQ.
     pub fn set_enable(irq_num: usize, enabled: bool) {}
 
@@ -83,6 +105,20 @@ pub mod irq {
     /// up in the IRQ handler table and calls the corresponding handler. If
     /// necessary, it also acknowledges the interrupt controller after handling.
     pub fn dispatch_irq(irq_num: usize) {}
+
+    /// Sends an IPI to the given CPU.
+    #[cfg(feature = "smp")]
+    pub(crate) fn send_ipi(cpu_id: usize) {}
+
+    /// Returns the IRQs allocatable to MSIs.
+    pub(crate) fn msi_irqs() -> core::ops::Range<usize> {
+        0..0
+    }
+
+    /// Returns the MSI message raising `irq` on the CPU `cpu_id`.
+    pub(crate) fn msi_message(irq: usize, cpu_id: usize) -> crate::irq::msi::MsiMessage {
+        unreachable!()
+    }
 }
 
 /// Initializes the platform devices for the primary CPU.
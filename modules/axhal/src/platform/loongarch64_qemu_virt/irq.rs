@@ -4,21 +4,57 @@ use loongArch64::register::{
 };
 
 /// The maximum number of IRQs.
-pub const MAX_IRQ_COUNT: usize = 12;
+pub const MAX_IRQ_COUNT: usize = 13;
 
 /// The timer IRQ number.
 pub const TIMER_IRQ_NUM: usize = estat::Interrupt::Timer as usize;
 
+/// The IRQ number of the IPIs.
+#[cfg(feature = "smp")]
+pub(crate) const IPI_IRQ_NUM: usize = estat::Interrupt::IPI as usize;
+
+/// The IOCSR registers of the IPIs.
+#[cfg(feature = "smp")]
+mod iocsr {
+    pub const IPI_STATUS: usize = 0x1000;
+    pub const IPI_ENABLE: usize = 0x1004;
+    pub const IPI_CLEAR: usize = 0x100c;
+}
+
+/// The action of the IPIs sent by [`send_ipi`], the boot of the CPUs using
+/// the action 1.
+#[cfg(feature = "smp")]
+const ACTION_IPI: u32 = 2;
+
 /// Enables or disables the given IRQ.
 pub fn set_enable(irq_num: usize, enabled: bool) {
-    if irq_num == TIMER_IRQ_NUM {
-        let old_value = ecfg::read().lie();
-        let new_value = match enabled {
-            true => old_value | LineBasedInterrupt::TIMER,
-            false => old_value & !LineBasedInterrupt::TIMER,
-        };
-        ecfg::set_lie(new_value);
+    let line = match irq_num {
+        TIMER_IRQ_NUM => LineBasedInterrupt::TIMER,
+        #[cfg(feature = "smp")]
+        IPI_IRQ_NUM => LineBasedInterrupt::IPI,
+        _ => return,
+    };
+    let old_value = ecfg::read().lie();
+    let new_value = match enabled {
+        true => old_value | line,
+        false => old_value & !line,
+    };
+    ecfg::set_lie(new_value);
+}
+
+/// Sends an IPI to the CPU `cpu_id`.
+#[cfg(feature = "smp")]
+pub(crate) fn send_ipi(cpu_id: usize) {
+    loongArch64::ipi::send_ipi_single(cpu_id, ACTION_IPI);
+}
+
+/// Enables the IPIs on the current CPU, as the line of the IPIs is per CPU.
+#[cfg(feature = "smp")]
+pub(super) fn init_percpu() {
+    unsafe {
+        core::arch::asm!("iocsrwr.w {}, {}", in(reg) u32::MAX, in(reg) iocsr::IPI_ENABLE);
     }
+    set_enable(IPI_IRQ_NUM, true);
 }
 
 /// Registers an IRQ handler for the given IRQ.
@@ -26,6 +62,17 @@ pub fn register_handler(irq_num: usize, handler: crate::irq::IrqHandler) -> bool
     crate::irq::register_handler_common(irq_num, handler)
 }
 
+/// Returns the IRQs allocatable to MSIs: none, as they aren't supported.
+pub(crate) fn msi_irqs() -> core::ops::Range<usize> {
+    0..0
+}
+
+/// Returns the MSI message raising `irq` on the CPU `cpu_id`, which can't be
+/// called without MSI IRQs.
+pub(crate) fn msi_message(_irq: usize, _cpu_id: usize) -> crate::irq::msi::MsiMessage {
+    unreachable!()
+}
+
 /// Dispatches the IRQ.
 ///
 /// This function is called by the common interrupt handler. It looks
@@ -35,5 +82,13 @@ pub fn dispatch_irq(irq_num: usize) {
     if irq_num == TIMER_IRQ_NUM {
         ticlr::clear_timer_interrupt();
     }
+    #[cfg(feature = "smp")]
+    if irq_num == IPI_IRQ_NUM {
+        unsafe {
+            let status: u32;
+            core::arch::asm!("iocsrrd.w {}, {}", out(reg) status, in(reg) iocsr::IPI_STATUS);
+            core::arch::asm!("iocsrwr.w {}, {}", in(reg) status, in(reg) iocsr::IPI_CLEAR);
+        }
+    }
     crate::irq::dispatch_irq_common(irq_num)
 }
@@ -18,6 +18,11 @@ pub fn epochoffset_nanos() -> u64 {
     unsafe { RTC_EPOCHOFFSET_NANOS }
 }
 
+/// Sets the epoch offset in nanoseconds.
+pub fn set_epochoffset_nanos(nanos: u64) {
+    unsafe { RTC_EPOCHOFFSET_NANOS = nanos }
+}
+
 /// Converts hardware ticks to nanoseconds.
 #[inline]
 pub fn ticks_to_nanos(ticks: u64) -> u64 {
@@ -46,6 +51,24 @@ pub fn set_oneshot_timer(deadline_ns: u64) {
     tcfg::set_en(true);
 }
 
+/// Sets the RTC alarm. There's no RTC support on this platform yet, so it
+/// always returns `false`.
+#[cfg(feature = "rtc")]
+pub fn set_rtc_alarm(_epoch_secs: u64) -> bool {
+    false
+}
+
+/// Disarms the RTC alarm.
+#[cfg(feature = "rtc")]
+pub fn clear_rtc_alarm() {}
+
+/// Writes the wall time to the RTC. There's no RTC support on this platform
+/// yet, so it always returns `false`.
+#[cfg(feature = "rtc")]
+pub fn set_rtc_time(_epoch_secs: u64) -> bool {
+    false
+}
+
 pub(super) fn init_percpu() {
     #[cfg(feature = "irq")]
     {
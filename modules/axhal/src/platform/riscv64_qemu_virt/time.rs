@@ -27,6 +27,11 @@ pub fn epochoffset_nanos() -> u64 {
     unsafe { RTC_EPOCHOFFSET_NANOS }
 }
 
+/// Sets the epoch offset in nanoseconds.
+pub fn set_epochoffset_nanos(nanos: u64) {
+    unsafe { RTC_EPOCHOFFSET_NANOS = nanos }
+}
+
 /// Set a one-shot timer.
 ///
 /// A timer interrupt will be triggered at the specified monotonic time deadline (in nanoseconds).
@@ -35,6 +40,75 @@ pub fn set_oneshot_timer(deadline_ns: u64) {
     sbi_rt::set_timer(nanos_to_ticks(deadline_ns));
 }
 
+/// Sets the RTC alarm to go off at the given wall time, in seconds since the
+/// epoch. The RTC raises its interrupt when the alarm goes off.
+///
+/// Returns `false` if there's no goldfish RTC on the platform.
+#[cfg(feature = "rtc")]
+pub fn set_rtc_alarm(epoch_secs: u64) -> bool {
+    if axconfig::devices::RTC_PADDR == 0 {
+        return false;
+    }
+    let nanos = epoch_secs * crate::time::NANOS_PER_SEC;
+    unsafe {
+        goldfish_write(GOLDFISH_IRQ_ENABLED, 1);
+        // writing the low half arms the alarm
+        goldfish_write(GOLDFISH_ALARM_HIGH, (nanos >> 32) as u32);
+        goldfish_write(GOLDFISH_ALARM_LOW, nanos as u32);
+    }
+    true
+}
+
+/// Writes the given wall time, in seconds since the epoch, to the RTC.
+///
+/// Returns `false` if there's no goldfish RTC on the platform.
+#[cfg(feature = "rtc")]
+pub fn set_rtc_time(epoch_secs: u64) -> bool {
+    if axconfig::devices::RTC_PADDR == 0 {
+        return false;
+    }
+    let nanos = epoch_secs * crate::time::NANOS_PER_SEC;
+    unsafe {
+        goldfish_write(GOLDFISH_TIME_HIGH, (nanos >> 32) as u32);
+        goldfish_write(GOLDFISH_TIME_LOW, nanos as u32);
+    }
+    true
+}
+
+/// Disarms the RTC alarm, and acknowledges its interrupt.
+#[cfg(feature = "rtc")]
+pub fn clear_rtc_alarm() {
+    if axconfig::devices::RTC_PADDR != 0 {
+        unsafe {
+            goldfish_write(GOLDFISH_CLEAR_ALARM, 1);
+            goldfish_write(GOLDFISH_CLEAR_INTERRUPT, 1);
+            goldfish_write(GOLDFISH_IRQ_ENABLED, 0);
+        }
+    }
+}
+
+#[cfg(feature = "rtc")]
+const GOLDFISH_TIME_LOW: usize = 0x00;
+#[cfg(feature = "rtc")]
+const GOLDFISH_TIME_HIGH: usize = 0x04;
+#[cfg(feature = "rtc")]
+const GOLDFISH_ALARM_LOW: usize = 0x08;
+#[cfg(feature = "rtc")]
+const GOLDFISH_ALARM_HIGH: usize = 0x0c;
+#[cfg(feature = "rtc")]
+const GOLDFISH_IRQ_ENABLED: usize = 0x10;
+#[cfg(feature = "rtc")]
+const GOLDFISH_CLEAR_ALARM: usize = 0x14;
+#[cfg(feature = "rtc")]
+const GOLDFISH_CLEAR_INTERRUPT: usize = 0x1c;
+
+#[cfg(feature = "rtc")]
+unsafe fn goldfish_write(offset: usize, value: u32) {
+    use crate::mem::phys_to_virt;
+    let base = phys_to_virt(pa!(axconfig::devices::RTC_PADDR)).as_usize();
+    unsafe { core::ptr::write_volatile((base + offset) as *mut u32, value) };
+}
+
 pub(super) fn init_early() {
     #[cfg(feature = "rtc")]
     if axconfig::devices::RTC_PADDR != 0 {
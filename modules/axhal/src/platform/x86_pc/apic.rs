@@ -16,6 +16,9 @@ pub(super) mod vectors {
     pub const APIC_TIMER_VECTOR: u8 = 0xf0;
     pub const APIC_SPURIOUS_VECTOR: u8 = 0xf1;
     pub const APIC_ERROR_VECTOR: u8 = 0xf2;
+    pub const APIC_IPI_VECTOR: u8 = 0xf3;
+    pub const MSI_VECTOR_START: u8 = 0x40;
+    pub const MSI_VECTOR_END: u8 = APIC_TIMER_VECTOR;
 }
 
 /// The maximum number of IRQs.
@@ -24,6 +27,10 @@ pub const MAX_IRQ_COUNT: usize = 256;
 /// The timer IRQ number.
 pub const TIMER_IRQ_NUM: usize = APIC_TIMER_VECTOR as usize;
 
+/// The IRQ number of the IPIs.
+#[cfg(all(feature = "irq", feature = "smp"))]
+pub(crate) const IPI_IRQ_NUM: usize = APIC_IPI_VECTOR as usize;
+
 const IO_APIC_BASE: PhysAddr = pa!(0xFEC0_0000);
 
 static LOCAL_APIC: SyncUnsafeCell<MaybeUninit<LocalApic>> =
@@ -34,8 +41,8 @@ static IO_APIC: LazyInit<SpinNoIrq<IoApic>> = LazyInit::new();
 /// Enables or disables the given IRQ.
 #[cfg(feature = "irq")]
 pub fn set_enable(vector: usize, enabled: bool) {
-    // should not affect LAPIC interrupts
-    if vector < APIC_TIMER_VECTOR as _ {
+    // should not affect LAPIC interrupts and MSIs
+    if vector < MSI_VECTOR_START as _ {
         unsafe {
             if enabled {
                 IO_APIC.lock().enable_irq(vector as u8);
@@ -66,6 +73,33 @@ pub fn dispatch_irq(vector: usize) {
     unsafe { local_apic().end_of_interrupt() };
 }
 
+/// Sends an IPI to the CPU `cpu_id` (its APIC ID).
+#[cfg(all(feature = "irq", feature = "smp"))]
+pub(crate) fn send_ipi(cpu_id: usize) {
+    let apic_id = raw_apic_id(cpu_id as u8);
+    unsafe { local_apic().send_ipi(APIC_IPI_VECTOR, apic_id) };
+}
+
+/// Returns the vectors allocatable to MSIs.
+#[cfg(feature = "irq")]
+pub(crate) fn msi_irqs() -> core::ops::Range<usize> {
+    MSI_VECTOR_START as usize..MSI_VECTOR_END as usize
+}
+
+/// Returns the MSI message raising `vector` on the CPU `cpu_id`: a fixed,
+/// edge-triggered interrupt.
+///
+/// As with the IPIs, the APIC ID of a CPU is its ID.
+#[cfg(feature = "irq")]
+pub(crate) fn msi_message(vector: usize, cpu_id: usize) -> crate::irq::msi::MsiMessage {
+    // the destination APIC ID is in bits 12..20 of the address.
+    const MSI_ADDR_BASE: u64 = 0xfee0_0000;
+    crate::irq::msi::MsiMessage {
+        address: MSI_ADDR_BASE | ((cpu_id as u64 & 0xff) << 12),
+        data: vector as u32,
+    }
+}
+
 pub(super) fn local_apic<'a>() -> &'a mut LocalApic {
     // It's safe as `LOCAL_APIC` is initialized in `init_primary`.
     unsafe { LOCAL_APIC.get().as_mut().unwrap().assume_init_mut() }
@@ -2,6 +2,9 @@ mod apic;
 mod boot;
 mod uart16550;
 
+#[cfg(feature = "iommu")]
+mod vtd;
+
 pub mod mem;
 pub mod misc;
 pub mod time;
@@ -18,6 +21,11 @@ pub mod console {
     pub use super::uart16550::*;
 }
 
+#[cfg(feature = "iommu")]
+pub(crate) mod iommu {
+    pub(crate) use super::vtd::probe;
+}
+
 unsafe extern "C" {
     fn rust_main(cpu_id: usize, dtb: usize) -> !;
     #[cfg(feature = "smp")]
@@ -35,6 +35,11 @@ pub fn epochoffset_nanos() -> u64 {
     unsafe { RTC_EPOCHOFFSET_NANOS }
 }
 
+/// Sets the epoch offset in nanoseconds.
+pub fn set_epochoffset_nanos(nanos: u64) {
+    unsafe { RTC_EPOCHOFFSET_NANOS = nanos }
+}
+
 /// Set a one-shot timer.
 ///
 /// A timer interrupt will be triggered at the specified monotonic time deadline (in nanoseconds).
@@ -81,6 +86,148 @@ pub(super) fn init_early() {
     }
 }
 
+/// Sets the RTC alarm to go off at the given wall time, in seconds since the
+/// epoch. The RTC raises IRQ 8 when the alarm goes off.
+///
+/// The CMOS alarm only compares the time of day, so a deadline more than a day
+/// ahead goes off early, at the same time of an earlier day. The RTC is assumed
+/// to keep UTC.
+#[cfg(feature = "rtc")]
+pub fn set_rtc_alarm(epoch_secs: u64) -> bool {
+    let secs = epoch_secs % 86400;
+    let hour = (secs / 3600) as u8;
+    let min = (secs / 60 % 60) as u8;
+    let sec = (secs % 60) as u8;
+
+    let _guard = kernel_guard::IrqSave::new();
+    let status_b = cmos::read(cmos::STATUS_B);
+    cmos::write(cmos::SECONDS_ALARM, cmos::encode(status_b, sec));
+    cmos::write(cmos::MINUTES_ALARM, cmos::encode(status_b, min));
+    cmos::write(cmos::HOURS_ALARM, cmos::encode_hour(status_b, hour));
+    cmos::write(cmos::STATUS_B, status_b | cmos::B_ALARM_INT);
+    // acknowledge a pending interrupt, or no more will be raised
+    cmos::read(cmos::STATUS_C);
+    true
+}
+
+/// Writes the given wall time, in seconds since the epoch, to the RTC.
+///
+/// The RTC is assumed to keep UTC, and only the year in the century is
+/// written.
+#[cfg(feature = "rtc")]
+pub fn set_rtc_time(epoch_secs: u64) -> bool {
+    let days = epoch_secs / 86400;
+    let secs = epoch_secs % 86400;
+    let (year, month, day) = civil_from_days(days);
+    // 1 is Sunday, and 1970-01-01 was a Thursday
+    let weekday = ((days + 4) % 7 + 1) as u8;
+
+    let _guard = kernel_guard::IrqSave::new();
+    let status_b = cmos::read(cmos::STATUS_B);
+    let encode = |v| cmos::encode(status_b, v);
+    // stop the updates while the time is written
+    cmos::write(cmos::STATUS_B, status_b | cmos::B_SET);
+    cmos::write(cmos::SECONDS, encode((secs % 60) as u8));
+    cmos::write(cmos::MINUTES, encode((secs / 60 % 60) as u8));
+    cmos::write(
+        cmos::HOURS,
+        cmos::encode_hour(status_b, (secs / 3600) as u8),
+    );
+    cmos::write(cmos::WEEKDAY, encode(weekday));
+    cmos::write(cmos::DAY_OF_MONTH, encode(day));
+    cmos::write(cmos::MONTH, encode(month));
+    cmos::write(cmos::YEAR, encode((year % 100) as u8));
+    cmos::write(cmos::STATUS_B, status_b & !cmos::B_SET);
+    true
+}
+
+/// Converts days since the epoch to a `(year, month, day)` date.
+///
+/// See <https://howardhinnant.github.io/date_algorithms.html#civil_from_days>.
+#[cfg(feature = "rtc")]
+fn civil_from_days(days: u64) -> (u64, u8, u8) {
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z % 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = era * 400 + yoe + (month <= 2) as u64;
+    (year, month as u8, day as u8)
+}
+
+/// Disarms the RTC alarm, and acknowledges its interrupt.
+#[cfg(feature = "rtc")]
+pub fn clear_rtc_alarm() {
+    let _guard = kernel_guard::IrqSave::new();
+    let status_b = cmos::read(cmos::STATUS_B);
+    cmos::write(cmos::STATUS_B, status_b & !cmos::B_ALARM_INT);
+    cmos::read(cmos::STATUS_C);
+}
+
+#[cfg(feature = "rtc")]
+mod cmos {
+    use x86_64::instructions::port::Port;
+
+    pub const SECONDS: u8 = 0x00;
+    pub const SECONDS_ALARM: u8 = 0x01;
+    pub const MINUTES: u8 = 0x02;
+    pub const MINUTES_ALARM: u8 = 0x03;
+    pub const HOURS: u8 = 0x04;
+    pub const HOURS_ALARM: u8 = 0x05;
+    pub const WEEKDAY: u8 = 0x06;
+    pub const DAY_OF_MONTH: u8 = 0x07;
+    pub const MONTH: u8 = 0x08;
+    pub const YEAR: u8 = 0x09;
+    pub const STATUS_B: u8 = 0x0b;
+    pub const STATUS_C: u8 = 0x0c;
+
+    pub const B_24_HOUR: u8 = 1 << 1;
+    pub const B_BINARY: u8 = 1 << 2;
+    pub const B_ALARM_INT: u8 = 1 << 5;
+    /// Stops the updates of the time registers.
+    pub const B_SET: u8 = 1 << 7;
+
+    /// Disables NMIs while a register is selected.
+    const NMI_DISABLE: u8 = 0x80;
+
+    pub fn read(reg: u8) -> u8 {
+        unsafe {
+            Port::<u8>::new(0x70).write(reg | NMI_DISABLE);
+            Port::<u8>::new(0x71).read()
+        }
+    }
+
+    pub fn write(reg: u8, value: u8) {
+        unsafe {
+            Port::<u8>::new(0x70).write(reg | NMI_DISABLE);
+            Port::<u8>::new(0x71).write(value);
+        }
+    }
+
+    /// Encodes a value in the format selected by status register B.
+    pub fn encode(status_b: u8, v: u8) -> u8 {
+        if status_b & B_BINARY != 0 {
+            v
+        } else {
+            ((v / 10) << 4) | (v % 10)
+        }
+    }
+
+    /// Encodes an hour (0-23) in the format selected by status register B.
+    pub fn encode_hour(status_b: u8, hour: u8) -> u8 {
+        if status_b & B_24_HOUR != 0 {
+            encode(status_b, hour)
+        } else {
+            // 12-hour mode: 12, 1, 2, ..., 11, with bit 7 set for PM
+            let pm = if hour >= 12 { 0x80 } else { 0 };
+            encode(status_b, if hour % 12 == 0 { 12 } else { hour % 12 }) | pm
+        }
+    }
+}
+
 pub(super) fn init_primary() {
     #[cfg(feature = "irq")]
     unsafe {
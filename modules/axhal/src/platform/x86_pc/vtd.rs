@@ -0,0 +1,252 @@
+//! Intel VT-d DMA remapping unit, with the legacy mode translation tables.
+//!
+//! The root table is indexed by the bus number of the requester ID, each of
+//! its entries points to a context table indexed by the device and function
+//! numbers, whose entries give the domain ID and the second-level page table
+//! of the devices.
+
+use core::arch::asm;
+
+use kspin::SpinNoIrq;
+use lazyinit::LazyInit;
+use memory_addr::{PhysAddr, VirtAddr};
+
+use crate::iommu::format::vtd::{self, ENTRY_PRESENT};
+use crate::iommu::{DeviceId, IommuError, IommuFlags, IommuHw, IommuResult, alloc_frames};
+use crate::mem::{PAGE_SIZE_4K, phys_to_virt};
+
+const VER_REG: usize = 0x00;
+const CAP_REG: usize = 0x08;
+const ECAP_REG: usize = 0x10;
+const GCMD_REG: usize = 0x18;
+const GSTS_REG: usize = 0x1c;
+const RTADDR_REG: usize = 0x20;
+const CCMD_REG: usize = 0x28;
+
+/// Translation enable.
+const GCMD_TE: u32 = 1 << 31;
+/// Set root table pointer.
+const GCMD_SRTP: u32 = 1 << 30;
+/// The bits of the status kept when issuing a command, excluding the one-shot
+/// ones.
+const GSTS_KEEP_MASK: u32 = 0x96ff_ffff;
+
+/// Invalidate context-cache.
+const CCMD_ICC: u64 = 1 << 63;
+/// Global invalidation of the context-cache.
+const CCMD_GLOBAL: u64 = 1 << 61;
+
+/// Invalidate IOTLB.
+const IOTLB_IVT: u64 = 1 << 63;
+/// Global invalidation of the IOTLB.
+const IOTLB_GLOBAL: u64 = 1 << 60;
+/// Domain-selective invalidation of the IOTLB.
+const IOTLB_DOMAIN: u64 = 2 << 60;
+/// Drain the reads and writes.
+const IOTLB_DRAIN: u64 = (1 << 49) | (1 << 48);
+
+const CACHE_LINE_SIZE: usize = 64;
+
+struct Vtd {
+    regs: VirtAddr,
+    /// The offset of the IOTLB registers.
+    iotlb_reg: usize,
+    levels: usize,
+    max_domains: usize,
+    /// Whether the accesses to the tables are coherent with the caches.
+    coherent: bool,
+    /// The root table, locked while the tables and the registers of the
+    /// commands are used.
+    root: SpinNoIrq<PhysAddr>,
+}
+
+static VTD: LazyInit<Vtd> = LazyInit::new();
+
+impl Vtd {
+    fn read32(&self, reg: usize) -> u32 {
+        unsafe { ((self.regs.as_usize() + reg) as *const u32).read_volatile() }
+    }
+
+    fn write32(&self, reg: usize, value: u32) {
+        unsafe { ((self.regs.as_usize() + reg) as *mut u32).write_volatile(value) }
+    }
+
+    fn read64(&self, reg: usize) -> u64 {
+        unsafe { ((self.regs.as_usize() + reg) as *const u64).read_volatile() }
+    }
+
+    fn write64(&self, reg: usize, value: u64) {
+        unsafe { ((self.regs.as_usize() + reg) as *mut u64).write_volatile(value) }
+    }
+
+    /// Issues the command `cmd` and waits for the `status` bit to be set.
+    fn command(&self, cmd: u32, status: u32) {
+        let sts = self.read32(GSTS_REG) & GSTS_KEEP_MASK;
+        self.write32(GCMD_REG, sts | cmd);
+        while self.read32(GSTS_REG) & status == 0 {
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Invalidates the IOTLB by `granularity`, the lock of the root table
+    /// must be held.
+    fn invalidate_iotlb(&self, granularity: u64) {
+        let reg = self.iotlb_reg + 8;
+        self.write64(reg, IOTLB_IVT | IOTLB_DRAIN | granularity);
+        while self.read64(reg) & IOTLB_IVT != 0 {
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Invalidates all the context entries and the translations cached, the
+    /// lock of the root table must be held.
+    fn invalidate_all(&self) {
+        self.write64(CCMD_REG, CCMD_ICC | CCMD_GLOBAL);
+        while self.read64(CCMD_REG) & CCMD_ICC != 0 {
+            core::hint::spin_loop();
+        }
+        self.invalidate_iotlb(IOTLB_GLOBAL);
+    }
+
+    /// Returns the context entry of `dev`, the missing context table is
+    /// created if `create`.
+    fn context_entry(&self, root: PhysAddr, dev: DeviceId, create: bool) -> IommuResult<*mut u64> {
+        let (bus, devfn) = vtd::context_index(dev).ok_or(IommuError::InvalidParam)?;
+        let root_entry = (phys_to_virt(root).as_usize() + bus * 16) as *mut u64;
+        let mut context_table = unsafe { root_entry.read_volatile() };
+        if context_table & ENTRY_PRESENT == 0 {
+            if !create {
+                return Err(IommuError::NotMapped);
+            }
+            let table = alloc_frames(1)?;
+            self.sync_table(phys_to_virt(table), PAGE_SIZE_4K);
+            context_table = vtd::root_entry(table);
+            unsafe { root_entry.write_volatile(context_table) };
+            self.sync_table(VirtAddr::from(root_entry as usize), 16);
+        }
+        let table = PhysAddr::from((context_table & !0xfff) as usize);
+        Ok((phys_to_virt(table).as_usize() + devfn * 16) as *mut u64)
+    }
+
+    fn enable(&self) {
+        let root = self.root.lock();
+        self.write64(RTADDR_REG, root.as_usize() as u64);
+        self.command(GCMD_SRTP, GCMD_SRTP);
+        self.invalidate_all();
+        self.command(GCMD_TE, GCMD_TE);
+    }
+}
+
+impl IommuHw for Vtd {
+    fn name(&self) -> &'static str {
+        "Intel VT-d"
+    }
+
+    fn levels(&self) -> usize {
+        self.levels
+    }
+
+    fn max_domains(&self) -> usize {
+        self.max_domains
+    }
+
+    fn table_pte(&self, paddr: PhysAddr) -> u64 {
+        vtd::table_pte(paddr)
+    }
+
+    fn page_pte(&self, paddr: PhysAddr, flags: IommuFlags) -> u64 {
+        vtd::page_pte(paddr, flags)
+    }
+
+    fn is_present(&self, pte: u64) -> bool {
+        vtd::is_present(pte)
+    }
+
+    fn sync_table(&self, vaddr: VirtAddr, size: usize) {
+        if !self.coherent {
+            let start = vaddr.as_usize() & !(CACHE_LINE_SIZE - 1);
+            for line in (start..vaddr.as_usize() + size).step_by(CACHE_LINE_SIZE) {
+                unsafe { asm!("clflush [{}]", in(reg) line) };
+            }
+        }
+        unsafe { asm!("mfence") };
+    }
+
+    fn attach(&self, dev: DeviceId, domain_id: u16, root: PhysAddr) -> IommuResult {
+        let root_table = self.root.lock();
+        let entry = self.context_entry(*root_table, dev, true)?;
+        let [lo, hi] = vtd::context_entry(root, domain_id, self.levels);
+        unsafe {
+            // present only once the upper half is written
+            entry.write_volatile(0);
+            entry.add(1).write_volatile(hi);
+            entry.write_volatile(lo);
+        }
+        self.sync_table(VirtAddr::from(entry as usize), 16);
+        self.invalidate_all();
+        Ok(())
+    }
+
+    fn detach(&self, dev: DeviceId) -> IommuResult {
+        let root_table = self.root.lock();
+        let entry = self.context_entry(*root_table, dev, false)?;
+        unsafe {
+            entry.write_volatile(0);
+            entry.add(1).write_volatile(0);
+        }
+        self.sync_table(VirtAddr::from(entry as usize), 16);
+        self.invalidate_all();
+        Ok(())
+    }
+
+    fn flush_domain(&self, domain_id: u16) {
+        let _root_table = self.root.lock();
+        self.invalidate_iotlb(IOTLB_DOMAIN | ((domain_id as u64) << 32));
+    }
+}
+
+/// Probes the VT-d DMA remapping unit at `iommu-paddr` and enables the
+/// translation, blocking the DMA of all the devices.
+pub(crate) fn probe() -> Option<&'static dyn IommuHw> {
+    let paddr = axconfig::devices::IOMMU_PADDR;
+    if paddr == 0 {
+        return None;
+    }
+    let regs = phys_to_virt(PhysAddr::from(paddr));
+    let read64 = |reg: usize| unsafe { ((regs.as_usize() + reg) as *const u64).read_volatile() };
+    let ver = unsafe { ((regs.as_usize() + VER_REG) as *const u32).read_volatile() };
+    if ver == 0 || ver == u32::MAX {
+        return None;
+    }
+    let (cap, ecap) = (read64(CAP_REG), read64(ECAP_REG));
+    // supported adjusted guest address widths
+    let sagaw = (cap >> 8) & 0x1f;
+    let levels = if sagaw & 0b100 != 0 {
+        4
+    } else if sagaw & 0b10 != 0 {
+        3
+    } else {
+        warn!("VT-d: unsupported address widths {:#x}", sagaw);
+        return None;
+    };
+    let root = alloc_frames(1).ok()?;
+    let vtd = VTD.init_once(Vtd {
+        regs,
+        iotlb_reg: ((ecap >> 8) & 0x3ff) as usize * 16,
+        levels,
+        max_domains: (1 << (4 + 2 * (cap & 0x7))).min(1 << 16),
+        coherent: ecap & 1 != 0,
+        root: SpinNoIrq::new(root),
+    });
+    vtd.sync_table(phys_to_virt(root), PAGE_SIZE_4K);
+    debug!(
+        "VT-d {}.{} at {:#x}: cap = {:#x}, ecap = {:#x}",
+        (ver >> 4) & 0xf,
+        ver & 0xf,
+        paddr,
+        cap,
+        ecap
+    );
+    vtd.enable();
+    Some(vtd)
+}
@@ -0,0 +1,267 @@
+//! The kernel CSPRNG (cryptographically secure pseudo-random number
+//! generator).
+//!
+//! It's a ChaCha20 generator with the fast key erasure: each block generated
+//! replaces the key by its first half and gives its second half as the
+//! output, so the numbers given can't be recovered from the state later.
+//!
+//! The key is seeded once per boot ([`init`]) from the random number
+//! generator of the CPU (`RDSEED`/`RDRAND` on x86_64, `RNDR` on AArch64) if
+//! there's one, and the timer. More entropy is mixed in with [`add_entropy`].
+
+use kspin::SpinNoIrq;
+
+/// "expand 32-byte k"
+const CHACHA_CONSTANTS: [u32; 4] = [0x6170_7865, 0x3320_646e, 0x7962_2d32, 0x6b20_6574];
+
+const KEY_WORDS: usize = 8;
+
+struct ChaCha20Rng {
+    key: [u32; KEY_WORDS],
+    counter: u64,
+    /// The output of the last block, given from the end.
+    buf: [u32; KEY_WORDS],
+    avail: usize,
+}
+
+static RNG: SpinNoIrq<ChaCha20Rng> = SpinNoIrq::new(ChaCha20Rng {
+    key: [0; KEY_WORDS],
+    counter: 0,
+    buf: [0; KEY_WORDS],
+    avail: 0,
+});
+
+fn quarter_round(s: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    s[a] = s[a].wrapping_add(s[b]);
+    s[d] = (s[d] ^ s[a]).rotate_left(16);
+    s[c] = s[c].wrapping_add(s[d]);
+    s[b] = (s[b] ^ s[c]).rotate_left(12);
+    s[a] = s[a].wrapping_add(s[b]);
+    s[d] = (s[d] ^ s[a]).rotate_left(8);
+    s[c] = s[c].wrapping_add(s[d]);
+    s[b] = (s[b] ^ s[c]).rotate_left(7);
+}
+
+/// Returns the block `counter` of the key stream of `key` and `nonce`, with
+/// the 64-bit counter and nonce of the original ChaCha.
+fn chacha20_block(key: &[u32; KEY_WORDS], counter: u64, nonce: u64) -> [u32; 16] {
+    let mut input = [0; 16];
+    input[..4].copy_from_slice(&CHACHA_CONSTANTS);
+    input[4..12].copy_from_slice(key);
+    input[12] = counter as u32;
+    input[13] = (counter >> 32) as u32;
+    input[14] = nonce as u32;
+    input[15] = (nonce >> 32) as u32;
+    let mut state = input;
+    for _ in 0..10 {
+        quarter_round(&mut state, 0, 4, 8, 12);
+        quarter_round(&mut state, 1, 5, 9, 13);
+        quarter_round(&mut state, 2, 6, 10, 14);
+        quarter_round(&mut state, 3, 7, 11, 15);
+        quarter_round(&mut state, 0, 5, 10, 15);
+        quarter_round(&mut state, 1, 6, 11, 12);
+        quarter_round(&mut state, 2, 7, 8, 13);
+        quarter_round(&mut state, 3, 4, 9, 14);
+    }
+    for (s, i) in state.iter_mut().zip(input) {
+        *s = s.wrapping_add(i);
+    }
+    state
+}
+
+impl ChaCha20Rng {
+    fn refill(&mut self) {
+        let block = chacha20_block(&self.key, self.counter, 0);
+        self.counter = self.counter.wrapping_add(1);
+        self.key.copy_from_slice(&block[..KEY_WORDS]);
+        self.buf.copy_from_slice(&block[KEY_WORDS..]);
+        self.avail = KEY_WORDS;
+    }
+
+    fn next_u32(&mut self) -> u32 {
+        if self.avail == 0 {
+            self.refill();
+        }
+        self.avail -= 1;
+        core::mem::take(&mut self.buf[self.avail])
+    }
+
+    fn mix(&mut self, entropy: u64) {
+        self.key[0] ^= entropy as u32;
+        self.key[1] ^= (entropy >> 32) as u32;
+        // spread it over the whole key, and drop the output of the old key
+        self.refill();
+        self.avail = 0;
+    }
+}
+
+/// Returns a random number from the CPU, or `None` if it has no random
+/// number generator or it fails.
+fn hw_random() -> Option<u64> {
+    cfg_if::cfg_if! {
+        if #[cfg(target_arch = "x86_64")] {
+            let cpuid = raw_cpuid::CpuId::new();
+            let rdseed = cpuid
+                .get_extended_feature_info()
+                .is_some_and(|info| info.has_rdseed());
+            let rdrand = cpuid.get_feature_info().is_some_and(|info| info.has_rdrand());
+            // it may fail when the entropy is exhausted, so retry a few times
+            for _ in 0..10 {
+                let (value, ok): (u64, u8);
+                if rdseed {
+                    unsafe {
+                        core::arch::asm!("rdseed {}", "setc {}", out(reg) value, out(reg_byte) ok)
+                    };
+                } else if rdrand {
+                    unsafe {
+                        core::arch::asm!("rdrand {}", "setc {}", out(reg) value, out(reg_byte) ok)
+                    };
+                } else {
+                    return None;
+                }
+                if ok != 0 {
+                    return Some(value);
+                }
+            }
+            None
+        } else if #[cfg(target_arch = "aarch64")] {
+            let isar0: u64;
+            unsafe { core::arch::asm!("mrs {}, id_aa64isar0_el1", out(reg) isar0) };
+            if (isar0 >> 60) & 0xf == 0 {
+                return None;
+            }
+            for _ in 0..10 {
+                let (value, nzcv): (u64, u64);
+                // `RNDR`, which sets `NZCV` to `0b0100` on failures
+                unsafe {
+                    core::arch::asm!(
+                        "mrs {}, s3_3_c2_c4_0",
+                        "mrs {}, nzcv",
+                        out(reg) value,
+                        out(reg) nzcv,
+                    )
+                };
+                if nzcv == 0 {
+                    return Some(value);
+                }
+            }
+            None
+        } else {
+            None
+        }
+    }
+}
+
+/// Seeds the generator, called once at boot.
+pub fn init() {
+    let mut rng = RNG.lock();
+    let hw = hw_random().is_some();
+    for _ in 0..KEY_WORDS / 2 {
+        rng.mix(hw_random().unwrap_or(0) ^ crate::time::current_ticks());
+    }
+    // the jitter of the timer is the only entropy without the CPU's generator
+    let mut ticks = crate::time::current_ticks();
+    for _ in 0..64 {
+        let now = crate::time::current_ticks();
+        rng.mix(now.wrapping_sub(ticks));
+        ticks = now;
+    }
+    if hw {
+        debug!("CSPRNG seeded by the CPU's random number generator");
+    } else {
+        info!("No random number generator in the CPU, CSPRNG seeded by the timer");
+    }
+}
+
+/// Mixes `entropy`, e.g. the time of an event, into the generator.
+pub fn add_entropy(entropy: u64) {
+    RNG.lock().mix(entropy);
+}
+
+/// Returns a random [`u32`].
+pub fn random_u32() -> u32 {
+    RNG.lock().next_u32()
+}
+
+/// Returns a random [`u64`].
+pub fn random_u64() -> u64 {
+    let mut rng = RNG.lock();
+    ((rng.next_u32() as u64) << 32) | rng.next_u32() as u64
+}
+
+/// Fills `buf` with random bytes.
+pub fn fill_bytes(buf: &mut [u8]) {
+    let mut rng = RNG.lock();
+    for chunk in buf.chunks_mut(4) {
+        let bytes = rng.next_u32().to_ne_bytes();
+        chunk.copy_from_slice(&bytes[..chunk.len()]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The test vector of the quarter round, in section 2.1.1 of RFC 8439.
+    #[test]
+    fn test_quarter_round() {
+        let mut s = [0; 16];
+        s[..4].copy_from_slice(&[0x1111_1111, 0x0102_0304, 0x9b8d_6f43, 0x0123_4567]);
+        quarter_round(&mut s, 0, 1, 2, 3);
+        assert_eq!(s[..4], [0xea2a_92f4, 0xcb1c_f8ce, 0x4581_472e, 0x5881_c4bb]);
+    }
+
+    /// The test vector of the block function, in section 2.3.2 of RFC 8439.
+    ///
+    /// Its 32-bit counter and 96-bit nonce are the 64-bit counter and nonce
+    /// here, with the first word of the nonce as the upper half of the
+    /// counter.
+    #[test]
+    fn test_chacha20_block() {
+        let key = core::array::from_fn(|i| {
+            let b = 4 * i as u32;
+            u32::from_le_bytes([b as u8, b as u8 + 1, b as u8 + 2, b as u8 + 3])
+        });
+        let block = chacha20_block(&key, (0x0900_0000 << 32) | 1, 0x4a00_0000);
+        assert_eq!(
+            block,
+            [
+                0xe4e7_f110,
+                0x1559_3bd1,
+                0x1fdd_0f50,
+                0xc471_20a3,
+                0xc7f4_d1c7,
+                0x0368_c033,
+                0x9aaa_2204,
+                0x4e6c_d4c3,
+                0x4664_82d2,
+                0x09aa_9f07,
+                0x05d7_c214,
+                0xa202_8bd9,
+                0xd19c_12b5,
+                0xb94e_16de,
+                0xe883_d0cb,
+                0x4e3c_50a2,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fast_key_erasure() {
+        let mut rng = ChaCha20Rng {
+            key: [0; KEY_WORDS],
+            counter: 0,
+            buf: [0; KEY_WORDS],
+            avail: 0,
+        };
+        let block = chacha20_block(&[0; KEY_WORDS], 0, 0);
+        // the second half is given from the end, the first half is the new key
+        for i in (KEY_WORDS..16).rev() {
+            assert_eq!(rng.next_u32(), block[i]);
+        }
+        assert_eq!(rng.key, block[..KEY_WORDS]);
+        assert!(rng.buf.iter().all(|&word| word == 0));
+        let next = chacha20_block(&rng.key, 1, 0);
+        assert_eq!(rng.next_u32(), next[15]);
+    }
+}
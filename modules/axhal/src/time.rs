@@ -12,6 +12,8 @@ pub type TimeValue = Duration;
 pub use crate::platform::irq::TIMER_IRQ_NUM;
 #[cfg(feature = "irq")]
 pub use crate::platform::time::set_oneshot_timer;
+#[cfg(feature = "rtc")]
+pub use crate::platform::time::{clear_rtc_alarm, set_rtc_alarm, set_rtc_time};
 pub use crate::platform::time::{current_ticks, epochoffset_nanos, nanos_to_ticks, ticks_to_nanos};
 
 /// Number of milliseconds in a second.
@@ -45,6 +47,18 @@ pub fn wall_time() -> TimeValue {
     TimeValue::from_nanos(monotonic_time_nanos() + epochoffset_nanos())
 }
 
+/// Sets the wall time, by adjusting its offset to the monotonic clock.
+///
+/// The wall time can't be earlier than the boot time, so it's clamped to it.
+/// With the `rtc` feature, the new time is also written back to the RTC, so
+/// that it's kept across reboots.
+pub fn set_wall_time(time: TimeValue) {
+    let nanos = (time.as_nanos() as u64).saturating_sub(monotonic_time_nanos());
+    crate::platform::time::set_epochoffset_nanos(nanos);
+    #[cfg(feature = "rtc")]
+    set_rtc_time(time.as_secs());
+}
+
 /// Busy waiting for the given duration.
 pub fn busy_wait(dur: Duration) {
     busy_wait_until(wall_time() + dur);
@@ -16,6 +16,40 @@ pub static IRQ: [fn(usize) -> bool];
 #[def_trap_handler]
 pub static PAGE_FAULT: [fn(VirtAddr, MappingFlags, bool) -> bool];
 
+/// The kind of a synchronous exception caused by the faulting instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExceptionKind {
+    /// Access to an unmapped address or a protection violation, which is not
+    /// resolved by the [`PAGE_FAULT`] handlers.
+    PageFault,
+    /// Misaligned access.
+    Misaligned,
+    /// Integer division by zero, or overflow.
+    DivideError,
+    /// Undefined or privileged instruction.
+    IllegalInstruction,
+}
+
+/// Information of a synchronous exception.
+#[derive(Debug, Clone, Copy)]
+pub struct ExceptionInfo {
+    /// The kind of the exception.
+    pub kind: ExceptionKind,
+    /// The faulting data address, or the address of the faulting instruction
+    /// if there's no data access.
+    pub fault_addr: VirtAddr,
+    /// Whether the exception is from user space.
+    pub is_user: bool,
+}
+
+/// A slice of synchronous exception handler functions.
+///
+/// The handler is called before the kernel panics on an exception, and
+/// returns whether the exception is handled. It may change the trap frame to
+/// resume somewhere else.
+#[def_trap_handler]
+pub static EXCEPTION: [fn(&mut TrapFrame, &ExceptionInfo) -> bool];
+
 /// A slice of syscall handler functions.
 #[cfg(feature = "uspace")]
 #[def_trap_handler]
@@ -41,6 +75,84 @@ macro_rules! handle_trap {
     }}
 }
 
+/// The trap frame of the code interrupted by the IRQ being handled on each
+/// CPU.
+#[percpu::def_percpu]
+static IRQ_TRAP_FRAME: usize = 0;
+
+/// Calls the [`IRQ`] handler, making `tf`, the trap frame of the code it
+/// interrupts, available to [`with_irq_trap_frame`].
+#[allow(dead_code)]
+pub(crate) fn handle_irq(tf: &TrapFrame, irq_num: usize) -> bool {
+    // Safety: IRQs are disabled in trap handlers, so it stays on this CPU.
+    let prev = unsafe { IRQ_TRAP_FRAME.read_current_raw() };
+    unsafe { IRQ_TRAP_FRAME.write_current_raw(tf as *const _ as usize) };
+    let handled = handle_trap!(IRQ, irq_num);
+    unsafe { IRQ_TRAP_FRAME.write_current_raw(prev) };
+    handled
+}
+
+/// Calls `f` with the trap frame of the code interrupted by the IRQ being
+/// handled, e.g. to sample where it was.
+///
+/// It must be called by an IRQ handler, and returns `None` if there's no IRQ
+/// being handled on the current CPU.
+pub fn with_irq_trap_frame<T>(f: impl FnOnce(&TrapFrame) -> T) -> Option<T> {
+    let tf = unsafe { IRQ_TRAP_FRAME.read_current_raw() } as *const TrapFrame;
+    // Safety: it's on the stack of the interrupted code until the IRQ returns.
+    unsafe { tf.as_ref() }.map(f)
+}
+
+/// Calls the [`EXCEPTION`] handler.
+#[allow(dead_code)]
+pub(crate) fn handle_exception(
+    tf: &mut TrapFrame,
+    kind: ExceptionKind,
+    fault_addr: usize,
+    is_user: bool,
+) -> bool {
+    let info = ExceptionInfo {
+        kind,
+        fault_addr: fault_addr.into(),
+        is_user,
+    };
+    handle_trap!(EXCEPTION, tf, &info)
+}
+
+unsafe extern "C" {
+    fn ax_upcall_trampoline();
+    fn ax_upcall_return();
+}
+
+/// Calls the function of an upcall, from the trampoline.
+extern "C" fn upcall_entry(func: extern "C" fn(usize), arg: usize) {
+    #[cfg(feature = "fp_simd")]
+    crate::arch::call_preserving_fp(func, arg);
+    #[cfg(not(feature = "fp_simd"))]
+    func(arg);
+}
+
+/// Makes the code interrupted by the trap of `tf` call `func(arg)` on its
+/// stack once the trap returns, e.g. to run a signal handler.
+///
+/// The interrupted context in `tf` is overwritten, so the caller saves it
+/// first. Once `func` returns, the trampoline calling it raises an exception
+/// (told apart by [`is_upcall_return`]), whose handler puts the saved context
+/// back in its trap frame to resume the interrupted code. The FP/SIMD
+/// registers are preserved across the call.
+pub fn set_upcall(tf: &mut TrapFrame, func: extern "C" fn(usize), arg: usize) {
+    tf.set_ip(ax_upcall_trampoline as usize);
+    tf.set_arg0(upcall_entry as usize);
+    tf.set_arg1(func as usize);
+    tf.set_arg2(arg);
+}
+
+/// Whether the exception of `tf` is raised by the trampoline of
+/// [`set_upcall`] once the function returns.
+pub fn is_upcall_return(tf: &TrapFrame) -> bool {
+    tf.ip() == ax_upcall_return as usize
+}
+
 #[unsafe(no_mangle)]
 pub(crate) fn post_trap_callback(tf: &mut TrapFrame, from_user: bool) {
     for cb in crate::trap::POST_TRAP.iter() {
@@ -0,0 +1,116 @@
+//! Encodes the symbol table given by `AX_KSYMS`, the output of
+//! `nm -n -C --defined-only` on the kernel of a previous link, into the
+//! format read by `src/table.rs`. Without it, the table is empty.
+
+use std::path::Path;
+
+const MAGIC: &[u8; 4] = b"KSYM";
+const HEADER_SIZE: usize = 24;
+/// The number of symbols between two markers.
+const GROUP_SIZE: usize = 64;
+const MAX_NAME_LEN: usize = 255;
+
+/// Returns the function symbols `(address, name)`, sorted by address, and
+/// the end of the text section.
+fn parse_nm(text: &str) -> (Vec<(u64, String)>, u64) {
+    let mut syms = Vec::new();
+    let mut end = 0;
+    for line in text.lines() {
+        let mut fields = line.splitn(3, ' ');
+        let (Some(addr), Some(ty), Some(name)) = (fields.next(), fields.next(), fields.next())
+        else {
+            continue;
+        };
+        let Ok(addr) = u64::from_str_radix(addr, 16) else {
+            continue;
+        };
+        if name == "_etext" {
+            end = addr;
+        }
+        if !matches!(ty, "t" | "T" | "w" | "W") || name.starts_with(".L") {
+            continue;
+        }
+        syms.push((addr, strip_hash(name).to_string()));
+    }
+    syms.sort_by_key(|&(addr, _)| addr);
+    syms.dedup_by_key(|&mut (addr, _)| addr);
+    if end == 0 {
+        end = syms.last().map_or(0, |&(addr, _)| addr + 1);
+    }
+    (syms, end)
+}
+
+/// Strips the hash of a demangled legacy Rust symbol, e.g. `::h0123456789abcdef`.
+fn strip_hash(name: &str) -> &str {
+    match name.rsplit_once("::h") {
+        Some((prefix, hash)) if hash.len() == 16 && hash.bytes().all(|b| b.is_ascii_hexdigit()) => {
+            prefix
+        }
+        _ => name,
+    }
+}
+
+fn write_uleb128(out: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+/// Encodes the table: a header, the markers of the groups of symbols, and
+/// the symbols, each with the delta of its address to the previous one and
+/// its name without the prefix shared with the previous one.
+fn encode(syms: &[(u64, String)], end: u64) -> Vec<u8> {
+    let groups = syms.len().div_ceil(GROUP_SIZE);
+    let mut markers = Vec::new();
+    let mut stream = Vec::new();
+    for group in syms.chunks(GROUP_SIZE) {
+        markers.extend_from_slice(&group[0].0.to_le_bytes());
+        markers.extend_from_slice(&(stream.len() as u32).to_le_bytes());
+        markers.extend_from_slice(&[0; 4]);
+        let (mut prev_addr, mut prev_name) = (group[0].0, &b""[..]);
+        for (addr, name) in group {
+            let name = &name.as_bytes()[..name.len().min(MAX_NAME_LEN)];
+            let shared = prev_name
+                .iter()
+                .zip(name)
+                .take_while(|(a, b)| a == b)
+                .count();
+            write_uleb128(&mut stream, addr - prev_addr);
+            stream.push(shared as u8);
+            stream.push((name.len() - shared) as u8);
+            stream.extend_from_slice(&name[shared..]);
+            (prev_addr, prev_name) = (*addr, name);
+        }
+    }
+
+    let size = HEADER_SIZE + markers.len() + stream.len();
+    let mut out = Vec::with_capacity(size);
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&(size as u32).to_le_bytes());
+    out.extend_from_slice(&(syms.len() as u32).to_le_bytes());
+    out.extend_from_slice(&(groups as u32).to_le_bytes());
+    out.extend_from_slice(&end.to_le_bytes());
+    out.extend_from_slice(&markers);
+    out.extend_from_slice(&stream);
+    out
+}
+
+fn main() {
+    println!("cargo:rerun-if-env-changed=AX_KSYMS");
+    let (syms, end) = match std::env::var("AX_KSYMS") {
+        Ok(path) if !path.is_empty() => {
+            println!("cargo:rerun-if-changed={}", path);
+            let text = std::fs::read_to_string(&path).expect("failed to read AX_KSYMS");
+            parse_nm(&text)
+        }
+        _ => (Vec::new(), 0),
+    };
+    let out_dir = std::env::var("OUT_DIR").unwrap();
+    std::fs::write(Path::new(&out_dir).join("ksyms.bin"), encode(&syms, end)).unwrap();
+}
@@ -0,0 +1,71 @@
+//! Backtraces by walking the frame pointers.
+
+/// The maximum distance between two frames considered valid.
+const MAX_FRAME_SIZE: usize = 0x10_0000;
+
+#[inline(always)]
+fn frame_pointer() -> usize {
+    let fp: usize;
+    unsafe {
+        #[cfg(target_arch = "x86_64")]
+        core::arch::asm!("mov {}, rbp", out(reg) fp);
+        #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+        core::arch::asm!("mv {}, s0", out(reg) fp);
+        #[cfg(target_arch = "aarch64")]
+        core::arch::asm!("mov {}, x29", out(reg) fp);
+        #[cfg(target_arch = "loongarch64")]
+        core::arch::asm!("move {}, $fp", out(reg) fp);
+    }
+    fp
+}
+
+/// Collects the return addresses of the callers into `bt` by walking the
+/// frame pointers, and returns their number.
+///
+/// It stops at a frame pointer that doesn't look valid, i.e., that is null,
+/// misaligned, or doesn't point to a caller's frame a bit higher on the
+/// stack. The kernel should be built with `-C force-frame-pointers=yes` to
+/// get useful backtraces.
+#[inline(always)]
+pub fn backtrace(bt: &mut [usize]) -> usize {
+    let fp = frame_pointer();
+    // without frame pointers, it may be anything, so start near the stack
+    backtrace_from(fp, &fp as *const _ as usize, bt)
+}
+
+/// Collects the return addresses into `bt` by walking the frame pointers from
+/// the frame `fp` of another context, e.g. the code interrupted by a trap, and
+/// returns their number.
+///
+/// `sp` is a lower bound of the stack of the context, e.g. its stack pointer:
+/// a first frame pointer not a bit higher is considered invalid. Otherwise,
+/// it's the same as [`backtrace`].
+pub fn backtrace_from(mut fp: usize, sp: usize, bt: &mut [usize]) -> usize {
+    // (offset of the previous frame pointer, offset of the return address)
+    #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+    const OFFSETS: (isize, isize) = (0, 1);
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    const OFFSETS: (isize, isize) = (-2, -1);
+
+    if fp < sp || fp - sp > MAX_FRAME_SIZE {
+        return 0;
+    }
+    let mut n = 0;
+    for entry in bt.iter_mut() {
+        if fp == 0 || fp % align_of::<usize>() != 0 {
+            break;
+        }
+        let frame = fp as *const usize;
+        let (prev, ra) = unsafe { (*frame.offset(OFFSETS.0), *frame.offset(OFFSETS.1)) };
+        if ra == 0 {
+            break;
+        }
+        *entry = ra;
+        n += 1;
+        if prev <= fp || prev - fp > MAX_FRAME_SIZE {
+            break;
+        }
+        fp = prev;
+    }
+    n
+}
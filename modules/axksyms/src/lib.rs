@@ -0,0 +1,22 @@
+//! [ArceOS](https://github.com/arceos-org/arceos) kernel symbol table and
+//! backtraces.
+//!
+//! The symbol table maps the addresses of the kernel functions to their
+//! names, so that backtraces (e.g. of panics and of the heap checker) show
+//! function names instead of raw addresses. It's generated from the kernel
+//! of a previous link: built with `KSYMS=y`, the kernel is linked a second
+//! time with the symbols of the first link, given by `AX_KSYMS`. The code
+//! doesn't depend on the size of the table, and the table comes after the
+//! text section, so the addresses of the functions don't change. Otherwise,
+//! the table is empty and [`lookup`] always fails.
+//!
+//! The names are demangled, without the hashes of Rust symbols, and
+//! compressed by sharing the prefix with the previous name.
+
+#![no_std]
+
+mod backtrace;
+mod table;
+
+pub use self::backtrace::{backtrace, backtrace_from};
+pub use self::table::{MAX_NAME_LEN, Symbol, Symbolized, lookup, symbol_count};
@@ -0,0 +1,164 @@
+//! The symbol table embedded at build time, see `build.rs` for its format.
+
+use core::fmt;
+
+/// The maximum length of the name of a symbol, longer ones are truncated.
+pub const MAX_NAME_LEN: usize = 255;
+
+const MAGIC: &[u8; 4] = b"KSYM";
+const HEADER_SIZE: usize = 24;
+const MARKER_SIZE: usize = 16;
+
+static KSYMS: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/ksyms.bin"));
+
+/// A function of the kernel.
+#[derive(Clone)]
+pub struct Symbol {
+    addr: usize,
+    name: [u8; MAX_NAME_LEN],
+    name_len: usize,
+}
+
+impl Symbol {
+    /// Returns the address of the symbol.
+    pub fn addr(&self) -> usize {
+        self.addr
+    }
+
+    /// Returns the (demangled) name of the symbol.
+    pub fn name(&self) -> &str {
+        let name = &self.name[..self.name_len];
+        // a truncated name may end in the middle of a character
+        match core::str::from_utf8(name) {
+            Ok(name) => name,
+            Err(e) => unsafe { core::str::from_utf8_unchecked(&name[..e.valid_up_to()]) },
+        }
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}@{:#x}", self.name(), self.addr)
+    }
+}
+
+fn read_u32(data: &[u8], off: usize) -> Option<usize> {
+    let bytes = data.get(off..off + 4)?;
+    Some(u32::from_le_bytes(bytes.try_into().unwrap()) as usize)
+}
+
+fn read_u64(data: &[u8], off: usize) -> Option<usize> {
+    let bytes = data.get(off..off + 8)?;
+    Some(u64::from_le_bytes(bytes.try_into().unwrap()) as usize)
+}
+
+fn read_uleb128(data: &[u8], off: &mut usize) -> Option<usize> {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = *data.get(*off)?;
+        *off += 1;
+        value |= ((byte & 0x7f) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+        shift += 7;
+    }
+}
+
+/// Returns the table.
+///
+/// Its size is read from its header rather than known at compile time, so
+/// that the code doesn't change when the table is embedded, and neither do
+/// the addresses of the functions.
+fn table() -> &'static [u8] {
+    let ptr = core::hint::black_box(KSYMS.as_ptr());
+    let header = unsafe { core::slice::from_raw_parts(ptr, HEADER_SIZE) };
+    if &header[..4] != MAGIC {
+        return &[];
+    }
+    let size = read_u32(header, 4).unwrap();
+    unsafe { core::slice::from_raw_parts(ptr, size) }
+}
+
+/// Returns the number of symbols in the table, which is 0 if the kernel was
+/// built without it.
+pub fn symbol_count() -> usize {
+    read_u32(table(), 8).unwrap_or(0)
+}
+
+/// Finds the function that contains `addr`, i.e. the last symbol at or before
+/// it.
+pub fn lookup(addr: usize) -> Option<Symbol> {
+    let data = table();
+    let groups = read_u32(data, 12)?;
+    let end = read_u64(data, 16)?;
+    if groups == 0 || addr >= end {
+        return None;
+    }
+    let marker = |i: usize| {
+        let off = HEADER_SIZE + i * MARKER_SIZE;
+        Some((read_u64(data, off)?, read_u32(data, off + 8)?))
+    };
+    if addr < marker(0)?.0 {
+        return None;
+    }
+    // the last group that starts at or before `addr`
+    let (mut lo, mut hi) = (0, groups);
+    while hi - lo > 1 {
+        let mid = (lo + hi) / 2;
+        if marker(mid)?.0 <= addr {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+    let (group_addr, stream_off) = marker(lo)?;
+    let stream_end = if lo + 1 < groups {
+        HEADER_SIZE + groups * MARKER_SIZE + marker(lo + 1)?.1
+    } else {
+        data.len()
+    };
+
+    let mut off = HEADER_SIZE + groups * MARKER_SIZE + stream_off;
+    let mut cur = Symbol {
+        addr: group_addr,
+        name: [0; MAX_NAME_LEN],
+        name_len: 0,
+    };
+    let mut found = false;
+    while off < stream_end {
+        let sym_addr = cur.addr + read_uleb128(data, &mut off)?;
+        if sym_addr > addr {
+            break;
+        }
+        let shared = *data.get(off)? as usize;
+        let suffix_len = *data.get(off + 1)? as usize;
+        let suffix = data.get(off + 2..off + 2 + suffix_len)?;
+        off += 2 + suffix_len;
+        let name_len = shared + suffix_len;
+        if shared > cur.name_len || name_len > MAX_NAME_LEN {
+            return None;
+        }
+        cur.name[shared..name_len].copy_from_slice(suffix);
+        cur.addr = sym_addr;
+        cur.name_len = name_len;
+        found = true;
+    }
+    found.then_some(cur)
+}
+
+/// An address, displayed with the function that contains it if it's known,
+/// e.g. `0xffffffc0802001a4 <axruntime::rust_main+0x1c>`.
+#[derive(Debug, Clone, Copy)]
+pub struct Symbolized(pub usize);
+
+impl fmt::Display for Symbolized {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:#x}", self.0)?;
+        if let Some(sym) = lookup(self.0) {
+            write!(f, " <{}+{:#x}>", sym.name(), self.0 - sym.addr())?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,209 @@
+//! The levels of the log records of each target, adjustable at runtime.
+//!
+//! A filter is written as `RUST_LOG` of `env_logger`: a list of directives
+//! separated by commas, each of them `target=level` for the records of
+//! `target` and the modules in it, `target` for all of them, or `level` for
+//! the targets not given. The most specific directive of a target wins, e.g.
+//! with `warn,axfs=debug,axfs::fops=off`, the records of `axfs::dev` up to
+//! `debug` are logged, and none of `axfs::fops`.
+//!
+//! The records of levels stripped at compile time by the `log-level-*`
+//! features are never logged, whatever the filter.
+
+use core::fmt;
+
+use kspin::SpinNoIrq;
+use log::LevelFilter;
+
+/// The maximum number of directives of targets in a filter.
+pub const MAX_DIRECTIVES: usize = 16;
+
+/// The maximum length of the target of a directive.
+pub const MAX_TARGET_LEN: usize = 64;
+
+/// An error of parsing or updating a [`LogFilter`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterError {
+    /// A level isn't one of `off`, `error`, `warn`, `info`, `debug` and
+    /// `trace`.
+    BadLevel,
+    /// A target is longer than [`MAX_TARGET_LEN`].
+    TargetTooLong,
+    /// There are more than [`MAX_DIRECTIVES`] targets.
+    TooManyDirectives,
+}
+
+impl fmt::Display for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::BadLevel => "invalid log level",
+            Self::TargetTooLong => "log target too long",
+            Self::TooManyDirectives => "too many log targets",
+        })
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Directive {
+    target: [u8; MAX_TARGET_LEN],
+    len: usize,
+    level: LevelFilter,
+}
+
+impl Directive {
+    fn target(&self) -> &str {
+        // copied from a `&str`
+        unsafe { core::str::from_utf8_unchecked(&self.target[..self.len]) }
+    }
+
+    /// Whether it applies to `target`, i.e. `target` is its target or a
+    /// module in it.
+    fn matches(&self, target: &str) -> bool {
+        target
+            .strip_prefix(self.target())
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+    }
+}
+
+/// The levels of the log records of each target.
+#[derive(Clone, Copy)]
+pub struct LogFilter {
+    /// The level of the targets without a directive.
+    default: LevelFilter,
+    directives: [Directive; MAX_DIRECTIVES],
+    count: usize,
+}
+
+impl LogFilter {
+    /// A filter logging the records up to `level` of every target.
+    pub const fn new(level: LevelFilter) -> Self {
+        Self {
+            default: level,
+            directives: [Directive {
+                target: [0; MAX_TARGET_LEN],
+                len: 0,
+                level: LevelFilter::Off,
+            }; MAX_DIRECTIVES],
+            count: 0,
+        }
+    }
+
+    /// Parses a filter, where the targets without a directive are off.
+    pub fn parse(spec: &str) -> Result<Self, FilterError> {
+        let mut filter = Self::new(LevelFilter::Off);
+        filter.update(spec)?;
+        Ok(filter)
+    }
+
+    /// Applies the directives of `spec` on the filter, keeping the levels of
+    /// the other targets.
+    ///
+    /// The filter is unchanged on errors.
+    pub fn update(&mut self, spec: &str) -> Result<(), FilterError> {
+        let mut new = *self;
+        for directive in spec.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            match directive.split_once('=') {
+                Some((target, level)) => {
+                    let level = level.trim().parse().map_err(|_| FilterError::BadLevel)?;
+                    new.set(target.trim(), level)?;
+                }
+                None => match directive.parse() {
+                    Ok(level) => new.default = level,
+                    // a target alone logs all its records
+                    Err(_) => new.set(directive, LevelFilter::Trace)?,
+                },
+            }
+        }
+        *self = new;
+        Ok(())
+    }
+
+    /// Sets the level of the records of `target` and the modules in it.
+    pub fn set(&mut self, target: &str, level: LevelFilter) -> Result<(), FilterError> {
+        if target.len() > MAX_TARGET_LEN {
+            return Err(FilterError::TargetTooLong);
+        }
+        let directives = &mut self.directives[..self.count];
+        if let Some(d) = directives.iter_mut().find(|d| d.target() == target) {
+            d.level = level;
+            return Ok(());
+        }
+        if self.count == MAX_DIRECTIVES {
+            return Err(FilterError::TooManyDirectives);
+        }
+        let d = &mut self.directives[self.count];
+        d.target[..target.len()].copy_from_slice(target.as_bytes());
+        d.len = target.len();
+        d.level = level;
+        self.count += 1;
+        Ok(())
+    }
+
+    /// Returns the maximum level of the records of `target` logged.
+    pub fn level(&self, target: &str) -> LevelFilter {
+        self.directives[..self.count]
+            .iter()
+            .filter(|d| d.matches(target))
+            .max_by_key(|d| d.len)
+            .map_or(self.default, |d| d.level)
+    }
+
+    /// Returns the maximum level of the records of all the targets.
+    pub fn max_level(&self) -> LevelFilter {
+        self.directives[..self.count]
+            .iter()
+            .map(|d| d.level)
+            .fold(self.default, LevelFilter::max)
+    }
+}
+
+fn level_name(level: LevelFilter) -> &'static str {
+    match level {
+        LevelFilter::Off => "off",
+        LevelFilter::Error => "error",
+        LevelFilter::Warn => "warn",
+        LevelFilter::Info => "info",
+        LevelFilter::Debug => "debug",
+        LevelFilter::Trace => "trace",
+    }
+}
+
+impl fmt::Display for LogFilter {
+    /// Formats the filter as it's parsed, e.g. `warn,axfs=debug`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(level_name(self.default))?;
+        for d in &self.directives[..self.count] {
+            write!(f, ",{}={}", d.target(), level_name(d.level))?;
+        }
+        Ok(())
+    }
+}
+
+static FILTER: SpinNoIrq<LogFilter> = SpinNoIrq::new(LogFilter::new(LevelFilter::Warn));
+
+/// Whether the records of `level` of `target` are logged.
+pub(crate) fn enabled(target: &str, level: log::Level) -> bool {
+    level <= FILTER.lock().level(target)
+}
+
+/// Returns the current log filter.
+pub fn log_filter() -> LogFilter {
+    *FILTER.lock()
+}
+
+/// Replaces the log filter with `spec`.
+pub fn set_log_filter(spec: &str) -> Result<(), FilterError> {
+    let filter = LogFilter::parse(spec)?;
+    *FILTER.lock() = filter;
+    log::set_max_level(filter.max_level());
+    Ok(())
+}
+
+/// Applies the directives of `spec` on the log filter, keeping the levels of
+/// the other targets.
+pub fn update_log_filter(spec: &str) -> Result<(), FilterError> {
+    let mut filter = FILTER.lock();
+    filter.update(spec)?;
+    log::set_max_level(filter.max_level());
+    Ok(())
+}
@@ -0,0 +1,193 @@
+//! The kernel log: the latest log records, kept in memory.
+//!
+//! Every record logged is kept in a ring of [`KMSG_LEN`] bytes, including the
+//! ones not printed to the console (see [`set_console_level`]), until newer
+//! ones overwrite it. Each record has a sequence number counting from 0, so
+//! readers can tell which ones they missed.
+//!
+//! [`set_console_level`]: crate::set_console_level
+
+use core::fmt::{self, Write};
+use core::time::Duration;
+
+use kspin::SpinNoIrq;
+use log::Level;
+
+/// The size of the ring of the records in bytes.
+pub const KMSG_LEN: usize = 32 * 1024;
+
+/// The maximum length of the text of a record, longer ones are truncated.
+pub const KMSG_TEXT_MAX: usize = 1024;
+
+/// The size of the header of a record in the ring: the timestamp in
+/// nanoseconds, the level, and the length of the text.
+const HEADER_LEN: usize = 8 + 1 + 2;
+
+/// A record of the kernel log.
+pub struct KmsgRecord {
+    /// The sequence number.
+    pub seq: u64,
+    /// When it was logged, since boot.
+    pub time: Duration,
+    pub level: Level,
+    text: [u8; KMSG_TEXT_MAX],
+    len: usize,
+}
+
+impl KmsgRecord {
+    /// The text of the record, without a trailing newline.
+    pub fn text(&self) -> &str {
+        // it was truncated at a character boundary
+        unsafe { core::str::from_utf8_unchecked(&self.text[..self.len]) }
+    }
+}
+
+impl fmt::Display for KmsgRecord {
+    /// Formats the record as `dmesg` does: `[  secs.usecs] text`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "[{:>5}.{:06}] {}",
+            self.time.as_secs(),
+            self.time.subsec_micros(),
+            self.text()
+        )
+    }
+}
+
+struct Kmsg {
+    buf: [u8; KMSG_LEN],
+    /// The offset of the oldest record in `buf`.
+    head: usize,
+    /// The number of bytes taken by the records.
+    used: usize,
+    /// The sequence number of the oldest record.
+    first_seq: u64,
+    /// The sequence number of the next record.
+    next_seq: u64,
+}
+
+static KMSG: SpinNoIrq<Kmsg> = SpinNoIrq::new(Kmsg {
+    buf: [0; KMSG_LEN],
+    head: 0,
+    used: 0,
+    first_seq: 0,
+    next_seq: 0,
+});
+
+/// Formats the text of a record, truncated to [`KMSG_TEXT_MAX`] bytes.
+struct TextWriter {
+    text: [u8; KMSG_TEXT_MAX],
+    len: usize,
+}
+
+impl Write for TextWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut len = s.len().min(KMSG_TEXT_MAX - self.len);
+        while !s.is_char_boundary(len) {
+            len -= 1;
+        }
+        self.text[self.len..self.len + len].copy_from_slice(&s.as_bytes()[..len]);
+        self.len += len;
+        Ok(())
+    }
+}
+
+impl Kmsg {
+    fn read_bytes(&self, offset: usize, dst: &mut [u8]) {
+        for (i, b) in dst.iter_mut().enumerate() {
+            *b = self.buf[(offset + i) % KMSG_LEN];
+        }
+    }
+
+    fn write_bytes(&mut self, offset: usize, src: &[u8]) {
+        for (i, &b) in src.iter().enumerate() {
+            self.buf[(offset + i) % KMSG_LEN] = b;
+        }
+    }
+
+    /// Reads the header of the record at `offset`, and returns its timestamp,
+    /// level and the length of its text.
+    fn header(&self, offset: usize) -> (u64, u8, usize) {
+        let mut header = [0; HEADER_LEN];
+        self.read_bytes(offset, &mut header);
+        let time = u64::from_le_bytes(header[..8].try_into().unwrap());
+        let len = u16::from_le_bytes(header[9..].try_into().unwrap());
+        (time, header[8], len as usize)
+    }
+
+    fn push(&mut self, time: Duration, level: Level, text: &[u8]) {
+        let len = HEADER_LEN + text.len();
+        while KMSG_LEN - self.used < len {
+            let (_, _, text_len) = self.header(self.head);
+            self.head = (self.head + HEADER_LEN + text_len) % KMSG_LEN;
+            self.used -= HEADER_LEN + text_len;
+            self.first_seq += 1;
+        }
+        let mut header = [0; HEADER_LEN];
+        header[..8].copy_from_slice(&(time.as_nanos() as u64).to_le_bytes());
+        header[8] = level as u8;
+        header[9..].copy_from_slice(&(text.len() as u16).to_le_bytes());
+        let tail = self.head + self.used;
+        self.write_bytes(tail, &header);
+        self.write_bytes(tail + HEADER_LEN, text);
+        self.used += len;
+        self.next_seq += 1;
+    }
+
+    /// Returns the record `seq`, or the oldest one after it if it's been
+    /// overwritten.
+    fn get(&self, seq: u64) -> Option<KmsgRecord> {
+        if seq >= self.next_seq {
+            return None;
+        }
+        let seq = seq.max(self.first_seq);
+        let mut offset = self.head;
+        for _ in self.first_seq..seq {
+            let (_, _, len) = self.header(offset);
+            offset += HEADER_LEN + len;
+        }
+        let (time, level, len) = self.header(offset);
+        let mut record = KmsgRecord {
+            seq,
+            time: Duration::from_nanos(time),
+            level: match level {
+                1 => Level::Error,
+                2 => Level::Warn,
+                3 => Level::Info,
+                4 => Level::Debug,
+                _ => Level::Trace,
+            },
+            text: [0; KMSG_TEXT_MAX],
+            len,
+        };
+        self.read_bytes(offset + HEADER_LEN, &mut record.text[..len]);
+        Some(record)
+    }
+}
+
+/// Adds a record to the kernel log.
+pub(crate) fn push(time: Duration, level: Level, args: fmt::Arguments) {
+    let mut writer = TextWriter {
+        text: [0; KMSG_TEXT_MAX],
+        len: 0,
+    };
+    writer.write_fmt(args).ok();
+    KMSG.lock().push(time, level, &writer.text[..writer.len]);
+}
+
+/// Returns the record of the kernel log numbered `seq`, or the oldest one
+/// after it if it's been overwritten, or [`None`] if it's not logged yet.
+pub fn kmsg_read(seq: u64) -> Option<KmsgRecord> {
+    KMSG.lock().get(seq)
+}
+
+/// Returns the sequence number of the oldest record of the kernel log.
+pub fn kmsg_first_seq() -> u64 {
+    KMSG.lock().first_seq
+}
+
+/// Returns the sequence number of the next record of the kernel log.
+pub fn kmsg_next_seq() -> u64 {
+    KMSG.lock().next_seq
+}
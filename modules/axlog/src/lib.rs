@@ -7,6 +7,12 @@
 //! If it is used in `no_std` environment, the users need to implement the
 //! [`LogIf`] to provide external functions such as console output.
 //!
+//! Besides being printed to the console, the records are kept in memory in
+//! the kernel log, to be read later with [`kmsg_read`] (e.g. by `dmesg`).
+//!
+//! The records logged can be chosen by target at runtime, with a filter like
+//! `RUST_LOG` of `env_logger`, e.g. `warn,axfs=debug` (see [`set_log_filter`]).
+//!
 //! To use in the `std` environment, please enable the `std` feature:
 //!
 //! ```toml
@@ -50,15 +56,27 @@
 
 extern crate log;
 
+mod filter;
+mod kmsg;
+
 use core::fmt::{self, Write};
-use core::str::FromStr;
+use core::sync::atomic::{AtomicUsize, Ordering};
 
-use log::{Level, LevelFilter, Log, Metadata, Record};
+use log::{Log, Metadata, Record};
 
 #[cfg(not(feature = "std"))]
 use crate_interface::call_interface;
 
-pub use log::{debug, error, info, trace, warn};
+pub use filter::{
+    FilterError, LogFilter, MAX_DIRECTIVES, MAX_TARGET_LEN, log_filter, set_log_filter,
+    update_log_filter,
+};
+pub use kmsg::{KMSG_LEN, KMSG_TEXT_MAX, KmsgRecord, kmsg_first_seq, kmsg_next_seq, kmsg_read};
+pub use log::{Level, LevelFilter, debug, error, info, trace, warn};
+
+/// The maximum level of the records printed to the console, as a
+/// [`LevelFilter`].
+static CONSOLE_LEVEL: AtomicUsize = AtomicUsize::new(LevelFilter::Trace as usize);
 
 /// Prints to the console.
 ///
@@ -144,8 +162,8 @@ impl Write for Logger {
 
 impl Log for Logger {
     #[inline]
-    fn enabled(&self, _metadata: &Metadata) -> bool {
-        true
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        filter::enabled(metadata.target(), metadata.level())
     }
 
     fn log(&self, record: &Record) {
@@ -156,6 +174,23 @@ impl Log for Logger {
         let level = record.level();
         let line = record.line().unwrap_or(0);
         let path = record.target();
+
+        cfg_if::cfg_if! {
+            if #[cfg(feature = "std")] {
+                let now = std::time::UNIX_EPOCH.elapsed().unwrap_or_default();
+            } else {
+                let now = call_interface!(LogIf::current_time);
+            }
+        }
+        kmsg::push(
+            now,
+            level,
+            format_args!("{}:{}: {}", path, line, record.args()),
+        );
+        if level as usize > CONSOLE_LEVEL.load(Ordering::Relaxed) {
+            return;
+        }
+
         let args_color = match level {
             Level::Error => ColorCode::Red,
             Level::Warn => ColorCode::Yellow,
@@ -177,7 +212,6 @@ impl Log for Logger {
             } else {
                 let cpu_id = call_interface!(LogIf::current_cpu_id);
                 let tid = call_interface!(LogIf::current_task_id);
-                let now = call_interface!(LogIf::current_time);
                 if let Some(cpu_id) = cpu_id {
                     if let Some(tid) = tid {
                         // show CPU ID and task ID
@@ -253,10 +287,26 @@ pub fn init() {
 /// this way incurs runtime overhead. In addition, this function is no effect
 /// when those features are enabled.
 ///
-/// `level` should be one of `off`, `error`, `warn`, `info`, `debug`, `trace`.
+/// `level` should be one of `off`, `error`, `warn`, `info`, `debug`, `trace`,
+/// or a filter with the levels of each target (see [`set_log_filter`]). The
+/// log is turned off if it's invalid.
 pub fn set_max_level(level: &str) {
-    let lf = LevelFilter::from_str(level)
-        .ok()
-        .unwrap_or(LevelFilter::Off);
-    log::set_max_level(lf);
+    if set_log_filter(level).is_err() {
+        set_log_filter("off").ok();
+    }
+}
+
+/// Sets the maximum level of the records printed to the console.
+///
+/// The records of higher levels are still kept in the kernel log. All of them
+/// are printed by default.
+pub fn set_console_level(level: LevelFilter) {
+    CONSOLE_LEVEL.store(level as usize, Ordering::Relaxed);
+}
+
+/// Returns the maximum level of the records printed to the console.
+pub fn console_level() -> LevelFilter {
+    LevelFilter::iter()
+        .nth(CONSOLE_LEVEL.load(Ordering::Relaxed))
+        .unwrap_or(LevelFilter::Trace)
 }
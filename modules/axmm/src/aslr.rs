@@ -0,0 +1,112 @@
+//! Address space layout randomization (ASLR).
+//!
+//! Each address space gets its own [`AddrLayout`], drawn from the kernel
+//! CSPRNG ([`axhal::random`]) when it's created or emptied for a new program
+//! ([`AddrSpace::unmap_user_areas`]), so the places of the program images and
+//! the mappings differ on each boot and on each program loaded.
+//!
+//! As `/proc/sys/kernel/randomize_va_space` of Linux, the randomization is
+//! set by [`set_randomize_va_space`]:
+//!
+//! - 0: disabled, e.g. to debug with the same addresses on each run;
+//! - 1: the program images and the mappings are randomized;
+//! - 2 (default): the same as 1, as the heap is in the linear mapping of the
+//!   physical memory, and can't be moved.
+//!
+//! It's disabled as well while [`set_addr_no_randomize`] is set, as the
+//! `ADDR_NO_RANDOMIZE` personality of Linux. Both take effect on the places
+//! chosen afterwards.
+//!
+//! [`AddrSpace::unmap_user_areas`]: crate::AddrSpace::unmap_user_areas
+
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+use axerrno::{AxResult, ax_err};
+use memory_addr::{PAGE_SIZE_4K, VirtAddr, VirtAddrRange};
+
+/// The bits of randomness of the program images and the mappings (1 TiB).
+const MMAP_RND_BITS: u32 = 28;
+
+static RANDOMIZE_VA_SPACE: AtomicU8 = AtomicU8::new(2);
+static ADDR_NO_RANDOMIZE: AtomicBool = AtomicBool::new(false);
+
+/// Returns the level of the randomization, see [the module](self).
+pub fn randomize_va_space() -> u8 {
+    RANDOMIZE_VA_SPACE.load(Ordering::Relaxed)
+}
+
+/// Sets the level of the randomization of the places chosen afterwards, see
+/// [the module](self).
+pub fn set_randomize_va_space(level: u8) -> AxResult {
+    if level > 2 {
+        return ax_err!(InvalidInput, "invalid randomization level");
+    }
+    info!("ASLR level set to {}", level);
+    RANDOMIZE_VA_SPACE.store(level, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Disables the randomization of the places chosen afterwards if `disabled`,
+/// whatever its level, or enables it back.
+pub fn set_addr_no_randomize(disabled: bool) {
+    ADDR_NO_RANDOMIZE.store(disabled, Ordering::Relaxed);
+}
+
+/// Whether the places chosen now are randomized.
+pub(crate) fn is_randomized() -> bool {
+    randomize_va_space() > 0 && !ADDR_NO_RANDOMIZE.load(Ordering::Relaxed)
+}
+
+/// Returns a random offset of pages below `2^bits` pages, and below `limit`.
+fn random_offset(bits: u32, limit: usize) -> usize {
+    let pages = (1usize << bits).min(limit / PAGE_SIZE_4K);
+    if pages == 0 {
+        return 0;
+    }
+    (axhal::random::random_u64() as usize % pages) * PAGE_SIZE_4K
+}
+
+/// The places of the parts of a program in an address space.
+///
+/// From the bottom of the address space: the program images from
+/// [`AddrLayout::load_base`], and the mappings from
+/// [`AddrLayout::mmap_base`]. Their random offsets are drawn once, and added
+/// only while the randomization is enabled.
+#[derive(Debug, Clone, Copy)]
+pub struct AddrLayout {
+    range: VirtAddrRange,
+    load_offset: usize,
+    mmap_offset: usize,
+}
+
+impl AddrLayout {
+    /// Draws a new layout in `range`.
+    ///
+    /// Each part is moved by up to 1/16 of the range, so that they stay apart
+    /// in the small address spaces.
+    pub fn new(range: VirtAddrRange) -> Self {
+        let limit = range.size() / 16;
+        // the time of the creation, as the entropy of each program
+        axhal::random::add_entropy(axhal::time::current_ticks());
+        Self {
+            range,
+            load_offset: random_offset(MMAP_RND_BITS, limit),
+            mmap_offset: random_offset(MMAP_RND_BITS, limit),
+        }
+    }
+
+    fn offset(&self, offset: usize) -> usize {
+        if is_randomized() { offset } else { 0 }
+    }
+
+    /// Returns where a position-independent program image is loaded.
+    pub fn load_base(&self) -> VirtAddr {
+        self.range.start + self.range.size() / 8 + self.offset(self.load_offset)
+    }
+
+    /// Returns where the search of free areas for the mappings without a
+    /// fixed address starts.
+    pub fn mmap_base(&self) -> VirtAddr {
+        self.range.start + self.range.size() / 4 + self.offset(self.mmap_offset)
+    }
+}
@@ -8,6 +8,7 @@ use memory_addr::{
 };
 use memory_set::{MemoryArea, MemorySet};
 
+use crate::aslr::AddrLayout;
 use crate::backend::Backend;
 use crate::mapping_err_to_ax_err;
 
@@ -16,6 +17,15 @@ pub struct AddrSpace {
     va_range: VirtAddrRange,
     areas: MemorySet<Backend>,
     pt: PageTable,
+    layout: AddrLayout,
+    /// Whether the mappings both writable and executable are rejected.
+    wx_enforced: bool,
+}
+
+/// Returns whether `flags` are both writable and executable, which W^X
+/// forbids.
+pub(crate) fn is_wx(flags: MappingFlags) -> bool {
+    flags.contains(MappingFlags::WRITE | MappingFlags::EXECUTE)
 }
 
 impl AddrSpace {
@@ -34,6 +44,12 @@ impl AddrSpace {
         self.va_range.size()
     }
 
+    /// Returns the (randomized) places of the parts of the program in the
+    /// address space.
+    pub const fn layout(&self) -> &AddrLayout {
+        &self.layout
+    }
+
     /// Returns the reference to the inner page table.
     pub const fn page_table(&self) -> &PageTable {
         &self.pt
@@ -50,15 +66,31 @@ impl AddrSpace {
             .contains_range(VirtAddrRange::from_start_size(start, size))
     }
 
-    /// Creates a new empty address space.
+    /// Creates a new empty address space, with a new [`AddrLayout`].
     pub fn new_empty(base: VirtAddr, size: usize) -> AxResult<Self> {
+        let va_range = VirtAddrRange::from_start_size(base, size);
         Ok(Self {
-            va_range: VirtAddrRange::from_start_size(base, size),
+            va_range,
             areas: MemorySet::new(),
             pt: PageTable::try_new().map_err(|_| AxError::NoMemory)?,
+            layout: AddrLayout::new(va_range),
+            wx_enforced: false,
         })
     }
 
+    /// Rejects the mappings both writable and executable from now on (W^X),
+    /// with [`AxError::PermissionDenied`], as in the kernel address space.
+    pub fn enforce_wx(&mut self) {
+        self.wx_enforced = true;
+    }
+
+    fn validate_flags(&self, flags: MappingFlags) -> AxResult {
+        if self.wx_enforced && is_wx(flags) {
+            return ax_err!(PermissionDenied, "writable and executable mapping");
+        }
+        Ok(())
+    }
+
     /// Copies page table mappings from another address space.
     ///
     /// It copies the page table entries only rather than the memory regions,
@@ -125,6 +157,7 @@ impl AddrSpace {
         flags: MappingFlags,
     ) -> AxResult {
         self.validate_region(start_vaddr, size)?;
+        self.validate_flags(flags)?;
         if !start_paddr.is_aligned_4k() {
             return ax_err!(InvalidInput, "address not aligned");
         }
@@ -153,6 +186,7 @@ impl AddrSpace {
         populate: bool,
     ) -> AxResult {
         self.validate_region(start, size)?;
+        self.validate_flags(flags)?;
 
         let area = MemoryArea::new(start, size, flags, Backend::new_alloc(populate));
         self.areas
@@ -161,6 +195,21 @@ impl AddrSpace {
         Ok(())
     }
 
+    /// Reserves `size` bytes at `start`, where any access faults, so that
+    /// they aren't used by other mappings.
+    pub(crate) fn map_reserved(&mut self, start: VirtAddr, size: usize) -> AxResult {
+        self.validate_region(start, size)?;
+        let area = MemoryArea::new(
+            start,
+            size,
+            MappingFlags::empty(),
+            Backend::new_alloc(false),
+        );
+        self.areas
+            .map(area, &mut self.pt, false)
+            .map_err(mapping_err_to_ax_err)
+    }
+
     /// Populates the area with physical frames, returning false if the area
     /// contains unmapped area.
     pub fn populate_area(&mut self, mut start: VirtAddr, size: usize) -> AxResult {
@@ -214,6 +263,8 @@ impl AddrSpace {
     }
 
     /// To remove user area mappings from address space.
+    ///
+    /// A new [`AddrLayout`] is drawn for the next program.
     pub fn unmap_user_areas(&mut self) -> AxResult {
         for area in self.areas.iter() {
             assert!(area.start().is_aligned_4k());
@@ -226,6 +277,7 @@ impl AddrSpace {
             );
         }
         self.areas.clear(&mut self.pt).unwrap();
+        self.layout = AddrLayout::new(self.va_range);
         Ok(())
     }
 
@@ -314,6 +366,7 @@ impl AddrSpace {
     /// Returns an error if the address range is out of the address space or not
     /// aligned.
     pub fn protect(&mut self, start: VirtAddr, size: usize, flags: MappingFlags) -> AxResult {
+        self.validate_flags(flags)?;
         // Populate the area first, which also checks the address range for us.
         self.populate_area(start, size)?;
 
@@ -384,6 +437,8 @@ impl AddrSpace {
     /// Clone a [`AddrSpace`] by re-mapping all [`MemoryArea`]s in a new page table and copying data in user space.
     pub fn clone_or_err(&mut self) -> AxResult<Self> {
         let mut new_aspace = Self::new_empty(self.base(), self.size())?;
+        new_aspace.layout = self.layout;
+        new_aspace.wx_enforced = self.wx_enforced;
 
         for area in self.areas.iter() {
             let backend = area.backend();
@@ -441,6 +496,7 @@ impl fmt::Debug for AddrSpace {
             .field("va_range", &self.va_range)
             .field("page_table_root", &self.pt.root_paddr())
             .field("areas", &self.areas)
+            .field("layout", &self.layout)
             .finish()
     }
 }
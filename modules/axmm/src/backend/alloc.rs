@@ -1,22 +1,21 @@
-use axalloc::global_allocator;
-use axhal::mem::{phys_to_virt, virt_to_phys};
+use axhal::mem::phys_to_virt;
 use axhal::paging::{MappingFlags, PageSize, PageTable};
 use memory_addr::{PAGE_SIZE_4K, PageIter4K, PhysAddr, VirtAddr};
 
 use super::Backend;
+use crate::frame::{FrameZone, alloc_frames, dealloc_frames};
 
 fn alloc_frame(zeroed: bool) -> Option<PhysAddr> {
-    let vaddr = VirtAddr::from(global_allocator().alloc_pages(1, PAGE_SIZE_4K).ok()?);
+    let paddr = alloc_frames(1, PAGE_SIZE_4K, FrameZone::Normal).ok()?;
     if zeroed {
+        let vaddr = phys_to_virt(paddr);
         unsafe { core::ptr::write_bytes(vaddr.as_mut_ptr(), 0, PAGE_SIZE_4K) };
     }
-    let paddr = virt_to_phys(vaddr);
     Some(paddr)
 }
 
 fn dealloc_frame(frame: PhysAddr) {
-    let vaddr = phys_to_virt(frame);
-    global_allocator().dealloc_pages(vaddr.as_usize(), 1);
+    dealloc_frames(frame, 1);
 }
 
 impl Backend {
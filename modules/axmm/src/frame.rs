@@ -0,0 +1,275 @@
+//! Allocation of physical frames, for the page tables, the user memory and the
+//! device drivers.
+//!
+//! The frames are allocated from the page allocator of [`axalloc`], which
+//! manages them by their addresses in the linear mapping, so that contiguous
+//! pages are also physically contiguous. The frames may be restricted to a
+//! [`FrameZone`], for devices that can only address the low physical memory.
+//!
+//! Each restricted zone has its own allocator, over a region of the low
+//! memory reserved at boot (see [`axhal::mem::dma_zone_region`]), as the
+//! global allocator may place its pages anywhere. The frames of a zone are
+//! allocated from its region first, then from the regions of the lower zones,
+//! as Linux does. The global allocator and the CMA region are tried last, and
+//! their pages are only kept if they happen to be in the zone. The frames of
+//! [`FrameZone::Normal`] fall back to the regions of the restricted zones
+//! when the global allocator is full.
+
+use core::ops::Range;
+
+use allocator::{BaseAllocator, BitmapPageAllocator, PageAllocator};
+use axalloc::{CmaPolicy, cma_alloc_pages, cma_dealloc_pages, global_allocator};
+use axerrno::{AxError, AxResult};
+use axhal::mem::{DMA_ZONE_LIMIT, DMA32_ZONE_LIMIT, phys_to_virt, virt_to_phys};
+use kspin::SpinNoIrq;
+use memory_addr::{MemoryAddr, PAGE_SIZE_4K, PhysAddr, VirtAddr};
+
+/// The physical memory a frame may be allocated from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameZone {
+    /// Below 16 MiB, for the devices with 24-bit DMA addresses, e.g. ISA
+    /// ones.
+    Dma,
+    /// Below 4 GiB, for the devices with 32-bit DMA addresses.
+    Dma32,
+    /// Anywhere.
+    Normal,
+}
+
+impl FrameZone {
+    /// Returns the end of the physical memory of the zone, or `None` if it's
+    /// unbounded.
+    pub const fn limit(self) -> Option<usize> {
+        match self {
+            Self::Dma => Some(DMA_ZONE_LIMIT),
+            Self::Dma32 => Some(DMA32_ZONE_LIMIT),
+            Self::Normal => None,
+        }
+    }
+
+    /// Whether the frames in `[paddr, paddr + size)` are in the zone.
+    pub fn contains(self, paddr: PhysAddr, size: usize) -> bool {
+        self.limit().is_none_or(|limit| {
+            paddr
+                .as_usize()
+                .checked_add(size)
+                .is_some_and(|end| end <= limit)
+        })
+    }
+
+    /// Returns the restricted zones whose regions the frames of the zone are
+    /// allocated from, in order: the zone itself, then the lower ones.
+    pub(crate) fn region_zones(self) -> &'static [FrameZone] {
+        match self {
+            Self::Dma => &[Self::Dma],
+            Self::Dma32 | Self::Normal => &[Self::Dma32, Self::Dma],
+        }
+    }
+}
+
+/// The allocator of the frames in the region of a restricted zone.
+pub(crate) struct ZoneRegion {
+    /// The addresses of the region in the linear mapping.
+    range: Range<usize>,
+    palloc: BitmapPageAllocator<PAGE_SIZE_4K>,
+}
+
+impl ZoneRegion {
+    pub(crate) const fn new() -> Self {
+        Self {
+            range: 0..0,
+            palloc: BitmapPageAllocator::new(),
+        }
+    }
+
+    pub(crate) fn init(&mut self, start_vaddr: usize, size: usize) {
+        self.range = start_vaddr..start_vaddr + size;
+        self.palloc.init(start_vaddr, size);
+    }
+
+    pub(crate) fn alloc(&mut self, num_frames: usize, align: usize) -> Option<usize> {
+        if self.range.is_empty() {
+            return None;
+        }
+        self.palloc.alloc_pages(num_frames, align).ok()
+    }
+
+    /// Returns `false` if the frames are not in the region.
+    pub(crate) fn dealloc(&mut self, vaddr: usize, num_frames: usize) -> bool {
+        if self.range.contains(&vaddr) {
+            self.palloc.dealloc_pages(vaddr, num_frames);
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// The regions of [`FrameZone::Dma`] and [`FrameZone::Dma32`].
+static ZONE_REGIONS: [SpinNoIrq<ZoneRegion>; 2] = [const { SpinNoIrq::new(ZoneRegion::new()) }; 2];
+
+fn zone_region(zone: FrameZone) -> &'static SpinNoIrq<ZoneRegion> {
+    match zone {
+        FrameZone::Dma => &ZONE_REGIONS[0],
+        FrameZone::Dma32 => &ZONE_REGIONS[1],
+        FrameZone::Normal => unreachable!(),
+    }
+}
+
+/// Sets up the allocators of the regions of the restricted zones.
+pub(crate) fn init() {
+    for zone in [FrameZone::Dma, FrameZone::Dma32] {
+        let limit = zone.limit().unwrap();
+        if let Some((paddr, size)) = axhal::mem::dma_zone_region(limit) {
+            debug!("frame zone {:?}: [{:#x}, {:#x})", zone, paddr, paddr + size);
+            zone_region(zone)
+                .lock()
+                .init(phys_to_virt(paddr).as_usize(), size);
+        }
+    }
+}
+
+/// Checks the frames at `vaddr` and gives them back if they are out of `zone`
+/// or not aligned to `align` physically.
+fn check_frames(
+    vaddr: usize,
+    num_frames: usize,
+    align: usize,
+    zone: FrameZone,
+) -> Option<PhysAddr> {
+    let paddr = virt_to_phys(VirtAddr::from(vaddr));
+    if zone.contains(paddr, num_frames * PAGE_SIZE_4K) && paddr.is_aligned(align) {
+        Some(paddr)
+    } else {
+        cma_dealloc_pages(vaddr, num_frames);
+        None
+    }
+}
+
+/// Allocates the frames from the regions of the restricted zones.
+fn alloc_from_regions(num_frames: usize, align: usize, zone: FrameZone) -> Option<PhysAddr> {
+    zone.region_zones().iter().find_map(|&region_zone| {
+        let mut region = zone_region(region_zone).lock();
+        let vaddr = region.alloc(num_frames, align)?;
+        let paddr = virt_to_phys(VirtAddr::from(vaddr));
+        // the linear mapping may be less aligned than the physical memory
+        if paddr.is_aligned(align) {
+            Some(paddr)
+        } else {
+            region.dealloc(vaddr, num_frames);
+            None
+        }
+    })
+}
+
+/// Allocates the frames from the global allocator, or from the CMA region if
+/// there are multiple ones.
+fn alloc_from_global(num_frames: usize, align: usize, zone: FrameZone) -> Option<PhysAddr> {
+    // the physical alignment is the virtual one unless the linear mapping is
+    // less aligned, which is checked then
+    let from_global = global_allocator()
+        .alloc_pages(num_frames, align)
+        .ok()
+        .and_then(|vaddr| check_frames(vaddr, num_frames, align, zone));
+    if from_global.is_some() || num_frames == 1 {
+        return from_global;
+    }
+    // the CMA region is kept for large buffers, never for a single frame
+    cma_alloc_pages(num_frames, align, CmaPolicy::Strict)
+        .ok()
+        .and_then(|vaddr| check_frames(vaddr, num_frames, align, zone))
+}
+
+/// Allocates `num_frames` physically contiguous frames in `zone`, whose
+/// physical address is aligned to `align`, returns the physical address of the
+/// first one.
+///
+/// `align` must be a power of 2, at least the size of a frame. It fails with
+/// [`AxError::NoMemory`] if there are no such frames free.
+pub fn alloc_frames(num_frames: usize, align: usize, zone: FrameZone) -> AxResult<PhysAddr> {
+    if num_frames == 0 || !align.is_power_of_two() || align < PAGE_SIZE_4K {
+        return Err(AxError::InvalidInput);
+    }
+    let paddr = if zone == FrameZone::Normal {
+        alloc_from_global(num_frames, align, zone)
+            .or_else(|| alloc_from_regions(num_frames, align, zone))
+    } else {
+        alloc_from_regions(num_frames, align, zone)
+            .or_else(|| alloc_from_global(num_frames, align, zone))
+    };
+    paddr.ok_or(AxError::NoMemory)
+}
+
+/// Gives back the frames allocated by [`alloc_frames`].
+pub fn dealloc_frames(paddr: PhysAddr, num_frames: usize) {
+    let vaddr = phys_to_virt(paddr).as_usize();
+    if ZONE_REGIONS
+        .iter()
+        .any(|region| region.lock().dealloc(vaddr, num_frames))
+    {
+        return;
+    }
+    // whether they are in the CMA region or not
+    cma_dealloc_pages(vaddr, num_frames);
+}
+
+/// Physically contiguous frames, given back when it's dropped.
+#[derive(Debug)]
+pub struct PhysFrames {
+    start_paddr: PhysAddr,
+    num_frames: usize,
+}
+
+impl PhysFrames {
+    /// Allocates `num_frames` physically contiguous frames, see
+    /// [`alloc_frames`].
+    pub fn alloc(num_frames: usize, align: usize, zone: FrameZone) -> AxResult<Self> {
+        let start_paddr = alloc_frames(num_frames, align, zone)?;
+        Ok(Self {
+            start_paddr,
+            num_frames,
+        })
+    }
+
+    /// Allocates `num_frames` physically contiguous frames filled with zero,
+    /// see [`alloc_frames`].
+    pub fn alloc_zero(num_frames: usize, align: usize, zone: FrameZone) -> AxResult<Self> {
+        let frames = Self::alloc(num_frames, align, zone)?;
+        unsafe { core::ptr::write_bytes(frames.start_vaddr().as_mut_ptr(), 0, frames.size()) };
+        Ok(frames)
+    }
+
+    /// Returns the physical address of the first frame.
+    pub fn start_paddr(&self) -> PhysAddr {
+        self.start_paddr
+    }
+
+    /// Returns the address of the first frame in the linear mapping.
+    pub fn start_vaddr(&self) -> VirtAddr {
+        phys_to_virt(self.start_paddr)
+    }
+
+    /// Returns the number of the frames.
+    pub fn num_frames(&self) -> usize {
+        self.num_frames
+    }
+
+    /// Returns the total size of the frames in bytes.
+    pub fn size(&self) -> usize {
+        self.num_frames * PAGE_SIZE_4K
+    }
+
+    /// Gives up the ownership of the frames, returning the physical address
+    /// of the first one, to be given back with [`dealloc_frames`].
+    pub fn leak(self) -> PhysAddr {
+        let paddr = self.start_paddr;
+        core::mem::forget(self);
+        paddr
+    }
+}
+
+impl Drop for PhysFrames {
+    fn drop(&mut self) {
+        dealloc_frames(self.start_paddr, self.num_frames);
+    }
+}
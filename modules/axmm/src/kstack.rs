@@ -0,0 +1,159 @@
+//! Kernel stacks of the tasks, mapped in the kernel address space with a
+//! guard area below each of them, so that an overflow faults rather than
+//! silently corrupting the memory around.
+//!
+//! The stacks are allocated in a window of [`KSTACK_WINDOW_SIZE`] bytes,
+//! placed at random in the kernel address space at boot. The window is
+//! aligned to its size, so it lies in a single entry of the top-level page
+//! table with all the supported paging modes, and this entry is filled
+//! before any user address space copies the kernel mappings (see
+//! [`AddrSpace::copy_mappings_from`]): the stacks mapped later are seen from
+//! all the address spaces.
+//!
+//! The range of a stack given back isn't reused before the stale TLB entries
+//! of the other CPUs are flushed by an IPI. Without IRQs on multiple CPUs,
+//! the stacks given back stay mapped, and are reused by the next allocations
+//! of the same size instead.
+//!
+//! Only x86_64 switches to another stack on the fault of an overflow, to
+//! report it (see the double fault of `axhal`). On the other architectures,
+//! the trap entry faults again in the guard area, so the CPU hangs, but the
+//! memory below the stack is never written.
+
+use axerrno::{AxError, AxResult, ax_err};
+use axhal::paging::MappingFlags;
+use lazyinit::LazyInit;
+use memory_addr::{PAGE_SIZE_4K, VirtAddr, VirtAddrRange, align_up_4k};
+
+use crate::{AddrSpace, kernel_aspace};
+
+/// The size of the guard area below a kernel stack, never accessible.
+pub const STACK_GUARD_SIZE: usize = PAGE_SIZE_4K;
+
+/// The size of the window of the kernel stacks.
+pub(crate) const KSTACK_WINDOW_SIZE: usize = 1 << 30;
+
+/// The window of the kernel stacks, without the page keeping its page tables.
+static KSTACK_WINDOW: LazyInit<VirtAddrRange> = LazyInit::new();
+
+/// The stacks given back, as `(top, size)`, kept mapped as they can't be
+/// unmapped from the other CPUs without IPIs.
+#[cfg(all(feature = "smp", not(feature = "irq")))]
+static FREE_STACKS: kspin::SpinNoIrq<alloc::vec::Vec<(VirtAddr, usize)>> =
+    kspin::SpinNoIrq::new(alloc::vec::Vec::new());
+
+/// Returns the starts of the windows of [`KSTACK_WINDOW_SIZE`] bytes aligned
+/// to their size in `range`.
+pub(crate) fn window_slots(range: VirtAddrRange) -> impl Iterator<Item = VirtAddr> {
+    let first = range.start.as_usize().div_ceil(KSTACK_WINDOW_SIZE);
+    let last = range.end.as_usize() / KSTACK_WINDOW_SIZE;
+    (first..last).map(|i| VirtAddr::from(i * KSTACK_WINDOW_SIZE))
+}
+
+/// Reserves the window of the kernel stacks in the kernel address space
+/// `aspace`, at a random free place above the base of its mappings.
+pub(crate) fn init_kstack_window(aspace: &mut AddrSpace) -> AxResult {
+    let range = VirtAddrRange::new(aspace.layout().mmap_base(), aspace.end());
+    let is_free = |start: &VirtAddr| {
+        let slot = VirtAddrRange::from_start_size(*start, KSTACK_WINDOW_SIZE);
+        aspace.find_free_area(*start, KSTACK_WINDOW_SIZE, slot) == Some(*start)
+    };
+    let count = window_slots(range).filter(is_free).count();
+    if count == 0 {
+        return ax_err!(NoMemory, "no room for the kernel stacks");
+    }
+    let nth = if crate::aslr::is_randomized() {
+        axhal::random::random_u64() as usize % count
+    } else {
+        0
+    };
+    let start = window_slots(range).filter(is_free).nth(nth).unwrap();
+
+    // a page kept mapped, so that the page tables of the window are never
+    // freed
+    aspace.map_alloc(start, PAGE_SIZE_4K, MappingFlags::READ, true)?;
+    KSTACK_WINDOW.init_once(VirtAddrRange::from_start_size(
+        start + PAGE_SIZE_4K,
+        KSTACK_WINDOW_SIZE - PAGE_SIZE_4K,
+    ));
+    Ok(())
+}
+
+/// Returns the window of the kernel stacks.
+pub(crate) fn kstack_window() -> VirtAddrRange {
+    *KSTACK_WINDOW
+}
+
+/// Allocates a kernel stack of `size` bytes (rounded up to pages), and
+/// returns its top.
+///
+/// The stack is populated at once.
+pub fn alloc_kernel_stack(size: usize) -> AxResult<VirtAddr> {
+    let size = align_up_4k(size);
+    #[cfg(all(feature = "smp", not(feature = "irq")))]
+    {
+        let mut free = FREE_STACKS.lock();
+        if let Some(i) = free.iter().position(|&(_, s)| s == size) {
+            return Ok(free.swap_remove(i).0);
+        }
+    }
+
+    let window = kstack_window();
+    let mut aspace = kernel_aspace().lock();
+    let bottom = aspace
+        .find_free_area(window.start, STACK_GUARD_SIZE + size, window)
+        .ok_or(AxError::NoMemory)?
+        + STACK_GUARD_SIZE;
+    let flags = MappingFlags::READ | MappingFlags::WRITE;
+    aspace.map_alloc(bottom, size, flags, true)?;
+    if let Err(e) = aspace.map_reserved(bottom - STACK_GUARD_SIZE, STACK_GUARD_SIZE) {
+        aspace.unmap(bottom, size).ok();
+        return Err(e);
+    }
+    Ok(bottom + size)
+}
+
+/// Gives back the kernel stack of `size` bytes at `top`, allocated by
+/// [`alloc_kernel_stack`], with its guard area.
+///
+/// It waits for the other CPUs to flush their TLB, so it must be called with
+/// IRQs enabled.
+pub fn dealloc_kernel_stack(top: VirtAddr, size: usize) {
+    let size = align_up_4k(size);
+    #[cfg(all(feature = "smp", not(feature = "irq")))]
+    {
+        FREE_STACKS.lock().push((top, size));
+    }
+
+    #[cfg(not(all(feature = "smp", not(feature = "irq"))))]
+    {
+        let bottom = top - size;
+        let res = {
+            let mut aspace = kernel_aspace().lock();
+            // keep the range reserved until the other CPUs forget it
+            aspace
+                .unmap(bottom, size)
+                .and_then(|_| aspace.map_reserved(bottom, size))
+        };
+        if let Err(e) = res {
+            warn!(
+                "failed to unmap the kernel stack at {:#x}: {:?}",
+                top.as_usize(),
+                e
+            );
+            return;
+        }
+        #[cfg(feature = "smp")]
+        axhal::irq::ipi::run_on_other_cpus(&|| {
+            let mut vaddr = bottom;
+            while vaddr < top {
+                axhal::arch::flush_tlb(Some(vaddr));
+                vaddr += PAGE_SIZE_4K;
+            }
+        });
+        kernel_aspace()
+            .lock()
+            .unmap(bottom - STACK_GUARD_SIZE, size + STACK_GUARD_SIZE)
+            .ok();
+    }
+}
@@ -1,22 +1,34 @@
 //! [ArceOS](https://github.com/arceos-org/arceos) memory management module.
 
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 
 #[macro_use]
 extern crate log;
 extern crate alloc;
 
+mod aslr;
 mod aspace;
 mod backend;
+mod frame;
+mod kstack;
 
+#[cfg(test)]
+mod tests;
+
+pub use self::aslr::{
+    AddrLayout, randomize_va_space, set_addr_no_randomize, set_randomize_va_space,
+};
 pub use self::aspace::AddrSpace;
 pub use self::backend::Backend;
+pub use self::frame::{FrameZone, PhysFrames, alloc_frames, dealloc_frames};
+pub use self::kstack::{STACK_GUARD_SIZE, alloc_kernel_stack, dealloc_kernel_stack};
 
 use axerrno::{AxError, AxResult};
 use axhal::mem::phys_to_virt;
+use axhal::paging::MappingFlags;
 use kspin::SpinNoIrq;
 use lazyinit::LazyInit;
-use memory_addr::{PhysAddr, va};
+use memory_addr::{MemoryAddr, PhysAddr, va};
 use memory_set::MappingError;
 
 static KERNEL_ASPACE: LazyInit<SpinNoIrq<AddrSpace>> = LazyInit::new();
@@ -31,11 +43,15 @@ fn mapping_err_to_ax_err(err: MappingError) -> AxError {
 }
 
 /// Creates a new address space for kernel itself.
+///
+/// No mapping in it may be both writable and executable (W^X, see
+/// [`AddrSpace::enforce_wx`]).
 pub fn new_kernel_aspace() -> AxResult<AddrSpace> {
     let mut aspace = AddrSpace::new_empty(
         va!(axconfig::plat::KERNEL_ASPACE_BASE),
         axconfig::plat::KERNEL_ASPACE_SIZE,
     )?;
+    aspace.enforce_wx();
     for r in axhal::mem::memory_regions() {
         aspace.map_linear(phys_to_virt(r.paddr), r.paddr, r.size, r.flags.into())?;
     }
@@ -52,6 +68,47 @@ pub fn kernel_page_table_root() -> PhysAddr {
     KERNEL_ASPACE.lock().page_table_root()
 }
 
+/// Adds a region of RAM discovered after boot (memory hotplug), e.g. by
+/// virtio-mem or a device tree overlay.
+///
+/// The region is registered in [`axhal::mem::memory_regions`], mapped to the
+/// linear mapping of the kernel address space, and given to the global
+/// allocator. It can't be removed later.
+///
+/// It fails with [`AxError::AlreadyExists`] if the region overlaps with a
+/// known one, and with [`AxError::NoMemory`] if there are too many regions.
+pub fn hotplug_memory(paddr: PhysAddr, size: usize) -> AxResult {
+    if size == 0 || !paddr.is_aligned_4k() || !size.is_aligned_4k() {
+        return Err(AxError::InvalidInput);
+    }
+    let end = paddr
+        .as_usize()
+        .checked_add(size)
+        .ok_or(AxError::InvalidInput)?;
+
+    // the lock serializes the hotplugs as well
+    let mut aspace = KERNEL_ASPACE.lock();
+    if axhal::mem::memory_regions().any(|r| r.paddr.as_usize() < end && paddr < r.paddr + r.size) {
+        return Err(AxError::AlreadyExists);
+    }
+    if !axhal::mem::add_hotplug_region(paddr, size) {
+        return Err(AxError::NoMemory);
+    }
+    let vaddr = phys_to_virt(paddr);
+    let flags = MappingFlags::READ | MappingFlags::WRITE;
+    if let Err(e) = aspace.map_linear(vaddr, paddr, size, flags) {
+        axhal::mem::remove_hotplug_region(paddr);
+        return Err(e);
+    }
+    if let Err(e) = axalloc::global_add_hotplug_memory(vaddr.as_usize(), size) {
+        warn!("failed to add hotplugged memory to the allocator: {:?}", e);
+        let _ = aspace.unmap(vaddr, size);
+        axhal::mem::remove_hotplug_region(paddr);
+        return Err(AxError::NoMemory);
+    }
+    Ok(())
+}
+
 /// Initializes virtual memory management.
 ///
 /// It mainly sets up the kernel virtual memory address space and recreate a
@@ -59,10 +116,35 @@ pub fn kernel_page_table_root() -> PhysAddr {
 pub fn init_memory_management() {
     info!("Initialize virtual memory management...");
 
-    let kernel_aspace = new_kernel_aspace().expect("failed to initialize kernel address space");
+    frame::init();
+    let mut kernel_aspace = new_kernel_aspace().expect("failed to initialize kernel address space");
+    kstack::init_kstack_window(&mut kernel_aspace).expect("failed to reserve the kernel stacks");
     debug!("kernel address space init OK: {:#x?}", kernel_aspace);
+    let layout = *kernel_aspace.layout();
     KERNEL_ASPACE.init_once(SpinNoIrq::new(kernel_aspace));
     axhal::paging::set_kernel_page_table_root(kernel_page_table_root());
+
+    // the kernel image is linked at a fixed address, so only the mappings
+    // and the kernel stacks are randomized
+    info!("Kernel memory layout:");
+    for r in axhal::mem::memory_regions() {
+        let vaddr = phys_to_virt(r.paddr).as_usize();
+        info!(
+            "  [{:#x}, {:#x}) {:?} {}",
+            vaddr,
+            vaddr + r.size,
+            MappingFlags::from(r.flags),
+            r.name
+        );
+    }
+    let kstacks = kstack::kstack_window();
+    info!(
+        "  [{:#x}, {:#x}) kernel stacks, with guard areas of {:#x} bytes",
+        kstacks.start.as_usize(),
+        kstacks.end.as_usize(),
+        STACK_GUARD_SIZE
+    );
+    info!("  mappings from {:#x}", layout.mmap_base().as_usize());
 }
 
 /// Initializes kernel paging for secondary CPUs.
@@ -0,0 +1,92 @@
+use axhal::paging::MappingFlags;
+use memory_addr::{PAGE_SIZE_4K, VirtAddr, VirtAddrRange, pa, va};
+
+use crate::FrameZone;
+use crate::aspace::is_wx;
+use crate::frame::ZoneRegion;
+use crate::kstack::{KSTACK_WINDOW_SIZE, window_slots};
+
+fn range(start: usize, end: usize) -> VirtAddrRange {
+    VirtAddrRange::new(va!(start), va!(end))
+}
+
+#[test]
+fn test_kstack_window_slots() {
+    const GIB: usize = KSTACK_WINDOW_SIZE;
+
+    // only the windows wholly in the range, aligned to their size
+    let slots: Vec<VirtAddr> = window_slots(range(GIB / 2, 3 * GIB + 1)).collect();
+    assert_eq!(slots, [va!(GIB), va!(2 * GIB)]);
+    assert!(
+        window_slots(range(GIB + 1, 2 * GIB + GIB / 2))
+            .next()
+            .is_none()
+    );
+
+    // at the top of the address space
+    let top = range(0xffff_ff80_0000_0000, 0xffff_ffff_ffff_f000);
+    let slots: Vec<VirtAddr> = window_slots(top).collect();
+    assert_eq!(slots.len(), 511);
+    assert_eq!(*slots.last().unwrap(), va!(0xffff_ffff_8000_0000));
+    assert!(slots.iter().all(|s| s.as_usize() % GIB == 0));
+}
+
+#[test]
+fn test_wx() {
+    assert!(is_wx(MappingFlags::WRITE | MappingFlags::EXECUTE));
+    assert!(is_wx(
+        MappingFlags::READ | MappingFlags::WRITE | MappingFlags::EXECUTE
+    ));
+    assert!(!is_wx(MappingFlags::READ | MappingFlags::EXECUTE));
+    assert!(!is_wx(MappingFlags::READ | MappingFlags::WRITE));
+}
+
+#[test]
+fn test_frame_zone_contains() {
+    assert!(FrameZone::Dma.contains(pa!(0), 16 << 20));
+    assert!(!FrameZone::Dma.contains(pa!(0), (16 << 20) + PAGE_SIZE_4K));
+    assert!(!FrameZone::Dma.contains(pa!(16 << 20), PAGE_SIZE_4K));
+    assert!(FrameZone::Dma32.contains(pa!((4 << 30) - PAGE_SIZE_4K), PAGE_SIZE_4K));
+    assert!(!FrameZone::Dma32.contains(pa!((4 << 30) - PAGE_SIZE_4K), 2 * PAGE_SIZE_4K));
+    // no overflow at the top of the address space
+    assert!(!FrameZone::Dma32.contains(pa!(usize::MAX - PAGE_SIZE_4K + 1), 2 * PAGE_SIZE_4K));
+    assert!(FrameZone::Normal.contains(pa!(usize::MAX - PAGE_SIZE_4K + 1), 2 * PAGE_SIZE_4K));
+}
+
+#[test]
+fn test_frame_zone_fallback() {
+    // the zone itself first, then the lower ones, never the higher ones
+    assert_eq!(FrameZone::Dma.region_zones(), [FrameZone::Dma]);
+    assert_eq!(
+        FrameZone::Dma32.region_zones(),
+        [FrameZone::Dma32, FrameZone::Dma]
+    );
+    assert_eq!(
+        FrameZone::Normal.region_zones(),
+        [FrameZone::Dma32, FrameZone::Dma]
+    );
+}
+
+#[test]
+fn test_zone_region() {
+    // only the bookkeeping, the memory itself is never accessed
+    const BASE: usize = 0x4000_0000;
+    let mut region = ZoneRegion::new();
+    assert_eq!(region.alloc(1, PAGE_SIZE_4K), None);
+    assert!(!region.dealloc(BASE, 1));
+
+    region.init(BASE, 16 * PAGE_SIZE_4K);
+    let a = region.alloc(4, 4 * PAGE_SIZE_4K).unwrap();
+    assert!((BASE..BASE + 16 * PAGE_SIZE_4K).contains(&a));
+    assert_eq!(a % (4 * PAGE_SIZE_4K), 0);
+    let b = region.alloc(12, PAGE_SIZE_4K).unwrap();
+    assert!(b + 12 * PAGE_SIZE_4K <= BASE + 16 * PAGE_SIZE_4K);
+    // full
+    assert_eq!(region.alloc(1, PAGE_SIZE_4K), None);
+
+    // the frames out of the region are not taken
+    assert!(!region.dealloc(BASE + 16 * PAGE_SIZE_4K, 1));
+    assert!(region.dealloc(a, 4));
+    assert_eq!(region.alloc(4, PAGE_SIZE_4K), Some(a));
+    assert!(region.dealloc(b, 12));
+}
@@ -7,8 +7,44 @@
 //! # Organization
 //!
 //! - [`TcpSocket`]: A TCP socket that provides POSIX-like APIs.
+//!   [`TcpSocket::connect_host`] and [`TcpSocket::connect_any`] connect to
+//!   hosts with several addresses, as configured by [`ConnectOptions`].
+//! - [`TcpInfo`], [`tcp_connections`]: Statistics of TCP connections, from
+//!   [`TcpSocket::tcp_info`] or of all the connections. [`proc_net_tcp`]
+//!   formats the connections as `/proc/net/tcp`.
 //! - [`UdpSocket`]: A UDP socket that provides POSIX-like APIs.
+//! - [`IcmpSocket`]: An ICMP echo ("ping") socket that provides POSIX-like APIs.
+//! - [`RawSocket`]: A raw IPv4 socket that provides POSIX-like APIs.
 //! - [`dns_query`]: Function for DNS query.
+//! - [`dns_reverse_query`]: Function for reverse DNS query.
+//! - [`set_nameservers`], [`load_resolv_conf`]: Functions to configure the
+//!   DNS resolver.
+//! - [`start_mdns`], [`stop_mdns`], [`register_mdns_service`],
+//!   [`unregister_mdns_service`]: Functions to advertise the host and its
+//!   services with mDNS. Names under `.local` are resolved with mDNS.
+//! - [`interfaces`], [`set_interface_addr`], [`set_interface_mtu`],
+//!   [`set_interface_rate`]: Functions to list and configure network
+//!   interfaces.
+//! - [`routes`], [`add_route`], [`del_route`]: Functions to manage the routing
+//!   tables. [`route_rules`], [`add_route_rule`], [`del_route_rule`]: Functions
+//!   to manage the policy rules choosing the tables. [`proc_net_route`]
+//!   formats the main table as `/proc/net/route`.
+//! - [`add_filter_rule`], [`del_filter_rule`], [`flush_filter_rules`],
+//!   [`filter_rules`]: Functions to manage the packet filter.
+//! - [`BpfProgram`]: A classic BPF program, attached to the sockets to filter
+//!   the packets they receive (`SO_ATTACH_FILTER`).
+//! - [`NetNamespace`], [`unshare_net_namespace`], [`set_net_namespace`]:
+//!   Network namespaces, with their own interfaces, routes and sockets.
+//!   [`add_veth_pair`] connects two of them, and [`del_veth`] disconnects
+//!   them.
+//!
+//! Each NIC becomes an interface named `eth0`, `eth1`, etc. Only `eth0` is
+//! configured at boot, with the address and gateway given at build time.
+//! Besides the NICs, there's always a loopback interface `lo` with the address
+//! `127.0.0.1/8`. The stack is IPv4-only, so `::1` is not available.
+//!
+//! The NICs are in the initial network namespace. The interfaces, routes and
+//! sockets are those of the [current](current_net_namespace) namespace.
 //!
 //! # Cargo Features
 //!
@@ -30,18 +66,55 @@ cfg_if::cfg_if! {
     }
 }
 
-pub use self::net_impl::TcpSocket;
 pub use self::net_impl::UdpSocket;
+pub use self::net_impl::{BpfInsn, BpfProgram};
+pub use self::net_impl::{ConnectOptions, TcpSocket};
+pub use self::net_impl::{
+    FilterAction, FilterHook, FilterRule, add_filter_rule, del_filter_rule, filter_rules,
+    flush_filter_rules,
+};
+pub use self::net_impl::{IcmpSocket, RawSocket};
+pub use self::net_impl::{
+    InterfaceInfo, InterfaceStats, MAIN_TABLE, Route, RouteRule, add_route, add_route_rule,
+    del_route, del_route_rule, interfaces, proc_net_route, route_rules, routes, set_interface_addr,
+    set_interface_mtu, set_interface_rate,
+};
+pub use self::net_impl::{
+    NetNamespace, add_veth_pair, current_net_namespace, del_veth, set_net_namespace,
+    unshare_net_namespace,
+};
+pub use self::net_impl::{TcpConnection, TcpInfo, TcpState, proc_net_tcp, tcp_connections};
 pub use self::net_impl::{bench_receive, bench_transmit};
-pub use self::net_impl::{dns_query, poll_interfaces};
+pub use self::net_impl::{
+    dns_query, dns_reverse_query, load_resolv_conf, nameservers, poll_interfaces, set_nameservers,
+};
+pub use self::net_impl::{register_mdns_service, start_mdns, stop_mdns, unregister_mdns_service};
 
 use axdriver::{AxDeviceContainer, prelude::*};
 
 /// Initializes the network subsystem by NIC devices.
+///
+/// The loopback interface (`127.0.0.1/8`) is always created, so local
+/// communication works even if there's no NIC.
 pub fn init_network(mut net_devs: AxDeviceContainer<AxNetDevice>) {
     info!("Initialize network subsystem...");
 
-    let dev = net_devs.take_one().expect("No NIC device found!");
-    info!("  use NIC 0: {:?}", dev.device_name());
-    net_impl::init(dev);
+    let mut devs = alloc::vec::Vec::new();
+    while let Some(dev) = net_devs.take_one() {
+        info!("  use NIC {}: {:?}", devs.len(), dev.device_name());
+        devs.push(dev);
+    }
+    if devs.is_empty() {
+        warn!("  no NIC device found, only loopback is available");
+    }
+    net_impl::init(devs);
+}
+
+/// Initializes the network subsystem on a secondary CPU, once its scheduler
+/// is initialized.
+///
+/// The frames received by the NICs whose queues interrupt this CPU are
+/// handled on it.
+pub fn init_network_secondary() {
+    net_impl::init_secondary();
 }
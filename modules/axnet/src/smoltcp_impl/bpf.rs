@@ -0,0 +1,318 @@
+//! Classic BPF socket filters (`SO_ATTACH_FILTER`).
+//!
+//! A filter is a program run on each packet received by a socket, before it's
+//! delivered. It returns the number of bytes of the packet to keep: 0 drops the
+//! packet, and a smaller length truncates it.
+//!
+//! The packet seen by the filter starts with the header of the transport
+//! protocol for UDP sockets, with the ICMP header for ICMP sockets, and with
+//! the IPv4 header for raw sockets. The ancillary data and the headers at
+//! negative offsets (`SKF_AD_OFF`, `SKF_NET_OFF` and `SKF_LL_OFF`) are not
+//! supported.
+
+use alloc::vec::Vec;
+
+use axerrno::{AxResult, ax_err};
+use spin::RwLock;
+
+/// The maximum number of instructions of a program.
+const MAX_INSNS: usize = 4096;
+/// The number of words of the scratch memory.
+const MEM_WORDS: usize = 16;
+/// The smallest offset of the ancillary data and the lower layer headers.
+const SKF_LL_OFF: u32 = -0x20_0000i32 as u32;
+
+// instruction classes
+const BPF_LD: u16 = 0x00;
+const BPF_LDX: u16 = 0x01;
+const BPF_ST: u16 = 0x02;
+const BPF_STX: u16 = 0x03;
+const BPF_ALU: u16 = 0x04;
+const BPF_JMP: u16 = 0x05;
+const BPF_RET: u16 = 0x06;
+const BPF_MISC: u16 = 0x07;
+
+// load sizes
+const BPF_W: u16 = 0x00;
+const BPF_H: u16 = 0x08;
+const BPF_B: u16 = 0x10;
+
+// load modes
+const BPF_IMM: u16 = 0x00;
+const BPF_ABS: u16 = 0x20;
+const BPF_IND: u16 = 0x40;
+const BPF_MEM: u16 = 0x60;
+const BPF_LEN: u16 = 0x80;
+const BPF_MSH: u16 = 0xa0;
+
+// ALU operations
+const BPF_ADD: u16 = 0x00;
+const BPF_SUB: u16 = 0x10;
+const BPF_MUL: u16 = 0x20;
+const BPF_DIV: u16 = 0x30;
+const BPF_OR: u16 = 0x40;
+const BPF_AND: u16 = 0x50;
+const BPF_LSH: u16 = 0x60;
+const BPF_RSH: u16 = 0x70;
+const BPF_NEG: u16 = 0x80;
+const BPF_MOD: u16 = 0x90;
+const BPF_XOR: u16 = 0xa0;
+
+// jumps
+const BPF_JA: u16 = 0x00;
+const BPF_JEQ: u16 = 0x10;
+const BPF_JGT: u16 = 0x20;
+const BPF_JGE: u16 = 0x30;
+const BPF_JSET: u16 = 0x40;
+
+// operand sources
+const BPF_K: u16 = 0x00;
+const BPF_X: u16 = 0x08;
+const BPF_A: u16 = 0x10;
+
+// miscellaneous operations
+const BPF_TAX: u16 = 0x00;
+const BPF_TXA: u16 = 0x80;
+
+/// A classic BPF instruction, laid out as `struct sock_filter`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BpfInsn {
+    /// The opcode.
+    pub code: u16,
+    /// The offset of the next instruction if a condition holds.
+    pub jt: u8,
+    /// The offset of the next instruction if a condition doesn't hold.
+    pub jf: u8,
+    /// The operand.
+    pub k: u32,
+}
+
+/// A classic BPF program, checked to be safe to run.
+#[derive(Debug, Clone)]
+pub struct BpfProgram {
+    insns: Vec<BpfInsn>,
+}
+
+impl BpfProgram {
+    /// Checks the instructions `insns`, and returns the program made of them.
+    ///
+    /// The program must have 1 to 4096 valid instructions, its jumps must stay
+    /// within it, and it must end with a return. Otherwise, it fails with
+    /// [`Err(InvalidInput)`](axerrno::AxError::InvalidInput).
+    pub fn new(insns: &[BpfInsn]) -> AxResult<Self> {
+        if insns.is_empty() || insns.len() > MAX_INSNS {
+            return ax_err!(InvalidInput, "BPF program too long or empty");
+        }
+        for (pc, insn) in insns.iter().enumerate() {
+            // the number of instructions after this one
+            let remaining = insns.len() - pc - 1;
+            if !check_insn(insn, remaining) {
+                debug!("invalid BPF instruction {}: {:x?}", pc, insn);
+                return ax_err!(InvalidInput, "invalid BPF instruction");
+            }
+        }
+        if insns.last().unwrap().code & 0x07 != BPF_RET {
+            return ax_err!(InvalidInput, "BPF program doesn't end with a return");
+        }
+        Ok(Self {
+            insns: insns.to_vec(),
+        })
+    }
+
+    /// Runs the program on `packet`, and returns the number of bytes to keep.
+    ///
+    /// Loads out of the packet and divisions by zero return 0.
+    pub fn run(&self, packet: &[u8]) -> u32 {
+        let mut a: u32 = 0;
+        let mut x: u32 = 0;
+        let mut mem = [0u32; MEM_WORDS];
+        let mut pc = 0;
+        loop {
+            let insn = &self.insns[pc];
+            let k = insn.k;
+            pc += 1;
+            match insn.code & 0x07 {
+                BPF_LD => {
+                    a = match insn.code & 0xe0 {
+                        BPF_IMM => k,
+                        BPF_ABS => match load(packet, k, insn.code & 0x18) {
+                            Some(val) => val,
+                            None => return 0,
+                        },
+                        BPF_IND => match load(packet, x.wrapping_add(k), insn.code & 0x18) {
+                            Some(val) => val,
+                            None => return 0,
+                        },
+                        BPF_MEM => mem[k as usize],
+                        _ => packet.len() as u32, // BPF_LEN
+                    }
+                }
+                BPF_LDX => {
+                    x = match insn.code & 0xe0 {
+                        BPF_IMM => k,
+                        BPF_MEM => mem[k as usize],
+                        BPF_LEN => packet.len() as u32,
+                        // BPF_MSH: the length of the IPv4 header at `k`
+                        _ => match packet.get(k as usize) {
+                            Some(&b) => (b as u32 & 0xf) << 2,
+                            None => return 0,
+                        },
+                    }
+                }
+                BPF_ST => mem[k as usize] = a,
+                BPF_STX => mem[k as usize] = x,
+                BPF_ALU => {
+                    let operand = if insn.code & BPF_X != 0 { x } else { k };
+                    a = match insn.code & 0xf0 {
+                        BPF_ADD => a.wrapping_add(operand),
+                        BPF_SUB => a.wrapping_sub(operand),
+                        BPF_MUL => a.wrapping_mul(operand),
+                        BPF_DIV => match a.checked_div(operand) {
+                            Some(val) => val,
+                            None => return 0,
+                        },
+                        BPF_MOD => match a.checked_rem(operand) {
+                            Some(val) => val,
+                            None => return 0,
+                        },
+                        BPF_OR => a | operand,
+                        BPF_AND => a & operand,
+                        BPF_XOR => a ^ operand,
+                        BPF_LSH => a.checked_shl(operand).unwrap_or(0),
+                        BPF_RSH => a.checked_shr(operand).unwrap_or(0),
+                        _ => a.wrapping_neg(), // BPF_NEG
+                    }
+                }
+                BPF_JMP => {
+                    let operand = if insn.code & BPF_X != 0 { x } else { k };
+                    let taken = match insn.code & 0xf0 {
+                        BPF_JA => {
+                            pc += k as usize;
+                            continue;
+                        }
+                        BPF_JEQ => a == operand,
+                        BPF_JGT => a > operand,
+                        BPF_JGE => a >= operand,
+                        _ => a & operand != 0, // BPF_JSET
+                    };
+                    pc += (if taken { insn.jt } else { insn.jf }) as usize;
+                }
+                BPF_RET => {
+                    return match insn.code & 0x18 {
+                        BPF_A => a,
+                        BPF_X => x,
+                        _ => k, // BPF_K
+                    };
+                }
+                _ => {
+                    // BPF_MISC
+                    if insn.code & 0xf8 == BPF_TXA {
+                        a = x;
+                    } else {
+                        x = a; // BPF_TAX
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Checks a single instruction, followed by `remaining` ones.
+fn check_insn(insn: &BpfInsn, remaining: usize) -> bool {
+    let code = insn.code;
+    let k = insn.k;
+    match code & 0x07 {
+        BPF_LD => {
+            let size_ok = matches!(code & 0x18, BPF_W | BPF_H | BPF_B);
+            code & 0xff00 == 0
+                && match code & 0xe0 {
+                    BPF_IMM | BPF_LEN => code & 0x18 == BPF_W,
+                    BPF_ABS => size_ok && k < SKF_LL_OFF,
+                    BPF_IND => size_ok,
+                    BPF_MEM => code & 0x18 == BPF_W && (k as usize) < MEM_WORDS,
+                    _ => false,
+                }
+        }
+        BPF_LDX => match code & !0x07 {
+            BPF_IMM | BPF_LEN => true,
+            BPF_MEM => (k as usize) < MEM_WORDS,
+            c => c == BPF_MSH | BPF_B,
+        },
+        BPF_ST | BPF_STX => code & 0xfff8 == 0 && (k as usize) < MEM_WORDS,
+        BPF_ALU => {
+            let op = code & 0xf0;
+            code & 0xff00 == 0
+                && op <= BPF_XOR
+                && if op == BPF_NEG {
+                    code & BPF_X == 0
+                } else {
+                    // divisions by a zero constant are rejected upfront
+                    !(matches!(op, BPF_DIV | BPF_MOD) && code & BPF_X == 0 && k == 0)
+                }
+        }
+        BPF_JMP => {
+            let op = code & 0xf0;
+            code & 0xff00 == 0
+                && if op == BPF_JA {
+                    code & BPF_X == 0 && (k as usize) < remaining
+                } else {
+                    op <= BPF_JSET
+                        && (insn.jt as usize) < remaining
+                        && (insn.jf as usize) < remaining
+                }
+        }
+        BPF_RET => matches!(code & !0x07, BPF_K | BPF_X | BPF_A),
+        BPF_MISC => matches!(code & !0x07, BPF_TAX | BPF_TXA),
+        _ => false,
+    }
+}
+
+/// Loads a big-endian word, half word or byte at `offset` of `packet`.
+fn load(packet: &[u8], offset: u32, size: u16) -> Option<u32> {
+    let offset = offset as usize;
+    let len = match size {
+        BPF_W => 4,
+        BPF_H => 2,
+        _ => 1,
+    };
+    let bytes = packet.get(offset..offset.checked_add(len)?)?;
+    Some(bytes.iter().fold(0, |val, &b| (val << 8) | b as u32))
+}
+
+/// The filter attached to a socket, if any.
+pub(super) struct SocketFilter(RwLock<Option<BpfProgram>>);
+
+impl SocketFilter {
+    pub const fn new() -> Self {
+        Self(RwLock::new(None))
+    }
+
+    /// Attaches `program`, replacing the filter attached before.
+    pub fn attach(&self, program: BpfProgram) {
+        *self.0.write() = Some(program);
+    }
+
+    /// Detaches the filter, or fails with
+    /// [`Err(NotFound)`](axerrno::AxError::NotFound) if there's none.
+    pub fn detach(&self) -> AxResult {
+        match self.0.write().take() {
+            Some(_) => Ok(()),
+            None => ax_err!(NotFound, "no filter attached"),
+        }
+    }
+
+    /// Returns whether a filter is attached.
+    pub fn is_attached(&self) -> bool {
+        self.0.read().is_some()
+    }
+
+    /// Runs the filter on `packet`, and returns the number of bytes to keep,
+    /// or the whole packet if there's no filter.
+    pub fn run(&self, packet: &[u8]) -> usize {
+        match self.0.read().as_ref() {
+            Some(program) => packet.len().min(program.run(packet) as usize),
+            None => packet.len(),
+        }
+    }
+}
@@ -1,90 +1,520 @@
-use alloc::vec::Vec;
-use axerrno::{AxError, AxResult, ax_err_type};
-use core::net::IpAddr;
-
-use smoltcp::iface::SocketHandle;
-use smoltcp::socket::dns::{self, GetQueryResultError, StartQueryError};
-use smoltcp::wire::DnsQueryType;
-
-use super::addr::into_core_ipaddr;
-use super::{ETH0, SOCKET_SET, SocketSetWrapper};
-
-/// A DNS socket.
-struct DnsSocket {
-    handle: Option<SocketHandle>,
-}
-
-impl DnsSocket {
-    #[allow(clippy::new_without_default)]
-    /// Creates a new DNS socket.
-    pub fn new() -> Self {
-        let socket = SocketSetWrapper::new_dns_socket();
-        let handle = Some(SOCKET_SET.add(socket));
-        Self { handle }
-    }
-
-    #[allow(dead_code)]
-    /// Update the list of DNS servers, will replace all existing servers.
-    pub fn update_servers(self, servers: &[smoltcp::wire::IpAddress]) {
-        SOCKET_SET.with_socket_mut::<dns::Socket, _, _>(self.handle.unwrap(), |socket| {
-            socket.update_servers(servers)
-        });
-    }
-
-    /// Query a address with given DNS query type.
-    pub fn query(&self, name: &str, query_type: DnsQueryType) -> AxResult<Vec<IpAddr>> {
-        // let local_addr = self.local_addr.unwrap_or_else(f);
-        let handle = self.handle.ok_or_else(|| ax_err_type!(InvalidInput))?;
-        let iface = &ETH0.iface;
-        let query_handle = SOCKET_SET
-            .with_socket_mut::<dns::Socket, _, _>(handle, |socket| {
-                socket.start_query(iface.lock().context(), name, query_type)
-            })
-            .map_err(|e| match e {
-                StartQueryError::NoFreeSlot => {
-                    ax_err_type!(ResourceBusy, "socket query() failed: no free slot")
-                }
-                StartQueryError::InvalidName => {
-                    ax_err_type!(InvalidInput, "socket query() failed: invalid name")
-                }
-                StartQueryError::NameTooLong => {
-                    ax_err_type!(InvalidInput, "socket query() failed: too long name")
-                }
-            })?;
+//! A stub DNS resolver.
+//!
+//! Queries are sent over UDP to the configured nameservers in turn, and
+//! retried over TCP if the answer is truncated. Names under `.local` are
+//! resolved with multicast DNS instead. Answers are cached until their TTL
+//! expires, and the answers without records for the time given by the SOA
+//! record of the zone (RFC 2308), or a short default.
+//!
+//! The query IDs are random, and a response is only accepted with the ID and
+//! the question of the query.
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::{format, vec, vec::Vec};
+use core::net::{IpAddr, Ipv4Addr, SocketAddr};
+use core::time::Duration;
+
+use axerrno::{AxError, AxResult, ax_err, ax_err_type};
+use axhal::time::monotonic_time;
+use axsync::Mutex;
+use spin::RwLock;
+
+use super::{TcpSocket, UdpSocket};
+
+const DEFAULT_NAMESERVER: IpAddr = IpAddr::V4(Ipv4Addr::new(8, 8, 8, 8));
+/// Maximum number of nameservers, the same as `MAXNS` of glibc.
+const MAX_NAMESERVERS: usize = 3;
+const DNS_PORT: u16 = 53;
+
+const QUERY_TIMEOUT: Duration = Duration::from_secs(2);
+const QUERY_ATTEMPTS: usize = 2;
+const MAX_UDP_MESSAGE_LEN: usize = 512;
+const MAX_CACHE_ENTRIES: usize = 128;
+const MAX_CACHE_TTL: u32 = 24 * 60 * 60;
+/// How long an answer without records is cached if there's no SOA record.
+const DEFAULT_NEGATIVE_TTL: u32 = 60;
+/// The maximum time an answer without records is cached, as suggested by
+/// RFC 2308.
+const MAX_NEGATIVE_TTL: u32 = 3 * 60 * 60;
+
+pub(super) const HEADER_LEN: usize = 12;
+pub(super) const FLAG_RESPONSE: u16 = 0x8000;
+const FLAG_TRUNCATED: u16 = 0x0200;
+const FLAG_RECURSION_DESIRED: u16 = 0x0100;
+const RCODE_NAME_ERROR: u16 = 3;
+
+pub(super) const TYPE_A: u16 = 1;
+const TYPE_SOA: u16 = 6;
+pub(super) const TYPE_PTR: u16 = 12;
+pub(super) const CLASS_IN: u16 = 1;
+
+/// Nameservers to query, [`DEFAULT_NAMESERVER`] is used if empty.
+static NAMESERVERS: RwLock<Vec<IpAddr>> = RwLock::new(Vec::new());
+static CACHE: Mutex<BTreeMap<(String, u16), CacheEntry>> = Mutex::new(BTreeMap::new());
+
+/// Data of a resource record.
+#[derive(Debug, Clone)]
+enum RecordData {
+    Addr(IpAddr),
+    Name(String),
+}
+
+struct CacheEntry {
+    records: Vec<RecordData>,
+    expires: Duration,
+}
+
+struct Response {
+    truncated: bool,
+    records: Vec<RecordData>,
+    ttl: u32,
+}
+
+/// Reads DNS messages, following compressed names.
+pub(super) struct MessageReader<'a> {
+    msg: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> MessageReader<'a> {
+    pub fn new(msg: &'a [u8]) -> Self {
+        Self { msg, pos: 0 }
+    }
+
+    pub fn bytes(&mut self, len: usize) -> AxResult<&'a [u8]> {
+        let data = self
+            .msg
+            .get(self.pos..self.pos + len)
+            .ok_or_else(|| ax_err_type!(InvalidData, "DNS message too short"))?;
+        self.pos += len;
+        Ok(data)
+    }
+
+    pub fn u16(&mut self) -> AxResult<u16> {
+        let data = self.bytes(2)?;
+        Ok(u16::from_be_bytes([data[0], data[1]]))
+    }
+
+    pub fn u32(&mut self) -> AxResult<u32> {
+        let data = self.bytes(4)?;
+        Ok(u32::from_be_bytes([data[0], data[1], data[2], data[3]]))
+    }
+
+    /// Reads a domain name, in dotted form without the trailing dot.
+    pub fn name(&mut self) -> AxResult<String> {
+        let mut name = String::new();
+        let mut pos = self.pos;
+        let mut end = None;
         loop {
-            SOCKET_SET.poll_interfaces();
-            match SOCKET_SET.with_socket_mut::<dns::Socket, _, _>(handle, |socket| {
-                socket.get_query_result(query_handle).map_err(|e| match e {
-                    GetQueryResultError::Pending => AxError::WouldBlock,
-                    GetQueryResultError::Failed => {
-                        ax_err_type!(ConnectionRefused, "socket query() failed")
+            let len = *self.msg.get(pos).ok_or(AxError::InvalidData)? as usize;
+            match len {
+                0 => {
+                    self.pos = end.unwrap_or(pos + 1);
+                    return Ok(name);
+                }
+                0xc0.. => {
+                    let low = *self.msg.get(pos + 1).ok_or(AxError::InvalidData)? as usize;
+                    let target = ((len & 0x3f) << 8) | low;
+                    // only backward pointers are allowed, so there are no loops
+                    if target >= pos {
+                        return ax_err!(InvalidData, "bad DNS name pointer");
                     }
-                })
-            }) {
-                Ok(n) => {
-                    let mut res = Vec::with_capacity(n.capacity());
-                    for ip in n {
-                        res.push(into_core_ipaddr(ip))
+                    end.get_or_insert(pos + 2);
+                    pos = target;
+                }
+                1..=63 => {
+                    let label = self
+                        .msg
+                        .get(pos + 1..pos + 1 + len)
+                        .ok_or(AxError::InvalidData)?;
+                    if !name.is_empty() {
+                        name.push('.');
                     }
-                    return Ok(res);
+                    name.extend(label.iter().map(|&c| c as char));
+                    pos += 1 + len;
+                }
+                _ => return ax_err!(InvalidData, "bad DNS label"),
+            }
+        }
+    }
+}
+
+/// Returns a random query ID, so that the responses can't be guessed by an
+/// off-path attacker.
+fn next_query_id() -> u16 {
+    axhal::random::random_u32() as u16
+}
+
+/// Appends the domain name `name`, in dotted form, to `msg`.
+pub(super) fn push_name(msg: &mut Vec<u8>, name: &str) -> AxResult {
+    let start = msg.len();
+    for label in name.strip_suffix('.').unwrap_or(name).split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return ax_err!(InvalidInput, "invalid domain name");
+        }
+        msg.push(label.len() as u8);
+        msg.extend_from_slice(label.as_bytes());
+    }
+    msg.push(0);
+    if msg.len() - start > 255 {
+        return ax_err!(InvalidInput, "domain name too long");
+    }
+    Ok(())
+}
+
+fn build_query(id: u16, name: &str, qtype: u16) -> AxResult<Vec<u8>> {
+    let mut msg = Vec::with_capacity(MAX_UDP_MESSAGE_LEN);
+    msg.extend_from_slice(&id.to_be_bytes());
+    msg.extend_from_slice(&FLAG_RECURSION_DESIRED.to_be_bytes());
+    msg.extend_from_slice(&1u16.to_be_bytes()); // QDCOUNT
+    msg.extend_from_slice(&[0; 6]); // ANCOUNT, NSCOUNT, ARCOUNT
+    push_name(&mut msg, name)?;
+    msg.extend_from_slice(&qtype.to_be_bytes());
+    msg.extend_from_slice(&CLASS_IN.to_be_bytes());
+    Ok(msg)
+}
+
+/// A resource record read from a message, but not its name.
+struct RawRecord<'a> {
+    rtype: u16,
+    rclass: u16,
+    ttl: u32,
+    /// The position of the data in the message, for the compressed names.
+    data_pos: usize,
+    data: &'a [u8],
+}
+
+impl<'a> MessageReader<'a> {
+    fn record(&mut self) -> AxResult<RawRecord<'a>> {
+        self.name()?;
+        let rtype = self.u16()?;
+        let rclass = self.u16()?;
+        let ttl = self.u32()?;
+        let rdlen = self.u16()? as usize;
+        let data_pos = self.pos;
+        let data = self.bytes(rdlen)?;
+        Ok(RawRecord {
+            rtype,
+            rclass,
+            ttl,
+            data_pos,
+            data,
+        })
+    }
+}
+
+/// Returns the TTL of the negative answers from the SOA record at `pos` with
+/// the TTL `rttl`: the smaller of it and the `MINIMUM` field.
+fn soa_negative_ttl(msg: &[u8], pos: usize, rttl: u32) -> AxResult<u32> {
+    let mut reader = MessageReader { msg, pos };
+    reader.name()?; // MNAME
+    reader.name()?; // RNAME
+    reader.bytes(16)?; // SERIAL, REFRESH, RETRY, EXPIRE
+    Ok(rttl.min(reader.u32()?))
+}
+
+fn parse_response(msg: &[u8], id: u16, name: &str, qtype: u16) -> AxResult<Response> {
+    let mut reader = MessageReader::new(msg);
+    if reader.u16()? != id {
+        return ax_err!(InvalidData, "DNS response ID mismatch");
+    }
+    let flags = reader.u16()?;
+    if flags & FLAG_RESPONSE == 0 {
+        return ax_err!(InvalidData, "not a DNS response");
+    }
+    let qdcount = reader.u16()?;
+    let ancount = reader.u16()?;
+    let nscount = reader.u16()?;
+    reader.bytes(2)?; // ARCOUNT
+
+    // the question must be that of the query
+    if qdcount != 1 {
+        return ax_err!(InvalidData, "bad DNS response question count");
+    }
+    let qname = reader.name()?;
+    let (rqtype, rqclass) = (reader.u16()?, reader.u16()?);
+    let query_name = name.strip_suffix('.').unwrap_or(name);
+    if !qname.eq_ignore_ascii_case(query_name) || rqtype != qtype || rqclass & 0x7fff != CLASS_IN {
+        return ax_err!(InvalidData, "DNS response to another question");
+    }
+
+    match flags & 0xf {
+        0 => {}
+        RCODE_NAME_ERROR => return Err(AxError::NotFound),
+        rcode => {
+            warn!("DNS query failed with rcode {}", rcode);
+            return Err(AxError::Io);
+        }
+    }
+
+    let mut records = Vec::new();
+    let mut ttl = MAX_CACHE_TTL;
+    for _ in 0..ancount {
+        let RawRecord {
+            rtype,
+            rclass,
+            ttl: rttl,
+            data_pos: rdata_pos,
+            data: rdata,
+        } = reader.record()?;
+        // the top bit of the class is the cache-flush bit of mDNS
+        if rtype != qtype || rclass & 0x7fff != CLASS_IN {
+            // e.g. CNAME records, the records of the target follow them
+            continue;
+        }
+        let record = match rtype {
+            TYPE_A if rdata.len() == 4 => {
+                RecordData::Addr(Ipv4Addr::new(rdata[0], rdata[1], rdata[2], rdata[3]).into())
+            }
+            TYPE_PTR => {
+                let mut rdata_reader = MessageReader {
+                    msg,
+                    pos: rdata_pos,
+                };
+                RecordData::Name(rdata_reader.name()?)
+            }
+            _ => continue,
+        };
+        records.push(record);
+        ttl = ttl.min(rttl);
+    }
+
+    let truncated = flags & FLAG_TRUNCATED != 0;
+    if records.is_empty() && !truncated {
+        // a negative answer, cached for the time given by the SOA record of
+        // the zone in the authority section, if any
+        let negative_ttl = || -> AxResult<Option<u32>> {
+            for _ in 0..nscount {
+                let record = reader.record()?;
+                if record.rtype == TYPE_SOA && record.rclass & 0x7fff == CLASS_IN {
+                    return soa_negative_ttl(msg, record.data_pos, record.ttl).map(Some);
                 }
-                Err(AxError::WouldBlock) => axtask::yield_now(),
-                Err(e) => return Err(e),
             }
+            Ok(None)
+        };
+        ttl = match negative_ttl() {
+            Ok(Some(ttl)) => ttl.min(MAX_NEGATIVE_TTL),
+            _ => DEFAULT_NEGATIVE_TTL,
+        };
+    }
+
+    Ok(Response {
+        truncated,
+        records,
+        ttl,
+    })
+}
+
+fn query_udp(server: IpAddr, msg: &[u8]) -> AxResult<Vec<u8>> {
+    let socket = UdpSocket::new();
+    socket.set_recv_timeout(Some(QUERY_TIMEOUT));
+    socket.set_send_timeout(Some(QUERY_TIMEOUT));
+    socket.connect(SocketAddr::new(server, DNS_PORT))?;
+    socket.send(msg)?;
+    let mut buf = vec![0; MAX_UDP_MESSAGE_LEN];
+    let len = socket.recv(&mut buf)?;
+    buf.truncate(len);
+    Ok(buf)
+}
+
+fn query_tcp(server: IpAddr, msg: &[u8]) -> AxResult<Vec<u8>> {
+    fn recv_exact(socket: &TcpSocket, mut buf: &mut [u8]) -> AxResult {
+        while !buf.is_empty() {
+            match socket.recv(buf)? {
+                0 => return Err(AxError::UnexpectedEof),
+                n => buf = &mut buf[n..],
+            }
+        }
+        Ok(())
+    }
+
+    let socket = TcpSocket::new();
+    socket.set_recv_timeout(Some(QUERY_TIMEOUT));
+    socket.set_send_timeout(Some(QUERY_TIMEOUT));
+    socket.connect(SocketAddr::new(server, DNS_PORT))?;
+
+    // messages over TCP are prefixed with their length
+    let mut request = Vec::with_capacity(2 + msg.len());
+    request.extend_from_slice(&(msg.len() as u16).to_be_bytes());
+    request.extend_from_slice(msg);
+    let mut sent = 0;
+    while sent < request.len() {
+        sent += socket.send(&request[sent..])?;
+    }
+
+    let mut len = [0; 2];
+    recv_exact(&socket, &mut len)?;
+    let mut buf = vec![0; u16::from_be_bytes(len) as usize];
+    recv_exact(&socket, &mut buf)?;
+    socket.shutdown().ok();
+    Ok(buf)
+}
+
+/// Resolves a `.local` name with a one-shot multicast DNS query.
+fn exchange_mdns(name: &str, qtype: u16) -> AxResult<Response> {
+    if qtype == TYPE_A {
+        if let Some(addrs) = super::mdns::own_addrs(name) {
+            return Ok(Response {
+                truncated: false,
+                records: addrs.into_iter().map(RecordData::Addr).collect(),
+                ttl: super::mdns::HOST_TTL,
+            });
+        }
+    }
+    let id = next_query_id();
+    let msg = build_query(id, name, qtype)?;
+    match super::mdns::query(&msg, QUERY_TIMEOUT) {
+        Ok(response) => parse_response(&response, id, name, qtype),
+        // no host answered
+        Err(AxError::WouldBlock) => Err(AxError::NotFound),
+        Err(e) => Err(e),
+    }
+}
+
+fn exchange(server: IpAddr, name: &str, qtype: u16) -> AxResult<Response> {
+    let id = next_query_id();
+    let msg = build_query(id, name, qtype)?;
+    let response = parse_response(&query_udp(server, &msg)?, id, name, qtype)?;
+    if !response.truncated {
+        return Ok(response);
+    }
+    debug!("DNS response from {} truncated, retrying over TCP", server);
+    parse_response(&query_tcp(server, &msg)?, id, name, qtype)
+}
+
+fn lookup_cache(key: &(String, u16)) -> Option<Vec<RecordData>> {
+    let mut cache = CACHE.lock();
+    let entry = cache.get(key)?;
+    if entry.expires > monotonic_time() {
+        return Some(entry.records.clone());
+    }
+    cache.remove(key);
+    None
+}
+
+fn insert_cache(key: (String, u16), records: Vec<RecordData>, ttl: u32) {
+    if ttl == 0 {
+        return;
+    }
+    let now = monotonic_time();
+    let mut cache = CACHE.lock();
+    if cache.len() >= MAX_CACHE_ENTRIES {
+        cache.retain(|_, entry| entry.expires > now);
+        if cache.len() >= MAX_CACHE_ENTRIES {
+            cache.pop_first();
         }
     }
+    let expires = now + Duration::from_secs(ttl.min(MAX_CACHE_TTL) as u64);
+    cache.insert(key, CacheEntry { records, expires });
 }
 
-impl Drop for DnsSocket {
-    fn drop(&mut self) {
-        if let Some(handle) = self.handle {
-            SOCKET_SET.remove(handle);
+fn resolve(name: &str, qtype: u16) -> AxResult<Vec<RecordData>> {
+    let key = (name.to_ascii_lowercase(), qtype);
+    if let Some(records) = lookup_cache(&key) {
+        return Ok(records);
+    }
+
+    if super::mdns::is_local_name(&key.0) {
+        let response = exchange_mdns(name, qtype)?;
+        insert_cache(key, response.records.clone(), response.ttl);
+        return Ok(response.records);
+    }
+
+    let servers = nameservers();
+    let mut last_err = AxError::NotFound;
+    for _ in 0..QUERY_ATTEMPTS {
+        for &server in &servers {
+            match exchange(server, name, qtype) {
+                Ok(response) => {
+                    insert_cache(key, response.records.clone(), response.ttl);
+                    return Ok(response.records);
+                }
+                Err(AxError::NotFound) => return Err(AxError::NotFound),
+                Err(AxError::InvalidInput) => return Err(AxError::InvalidInput),
+                Err(e) => {
+                    debug!("DNS query {:?} to {} failed: {:?}", name, server, e);
+                    last_err = e;
+                }
+            }
         }
     }
+    Err(last_err)
+}
+
+/// Returns the nameservers used by the resolver.
+pub fn nameservers() -> Vec<IpAddr> {
+    let servers = NAMESERVERS.read();
+    if servers.is_empty() {
+        vec![DEFAULT_NAMESERVER]
+    } else {
+        servers.clone()
+    }
+}
+
+/// Replaces the nameservers used by the resolver.
+///
+/// At most 3 nameservers are used, and the default one (`8.8.8.8`) is
+/// restored if `servers` is empty. Cached answers are dropped.
+pub fn set_nameservers(servers: &[IpAddr]) {
+    let servers = &servers[..servers.len().min(MAX_NAMESERVERS)];
+    info!("DNS nameservers: {:?}", servers);
+    *NAMESERVERS.write() = servers.to_vec();
+    CACHE.lock().clear();
+}
+
+/// Configures the resolver from the content of a `resolv.conf(5)` file.
+///
+/// Only `nameserver` lines are recognized, other options are ignored.
+pub fn load_resolv_conf(content: &str) {
+    let servers = content
+        .lines()
+        .filter_map(|line| {
+            let line = line.split(['#', ';']).next().unwrap_or_default();
+            let mut words = line.split_whitespace();
+            match (words.next(), words.next()) {
+                (Some("nameserver"), Some(addr)) => match addr.parse::<IpAddr>() {
+                    Ok(addr) => Some(addr),
+                    Err(_) => {
+                        warn!("resolv.conf: invalid nameserver {:?}", addr);
+                        None
+                    }
+                },
+                _ => None,
+            }
+        })
+        .collect::<Vec<_>>();
+    set_nameservers(&servers);
 }
 
 /// Public function for DNS query.
-pub fn dns_query(name: &str) -> AxResult<alloc::vec::Vec<IpAddr>> {
-    let socket = DnsSocket::new();
-    socket.query(name, DnsQueryType::A)
+///
+/// Returns the IPv4 addresses of `name`, or
+/// [`Err(NotFound)`](AxError::NotFound) if the name does not exist.
+pub fn dns_query(name: &str) -> AxResult<Vec<IpAddr>> {
+    Ok(resolve(name, TYPE_A)?
+        .into_iter()
+        .filter_map(|record| match record {
+            RecordData::Addr(addr) => Some(addr),
+            RecordData::Name(_) => None,
+        })
+        .collect())
+}
+
+/// Looks up the domain name of an address (a `PTR` query).
+pub fn dns_reverse_query(addr: IpAddr) -> AxResult<String> {
+    let name = match addr {
+        IpAddr::V4(v4) => {
+            let [a, b, c, d] = v4.octets();
+            format!("{}.{}.{}.{}.in-addr.arpa", d, c, b, a)
+        }
+        IpAddr::V6(_) => return ax_err!(Unsupported, "IPv6 is not supported"),
+    };
+    resolve(&name, TYPE_PTR)?
+        .into_iter()
+        .find_map(|record| match record {
+            RecordData::Name(name) => Some(name),
+            RecordData::Addr(_) => None,
+        })
+        .ok_or(AxError::NotFound)
 }
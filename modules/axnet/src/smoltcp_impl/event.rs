@@ -0,0 +1,161 @@
+//! Waiting for network events, rather than polling the interfaces in a loop.
+//!
+//! An event is a poll of the interfaces which made progress, e.g. received
+//! frames or delivered the frames sent to the loopback interface. The blocked
+//! tasks wait for the next event, and then poll the interfaces again.
+//!
+//! The frames received by the NICs are taken by a polling task on each CPU,
+//! woken up by the IRQs of the NICs on its CPU (see [`axdriver::net_irq`]),
+//! as NAPI does. The NICs with multiple queues interrupt the CPU which polls
+//! each of them first, so the frames of a queue are handled on its CPU.
+//!
+//! The tasks only wait if all the NICs interrupt the CPU, with the `irq` and
+//! `multitask` features, and otherwise keep polling. They wait
+//! [`MAX_WAIT`] at most, for the timers of the sockets (retransmissions,
+//! delayed ACKs) which need the interfaces to be polled.
+
+use core::time::Duration;
+
+/// The longest time a task waits for an event.
+#[cfg_attr(not(all(feature = "irq", feature = "multitask")), allow(dead_code))]
+const MAX_WAIT: Duration = Duration::from_millis(10);
+
+cfg_if::cfg_if! {
+    if #[cfg(all(feature = "irq", feature = "multitask"))] {
+        use alloc::format;
+        use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+        use axtask::{AxCpuMask, TaskInner, WaitQueue};
+
+        /// The number of events so far.
+        static EVENTS: AtomicU64 = AtomicU64::new(0);
+        static WAIT_QUEUE: WaitQueue = WaitQueue::new();
+        /// Whether all the NICs interrupt the CPU.
+        static ENABLED: AtomicBool = AtomicBool::new(false);
+
+        /// The polling task of a CPU.
+        struct Poller {
+            wait_queue: WaitQueue,
+            /// Whether the NICs interrupted the CPU since the last poll.
+            pending: AtomicBool,
+            /// Whether the task is spawned.
+            running: AtomicBool,
+        }
+
+        impl Poller {
+            const fn new() -> Self {
+                Self {
+                    wait_queue: WaitQueue::new(),
+                    pending: AtomicBool::new(false),
+                    running: AtomicBool::new(false),
+                }
+            }
+        }
+
+        static POLLERS: [Poller; axconfig::SMP] = [const { Poller::new() }; axconfig::SMP];
+
+        /// Waits for the events if all the `num_nics` NICs interrupt the CPU,
+        /// and spawns the polling task of the primary CPU.
+        pub(super) fn init(num_nics: usize) {
+            if axdriver::net_irq::num_rx_irq_nics() < num_nics {
+                info!("some NICs have no RX IRQs, the network stack polls them");
+                return;
+            }
+            axdriver::net_irq::set_rx_notifier(on_rx);
+            ENABLED.store(true, Ordering::Release);
+            spawn_poller();
+        }
+
+        /// Spawns the polling task of a secondary CPU, once its run queue is
+        /// initialized.
+        pub(super) fn init_secondary() {
+            if enabled() {
+                spawn_poller();
+            }
+        }
+
+        fn spawn_poller() {
+            let cpu_id = axhal::cpu::this_cpu_id();
+            let task = TaskInner::new(
+                move || poll_loop(cpu_id),
+                format!("netpoll/{}", cpu_id),
+                axconfig::TASK_STACK_SIZE,
+            );
+            task.set_cpumask(AxCpuMask::one_shot(cpu_id));
+            axtask::spawn_task(task);
+            POLLERS[cpu_id].running.store(true, Ordering::Release);
+        }
+
+        fn poll_loop(cpu_id: usize) {
+            let poller = &POLLERS[cpu_id];
+            loop {
+                poller
+                    .wait_queue
+                    .wait_until(|| poller.pending.swap(false, Ordering::AcqRel));
+                super::poll_interfaces();
+            }
+        }
+
+        /// Wakes up the polling task of the current CPU, called from the IRQ
+        /// handlers of the NICs.
+        fn on_rx() {
+            let poller = &POLLERS[axhal::cpu::this_cpu_id()];
+            if poller.running.load(Ordering::Acquire) {
+                poller.pending.store(true, Ordering::Release);
+                poller.wait_queue.notify_one(false);
+            } else {
+                // the CPU isn't initialized yet, the blocked tasks poll
+                notify();
+            }
+        }
+
+        /// Whether the tasks wait for the events.
+        pub(super) fn enabled() -> bool {
+            ENABLED.load(Ordering::Acquire)
+        }
+
+        /// Returns the number of events so far, to be given to [`wait`] once
+        /// the interfaces are polled.
+        pub(super) fn current() -> u64 {
+            EVENTS.load(Ordering::Acquire)
+        }
+
+        /// Wakes up the tasks waiting for an event.
+        pub(super) fn notify() {
+            EVENTS.fetch_add(1, Ordering::AcqRel);
+            WAIT_QUEUE.notify_all(false);
+        }
+
+        /// Waits until an event after the first `seen` ones, `timeout` at
+        /// most, or the task is interrupted.
+        ///
+        /// Returns `false` at once if the tasks don't wait for the events.
+        pub(super) fn wait(seen: u64, timeout: Duration) -> bool {
+            if !enabled() {
+                return false;
+            }
+            WAIT_QUEUE.wait_timeout_until_interruptible(timeout.min(MAX_WAIT), || {
+                EVENTS.load(Ordering::Acquire) != seen
+            });
+            true
+        }
+    } else {
+        pub(super) fn init(_num_nics: usize) {}
+
+        pub(super) fn init_secondary() {}
+
+        pub(super) fn enabled() -> bool {
+            false
+        }
+
+        pub(super) fn current() -> u64 {
+            0
+        }
+
+        pub(super) fn notify() {}
+
+        pub(super) fn wait(_seen: u64, _timeout: Duration) -> bool {
+            false
+        }
+    }
+}
@@ -0,0 +1,142 @@
+//! Connecting to hosts with several addresses ("Happy Eyeballs", RFC 8305).
+//!
+//! The connection attempts to the addresses of a host are started in order,
+//! each one `attempt_delay` after the previous one, or as soon as the previous
+//! one fails. They then run in parallel: the first to succeed wins, and the
+//! others are aborted. A host with an unreachable address is thus reached after
+//! a short delay, rather than after the timeout of the first attempt.
+//!
+//! The stack is IPv4-only, so the IPv6 addresses are skipped rather than
+//! interleaved with the IPv4 ones.
+
+use alloc::vec::Vec;
+use core::net::{IpAddr, SocketAddr};
+use core::time::Duration;
+
+use axerrno::{AxError, AxResult, ax_err_type};
+use axhal::time::monotonic_time;
+
+use super::{TcpSocket, dns_query, poll_interfaces};
+
+/// The delay between connection attempts recommended by RFC 8305.
+const DEFAULT_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// Options of [`TcpSocket::connect_any`].
+#[derive(Debug, Clone, Copy)]
+pub struct ConnectOptions {
+    /// The delay before starting the next attempt, while the previous ones
+    /// are still in progress.
+    pub attempt_delay: Duration,
+    /// The timeout of each attempt, or `None` to wait until it fails.
+    pub attempt_timeout: Option<Duration>,
+    /// The timeout of the whole connection, or `None` to wait until all the
+    /// attempts fail.
+    pub timeout: Option<Duration>,
+}
+
+impl Default for ConnectOptions {
+    fn default() -> Self {
+        Self {
+            attempt_delay: DEFAULT_ATTEMPT_DELAY,
+            attempt_timeout: None,
+            timeout: None,
+        }
+    }
+}
+
+struct Attempt {
+    socket: TcpSocket,
+    addr: SocketAddr,
+    deadline: Option<Duration>,
+}
+
+impl TcpSocket {
+    /// Connects to the first of `addrs` that accepts the connection, trying
+    /// them in parallel as described in the [module docs](self).
+    ///
+    /// Returns a connected socket in blocking mode, or the error of the last
+    /// failed attempt. Timeouts fail with [`Err(WouldBlock)`](AxError::WouldBlock).
+    pub fn connect_any(addrs: &[SocketAddr], options: &ConnectOptions) -> AxResult<TcpSocket> {
+        let now = monotonic_time();
+        let deadline = options.timeout.map(|t| now + t);
+        let mut addrs = addrs.iter().filter(|addr| addr.is_ipv4());
+        let mut attempts: Vec<Attempt> = Vec::new();
+        let mut next_attempt = now;
+        let mut last_err = None;
+        loop {
+            let now = monotonic_time();
+            if attempts.is_empty() || now >= next_attempt {
+                if let Some(&addr) = addrs.next() {
+                    let socket = TcpSocket::new();
+                    socket.set_nonblocking(true);
+                    match socket.connect(addr) {
+                        Ok(()) | Err(AxError::WouldBlock) => {
+                            attempts.push(Attempt {
+                                socket,
+                                addr,
+                                deadline: options.attempt_timeout.map(|t| now + t),
+                            });
+                            next_attempt = now + options.attempt_delay;
+                        }
+                        Err(e) => {
+                            debug!("connection attempt to {addr} failed: {e:?}");
+                            last_err = Some(e);
+                        }
+                    }
+                    continue;
+                } else if attempts.is_empty() {
+                    return Err(last_err.unwrap_or_else(|| {
+                        ax_err_type!(InvalidInput, "no IPv4 address to connect to")
+                    }));
+                }
+            }
+
+            poll_interfaces();
+            let now = monotonic_time();
+            let mut i = 0;
+            while i < attempts.len() {
+                let attempt = &attempts[i];
+                let err = if attempt.socket.poll()?.writable {
+                    match attempt.socket.take_error() {
+                        None => {
+                            let attempt = attempts.swap_remove(i);
+                            debug!("connected to {}", attempt.addr);
+                            attempt.socket.set_nonblocking(false);
+                            return Ok(attempt.socket);
+                        }
+                        Some(e) => e,
+                    }
+                } else if attempt.deadline.is_some_and(|ddl| now >= ddl) {
+                    AxError::WouldBlock
+                } else {
+                    i += 1;
+                    continue;
+                };
+                debug!("connection attempt to {} failed: {err:?}", attempt.addr);
+                attempts.swap_remove(i);
+                last_err = Some(err);
+                // start the next attempt right away
+                next_attempt = now;
+            }
+
+            if deadline.is_some_and(|ddl| now >= ddl) {
+                return Err(AxError::WouldBlock);
+            }
+            axtask::yield_now();
+        }
+    }
+
+    /// Resolves `host`, a host name or an IP address, and connects to `port`
+    /// on one of its addresses with [`connect_any`](Self::connect_any).
+    pub fn connect_host(host: &str, port: u16, options: &ConnectOptions) -> AxResult<TcpSocket> {
+        let addrs = match host.parse::<IpAddr>() {
+            Ok(ip) => alloc::vec![ip],
+            Err(_) => dns_query(host)?,
+        };
+        let addrs: Vec<_> = addrs
+            .into_iter()
+            .map(|ip| SocketAddr::new(ip, port))
+            .collect();
+        Self::connect_any(&addrs, options)
+    }
+}
@@ -0,0 +1,262 @@
+use alloc::sync::Arc;
+use core::net::IpAddr;
+use core::sync::atomic::{AtomicBool, AtomicU16, Ordering};
+use core::time::Duration;
+
+use axerrno::{AxError, AxResult, ax_err, ax_err_type};
+use axio::PollState;
+use spin::RwLock;
+
+use smoltcp::iface::SocketHandle;
+use smoltcp::socket::icmp::{self, SendError};
+use smoltcp::wire::{Icmpv4Message, Icmpv4Packet, IpAddress};
+
+use super::addr::{from_core_ipaddr, into_core_ipaddr};
+use super::bpf::{BpfProgram, SocketFilter};
+use super::netns::{NetNamespace, current_net_namespace};
+use super::{SocketSetWrapper, block_on_until};
+
+/// An ICMP "ping" socket that provides POSIX-like APIs.
+///
+/// It's the equivalent of a Linux `socket(AF_INET, SOCK_DGRAM, IPPROTO_ICMP)`:
+/// messages are sent and received without the IP header, and the identifier
+/// of outgoing echo requests is replaced by the one allocated to the socket,
+/// so that only the matching echo replies are received.
+pub struct IcmpSocket {
+    ns: Arc<NetNamespace>,
+    handle: SocketHandle,
+    ident: u16,
+    peer_addr: RwLock<Option<IpAddress>>,
+    nonblock: AtomicBool,
+    recv_timeout: RwLock<Option<Duration>>,
+    send_timeout: RwLock<Option<Duration>>,
+    filter: SocketFilter,
+}
+
+impl IcmpSocket {
+    /// Creates a new ICMP socket in the current network namespace.
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        let ident = get_ephemeral_ident();
+        let mut socket = SocketSetWrapper::new_icmp_socket();
+        socket
+            .bind(icmp::Endpoint::Ident(ident))
+            .expect("failed to bind ICMP socket");
+        let ns = current_net_namespace();
+        let handle = ns.sockets.add(socket);
+        Self {
+            ns,
+            handle,
+            ident,
+            peer_addr: RwLock::new(None),
+            nonblock: AtomicBool::new(false),
+            recv_timeout: RwLock::new(None),
+            send_timeout: RwLock::new(None),
+            filter: SocketFilter::new(),
+        }
+    }
+
+    /// Returns the identifier used in echo requests sent by this socket.
+    #[inline]
+    pub fn ident(&self) -> u16 {
+        self.ident
+    }
+
+    /// Returns the remote address, or
+    /// [`Err(NotConnected)`](AxError::NotConnected) if not connected.
+    pub fn peer_addr(&self) -> AxResult<IpAddr> {
+        self.remote_addr().map(into_core_ipaddr)
+    }
+
+    /// Returns whether this socket is in nonblocking mode.
+    #[inline]
+    pub fn is_nonblocking(&self) -> bool {
+        self.nonblock.load(Ordering::Acquire)
+    }
+
+    /// Moves this ICMP socket into or out of nonblocking mode.
+    #[inline]
+    pub fn set_nonblocking(&self, nonblocking: bool) {
+        self.nonblock.store(nonblocking, Ordering::Release);
+    }
+
+    /// Returns the timeout of blocking receive operations (`SO_RCVTIMEO`).
+    #[inline]
+    pub fn recv_timeout(&self) -> Option<Duration> {
+        *self.recv_timeout.read()
+    }
+
+    /// Sets the timeout of blocking receive operations (`SO_RCVTIMEO`).
+    #[inline]
+    pub fn set_recv_timeout(&self, timeout: Option<Duration>) {
+        *self.recv_timeout.write() = timeout;
+    }
+
+    /// Returns the timeout of blocking send operations (`SO_SNDTIMEO`).
+    #[inline]
+    pub fn send_timeout(&self) -> Option<Duration> {
+        *self.send_timeout.read()
+    }
+
+    /// Sets the timeout of blocking send operations (`SO_SNDTIMEO`).
+    #[inline]
+    pub fn set_send_timeout(&self, timeout: Option<Duration>) {
+        *self.send_timeout.write() = timeout;
+    }
+
+    /// Attaches a BPF filter (`SO_ATTACH_FILTER`) to the socket, replacing the
+    /// one attached before.
+    ///
+    /// The filter drops or truncates the messages received. It sees them without the IP header.
+    pub fn attach_filter(&self, program: BpfProgram) {
+        self.filter.attach(program);
+    }
+
+    /// Detaches the BPF filter of the socket (`SO_DETACH_FILTER`).
+    pub fn detach_filter(&self) -> AxResult {
+        self.filter.detach()
+    }
+
+    /// Returns the capacity of the receive buffer (`SO_RCVBUF`).
+    #[inline]
+    pub fn recv_buffer_size(&self) -> usize {
+        super::ICMP_RX_BUF_LEN
+    }
+
+    /// Returns the capacity of the send buffer (`SO_SNDBUF`).
+    #[inline]
+    pub fn send_buffer_size(&self) -> usize {
+        super::ICMP_TX_BUF_LEN
+    }
+
+    /// Sets the default destination of [`send`](Self::send).
+    pub fn connect(&self, addr: IpAddr) -> AxResult {
+        *self.peer_addr.write() = Some(from_core_ipaddr(addr));
+        debug!("ICMP socket {}: connected to {}", self.handle, addr);
+        Ok(())
+    }
+
+    /// Sends an ICMP message, starting with the ICMP header, to the given
+    /// address. On success, returns the number of bytes written.
+    pub fn send_to(&self, buf: &[u8], remote_addr: IpAddr) -> AxResult<usize> {
+        if remote_addr.is_unspecified() {
+            return ax_err!(InvalidInput, "socket send_to() failed: invalid address");
+        }
+        self.send_impl(buf, from_core_ipaddr(remote_addr))
+    }
+
+    /// Sends an ICMP message to the address to which the socket is connected.
+    pub fn send(&self, buf: &[u8]) -> AxResult<usize> {
+        let remote_addr = self.remote_addr()?;
+        self.send_impl(buf, remote_addr)
+    }
+
+    /// Receives a single ICMP message, without the IP header. On success,
+    /// returns the number of bytes read and the origin.
+    pub fn recv_from(&self, buf: &mut [u8]) -> AxResult<(usize, IpAddr)> {
+        self.block_on(self.recv_timeout(), || {
+            self.ns
+                .sockets
+                .with_socket_mut::<icmp::Socket, _, _>(self.handle, |socket| {
+                    while socket.can_recv() {
+                        let (data, addr) = socket
+                            .recv()
+                            .map_err(|_| ax_err_type!(BadState, "socket recv_from() failed"))?;
+                        let kept = self.filter.run(data);
+                        if kept == 0 {
+                            // rejected by the filter
+                            continue;
+                        }
+                        // excess bytes are discarded, as for other datagram sockets
+                        let len = kept.min(buf.len());
+                        buf[..len].copy_from_slice(&data[..len]);
+                        return Ok((len, into_core_ipaddr(addr)));
+                    }
+                    Err(AxError::WouldBlock)
+                })
+        })
+    }
+
+    /// Receives a single ICMP message from the address to which the socket is
+    /// connected. On success, returns the number of bytes read.
+    pub fn recv(&self, buf: &mut [u8]) -> AxResult<usize> {
+        let remote_addr = into_core_ipaddr(self.remote_addr()?);
+        loop {
+            let (len, addr) = self.recv_from(buf)?;
+            if addr == remote_addr {
+                return Ok(len);
+            }
+        }
+    }
+
+    /// Close the socket.
+    pub fn shutdown(&self) -> AxResult {
+        debug!("ICMP socket {}: shutting down", self.handle);
+        Ok(())
+    }
+
+    /// Whether the socket is readable or writable.
+    pub fn poll(&self) -> AxResult<PollState> {
+        self.ns
+            .sockets
+            .with_socket_mut::<icmp::Socket, _, _>(self.handle, |socket| {
+                Ok(PollState {
+                    readable: socket.can_recv(),
+                    writable: socket.can_send(),
+                })
+            })
+    }
+}
+
+/// Private methods
+impl IcmpSocket {
+    fn remote_addr(&self) -> AxResult<IpAddress> {
+        self.peer_addr.read().ok_or(AxError::NotConnected)
+    }
+
+    fn send_impl(&self, buf: &[u8], remote_addr: IpAddress) -> AxResult<usize> {
+        let mut packet = Icmpv4Packet::new_checked(buf.to_vec())
+            .map_err(|_| ax_err_type!(InvalidInput, "socket send() failed: bad ICMP header"))?;
+        if packet.msg_type() == Icmpv4Message::EchoRequest {
+            packet.set_echo_ident(self.ident);
+            packet.fill_checksum();
+        }
+        let data = packet.into_inner();
+
+        self.block_on(self.send_timeout(), || {
+            self.ns
+                .sockets
+                .with_socket_mut::<icmp::Socket, _, _>(self.handle, |socket| {
+                    if !socket.can_send() {
+                        return Err(AxError::WouldBlock);
+                    }
+                    socket.send_slice(&data, remote_addr).map_err(|e| match e {
+                        SendError::BufferFull => AxError::WouldBlock,
+                        SendError::Unaddressable => {
+                            ax_err_type!(ConnectionRefused, "socket send() failed")
+                        }
+                    })?;
+                    Ok(data.len())
+                })
+        })
+    }
+
+    fn block_on<F, T>(&self, timeout: Option<Duration>, f: F) -> AxResult<T>
+    where
+        F: FnMut() -> AxResult<T>,
+    {
+        block_on_until(self.is_nonblocking(), timeout, f)
+    }
+}
+
+impl Drop for IcmpSocket {
+    fn drop(&mut self) {
+        self.shutdown().ok();
+        self.ns.sockets.remove(self.handle);
+    }
+}
+
+fn get_ephemeral_ident() -> u16 {
+    static CURR: AtomicU16 = AtomicU16::new(1);
+    CURR.fetch_add(1, Ordering::Relaxed)
+}
@@ -1,25 +1,29 @@
-use alloc::{boxed::Box, collections::VecDeque};
-use core::ops::{Deref, DerefMut};
+use alloc::{boxed::Box, collections::VecDeque, vec::Vec};
 
-use axerrno::{AxError, AxResult, ax_err};
+use axerrno::{AxError, AxResult, ax_err, ax_err_type};
 use axsync::Mutex;
 use smoltcp::iface::{SocketHandle, SocketSet};
 use smoltcp::socket::tcp::{self, State};
 use smoltcp::wire::{IpAddress, IpEndpoint, IpListenEndpoint};
 
-use super::{LISTEN_QUEUE_SIZE, SOCKET_SET, SocketSetWrapper};
+use super::{LISTEN_QUEUE_SIZE, SocketSetWrapper};
 
 const PORT_NUM: usize = 65536;
 
 struct ListenTableEntry {
     listen_endpoint: IpListenEndpoint,
+    /// Identifies the listening socket among the ones sharing the port.
+    key: usize,
+    reuse_port: bool,
     syn_queue: VecDeque<SocketHandle>,
 }
 
 impl ListenTableEntry {
-    pub fn new(listen_endpoint: IpListenEndpoint) -> Self {
+    pub fn new(listen_endpoint: IpListenEndpoint, key: usize, reuse_port: bool) -> Self {
         Self {
             listen_endpoint,
+            key,
+            reuse_port,
             syn_queue: VecDeque::with_capacity(LISTEN_QUEUE_SIZE),
         }
     }
@@ -33,16 +37,17 @@ impl ListenTableEntry {
     }
 }
 
-impl Drop for ListenTableEntry {
-    fn drop(&mut self) {
-        for &handle in &self.syn_queue {
-            SOCKET_SET.remove(handle);
-        }
-    }
-}
-
+/// The listening TCP sockets on each port.
+///
+/// Several sockets can listen on the same port if all of them set
+/// `SO_REUSEPORT`. New connections are then distributed among them by a hash
+/// of the source address and port, so that the workers accepting on each
+/// socket share the load.
+///
+/// Each network namespace has its own table, whose sockets are in the socket
+/// set of the namespace.
 pub struct ListenTable {
-    tcp: Box<[Mutex<Option<Box<ListenTableEntry>>>]>,
+    tcp: Box<[Mutex<Vec<ListenTableEntry>>]>,
 }
 
 impl ListenTable {
@@ -50,7 +55,7 @@ impl ListenTable {
         let tcp = unsafe {
             let mut buf = Box::new_uninit_slice(PORT_NUM);
             for i in 0..PORT_NUM {
-                buf[i].write(Mutex::new(None));
+                buf[i].write(Mutex::new(Vec::new()));
             }
             buf.assume_init()
         };
@@ -58,56 +63,76 @@ impl ListenTable {
     }
 
     pub fn can_listen(&self, port: u16) -> bool {
-        self.tcp[port as usize].lock().is_none()
+        self.tcp[port as usize].lock().is_empty()
     }
 
-    pub fn listen(&self, listen_endpoint: IpListenEndpoint) -> AxResult {
+    pub fn listen(
+        &self,
+        listen_endpoint: IpListenEndpoint,
+        key: usize,
+        reuse_port: bool,
+    ) -> AxResult {
         let port = listen_endpoint.port;
         assert_ne!(port, 0);
-        let mut entry = self.tcp[port as usize].lock();
-        if entry.is_none() {
-            *entry = Some(Box::new(ListenTableEntry::new(listen_endpoint)));
+        let mut entries = self.tcp[port as usize].lock();
+        if entries.iter().all(|e| reuse_port && e.reuse_port) {
+            entries.push(ListenTableEntry::new(listen_endpoint, key, reuse_port));
             Ok(())
         } else {
             ax_err!(AddrInUse, "socket listen() failed")
         }
     }
 
-    pub fn unlisten(&self, port: u16) {
+    /// Removes the listening socket, and the connections it hasn't accepted
+    /// from `sockets`.
+    pub fn unlisten(&self, port: u16, key: usize, sockets: &SocketSetWrapper) {
         debug!("TCP socket unlisten on {}", port);
-        *self.tcp[port as usize].lock() = None;
+        let mut entries = self.tcp[port as usize].lock();
+        let Ok(pos) = find_entry(&entries, key) else {
+            return;
+        };
+        let entry = entries.remove(pos);
+        // The socket set is locked before the table when polling.
+        drop(entries);
+        for handle in entry.syn_queue {
+            sockets.remove(handle);
+        }
     }
 
-    pub fn can_accept(&self, port: u16) -> AxResult<bool> {
-        if let Some(entry) = self.tcp[port as usize].lock().deref() {
-            Ok(entry.syn_queue.iter().any(|&handle| is_connected(handle)))
-        } else {
-            ax_err!(InvalidInput, "socket accept() failed: not listen")
-        }
+    pub fn can_accept(&self, port: u16, key: usize, sockets: &SocketSetWrapper) -> AxResult<bool> {
+        let entries = self.tcp[port as usize].lock();
+        let entry = &entries[find_entry(&entries, key)?];
+        Ok(entry
+            .syn_queue
+            .iter()
+            .any(|&handle| is_connected(sockets, handle)))
     }
 
-    pub fn accept(&self, port: u16) -> AxResult<(SocketHandle, (IpEndpoint, IpEndpoint))> {
-        if let Some(entry) = self.tcp[port as usize].lock().deref_mut() {
-            let syn_queue = &mut entry.syn_queue;
-            let (idx, addr_tuple) = syn_queue
-                .iter()
-                .enumerate()
-                .find_map(|(idx, &handle)| {
-                    is_connected(handle).then(|| (idx, get_addr_tuple(handle)))
-                })
-                .ok_or(AxError::WouldBlock)?; // wait for connection
-            if idx > 0 {
-                warn!(
-                    "slow SYN queue enumeration: index = {}, len = {}!",
-                    idx,
-                    syn_queue.len()
-                );
-            }
-            let handle = syn_queue.swap_remove_front(idx).unwrap();
-            Ok((handle, addr_tuple))
-        } else {
-            ax_err!(InvalidInput, "socket accept() failed: not listen")
+    pub fn accept(
+        &self,
+        port: u16,
+        key: usize,
+        sockets: &SocketSetWrapper,
+    ) -> AxResult<(SocketHandle, (IpEndpoint, IpEndpoint))> {
+        let mut entries = self.tcp[port as usize].lock();
+        let pos = find_entry(&entries, key)?;
+        let syn_queue = &mut entries[pos].syn_queue;
+        let (idx, addr_tuple) = syn_queue
+            .iter()
+            .enumerate()
+            .find_map(|(idx, &handle)| {
+                is_connected(sockets, handle).then(|| (idx, get_addr_tuple(sockets, handle)))
+            })
+            .ok_or(AxError::WouldBlock)?; // wait for connection
+        if idx > 0 {
+            warn!(
+                "slow SYN queue enumeration: index = {}, len = {}!",
+                idx,
+                syn_queue.len()
+            );
         }
+        let handle = syn_queue.swap_remove_front(idx).unwrap();
+        Ok((handle, addr_tuple))
     }
 
     pub fn incoming_tcp_packet(
@@ -116,37 +141,62 @@ impl ListenTable {
         dst: IpEndpoint,
         sockets: &mut SocketSet<'_>,
     ) {
-        if let Some(entry) = self.tcp[dst.port as usize].lock().deref_mut() {
-            if !entry.can_accept(dst.addr) {
-                // not listening on this address
-                return;
-            }
-            if entry.syn_queue.len() >= LISTEN_QUEUE_SIZE {
-                // SYN queue is full, drop the packet
-                warn!("SYN queue overflow!");
-                return;
-            }
-            let mut socket = SocketSetWrapper::new_tcp_socket();
-            if socket.listen(entry.listen_endpoint).is_ok() {
-                let handle = sockets.add(socket);
-                debug!(
-                    "TCP socket {}: prepare for connection {} -> {}",
-                    handle, src, entry.listen_endpoint
-                );
-                entry.syn_queue.push_back(handle);
-            }
+        let mut entries = self.tcp[dst.port as usize].lock();
+        let candidates: Vec<usize> = (0..entries.len())
+            .filter(|&i| entries[i].can_accept(dst.addr))
+            .collect();
+        if candidates.is_empty() {
+            // not listening on this address
+            return;
+        }
+        // retransmitted SYNs of a connection go to the same socket
+        let entry = &mut entries[candidates[flow_hash(src) % candidates.len()]];
+        if entry.syn_queue.len() >= LISTEN_QUEUE_SIZE {
+            // SYN queue is full, drop the packet
+            warn!("SYN queue overflow!");
+            return;
+        }
+        let mut socket = SocketSetWrapper::new_tcp_socket();
+        if socket.listen(entry.listen_endpoint).is_ok() {
+            let handle = sockets.add(socket);
+            debug!(
+                "TCP socket {}: prepare for connection {} -> {}",
+                handle, src, entry.listen_endpoint
+            );
+            entry.syn_queue.push_back(handle);
         }
     }
 }
 
-fn is_connected(handle: SocketHandle) -> bool {
-    SOCKET_SET.with_socket::<tcp::Socket, _, _>(handle, |socket| {
+fn find_entry(entries: &[ListenTableEntry], key: usize) -> AxResult<usize> {
+    entries
+        .iter()
+        .position(|e| e.key == key)
+        .ok_or_else(|| ax_err_type!(InvalidInput, "socket accept() failed: not listen"))
+}
+
+/// Hashes the source of a connection with FNV-1a.
+fn flow_hash(src: IpEndpoint) -> usize {
+    let port = src.port.to_be_bytes();
+    let hash = src
+        .addr
+        .as_bytes()
+        .iter()
+        .chain(port.iter())
+        .fold(0x811c_9dc5_u32, |h, &b| {
+            (h ^ b as u32).wrapping_mul(0x0100_0193)
+        });
+    hash as usize
+}
+
+fn is_connected(sockets: &SocketSetWrapper, handle: SocketHandle) -> bool {
+    sockets.with_socket::<tcp::Socket, _, _>(handle, |socket| {
         !matches!(socket.state(), State::Listen | State::SynReceived)
     })
 }
 
-fn get_addr_tuple(handle: SocketHandle) -> (IpEndpoint, IpEndpoint) {
-    SOCKET_SET.with_socket::<tcp::Socket, _, _>(handle, |socket| {
+fn get_addr_tuple(sockets: &SocketSetWrapper, handle: SocketHandle) -> (IpEndpoint, IpEndpoint) {
+    sockets.with_socket::<tcp::Socket, _, _>(handle, |socket| {
         (
             socket.local_endpoint().unwrap(),
             socket.remote_endpoint().unwrap(),
@@ -0,0 +1,107 @@
+use alloc::sync::Arc;
+use alloc::{collections::VecDeque, vec, vec::Vec};
+
+use smoltcp::iface::SocketSet;
+use smoltcp::phy::{Device, DeviceCapabilities, Medium, RxToken, TxToken};
+use smoltcp::time::Instant;
+
+use super::listen_table::ListenTable;
+use super::netfilter::{self, FilterHook};
+use super::snoop_tcp_packet;
+use super::tcp_info::TcpStatsTable;
+
+/// The MTU of the loopback device, including the Ethernet header.
+const LOOPBACK_MTU: usize = 65535;
+
+/// A software network device that sends every frame back to itself.
+///
+/// Unlike [`smoltcp::phy::Loopback`], it uses the Ethernet medium so that the
+/// loopback interface never takes the packets of other interfaces (there's no
+/// route to them), and it snoops incoming TCP packets like a real NIC does so
+/// that listening sockets can accept local connections.
+pub(super) struct LoopbackDevice {
+    queue: VecDeque<Vec<u8>>,
+    /// The listen table of the namespace the device is in.
+    listen_table: Arc<ListenTable>,
+    /// The TCP connections tracked in the namespace the device is in.
+    tcp_stats: Arc<TcpStatsTable>,
+}
+
+impl LoopbackDevice {
+    pub fn new(listen_table: Arc<ListenTable>, tcp_stats: Arc<TcpStatsTable>) -> Self {
+        Self {
+            queue: VecDeque::new(),
+            listen_table,
+            tcp_stats,
+        }
+    }
+}
+
+impl Device for LoopbackDevice {
+    type RxToken<'a>
+        = LoopbackRxToken<'a>
+    where
+        Self: 'a;
+    type TxToken<'a>
+        = LoopbackTxToken<'a>
+    where
+        Self: 'a;
+
+    fn receive(&mut self, _timestamp: Instant) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
+        let buf = loop {
+            let buf = self.queue.pop_front()?;
+            if netfilter::check(FilterHook::Ingress, &buf) {
+                break buf;
+            }
+        };
+        Some((
+            LoopbackRxToken(buf, &self.listen_table, &self.tcp_stats),
+            LoopbackTxToken(&mut self.queue, &self.tcp_stats),
+        ))
+    }
+
+    fn transmit(&mut self, _timestamp: Instant) -> Option<Self::TxToken<'_>> {
+        Some(LoopbackTxToken(&mut self.queue, &self.tcp_stats))
+    }
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        let mut caps = DeviceCapabilities::default();
+        caps.max_transmission_unit = LOOPBACK_MTU;
+        caps.max_burst_size = None;
+        caps.medium = Medium::Ethernet;
+        caps
+    }
+}
+
+pub(super) struct LoopbackRxToken<'a>(Vec<u8>, &'a ListenTable, &'a TcpStatsTable);
+pub(super) struct LoopbackTxToken<'a>(&'a mut VecDeque<Vec<u8>>, &'a TcpStatsTable);
+
+impl RxToken for LoopbackRxToken<'_> {
+    fn preprocess(&self, sockets: &mut SocketSet<'_>) {
+        snoop_tcp_packet(&self.0, sockets, self.1, self.2).ok();
+    }
+
+    fn consume<R, F>(mut self, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        trace!("LO RECV {} bytes: {:02X?}", self.0.len(), self.0);
+        f(&mut self.0)
+    }
+}
+
+impl TxToken for LoopbackTxToken<'_> {
+    fn consume<R, F>(self, len: usize, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        let mut buf = vec![0; len];
+        let ret = f(&mut buf);
+        trace!("LO SEND {} bytes: {:02X?}", len, buf);
+        if netfilter::check(FilterHook::Egress, &buf) {
+            self.1.on_outgoing_frame(&buf);
+            self.0.push_back(buf);
+        }
+        ret
+    }
+}
@@ -0,0 +1,504 @@
+//! Multicast DNS (RFC 6762) and DNS-based service discovery (RFC 6763).
+//!
+//! The responder answers the queries for the host name `<hostname>.local` and
+//! for the registered services on all the NICs, so the host can be found on a
+//! LAN without a DNS server. It announces the records when they're added, but
+//! doesn't probe for conflicts: the names must be unique on the link.
+//!
+//! The responder runs when the interfaces are polled, so it only answers while
+//! some task does network I/O or calls [`poll_interfaces`](super::poll_interfaces).
+//! It runs in the initial network namespace, which has the NICs.
+//!
+//! Names under `.local` are resolved with one-shot queries by the
+//! [DNS resolver](super::dns_query).
+
+use alloc::string::{String, ToString};
+use alloc::{format, vec, vec::Vec};
+use core::net::{IpAddr, Ipv4Addr, SocketAddr};
+use core::time::Duration;
+
+use axerrno::{AxResult, ax_err, ax_err_type};
+use smoltcp::iface::{SocketHandle, SocketSet};
+use smoltcp::socket::udp;
+use smoltcp::wire::{IpAddress, IpCidr, IpEndpoint, Ipv4Address};
+use spin::Mutex;
+
+use super::addr::into_core_ipaddr;
+use super::dns::{CLASS_IN, FLAG_RESPONSE, HEADER_LEN, MessageReader, TYPE_A, TYPE_PTR, push_name};
+use super::netns::initial;
+use super::{SocketSetWrapper, UdpSocket, poll_interfaces};
+
+const MDNS_PORT: u16 = 5353;
+const MDNS_GROUP: Ipv4Address = Ipv4Address::new(224, 0, 0, 251);
+const MDNS_ENDPOINT: IpEndpoint = IpEndpoint::new(IpAddress::Ipv4(MDNS_GROUP), MDNS_PORT);
+
+const TYPE_TXT: u16 = 16;
+const TYPE_SRV: u16 = 33;
+const TYPE_ANY: u16 = 255;
+
+/// Marks the unique records in multicast responses.
+const CLASS_CACHE_FLUSH: u16 = 0x8000;
+/// Marks the questions asking for a unicast response.
+const CLASS_UNICAST_RESPONSE: u16 = 0x8000;
+const FLAG_AUTHORITATIVE: u16 = 0x0400;
+const OPCODE_MASK: u16 = 0x7800;
+
+/// The TTL of the records naming the host: the address and SRV records.
+pub(super) const HOST_TTL: u32 = 120;
+/// The TTL of the other records.
+const OTHER_TTL: u32 = 4500;
+/// The maximum TTL in the responses to legacy unicast queries.
+const LEGACY_TTL: u32 = 10;
+
+const SERVICES_NAME: &str = "_services._dns-sd._udp.local";
+const MAX_MESSAGE_LEN: usize = 1472;
+
+static RESPONDER: Mutex<Option<Responder>> = Mutex::new(None);
+
+struct Service {
+    instance: String,
+    service_type: String,
+    port: u16,
+    txt: Vec<String>,
+}
+
+impl Service {
+    fn type_name(&self) -> String {
+        format!("{}.local", self.service_type)
+    }
+
+    fn instance_name(&self) -> String {
+        format!("{}.{}.local", self.instance, self.service_type)
+    }
+}
+
+struct Responder {
+    handle: SocketHandle,
+    host_name: String,
+    services: Vec<Service>,
+    /// The announcements to send on the next poll.
+    pending: Vec<Vec<u8>>,
+}
+
+/// A response being built.
+struct Response {
+    msg: Vec<u8>,
+    count: u16,
+    /// Whether it's multicast: unique records have the cache-flush bit.
+    multicast: bool,
+    max_ttl: u32,
+}
+
+impl Response {
+    fn new(id: u16, multicast: bool) -> Self {
+        let mut msg = Vec::with_capacity(MAX_MESSAGE_LEN);
+        msg.extend_from_slice(&id.to_be_bytes());
+        msg.extend_from_slice(&(FLAG_RESPONSE | FLAG_AUTHORITATIVE).to_be_bytes());
+        msg.extend_from_slice(&[0; 8]); // QDCOUNT, ANCOUNT, NSCOUNT, ARCOUNT
+        Self {
+            msg,
+            count: 0,
+            multicast,
+            max_ttl: if multicast { u32::MAX } else { LEGACY_TTL },
+        }
+    }
+
+    /// Echoes the question, as legacy unicast responses do.
+    fn question(&mut self, name: &str, qtype: u16, qclass: u16) {
+        let len = self.msg.len();
+        if push_name(&mut self.msg, name).is_err() {
+            self.msg.truncate(len);
+            return;
+        }
+        self.msg.extend_from_slice(&qtype.to_be_bytes());
+        self.msg.extend_from_slice(&qclass.to_be_bytes());
+        self.msg[4..6].copy_from_slice(&1u16.to_be_bytes());
+    }
+
+    fn record(&mut self, name: &str, rtype: u16, unique: bool, ttl: u32, rdata: &[u8]) {
+        let len = self.msg.len();
+        if push_name(&mut self.msg, name).is_err() {
+            self.msg.truncate(len);
+            return;
+        }
+        let class = if unique && self.multicast {
+            CLASS_IN | CLASS_CACHE_FLUSH
+        } else {
+            CLASS_IN
+        };
+        self.msg.extend_from_slice(&rtype.to_be_bytes());
+        self.msg.extend_from_slice(&class.to_be_bytes());
+        self.msg
+            .extend_from_slice(&ttl.min(self.max_ttl).to_be_bytes());
+        self.msg
+            .extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+        self.msg.extend_from_slice(rdata);
+        if self.msg.len() > MAX_MESSAGE_LEN {
+            self.msg.truncate(len);
+            return;
+        }
+        self.count += 1;
+        self.msg[6..8].copy_from_slice(&self.count.to_be_bytes());
+    }
+
+    fn name_record(&mut self, name: &str, rtype: u16, unique: bool, ttl: u32, target: &str) {
+        let mut rdata = Vec::new();
+        if push_name(&mut rdata, target).is_ok() {
+            self.record(name, rtype, unique, ttl, &rdata);
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+}
+
+/// Returns the IPv4 addresses of the NICs, only those on the network of `peer`
+/// if there are some.
+fn host_addrs(peer: Option<Ipv4Address>) -> Vec<Ipv4Address> {
+    let cidrs: Vec<_> = initial()
+        .nics()
+        .iter()
+        .flat_map(|nic| nic.ip_addrs())
+        .filter_map(|cidr| match cidr {
+            IpCidr::Ipv4(cidr) => Some(cidr),
+        })
+        .collect();
+    let local: Vec<_> = cidrs
+        .iter()
+        .filter(|cidr| peer.is_some_and(|peer| cidr.contains_addr(&peer)))
+        .map(|cidr| cidr.address())
+        .collect();
+    if local.is_empty() {
+        cidrs.iter().map(|cidr| cidr.address()).collect()
+    } else {
+        local
+    }
+}
+
+impl Responder {
+    fn host_records(&self, res: &mut Response, peer: Option<Ipv4Address>) {
+        for addr in host_addrs(peer) {
+            res.record(&self.host_name, TYPE_A, true, HOST_TTL, &addr.0);
+        }
+    }
+
+    fn service_records(&self, res: &mut Response, service: &Service, rtype: u16) {
+        let name = service.instance_name();
+        if matches!(rtype, TYPE_SRV | TYPE_ANY) {
+            let mut rdata = Vec::new();
+            rdata.extend_from_slice(&[0; 4]); // priority, weight
+            rdata.extend_from_slice(&service.port.to_be_bytes());
+            if push_name(&mut rdata, &self.host_name).is_ok() {
+                res.record(&name, TYPE_SRV, true, HOST_TTL, &rdata);
+            }
+        }
+        if matches!(rtype, TYPE_TXT | TYPE_ANY) {
+            let mut rdata = Vec::new();
+            for entry in &service.txt {
+                rdata.push(entry.len() as u8);
+                rdata.extend_from_slice(entry.as_bytes());
+            }
+            if rdata.is_empty() {
+                // a TXT record has at least one (empty) string
+                rdata.push(0);
+            }
+            res.record(&name, TYPE_TXT, true, OTHER_TTL, &rdata);
+        }
+    }
+
+    /// Adds the records answering the question for `name` to `res`.
+    fn answer(&self, res: &mut Response, name: &str, qtype: u16, peer: Option<Ipv4Address>) {
+        if name.eq_ignore_ascii_case(&self.host_name) {
+            if matches!(qtype, TYPE_A | TYPE_ANY) {
+                self.host_records(res, peer);
+            }
+            return;
+        }
+        let ptr = matches!(qtype, TYPE_PTR | TYPE_ANY);
+        if name.eq_ignore_ascii_case(SERVICES_NAME) {
+            if ptr {
+                let mut types: Vec<_> = self.services.iter().map(Service::type_name).collect();
+                types.sort_unstable();
+                types.dedup();
+                for service_type in types {
+                    res.name_record(name, TYPE_PTR, false, OTHER_TTL, &service_type);
+                }
+            }
+            return;
+        }
+        for service in &self.services {
+            if ptr && name.eq_ignore_ascii_case(&service.type_name()) {
+                res.name_record(name, TYPE_PTR, false, OTHER_TTL, &service.instance_name());
+            } else if name.eq_ignore_ascii_case(&service.instance_name()) {
+                self.service_records(res, service, qtype);
+                if matches!(qtype, TYPE_SRV | TYPE_ANY) {
+                    // the address of the target, which the querier needs next
+                    self.host_records(res, peer);
+                }
+            }
+        }
+    }
+
+    /// Answers the query `msg` from `peer`. Returns the response and where to
+    /// send it, if any record matches.
+    fn respond(&self, msg: &[u8], peer: IpEndpoint) -> Option<(Vec<u8>, IpEndpoint)> {
+        let mut reader = MessageReader::new(msg);
+        let id = reader.u16().ok()?;
+        let flags = reader.u16().ok()?;
+        if flags & (FLAG_RESPONSE | OPCODE_MASK) != 0 {
+            return None;
+        }
+        let qdcount = reader.u16().ok()?;
+        reader.bytes(HEADER_LEN - 6).ok()?;
+
+        let IpAddress::Ipv4(peer_addr) = peer.addr;
+        // Legacy resolvers send one-shot queries from another port, and only
+        // accept unicast responses with the ID and the question.
+        let legacy = peer.port != MDNS_PORT;
+        let mut res = Response::new(if legacy { id } else { 0 }, !legacy);
+        let mut unicast = legacy;
+        for i in 0..qdcount {
+            let name = reader.name().ok()?;
+            let qtype = reader.u16().ok()?;
+            let qclass = reader.u16().ok()?;
+            if qclass & !CLASS_UNICAST_RESPONSE != CLASS_IN {
+                continue;
+            }
+            unicast |= qclass & CLASS_UNICAST_RESPONSE != 0;
+            if legacy && i == 0 {
+                res.question(&name, qtype, qclass);
+            }
+            self.answer(&mut res, &name, qtype, Some(peer_addr));
+        }
+        if res.is_empty() {
+            return None;
+        }
+        Some((res.msg, if unicast { peer } else { MDNS_ENDPOINT }))
+    }
+
+    /// Queues an announcement of the host, and of `service` if given.
+    fn announce(&mut self, service: Option<usize>) {
+        let mut res = Response::new(0, true);
+        self.host_records(&mut res, None);
+        if let Some(service) = service.map(|i| &self.services[i]) {
+            let type_name = service.type_name();
+            res.name_record(
+                &type_name,
+                TYPE_PTR,
+                false,
+                OTHER_TTL,
+                &service.instance_name(),
+            );
+            res.name_record(SERVICES_NAME, TYPE_PTR, false, OTHER_TTL, &type_name);
+            self.service_records(&mut res, service, TYPE_ANY);
+        }
+        if !res.is_empty() {
+            self.pending.push(res.msg);
+        }
+    }
+}
+
+fn check_label(label: &str) -> AxResult {
+    if label.is_empty() || label.len() > 63 || label.contains('.') {
+        return ax_err!(InvalidInput, "invalid mDNS name");
+    }
+    Ok(())
+}
+
+/// Starts the mDNS responder, advertising the host as `<hostname>.local`.
+///
+/// It joins the mDNS group on all the NICs and announces the addresses of the
+/// host.
+pub fn start_mdns(hostname: &str) -> AxResult {
+    check_label(hostname)?;
+    let ns = initial();
+    let nics = ns.nics();
+    for (i, nic) in nics.iter().enumerate() {
+        if let Err(e) = nic.update_multicast_group(MDNS_GROUP, true) {
+            for nic in &nics[..i] {
+                nic.update_multicast_group(MDNS_GROUP, false).ok();
+            }
+            return Err(e);
+        }
+    }
+
+    let mut socket = SocketSetWrapper::new_udp_socket();
+    socket.set_hop_limit(Some(255));
+    socket.bind(MDNS_PORT).unwrap();
+    let handle = ns.sockets.add(socket);
+
+    let mut responder = RESPONDER.lock();
+    if responder.is_some() {
+        drop(responder);
+        ns.sockets.remove(handle);
+        for nic in nics.iter() {
+            nic.update_multicast_group(MDNS_GROUP, false).ok();
+        }
+        return ax_err!(AlreadyExists, "mDNS responder already started");
+    }
+    let mut new = Responder {
+        handle,
+        host_name: format!("{hostname}.local"),
+        services: Vec::new(),
+        pending: Vec::new(),
+    };
+    new.announce(None);
+    *responder = Some(new);
+    drop(responder);
+
+    info!("mDNS responder started as {hostname}.local");
+    poll_interfaces();
+    Ok(())
+}
+
+/// Stops the mDNS responder, and unregisters all the services.
+pub fn stop_mdns() -> AxResult {
+    let responder = RESPONDER
+        .lock()
+        .take()
+        .ok_or_else(|| ax_err_type!(NotFound, "mDNS responder not started"))?;
+    let ns = initial();
+    ns.sockets.remove(responder.handle);
+    for nic in ns.nics().iter() {
+        nic.update_multicast_group(MDNS_GROUP, false).ok();
+    }
+    info!("mDNS responder stopped");
+    Ok(())
+}
+
+/// Advertises the service instance `instance` of type `service_type` (e.g.
+/// `_http._tcp`) on `port` of the host, with the TXT record entries `txt`.
+///
+/// The mDNS responder must be started first.
+pub fn register_mdns_service(
+    instance: &str,
+    service_type: &str,
+    port: u16,
+    txt: &[&str],
+) -> AxResult {
+    check_label(instance)?;
+    let mut labels = service_type.split('.');
+    let valid_type = match (labels.next(), labels.next(), labels.next()) {
+        (Some(name), Some(proto), None) => {
+            name.len() > 1
+                && name.starts_with('_')
+                && check_label(name).is_ok()
+                && matches!(proto, "_tcp" | "_udp")
+        }
+        _ => false,
+    };
+    if !valid_type {
+        return ax_err!(InvalidInput, "invalid service type");
+    }
+    if txt.iter().any(|entry| entry.len() > 255) {
+        return ax_err!(InvalidInput, "TXT entry too long");
+    }
+
+    let service = Service {
+        instance: instance.to_string(),
+        service_type: service_type.to_string(),
+        port,
+        txt: txt.iter().map(|entry| entry.to_string()).collect(),
+    };
+    let name = service.instance_name();
+    let mut guard = RESPONDER.lock();
+    let responder = guard
+        .as_mut()
+        .ok_or_else(|| ax_err_type!(BadState, "mDNS responder not started"))?;
+    if responder
+        .services
+        .iter()
+        .any(|s| s.instance_name().eq_ignore_ascii_case(&name))
+    {
+        return ax_err!(AlreadyExists, "mDNS service already registered");
+    }
+    responder.services.push(service);
+    responder.announce(Some(responder.services.len() - 1));
+    drop(guard);
+
+    debug!("mDNS service {name} registered on port {port}");
+    poll_interfaces();
+    Ok(())
+}
+
+/// Stops advertising the service instance `instance` of type `service_type`.
+pub fn unregister_mdns_service(instance: &str, service_type: &str) -> AxResult {
+    let mut responder = RESPONDER.lock();
+    let responder = responder
+        .as_mut()
+        .ok_or_else(|| ax_err_type!(BadState, "mDNS responder not started"))?;
+    let name = format!("{instance}.{service_type}.local");
+    let index = responder
+        .services
+        .iter()
+        .position(|s| s.instance_name().eq_ignore_ascii_case(&name))
+        .ok_or_else(|| ax_err_type!(NotFound, "mDNS service not registered"))?;
+    responder.services.remove(index);
+    debug!("mDNS service {name} unregistered");
+    Ok(())
+}
+
+/// Answers the mDNS queries received, and sends the pending announcements.
+/// Returns whether anything was sent.
+///
+/// Called when the interfaces are polled, with the socket set locked.
+pub(super) fn poll(sockets: &mut SocketSet) -> bool {
+    let mut responder = RESPONDER.lock();
+    let Some(responder) = responder.as_mut() else {
+        return false;
+    };
+    let socket = sockets.get_mut::<udp::Socket>(responder.handle);
+    let mut sent = false;
+    for msg in responder.pending.drain(..) {
+        sent |= socket.send_slice(&msg, MDNS_ENDPOINT).is_ok();
+    }
+    let mut buf = [0; MAX_MESSAGE_LEN];
+    while let Ok((len, meta)) = socket.recv_slice(&mut buf) {
+        if let Some((res, dest)) = responder.respond(&buf[..len], meta.endpoint) {
+            sent |= socket.send_slice(&res, dest).is_ok();
+        }
+    }
+    sent
+}
+
+/// Returns whether `name` (in lower case) is resolved with mDNS.
+pub(super) fn is_local_name(name: &str) -> bool {
+    let name = name.strip_suffix('.').unwrap_or(name);
+    name.ends_with(".local")
+}
+
+/// Returns the addresses of the host if `name` is its mDNS host name.
+pub(super) fn own_addrs(name: &str) -> Option<Vec<IpAddr>> {
+    let name = name.strip_suffix('.').unwrap_or(name);
+    let responder = RESPONDER.lock();
+    if !responder.as_ref()?.host_name.eq_ignore_ascii_case(name) {
+        return None;
+    }
+    drop(responder);
+    Some(
+        host_addrs(None)
+            .into_iter()
+            .map(|addr| into_core_ipaddr(IpAddress::Ipv4(addr)))
+            .collect(),
+    )
+}
+
+/// Sends the one-shot query `msg` to the mDNS group, and returns the first
+/// response with the same ID.
+pub(super) fn query(msg: &[u8], timeout: Duration) -> AxResult<Vec<u8>> {
+    let socket = UdpSocket::new();
+    socket.set_recv_timeout(Some(timeout));
+    socket.set_multicast_ttl_v4(255);
+    socket.bind(SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0))?;
+    let group = Ipv4Addr::from(MDNS_GROUP.0);
+    socket.send_to(msg, SocketAddr::new(IpAddr::V4(group), MDNS_PORT))?;
+    let mut buf = vec![0; MAX_MESSAGE_LEN];
+    loop {
+        let (len, _) = socket.recv_from(&mut buf)?;
+        if len >= HEADER_LEN && buf[..2] == msg[..2] {
+            buf.truncate(len);
+            return Ok(buf);
+        }
+    }
+}
@@ -1,30 +1,74 @@
 mod addr;
 mod bench;
+mod bpf;
 mod dns;
+mod event;
+mod happy_eyeballs;
+mod icmp;
 mod listen_table;
+mod loopback;
+mod mdns;
+mod multicast;
+mod netfilter;
+mod netns;
+mod qdisc;
+mod raw;
+mod route;
 mod tcp;
+mod tcp_info;
 mod udp;
+mod veth;
 
-use alloc::vec;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::{vec, vec::Vec};
 use core::cell::RefCell;
+use core::net::IpAddr;
 use core::ops::DerefMut;
+use core::time::Duration;
 
 use axdriver::prelude::*;
 use axdriver_net::{DevError, NetBufPtr};
-use axhal::time::{NANOS_PER_MICROS, wall_time_nanos};
+use axerrno::{AxError, AxResult, ax_err, ax_err_type};
+use axhal::time::{NANOS_PER_MICROS, monotonic_time, wall_time_nanos};
 use axsync::Mutex;
-use lazyinit::LazyInit;
 use smoltcp::iface::{Config, Interface, SocketHandle, SocketSet};
 use smoltcp::phy::{Device, DeviceCapabilities, Medium, RxToken, TxToken};
 use smoltcp::socket::{self, AnySocket};
 use smoltcp::time::Instant;
-use smoltcp::wire::{EthernetAddress, HardwareAddress, IpAddress, IpCidr};
+use smoltcp::wire::{
+    EthernetAddress, HardwareAddress, IpAddress, IpCidr, IpProtocol, IpVersion, Ipv4Address,
+};
 
+use self::addr::{from_core_ipaddr, into_core_ipaddr};
 use self::listen_table::ListenTable;
-
-pub use self::dns::dns_query;
+use self::qdisc::Qdisc;
+use self::tcp_info::TcpStatsTable;
+use self::veth::VethDevice;
+
+pub use self::bpf::{BpfInsn, BpfProgram};
+pub use self::dns::{dns_query, dns_reverse_query, load_resolv_conf, nameservers, set_nameservers};
+pub use self::happy_eyeballs::ConnectOptions;
+pub use self::icmp::IcmpSocket;
+pub use self::mdns::{register_mdns_service, start_mdns, stop_mdns, unregister_mdns_service};
+pub use self::netfilter::{
+    FilterAction, FilterHook, FilterRule, add_filter_rule, del_filter_rule, filter_rules,
+    flush_filter_rules,
+};
+pub use self::netns::{
+    NetNamespace, current_net_namespace, set_net_namespace, unshare_net_namespace,
+};
+pub use self::qdisc::InterfaceStats;
+pub use self::raw::RawSocket;
+pub use self::route::{
+    MAIN_TABLE, Route, RouteRule, add_route, add_route_rule, del_route, del_route_rule,
+    proc_net_route, route_rules, routes,
+};
 pub use self::tcp::TcpSocket;
+pub use self::tcp_info::{TcpConnection, TcpInfo, TcpState, proc_net_tcp, tcp_connections};
 pub use self::udp::UdpSocket;
+pub use self::veth::{add_veth_pair, del_veth};
 
 macro_rules! env_or_default {
     ($key:literal) => {
@@ -37,10 +81,17 @@ macro_rules! env_or_default {
 
 const IP: &str = env_or_default!("AX_IP");
 const GATEWAY: &str = env_or_default!("AX_GW");
-const DNS_SEVER: &str = "8.8.8.8";
 const IP_PREFIX: u8 = 24;
 
+const LOOPBACK_IP: Ipv4Address = Ipv4Address::new(127, 0, 0, 1);
+const LOOPBACK_PREFIX: u8 = 8;
+
 const STANDARD_MTU: usize = 1500;
+/// The smallest MTU every IPv4 link must support.
+const MIN_MTU: usize = 68;
+/// The largest MTU of the NICs, for jumbo frames.
+const MAX_MTU: usize = 9000;
+const ETHERNET_HEADER_LEN: usize = 14;
 
 const RANDOM_SEED: u64 = 0xA2CE_05A2_CE05_A2CE;
 
@@ -48,23 +99,40 @@ const TCP_RX_BUF_LEN: usize = 64 * 1024;
 const TCP_TX_BUF_LEN: usize = 64 * 1024;
 const UDP_RX_BUF_LEN: usize = 64 * 1024;
 const UDP_TX_BUF_LEN: usize = 64 * 1024;
+const ICMP_RX_BUF_LEN: usize = 16 * 1024;
+const ICMP_TX_BUF_LEN: usize = 16 * 1024;
+const RAW_RX_BUF_LEN: usize = 64 * 1024;
+const RAW_TX_BUF_LEN: usize = 64 * 1024;
+/// The number of datagrams each UDP buffer holds, enough for a whole
+/// segmented send.
+const UDP_PACKET_QUEUE_LEN: usize = 64;
 const LISTEN_QUEUE_SIZE: usize = 512;
 
-static LISTEN_TABLE: LazyInit<ListenTable> = LazyInit::new();
-static SOCKET_SET: LazyInit<SocketSetWrapper> = LazyInit::new();
-static ETH0: LazyInit<InterfaceWrapper> = LazyInit::new();
-
 struct SocketSetWrapper<'a>(Mutex<SocketSet<'a>>);
 
 struct DeviceWrapper {
-    inner: RefCell<AxNetDevice>, // use `RefCell` is enough since it's wrapped in `Mutex` in `InterfaceWrapper`.
+    inner: NicDevice,
+    /// The MTU, excluding the Ethernet header.
+    mtu: usize,
+    /// The listen table of the namespace the NIC is in.
+    listen_table: Arc<ListenTable>,
+    /// The TCP connections tracked in the namespace the NIC is in.
+    tcp_stats: Arc<TcpStatsTable>,
 }
 
-struct InterfaceWrapper {
-    name: &'static str,
+enum NicDevice {
+    Driver(RefCell<AxNetDevice>), // use `RefCell` is enough since it's wrapped in `Mutex` in `InterfaceWrapper`.
+    Veth(VethDevice),
+}
+
+struct InterfaceWrapper<D> {
+    name: String,
     ether_addr: EthernetAddress,
-    dev: Mutex<DeviceWrapper>,
+    dev: Mutex<D>,
     iface: Mutex<Interface>,
+    qdisc: Qdisc,
+    /// The joined multicast groups, and the number of sockets in each.
+    multicast_groups: Mutex<BTreeMap<Ipv4Address, usize>>,
 }
 
 impl<'a> SocketSetWrapper<'a> {
@@ -80,19 +148,38 @@ impl<'a> SocketSetWrapper<'a> {
 
     pub fn new_udp_socket() -> socket::udp::Socket<'a> {
         let udp_rx_buffer = socket::udp::PacketBuffer::new(
-            vec![socket::udp::PacketMetadata::EMPTY; 8],
+            vec![socket::udp::PacketMetadata::EMPTY; UDP_PACKET_QUEUE_LEN],
             vec![0; UDP_RX_BUF_LEN],
         );
         let udp_tx_buffer = socket::udp::PacketBuffer::new(
-            vec![socket::udp::PacketMetadata::EMPTY; 8],
+            vec![socket::udp::PacketMetadata::EMPTY; UDP_PACKET_QUEUE_LEN],
             vec![0; UDP_TX_BUF_LEN],
         );
         socket::udp::Socket::new(udp_rx_buffer, udp_tx_buffer)
     }
 
-    pub fn new_dns_socket() -> socket::dns::Socket<'a> {
-        let server_addr = DNS_SEVER.parse().expect("invalid DNS server address");
-        socket::dns::Socket::new(&[server_addr], vec![])
+    pub fn new_icmp_socket() -> socket::icmp::Socket<'a> {
+        let icmp_rx_buffer = socket::icmp::PacketBuffer::new(
+            vec![socket::icmp::PacketMetadata::EMPTY; 8],
+            vec![0; ICMP_RX_BUF_LEN],
+        );
+        let icmp_tx_buffer = socket::icmp::PacketBuffer::new(
+            vec![socket::icmp::PacketMetadata::EMPTY; 8],
+            vec![0; ICMP_TX_BUF_LEN],
+        );
+        socket::icmp::Socket::new(icmp_rx_buffer, icmp_tx_buffer)
+    }
+
+    pub fn new_raw_socket(protocol: IpProtocol) -> socket::raw::Socket<'a> {
+        let raw_rx_buffer = socket::raw::PacketBuffer::new(
+            vec![socket::raw::PacketMetadata::EMPTY; 8],
+            vec![0; RAW_RX_BUF_LEN],
+        );
+        let raw_tx_buffer = socket::raw::PacketBuffer::new(
+            vec![socket::raw::PacketMetadata::EMPTY; 8],
+            vec![0; RAW_TX_BUF_LEN],
+        );
+        socket::raw::Socket::new(IpVersion::Ipv4, protocol, raw_rx_buffer, raw_tx_buffer)
     }
 
     pub fn add<T: AnySocket<'a>>(&self, socket: T) -> SocketHandle {
@@ -119,70 +206,153 @@ impl<'a> SocketSetWrapper<'a> {
         f(socket)
     }
 
-    pub fn poll_interfaces(&self) {
-        ETH0.poll(&self.0);
-    }
-
     pub fn remove(&self, handle: SocketHandle) {
         self.0.lock().remove(handle);
         debug!("socket {}: destroyed", handle);
     }
 }
 
-impl InterfaceWrapper {
-    fn new(name: &'static str, dev: AxNetDevice, ether_addr: EthernetAddress) -> Self {
+impl<D: Device> InterfaceWrapper<D> {
+    fn new(name: String, mut dev: D, ether_addr: EthernetAddress) -> Self {
         let mut config = Config::new(HardwareAddress::Ethernet(ether_addr));
         config.random_seed = RANDOM_SEED;
 
-        let mut dev = DeviceWrapper::new(dev);
-        let iface = Mutex::new(Interface::new(config, &mut dev, Self::current_time()));
+        let iface = Mutex::new(Interface::new(config, &mut dev, current_time()));
         Self {
             name,
             ether_addr,
             dev: Mutex::new(dev),
             iface,
+            qdisc: Qdisc::new(),
+            multicast_groups: Mutex::new(BTreeMap::new()),
         }
     }
 
-    fn current_time() -> Instant {
-        Instant::from_micros_const((wall_time_nanos() / NANOS_PER_MICROS) as i64)
-    }
-
     pub fn name(&self) -> &str {
-        self.name
+        &self.name
     }
 
     pub fn ethernet_address(&self) -> EthernetAddress {
         self.ether_addr
     }
 
-    pub fn setup_ip_addr(&self, ip: IpAddress, prefix_len: u8) {
+    /// Replaces the addresses of the interface with `ip`.
+    pub fn set_ip_addr(&self, ip: IpAddress, prefix_len: u8) {
         let mut iface = self.iface.lock();
         iface.update_ip_addrs(|ip_addrs| {
+            ip_addrs.clear();
             ip_addrs.push(IpCidr::new(ip, prefix_len)).unwrap();
         });
     }
 
-    pub fn setup_gateway(&self, gateway: IpAddress) {
-        let mut iface = self.iface.lock();
-        match gateway {
-            IpAddress::Ipv4(v4) => iface.routes_mut().add_default_ipv4_route(v4).unwrap(),
+    pub fn ip_addrs(&self) -> Vec<IpCidr> {
+        self.iface.lock().ip_addrs().to_vec()
+    }
+
+    pub fn has_ip_addr(&self, addr: IpAddress) -> bool {
+        self.iface.lock().has_ip_addr(addr)
+    }
+
+    /// Returns the MTU, excluding the Ethernet header.
+    pub fn mtu(&self) -> usize {
+        self.dev.lock().capabilities().ip_mtu()
+    }
+
+    /// Adds a socket to (`join` is true) or removes one from the multicast
+    /// group `group`. IGMP messages are sent when the first socket joins and
+    /// the last one leaves.
+    ///
+    /// Returns [`NotFound`](AxError::NotFound) if no socket joined the group.
+    pub fn update_multicast_group(&self, group: Ipv4Address, join: bool) -> AxResult {
+        let mut groups = self.multicast_groups.lock();
+        let count = groups.get(&group).copied().unwrap_or(0);
+        let new_count = if join {
+            count + 1
+        } else if count > 0 {
+            count - 1
+        } else {
+            return ax_err!(NotFound, "multicast group not joined");
         };
+        if (count == 0) != (new_count == 0) {
+            let mut dev = self.dev.lock();
+            let mut iface = self.iface.lock();
+            let dev = &mut self.qdisc.wrap(dev.deref_mut());
+            if join {
+                // Fails if the report can't be sent as well, but it's sent
+                // again when the routers query.
+                let res = iface.join_multicast_group(dev, group, current_time());
+                if res.is_err() && !iface.has_multicast_group(group) {
+                    return ax_err!(NoMemory, "too many multicast groups");
+                }
+            } else {
+                iface.leave_multicast_group(dev, group, current_time()).ok();
+            }
+            debug!(
+                "{} multicast group {} on {:?}",
+                if join { "join" } else { "leave" },
+                group,
+                self.name
+            );
+        }
+        if new_count == 0 {
+            groups.remove(&group);
+        } else {
+            groups.insert(group, new_count);
+        }
+        Ok(())
     }
 
-    pub fn poll(&self, sockets: &Mutex<SocketSet>) {
+    pub fn info(&self) -> InterfaceInfo {
+        InterfaceInfo {
+            name: self.name.clone(),
+            ether_addr: self.ether_addr.0,
+            addrs: self
+                .iface
+                .lock()
+                .ip_addrs()
+                .iter()
+                .map(|cidr| (into_core_ipaddr(cidr.address()), cidr.prefix_len()))
+                .collect(),
+            mtu: self.mtu(),
+            rate_limit: self.qdisc.rate(),
+            stats: self.qdisc.stats(),
+        }
+    }
+
+    /// Polls the interface, and returns whether the state of the sockets may
+    /// have changed.
+    pub fn poll(&self, sockets: &mut SocketSet) -> bool {
         let mut dev = self.dev.lock();
         let mut iface = self.iface.lock();
-        let mut sockets = sockets.lock();
-        let timestamp = Self::current_time();
-        iface.poll(timestamp, dev.deref_mut(), &mut sockets);
+        let timestamp = current_time();
+        iface.poll(timestamp, &mut self.qdisc.wrap(dev.deref_mut()), sockets)
     }
 }
 
 impl DeviceWrapper {
-    fn new(inner: AxNetDevice) -> Self {
+    fn new_driver(
+        dev: AxNetDevice,
+        listen_table: Arc<ListenTable>,
+        tcp_stats: Arc<TcpStatsTable>,
+    ) -> Self {
+        Self {
+            inner: NicDevice::Driver(RefCell::new(dev)),
+            mtu: STANDARD_MTU,
+            listen_table,
+            tcp_stats,
+        }
+    }
+
+    fn new_veth(
+        dev: VethDevice,
+        listen_table: Arc<ListenTable>,
+        tcp_stats: Arc<TcpStatsTable>,
+    ) -> Self {
         Self {
-            inner: RefCell::new(inner),
+            inner: NicDevice::Veth(dev),
+            mtu: STANDARD_MTU,
+            listen_table,
+            tcp_stats,
         }
     }
 }
@@ -198,70 +368,118 @@ impl Device for DeviceWrapper {
         Self: 'a;
 
     fn receive(&mut self, _timestamp: Instant) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
-        let mut dev = self.inner.borrow_mut();
-        if let Err(e) = dev.recycle_tx_buffers() {
-            warn!("recycle_tx_buffers failed: {:?}", e);
-            return None;
-        }
+        let rx_buf = match &self.inner {
+            NicDevice::Driver(dev) => {
+                let mut dev = dev.borrow_mut();
+                if let Err(e) = dev.recycle_tx_buffers() {
+                    warn!("recycle_tx_buffers failed: {:?}", e);
+                    return None;
+                }
 
-        if !dev.can_transmit() {
-            return None;
-        }
-        let rx_buf = match dev.receive() {
-            Ok(buf) => buf,
-            Err(err) => {
-                if !matches!(err, DevError::Again) {
-                    warn!("receive failed: {:?}", err);
+                if !dev.can_transmit() {
+                    return None;
+                }
+                loop {
+                    let rx_buf = match dev.receive() {
+                        Ok(buf) => buf,
+                        Err(err) => {
+                            if !matches!(err, DevError::Again) {
+                                warn!("receive failed: {:?}", err);
+                            }
+                            return None;
+                        }
+                    };
+                    if netfilter::check(FilterHook::Ingress, rx_buf.packet()) {
+                        break RxBuf::Driver(rx_buf);
+                    }
+                    if let Err(e) = dev.recycle_rx_buffer(rx_buf) {
+                        warn!("recycle_rx_buffer failed: {:?}", e);
+                        return None;
+                    }
                 }
-                return None;
             }
+            NicDevice::Veth(veth) => loop {
+                let buf = veth.receive()?;
+                if netfilter::check(FilterHook::Ingress, &buf) {
+                    break RxBuf::Veth(buf);
+                }
+            },
         };
-        Some((AxNetRxToken(&self.inner, rx_buf), AxNetTxToken(&self.inner)))
+        Some((AxNetRxToken(self, rx_buf), AxNetTxToken(self)))
     }
 
     fn transmit(&mut self, _timestamp: Instant) -> Option<Self::TxToken<'_>> {
-        let mut dev = self.inner.borrow_mut();
-        if let Err(e) = dev.recycle_tx_buffers() {
-            warn!("recycle_tx_buffers failed: {:?}", e);
-            return None;
-        }
-        if dev.can_transmit() {
-            Some(AxNetTxToken(&self.inner))
-        } else {
-            None
+        if let NicDevice::Driver(dev) = &self.inner {
+            let mut dev = dev.borrow_mut();
+            if let Err(e) = dev.recycle_tx_buffers() {
+                warn!("recycle_tx_buffers failed: {:?}", e);
+                return None;
+            }
+            if !dev.can_transmit() {
+                return None;
+            }
         }
+        Some(AxNetTxToken(self))
     }
 
     fn capabilities(&self) -> DeviceCapabilities {
         let mut caps = DeviceCapabilities::default();
-        caps.max_transmission_unit = 1514;
+        caps.max_transmission_unit = self.mtu + ETHERNET_HEADER_LEN;
         caps.max_burst_size = None;
         caps.medium = Medium::Ethernet;
         caps
     }
 }
 
-struct AxNetRxToken<'a>(&'a RefCell<AxNetDevice>, NetBufPtr);
-struct AxNetTxToken<'a>(&'a RefCell<AxNetDevice>);
+/// A received frame, in a buffer of the driver or sent by the veth peer.
+enum RxBuf {
+    Driver(NetBufPtr),
+    Veth(Vec<u8>),
+}
+
+impl RxBuf {
+    fn packet(&self) -> &[u8] {
+        match self {
+            Self::Driver(buf) => buf.packet(),
+            Self::Veth(buf) => buf,
+        }
+    }
+}
+
+struct AxNetRxToken<'a>(&'a DeviceWrapper, RxBuf);
+struct AxNetTxToken<'a>(&'a DeviceWrapper);
 
 impl RxToken for AxNetRxToken<'_> {
     fn preprocess(&self, sockets: &mut SocketSet<'_>) {
-        snoop_tcp_packet(self.1.packet(), sockets).ok();
+        snoop_tcp_packet(
+            self.1.packet(),
+            sockets,
+            &self.0.listen_table,
+            &self.0.tcp_stats,
+        )
+        .ok();
     }
 
     fn consume<R, F>(self, f: F) -> R
     where
         F: FnOnce(&mut [u8]) -> R,
     {
-        let mut rx_buf = self.1;
         trace!(
             "RECV {} bytes: {:02X?}",
-            rx_buf.packet_len(),
-            rx_buf.packet()
+            self.1.packet().len(),
+            self.1.packet()
         );
-        let result = f(rx_buf.packet_mut());
-        self.0.borrow_mut().recycle_rx_buffer(rx_buf).unwrap();
-        result
+        match (&self.0.inner, self.1) {
+            (NicDevice::Driver(dev), RxBuf::Driver(mut rx_buf)) => {
+                let result = f(rx_buf.packet_mut());
+                if let Err(e) = dev.borrow_mut().recycle_rx_buffer(rx_buf) {
+                    warn!("recycle_rx_buffer failed: {:?}", e);
+                }
+                result
+            }
+            (_, RxBuf::Veth(mut buf)) => f(&mut buf),
+            (NicDevice::Veth(_), RxBuf::Driver(_)) => unreachable!(),
+        }
     }
 }
 
@@ -270,16 +488,72 @@ impl TxToken for AxNetTxToken<'_> {
     where
         F: FnOnce(&mut [u8]) -> R,
     {
-        let mut dev = self.0.borrow_mut();
-        let mut tx_buf = dev.alloc_tx_buffer(len).unwrap();
-        let ret = f(tx_buf.packet_mut());
-        trace!("SEND {} bytes: {:02X?}", len, tx_buf.packet());
-        dev.transmit(tx_buf).unwrap();
-        ret
+        let dev = match &self.0.inner {
+            NicDevice::Driver(dev) => dev,
+            NicDevice::Veth(veth) => {
+                let mut buf = vec![0; len];
+                let ret = f(&mut buf);
+                trace!("SEND {} bytes: {:02X?}", len, buf);
+                if netfilter::check(FilterHook::Egress, &buf) {
+                    self.0.tcp_stats.on_outgoing_frame(&buf);
+                    veth.send(buf);
+                }
+                return ret;
+            }
+        };
+        let mut dev = dev.borrow_mut();
+        if netfilter::is_active(FilterHook::Egress) {
+            // The packet is built aside, as a TX buffer can't be freed without
+            // being sent.
+            let mut buf = vec![0; len];
+            let ret = f(&mut buf);
+            if netfilter::check(FilterHook::Egress, &buf) {
+                self.0.tcp_stats.on_outgoing_frame(&buf);
+                match dev.alloc_tx_buffer(len) {
+                    Ok(mut tx_buf) => {
+                        tx_buf.packet_mut().copy_from_slice(&buf);
+                        trace!("SEND {} bytes: {:02X?}", len, tx_buf.packet());
+                        dev.transmit(tx_buf).unwrap();
+                    }
+                    Err(e) => warn!("alloc_tx_buffer({}) failed: {:?}", len, e),
+                }
+            }
+            return ret;
+        }
+        match dev.alloc_tx_buffer(len) {
+            Ok(mut tx_buf) => {
+                let ret = f(tx_buf.packet_mut());
+                trace!("SEND {} bytes: {:02X?}", len, tx_buf.packet());
+                self.0.tcp_stats.on_outgoing_frame(tx_buf.packet());
+                dev.transmit(tx_buf).unwrap();
+                ret
+            }
+            Err(e) => {
+                // e.g. a jumbo frame larger than the buffers of the driver
+                warn!("alloc_tx_buffer({}) failed: {:?}", len, e);
+                f(&mut vec![0; len])
+            }
+        }
+    }
+}
+
+fn current_time() -> Instant {
+    Instant::from_micros_const((wall_time_nanos() / NANOS_PER_MICROS) as i64)
+}
+
+/// Returns whether `addr` is in the loopback network `127.0.0.0/8`.
+fn is_loopback(addr: IpAddress) -> bool {
+    match addr {
+        IpAddress::Ipv4(v4) => v4.is_loopback(),
     }
 }
 
-fn snoop_tcp_packet(buf: &[u8], sockets: &mut SocketSet<'_>) -> Result<(), smoltcp::wire::Error> {
+fn snoop_tcp_packet(
+    buf: &[u8],
+    sockets: &mut SocketSet<'_>,
+    listen_table: &ListenTable,
+    tcp_stats: &TcpStatsTable,
+) -> Result<(), smoltcp::wire::Error> {
     use smoltcp::wire::{EthernetFrame, IpProtocol, Ipv4Packet, TcpPacket};
 
     let ether_frame = EthernetFrame::new_checked(buf)?;
@@ -292,8 +566,9 @@ fn snoop_tcp_packet(buf: &[u8], sockets: &mut SocketSet<'_>) -> Result<(), smolt
         let is_first = tcp_packet.syn() && !tcp_packet.ack();
         if is_first {
             // create a socket for the first incoming TCP packet, as the later accept() returns.
-            LISTEN_TABLE.incoming_tcp_packet(src_addr, dst_addr, sockets);
+            listen_table.incoming_tcp_packet(src_addr, dst_addr, sockets);
         }
+        tcp_stats.on_segment(src_addr, dst_addr, &tcp_packet, false);
     }
     Ok(())
 }
@@ -301,36 +576,190 @@ fn snoop_tcp_packet(buf: &[u8], sockets: &mut SocketSet<'_>) -> Result<(), smolt
 /// Poll the network stack.
 ///
 /// It may receive packets from the NIC and process them, and transmit queued
-/// packets to the NIC.
+/// packets to the NIC. The interfaces of all the network namespaces are
+/// polled, and the tasks waiting for the sockets are woken up if some of them
+/// may have changed.
 pub fn poll_interfaces() {
-    SOCKET_SET.poll_interfaces();
+    if netns::poll_all() {
+        event::notify();
+    }
 }
 
-/// Benchmark raw socket transmit bandwidth.
-pub fn bench_transmit() {
-    ETH0.dev.lock().bench_transmit_bandwidth();
+/// Calls `f` until it completes or fails, polling the interfaces in between.
+///
+/// If `nonblock` is set, `f` is called only once. Otherwise `f` is retried as
+/// long as it returns [`Err(WouldBlock)`](AxError::WouldBlock), and
+/// `WouldBlock` is returned once the optional `timeout` expires.
+///
+/// Between the retries, the task sleeps until the next network event if the
+/// NICs interrupt the CPU, and yields otherwise (see `event`).
+fn block_on_until<F, T>(nonblock: bool, timeout: Option<Duration>, mut f: F) -> AxResult<T>
+where
+    F: FnMut() -> AxResult<T>,
+{
+    if nonblock {
+        return f().inspect(|_| flush());
+    }
+    let deadline = timeout.map(|t| monotonic_time() + t);
+    loop {
+        let seen = event::current();
+        poll_interfaces();
+        match f() {
+            Ok(t) => {
+                flush();
+                return Ok(t);
+            }
+            Err(AxError::WouldBlock) => {
+                // An interrupted wait also fails with `WouldBlock`: the caller
+                // tells it from a timeout with `axtask::interrupt_pending()`.
+                let now = monotonic_time();
+                if deadline.is_some_and(|ddl| now >= ddl) || axtask::interrupt_pending() {
+                    return Err(AxError::WouldBlock);
+                }
+                let remaining = deadline.map_or(Duration::MAX, |ddl| ddl - now);
+                if !event::wait(seen, remaining) {
+                    axtask::yield_now();
+                }
+            }
+            Err(e) => return Err(e),
+        }
+    }
 }
 
-/// Benchmark raw socket receive bandwidth.
-pub fn bench_receive() {
-    ETH0.dev.lock().bench_receive_bandwidth();
+/// Sends what `f` of [`block_on_until`] queued at once, as no task polls the
+/// interfaces in a loop when the tasks wait for the network events.
+fn flush() {
+    if event::enabled() {
+        poll_interfaces();
+    }
 }
 
-pub(crate) fn init(net_dev: AxNetDevice) {
-    let ether_addr = EthernetAddress(net_dev.mac_address().0);
-    let eth0 = InterfaceWrapper::new("eth0", net_dev, ether_addr);
+/// Information about a network interface, returned by [`interfaces`].
+#[derive(Debug, Clone)]
+pub struct InterfaceInfo {
+    /// The name of the interface, e.g. `lo` or `eth0`.
+    pub name: String,
+    /// The MAC address, all zeros for the loopback interface.
+    pub ether_addr: [u8; 6],
+    /// The IP addresses and their prefix lengths.
+    pub addrs: Vec<(IpAddr, u8)>,
+    /// The MTU in bytes, excluding the Ethernet header.
+    pub mtu: usize,
+    /// The transmit rate limit in bytes per second, or `None` if unlimited.
+    pub rate_limit: Option<u64>,
+    /// The traffic statistics.
+    pub stats: InterfaceStats,
+}
 
-    let ip = IP.parse().expect("invalid IP address");
-    let gateway = GATEWAY.parse().expect("invalid gateway IP address");
-    eth0.setup_ip_addr(ip, IP_PREFIX);
-    eth0.setup_gateway(gateway);
+/// Returns the network interfaces of the current namespace, starting with the
+/// loopback interface.
+pub fn interfaces() -> Vec<InterfaceInfo> {
+    let ns = current_net_namespace();
+    let mut ifaces = vec![ns.lo.info()];
+    ifaces.extend(ns.nics().iter().map(|nic| nic.info()));
+    ifaces
+}
 
-    ETH0.init_once(eth0);
-    SOCKET_SET.init_once(SocketSetWrapper::new());
-    LISTEN_TABLE.init_once(ListenTable::new());
+/// Sets the IP address of the interface `name` of the current namespace,
+/// replacing the old ones.
+///
+/// The direct route to the network of the old address is replaced as well.
+/// The loopback interface can't be configured.
+pub fn set_interface_addr(name: &str, addr: IpAddr, prefix_len: u8) -> AxResult {
+    let ns = current_net_namespace();
+    if name == ns.lo.name() {
+        return ax_err!(Unsupported, "cannot configure the loopback interface");
+    }
+    if !addr.is_ipv4() {
+        return ax_err!(Unsupported, "IPv6 not supported");
+    }
+    let ip = from_core_ipaddr(addr);
+    let network = route::network_of(ip, prefix_len)?;
+    let nic = ns.find_nic(name)?;
+    nic.set_ip_addr(ip, prefix_len);
+    route::set_direct_route(&ns, &nic, network, ip);
+    info!("set address of {:?}: {}/{}", name, addr, prefix_len);
+    Ok(())
+}
+
+/// Limits the transmit rate of the interface `name` of the current namespace
+/// to `rate` bytes per second, or removes the limit if `rate` is `None`.
+///
+/// Packets over the limit are delayed rather than dropped.
+pub fn set_interface_rate(name: &str, rate: Option<u64>) -> AxResult {
+    if rate == Some(0) {
+        return ax_err!(InvalidInput, "rate limit must be positive");
+    }
+    let ns = current_net_namespace();
+    if name == ns.lo.name() {
+        ns.lo.qdisc.set_rate(rate);
+    } else {
+        ns.find_nic(name)?.qdisc.set_rate(rate);
+    }
+    info!("set rate limit of {:?}: {:?} bytes/s", name, rate);
+    Ok(())
+}
 
-    info!("created net interface {:?}:", ETH0.name());
-    info!("  ether:    {}", ETH0.ethernet_address());
-    info!("  ip:       {}/{}", ip, IP_PREFIX);
-    info!("  gateway:  {}", gateway);
+/// Sets the MTU of the interface `name` of the current namespace, excluding
+/// the Ethernet header.
+///
+/// MTUs from 68 up to 9000 (jumbo frames) are accepted, but the NIC driver must
+/// have buffers large enough for the frames: the larger ones it can't send are
+/// dropped. The loopback interface can't be configured.
+pub fn set_interface_mtu(name: &str, mtu: usize) -> AxResult {
+    let ns = current_net_namespace();
+    if name == ns.lo.name() {
+        return ax_err!(Unsupported, "cannot configure the loopback interface");
+    }
+    if !(MIN_MTU..=MAX_MTU).contains(&mtu) {
+        return ax_err!(InvalidInput, "MTU out of range");
+    }
+    ns.find_nic(name)?.dev.lock().mtu = mtu;
+    info!("set MTU of {:?}: {}", name, mtu);
+    Ok(())
+}
+
+fn first_nic() -> AxResult<Arc<InterfaceWrapper<DeviceWrapper>>> {
+    let nics = netns::initial().nics();
+    nics.first()
+        .cloned()
+        .ok_or_else(|| ax_err_type!(NotFound, "no NIC device"))
+}
+
+/// Benchmark raw socket transmit bandwidth of the first NIC.
+pub fn bench_transmit() -> AxResult {
+    first_nic()?.dev.lock().bench_transmit_bandwidth();
+    Ok(())
+}
+
+/// Benchmark raw socket receive bandwidth of the first NIC.
+pub fn bench_receive() -> AxResult {
+    first_nic()?.dev.lock().bench_receive_bandwidth();
+    Ok(())
+}
+
+/// Starts the network processing of a secondary CPU.
+pub(crate) fn init_secondary() {
+    event::init_secondary();
+}
+
+pub(crate) fn init(net_devs: Vec<AxNetDevice>) {
+    let num_nics = net_devs.len();
+    netns::init(net_devs);
+    event::init(num_nics);
+    let has_nic = num_nics > 0;
+
+    // The first NIC is configured at build time.
+    if has_nic {
+        let ip: IpAddr = IP.parse().expect("invalid IP address");
+        let gateway: IpAddr = GATEWAY.parse().expect("invalid gateway IP address");
+        set_interface_addr("eth0", ip, IP_PREFIX).unwrap();
+        add_route(&Route::new(
+            IpAddr::from([0, 0, 0, 0]),
+            0,
+            Some(gateway),
+            "eth0",
+        ))
+        .unwrap();
+    }
 }
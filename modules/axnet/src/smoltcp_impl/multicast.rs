@@ -0,0 +1,126 @@
+//! IPv4 multicast group membership.
+//!
+//! Sockets join groups on an interface. The interface takes part in IGMPv2 for
+//! the groups joined by at least one socket: it reports a group when the first
+//! socket joins, answers the queries of the routers, and sends a leave message
+//! when the last socket leaves. Datagrams to a joined group are delivered to
+//! the UDP sockets bound to their port.
+//!
+//! The NIC drivers have no multicast filters to program, so the NICs receive
+//! all the multicast frames they let through, and the frames of the groups not
+//! joined are dropped by the stack.
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::net::Ipv4Addr;
+
+use axerrno::{AxResult, ax_err, ax_err_type};
+use smoltcp::wire::{IpAddress, Ipv4Address};
+use spin::Mutex;
+
+use super::netns::{NetNamespace, Nic};
+use super::route;
+
+/// An interface of the namespace: a NIC, or `None` for the loopback
+/// interface.
+type IfaceId = Option<Nic>;
+
+fn same_iface(a: &IfaceId, b: &IfaceId) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+/// Finds the interface with the address `interface`, or the one through which
+/// packets to `group` are sent if it's unspecified.
+fn find_iface(ns: &NetNamespace, group: Ipv4Address, interface: Ipv4Address) -> AxResult<IfaceId> {
+    if interface.is_unspecified() {
+        return match route::lookup(ns, IpAddress::Ipv4(group), None) {
+            Some(nic) => Ok(Some(nic)),
+            None => ax_err!(NotFound, "no route to the multicast group"),
+        };
+    }
+    let addr = IpAddress::Ipv4(interface);
+    if ns.lo.has_ip_addr(addr) {
+        return Ok(None);
+    }
+    ns.nics()
+        .into_iter()
+        .find(|nic| nic.has_ip_addr(addr))
+        .map(Some)
+        .ok_or_else(|| ax_err_type!(NotFound, "no interface with the address"))
+}
+
+fn update_group(ns: &NetNamespace, iface: &IfaceId, group: Ipv4Address, join: bool) -> AxResult {
+    match iface {
+        None => ns.lo.update_multicast_group(group, join),
+        Some(nic) => nic.update_multicast_group(group, join),
+    }
+}
+
+/// The multicast groups joined by a socket, which are left when it's dropped.
+pub(super) struct Memberships {
+    /// The namespace of the socket.
+    ns: Arc<NetNamespace>,
+    groups: Mutex<Vec<(Ipv4Address, IfaceId)>>,
+}
+
+impl Memberships {
+    pub fn new(ns: Arc<NetNamespace>) -> Self {
+        Self {
+            ns,
+            groups: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Joins `group` on the interface with the address `interface`, or on the
+    /// one chosen by the routing table if it's unspecified.
+    pub fn join(&self, group: Ipv4Addr, interface: Ipv4Addr) -> AxResult {
+        if !group.is_multicast() {
+            return ax_err!(InvalidInput, "not a multicast address");
+        }
+        let group = Ipv4Address(group.octets());
+        let iface = find_iface(&self.ns, group, Ipv4Address(interface.octets()))?;
+        let mut groups = self.groups.lock();
+        if groups
+            .iter()
+            .any(|(g, i)| *g == group && same_iface(i, &iface))
+        {
+            return ax_err!(AddrInUse, "multicast group already joined");
+        }
+        update_group(&self.ns, &iface, group, true)?;
+        groups.push((group, iface));
+        Ok(())
+    }
+
+    /// Leaves `group` on the interface with the address `interface`, or on
+    /// any interface it was joined on if it's unspecified.
+    pub fn leave(&self, group: Ipv4Addr, interface: Ipv4Addr) -> AxResult {
+        let group = Ipv4Address(group.octets());
+        let interface = Ipv4Address(interface.octets());
+        let iface = if interface.is_unspecified() {
+            None
+        } else {
+            Some(find_iface(&self.ns, group, interface)?)
+        };
+        let mut groups = self.groups.lock();
+        let index = groups
+            .iter()
+            .position(|(g, i)| {
+                *g == group && iface.as_ref().is_none_or(|iface| same_iface(iface, i))
+            })
+            .ok_or_else(|| ax_err_type!(NotFound, "multicast group not joined"))?;
+        let (group, iface) = groups.swap_remove(index);
+        update_group(&self.ns, &iface, group, false)
+    }
+}
+
+impl Drop for Memberships {
+    fn drop(&mut self) {
+        for (group, iface) in self.groups.get_mut().drain(..) {
+            update_group(&self.ns, &iface, group, false).ok();
+        }
+    }
+}
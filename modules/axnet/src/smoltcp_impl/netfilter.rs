@@ -0,0 +1,202 @@
+//! A minimal packet filter.
+//!
+//! Each hook has a chain of rules, which is checked against every IPv4 packet
+//! received from (ingress) or sent to (egress) an interface, including the
+//! loopback one. The first matching rule that accepts or drops the packet
+//! decides its fate, and the packets matching no such rule are accepted. Other
+//! packets, e.g. ARP, always pass.
+
+use alloc::vec::Vec;
+use core::net::{IpAddr, Ipv4Addr};
+
+use axerrno::{AxResult, ax_err};
+use smoltcp::wire::{
+    EthernetFrame, EthernetProtocol, IpProtocol, Ipv4Packet, TcpPacket, UdpPacket,
+};
+use spin::RwLock;
+
+/// Where a packet is filtered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterHook {
+    /// Packets received from an interface.
+    Ingress,
+    /// Packets sent to an interface.
+    Egress,
+}
+
+/// What to do with a packet matching a rule.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterAction {
+    /// Let the packet pass, skipping the rest of the chain.
+    Accept,
+    /// Discard the packet silently.
+    Drop,
+    /// Log the packet, and go on with the next rule.
+    Log,
+}
+
+/// A rule of a filter chain. Unset fields match any packet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilterRule {
+    /// The IP protocol number, e.g. 6 for TCP.
+    pub protocol: Option<u8>,
+    /// The source network and its prefix length.
+    pub src: Option<(IpAddr, u8)>,
+    /// The destination network and its prefix length.
+    pub dst: Option<(IpAddr, u8)>,
+    /// The source port, only matching TCP and UDP packets.
+    pub src_port: Option<u16>,
+    /// The destination port, only matching TCP and UDP packets.
+    pub dst_port: Option<u16>,
+    /// The action on matching packets.
+    pub action: FilterAction,
+}
+
+struct Entry {
+    rule: FilterRule,
+    /// The number of matching packets.
+    packets: u64,
+}
+
+/// The ingress and egress chains.
+static CHAINS: [RwLock<Vec<Entry>>; 2] = [RwLock::new(Vec::new()), RwLock::new(Vec::new())];
+
+fn chain(hook: FilterHook) -> &'static RwLock<Vec<Entry>> {
+    &CHAINS[hook as usize]
+}
+
+/// The fields of a packet that rules match on.
+struct PacketInfo {
+    protocol: u8,
+    src: Ipv4Addr,
+    dst: Ipv4Addr,
+    ports: Option<(u16, u16)>,
+}
+
+impl PacketInfo {
+    fn parse(frame: &[u8]) -> Option<Self> {
+        let frame = EthernetFrame::new_checked(frame).ok()?;
+        if frame.ethertype() != EthernetProtocol::Ipv4 {
+            return None;
+        }
+        let packet = Ipv4Packet::new_checked(frame.payload()).ok()?;
+        let ports = match packet.next_header() {
+            IpProtocol::Tcp => TcpPacket::new_checked(packet.payload())
+                .ok()
+                .map(|tcp| (tcp.src_port(), tcp.dst_port())),
+            IpProtocol::Udp => UdpPacket::new_checked(packet.payload())
+                .ok()
+                .map(|udp| (udp.src_port(), udp.dst_port())),
+            _ => None,
+        };
+        Some(Self {
+            protocol: packet.next_header().into(),
+            src: Ipv4Addr::from(packet.src_addr().0),
+            dst: Ipv4Addr::from(packet.dst_addr().0),
+            ports,
+        })
+    }
+}
+
+fn in_network(addr: Ipv4Addr, network: Option<(IpAddr, u8)>) -> bool {
+    match network {
+        Some((IpAddr::V4(net), prefix_len)) => {
+            let mask = u32::MAX.checked_shl(32 - prefix_len as u32).unwrap_or(0);
+            u32::from(addr) & mask == u32::from(net) & mask
+        }
+        Some((IpAddr::V6(_), _)) => false,
+        None => true,
+    }
+}
+
+impl FilterRule {
+    fn matches(&self, info: &PacketInfo) -> bool {
+        let port_matches = |port: Option<u16>, pick: fn((u16, u16)) -> u16| match port {
+            Some(port) => info.ports.is_some_and(|ports| pick(ports) == port),
+            None => true,
+        };
+        self.protocol.is_none_or(|p| p == info.protocol)
+            && in_network(info.src, self.src)
+            && in_network(info.dst, self.dst)
+            && port_matches(self.src_port, |(src, _)| src)
+            && port_matches(self.dst_port, |(_, dst)| dst)
+    }
+}
+
+/// Returns whether there're rules on `hook`, so that the packets need to be
+/// checked.
+pub(super) fn is_active(hook: FilterHook) -> bool {
+    !chain(hook).read().is_empty()
+}
+
+/// Runs the ethernet frame `frame` through the rules on `hook`, and returns
+/// whether it's accepted.
+pub(super) fn check(hook: FilterHook, frame: &[u8]) -> bool {
+    if !is_active(hook) {
+        return true;
+    }
+    let Some(info) = PacketInfo::parse(frame) else {
+        return true;
+    };
+    let mut chain = chain(hook).write();
+    for entry in chain.iter_mut().filter(|e| e.rule.matches(&info)) {
+        entry.packets += 1;
+        match entry.rule.action {
+            FilterAction::Accept => return true,
+            FilterAction::Drop => return false,
+            FilterAction::Log => {
+                let ports = info.ports.unwrap_or_default();
+                info!(
+                    "netfilter {:?}: proto {} {}:{} -> {}:{}",
+                    hook, info.protocol, info.src, ports.0, info.dst, ports.1
+                );
+            }
+        }
+    }
+    true
+}
+
+fn check_network(network: Option<(IpAddr, u8)>) -> AxResult {
+    match network {
+        Some((IpAddr::V6(_), _)) => ax_err!(Unsupported, "IPv6 not supported"),
+        Some((_, prefix_len)) if prefix_len > 32 => {
+            ax_err!(InvalidInput, "invalid prefix length")
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Appends `rule` to the chain of `hook`.
+pub fn add_filter_rule(hook: FilterHook, rule: FilterRule) -> AxResult {
+    check_network(rule.src)?;
+    check_network(rule.dst)?;
+    info!("add filter rule on {:?}: {:?}", hook, rule);
+    chain(hook).write().push(Entry { rule, packets: 0 });
+    Ok(())
+}
+
+/// Removes the rule at `index` from the chain of `hook`.
+pub fn del_filter_rule(hook: FilterHook, index: usize) -> AxResult {
+    let mut chain = chain(hook).write();
+    if index >= chain.len() {
+        return ax_err!(NotFound, "no such rule");
+    }
+    let entry = chain.remove(index);
+    info!("delete filter rule on {:?}: {:?}", hook, entry.rule);
+    Ok(())
+}
+
+/// Removes all the rules from the chain of `hook`.
+pub fn flush_filter_rules(hook: FilterHook) {
+    chain(hook).write().clear();
+}
+
+/// Returns the rules on `hook` in order, with the number of packets that
+/// matched each of them.
+pub fn filter_rules(hook: FilterHook) -> Vec<(FilterRule, u64)> {
+    chain(hook)
+        .read()
+        .iter()
+        .map(|e| (e.rule.clone(), e.packets))
+        .collect()
+}
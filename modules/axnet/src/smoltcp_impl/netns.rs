@@ -0,0 +1,307 @@
+//! Network namespaces (`CLONE_NEWNET`).
+//!
+//! A network namespace has its own interfaces, routing table and sockets: a
+//! socket only sends and receives packets through the interfaces of the
+//! namespace it was created in, and its ports don't clash with the ones of
+//! other namespaces. Each namespace has a loopback interface of its own.
+//!
+//! The NICs are in the initial namespace. Other namespaces start with only a
+//! loopback interface, and are connected to each other with veth pairs (see
+//! [`add_veth_pair`](super::add_veth_pair)).
+//!
+//! The current namespace is a resource of [`axns`], so each process (or the
+//! whole system, without thread-local namespaces) can have its own. A process
+//! starts in the initial namespace, and moves to another one with
+//! [`unshare_net_namespace`] or [`set_net_namespace`]. The sockets stay in
+//! the namespace they were created in.
+//!
+//! All the namespaces are polled by [`poll_interfaces`](super::poll_interfaces),
+//! so the packets crossing a veth pair are processed whichever task polls.
+//! The packet filter is shared by all the namespaces.
+
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use axdriver::prelude::*;
+use axerrno::{AxResult, ax_err, ax_err_type};
+use lazyinit::LazyInit;
+use smoltcp::iface::Interface;
+use smoltcp::wire::{EthernetAddress, IpAddress};
+use spin::{Mutex, RwLock};
+
+use super::listen_table::ListenTable;
+use super::loopback::LoopbackDevice;
+use super::route::{self, RouteTable};
+use super::tcp_info::TcpStatsTable;
+use super::{
+    DeviceWrapper, InterfaceWrapper, LOOPBACK_IP, LOOPBACK_PREFIX, SocketSetWrapper, is_loopback,
+    mdns,
+};
+
+/// A NIC of a namespace.
+pub(super) type Nic = Arc<InterfaceWrapper<DeviceWrapper>>;
+
+/// A network namespace, see the [module docs](self).
+pub struct NetNamespace {
+    id: usize,
+    pub(super) sockets: SocketSetWrapper<'static>,
+    pub(super) listen_table: Arc<ListenTable>,
+    pub(super) tcp_stats: Arc<TcpStatsTable>,
+    pub(super) lo: InterfaceWrapper<LoopbackDevice>,
+    /// The NICs, in the order they were added.
+    nics: RwLock<Vec<Nic>>,
+    pub(super) routes: RouteTable,
+}
+
+static INITIAL: LazyInit<Arc<NetNamespace>> = LazyInit::new();
+
+/// All the namespaces, polled in turn.
+static NAMESPACES: Mutex<Vec<Weak<NetNamespace>>> = Mutex::new(Vec::new());
+
+axns::def_resource! {
+    /// The namespace of the current process, or `None` for the initial one.
+    ///
+    /// `None` is what a new thread-local namespace copies, as it can't hold a
+    /// reference without counting it.
+    static CURRENT: RwLock<Option<Arc<NetNamespace>>> = RwLock::new(None);
+}
+
+impl NetNamespace {
+    fn create(devs: Vec<AxNetDevice>) -> Arc<Self> {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+        let listen_table = Arc::new(ListenTable::new());
+        let tcp_stats = Arc::new(TcpStatsTable::new());
+        let lo = InterfaceWrapper::new(
+            "lo".into(),
+            LoopbackDevice::new(listen_table.clone(), tcp_stats.clone()),
+            EthernetAddress([0; 6]),
+        );
+        lo.set_ip_addr(IpAddress::Ipv4(LOOPBACK_IP), LOOPBACK_PREFIX);
+        let nics = devs
+            .into_iter()
+            .enumerate()
+            .map(|(i, dev)| {
+                let ether_addr = EthernetAddress(dev.mac_address().0);
+                let dev = DeviceWrapper::new_driver(dev, listen_table.clone(), tcp_stats.clone());
+                Arc::new(InterfaceWrapper::new(
+                    alloc::format!("eth{}", i),
+                    dev,
+                    ether_addr,
+                ))
+            })
+            .collect();
+        let ns = Arc::new(Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            sockets: SocketSetWrapper::new(),
+            listen_table,
+            tcp_stats,
+            lo,
+            nics: RwLock::new(nics),
+            routes: RouteTable::new(),
+        });
+        route::update_poll_order(&ns);
+
+        let mut namespaces = NAMESPACES.lock();
+        namespaces.retain(|ns| ns.strong_count() > 0);
+        namespaces.push(Arc::downgrade(&ns));
+        ns
+    }
+
+    /// Creates a namespace with only a loopback interface.
+    pub fn new() -> Arc<Self> {
+        let ns = Self::create(Vec::new());
+        debug!("network namespace {} created", ns.id);
+        ns
+    }
+
+    /// Returns the ID of the namespace, 0 for the initial one.
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// Returns all the NICs, in the order they were added.
+    pub(super) fn nics(&self) -> Vec<Nic> {
+        self.nics.read().clone()
+    }
+
+    /// Returns the NIC `name`.
+    pub(super) fn find_nic(&self, name: &str) -> AxResult<Nic> {
+        self.nics
+            .read()
+            .iter()
+            .find(|nic| nic.name() == name)
+            .cloned()
+            .ok_or_else(|| ax_err_type!(NotFound, "no such interface"))
+    }
+
+    /// Whether `nic` is still in the namespace.
+    pub(super) fn has_nic(&self, nic: &Nic) -> bool {
+        self.nics.read().iter().any(|n| Arc::ptr_eq(n, nic))
+    }
+
+    /// Checks that no interface of the namespace is named `name`.
+    pub(super) fn check_nic_name(&self, name: &str) -> AxResult {
+        check_name(&self.lo, &self.nics.read(), name)
+    }
+
+    /// Adds a NIC, which must have a name not used in the namespace, and
+    /// returns it.
+    pub(super) fn add_nic(&self, nic: InterfaceWrapper<DeviceWrapper>) -> AxResult<Nic> {
+        let mut nics = self.nics.write();
+        check_name(&self.lo, &nics, nic.name())?;
+        info!(
+            "created net interface {:?} in namespace {}:",
+            nic.name(),
+            self.id
+        );
+        info!("  ether:    {}", nic.ethernet_address());
+        let nic = Arc::new(nic);
+        nics.push(nic.clone());
+        drop(nics);
+        route::update_poll_order(self);
+        Ok(nic)
+    }
+
+    /// Removes `nic` and its routes, if it's still in the namespace.
+    ///
+    /// The sockets which joined multicast groups on it keep it until they
+    /// leave them, but no packet is sent through it anymore.
+    pub(super) fn remove_nic(&self, nic: &Nic) {
+        route::remove_nic(self, nic, || {
+            let mut nics = self.nics.write();
+            let len = nics.len();
+            nics.retain(|n| !Arc::ptr_eq(n, nic));
+            if nics.len() != len {
+                info!(
+                    "removed net interface {:?} from namespace {}",
+                    nic.name(),
+                    self.id
+                );
+            }
+        });
+    }
+
+    /// Runs `f` on the interface through which packets from `src` (if known)
+    /// to `dst` are sent.
+    ///
+    /// Falls back to the loopback interface if there's no route, which fails
+    /// to send the packets.
+    pub(super) fn with_route_iface<R>(
+        &self,
+        dst: IpAddress,
+        src: Option<IpAddress>,
+        f: impl FnOnce(&mut Interface) -> R,
+    ) -> R {
+        if !is_loopback(dst) {
+            if let Some(nic) = route::lookup(self, dst, src) {
+                return f(&mut nic.iface.lock());
+            }
+        }
+        f(&mut self.lo.iface.lock())
+    }
+
+    /// Returns the source address of the packets to `dst`.
+    ///
+    /// Falls back to the address of the loopback interface if there's no
+    /// route.
+    pub(super) fn source_addr(&self, dst: IpAddress) -> Option<IpAddress> {
+        route::select_source(self, dst)
+            .or_else(|| self.lo.iface.lock().ipv4_addr().map(IpAddress::Ipv4))
+    }
+
+    /// Returns the MTU of the interface through which packets to `addr` are
+    /// sent.
+    pub(super) fn path_mtu(&self, addr: IpAddress) -> usize {
+        match route::lookup(self, addr, None) {
+            Some(nic) => nic.mtu(),
+            None => self.lo.mtu(),
+        }
+    }
+
+    /// Polls the interfaces, and returns whether the state of the sockets may
+    /// have changed.
+    fn poll(&self) -> bool {
+        let mut sockets = self.sockets.0.lock();
+        // The loopback interface goes first: it drains the sockets talking to
+        // local addresses, which the NIC would otherwise send to the gateway.
+        // Sockets talking to other hosts have no route there, and are only
+        // left for the NICs.
+        let mut changed = self.lo.poll(&mut sockets);
+        let nics = route::poll_order(self);
+        for nic in &nics {
+            changed |= nic.poll(&mut sockets);
+        }
+        // The mDNS responses are sent right away, rather than on the next poll.
+        if self.id == 0 && mdns::poll(&mut sockets) {
+            for nic in &nics {
+                changed |= nic.poll(&mut sockets);
+            }
+        }
+        changed
+    }
+}
+
+fn check_name(lo: &InterfaceWrapper<LoopbackDevice>, nics: &[Nic], name: &str) -> AxResult {
+    if name.is_empty() {
+        return ax_err!(InvalidInput, "empty interface name");
+    }
+    if name == lo.name() || nics.iter().any(|nic| nic.name() == name) {
+        return ax_err!(AlreadyExists, "interface name already used");
+    }
+    Ok(())
+}
+
+/// Returns the initial namespace, which has the NICs.
+pub(super) fn initial() -> &'static Arc<NetNamespace> {
+    &INITIAL
+}
+
+/// Returns the namespace of the current process.
+pub fn current_net_namespace() -> Arc<NetNamespace> {
+    CURRENT.read().clone().unwrap_or_else(|| INITIAL.clone())
+}
+
+/// Moves the current process to the namespace `ns` (`setns`).
+///
+/// The sockets created before stay in their namespace.
+pub fn set_net_namespace(ns: Arc<NetNamespace>) {
+    debug!("enter network namespace {}", ns.id);
+    *CURRENT.write() = Some(ns);
+}
+
+/// Moves the current process to a new namespace with only a loopback
+/// interface (`unshare(CLONE_NEWNET)`), and returns it.
+pub fn unshare_net_namespace() -> Arc<NetNamespace> {
+    let ns = NetNamespace::new();
+    set_net_namespace(ns.clone());
+    ns
+}
+
+/// Polls the interfaces of all the namespaces, and returns whether the state
+/// of some sockets may have changed.
+pub(super) fn poll_all() -> bool {
+    let namespaces: Vec<_> = NAMESPACES
+        .lock()
+        .iter()
+        .filter_map(|ns| ns.upgrade())
+        .collect();
+    let mut changed = false;
+    for ns in namespaces {
+        changed |= ns.poll();
+    }
+    changed
+}
+
+/// Creates the initial namespace with the NICs of `devs`.
+pub(super) fn init(devs: Vec<AxNetDevice>) {
+    let ns = NetNamespace::create(devs);
+    info!("created net interface {:?}:", ns.lo.name());
+    info!("  ip:       {}/{}", LOOPBACK_IP, LOOPBACK_PREFIX);
+    for nic in ns.nics.read().iter() {
+        info!("created net interface {:?}:", nic.name());
+        info!("  ether:    {}", nic.ethernet_address());
+    }
+    INITIAL.init_once(ns);
+}
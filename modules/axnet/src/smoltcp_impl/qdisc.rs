@@ -0,0 +1,245 @@
+//! Traffic shaping and statistics of the interfaces.
+//!
+//! Each interface has a queueing discipline: a token bucket that bounds its
+//! transmit rate, and the counters of the packets it has sent and received.
+//! When the bucket runs out, the interface stops transmitting until it's
+//! refilled, and the packets wait in the socket buffers, so the shaper never
+//! drops them. As the size of a packet isn't known before it's built, the
+//! bucket may go into debt by one packet.
+//!
+//! Sockets are paced the same way (`SO_MAX_PACING_RATE`), by charging the data
+//! they queue to a bucket of their own.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use axhal::time::{NANOS_PER_SEC, monotonic_time_nanos};
+use smoltcp::iface::SocketSet;
+use smoltcp::phy::{Device, DeviceCapabilities, RxToken, TxToken};
+use smoltcp::time::Instant;
+use spin::Mutex;
+
+/// The minimum burst size of a bucket, enough for two full-sized frames.
+const MIN_BURST: u64 = 2 * 1514;
+
+/// A token bucket, with tokens in bytes.
+pub(crate) struct TokenBucket {
+    /// The rate in bytes per second, or 0 if unlimited.
+    rate: u64,
+    /// The maximum number of tokens.
+    burst: u64,
+    /// Negative if the bucket is in debt.
+    tokens: i64,
+    /// The time when the tokens were last refilled, in nanoseconds.
+    last_refill: u64,
+}
+
+impl TokenBucket {
+    /// Creates an unlimited bucket.
+    pub const fn new() -> Self {
+        Self {
+            rate: 0,
+            burst: 0,
+            tokens: 0,
+            last_refill: 0,
+        }
+    }
+
+    /// Returns the rate in bytes per second, or `None` if unlimited.
+    pub fn rate(&self) -> Option<u64> {
+        (self.rate != 0).then_some(self.rate)
+    }
+
+    /// Sets the rate in bytes per second, or removes the limit if `None`.
+    ///
+    /// The bucket holds the tokens of 10ms, and starts full. Nothing changes if
+    /// the rate is the same.
+    pub fn set_rate(&mut self, rate: Option<u64>) {
+        if rate == self.rate() {
+            return;
+        }
+        self.rate = rate.unwrap_or(0);
+        self.burst = (self.rate / 100).max(MIN_BURST);
+        self.tokens = self.burst as i64;
+        self.last_refill = monotonic_time_nanos();
+    }
+
+    fn refill(&mut self) {
+        let now = monotonic_time_nanos();
+        let elapsed = now.saturating_sub(self.last_refill);
+        let added = (elapsed as u128 * self.rate as u128 / NANOS_PER_SEC as u128)
+            .min(self.burst as u128) as i64;
+        if self.tokens + added >= self.burst as i64 {
+            self.tokens = self.burst as i64;
+            self.last_refill = now;
+        } else if added > 0 {
+            self.tokens += added;
+            // keep the fraction of a token that was not added
+            self.last_refill += (added as u128 * NANOS_PER_SEC as u128 / self.rate as u128) as u64;
+        }
+    }
+
+    /// Returns the number of bytes that can be sent now.
+    pub fn available(&mut self) -> u64 {
+        if self.rate == 0 {
+            return u64::MAX;
+        }
+        self.refill();
+        self.tokens.max(0) as u64
+    }
+
+    /// Takes `len` bytes of tokens, going into debt if there are not enough.
+    pub fn consume(&mut self, len: usize) {
+        if self.rate != 0 {
+            self.tokens = self.tokens.saturating_sub(len as i64);
+        }
+    }
+}
+
+/// Statistics of a network interface, as in `/proc/net/dev`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct InterfaceStats {
+    /// The number of packets received.
+    pub rx_packets: u64,
+    /// The number of bytes received.
+    pub rx_bytes: u64,
+    /// The number of packets transmitted.
+    pub tx_packets: u64,
+    /// The number of bytes transmitted.
+    pub tx_bytes: u64,
+    /// The number of times transmission was deferred by the rate limit.
+    pub tx_throttled: u64,
+}
+
+/// The queueing discipline of an interface.
+pub(super) struct Qdisc {
+    bucket: Mutex<TokenBucket>,
+    rx_packets: AtomicU64,
+    rx_bytes: AtomicU64,
+    tx_packets: AtomicU64,
+    tx_bytes: AtomicU64,
+    tx_throttled: AtomicU64,
+}
+
+impl Qdisc {
+    pub const fn new() -> Self {
+        Self {
+            bucket: Mutex::new(TokenBucket::new()),
+            rx_packets: AtomicU64::new(0),
+            rx_bytes: AtomicU64::new(0),
+            tx_packets: AtomicU64::new(0),
+            tx_bytes: AtomicU64::new(0),
+            tx_throttled: AtomicU64::new(0),
+        }
+    }
+
+    pub fn rate(&self) -> Option<u64> {
+        self.bucket.lock().rate()
+    }
+
+    pub fn set_rate(&self, rate: Option<u64>) {
+        self.bucket.lock().set_rate(rate);
+    }
+
+    pub fn stats(&self) -> InterfaceStats {
+        InterfaceStats {
+            rx_packets: self.rx_packets.load(Ordering::Relaxed),
+            rx_bytes: self.rx_bytes.load(Ordering::Relaxed),
+            tx_packets: self.tx_packets.load(Ordering::Relaxed),
+            tx_bytes: self.tx_bytes.load(Ordering::Relaxed),
+            tx_throttled: self.tx_throttled.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Wraps `dev`, so that its traffic goes through the queueing discipline.
+    pub fn wrap<'a, D: Device>(&'a self, dev: &'a mut D) -> ShapedDevice<'a, D> {
+        ShapedDevice { dev, qdisc: self }
+    }
+
+    fn received(&self, len: usize) {
+        self.rx_packets.fetch_add(1, Ordering::Relaxed);
+        self.rx_bytes.fetch_add(len as u64, Ordering::Relaxed);
+    }
+
+    fn transmitted(&self, len: usize) {
+        self.bucket.lock().consume(len);
+        self.tx_packets.fetch_add(1, Ordering::Relaxed);
+        self.tx_bytes.fetch_add(len as u64, Ordering::Relaxed);
+    }
+}
+
+pub(super) struct ShapedDevice<'a, D> {
+    dev: &'a mut D,
+    qdisc: &'a Qdisc,
+}
+
+pub(super) struct ShapedRxToken<'a, T> {
+    token: T,
+    qdisc: &'a Qdisc,
+}
+
+pub(super) struct ShapedTxToken<'a, T> {
+    token: T,
+    qdisc: &'a Qdisc,
+}
+
+impl<D: Device> Device for ShapedDevice<'_, D> {
+    type RxToken<'a>
+        = ShapedRxToken<'a, D::RxToken<'a>>
+    where
+        Self: 'a;
+    type TxToken<'a>
+        = ShapedTxToken<'a, D::TxToken<'a>>
+    where
+        Self: 'a;
+
+    fn receive(&mut self, timestamp: Instant) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
+        // Receiving is never limited, the replies are charged when they're sent.
+        let qdisc = self.qdisc;
+        let (rx, tx) = self.dev.receive(timestamp)?;
+        Some((
+            ShapedRxToken { token: rx, qdisc },
+            ShapedTxToken { token: tx, qdisc },
+        ))
+    }
+
+    fn transmit(&mut self, timestamp: Instant) -> Option<Self::TxToken<'_>> {
+        let qdisc = self.qdisc;
+        if qdisc.bucket.lock().available() == 0 {
+            qdisc.tx_throttled.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+        let token = self.dev.transmit(timestamp)?;
+        Some(ShapedTxToken { token, qdisc })
+    }
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        self.dev.capabilities()
+    }
+}
+
+impl<T: RxToken> RxToken for ShapedRxToken<'_, T> {
+    fn preprocess(&self, sockets: &mut SocketSet<'_>) {
+        self.token.preprocess(sockets);
+    }
+
+    fn consume<R, F>(self, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        let qdisc = self.qdisc;
+        self.token.consume(|buf| {
+            qdisc.received(buf.len());
+            f(buf)
+        })
+    }
+}
+
+impl<T: TxToken> TxToken for ShapedTxToken<'_, T> {
+    fn consume<R, F>(self, len: usize, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        self.qdisc.transmitted(len);
+        self.token.consume(len, f)
+    }
+}
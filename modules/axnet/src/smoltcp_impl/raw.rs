@@ -0,0 +1,305 @@
+use alloc::sync::Arc;
+use alloc::{vec, vec::Vec};
+use core::net::IpAddr;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::time::Duration;
+
+use axerrno::{AxError, AxResult, ax_err, ax_err_type};
+use axio::PollState;
+use spin::RwLock;
+
+use smoltcp::iface::SocketHandle;
+use smoltcp::phy::ChecksumCapabilities;
+use smoltcp::socket::raw::{self, SendError};
+use smoltcp::wire::{IpAddress, IpProtocol, Ipv4Packet, Ipv4Repr};
+
+use super::addr::{from_core_ipaddr, into_core_ipaddr};
+use super::bpf::{BpfProgram, SocketFilter};
+use super::netns::{NetNamespace, current_net_namespace};
+use super::{SocketSetWrapper, block_on_until};
+
+const IPV4_HEADER_LEN: usize = 20;
+const DEFAULT_HOP_LIMIT: u8 = 64;
+
+/// A raw IPv4 socket that provides POSIX-like APIs.
+///
+/// It receives all IPv4 packets of the given protocol, including their IP
+/// headers. Outgoing packets get an IP header built by the stack, unless
+/// [`set_header_included`](Self::set_header_included) is set (`IP_HDRINCL`),
+/// in which case the caller provides the complete packet.
+pub struct RawSocket {
+    ns: Arc<NetNamespace>,
+    handle: SocketHandle,
+    protocol: IpProtocol,
+    header_included: AtomicBool,
+    peer_addr: RwLock<Option<IpAddress>>,
+    nonblock: AtomicBool,
+    recv_timeout: RwLock<Option<Duration>>,
+    send_timeout: RwLock<Option<Duration>>,
+    filter: SocketFilter,
+}
+
+impl RawSocket {
+    /// Creates a new raw socket for the given IP protocol number, in the
+    /// current network namespace.
+    ///
+    /// `IPPROTO_RAW` (255) sockets always have `IP_HDRINCL` set, as on Linux.
+    pub fn new(protocol: u8) -> Self {
+        let protocol = IpProtocol::from(protocol);
+        let socket = SocketSetWrapper::new_raw_socket(protocol);
+        let ns = current_net_namespace();
+        let handle = ns.sockets.add(socket);
+        Self {
+            ns,
+            handle,
+            protocol,
+            header_included: AtomicBool::new(protocol == IpProtocol::Unknown(255)),
+            peer_addr: RwLock::new(None),
+            nonblock: AtomicBool::new(false),
+            recv_timeout: RwLock::new(None),
+            send_timeout: RwLock::new(None),
+            filter: SocketFilter::new(),
+        }
+    }
+
+    /// Returns the IP protocol number of this socket.
+    #[inline]
+    pub fn protocol(&self) -> u8 {
+        self.protocol.into()
+    }
+
+    /// Returns whether the caller provides the IP header of outgoing packets
+    /// (`IP_HDRINCL`).
+    #[inline]
+    pub fn header_included(&self) -> bool {
+        self.header_included.load(Ordering::Acquire)
+    }
+
+    /// Sets whether the caller provides the IP header of outgoing packets
+    /// (`IP_HDRINCL`).
+    #[inline]
+    pub fn set_header_included(&self, included: bool) {
+        self.header_included.store(included, Ordering::Release);
+    }
+
+    /// Returns the remote address, or
+    /// [`Err(NotConnected)`](AxError::NotConnected) if not connected.
+    pub fn peer_addr(&self) -> AxResult<IpAddr> {
+        self.remote_addr().map(into_core_ipaddr)
+    }
+
+    /// Returns whether this socket is in nonblocking mode.
+    #[inline]
+    pub fn is_nonblocking(&self) -> bool {
+        self.nonblock.load(Ordering::Acquire)
+    }
+
+    /// Moves this raw socket into or out of nonblocking mode.
+    #[inline]
+    pub fn set_nonblocking(&self, nonblocking: bool) {
+        self.nonblock.store(nonblocking, Ordering::Release);
+    }
+
+    /// Returns the timeout of blocking receive operations (`SO_RCVTIMEO`).
+    #[inline]
+    pub fn recv_timeout(&self) -> Option<Duration> {
+        *self.recv_timeout.read()
+    }
+
+    /// Sets the timeout of blocking receive operations (`SO_RCVTIMEO`).
+    #[inline]
+    pub fn set_recv_timeout(&self, timeout: Option<Duration>) {
+        *self.recv_timeout.write() = timeout;
+    }
+
+    /// Returns the timeout of blocking send operations (`SO_SNDTIMEO`).
+    #[inline]
+    pub fn send_timeout(&self) -> Option<Duration> {
+        *self.send_timeout.read()
+    }
+
+    /// Sets the timeout of blocking send operations (`SO_SNDTIMEO`).
+    #[inline]
+    pub fn set_send_timeout(&self, timeout: Option<Duration>) {
+        *self.send_timeout.write() = timeout;
+    }
+
+    /// Attaches a BPF filter (`SO_ATTACH_FILTER`) to the socket, replacing the
+    /// one attached before.
+    ///
+    /// The filter drops or truncates the packets received. It sees them with their IPv4 header.
+    pub fn attach_filter(&self, program: BpfProgram) {
+        self.filter.attach(program);
+    }
+
+    /// Detaches the BPF filter of the socket (`SO_DETACH_FILTER`).
+    pub fn detach_filter(&self) -> AxResult {
+        self.filter.detach()
+    }
+
+    /// Returns the capacity of the receive buffer (`SO_RCVBUF`).
+    #[inline]
+    pub fn recv_buffer_size(&self) -> usize {
+        super::RAW_RX_BUF_LEN
+    }
+
+    /// Returns the capacity of the send buffer (`SO_SNDBUF`).
+    #[inline]
+    pub fn send_buffer_size(&self) -> usize {
+        super::RAW_TX_BUF_LEN
+    }
+
+    /// Sets the default destination of [`send`](Self::send).
+    pub fn connect(&self, addr: IpAddr) -> AxResult {
+        *self.peer_addr.write() = Some(from_core_ipaddr(addr));
+        debug!("raw socket {}: connected to {}", self.handle, addr);
+        Ok(())
+    }
+
+    /// Sends a packet to the given address. On success, returns the number of
+    /// bytes written.
+    ///
+    /// If `IP_HDRINCL` is set, `buf` is a complete IPv4 packet and
+    /// `remote_addr` is ignored, as the destination is taken from the header.
+    pub fn send_to(&self, buf: &[u8], remote_addr: IpAddr) -> AxResult<usize> {
+        if remote_addr.is_unspecified() && !self.header_included() {
+            return ax_err!(InvalidInput, "socket send_to() failed: invalid address");
+        }
+        self.send_impl(buf, from_core_ipaddr(remote_addr))
+    }
+
+    /// Sends a packet to the address to which the socket is connected.
+    pub fn send(&self, buf: &[u8]) -> AxResult<usize> {
+        if self.header_included() {
+            return self.send_impl(buf, super::addr::UNSPECIFIED_IP);
+        }
+        let remote_addr = self.remote_addr()?;
+        self.send_impl(buf, remote_addr)
+    }
+
+    /// Receives a single packet, including its IPv4 header. On success,
+    /// returns the number of bytes read and the origin.
+    pub fn recv_from(&self, buf: &mut [u8]) -> AxResult<(usize, IpAddr)> {
+        self.block_on(self.recv_timeout(), || {
+            self.ns
+                .sockets
+                .with_socket_mut::<raw::Socket, _, _>(self.handle, |socket| {
+                    while socket.can_recv() {
+                        let data = socket
+                            .recv()
+                            .map_err(|_| ax_err_type!(BadState, "socket recv_from() failed"))?;
+                        let src_addr = Ipv4Packet::new_checked(data)
+                            .map_err(|_| ax_err_type!(InvalidData, "socket recv_from() failed"))?
+                            .src_addr();
+                        let kept = self.filter.run(data);
+                        if kept == 0 {
+                            // rejected by the filter
+                            continue;
+                        }
+                        // excess bytes are discarded, as for other datagram sockets
+                        let len = kept.min(buf.len());
+                        buf[..len].copy_from_slice(&data[..len]);
+                        return Ok((len, into_core_ipaddr(IpAddress::Ipv4(src_addr))));
+                    }
+                    Err(AxError::WouldBlock)
+                })
+        })
+    }
+
+    /// Receives a single packet from the address to which the socket is
+    /// connected. On success, returns the number of bytes read.
+    pub fn recv(&self, buf: &mut [u8]) -> AxResult<usize> {
+        let remote_addr = into_core_ipaddr(self.remote_addr()?);
+        loop {
+            let (len, addr) = self.recv_from(buf)?;
+            if addr == remote_addr {
+                return Ok(len);
+            }
+        }
+    }
+
+    /// Close the socket.
+    pub fn shutdown(&self) -> AxResult {
+        debug!("raw socket {}: shutting down", self.handle);
+        Ok(())
+    }
+
+    /// Whether the socket is readable or writable.
+    pub fn poll(&self) -> AxResult<PollState> {
+        self.ns
+            .sockets
+            .with_socket_mut::<raw::Socket, _, _>(self.handle, |socket| {
+                Ok(PollState {
+                    readable: socket.can_recv(),
+                    writable: socket.can_send(),
+                })
+            })
+    }
+}
+
+/// Private methods
+impl RawSocket {
+    fn remote_addr(&self) -> AxResult<IpAddress> {
+        self.peer_addr.read().ok_or(AxError::NotConnected)
+    }
+
+    /// Builds the packet to be queued, adding an IPv4 header unless the
+    /// caller has provided one.
+    fn build_packet(&self, buf: &[u8], remote_addr: IpAddress) -> AxResult<Vec<u8>> {
+        if self.header_included() {
+            Ipv4Packet::new_checked(buf)
+                .map_err(|_| ax_err_type!(InvalidInput, "socket send() failed: bad IP header"))?;
+            return Ok(buf.to_vec());
+        }
+
+        let src_addr = match self.ns.source_addr(remote_addr) {
+            Some(IpAddress::Ipv4(v4)) => v4,
+            None => return ax_err!(BadState, "socket send() failed: no IP address"),
+        };
+        let dst_addr = match remote_addr {
+            IpAddress::Ipv4(v4) => v4,
+        };
+        let repr = Ipv4Repr {
+            src_addr,
+            dst_addr,
+            next_header: self.protocol,
+            payload_len: buf.len(),
+            hop_limit: DEFAULT_HOP_LIMIT,
+        };
+        let mut packet = Ipv4Packet::new_unchecked(vec![0; IPV4_HEADER_LEN + buf.len()]);
+        repr.emit(&mut packet, &ChecksumCapabilities::default());
+        packet.payload_mut().copy_from_slice(buf);
+        Ok(packet.into_inner())
+    }
+
+    fn send_impl(&self, buf: &[u8], remote_addr: IpAddress) -> AxResult<usize> {
+        let packet = self.build_packet(buf, remote_addr)?;
+        self.block_on(self.send_timeout(), || {
+            self.ns
+                .sockets
+                .with_socket_mut::<raw::Socket, _, _>(self.handle, |socket| {
+                    if !socket.can_send() {
+                        return Err(AxError::WouldBlock);
+                    }
+                    socket.send_slice(&packet).map_err(|e| match e {
+                        SendError::BufferFull => AxError::WouldBlock,
+                    })?;
+                    Ok(buf.len())
+                })
+        })
+    }
+
+    fn block_on<F, T>(&self, timeout: Option<Duration>, f: F) -> AxResult<T>
+    where
+        F: FnMut() -> AxResult<T>,
+    {
+        block_on_until(self.is_nonblocking(), timeout, f)
+    }
+}
+
+impl Drop for RawSocket {
+    fn drop(&mut self) {
+        self.shutdown().ok();
+        self.ns.sockets.remove(self.handle);
+    }
+}
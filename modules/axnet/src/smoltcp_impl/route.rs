@@ -0,0 +1,492 @@
+//! The routing tables and policy rules of a network namespace, shared by its
+//! NICs.
+//!
+//! Each route sends the packets to a destination network through a NIC,
+//! either directly or via a gateway. A namespace has several routing tables,
+//! and the policy rules, in the order of their priorities, choose the tables
+//! to look up by the source and destination of the packets. In a table the
+//! most specific route wins, then the one with the lowest metric. By default,
+//! there's a single rule looking up the [main table](MAIN_TABLE). Every NIC
+//! with an address has a direct route to its own network in the main table.
+//!
+//! The tables decide the interface and the source address of new connections,
+//! which is the route's preferred source if any, or else the address of the
+//! NIC on the network of the next hop. The routes with a gateway are also
+//! installed into the routing table of smoltcp's interface, which has a fixed
+//! capacity (`IFACE_MAX_ROUTE_COUNT`) and only one gateway for each network,
+//! the one of the route with the lowest metric (of the main table first).
+//! Since smoltcp sends the packets of a socket through the first polled
+//! interface that has a route to the destination, the NICs are polled in the
+//! order of their least specific routes, so that e.g. the NIC with the
+//! default route doesn't take the packets to the networks of other NICs.
+//! Other overlapping routes of different NICs are not supported.
+
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::{vec, vec::Vec};
+use core::cmp::Reverse;
+use core::fmt::Write;
+use core::net::IpAddr;
+
+use axerrno::{AxResult, ax_err, ax_err_type};
+use smoltcp::iface::Route as IfaceRoute;
+use smoltcp::wire::{IpAddress, IpCidr, Ipv4Cidr};
+use spin::RwLock;
+
+use super::addr::{from_core_ipaddr, into_core_ipaddr};
+use super::is_loopback;
+use super::netns::{NetNamespace, Nic, current_net_namespace};
+
+/// The ID of the main routing table, as on Linux.
+pub const MAIN_TABLE: u32 = 254;
+
+/// The priority of the default rule, which looks up the main table.
+const MAIN_RULE_PRIORITY: u32 = 32766;
+
+/// An entry of the routing tables.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Route {
+    /// The address of the destination network.
+    pub dest: IpAddr,
+    /// The prefix length of the destination network.
+    pub prefix_len: u8,
+    /// The gateway, or `None` if the destination is directly reachable.
+    pub gateway: Option<IpAddr>,
+    /// The name of the outgoing interface.
+    pub iface: String,
+    /// The preferred source address, which must be an address of the
+    /// interface.
+    pub src: Option<IpAddr>,
+    /// The metric, the route with the lowest one wins among the routes to
+    /// the same network.
+    pub metric: u32,
+    /// The ID of the routing table.
+    pub table: u32,
+}
+
+impl Route {
+    /// Creates a route of the main table, with a metric of 0 and no
+    /// preferred source address.
+    pub fn new(dest: IpAddr, prefix_len: u8, gateway: Option<IpAddr>, iface: &str) -> Self {
+        Self {
+            dest,
+            prefix_len,
+            gateway,
+            iface: iface.into(),
+            src: None,
+            metric: 0,
+            table: MAIN_TABLE,
+        }
+    }
+}
+
+/// A policy rule, choosing the routing table to look up (`ip rule`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RouteRule {
+    /// The priority, the rules with the lowest ones are tried first.
+    pub priority: u32,
+    /// The source network the rule applies to, or `None` for all sources.
+    pub src: Option<(IpAddr, u8)>,
+    /// The destination network the rule applies to, or `None` for all
+    /// destinations.
+    pub dest: Option<(IpAddr, u8)>,
+    /// The ID of the routing table to look up.
+    pub table: u32,
+}
+
+struct RouteEntry {
+    cidr: IpCidr,
+    gateway: Option<IpAddress>,
+    /// The outgoing NIC.
+    nic: Nic,
+    src: Option<IpAddress>,
+    metric: u32,
+    table: u32,
+}
+
+struct RuleEntry {
+    priority: u32,
+    src: Option<IpCidr>,
+    dest: Option<IpCidr>,
+    table: u32,
+}
+
+impl RuleEntry {
+    fn matches(&self, dst: IpAddress, src: Option<IpAddress>) -> bool {
+        self.dest.is_none_or(|cidr| cidr.contains_addr(&dst))
+            && self
+                .src
+                .is_none_or(|cidr| src.is_some_and(|src| cidr.contains_addr(&src)))
+    }
+}
+
+/// The routes and the policy rules of a namespace.
+pub(super) struct RouteTable {
+    routes: RwLock<Vec<RouteEntry>>,
+    /// Sorted by priority.
+    rules: RwLock<Vec<RuleEntry>>,
+    /// The NICs in the order of polling.
+    poll_order: RwLock<Vec<Nic>>,
+}
+
+impl RouteTable {
+    pub fn new() -> Self {
+        Self {
+            routes: RwLock::new(Vec::new()),
+            rules: RwLock::new(vec![RuleEntry {
+                priority: MAIN_RULE_PRIORITY,
+                src: None,
+                dest: None,
+                table: MAIN_TABLE,
+            }]),
+            poll_order: RwLock::new(Vec::new()),
+        }
+    }
+
+    /// Returns the route of the packets from `src` (if known) to `dst`.
+    fn lookup<R>(
+        &self,
+        dst: IpAddress,
+        src: Option<IpAddress>,
+        f: impl FnOnce(&RouteEntry) -> R,
+    ) -> Option<R> {
+        let routes = self.routes.read();
+        self.rules
+            .read()
+            .iter()
+            .filter(|rule| rule.matches(dst, src))
+            .find_map(|rule| {
+                routes
+                    .iter()
+                    .filter(|r| r.table == rule.table && r.cidr.contains_addr(&dst))
+                    .min_by_key(|r| (Reverse(r.cidr.prefix_len()), r.metric))
+            })
+            .map(f)
+    }
+}
+
+fn to_ipv4(addr: IpAddr) -> AxResult<IpAddress> {
+    match addr {
+        IpAddr::V4(_) => Ok(from_core_ipaddr(addr)),
+        IpAddr::V6(_) => ax_err!(Unsupported, "IPv6 not supported"),
+    }
+}
+
+/// Returns the network of the given prefix length that `addr` belongs to.
+pub(super) fn network_of(addr: IpAddress, prefix_len: u8) -> AxResult<IpCidr> {
+    if prefix_len > 32 {
+        return ax_err!(InvalidInput, "invalid prefix length");
+    }
+    match addr {
+        IpAddress::Ipv4(v4) => Ok(IpCidr::Ipv4(Ipv4Cidr::new(v4, prefix_len).network())),
+    }
+}
+
+fn to_network(network: Option<(IpAddr, u8)>) -> AxResult<Option<IpCidr>> {
+    network
+        .map(|(addr, prefix_len)| network_of(to_ipv4(addr)?, prefix_len))
+        .transpose()
+}
+
+/// Installs the routes with a gateway of the NIC into its interface.
+fn sync_nic(routes: &[RouteEntry], nic: &Nic) {
+    let mut nic_routes: Vec<_> = routes
+        .iter()
+        .filter(|r| Arc::ptr_eq(&r.nic, nic) && r.gateway.is_some())
+        .collect();
+    nic_routes.sort_by_key(|r| (r.table != MAIN_TABLE, r.metric));
+
+    nic.iface.lock().routes_mut().update(|storage| {
+        storage.clear();
+        for route in nic_routes {
+            if storage.iter().any(|r| r.cidr == route.cidr) {
+                continue;
+            }
+            let route = IfaceRoute {
+                cidr: route.cidr,
+                via_router: route.gateway.unwrap(),
+                preferred_until: None,
+                expires_at: None,
+            };
+            if storage.push(route).is_err() {
+                warn!(
+                    "too many routes on {}, {} is ignored",
+                    nic.name(),
+                    route.cidr
+                );
+            }
+        }
+    });
+}
+
+/// Sorts the NICs by the prefix length of their least specific routes, from
+/// the longest to the shortest.
+fn sort_poll_order(ns: &NetNamespace, routes: &[RouteEntry]) {
+    let min_prefix_len = |nic: &Nic| {
+        routes
+            .iter()
+            .filter(|r| Arc::ptr_eq(&r.nic, nic))
+            .map(|r| r.cidr.prefix_len())
+            .min()
+            .unwrap_or(u8::MAX)
+    };
+    let mut order = ns.nics();
+    order.sort_by_key(|nic| Reverse(min_prefix_len(nic)));
+    *ns.routes.poll_order.write() = order;
+}
+
+/// Updates the order of polling after NICs are added.
+pub(super) fn update_poll_order(ns: &NetNamespace) {
+    sort_poll_order(ns, &ns.routes.routes.read());
+}
+
+/// Removes the routes of `nic`, and the NIC itself with `remove`.
+///
+/// The NIC is removed with the routes locked, so that no route added
+/// meanwhile refers to it.
+pub(super) fn remove_nic(ns: &NetNamespace, nic: &Nic, remove: impl FnOnce()) {
+    let mut routes = ns.routes.routes.write();
+    routes.retain(|r| !Arc::ptr_eq(&r.nic, nic));
+    remove();
+    sync_nic(&routes, nic);
+    sort_poll_order(ns, &routes);
+}
+
+/// Returns the NICs in the order of polling.
+pub(super) fn poll_order(ns: &NetNamespace) -> Vec<Nic> {
+    ns.routes.poll_order.read().clone()
+}
+
+/// Returns the NIC through which packets from `src` (if known) to `dst` are
+/// sent.
+pub(super) fn lookup(ns: &NetNamespace, dst: IpAddress, src: Option<IpAddress>) -> Option<Nic> {
+    ns.routes.lookup(dst, src, |route| route.nic.clone())
+}
+
+/// Returns the source address of the packets to `dst`, or `None` if there's
+/// no route or it's a loopback address.
+pub(super) fn select_source(ns: &NetNamespace, dst: IpAddress) -> Option<IpAddress> {
+    if is_loopback(dst) {
+        return None;
+    }
+    let (nic, src, next_hop) = ns.routes.lookup(dst, None, |route| {
+        (route.nic.clone(), route.src, route.gateway.unwrap_or(dst))
+    })?;
+    if src.is_some() {
+        return src;
+    }
+    let addrs = nic.ip_addrs();
+    addrs
+        .iter()
+        .find(|cidr| cidr.contains_addr(&next_hop))
+        .or(addrs.first())
+        .map(|cidr| cidr.address())
+}
+
+/// Replaces the direct route of the NIC with the route to `network`, from
+/// its address `addr`.
+pub(super) fn set_direct_route(ns: &NetNamespace, nic: &Nic, network: IpCidr, addr: IpAddress) {
+    let mut routes = ns.routes.routes.write();
+    routes.retain(|r| !Arc::ptr_eq(&r.nic, nic) || r.gateway.is_some() || r.table != MAIN_TABLE);
+    if !ns.has_nic(nic) {
+        // removed meanwhile
+        return;
+    }
+    routes.push(RouteEntry {
+        cidr: network,
+        gateway: None,
+        nic: nic.clone(),
+        src: Some(addr),
+        metric: 0,
+        table: MAIN_TABLE,
+    });
+    sort_poll_order(ns, &routes);
+}
+
+/// Returns the routes of all the routing tables of the current namespace.
+pub fn routes() -> Vec<Route> {
+    let ns = current_net_namespace();
+    ns.routes
+        .routes
+        .read()
+        .iter()
+        .map(|route| Route {
+            dest: into_core_ipaddr(route.cidr.address()),
+            prefix_len: route.cidr.prefix_len(),
+            gateway: route.gateway.map(into_core_ipaddr),
+            iface: route.nic.name().into(),
+            src: route.src.map(into_core_ipaddr),
+            metric: route.metric,
+            table: route.table,
+        })
+        .collect()
+}
+
+/// Adds `route` to the current namespace.
+///
+/// Returns [`AlreadyExists`](axerrno::AxError::AlreadyExists) if there's
+/// already a route to the same network with the same metric in the table.
+pub fn add_route(route: &Route) -> AxResult {
+    if route.table == 0 {
+        return ax_err!(InvalidInput, "invalid routing table");
+    }
+    let cidr = network_of(to_ipv4(route.dest)?, route.prefix_len)?;
+    let gateway = route.gateway.map(to_ipv4).transpose()?;
+    let src = route.src.map(to_ipv4).transpose()?;
+    let ns = current_net_namespace();
+    let nic = ns.find_nic(&route.iface)?;
+    if src.is_some_and(|src| !nic.has_ip_addr(src)) {
+        return ax_err!(InvalidInput, "source address not on the interface");
+    }
+
+    let mut routes = ns.routes.routes.write();
+    if !ns.has_nic(&nic) {
+        return ax_err!(NotFound, "no such interface");
+    }
+    if routes
+        .iter()
+        .any(|r| r.table == route.table && r.cidr == cidr && r.metric == route.metric)
+    {
+        return ax_err!(AlreadyExists, "route already exists");
+    }
+    routes.push(RouteEntry {
+        cidr,
+        gateway,
+        nic: nic.clone(),
+        src,
+        metric: route.metric,
+        table: route.table,
+    });
+    sync_nic(&routes, &nic);
+    sort_poll_order(&ns, &routes);
+    info!(
+        "route added: {} via {:?} dev {} metric {} table {}",
+        cidr, gateway, route.iface, route.metric, route.table
+    );
+    Ok(())
+}
+
+/// Removes the route to the same network with the same metric as `route`
+/// from its table in the current namespace.
+///
+/// The other fields of `route` are ignored.
+pub fn del_route(route: &Route) -> AxResult {
+    let cidr = network_of(to_ipv4(route.dest)?, route.prefix_len)?;
+
+    let ns = current_net_namespace();
+    let mut routes = ns.routes.routes.write();
+    let pos = routes
+        .iter()
+        .position(|r| r.table == route.table && r.cidr == cidr && r.metric == route.metric)
+        .ok_or_else(|| ax_err_type!(NotFound, "no such route"))?;
+    let removed = routes.remove(pos);
+    sync_nic(&routes, &removed.nic);
+    sort_poll_order(&ns, &routes);
+    info!(
+        "route deleted: {} metric {} table {}",
+        cidr, route.metric, route.table
+    );
+    Ok(())
+}
+
+/// Returns the policy rules of the current namespace, in the order of their
+/// priorities.
+pub fn route_rules() -> Vec<RouteRule> {
+    let into_network = |cidr: IpCidr| (into_core_ipaddr(cidr.address()), cidr.prefix_len());
+    current_net_namespace()
+        .routes
+        .rules
+        .read()
+        .iter()
+        .map(|rule| RouteRule {
+            priority: rule.priority,
+            src: rule.src.map(into_network),
+            dest: rule.dest.map(into_network),
+            table: rule.table,
+        })
+        .collect()
+}
+
+/// Adds the policy rule `rule` to the current namespace.
+///
+/// Returns [`AlreadyExists`](axerrno::AxError::AlreadyExists) if there's
+/// already a rule with the same priority.
+pub fn add_route_rule(rule: &RouteRule) -> AxResult {
+    if rule.table == 0 {
+        return ax_err!(InvalidInput, "invalid routing table");
+    }
+    let entry = RuleEntry {
+        priority: rule.priority,
+        src: to_network(rule.src)?,
+        dest: to_network(rule.dest)?,
+        table: rule.table,
+    };
+    let ns = current_net_namespace();
+    let mut rules = ns.routes.rules.write();
+    let pos = match rules.binary_search_by_key(&rule.priority, |r| r.priority) {
+        Ok(_) => return ax_err!(AlreadyExists, "rule already exists"),
+        Err(pos) => pos,
+    };
+    rules.insert(pos, entry);
+    info!("rule added: {} lookup {}", rule.priority, rule.table);
+    Ok(())
+}
+
+/// Removes the policy rule with the priority `priority` from the current
+/// namespace.
+pub fn del_route_rule(priority: u32) -> AxResult {
+    let ns = current_net_namespace();
+    let mut rules = ns.routes.rules.write();
+    let pos = rules
+        .binary_search_by_key(&priority, |r| r.priority)
+        .map_err(|_| ax_err_type!(NotFound, "no such rule"))?;
+    rules.remove(pos);
+    info!("rule deleted: {}", priority);
+    Ok(())
+}
+
+/// Returns the main routing table of the current namespace, in the format of
+/// Linux's `/proc/net/route`.
+pub fn proc_net_route() -> String {
+    const RTF_UP: u16 = 0x1;
+    const RTF_GATEWAY: u16 = 0x2;
+    // The lines are padded to 127 characters.
+    const LINE_LEN: usize = 127;
+
+    // The addresses are in hex, as the `u32` of their bytes in memory.
+    let hex = |addr: IpAddress| match addr {
+        IpAddress::Ipv4(v4) => u32::from_le_bytes(v4.0),
+    };
+    let mut content = String::new();
+    let mut push_line = |line: &str| {
+        writeln!(content, "{:<1$}", line, LINE_LEN).unwrap();
+    };
+    push_line(
+        "Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT",
+    );
+
+    let ns = current_net_namespace();
+    for route in ns.routes.routes.read().iter() {
+        if route.table != MAIN_TABLE {
+            continue;
+        }
+        let mut flags = RTF_UP;
+        if route.gateway.is_some() {
+            flags |= RTF_GATEWAY;
+        }
+        let mask = match route.cidr {
+            IpCidr::Ipv4(cidr) => IpAddress::Ipv4(cidr.netmask()),
+        };
+        let gateway = route.gateway.map_or(0, hex);
+        push_line(&alloc::format!(
+            "{}\t{:08X}\t{:08X}\t{:04X}\t0\t0\t{}\t{:08X}\t0\t0\t0",
+            route.nic.name(),
+            hex(route.cidr.address()),
+            gateway,
+            flags,
+            route.metric,
+            hex(mask),
+        ));
+    }
+    content
+}
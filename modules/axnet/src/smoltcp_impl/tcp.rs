@@ -1,17 +1,23 @@
+use alloc::sync::Arc;
 use core::cell::UnsafeCell;
 use core::net::SocketAddr;
-use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering};
+use core::time::Duration;
 
 use axerrno::{AxError, AxResult, ax_err, ax_err_type};
 use axio::PollState;
 use axsync::Mutex;
+use spin::RwLock;
 
 use smoltcp::iface::SocketHandle;
 use smoltcp::socket::tcp::{self, ConnectError, State};
 use smoltcp::wire::{IpEndpoint, IpListenEndpoint};
 
 use super::addr::{UNSPECIFIED_ENDPOINT, from_core_sockaddr, into_core_sockaddr, is_unspecified};
-use super::{ETH0, LISTEN_TABLE, SOCKET_SET, SocketSetWrapper};
+use super::netns::{NetNamespace, current_net_namespace};
+use super::qdisc::TokenBucket;
+use super::tcp_info::{self, TcpInfo, TcpState};
+use super::{SocketSetWrapper, block_on_until, poll_interfaces};
 
 // State transitions:
 // CLOSED -(connect)-> BUSY -> CONNECTING -> CONNECTED -(shutdown)-> BUSY -> CLOSED
@@ -25,6 +31,14 @@ const STATE_CONNECTING: u8 = 2;
 const STATE_CONNECTED: u8 = 3;
 const STATE_LISTENING: u8 = 4;
 
+/// Default idle time before the peer is probed (`TCP_KEEPIDLE`), as on Linux.
+const TCP_KEEPALIVE_IDLE: Duration = Duration::from_secs(7200);
+/// Default interval between keep-alive probes (`TCP_KEEPINTVL`).
+const TCP_KEEPALIVE_INTERVAL: Duration = Duration::from_secs(75);
+/// Default number of unanswered probes before the connection is dropped
+/// (`TCP_KEEPCNT`).
+const TCP_KEEPALIVE_COUNT: u32 = 9;
+
 /// A TCP socket that provides POSIX-like APIs.
 ///
 /// - [`connect`] is for TCP clients.
@@ -36,39 +50,75 @@ const STATE_LISTENING: u8 = 4;
 /// [`listen`]: TcpSocket::listen
 /// [`accept`]: TcpSocket::accept
 pub struct TcpSocket {
+    ns: Arc<NetNamespace>,
     state: AtomicU8,
     handle: UnsafeCell<Option<SocketHandle>>,
     local_addr: UnsafeCell<IpEndpoint>,
     peer_addr: UnsafeCell<IpEndpoint>,
     nonblock: AtomicBool,
+    reuse_addr: AtomicBool,
+    reuse_port: AtomicBool,
+    /// Identifies the socket in the listen table while it's listening.
+    listen_key: AtomicUsize,
+    nodelay: AtomicBool,
+    keepalive: AtomicBool,
+    keepalive_params: Mutex<KeepAliveParams>,
+    recv_timeout: RwLock<Option<Duration>>,
+    send_timeout: RwLock<Option<Duration>>,
+    pacing: Mutex<TokenBucket>,
+    error: Mutex<Option<AxError>>,
 }
 
 unsafe impl Sync for TcpSocket {}
 
 impl TcpSocket {
-    /// Creates a new TCP socket.
-    pub const fn new() -> Self {
+    /// Creates a new TCP socket in the current network namespace.
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
         Self {
+            ns: current_net_namespace(),
             state: AtomicU8::new(STATE_CLOSED),
             handle: UnsafeCell::new(None),
             local_addr: UnsafeCell::new(UNSPECIFIED_ENDPOINT),
             peer_addr: UnsafeCell::new(UNSPECIFIED_ENDPOINT),
             nonblock: AtomicBool::new(false),
+            reuse_addr: AtomicBool::new(false),
+            reuse_port: AtomicBool::new(false),
+            listen_key: AtomicUsize::new(0),
+            nodelay: AtomicBool::new(false),
+            keepalive: AtomicBool::new(false),
+            keepalive_params: Mutex::new(KeepAliveParams::default()),
+            recv_timeout: RwLock::new(None),
+            send_timeout: RwLock::new(None),
+            pacing: Mutex::new(TokenBucket::new()),
+            error: Mutex::new(None),
         }
     }
 
     /// Creates a new TCP socket that is already connected.
-    const fn new_connected(
+    fn new_connected(
+        ns: Arc<NetNamespace>,
         handle: SocketHandle,
         local_addr: IpEndpoint,
         peer_addr: IpEndpoint,
     ) -> Self {
         Self {
+            ns,
             state: AtomicU8::new(STATE_CONNECTED),
             handle: UnsafeCell::new(Some(handle)),
             local_addr: UnsafeCell::new(local_addr),
             peer_addr: UnsafeCell::new(peer_addr),
             nonblock: AtomicBool::new(false),
+            reuse_addr: AtomicBool::new(false),
+            reuse_port: AtomicBool::new(false),
+            listen_key: AtomicUsize::new(0),
+            nodelay: AtomicBool::new(false),
+            keepalive: AtomicBool::new(false),
+            keepalive_params: Mutex::new(KeepAliveParams::default()),
+            recv_timeout: RwLock::new(None),
+            send_timeout: RwLock::new(None),
+            pacing: Mutex::new(TokenBucket::new()),
+            error: Mutex::new(None),
         }
     }
 
@@ -115,23 +165,248 @@ impl TcpSocket {
         self.nonblock.store(nonblocking, Ordering::Release);
     }
 
+    /// Returns and clears the pending error of the socket (`SO_ERROR`).
+    ///
+    /// It's mainly used to get the result of a nonblocking [`connect`] after
+    /// the socket becomes writable.
+    ///
+    /// [`connect`]: Self::connect
+    pub fn take_error(&self) -> Option<AxError> {
+        self.error.lock().take()
+    }
+
+    /// Returns whether local addresses can be reused (`SO_REUSEADDR`).
+    #[inline]
+    pub fn reuse_addr(&self) -> bool {
+        self.reuse_addr.load(Ordering::Acquire)
+    }
+
+    /// Allows or disallows reusing local addresses (`SO_REUSEADDR`).
+    ///
+    /// The flag is only recorded for now, as ports are not held in `TIME_WAIT`
+    /// after a connection is closed.
+    #[inline]
+    pub fn set_reuse_addr(&self, reuse_addr: bool) {
+        self.reuse_addr.store(reuse_addr, Ordering::Release);
+    }
+
+    /// Returns whether the port can be shared with other listening sockets
+    /// (`SO_REUSEPORT`).
+    #[inline]
+    pub fn reuse_port(&self) -> bool {
+        self.reuse_port.load(Ordering::Acquire)
+    }
+
+    /// Allows or disallows sharing the port with other listening sockets
+    /// (`SO_REUSEPORT`).
+    ///
+    /// It must be set on all the sockets before they [`listen`]. The incoming
+    /// connections are then distributed among them by a hash of the source
+    /// address and port.
+    ///
+    /// [`listen`]: Self::listen
+    #[inline]
+    pub fn set_reuse_port(&self, reuse_port: bool) {
+        self.reuse_port.store(reuse_port, Ordering::Release);
+    }
+
+    /// Returns whether the Nagle algorithm is disabled (`TCP_NODELAY`).
+    #[inline]
+    pub fn nodelay(&self) -> bool {
+        self.nodelay.load(Ordering::Acquire)
+    }
+
+    /// Enables or disables the Nagle algorithm (`TCP_NODELAY`).
+    pub fn set_nodelay(&self, nodelay: bool) {
+        self.nodelay.store(nodelay, Ordering::Release);
+        self.apply_socket_options();
+    }
+
+    /// Returns whether keep-alive probes are enabled (`SO_KEEPALIVE`).
+    #[inline]
+    pub fn keepalive(&self) -> bool {
+        self.keepalive.load(Ordering::Acquire)
+    }
+
+    /// Enables or disables sending keep-alive probes (`SO_KEEPALIVE`).
+    ///
+    /// With keep-alive enabled, a peer that stops answering is considered
+    /// dead after [`keepalive_idle`](Self::keepalive_idle) plus
+    /// [`keepalive_count`](Self::keepalive_count) times
+    /// [`keepalive_interval`](Self::keepalive_interval) without any segment
+    /// from it. The connection is then aborted, and blocked calls fail with
+    /// [`Err(ConnectionReset)`](AxError::ConnectionReset).
+    pub fn set_keepalive(&self, keepalive: bool) {
+        self.keepalive.store(keepalive, Ordering::Release);
+        self.apply_socket_options();
+    }
+
+    /// Returns the idle time before the peer is probed (`TCP_KEEPIDLE`).
+    pub fn keepalive_idle(&self) -> Duration {
+        self.keepalive_params.lock().idle
+    }
+
+    /// Sets the idle time before the peer is probed (`TCP_KEEPIDLE`).
+    ///
+    /// smoltcp can't wait longer for the first probe than between the next
+    /// ones, so the probes are sent every
+    /// [`keepalive_interval`](Self::keepalive_interval) from the start. This
+    /// only adds a few packets: the time after which the peer is considered
+    /// dead is the same.
+    pub fn set_keepalive_idle(&self, idle: Duration) {
+        self.keepalive_params.lock().idle = idle;
+        self.apply_socket_options();
+    }
+
+    /// Returns the interval between keep-alive probes (`TCP_KEEPINTVL`).
+    pub fn keepalive_interval(&self) -> Duration {
+        self.keepalive_params.lock().interval
+    }
+
+    /// Sets the interval between keep-alive probes (`TCP_KEEPINTVL`).
+    pub fn set_keepalive_interval(&self, interval: Duration) {
+        self.keepalive_params.lock().interval = interval;
+        self.apply_socket_options();
+    }
+
+    /// Returns the number of unanswered probes before the connection is
+    /// dropped (`TCP_KEEPCNT`).
+    pub fn keepalive_count(&self) -> u32 {
+        self.keepalive_params.lock().count
+    }
+
+    /// Sets the number of unanswered probes before the connection is dropped
+    /// (`TCP_KEEPCNT`).
+    pub fn set_keepalive_count(&self, count: u32) {
+        self.keepalive_params.lock().count = count;
+        self.apply_socket_options();
+    }
+
+    /// Returns the timeout of blocking receive operations (`SO_RCVTIMEO`).
+    #[inline]
+    pub fn recv_timeout(&self) -> Option<Duration> {
+        *self.recv_timeout.read()
+    }
+
+    /// Sets the timeout of blocking receive operations (`SO_RCVTIMEO`).
+    ///
+    /// If the timeout expires, [`recv`](Self::recv) and [`accept`](Self::accept)
+    /// fail with [`Err(WouldBlock)`](AxError::WouldBlock). `None` means
+    /// blocking forever.
+    #[inline]
+    pub fn set_recv_timeout(&self, timeout: Option<Duration>) {
+        *self.recv_timeout.write() = timeout;
+    }
+
+    /// Returns the timeout of blocking send operations (`SO_SNDTIMEO`).
+    #[inline]
+    pub fn send_timeout(&self) -> Option<Duration> {
+        *self.send_timeout.read()
+    }
+
+    /// Sets the timeout of blocking send operations (`SO_SNDTIMEO`).
+    ///
+    /// If the timeout expires, [`send`](Self::send) and
+    /// [`connect`](Self::connect) fail with
+    /// [`Err(WouldBlock)`](AxError::WouldBlock). `None` means blocking forever.
+    #[inline]
+    pub fn set_send_timeout(&self, timeout: Option<Duration>) {
+        *self.send_timeout.write() = timeout;
+    }
+
+    /// Returns the maximum sending rate in bytes per second
+    /// (`SO_MAX_PACING_RATE`), or `None` if unlimited.
+    pub fn max_pacing_rate(&self) -> Option<u64> {
+        self.pacing.lock().rate()
+    }
+
+    /// Limits the sending rate to `rate` bytes per second
+    /// (`SO_MAX_PACING_RATE`), or removes the limit if `None`.
+    ///
+    /// Data over the limit is held back by [`send`](Self::send).
+    pub fn set_max_pacing_rate(&self, rate: Option<u64>) {
+        self.pacing.lock().set_rate(rate);
+    }
+
+    /// Returns the capacity of the receive buffer (`SO_RCVBUF`).
+    pub fn recv_buffer_size(&self) -> usize {
+        match self.connected_handle() {
+            Some(handle) => self
+                .ns
+                .sockets
+                .with_socket::<tcp::Socket, _, _>(handle, |socket| socket.recv_capacity()),
+            None => super::TCP_RX_BUF_LEN,
+        }
+    }
+
+    /// Returns the number of bytes received and not read yet (`FIONREAD`).
+    pub fn recv_queue_len(&self) -> usize {
+        match self.connected_handle() {
+            Some(handle) => self
+                .ns
+                .sockets
+                .with_socket::<tcp::Socket, _, _>(handle, |socket| socket.recv_queue()),
+            None => 0,
+        }
+    }
+
+    /// Returns the capacity of the send buffer (`SO_SNDBUF`).
+    pub fn send_buffer_size(&self) -> usize {
+        match self.connected_handle() {
+            Some(handle) => self
+                .ns
+                .sockets
+                .with_socket::<tcp::Socket, _, _>(handle, |socket| socket.send_capacity()),
+            None => super::TCP_TX_BUF_LEN,
+        }
+    }
+
     /// Connects to the given address and port.
     ///
     /// The local port is generated automatically.
+    ///
+    /// If the socket is in nonblocking mode, it returns
+    /// [`Err(WouldBlock)`](AxError::WouldBlock) after the connection is
+    /// initiated. The socket becomes writable when the connection completes,
+    /// and the result can be obtained by [`take_error`](Self::take_error).
+    /// Otherwise, it waits for at most the [send timeout](Self::send_timeout).
+    ///
+    /// If a nonblocking connection failed and the error wasn't taken, it
+    /// returns the error instead of connecting again, as on Linux.
     pub fn connect(&self, remote_addr: SocketAddr) -> AxResult {
+        self.connect_timeout(remote_addr, self.send_timeout())
+    }
+
+    /// Connects to the given address and port like [`connect`](Self::connect),
+    /// but fails with [`Err(WouldBlock)`](AxError::WouldBlock) if the
+    /// connection isn't established before `timeout` expires.
+    ///
+    /// The socket is left connecting after a timeout, and should be dropped.
+    pub fn connect_timeout(&self, remote_addr: SocketAddr, timeout: Option<Duration>) -> AxResult {
+        if self.get_state() == STATE_CLOSED {
+            if let Some(err) = self.take_error() {
+                return Err(err);
+            }
+        }
         self.update_state(STATE_CLOSED, STATE_CONNECTING, || {
             // SAFETY: no other threads can read or write these fields.
             let handle = unsafe { self.handle.get().read() }
-                .unwrap_or_else(|| SOCKET_SET.add(SocketSetWrapper::new_tcp_socket()));
+                .unwrap_or_else(|| self.ns.sockets.add(SocketSetWrapper::new_tcp_socket()));
 
             // TODO: check remote addr unreachable
             let remote_endpoint = from_core_sockaddr(remote_addr);
-            let bound_endpoint = self.bound_endpoint()?;
-            let iface = &ETH0.iface;
-            let (local_endpoint, remote_endpoint) = SOCKET_SET
+            let mut bound_endpoint = self.bound_endpoint()?;
+            if bound_endpoint.addr.is_none() {
+                bound_endpoint.addr = self.ns.source_addr(remote_endpoint.addr);
+            }
+            let (local_endpoint, remote_endpoint) = self
+                .ns
+                .sockets
                 .with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
-                    socket
-                        .connect(iface.lock().context(), remote_endpoint, bound_endpoint)
+                    self.ns
+                        .with_route_iface(remote_endpoint.addr, bound_endpoint.addr, |iface| {
+                            socket.connect(iface.context(), remote_endpoint, bound_endpoint)
+                        })
                         .or_else(|e| match e {
                             ConnectError::InvalidState => {
                                 ax_err!(BadState, "socket connect() failed")
@@ -152,21 +427,25 @@ impl TcpSocket {
                 self.peer_addr.get().write(remote_endpoint);
                 self.handle.get().write(Some(handle));
             }
+            self.apply_options_to(handle);
+            self.ns.tcp_stats.register(local_endpoint, remote_endpoint);
             Ok(())
         })
         .unwrap_or_else(|_| ax_err!(AlreadyExists, "socket connect() failed: already connected"))?; // EISCONN
 
         // Here our state must be `CONNECTING`, and only one thread can run here.
+        self.error.lock().take();
         if self.is_nonblocking() {
             Err(AxError::WouldBlock)
         } else {
-            self.block_on(|| {
+            self.block_on(timeout, || {
                 let PollState { writable, .. } = self.poll_connect()?;
                 if !writable {
                     Err(AxError::WouldBlock)
                 } else if self.get_state() == STATE_CONNECTED {
                     Ok(())
                 } else {
+                    self.error.lock().take(); // reported by `connect` itself
                     ax_err!(ConnectionRefused, "socket connect() failed")
                 }
             })
@@ -183,7 +462,7 @@ impl TcpSocket {
         self.update_state(STATE_CLOSED, STATE_CLOSED, || {
             // TODO: check addr is available
             if local_addr.port() == 0 {
-                local_addr.set_port(get_ephemeral_port()?);
+                local_addr.set_port(get_ephemeral_port(&self.ns)?);
             }
             // SAFETY: no other threads can read or write `self.local_addr` as we
             // have changed the state to `BUSY`.
@@ -209,7 +488,12 @@ impl TcpSocket {
             unsafe {
                 (*self.local_addr.get()).port = bound_endpoint.port;
             }
-            LISTEN_TABLE.listen(bound_endpoint)?;
+            static NEXT_KEY: AtomicUsize = AtomicUsize::new(1);
+            let key = NEXT_KEY.fetch_add(1, Ordering::Relaxed);
+            self.ns
+                .listen_table
+                .listen(bound_endpoint, key, self.reuse_port())?;
+            self.listen_key.store(key, Ordering::Release);
             debug!("TCP socket listening on {}", bound_endpoint);
             Ok(())
         })
@@ -229,10 +513,17 @@ impl TcpSocket {
 
         // SAFETY: `self.local_addr` should be initialized after `bind()`.
         let local_port = unsafe { self.local_addr.get().read().port };
-        self.block_on(|| {
-            let (handle, (local_addr, peer_addr)) = LISTEN_TABLE.accept(local_port)?;
+        let key = self.listen_key.load(Ordering::Acquire);
+        self.block_on(self.recv_timeout(), || {
+            let (handle, (local_addr, peer_addr)) =
+                self.ns
+                    .listen_table
+                    .accept(local_port, key, &self.ns.sockets)?;
             debug!("TCP socket accepted a new connection {}", peer_addr);
-            Ok(TcpSocket::new_connected(handle, local_addr, peer_addr))
+            self.ns.tcp_stats.register(local_addr, peer_addr);
+            let socket = TcpSocket::new_connected(self.ns.clone(), handle, local_addr, peer_addr);
+            socket.inherit_options(self);
+            Ok(socket)
         })
     }
 
@@ -243,12 +534,14 @@ impl TcpSocket {
             // SAFETY: `self.handle` should be initialized in a connected socket, and
             // no other threads can read or write it.
             let handle = unsafe { self.handle.get().read().unwrap() };
-            SOCKET_SET.with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
-                debug!("TCP socket {}: shutting down", handle);
-                socket.close();
-            });
+            self.ns
+                .sockets
+                .with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
+                    debug!("TCP socket {}: shutting down", handle);
+                    socket.close();
+                });
             unsafe { self.local_addr.get().write(UNSPECIFIED_ENDPOINT) }; // clear bound address
-            SOCKET_SET.poll_interfaces();
+            poll_interfaces();
             Ok(())
         })
         .unwrap_or(Ok(()))?;
@@ -259,8 +552,12 @@ impl TcpSocket {
             // and no other threads can read or write it.
             let local_port = unsafe { self.local_addr.get().read().port };
             unsafe { self.local_addr.get().write(UNSPECIFIED_ENDPOINT) }; // clear bound address
-            LISTEN_TABLE.unlisten(local_port);
-            SOCKET_SET.poll_interfaces();
+            self.ns.listen_table.unlisten(
+                local_port,
+                self.listen_key.load(Ordering::Acquire),
+                &self.ns.sockets,
+            );
+            poll_interfaces();
             Ok(())
         })
         .unwrap_or(Ok(()))?;
@@ -279,27 +576,30 @@ impl TcpSocket {
 
         // SAFETY: `self.handle` should be initialized in a connected socket.
         let handle = unsafe { self.handle.get().read().unwrap() };
-        self.block_on(|| {
-            SOCKET_SET.with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
-                if !socket.is_active() {
-                    // not open
-                    ax_err!(ConnectionRefused, "socket recv() failed")
-                } else if !socket.may_recv() {
-                    // connection closed
-                    Ok(0)
-                } else if socket.recv_queue() > 0 {
-                    // data available
-                    // TODO: use socket.recv(|buf| {...})
-                    let len = socket
-                        .recv_slice(buf)
-                        .map_err(|_| ax_err_type!(BadState, "socket recv() failed"))?;
-                    Ok(len)
-                } else {
-                    // no more data
-                    Err(AxError::WouldBlock)
-                }
-            })
+        self.block_on(self.recv_timeout(), || {
+            self.ns
+                .sockets
+                .with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
+                    if !socket.is_active() {
+                        // reset by the peer, or aborted by keep-alive
+                        ax_err!(ConnectionReset, "socket recv() failed")
+                    } else if !socket.may_recv() {
+                        // connection closed
+                        Ok(0)
+                    } else if socket.recv_queue() > 0 {
+                        // data available
+                        // TODO: use socket.recv(|buf| {...})
+                        let len = socket
+                            .recv_slice(buf)
+                            .map_err(|_| ax_err_type!(BadState, "socket recv() failed"))?;
+                        Ok(len)
+                    } else {
+                        // no more data
+                        Err(AxError::WouldBlock)
+                    }
+                })
         })
+        .inspect(|&len| axtask::account_net(len, 0))
     }
 
     /// Transmits data in the given buffer.
@@ -312,24 +612,56 @@ impl TcpSocket {
 
         // SAFETY: `self.handle` should be initialized in a connected socket.
         let handle = unsafe { self.handle.get().read().unwrap() };
-        self.block_on(|| {
-            SOCKET_SET.with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
-                if !socket.is_active() || !socket.may_send() {
-                    // closed by remote
-                    ax_err!(ConnectionReset, "socket send() failed")
-                } else if socket.can_send() {
-                    // connected, and the tx buffer is not full
-                    // TODO: use socket.send(|buf| {...})
-                    let len = socket
-                        .send_slice(buf)
-                        .map_err(|_| ax_err_type!(BadState, "socket send() failed"))?;
-                    Ok(len)
-                } else {
-                    // tx buffer is full
-                    Err(AxError::WouldBlock)
-                }
-            })
+        self.block_on(self.send_timeout(), || {
+            self.ns
+                .sockets
+                .with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
+                    if !socket.is_active() || !socket.may_send() {
+                        // closed by remote
+                        ax_err!(ConnectionReset, "socket send() failed")
+                    } else if socket.can_send() {
+                        // connected, and the tx buffer is not full
+                        let mut pacing = self.pacing.lock();
+                        let allowed = pacing.available().min(buf.len() as u64) as usize;
+                        if allowed == 0 && !buf.is_empty() {
+                            // over the pacing rate
+                            return Err(AxError::WouldBlock);
+                        }
+                        // TODO: use socket.send(|buf| {...})
+                        let len = socket
+                            .send_slice(&buf[..allowed])
+                            .map_err(|_| ax_err_type!(BadState, "socket send() failed"))?;
+                        pacing.consume(len);
+                        Ok(len)
+                    } else {
+                        // tx buffer is full
+                        Err(AxError::WouldBlock)
+                    }
+                })
         })
+        .inspect(|&len| axtask::account_net(0, len))
+    }
+
+    /// Returns the statistics of the connection (`TCP_INFO`).
+    ///
+    /// Only the state is known if there's no connection.
+    pub fn tcp_info(&self) -> TcpInfo {
+        match self.connected_handle() {
+            Some(handle) => self
+                .ns
+                .sockets
+                .with_socket::<tcp::Socket, _, _>(handle, |socket| {
+                    tcp_info::connection_info(&self.ns, socket)
+                }),
+            None => TcpInfo {
+                state: if self.is_listening() {
+                    TcpState::Listen
+                } else {
+                    TcpState::Close
+                },
+                ..Default::default()
+            },
+        }
     }
 
     /// Whether the socket is readable or writable.
@@ -340,7 +672,8 @@ impl TcpSocket {
             STATE_LISTENING => self.poll_listener(),
             _ => Ok(PollState {
                 readable: false,
-                writable: false,
+                // a failed connection stays writable until the error is taken
+                writable: self.error.lock().is_some(),
             }),
         }
     }
@@ -402,13 +735,61 @@ impl TcpSocket {
         self.get_state() == STATE_LISTENING
     }
 
+    /// Returns the socket handle if the connection has been set up.
+    fn connected_handle(&self) -> Option<SocketHandle> {
+        match self.get_state() {
+            // SAFETY: `self.handle` should be initialized in these states.
+            STATE_CONNECTING | STATE_CONNECTED => unsafe { self.handle.get().read() },
+            _ => None,
+        }
+    }
+
+    /// Pushes the options (Nagle, keep-alive) down to the smoltcp socket, if
+    /// it has been created.
+    ///
+    /// The keep-alive timeout aborts the connection when nothing has been
+    /// received from the peer for that long, despite the probes.
+    fn apply_socket_options(&self) {
+        if let Some(handle) = self.connected_handle() {
+            self.apply_options_to(handle);
+        }
+    }
+
+    /// Copies the options an accepted socket inherits (Nagle, keep-alive)
+    /// from the listening socket `listener`, as on Linux.
+    fn inherit_options(&self, listener: &Self) {
+        self.nodelay.store(listener.nodelay(), Ordering::Release);
+        self.keepalive
+            .store(listener.keepalive(), Ordering::Release);
+        *self.keepalive_params.lock() = *listener.keepalive_params.lock();
+        self.apply_socket_options();
+    }
+
+    fn apply_options_to(&self, handle: SocketHandle) {
+        let nagle = !self.nodelay();
+        let params = *self.keepalive_params.lock();
+        let (interval, timeout) = if self.keepalive() {
+            let timeout = params.idle + params.interval * params.count;
+            (Some(params.interval.into()), Some(timeout.into()))
+        } else {
+            (None, None)
+        };
+        self.ns
+            .sockets
+            .with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
+                socket.set_nagle_enabled(nagle);
+                socket.set_keep_alive(interval);
+                socket.set_timeout(timeout);
+            });
+    }
+
     fn bound_endpoint(&self) -> AxResult<IpListenEndpoint> {
         // SAFETY: no other threads can read or write `self.local_addr`.
         let local_addr = unsafe { self.local_addr.get().read() };
         let port = if local_addr.port != 0 {
             local_addr.port
         } else {
-            get_ephemeral_port()?
+            get_ephemeral_port(&self.ns)?
         };
         assert_ne!(port, 0);
         let addr = if !is_unspecified(local_addr.addr) {
@@ -422,8 +803,10 @@ impl TcpSocket {
     fn poll_connect(&self) -> AxResult<PollState> {
         // SAFETY: `self.handle` should be initialized above.
         let handle = unsafe { self.handle.get().read().unwrap() };
-        let writable =
-            SOCKET_SET.with_socket::<tcp::Socket, _, _>(handle, |socket| match socket.state() {
+        let writable = self
+            .ns
+            .sockets
+            .with_socket::<tcp::Socket, _, _>(handle, |socket| match socket.state() {
                 State::SynSent => false, // wait for connection
                 State::Established => {
                     self.set_state(STATE_CONNECTED); // connected
@@ -436,9 +819,13 @@ impl TcpSocket {
                 }
                 _ => {
                     unsafe {
+                        self.ns
+                            .tcp_stats
+                            .unregister(self.local_addr.get().read(), self.peer_addr.get().read());
                         self.local_addr.get().write(UNSPECIFIED_ENDPOINT);
                         self.peer_addr.get().write(UNSPECIFIED_ENDPOINT);
                     }
+                    *self.error.lock() = Some(AxError::ConnectionRefused);
                     self.set_state(STATE_CLOSED); // connection failed
                     true
                 }
@@ -452,19 +839,25 @@ impl TcpSocket {
     fn poll_stream(&self) -> AxResult<PollState> {
         // SAFETY: `self.handle` should be initialized in a connected socket.
         let handle = unsafe { self.handle.get().read().unwrap() };
-        SOCKET_SET.with_socket::<tcp::Socket, _, _>(handle, |socket| {
-            Ok(PollState {
-                readable: !socket.may_recv() || socket.can_recv(),
-                writable: !socket.may_send() || socket.can_send(),
+        self.ns
+            .sockets
+            .with_socket::<tcp::Socket, _, _>(handle, |socket| {
+                Ok(PollState {
+                    readable: !socket.may_recv() || socket.can_recv(),
+                    writable: !socket.may_send() || socket.can_send(),
+                })
             })
-        })
     }
 
     fn poll_listener(&self) -> AxResult<PollState> {
         // SAFETY: `self.local_addr` should be initialized in a listening socket.
         let local_addr = unsafe { self.local_addr.get().read() };
         Ok(PollState {
-            readable: LISTEN_TABLE.can_accept(local_addr.port)?,
+            readable: self.ns.listen_table.can_accept(
+                local_addr.port,
+                self.listen_key.load(Ordering::Acquire),
+                &self.ns.sockets,
+            )?,
             writable: false,
         })
     }
@@ -473,22 +866,30 @@ impl TcpSocket {
     ///
     /// If the socket is non-blocking, it calls the function once and returns
     /// immediately. Otherwise, it may call the function multiple times if it
-    /// returns [`Err(WouldBlock)`](AxError::WouldBlock).
-    fn block_on<F, T>(&self, mut f: F) -> AxResult<T>
+    /// returns [`Err(WouldBlock)`](AxError::WouldBlock), until the optional
+    /// `timeout` expires.
+    fn block_on<F, T>(&self, timeout: Option<Duration>, f: F) -> AxResult<T>
     where
         F: FnMut() -> AxResult<T>,
     {
-        if self.is_nonblocking() {
-            f()
-        } else {
-            loop {
-                SOCKET_SET.poll_interfaces();
-                match f() {
-                    Ok(t) => return Ok(t),
-                    Err(AxError::WouldBlock) => axtask::yield_now(),
-                    Err(e) => return Err(e),
-                }
-            }
+        block_on_until(self.is_nonblocking(), timeout, f)
+    }
+}
+
+/// The keep-alive parameters of a [`TcpSocket`].
+#[derive(Clone, Copy)]
+struct KeepAliveParams {
+    idle: Duration,
+    interval: Duration,
+    count: u32,
+}
+
+impl Default for KeepAliveParams {
+    fn default() -> Self {
+        Self {
+            idle: TCP_KEEPALIVE_IDLE,
+            interval: TCP_KEEPALIVE_INTERVAL,
+            count: TCP_KEEPALIVE_COUNT,
         }
     }
 }
@@ -498,12 +899,17 @@ impl Drop for TcpSocket {
         self.shutdown().ok();
         // Safe because we have mut reference to `self`.
         if let Some(handle) = unsafe { self.handle.get().read() } {
-            SOCKET_SET.remove(handle);
+            unsafe {
+                self.ns
+                    .tcp_stats
+                    .unregister(self.local_addr.get().read(), self.peer_addr.get().read());
+            }
+            self.ns.sockets.remove(handle);
         }
     }
 }
 
-fn get_ephemeral_port() -> AxResult<u16> {
+fn get_ephemeral_port(ns: &NetNamespace) -> AxResult<u16> {
     const PORT_START: u16 = 0xc000;
     const PORT_END: u16 = 0xffff;
     static CURR: Mutex<u16> = Mutex::new(PORT_START);
@@ -518,7 +924,7 @@ fn get_ephemeral_port() -> AxResult<u16> {
         } else {
             *curr += 1;
         }
-        if LISTEN_TABLE.can_listen(port) {
+        if ns.listen_table.can_listen(port) {
             return Ok(port);
         }
         tries += 1;
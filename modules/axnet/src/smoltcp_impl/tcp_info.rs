@@ -0,0 +1,518 @@
+//! Statistics of TCP connections (`TCP_INFO`) and connection diagnostics.
+//!
+//! smoltcp doesn't report its RTT estimate, congestion window or
+//! retransmissions, so they are estimated from the segments of the
+//! connections seen by the interfaces, as a packet capture would:
+//!
+//! - The RTT is sampled on the ACK of a timed segment, one at a time, and
+//!   smoothed as in RFC 6298. Retransmitted segments are not timed (Karn's
+//!   algorithm).
+//! - A segment is a retransmission if it starts before the highest sequence
+//!   number sent.
+//! - smoltcp has no congestion control, so the congestion window is the
+//!   window advertised by the peer, in segments.
+//!
+//! A connection is tracked from the time it's connecting or accepted, until
+//! its socket is dropped.
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+use core::net::SocketAddr;
+use core::time::Duration;
+
+use axhal::time::monotonic_time;
+use smoltcp::iface::SocketSet;
+use smoltcp::socket::AnySocket;
+use smoltcp::socket::tcp::{self, State};
+use smoltcp::wire::{
+    EthernetFrame, IpAddress, IpEndpoint, IpProtocol, Ipv4Packet, TcpOption, TcpPacket,
+    TcpSeqNumber,
+};
+use spin::Mutex;
+
+use super::addr::into_core_sockaddr;
+use super::netns::{NetNamespace, current_net_namespace};
+
+/// The default MSS of IPv4 (RFC 1122), if the peer doesn't advertise one.
+const DEFAULT_MSS: u32 = 536;
+/// The length of the IPv4 and TCP headers without options.
+const TCP_IPV4_HEADER_LEN: usize = 40;
+
+/// The state of a TCP connection, numbered as in Linux.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TcpState {
+    /// The connection is established.
+    Established = 1,
+    /// A SYN has been sent, waiting for the SYN-ACK.
+    SynSent,
+    /// A SYN has been received, waiting for the ACK of the SYN-ACK.
+    SynRecv,
+    /// The local end has closed, waiting for the ACK of its FIN.
+    FinWait1,
+    /// The local end has closed and its FIN is acknowledged.
+    FinWait2,
+    /// Both ends have closed, waiting for the late segments.
+    TimeWait,
+    /// There's no connection.
+    #[default]
+    Close,
+    /// The peer has closed, waiting for the local end to close.
+    CloseWait,
+    /// Both ends have closed, waiting for the ACK of the local FIN.
+    LastAck,
+    /// Waiting for connections.
+    Listen,
+    /// Both ends are closing simultaneously.
+    Closing,
+}
+
+impl From<State> for TcpState {
+    fn from(state: State) -> Self {
+        match state {
+            State::Closed => Self::Close,
+            State::Listen => Self::Listen,
+            State::SynSent => Self::SynSent,
+            State::SynReceived => Self::SynRecv,
+            State::Established => Self::Established,
+            State::FinWait1 => Self::FinWait1,
+            State::FinWait2 => Self::FinWait2,
+            State::CloseWait => Self::CloseWait,
+            State::Closing => Self::Closing,
+            State::LastAck => Self::LastAck,
+            State::TimeWait => Self::TimeWait,
+        }
+    }
+}
+
+/// Statistics of a TCP connection, see the [module docs](self).
+///
+/// The durations since the last events are zero if they haven't happened.
+#[derive(Debug, Clone, Default)]
+pub struct TcpInfo {
+    /// The state of the connection.
+    pub state: TcpState,
+    /// The number of retransmissions since the last ACK acknowledging new
+    /// data.
+    pub retransmits: u8,
+    /// The number of retransmitted segments.
+    pub total_retrans: u32,
+    /// The window scale shift of the peer, 0 without window scaling.
+    pub snd_wscale: u8,
+    /// The local window scale shift, 0 without window scaling.
+    pub rcv_wscale: u8,
+    /// The maximum segment size of the peer.
+    pub snd_mss: u32,
+    /// The local maximum segment size.
+    pub rcv_mss: u32,
+    /// The number of segments sent but not acknowledged yet.
+    pub unacked: u32,
+    /// The MTU of the path to the peer.
+    pub pmtu: u32,
+    /// The smoothed RTT, zero if not sampled yet.
+    pub rtt: Duration,
+    /// The variation of the RTT.
+    pub rttvar: Duration,
+    /// The minimum RTT sampled, zero if not sampled yet.
+    pub min_rtt: Duration,
+    /// The retransmission timeout computed from the RTT as in RFC 6298, zero
+    /// if not sampled yet.
+    pub rto: Duration,
+    /// The congestion window, in segments.
+    pub snd_cwnd: u32,
+    /// The window advertised by the peer, in bytes.
+    pub snd_wnd: u32,
+    /// The number of bytes sent, retransmissions included.
+    pub bytes_sent: u64,
+    /// The number of bytes retransmitted.
+    pub bytes_retrans: u64,
+    /// The number of bytes acknowledged by the peer.
+    pub bytes_acked: u64,
+    /// The number of bytes received from the peer.
+    pub bytes_received: u64,
+    /// The number of segments sent.
+    pub segs_out: u32,
+    /// The number of segments received.
+    pub segs_in: u32,
+    /// The number of segments with data sent.
+    pub data_segs_out: u32,
+    /// The number of segments with data received.
+    pub data_segs_in: u32,
+    /// The time since data was last sent.
+    pub last_data_sent: Duration,
+    /// The time since data was last received.
+    pub last_data_recv: Duration,
+    /// The time since an ACK was last received.
+    pub last_ack_recv: Duration,
+    /// The number of bytes in the send buffer.
+    pub send_queue: usize,
+    /// The number of bytes in the send buffer not sent yet.
+    pub notsent_bytes: usize,
+    /// The number of bytes in the receive buffer.
+    pub recv_queue: usize,
+}
+
+/// A TCP connection of the current network namespace, returned by
+/// [`tcp_connections`].
+#[derive(Debug, Clone)]
+pub struct TcpConnection {
+    /// The local address and port.
+    pub local_addr: SocketAddr,
+    /// The address and port of the peer.
+    pub peer_addr: SocketAddr,
+    /// The statistics of the connection.
+    pub info: TcpInfo,
+}
+
+/// What is tracked of a connection.
+#[derive(Default)]
+struct ConnStats {
+    snd_una: Option<TcpSeqNumber>,
+    snd_nxt: Option<TcpSeqNumber>,
+    rcv_nxt: Option<TcpSeqNumber>,
+    /// The end of the timed segment, and when it was sent.
+    timed: Option<(TcpSeqNumber, Duration)>,
+    srtt: Option<Duration>,
+    rttvar: Duration,
+    min_rtt: Option<Duration>,
+    snd_mss: Option<u16>,
+    rcv_mss: Option<u16>,
+    snd_wscale: Option<u8>,
+    rcv_wscale: Option<u8>,
+    snd_wnd: u32,
+    retransmits: u8,
+    total_retrans: u32,
+    bytes_sent: u64,
+    bytes_retrans: u64,
+    bytes_acked: u64,
+    bytes_received: u64,
+    segs_out: u32,
+    segs_in: u32,
+    data_segs_out: u32,
+    data_segs_in: u32,
+    last_data_sent: Option<Duration>,
+    last_data_recv: Option<Duration>,
+    last_ack_recv: Option<Duration>,
+}
+
+/// Returns the MSS and window scale options of a SYN.
+fn syn_options(tcp_packet: &TcpPacket<&[u8]>) -> (Option<u16>, Option<u8>) {
+    let (mut mss, mut wscale) = (None, None);
+    let mut options = tcp_packet.options();
+    while !options.is_empty() {
+        let Ok((rest, option)) = TcpOption::parse(options) else {
+            break;
+        };
+        match option {
+            TcpOption::EndOfList => break,
+            TcpOption::MaxSegmentSize(value) => mss = Some(value),
+            TcpOption::WindowScale(value) => wscale = Some(value),
+            _ => {}
+        }
+        options = rest;
+    }
+    (mss, wscale)
+}
+
+impl ConnStats {
+    fn on_outgoing(&mut self, tcp_packet: &TcpPacket<&[u8]>, now: Duration) {
+        self.segs_out += 1;
+        let seq = tcp_packet.seq_number();
+        if tcp_packet.syn() {
+            (self.rcv_mss, self.rcv_wscale) = syn_options(tcp_packet);
+            // the SYN isn't counted in the acknowledged bytes.
+            self.snd_una.get_or_insert(seq + 1);
+        }
+        let seg_len = tcp_packet.segment_len();
+        if seg_len == 0 {
+            return;
+        }
+        let payload_len = tcp_packet.payload().len() as u64;
+        if payload_len > 0 {
+            self.data_segs_out += 1;
+            self.last_data_sent = Some(now);
+        }
+        self.bytes_sent += payload_len;
+
+        let end = seq + seg_len;
+        match self.snd_nxt {
+            Some(snd_nxt) if seq < snd_nxt => {
+                self.retransmits = self.retransmits.saturating_add(1);
+                self.total_retrans += 1;
+                self.bytes_retrans += payload_len;
+                // Karn's algorithm: the ACK may be of either transmission.
+                self.timed = None;
+            }
+            _ => {
+                if self.timed.is_none() {
+                    self.timed = Some((end, now));
+                }
+            }
+        }
+        if self.snd_nxt.is_none_or(|snd_nxt| end > snd_nxt) {
+            self.snd_nxt = Some(end);
+        }
+        self.snd_una.get_or_insert(seq);
+    }
+
+    fn on_incoming(&mut self, tcp_packet: &TcpPacket<&[u8]>, now: Duration) {
+        self.segs_in += 1;
+        let mut seq = tcp_packet.seq_number();
+        if tcp_packet.syn() {
+            (self.snd_mss, self.snd_wscale) = syn_options(tcp_packet);
+            seq = seq + 1;
+            self.rcv_nxt.get_or_insert(seq);
+        }
+        let payload_len = tcp_packet.payload().len();
+        if payload_len > 0 {
+            self.data_segs_in += 1;
+            self.last_data_recv = Some(now);
+            // only the new bytes are counted.
+            let end = seq + payload_len;
+            let rcv_nxt = *self.rcv_nxt.get_or_insert(seq);
+            if end > rcv_nxt {
+                let start = if seq > rcv_nxt { seq } else { rcv_nxt };
+                self.bytes_received += (end - start) as u64;
+                self.rcv_nxt = Some(end);
+            }
+        }
+
+        if !tcp_packet.ack() {
+            return;
+        }
+        self.last_ack_recv = Some(now);
+        let shift = match (self.snd_wscale, self.rcv_wscale) {
+            (Some(shift), Some(_)) if !tcp_packet.syn() => shift.min(14),
+            _ => 0,
+        };
+        self.snd_wnd = (tcp_packet.window_len() as u32) << shift;
+        let ack = tcp_packet.ack_number();
+        if let Some(snd_una) = self.snd_una {
+            if ack > snd_una && self.snd_nxt.is_some_and(|snd_nxt| ack <= snd_nxt) {
+                self.bytes_acked += (ack - snd_una) as u64;
+                self.snd_una = Some(ack);
+                self.retransmits = 0;
+            }
+        }
+        if let Some((end, sent_at)) = self.timed {
+            if ack >= end {
+                self.sample_rtt(now - sent_at);
+                self.timed = None;
+            }
+        }
+    }
+
+    /// Updates the RTT estimate as in RFC 6298.
+    fn sample_rtt(&mut self, rtt: Duration) {
+        match self.srtt {
+            None => {
+                self.srtt = Some(rtt);
+                self.rttvar = rtt / 2;
+            }
+            Some(srtt) => {
+                self.rttvar = (self.rttvar * 3 + srtt.abs_diff(rtt)) / 4;
+                self.srtt = Some((srtt * 7 + rtt) / 8);
+            }
+        }
+        self.min_rtt = Some(self.min_rtt.map_or(rtt, |min_rtt| min_rtt.min(rtt)));
+    }
+
+    fn fill(&self, info: &mut TcpInfo, now: Duration) {
+        let since = |time: Option<Duration>| time.map_or(Duration::ZERO, |time| now - time);
+        let snd_mss = self.snd_mss.map_or(info.snd_mss, u32::from).max(1);
+        let in_flight = match (self.snd_una, self.snd_nxt) {
+            (Some(snd_una), Some(snd_nxt)) if snd_nxt > snd_una => snd_nxt - snd_una,
+            _ => 0,
+        };
+        info.retransmits = self.retransmits;
+        info.total_retrans = self.total_retrans;
+        if let (Some(snd_wscale), Some(rcv_wscale)) = (self.snd_wscale, self.rcv_wscale) {
+            info.snd_wscale = snd_wscale;
+            info.rcv_wscale = rcv_wscale;
+        }
+        info.snd_mss = snd_mss;
+        info.rcv_mss = self.rcv_mss.map_or(info.rcv_mss, u32::from);
+        info.unacked = in_flight.div_ceil(snd_mss as usize) as u32;
+        info.rtt = self.srtt.unwrap_or_default();
+        info.rttvar = self.rttvar;
+        info.min_rtt = self.min_rtt.unwrap_or_default();
+        if let Some(srtt) = self.srtt {
+            info.rto = srtt + self.rttvar * 4;
+        }
+        info.snd_cwnd = (self.snd_wnd / snd_mss).max(1);
+        info.snd_wnd = self.snd_wnd;
+        info.bytes_sent = self.bytes_sent;
+        info.bytes_retrans = self.bytes_retrans;
+        info.bytes_acked = self.bytes_acked;
+        info.bytes_received = self.bytes_received;
+        info.segs_out = self.segs_out;
+        info.segs_in = self.segs_in;
+        info.data_segs_out = self.data_segs_out;
+        info.data_segs_in = self.data_segs_in;
+        info.last_data_sent = since(self.last_data_sent);
+        info.last_data_recv = since(self.last_data_recv);
+        info.last_ack_recv = since(self.last_ack_recv);
+        info.notsent_bytes = info.send_queue.saturating_sub(in_flight);
+    }
+}
+
+/// The connections tracked in a network namespace, by their local and remote
+/// endpoints.
+pub(super) struct TcpStatsTable {
+    conns: Mutex<BTreeMap<(IpEndpoint, IpEndpoint), ConnStats>>,
+}
+
+impl TcpStatsTable {
+    pub const fn new() -> Self {
+        Self {
+            conns: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Starts tracking the connection from `local` to `remote`.
+    pub fn register(&self, local: IpEndpoint, remote: IpEndpoint) {
+        self.conns
+            .lock()
+            .insert((local, remote), ConnStats::default());
+    }
+
+    /// Stops tracking the connection from `local` to `remote`.
+    pub fn unregister(&self, local: IpEndpoint, remote: IpEndpoint) {
+        self.conns.lock().remove(&(local, remote));
+    }
+
+    /// Accounts a TCP segment from `src` to `dst`, sent by the namespace if
+    /// `outgoing` is set.
+    pub fn on_segment(
+        &self,
+        src: IpEndpoint,
+        dst: IpEndpoint,
+        tcp_packet: &TcpPacket<&[u8]>,
+        outgoing: bool,
+    ) {
+        let mut conns = self.conns.lock();
+        if conns.is_empty() {
+            return;
+        }
+        let now = monotonic_time();
+        if outgoing {
+            if let Some(stats) = conns.get_mut(&(src, dst)) {
+                stats.on_outgoing(tcp_packet, now);
+            }
+        } else if let Some(stats) = conns.get_mut(&(dst, src)) {
+            stats.on_incoming(tcp_packet, now);
+        }
+    }
+
+    /// Accounts the Ethernet frame `buf` sent by the namespace, if it has a
+    /// TCP segment.
+    pub fn on_outgoing_frame(&self, buf: &[u8]) {
+        if self.conns.lock().is_empty() {
+            return;
+        }
+        let Ok(ether_frame) = EthernetFrame::new_checked(buf) else {
+            return;
+        };
+        let Ok(ipv4_packet) = Ipv4Packet::new_checked(ether_frame.payload()) else {
+            return;
+        };
+        if ipv4_packet.next_header() != IpProtocol::Tcp {
+            return;
+        }
+        if let Ok(tcp_packet) = TcpPacket::new_checked(ipv4_packet.payload()) {
+            let src = (ipv4_packet.src_addr(), tcp_packet.src_port()).into();
+            let dst = (ipv4_packet.dst_addr(), tcp_packet.dst_port()).into();
+            self.on_segment(src, dst, &tcp_packet, true);
+        }
+    }
+}
+
+/// Returns the statistics of the connection of `socket`, a socket of `ns`.
+pub(super) fn connection_info(ns: &NetNamespace, socket: &tcp::Socket) -> TcpInfo {
+    let mut info = TcpInfo {
+        state: socket.state().into(),
+        send_queue: socket.send_queue(),
+        recv_queue: socket.recv_queue(),
+        ..Default::default()
+    };
+    let (Some(local), Some(remote)) = (socket.local_endpoint(), socket.remote_endpoint()) else {
+        return info;
+    };
+    let pmtu = ns.path_mtu(remote.addr);
+    info.pmtu = pmtu as u32;
+    info.snd_mss = pmtu
+        .checked_sub(TCP_IPV4_HEADER_LEN)
+        .map_or(DEFAULT_MSS, |mss| mss as u32);
+    info.rcv_mss = info.snd_mss;
+    if let Some(stats) = ns.tcp_stats.conns.lock().get(&(local, remote)) {
+        stats.fill(&mut info, monotonic_time());
+    }
+    info
+}
+
+/// Returns the TCP connections of the current network namespace, including
+/// the ones not accepted yet and the ones closing after their socket is
+/// dropped.
+///
+/// The listening sockets are not included.
+pub fn tcp_connections() -> Vec<TcpConnection> {
+    let ns = current_net_namespace();
+    let sockets = ns.sockets.0.lock();
+    tcp_sockets(&sockets)
+        .filter_map(|socket| {
+            let local = socket.local_endpoint()?;
+            let remote = socket.remote_endpoint()?;
+            Some(TcpConnection {
+                local_addr: into_core_sockaddr(local),
+                peer_addr: into_core_sockaddr(remote),
+                info: connection_info(&ns, socket),
+            })
+        })
+        .collect()
+}
+
+fn tcp_sockets<'a, 'b>(sockets: &'a SocketSet<'b>) -> impl Iterator<Item = &'a tcp::Socket<'b>> {
+    sockets
+        .iter()
+        .filter_map(|(_, socket)| tcp::Socket::downcast(socket))
+}
+
+/// Returns the TCP connections of the current network namespace in the format
+/// of `/proc/net/tcp` in Linux.
+pub fn proc_net_tcp() -> String {
+    // The addresses are in hex, as the `u32` of their bytes in memory.
+    let hex = |addr: IpAddress| match addr {
+        IpAddress::Ipv4(v4) => u32::from_le_bytes(v4.0),
+    };
+    let mut content = String::from(
+        "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode\n",
+    );
+    let ns = current_net_namespace();
+    let sockets = ns.sockets.0.lock();
+    let conns = tcp_sockets(&sockets)
+        .filter_map(|socket| Some((socket.local_endpoint()?, socket.remote_endpoint()?, socket)));
+    for (i, (local, remote, socket)) in conns.enumerate() {
+        let info = connection_info(&ns, socket);
+        writeln!(
+            content,
+            "{:4}: {:08X}:{:04X} {:08X}:{:04X} {:02X} {:08X}:{:08X} 00:00000000 {:08X} {:5} {:8} 0 1 0000000000000000 {} 0 0 {} -1",
+            i,
+            hex(local.addr),
+            local.port,
+            hex(remote.addr),
+            remote.port,
+            info.state as u8,
+            info.send_queue,
+            info.recv_queue,
+            info.retransmits,
+            0,
+            0,
+            info.rto.as_millis() / 10,
+            info.snd_cwnd,
+        )
+        .unwrap();
+    }
+    content
+}
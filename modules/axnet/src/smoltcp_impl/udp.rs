@@ -1,5 +1,8 @@
-use core::net::SocketAddr;
-use core::sync::atomic::{AtomicBool, Ordering};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::net::{Ipv4Addr, SocketAddr};
+use core::sync::atomic::{AtomicBool, AtomicU8, AtomicU16, Ordering};
+use core::time::Duration;
 
 use axerrno::{AxError, AxResult, ax_err, ax_err_type};
 use axio::PollState;
@@ -11,27 +14,58 @@ use smoltcp::socket::udp::{self, BindError, SendError};
 use smoltcp::wire::{IpEndpoint, IpListenEndpoint};
 
 use super::addr::{UNSPECIFIED_ENDPOINT, from_core_sockaddr, into_core_sockaddr, is_unspecified};
-use super::{SOCKET_SET, SocketSetWrapper};
+use super::bpf::{BpfProgram, SocketFilter};
+use super::multicast::Memberships;
+use super::netns::{NetNamespace, current_net_namespace};
+use super::qdisc::TokenBucket;
+use super::{SocketSetWrapper, block_on_until, poll_interfaces};
+
+/// The maximum number of datagrams sent or received at once with segmentation
+/// offload.
+const MAX_SEGMENTS: usize = 64;
+const UDP_HEADER_LEN: usize = 8;
+/// The length of the IPv4 and UDP headers of a datagram.
+const HEADERS_LEN: usize = 20 + UDP_HEADER_LEN;
 
 /// A UDP socket that provides POSIX-like APIs.
 pub struct UdpSocket {
+    ns: Arc<NetNamespace>,
     handle: SocketHandle,
     local_addr: RwLock<Option<IpEndpoint>>,
     peer_addr: RwLock<Option<IpEndpoint>>,
     nonblock: AtomicBool,
+    reuse_addr: AtomicBool,
+    recv_timeout: RwLock<Option<Duration>>,
+    send_timeout: RwLock<Option<Duration>>,
+    pacing: Mutex<TokenBucket>,
+    multicast_ttl: AtomicU8,
+    /// The size of the segments of a send, or 0 if it's not segmented.
+    segment_size: AtomicU16,
+    memberships: Memberships,
+    filter: SocketFilter,
 }
 
 impl UdpSocket {
-    /// Creates a new UDP socket.
+    /// Creates a new UDP socket in the current network namespace.
     #[allow(clippy::new_without_default)]
     pub fn new() -> Self {
+        let ns = current_net_namespace();
         let socket = SocketSetWrapper::new_udp_socket();
-        let handle = SOCKET_SET.add(socket);
+        let handle = ns.sockets.add(socket);
         Self {
+            ns: ns.clone(),
             handle,
             local_addr: RwLock::new(None),
             peer_addr: RwLock::new(None),
             nonblock: AtomicBool::new(false),
+            reuse_addr: AtomicBool::new(false),
+            recv_timeout: RwLock::new(None),
+            send_timeout: RwLock::new(None),
+            pacing: Mutex::new(TokenBucket::new()),
+            multicast_ttl: AtomicU8::new(1),
+            segment_size: AtomicU16::new(0),
+            memberships: Memberships::new(ns),
+            filter: SocketFilter::new(),
         }
     }
 
@@ -69,6 +103,176 @@ impl UdpSocket {
         self.nonblock.store(nonblocking, Ordering::Release);
     }
 
+    /// Returns whether local addresses can be reused (`SO_REUSEADDR`).
+    #[inline]
+    pub fn reuse_addr(&self) -> bool {
+        self.reuse_addr.load(Ordering::Acquire)
+    }
+
+    /// Allows or disallows reusing local addresses (`SO_REUSEADDR`).
+    ///
+    /// The flag is only recorded for now, as [`bind`](Self::bind) doesn't
+    /// check whether the address is in use: any number of UDP sockets can
+    /// bind the same address, and each datagram goes to the first of them.
+    #[inline]
+    pub fn set_reuse_addr(&self, reuse_addr: bool) {
+        self.reuse_addr.store(reuse_addr, Ordering::Release);
+    }
+
+    /// Returns the timeout of blocking receive operations (`SO_RCVTIMEO`).
+    #[inline]
+    pub fn recv_timeout(&self) -> Option<Duration> {
+        *self.recv_timeout.read()
+    }
+
+    /// Sets the timeout of blocking receive operations (`SO_RCVTIMEO`).
+    ///
+    /// If the timeout expires, receive operations fail with
+    /// [`Err(WouldBlock)`](AxError::WouldBlock). `None` means blocking forever.
+    #[inline]
+    pub fn set_recv_timeout(&self, timeout: Option<Duration>) {
+        *self.recv_timeout.write() = timeout;
+    }
+
+    /// Returns the timeout of blocking send operations (`SO_SNDTIMEO`).
+    #[inline]
+    pub fn send_timeout(&self) -> Option<Duration> {
+        *self.send_timeout.read()
+    }
+
+    /// Sets the timeout of blocking send operations (`SO_SNDTIMEO`).
+    ///
+    /// If the timeout expires, send operations fail with
+    /// [`Err(WouldBlock)`](AxError::WouldBlock). `None` means blocking forever.
+    #[inline]
+    pub fn set_send_timeout(&self, timeout: Option<Duration>) {
+        *self.send_timeout.write() = timeout;
+    }
+
+    /// Returns the maximum sending rate in bytes per second
+    /// (`SO_MAX_PACING_RATE`), or `None` if unlimited.
+    pub fn max_pacing_rate(&self) -> Option<u64> {
+        self.pacing.lock().rate()
+    }
+
+    /// Limits the sending rate to `rate` bytes per second
+    /// (`SO_MAX_PACING_RATE`), or removes the limit if `None`.
+    ///
+    /// Send operations wait until the datagram is within the limit.
+    pub fn set_max_pacing_rate(&self, rate: Option<u64>) {
+        self.pacing.lock().set_rate(rate);
+    }
+
+    /// Returns the TTL of the multicast datagrams sent (`IP_MULTICAST_TTL`).
+    #[inline]
+    pub fn multicast_ttl_v4(&self) -> u8 {
+        self.multicast_ttl.load(Ordering::Acquire)
+    }
+
+    /// Sets the TTL of the multicast datagrams sent (`IP_MULTICAST_TTL`). It's
+    /// 1 by default, keeping them in the local network.
+    ///
+    /// With a TTL of 0 they don't leave the host. There's no multicast
+    /// loopback, so they are dropped, as Linux does with `IP_MULTICAST_LOOP`
+    /// disabled.
+    pub fn set_multicast_ttl_v4(&self, ttl: u8) {
+        self.multicast_ttl.store(ttl, Ordering::Release);
+    }
+
+    /// Returns the size of the datagrams a send is split into (`UDP_SEGMENT`),
+    /// or `None` if sends are not split.
+    pub fn segment_size(&self) -> Option<u16> {
+        match self.segment_size.load(Ordering::Acquire) {
+            0 => None,
+            size => Some(size),
+        }
+    }
+
+    /// Splits the data of each send into datagrams of `size` bytes, the last
+    /// one possibly shorter (`UDP_SEGMENT`), or stops splitting it if `None`.
+    ///
+    /// It's generic segmentation offload: up to 64 datagrams are sent with a
+    /// single call. The NIC drivers can't segment the data themselves, so the
+    /// stack does, and the datagrams must fit in the MTU of the interface.
+    pub fn set_segment_size(&self, size: Option<u16>) -> AxResult {
+        if size == Some(0) {
+            return ax_err!(InvalidInput, "segment size must be positive");
+        }
+        self.segment_size
+            .store(size.unwrap_or_default(), Ordering::Release);
+        Ok(())
+    }
+
+    /// Joins the multicast group `multiaddr` (`IP_ADD_MEMBERSHIP`) on the
+    /// interface with the address `interface`, or on the one chosen by the
+    /// routing table if it's unspecified.
+    ///
+    /// The socket receives the datagrams sent to the group and its port, and
+    /// leaves the group when it's dropped.
+    pub fn join_multicast_v4(&self, multiaddr: Ipv4Addr, interface: Ipv4Addr) -> AxResult {
+        self.memberships.join(multiaddr, interface)?;
+        debug!("UDP socket {}: joined {}", self.handle, multiaddr);
+        Ok(())
+    }
+
+    /// Leaves the multicast group `multiaddr` (`IP_DROP_MEMBERSHIP`) on the
+    /// interface with the address `interface`, or on any interface if it's
+    /// unspecified.
+    pub fn leave_multicast_v4(&self, multiaddr: Ipv4Addr, interface: Ipv4Addr) -> AxResult {
+        self.memberships.leave(multiaddr, interface)?;
+        debug!("UDP socket {}: left {}", self.handle, multiaddr);
+        Ok(())
+    }
+
+    /// Attaches a BPF filter (`SO_ATTACH_FILTER`) to the socket, replacing the
+    /// one attached before.
+    ///
+    /// The filter drops or truncates the datagrams received. It sees them with
+    /// a UDP header, whose checksum is zero.
+    pub fn attach_filter(&self, program: BpfProgram) {
+        self.filter.attach(program);
+    }
+
+    /// Detaches the BPF filter of the socket (`SO_DETACH_FILTER`).
+    pub fn detach_filter(&self) -> AxResult {
+        self.filter.detach()
+    }
+
+    /// Returns the capacity of the receive buffer (`SO_RCVBUF`).
+    #[inline]
+    pub fn recv_buffer_size(&self) -> usize {
+        super::UDP_RX_BUF_LEN
+    }
+
+    /// Returns the capacity of the send buffer (`SO_SNDBUF`).
+    #[inline]
+    pub fn send_buffer_size(&self) -> usize {
+        super::UDP_TX_BUF_LEN
+    }
+
+    /// Returns the size of the next datagram to be received, or 0 if there's
+    /// none (`FIONREAD`).
+    pub fn recv_queue_len(&self) -> usize {
+        if self.local_addr.read().is_none() {
+            return 0;
+        }
+        let local_port = self.local_port();
+        self.ns
+            .sockets
+            .with_socket_mut::<udp::Socket, _, _>(self.handle, |socket| {
+                while let Ok((payload, meta)) = socket.peek() {
+                    match self.filter_datagram(payload, meta.endpoint.port, local_port) {
+                        Some(len) => return len,
+                        // rejected by the filter
+                        None => {
+                            socket.recv().ok();
+                        }
+                    }
+                }
+                0
+            })
+    }
+
     /// Binds an unbound socket to the given address and port.
     ///
     /// It's must be called before [`send_to`](Self::send_to) and
@@ -88,12 +292,14 @@ impl UdpSocket {
             addr: (!is_unspecified(local_endpoint.addr)).then_some(local_endpoint.addr),
             port: local_endpoint.port,
         };
-        SOCKET_SET.with_socket_mut::<udp::Socket, _, _>(self.handle, |socket| {
-            socket.bind(endpoint).or_else(|e| match e {
-                BindError::InvalidState => ax_err!(AlreadyExists, "socket bind() failed"),
-                BindError::Unaddressable => ax_err!(InvalidInput, "socket bind() failed"),
-            })
-        })?;
+        self.ns
+            .sockets
+            .with_socket_mut::<udp::Socket, _, _>(self.handle, |socket| {
+                socket.bind(endpoint).or_else(|e| match e {
+                    BindError::InvalidState => ax_err!(AlreadyExists, "socket bind() failed"),
+                    BindError::Unaddressable => ax_err!(InvalidInput, "socket bind() failed"),
+                })
+            })?;
 
         *self_local_addr = Some(local_endpoint);
         debug!("UDP socket {}: bound on {}", self.handle, endpoint);
@@ -112,18 +318,63 @@ impl UdpSocket {
     /// Receives a single datagram message on the socket. On success, returns
     /// the number of bytes read and the origin.
     pub fn recv_from(&self, buf: &mut [u8]) -> AxResult<(usize, SocketAddr)> {
-        self.recv_impl(|socket| match socket.recv_slice(buf) {
-            Ok((len, meta)) => Ok((len, into_core_sockaddr(meta.endpoint))),
-            Err(_) => ax_err!(BadState, "socket recv_from() failed"),
+        self.recv_impl(|socket, limit| {
+            let len = buf.len().min(limit);
+            match socket.recv_slice(&mut buf[..len]) {
+                Ok((len, meta)) => Ok((len, into_core_sockaddr(meta.endpoint))),
+                Err(_) => ax_err!(BadState, "socket recv_from() failed"),
+            }
         })
+        .inspect(|&(len, _)| axtask::account_net(len, 0))
+    }
+
+    /// Receives several datagrams from the same origin at once (generic receive
+    /// offload), as many as `buf` holds.
+    ///
+    /// The datagrams are all as long as the first one, except the last one,
+    /// which may be shorter. On success, returns the number of bytes read, the
+    /// origin, and the length of the datagrams.
+    pub fn recv_from_gro(&self, buf: &mut [u8]) -> AxResult<(usize, SocketAddr, usize)> {
+        let local_port = self.local_port();
+        self.recv_impl(|socket, limit| {
+            let first_len = buf.len().min(limit);
+            let (mut len, meta) = socket
+                .recv_slice(&mut buf[..first_len])
+                .map_err(|_| ax_err_type!(BadState, "socket recv_from() failed"))?;
+            let segment_size = len;
+            let mut segments = 1;
+            while segments < MAX_SEGMENTS && segment_size > 0 {
+                match socket.peek() {
+                    Ok((payload, next))
+                        if next.endpoint == meta.endpoint
+                            && payload.len() <= segment_size
+                            && len + payload.len() <= buf.len()
+                            && self.filter_datagram(payload, next.endpoint.port, local_port)
+                                == Some(payload.len()) => {}
+                    _ => break,
+                }
+                let (n, _) = socket.recv_slice(&mut buf[len..]).unwrap();
+                len += n;
+                segments += 1;
+                if n < segment_size {
+                    // a shorter datagram ends the batch
+                    break;
+                }
+            }
+            Ok((len, into_core_sockaddr(meta.endpoint), segment_size))
+        })
+        .inspect(|&(len, ..)| axtask::account_net(len, 0))
     }
 
     /// Receives a single datagram message on the socket, without removing it from
     /// the queue. On success, returns the number of bytes read and the origin.
     pub fn peek_from(&self, buf: &mut [u8]) -> AxResult<(usize, SocketAddr)> {
-        self.recv_impl(|socket| match socket.peek_slice(buf) {
-            Ok((len, meta)) => Ok((len, into_core_sockaddr(meta.endpoint))),
-            Err(_) => ax_err!(BadState, "socket recv_from() failed"),
+        self.recv_impl(|socket, limit| {
+            let len = buf.len().min(limit);
+            match socket.peek_slice(&mut buf[..len]) {
+                Ok((len, meta)) => Ok((len, into_core_sockaddr(meta.endpoint))),
+                Err(_) => ax_err!(BadState, "socket recv_from() failed"),
+            }
         })
     }
 
@@ -156,9 +407,10 @@ impl UdpSocket {
     /// to which it is connected. On success, returns the number of bytes read.
     pub fn recv(&self, buf: &mut [u8]) -> AxResult<usize> {
         let remote_endpoint = self.remote_endpoint()?;
-        self.recv_impl(|socket| {
+        self.recv_impl(|socket, limit| {
+            let len = buf.len().min(limit);
             let (len, meta) = socket
-                .recv_slice(buf)
+                .recv_slice(&mut buf[..len])
                 .map_err(|_| ax_err_type!(BadState, "socket recv() failed"))?;
             if !is_unspecified(remote_endpoint.addr) && remote_endpoint.addr != meta.endpoint.addr {
                 return Err(AxError::WouldBlock);
@@ -168,15 +420,18 @@ impl UdpSocket {
             }
             Ok(len)
         })
+        .inspect(|&len| axtask::account_net(len, 0))
     }
 
     /// Close the socket.
     pub fn shutdown(&self) -> AxResult {
-        SOCKET_SET.with_socket_mut::<udp::Socket, _, _>(self.handle, |socket| {
-            debug!("UDP socket {}: shutting down", self.handle);
-            socket.close();
-        });
-        SOCKET_SET.poll_interfaces();
+        self.ns
+            .sockets
+            .with_socket_mut::<udp::Socket, _, _>(self.handle, |socket| {
+                debug!("UDP socket {}: shutting down", self.handle);
+                socket.close();
+            });
+        poll_interfaces();
         Ok(())
     }
 
@@ -188,12 +443,14 @@ impl UdpSocket {
                 writable: false,
             });
         }
-        SOCKET_SET.with_socket_mut::<udp::Socket, _, _>(self.handle, |socket| {
-            Ok(PollState {
-                readable: socket.can_recv(),
-                writable: socket.can_send(),
+        self.ns
+            .sockets
+            .with_socket_mut::<udp::Socket, _, _>(self.handle, |socket| {
+                Ok(PollState {
+                    readable: socket.can_recv(),
+                    writable: socket.can_send(),
+                })
             })
-        })
     }
 }
 
@@ -211,9 +468,70 @@ impl UdpSocket {
             return ax_err!(NotConnected, "socket send() failed");
         }
 
-        self.block_on(|| {
-            SOCKET_SET.with_socket_mut::<udp::Socket, _, _>(self.handle, |socket| {
-                if socket.can_send() {
+        match self.segment_size() {
+            Some(size) if buf.len() > size as usize => {
+                self.send_segments(buf, size as usize, remote_endpoint)
+            }
+            _ => self.block_on(self.send_timeout(), || self.try_send(buf, remote_endpoint)),
+        }
+    }
+
+    /// Sends `buf` as datagrams of `segment_size` bytes.
+    ///
+    /// If it times out, or would block, after some of them are sent, returns
+    /// the number of bytes sent.
+    fn send_segments(
+        &self,
+        buf: &[u8],
+        segment_size: usize,
+        remote_endpoint: IpEndpoint,
+    ) -> AxResult<usize> {
+        if buf.len().div_ceil(segment_size) > MAX_SEGMENTS {
+            return ax_err!(InvalidInput, "too many segments");
+        }
+        if segment_size + HEADERS_LEN > self.ns.path_mtu(remote_endpoint.addr) {
+            return ax_err!(InvalidInput, "segment larger than the MTU");
+        }
+
+        let mut sent = 0;
+        let res = self.block_on(self.send_timeout(), || {
+            for segment in buf[sent..].chunks(segment_size) {
+                sent += self.try_send(segment, remote_endpoint)?;
+            }
+            Ok(())
+        });
+        match res {
+            Ok(()) => Ok(sent),
+            Err(AxError::WouldBlock) if sent > 0 => Ok(sent),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn try_send(&self, buf: &[u8], remote_endpoint: IpEndpoint) -> AxResult<usize> {
+        self.ns
+            .sockets
+            .with_socket_mut::<udp::Socket, _, _>(self.handle, |socket| {
+                // The hop limit applies to all the queued datagrams, so they are
+                // sent before it changes.
+                let hop_limit = remote_endpoint
+                    .addr
+                    .is_multicast()
+                    .then(|| self.multicast_ttl_v4());
+                if hop_limit == Some(0) {
+                    // not to leave the host, see `set_multicast_ttl_v4`
+                    return Ok(buf.len());
+                }
+                if socket.hop_limit() != hop_limit {
+                    if socket.send_queue() > 0 {
+                        return Err(AxError::WouldBlock);
+                    }
+                    socket.set_hop_limit(hop_limit);
+                }
+                let mut pacing = self.pacing.lock();
+                if pacing.available() == 0 {
+                    // over the pacing rate
+                    Err(AxError::WouldBlock)
+                } else if socket.can_send() {
                     socket
                         .send_slice(buf, remote_endpoint)
                         .map_err(|e| match e {
@@ -222,59 +540,82 @@ impl UdpSocket {
                                 ax_err_type!(ConnectionRefused, "socket send() failed")
                             }
                         })?;
+                    pacing.consume(buf.len());
+                    axtask::account_net(0, buf.len());
                     Ok(buf.len())
                 } else {
                     // tx buffer is full
                     Err(AxError::WouldBlock)
                 }
             })
-        })
     }
 
+    /// Runs `op` on the socket once a datagram passing the filter is queued,
+    /// with the number of bytes of it to receive.
     fn recv_impl<F, T>(&self, mut op: F) -> AxResult<T>
     where
-        F: FnMut(&mut udp::Socket) -> AxResult<T>,
+        F: FnMut(&mut udp::Socket, usize) -> AxResult<T>,
     {
         if self.local_addr.read().is_none() {
             return ax_err!(NotConnected, "socket send() failed");
         }
 
-        self.block_on(|| {
-            SOCKET_SET.with_socket_mut::<udp::Socket, _, _>(self.handle, |socket| {
-                if socket.can_recv() {
-                    // data available
-                    op(socket)
-                } else {
+        let local_port = self.local_port();
+        self.block_on(self.recv_timeout(), || {
+            self.ns
+                .sockets
+                .with_socket_mut::<udp::Socket, _, _>(self.handle, |socket| {
+                    while let Ok((payload, meta)) = socket.peek() {
+                        match self.filter_datagram(payload, meta.endpoint.port, local_port) {
+                            // data available
+                            Some(len) => return op(socket, len),
+                            // rejected by the filter
+                            None => {
+                                socket.recv().ok();
+                            }
+                        }
+                    }
                     // no more data
                     Err(AxError::WouldBlock)
-                }
-            })
+                })
         })
     }
 
-    fn block_on<F, T>(&self, mut f: F) -> AxResult<T>
+    fn local_port(&self) -> u16 {
+        self.local_addr.read().map_or(0, |endpoint| endpoint.port)
+    }
+
+    /// Runs the filter on a datagram, and returns the number of bytes of its
+    /// payload to keep, or `None` if it's dropped.
+    fn filter_datagram(&self, payload: &[u8], src_port: u16, dst_port: u16) -> Option<usize> {
+        if !self.filter.is_attached() {
+            return Some(payload.len());
+        }
+        let len = UDP_HEADER_LEN + payload.len();
+        let mut packet = Vec::with_capacity(len);
+        packet.extend_from_slice(&src_port.to_be_bytes());
+        packet.extend_from_slice(&dst_port.to_be_bytes());
+        packet.extend_from_slice(&(len as u16).to_be_bytes());
+        packet.extend_from_slice(&[0, 0]);
+        packet.extend_from_slice(payload);
+        match self.filter.run(&packet) {
+            0 => None,
+            kept => Some(kept.saturating_sub(UDP_HEADER_LEN)),
+        }
+    }
+
+    fn block_on<F, T>(&self, timeout: Option<Duration>, f: F) -> AxResult<T>
     where
         F: FnMut() -> AxResult<T>,
     {
-        if self.is_nonblocking() {
-            f()
-        } else {
-            loop {
-                SOCKET_SET.poll_interfaces();
-                match f() {
-                    Ok(t) => return Ok(t),
-                    Err(AxError::WouldBlock) => axtask::yield_now(),
-                    Err(e) => return Err(e),
-                }
-            }
-        }
+        block_on_until(self.is_nonblocking(), timeout, f)
     }
 }
 
 impl Drop for UdpSocket {
     fn drop(&mut self) {
         self.shutdown().ok();
-        SOCKET_SET.remove(self.handle);
+        self.ns.sockets.remove(self.handle);
     }
 }
 
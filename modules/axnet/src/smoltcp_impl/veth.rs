@@ -0,0 +1,151 @@
+//! Virtual Ethernet (veth) pairs, connecting network namespaces.
+//!
+//! The two ends of a pair are NICs, usually in different namespaces: the
+//! frames sent by one end are received by the other. Each end is configured
+//! like any NIC, e.g. with [`set_interface_addr`](super::set_interface_addr)
+//! run in its namespace. Deleting either end deletes both ([`del_veth`]).
+
+use alloc::collections::VecDeque;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+
+use axerrno::{AxResult, ax_err};
+use smoltcp::wire::EthernetAddress;
+use spin::Mutex;
+
+use super::netns::{NetNamespace, Nic, current_net_namespace};
+use super::{DeviceWrapper, InterfaceWrapper, NicDevice};
+
+/// The maximum number of frames waiting to be received by an end. More are
+/// dropped, as a NIC whose ring is full does.
+const QUEUE_LEN: usize = 1024;
+
+type FrameQueue = Mutex<VecDeque<Vec<u8>>>;
+
+/// An end of a veth pair.
+pub(super) struct VethDevice {
+    rx: Arc<FrameQueue>,
+    /// The receive queue of the other end, gone if it has been dropped along
+    /// with its namespace.
+    peer_rx: Weak<FrameQueue>,
+    /// The namespace of the other end.
+    peer_ns: Weak<NetNamespace>,
+}
+
+impl VethDevice {
+    fn new_pair(ns: &Arc<NetNamespace>, peer_ns: &Arc<NetNamespace>) -> (Self, Self) {
+        let queue0 = Arc::new(Mutex::new(VecDeque::new()));
+        let queue1 = Arc::new(Mutex::new(VecDeque::new()));
+        let end0 = Self {
+            peer_rx: Arc::downgrade(&queue1),
+            rx: queue0,
+            peer_ns: Arc::downgrade(peer_ns),
+        };
+        let end1 = Self {
+            peer_rx: Arc::downgrade(&end0.rx),
+            rx: queue1,
+            peer_ns: Arc::downgrade(ns),
+        };
+        (end0, end1)
+    }
+
+    /// Takes the next frame sent by the other end.
+    pub fn receive(&self) -> Option<Vec<u8>> {
+        self.rx.lock().pop_front()
+    }
+
+    /// Sends `frame` to the other end.
+    pub fn send(&self, frame: Vec<u8>) {
+        let Some(peer_rx) = self.peer_rx.upgrade() else {
+            return;
+        };
+        let mut queue = peer_rx.lock();
+        if queue.len() < QUEUE_LEN {
+            queue.push_back(frame);
+        } else {
+            warn!("veth queue full, frame dropped");
+        }
+    }
+}
+
+/// Returns a random locally administered unicast MAC address for a new end,
+/// as Linux does, which isn't used by any NIC of `namespaces`.
+fn new_ether_addr(namespaces: &[&Arc<NetNamespace>]) -> EthernetAddress {
+    loop {
+        let mut addr = [0; 6];
+        axhal::random::fill_bytes(&mut addr);
+        addr[0] = (addr[0] & !0x01) | 0x02;
+        let addr = EthernetAddress(addr);
+        if namespaces
+            .iter()
+            .all(|ns| ns.nics().iter().all(|nic| nic.ethernet_address() != addr))
+        {
+            return addr;
+        }
+    }
+}
+
+/// Creates a veth pair: the interface `name` in the current namespace, and
+/// its peer `peer_name` in `peer_ns` (`ip link add <name> type veth peer name
+/// <peer_name> netns <peer_ns>`).
+///
+/// Both ends are down until they're given an address. The names must not be
+/// used in their namespaces, otherwise neither end is created.
+pub fn add_veth_pair(name: &str, peer_name: &str, peer_ns: &Arc<NetNamespace>) -> AxResult {
+    let ns = current_net_namespace();
+    ns.check_nic_name(name)?;
+    peer_ns.check_nic_name(peer_name)?;
+    if Arc::ptr_eq(&ns, peer_ns) && name == peer_name {
+        return ax_err!(AlreadyExists, "interface name already used");
+    }
+    let (end, peer_end) = VethDevice::new_pair(&ns, peer_ns);
+    let ether_addr = new_ether_addr(&[&ns, peer_ns]);
+    let mut peer_ether_addr = new_ether_addr(&[&ns, peer_ns]);
+    while peer_ether_addr == ether_addr {
+        peer_ether_addr = new_ether_addr(&[&ns, peer_ns]);
+    }
+    let end = InterfaceWrapper::new(
+        name.into(),
+        DeviceWrapper::new_veth(end, ns.listen_table.clone(), ns.tcp_stats.clone()),
+        ether_addr,
+    );
+    let peer_end = InterfaceWrapper::new(
+        peer_name.into(),
+        DeviceWrapper::new_veth(
+            peer_end,
+            peer_ns.listen_table.clone(),
+            peer_ns.tcp_stats.clone(),
+        ),
+        peer_ether_addr,
+    );
+    // the names may have been taken meanwhile
+    let end = ns.add_nic(end)?;
+    if let Err(e) = peer_ns.add_nic(peer_end) {
+        ns.remove_nic(&end);
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// Deletes the veth interface `name` of the current namespace, along with its
+/// peer (`ip link del <name>`).
+///
+/// Returns [`Unsupported`](axerrno::AxError::Unsupported) if `name` is not a
+/// veth interface.
+pub fn del_veth(name: &str) -> AxResult {
+    let ns = current_net_namespace();
+    let nic = ns.find_nic(name)?;
+    let (peer_rx, peer_ns) = match &nic.dev.lock().inner {
+        NicDevice::Veth(veth) => (veth.peer_rx.clone(), veth.peer_ns.clone()),
+        NicDevice::Driver(_) => return ax_err!(Unsupported, "not a veth interface"),
+    };
+    ns.remove_nic(&nic);
+    // the peer is gone if its namespace has been dropped
+    if let (Some(peer_rx), Some(peer_ns)) = (peer_rx.upgrade(), peer_ns.upgrade()) {
+        let is_peer = |peer: &Nic| matches!(&peer.dev.lock().inner, NicDevice::Veth(veth) if Arc::ptr_eq(&veth.rx, &peer_rx));
+        if let Some(peer) = peer_ns.nics().into_iter().find(is_peer) {
+            peer_ns.remove_nic(&peer);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,47 @@
+//! [ArceOS](https://github.com/arceos-org/arceos) loader of kernel-space
+//! plugins.
+//!
+//! A plugin is a position-independent 64-bit ELF shared object (e.g., built
+//! with `-fPIC -shared -nostdlib`) that is loaded into the kernel at runtime.
+//! The loader maps its `PT_LOAD` segments, applies the `R_*_RELATIVE`, GOT
+//! and PLT relocations, and resolves undefined symbols against the symbols
+//! exported by the kernel with [`export_symbol!`].
+//!
+//! # Cargo Features
+//!
+//! - `paging`: Apply the segment permissions (e.g., make `.text` executable
+//!   and read-only) in the kernel page table, and load the plugins at a
+//!   randomized address.
+//! - `fs`: Enable loading plugins from the file system by [`load_file`].
+
+#![no_std]
+
+#[macro_use]
+extern crate log;
+extern crate alloc;
+
+mod loader;
+mod symbols;
+
+#[doc(hidden)]
+pub use linkme;
+
+pub use self::loader::{MainArgs, Plugin};
+pub use self::symbols::{KERNEL_SYMBOLS, KernelSymbol, lookup_kernel_symbol};
+
+use axerrno::AxResult;
+
+/// Loads a plugin from the given ELF image.
+///
+/// The image is copied into newly allocated pages, so it can be freed after
+/// this function returns.
+pub fn load(image: &[u8]) -> AxResult<Plugin> {
+    Plugin::load(image)
+}
+
+/// Loads a plugin from the ELF file at the given path.
+#[cfg(feature = "fs")]
+pub fn load_file(path: &str) -> AxResult<Plugin> {
+    let image = axfs::api::read(path)?;
+    Plugin::load(&image)
+}
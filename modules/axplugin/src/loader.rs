@@ -0,0 +1,439 @@
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::ffi::{c_char, c_int};
+
+use axerrno::{AxError, AxResult, ax_err, ax_err_type};
+use memory_addr::{MemoryAddr, PAGE_SIZE_4K};
+use xmas_elf::ElfFile;
+use xmas_elf::header::{self, Machine};
+use xmas_elf::program;
+use xmas_elf::sections::{SectionData, ShType};
+use xmas_elf::symbol_table::{DynEntry64, Entry};
+
+use crate::symbols::lookup_kernel_symbol;
+
+#[cfg(target_arch = "x86_64")]
+mod arch {
+    use super::{Machine, RelocKind};
+
+    pub const MACHINE: Machine = Machine::X86_64;
+
+    pub const fn reloc_kind(ty: u32) -> Option<RelocKind> {
+        match ty {
+            0 => Some(RelocKind::None),     // R_X86_64_NONE
+            1 => Some(RelocKind::Absolute), // R_X86_64_64
+            6 | 7 => Some(RelocKind::Got),  // R_X86_64_GLOB_DAT, R_X86_64_JUMP_SLOT
+            8 => Some(RelocKind::Relative), // R_X86_64_RELATIVE
+            _ => None,
+        }
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod arch {
+    use super::{Machine, RelocKind};
+
+    pub const MACHINE: Machine = Machine::AArch64;
+
+    pub const fn reloc_kind(ty: u32) -> Option<RelocKind> {
+        match ty {
+            0 => Some(RelocKind::None),          // R_AARCH64_NONE
+            257 => Some(RelocKind::Absolute),    // R_AARCH64_ABS64
+            1025 | 1026 => Some(RelocKind::Got), // R_AARCH64_GLOB_DAT, R_AARCH64_JUMP_SLOT
+            1027 => Some(RelocKind::Relative),   // R_AARCH64_RELATIVE
+            _ => None,
+        }
+    }
+}
+
+#[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+mod arch {
+    use super::{Machine, RelocKind};
+
+    pub const MACHINE: Machine = Machine::RISC_V;
+
+    pub const fn reloc_kind(ty: u32) -> Option<RelocKind> {
+        match ty {
+            0 => Some(RelocKind::None),     // R_RISCV_NONE
+            2 => Some(RelocKind::Absolute), // R_RISCV_64 (also used for GOT entries)
+            3 => Some(RelocKind::Relative), // R_RISCV_RELATIVE
+            5 => Some(RelocKind::Got),      // R_RISCV_JUMP_SLOT
+            _ => None,
+        }
+    }
+}
+
+#[cfg(target_arch = "loongarch64")]
+mod arch {
+    use super::{Machine, RelocKind};
+
+    pub const MACHINE: Machine = Machine::Other(258); // EM_LOONGARCH
+
+    pub const fn reloc_kind(ty: u32) -> Option<RelocKind> {
+        match ty {
+            0 => Some(RelocKind::None),     // R_LARCH_NONE
+            2 => Some(RelocKind::Absolute), // R_LARCH_64
+            3 => Some(RelocKind::Relative), // R_LARCH_RELATIVE
+            5 => Some(RelocKind::Got),      // R_LARCH_JUMP_SLOT
+            _ => None,
+        }
+    }
+}
+
+/// Relocation types supported by the loader, independent of the architecture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RelocKind {
+    /// Nothing to do.
+    None,
+    /// `S + A`
+    Absolute,
+    /// `S`, for GOT and PLT entries.
+    Got,
+    /// `B + A`
+    Relative,
+}
+
+/// Allocates the memory of a plugin of `num_pages` pages, at the randomized
+/// load address of the kernel address space (see [`axmm::AddrLayout`]).
+#[cfg(feature = "paging")]
+fn alloc_image(num_pages: usize) -> AxResult<usize> {
+    use axhal::paging::MappingFlags;
+    use memory_addr::VirtAddrRange;
+
+    let size = num_pages * PAGE_SIZE_4K;
+    let mut aspace = axmm::kernel_aspace().lock();
+    let limit = VirtAddrRange::new(aspace.base(), aspace.end());
+    let start = aspace
+        .find_free_area(aspace.layout().load_base(), size, limit)
+        .ok_or(AxError::NoMemory)?;
+    aspace.map_alloc(start, size, MappingFlags::READ | MappingFlags::WRITE, true)?;
+    Ok(start.as_usize())
+}
+
+/// Allocates the memory of a plugin of `num_pages` pages, in the linear
+/// mapping.
+#[cfg(not(feature = "paging"))]
+fn alloc_image(num_pages: usize) -> AxResult<usize> {
+    axalloc::global_allocator()
+        .alloc_pages(num_pages, PAGE_SIZE_4K)
+        .map_err(|_| AxError::NoMemory)
+}
+
+/// A plugin loaded into the kernel.
+///
+/// The memory of the plugin is freed when it's dropped, so all pointers
+/// obtained from [`Plugin::symbol`] must not be used after that.
+pub struct Plugin {
+    base: usize,
+    num_pages: usize,
+    symbols: BTreeMap<String, usize>,
+}
+
+impl Plugin {
+    pub(crate) fn load(image: &[u8]) -> AxResult<Self> {
+        let elf = ElfFile::new(image).map_err(|e| {
+            warn!("invalid plugin image: {}", e);
+            AxError::InvalidData
+        })?;
+        // the plugins run in the kernel, there's no compat mode to run 32-bit
+        // code in
+        if elf.header.pt1.class() != header::Class::SixtyFour {
+            return ax_err!(Unsupported, "32-bit plugins are not supported");
+        }
+        if elf.header.pt2.type_().as_type() != header::Type::SharedObject {
+            return ax_err!(InvalidData, "plugin is not a position-independent object");
+        }
+        if elf.header.pt2.machine().as_machine() != arch::MACHINE {
+            return ax_err!(InvalidData, "plugin is built for another architecture");
+        }
+
+        let segments = elf
+            .program_iter()
+            .filter(|ph| ph.get_type() == Ok(program::Type::Load))
+            .collect::<Vec<_>>();
+        if segments.iter().any(|ph| ph.file_size() > ph.mem_size()) {
+            return ax_err!(InvalidData, "segment larger in the file than in memory");
+        }
+        let size = segments
+            .iter()
+            .map(|ph| ph.virtual_addr().checked_add(ph.mem_size()))
+            .collect::<Option<Vec<_>>>()
+            .and_then(|ends| ends.into_iter().max())
+            .and_then(|size| usize::try_from(size).ok())
+            .ok_or(AxError::InvalidData)?;
+        let num_pages = size.align_up_4k() / PAGE_SIZE_4K;
+
+        let base = alloc_image(num_pages)?;
+        let mut plugin = Self {
+            base,
+            num_pages,
+            symbols: BTreeMap::new(),
+        };
+        plugin.memory_mut().fill(0);
+
+        for ph in &segments {
+            let offset = ph.offset() as usize;
+            let file_size = ph.file_size() as usize;
+            let vaddr = ph.virtual_addr() as usize;
+            let data = offset
+                .checked_add(file_size)
+                .and_then(|end| image.get(offset..end))
+                .ok_or(AxError::InvalidData)?;
+            // `vaddr + file_size` is within the size as `filesz <= memsz`
+            plugin.memory_mut()[vaddr..vaddr + file_size].copy_from_slice(data);
+        }
+
+        let dynsym = dynamic_symbols(&elf)?;
+        plugin.relocate(&elf, dynsym)?;
+        plugin.collect_symbols(&elf, dynsym);
+        #[cfg(feature = "paging")]
+        plugin.protect(&segments)?;
+        axhal::arch::flush_icache_all();
+
+        debug!(
+            "plugin loaded at {:#x}, size {:#x}, {} symbols exported",
+            base,
+            size,
+            plugin.symbols.len()
+        );
+        Ok(plugin)
+    }
+
+    /// Returns the address where the plugin is loaded.
+    pub fn base(&self) -> usize {
+        self.base
+    }
+
+    /// Looks up the address of a symbol defined by the plugin.
+    pub fn symbol(&self, name: &str) -> Option<usize> {
+        self.symbols.get(name).copied()
+    }
+
+    /// Returns an iterator over the names and addresses of all symbols defined
+    /// by the plugin.
+    pub fn symbols(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.symbols
+            .iter()
+            .map(|(name, &addr)| (name.as_str(), addr))
+    }
+
+    /// Calls `int main(int argc, char *argv[], char *envp[])` defined by the
+    /// plugin in the current task with `args`, and returns its exit code.
+    ///
+    /// It allocates nothing, so the plugin may end the task instead of
+    /// returning (e.g. by `exit`) without leaking memory.
+    pub fn run_main(&self, args: &MainArgs) -> AxResult<i32> {
+        type MainFn = extern "C" fn(c_int, *const *const c_char, *const *const c_char) -> c_int;
+
+        let main = self
+            .symbol("main")
+            .ok_or_else(|| ax_err_type!(NotFound, "plugin has no main function"))?;
+        // SAFETY: `main` is a function defined by the plugin, which lives as
+        // long as `self`.
+        let main: MainFn = unsafe { core::mem::transmute(main) };
+        Ok(main(
+            args.argv.len() as c_int,
+            args.argv.as_ptr(),
+            args.envp.as_ptr(),
+        ))
+    }
+
+    fn memory_mut(&mut self) -> &mut [u8] {
+        unsafe { core::slice::from_raw_parts_mut(self.base as *mut u8, self.size()) }
+    }
+
+    fn size(&self) -> usize {
+        self.num_pages * PAGE_SIZE_4K
+    }
+
+    fn resolve(&self, elf: &ElfFile, dynsym: &[DynEntry64], index: u32) -> AxResult<usize> {
+        let sym = dynsym.get(index as usize).ok_or(AxError::InvalidData)?;
+        if sym.shndx() != 0 {
+            // defined in the plugin itself
+            return Ok(self.base + sym.value() as usize);
+        }
+        let name = sym.get_name(elf).map_err(|_| AxError::InvalidData)?;
+        lookup_kernel_symbol(name).ok_or_else(|| {
+            warn!("plugin: undefined symbol {:?}", name);
+            AxError::NotFound
+        })
+    }
+
+    fn relocate(&mut self, elf: &ElfFile, dynsym: &[DynEntry64]) -> AxResult {
+        for section in elf.section_iter() {
+            if section.get_type() != Ok(ShType::Rela) {
+                continue;
+            }
+            let Ok(SectionData::Rela64(relas)) = section.get_data(elf) else {
+                return ax_err!(InvalidData, "bad relocation section");
+            };
+            for rela in relas {
+                let offset = rela.get_offset() as usize;
+                let addend = rela.get_addend() as usize;
+                let value = match arch::reloc_kind(rela.get_type()) {
+                    Some(RelocKind::None) => continue,
+                    Some(RelocKind::Relative) => self.base.wrapping_add(addend),
+                    Some(RelocKind::Absolute) => self
+                        .resolve(elf, dynsym, rela.get_symbol_table_index())?
+                        .wrapping_add(addend),
+                    Some(RelocKind::Got) => {
+                        self.resolve(elf, dynsym, rela.get_symbol_table_index())?
+                    }
+                    None => {
+                        warn!("plugin: unsupported relocation type {}", rela.get_type());
+                        return Err(AxError::Unsupported);
+                    }
+                };
+                if offset
+                    .checked_add(size_of::<usize>())
+                    .is_none_or(|end| end > self.size())
+                {
+                    return ax_err!(InvalidData, "relocation out of range");
+                }
+                unsafe { ((self.base + offset) as *mut usize).write_unaligned(value) };
+            }
+        }
+        Ok(())
+    }
+
+    fn collect_symbols(&mut self, elf: &ElfFile, dynsym: &[DynEntry64]) {
+        use xmas_elf::symbol_table::Binding;
+        for sym in dynsym {
+            if sym.shndx() == 0 || sym.get_binding() == Ok(Binding::Local) {
+                continue;
+            }
+            if let Ok(name) = sym.get_name(elf) {
+                if !name.is_empty() {
+                    self.symbols
+                        .insert(name.to_string(), self.base + sym.value() as usize);
+                }
+            }
+        }
+    }
+
+    /// Applies the segment permissions in the kernel page table.
+    ///
+    /// If two segments share a page, the page gets the union of their
+    /// permissions.
+    #[cfg(feature = "paging")]
+    fn protect(&self, segments: &[program::ProgramHeader]) -> AxResult {
+        use axhal::paging::MappingFlags;
+
+        let mut flags = alloc::vec![MappingFlags::empty(); self.num_pages];
+        for ph in segments {
+            let start = ph.virtual_addr() as usize / PAGE_SIZE_4K;
+            let end = (ph.virtual_addr() + ph.mem_size()) as usize;
+            let mut seg_flags = MappingFlags::READ;
+            if ph.flags().is_write() {
+                seg_flags |= MappingFlags::WRITE;
+            }
+            if ph.flags().is_execute() {
+                seg_flags |= MappingFlags::EXECUTE;
+            }
+            for page in &mut flags[start..end.align_up_4k() / PAGE_SIZE_4K] {
+                *page |= seg_flags;
+            }
+        }
+
+        let mut aspace = axmm::kernel_aspace().lock();
+        for (i, &page_flags) in flags.iter().enumerate() {
+            // the kernel address space is W^X
+            if page_flags.contains(MappingFlags::WRITE | MappingFlags::EXECUTE) {
+                return ax_err!(InvalidData, "writable and executable segments share a page");
+            }
+            // pages not covered by any segment stay readable and writable
+            if !page_flags.is_empty() {
+                let vaddr = (self.base + i * PAGE_SIZE_4K).into();
+                aspace.protect(vaddr, PAGE_SIZE_4K, page_flags)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Plugin {
+    fn drop(&mut self) {
+        #[cfg(feature = "paging")]
+        axmm::kernel_aspace()
+            .lock()
+            .unmap(self.base.into(), self.size())
+            .ok();
+        #[cfg(not(feature = "paging"))]
+        axalloc::global_allocator().dealloc_pages(self.base, self.num_pages);
+    }
+}
+
+fn dynamic_symbols<'a>(elf: &ElfFile<'a>) -> AxResult<&'a [DynEntry64]> {
+    match elf.find_section_by_name(".dynsym") {
+        Some(section) => match section.get_data(elf) {
+            Ok(SectionData::DynSymbolTable64(syms)) => Ok(syms),
+            _ => ax_err!(InvalidData, "bad .dynsym section"),
+        },
+        None => Ok(&[]),
+    }
+}
+
+/// The arguments of the `main` function of a plugin, see
+/// [`Plugin::run_main`].
+pub struct MainArgs {
+    argv: CStrArray,
+    envp: CStrArray,
+}
+
+impl MainArgs {
+    /// Builds `argv` from `args`, and `envp` from `envs` in the form of
+    /// `KEY=VALUE`.
+    pub fn new<A, E>(args: &[A], envs: &[E]) -> AxResult<Self>
+    where
+        A: AsRef<str>,
+        E: AsRef<str>,
+    {
+        Ok(Self {
+            argv: CStrArray::new(args)?,
+            envp: CStrArray::new(envs)?,
+        })
+    }
+}
+
+/// A null-terminated array of C strings, such as `argv`.
+struct CStrArray {
+    _strs: Vec<Vec<u8>>,
+    ptrs: Vec<*const c_char>,
+}
+
+// The pointers are into the strings owned by the array.
+unsafe impl Send for CStrArray {}
+unsafe impl Sync for CStrArray {}
+
+impl CStrArray {
+    fn new<S: AsRef<str>>(strs: &[S]) -> AxResult<Self> {
+        let strs = strs
+            .iter()
+            .map(|s| {
+                let s = s.as_ref().as_bytes();
+                if s.contains(&0) {
+                    return ax_err!(InvalidInput, "string contains a nul byte");
+                }
+                let mut buf = Vec::with_capacity(s.len() + 1);
+                buf.extend_from_slice(s);
+                buf.push(0);
+                Ok(buf)
+            })
+            .collect::<AxResult<Vec<_>>>()?;
+        let ptrs = strs
+            .iter()
+            .map(|s| s.as_ptr() as *const c_char)
+            .chain(core::iter::once(core::ptr::null()))
+            .collect();
+        Ok(Self { _strs: strs, ptrs })
+    }
+
+    fn len(&self) -> usize {
+        self.ptrs.len() - 1
+    }
+
+    fn as_ptr(&self) -> *const *const c_char {
+        self.ptrs.as_ptr()
+    }
+}
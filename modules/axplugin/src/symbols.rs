@@ -0,0 +1,75 @@
+//! Symbols exported by the kernel to plugins.
+
+/// A symbol exported by the kernel, see [`export_symbol!`].
+///
+/// [`export_symbol!`]: crate::export_symbol
+pub struct KernelSymbol {
+    name: &'static str,
+    addr: *const (),
+}
+
+unsafe impl Sync for KernelSymbol {}
+
+impl KernelSymbol {
+    #[doc(hidden)]
+    pub const fn new(name: &'static str, addr: *const ()) -> Self {
+        Self { name, addr }
+    }
+
+    /// Returns the name of the symbol.
+    pub const fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Returns the address of the symbol.
+    pub fn addr(&self) -> usize {
+        self.addr as usize
+    }
+}
+
+/// The table of all symbols exported by [`export_symbol!`].
+///
+/// [`export_symbol!`]: crate::export_symbol
+#[linkme::distributed_slice]
+pub static KERNEL_SYMBOLS: [KernelSymbol];
+
+/// Exports a kernel function (or a static, with the `static` prefix) to
+/// plugins, under its own name.
+///
+/// Functions should be `extern "C"` if they are going to be called from C
+/// plugins.
+///
+/// # Example
+///
+/// ```ignore
+/// extern "C" fn ax_console_write(buf: *const u8, len: usize) { /* ... */ }
+/// static AX_PLUGIN_ABI_VERSION: u32 = 1;
+///
+/// axplugin::export_symbol!(ax_console_write);
+/// axplugin::export_symbol!(static AX_PLUGIN_ABI_VERSION);
+/// ```
+#[macro_export]
+macro_rules! export_symbol {
+    ($sym:ident) => {
+        $crate::export_symbol!(@export $sym, $sym as *const ());
+    };
+    (static $sym:ident) => {
+        $crate::export_symbol!(@export $sym, &raw const $sym as *const ());
+    };
+    (@export $sym:ident, $addr:expr) => {
+        const _: () = {
+            #[$crate::linkme::distributed_slice($crate::KERNEL_SYMBOLS)]
+            #[linkme(crate = $crate::linkme)]
+            static __SYMBOL: $crate::KernelSymbol =
+                $crate::KernelSymbol::new(stringify!($sym), $addr);
+        };
+    };
+}
+
+/// Looks up the address of a symbol exported by the kernel.
+pub fn lookup_kernel_symbol(name: &str) -> Option<usize> {
+    KERNEL_SYMBOLS
+        .iter()
+        .find(|sym| sym.name == name)
+        .map(KernelSymbol::addr)
+}
@@ -0,0 +1,187 @@
+//! [ArceOS](https://github.com/arceos-org/arceos) sampling profiler on the
+//! timer interrupt.
+//!
+//! While the profiler is running (see [`start`]), each timer IRQ samples where
+//! the interrupted code was by calling [`sample`]: either its PC alone
+//! ([`SampleMode::Pc`]), or its whole stack by walking the frame pointers
+//! ([`SampleMode::FramePointer`]), along with the current task. The samples
+//! are kept in a buffer allocated by [`start`], and the ones taken once it's
+//! full are lost.
+//!
+//! The samples are aggregated by task and stack into a report in the folded
+//! format of the flame graph tools ([`report_folded`]), e.g. to be passed to
+//! `flamegraph.pl`. The addresses are symbolized with [`axksyms`], so the
+//! kernel should be built with `KSYMS=y`, and with frame pointers for the
+//! stacks.
+
+#![no_std]
+
+extern crate alloc;
+
+mod report;
+
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering};
+
+use axhal::arch::TrapFrame;
+use kspin::SpinNoIrq;
+
+pub use self::report::report_folded;
+
+/// The maximum number of frames of a stack sampled, the outermost ones are
+/// dropped.
+pub const MAX_DEPTH: usize = 32;
+
+/// The maximum length of the name of a task kept with a sample.
+pub const MAX_TASK_NAME_LEN: usize = 32;
+
+/// The number of the samples kept by default.
+pub const DEFAULT_CAPACITY: usize = 4096;
+
+/// What is sampled on each timer IRQ.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum SampleMode {
+    /// Only the PC of the interrupted code, which costs little and works
+    /// without frame pointers.
+    Pc,
+    /// The PC and the return addresses of the callers, found by walking the
+    /// frame pointers.
+    FramePointer,
+}
+
+/// A stack sampled in a task.
+#[derive(Clone, Copy)]
+struct Sample {
+    task_id: u64,
+    task_name: [u8; MAX_TASK_NAME_LEN],
+    name_len: usize,
+    /// The PC followed by the return addresses, from the innermost frame.
+    stack: [usize; MAX_DEPTH],
+    depth: usize,
+}
+
+impl Sample {
+    fn task_name(&self) -> &str {
+        // cut at a char boundary of a `&str`
+        unsafe { core::str::from_utf8_unchecked(&self.task_name[..self.name_len]) }
+    }
+
+    fn stack(&self) -> &[usize] {
+        &self.stack[..self.depth]
+    }
+}
+
+static SAMPLES: SpinNoIrq<Vec<Sample>> = SpinNoIrq::new(Vec::new());
+
+/// The number of the samples lost as the buffer was full or busy.
+static LOST: AtomicUsize = AtomicUsize::new(0);
+
+static RUNNING: AtomicBool = AtomicBool::new(false);
+static MODE: AtomicU8 = AtomicU8::new(SampleMode::Pc as u8);
+
+/// Starts the profiler, keeping at most `capacity` samples taken in `mode`.
+///
+/// The samples taken before are discarded.
+pub fn start(mode: SampleMode, capacity: usize) {
+    RUNNING.store(false, Ordering::Release);
+    // allocate out of the lock, which disables IRQs
+    let samples = Vec::with_capacity(capacity);
+    let old = core::mem::replace(&mut *SAMPLES.lock(), samples);
+    drop(old);
+    LOST.store(0, Ordering::Relaxed);
+    MODE.store(mode as u8, Ordering::Relaxed);
+    RUNNING.store(true, Ordering::Release);
+}
+
+/// Stops the profiler.
+///
+/// The samples taken are kept until the next [`start`].
+pub fn stop() {
+    RUNNING.store(false, Ordering::Release);
+}
+
+/// Whether the profiler is running.
+pub fn is_running() -> bool {
+    RUNNING.load(Ordering::Acquire)
+}
+
+/// Returns the mode of the samples, the one given to the last [`start`].
+pub fn mode() -> SampleMode {
+    match MODE.load(Ordering::Relaxed) {
+        0 => SampleMode::Pc,
+        _ => SampleMode::FramePointer,
+    }
+}
+
+/// Returns the number of the samples taken since the last [`start`].
+pub fn sample_count() -> usize {
+    SAMPLES.lock().len()
+}
+
+/// Returns the number of the samples lost since the last [`start`], as there
+/// was no room left or the buffer was busy, e.g. while reporting on the same
+/// CPU.
+pub fn lost_count() -> usize {
+    LOST.load(Ordering::Relaxed)
+}
+
+/// Fills the stack of a sample from the trap frame of the interrupted code.
+fn capture(tf: &TrapFrame, sample: &mut Sample) {
+    sample.stack[0] = tf.ip();
+    sample.depth = 1;
+    if mode() == SampleMode::FramePointer {
+        // the interrupted frames are above the trap frame on the same stack,
+        // and the user stacks are below the kernel, thus never walked
+        let sp = tf as *const _ as usize;
+        sample.depth += axksyms::backtrace_from(tf.fp(), sp, &mut sample.stack[1..]);
+    }
+}
+
+/// Fills the task of a sample with the current one.
+fn current_task(sample: &mut Sample) {
+    #[cfg(feature = "multitask")]
+    if let Some(curr) = axtask::current_may_uninit() {
+        let name = curr.name();
+        let mut len = name.len().min(MAX_TASK_NAME_LEN);
+        while !name.is_char_boundary(len) {
+            len -= 1;
+        }
+        sample.task_id = curr.id().as_u64();
+        sample.task_name[..len].copy_from_slice(&name.as_bytes()[..len]);
+        sample.name_len = len;
+        return;
+    }
+    let name = b"kernel";
+    sample.task_name[..name.len()].copy_from_slice(name);
+    sample.name_len = name.len();
+}
+
+/// Takes a sample of the code interrupted by the timer IRQ being handled, if
+/// the profiler is running.
+///
+/// It must be called by the handler of the timer IRQ.
+pub fn sample() {
+    if !is_running() {
+        return;
+    }
+    let mut sample = Sample {
+        task_id: 0,
+        task_name: [0; MAX_TASK_NAME_LEN],
+        name_len: 0,
+        stack: [0; MAX_DEPTH],
+        depth: 0,
+    };
+    if axhal::trap::with_irq_trap_frame(|tf| capture(tf, &mut sample)).is_none() {
+        return;
+    }
+    current_task(&mut sample);
+
+    // the lock may be held by the code interrupted on this CPU
+    match SAMPLES.try_lock() {
+        Some(mut samples) if samples.len() < samples.capacity() => samples.push(sample),
+        _ => {
+            LOST.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
@@ -0,0 +1,61 @@
+//! The report of the samples taken.
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{self, Write};
+
+use crate::SAMPLES;
+
+/// Writes `addr` as the name of its function, or in hexadecimal if it's
+/// unknown.
+///
+/// A return address may be past the end of the function of the call, so the
+/// callers are looked up by the address before it.
+fn write_frame(out: &mut String, addr: usize, is_caller: bool) -> fmt::Result {
+    let lookup_addr = if is_caller { addr - 1 } else { addr };
+    match axksyms::lookup(lookup_addr) {
+        Some(sym) => out.write_str(sym.name()),
+        None => write!(out, "{:#x}", addr),
+    }
+}
+
+/// Writes the samples taken since the last [`start`](crate::start) in the
+/// folded format of the flame graph tools, a line of each task and stack
+/// with the number of its samples, e.g.:
+///
+/// ```text
+/// main-2;main;fib;fib 42
+/// idle-1;cpu_idle 7
+/// ```
+///
+/// The tasks are shown as their names followed by their IDs, and the frames
+/// from the outermost one.
+pub fn report_folded<W: Write>(w: &mut W) -> fmt::Result {
+    // count the stacks quickly, as IRQs are disabled with the lock held
+    let mut stacks: BTreeMap<(u64, String, Vec<usize>), usize> = BTreeMap::new();
+    for sample in SAMPLES.lock().iter() {
+        let key = (
+            sample.task_id,
+            String::from(sample.task_name()),
+            sample.stack().to_vec(),
+        );
+        *stacks.entry(key).or_default() += 1;
+    }
+
+    // the addresses in the same functions are merged once symbolized
+    let mut lines: BTreeMap<String, usize> = BTreeMap::new();
+    for ((task_id, task_name, stack), count) in stacks {
+        let mut line = String::new();
+        write!(line, "{}-{}", task_name, task_id)?;
+        for (i, &addr) in stack.iter().enumerate().rev() {
+            line.push(';');
+            write_frame(&mut line, addr, i > 0)?;
+        }
+        *lines.entry(line).or_default() += count;
+    }
+    for (line, count) in lines {
+        writeln!(w, "{} {}", line, count)?;
+    }
+    Ok(())
+}
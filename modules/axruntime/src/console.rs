@@ -0,0 +1,139 @@
+//! Console devices.
+//!
+//! Each port of the console devices is a character device `/dev/hvcN`,
+//! numbered across the devices in the order they are found. The port named
+//! by `AX_CONSOLE` at build time (e.g. `hvc0`) becomes the primary console,
+//! used for the standard input and output instead of the UART.
+
+use alloc::{boxed::Box, format, vec::Vec};
+
+use axdriver::{AxDeviceContainer, prelude::*};
+use axhal::console::PrimaryConsole;
+use kspin::SpinNoIrq;
+
+/// A port of a console device.
+#[derive(Clone, Copy)]
+struct ConsolePort {
+    dev: &'static SpinNoIrq<AxConsoleDevice>,
+    port: usize,
+}
+
+/// Writes all of `buf` to `port`, waiting for the device to take it.
+fn write_all(dev: &mut AxConsoleDevice, port: usize, mut buf: &[u8]) -> DevResult {
+    while !buf.is_empty() {
+        match dev.write(port, buf) {
+            Ok(n) => buf = &buf[n..],
+            Err(DevError::Again) => core::hint::spin_loop(),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+impl PrimaryConsole for ConsolePort {
+    fn write_bytes(&self, bytes: &[u8]) -> bool {
+        // don't spin on the lock, e.g. when panicking while writing
+        match self.dev.try_lock() {
+            Some(mut dev) => write_all(&mut dev, self.port, bytes).is_ok(),
+            None => false,
+        }
+    }
+
+    fn read_bytes(&self, bytes: &mut [u8]) -> usize {
+        self.dev.lock().read(self.port, bytes).unwrap_or(0)
+    }
+}
+
+#[cfg(feature = "fs")]
+mod devfs {
+    use axdriver::prelude::*;
+    use axfs_vfs::{VfsError, VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeType, VfsResult};
+
+    use super::{ConsolePort, write_all};
+
+    fn as_vfs_err(err: DevError) -> VfsError {
+        match err {
+            DevError::Again => VfsError::WouldBlock,
+            DevError::BadState => VfsError::BadState,
+            DevError::InvalidParam => VfsError::InvalidInput,
+            DevError::NoMemory => VfsError::NoMemory,
+            DevError::Unsupported => VfsError::Unsupported,
+            _ => VfsError::Io,
+        }
+    }
+
+    impl VfsNodeOps for ConsolePort {
+        fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+            Ok(VfsNodeAttr::new(
+                VfsNodePerm::default_file(),
+                VfsNodeType::CharDevice,
+                0,
+                0,
+            ))
+        }
+
+        /// Waits until some bytes are received.
+        fn read_at(&self, _offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+            if buf.is_empty() {
+                return Ok(0);
+            }
+            loop {
+                match self.dev.lock().read(self.port, buf) {
+                    Ok(n) => return Ok(n),
+                    Err(DevError::Again) => {}
+                    Err(e) => return Err(as_vfs_err(e)),
+                }
+                #[cfg(feature = "multitask")]
+                axtask::yield_now();
+                #[cfg(not(feature = "multitask"))]
+                core::hint::spin_loop();
+            }
+        }
+
+        fn write_at(&self, _offset: u64, buf: &[u8]) -> VfsResult<usize> {
+            write_all(&mut self.dev.lock(), self.port, buf).map_err(as_vfs_err)?;
+            Ok(buf.len())
+        }
+
+        fn truncate(&self, _size: u64) -> VfsResult {
+            Ok(())
+        }
+
+        axfs_vfs::impl_vfs_non_dir_default! {}
+    }
+}
+
+/// Sets up the ports of the console devices, and the primary console.
+pub fn init_consoles(mut console_devs: AxDeviceContainer<AxConsoleDevice>) {
+    info!("Initialize console devices...");
+
+    let mut ports = Vec::new();
+    while let Some(dev) = console_devs.take_one() {
+        let dev: &'static SpinNoIrq<_> = Box::leak(Box::new(SpinNoIrq::new(dev)));
+        let mut guard = dev.lock();
+        for port in 0..guard.num_ports() {
+            if guard.port_present(port) {
+                ports.push(ConsolePort { dev, port });
+            }
+        }
+    }
+
+    let primary = option_env!("AX_CONSOLE").unwrap_or("");
+    for (i, port) in ports.into_iter().enumerate() {
+        let name = format!("hvc{}", i);
+        info!(
+            "  {}: port {} of {:?}",
+            name,
+            port.port,
+            port.dev.lock().device_name()
+        );
+        if name == primary {
+            info!("  use {} as the primary console", name);
+            axhal::console::set_primary(Box::leak(Box::new(port)));
+        }
+        #[cfg(feature = "fs")]
+        if let Err(e) = axfs::add_device(name.leak(), alloc::sync::Arc::new(port)) {
+            warn!("failed to add /dev/hvc{}: {:?}", i, e);
+        }
+    }
+}
@@ -0,0 +1,235 @@
+//! Environment variables of the processes.
+//!
+//! Each process (namespace of `axns`) has its own environment, [`ENVIRON`],
+//! and a new process starts with a copy of the environment of its parent
+//! ([`ENVIRON::copy_inner`]). The environment of the first process is empty
+//! at boot. With the `procfs` feature of `axfs`, `/proc/self/environ` holds the
+//! environment most recently changed.
+
+use alloc::collections::BTreeMap;
+use alloc::ffi::CString;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ffi::c_char;
+
+use axerrno::{AxResult, ax_err};
+use axns::{ResArc, def_resource};
+use kspin::SpinNoPreempt;
+
+/// The environment of a process.
+pub struct Environ(SpinNoPreempt<Inner>);
+
+struct Inner {
+    /// The variables in insertion order, as in the `environ` of Linux.
+    vars: Vec<(String, String)>,
+    /// The number of changes so far.
+    generation: u64,
+    /// Whether `ptrs` is out of date.
+    dirty: bool,
+    /// The `KEY=VALUE` strings of [`Environ::c_environ`], by their contents.
+    ///
+    /// They are never freed, as the pointers to them (e.g. the results of
+    /// `getenv`) may be kept after the variables change, as with the `setenv`
+    /// of glibc. Only the distinct strings are kept.
+    strings: BTreeMap<String, CString>,
+    /// Pointers to `strings` in the order of `vars`, ending with NULL.
+    ptrs: Vec<*const c_char>,
+    /// The arrays replaced by larger ones, kept for the same reason.
+    old_ptrs: Vec<Vec<*const c_char>>,
+}
+
+// Safety: the pointers only point to the strings owned by the same struct.
+unsafe impl Send for Inner {}
+
+def_resource! {
+    /// The environment of the current process.
+    pub static ENVIRON: ResArc<Environ> = ResArc::new();
+}
+
+impl ENVIRON {
+    /// Returns a copy of the environment, for a new process.
+    pub fn copy_inner(&self) -> Environ {
+        Environ::with_vars(self.0.lock().vars.clone())
+    }
+}
+
+impl Environ {
+    /// Creates an empty environment.
+    pub fn new() -> Self {
+        Self::with_vars(Vec::new())
+    }
+
+    fn with_vars(vars: Vec<(String, String)>) -> Self {
+        Self(SpinNoPreempt::new(Inner {
+            vars,
+            generation: 0,
+            dirty: true,
+            strings: BTreeMap::new(),
+            ptrs: Vec::new(),
+            old_ptrs: Vec::new(),
+        }))
+    }
+
+    /// Returns the environment as a NULL-terminated array of `KEY=VALUE`
+    /// strings, the `environ` of C.
+    ///
+    /// The array and the strings stay valid as long as the environment lives,
+    /// but the array is only up to date until the next change.
+    pub fn c_environ(&self) -> *const *const c_char {
+        let mut inner = self.0.lock();
+        if inner.dirty {
+            inner.update_ptrs();
+        }
+        inner.ptrs.as_ptr()
+    }
+}
+
+impl Default for Environ {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Inner {
+    /// Rebuilds `ptrs` in place, or in a larger array if it's full.
+    fn update_ptrs(&mut self) {
+        let Self {
+            vars,
+            strings,
+            ptrs,
+            old_ptrs,
+            ..
+        } = self;
+        if ptrs.capacity() < vars.len() + 1 {
+            let new = Vec::with_capacity((vars.len() + 1).next_power_of_two());
+            old_ptrs.push(core::mem::replace(ptrs, new));
+        }
+        ptrs.clear();
+        for (key, value) in vars.iter() {
+            let entry = alloc::format!("{}={}", key, value);
+            let string = strings.entry(entry).or_insert_with_key(|entry| {
+                // the keys and values are checked to have no NUL
+                CString::new(entry.as_str()).unwrap()
+            });
+            ptrs.push(string.as_ptr());
+        }
+        ptrs.push(core::ptr::null());
+        self.dirty = false;
+    }
+
+    fn changed(&mut self) {
+        self.dirty = true;
+        self.generation += 1;
+    }
+}
+
+/// Writes the environment to `/proc/self/environ`.
+///
+/// It's written without the lock held, as writing the file may block, so it's
+/// written again if the environment changed meanwhile.
+#[cfg(feature = "fs")]
+fn update_procfs() {
+    loop {
+        let (generation, buf) = {
+            let inner = ENVIRON.0.lock();
+            let mut buf = Vec::new();
+            for (key, value) in &inner.vars {
+                buf.extend_from_slice(key.as_bytes());
+                buf.push(b'=');
+                buf.extend_from_slice(value.as_bytes());
+                buf.push(0);
+            }
+            (inner.generation, buf)
+        };
+        // fails if there's no procfs
+        axfs::api::write("/proc/self/environ", buf).ok();
+        if ENVIRON.0.lock().generation == generation {
+            break;
+        }
+    }
+}
+
+#[cfg(not(feature = "fs"))]
+fn update_procfs() {}
+
+/// Initializes the environment of the first process.
+pub(crate) fn init() {
+    ENVIRON.init_new(Environ::new());
+}
+
+/// Returns whether `key` can be the name of an environment variable, i.e. it's
+/// not empty and contains neither `=` nor NUL.
+pub fn is_valid_key(key: &str) -> bool {
+    !key.is_empty() && !key.contains(['=', '\0'])
+}
+
+/// Returns the value of the environment variable `key`.
+pub fn var(key: &str) -> Option<String> {
+    ENVIRON
+        .0
+        .lock()
+        .vars
+        .iter()
+        .find(|(k, _)| k == key)
+        .map(|(_, v)| v.clone())
+}
+
+/// Returns a snapshot of all environment variables.
+pub fn vars() -> Vec<(String, String)> {
+    ENVIRON.0.lock().vars.clone()
+}
+
+/// Sets the environment variable `key` to `value`.
+///
+/// Returns [`InvalidInput`](axerrno::AxError::InvalidInput) if `key` is not
+/// [valid](is_valid_key), or `value` contains NUL.
+pub fn set_var(key: &str, value: &str) -> AxResult {
+    set_var_inner(key, value, true)
+}
+
+/// Sets the environment variable `key` to `value`, unless it's already set.
+///
+/// It fails as [`set_var`] does.
+pub fn set_var_if_absent(key: &str, value: &str) -> AxResult {
+    set_var_inner(key, value, false)
+}
+
+fn set_var_inner(key: &str, value: &str, overwrite: bool) -> AxResult {
+    if !is_valid_key(key) {
+        return ax_err!(InvalidInput, "invalid environment variable name");
+    }
+    if value.contains('\0') {
+        return ax_err!(InvalidInput, "invalid environment variable value");
+    }
+    {
+        let mut inner = ENVIRON.0.lock();
+        match inner.vars.iter_mut().find(|(k, _)| k == key) {
+            Some(_) if !overwrite => return Ok(()),
+            Some((_, v)) => *v = value.into(),
+            None => inner.vars.push((key.into(), value.into())),
+        }
+        inner.changed();
+    }
+    update_procfs();
+    Ok(())
+}
+
+/// Removes the environment variable `key`, if it exists.
+pub fn remove_var(key: &str) {
+    {
+        let mut inner = ENVIRON.0.lock();
+        let len = inner.vars.len();
+        inner.vars.retain(|(k, _)| k != key);
+        if inner.vars.len() == len {
+            return;
+        }
+        inner.changed();
+    }
+    update_procfs();
+}
+
+/// Returns the environment of the current process in the layout of the C
+/// `environ`, see [`Environ::c_environ`].
+pub fn c_environ() -> *const *const c_char {
+    ENVIRON.c_environ()
+}
@@ -3,5 +3,13 @@ use core::panic::PanicInfo;
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
     error!("{}", info);
+    let mut bt = [0; 16];
+    let n = axksyms::backtrace(&mut bt);
+    if n > 0 {
+        error!("backtrace:");
+        for (i, &ra) in bt[..n].iter().enumerate() {
+            error!("  #{} {}", i, axksyms::Symbolized(ra));
+        }
+    }
     axhal::misc::terminate()
 }
@@ -5,14 +5,19 @@
 //!
 //! # Cargo Features
 //!
-//! - `alloc`: Enable global memory allocator.
+//! - `alloc`: Enable global memory allocator and environment variables.
 //! - `paging`: Enable page table manipulation support.
+//! - `iommu`: Enable the IOMMU, isolating the DMA of the devices.
 //! - `irq`: Enable interrupt handling support.
 //! - `multitask`: Enable multi-threading support.
 //! - `smp`: Enable SMP (symmetric multiprocessing) support.
 //! - `fs`: Enable filesystem support.
+//! - `initramfs`: Use the initial RAM disk loaded by the bootloader, a cpio
+//!   archive, as the root filesystem if there is one.
 //! - `net`: Enable networking support.
 //! - `display`: Enable graphics support.
+//! - `console`: Enable console devices (`/dev/hvcN`), which can replace the
+//!   console of the platform.
 //!
 //! All the features are optional and disabled by default.
 
@@ -22,12 +27,21 @@
 #[macro_use]
 extern crate axlog;
 
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
 #[cfg(all(target_os = "none", not(test)))]
 mod lang_items;
 
+#[cfg(feature = "console")]
+mod console;
+
 #[cfg(feature = "smp")]
 mod mp;
 
+#[cfg(feature = "alloc")]
+pub mod env;
+
 #[cfg(feature = "smp")]
 pub use self::mp::rust_main_secondary;
 
@@ -83,6 +97,34 @@ impl axlog::LogIf for LogIfImpl {
     }
 }
 
+#[cfg(feature = "trace")]
+struct TraceIfImpl;
+
+#[cfg(feature = "trace")]
+#[crate_interface::impl_interface]
+impl axtrace::TraceIf for TraceIfImpl {
+    fn current_ticks() -> u64 {
+        axhal::time::current_ticks()
+    }
+
+    fn ticks_to_nanos(ticks: u64) -> u64 {
+        axhal::time::ticks_to_nanos(ticks)
+    }
+
+    fn current_cpu_id() -> usize {
+        axhal::cpu::this_cpu_id()
+    }
+
+    fn current_task_id() -> u64 {
+        #[cfg(feature = "multitask")]
+        {
+            axtask::current_may_uninit().map_or(0, |curr| curr.id().as_u64())
+        }
+        #[cfg(not(feature = "multitask"))]
+        0
+    }
+}
+
 use core::sync::atomic::{AtomicUsize, Ordering};
 
 static INITED_CPUS: AtomicUsize = AtomicUsize::new(0);
@@ -127,9 +169,13 @@ pub extern "C" fn rust_main(cpu_id: usize, dtb: usize) -> ! {
 
     axlog::init();
     axlog::set_max_level(option_env!("AX_LOG").unwrap_or("")); // no effect if set `log-level-*` features
+    if let Err(e) = axlog::update_log_filter(option_env!("AX_LOG_FILTER").unwrap_or("")) {
+        warn!("Invalid log filter: {}", e);
+    }
     info!("Logging is enabled.");
     info!("Primary CPU {} started, dtb = {:#x}.", cpu_id, dtb);
 
+    axhal::mem::init_memblock(dtb);
     info!("Found physcial memory regions:");
     for r in axhal::mem::memory_regions() {
         info!(
@@ -142,7 +188,12 @@ pub extern "C" fn rust_main(cpu_id: usize, dtb: usize) -> ! {
     }
 
     #[cfg(feature = "alloc")]
-    init_allocator();
+    {
+        init_allocator();
+        env::init();
+    }
+
+    axhal::random::init();
 
     #[cfg(feature = "paging")]
     axmm::init_memory_management();
@@ -150,20 +201,46 @@ pub extern "C" fn rust_main(cpu_id: usize, dtb: usize) -> ! {
     info!("Initialize platform devices...");
     axhal::platform_init();
 
+    #[cfg(feature = "iommu")]
+    axhal::iommu::init();
+
     #[cfg(feature = "multitask")]
     axtask::init_scheduler();
-
-    #[cfg(any(feature = "fs", feature = "net", feature = "display"))]
+    // account the heap memory to the group of each task
+    #[cfg(all(feature = "multitask", feature = "alloc"))]
+    axalloc::set_usage_hook(axtask::account_heap);
+
+    #[cfg(any(
+        feature = "fs",
+        feature = "net",
+        feature = "display",
+        feature = "console"
+    ))]
     {
         #[allow(unused_variables)]
         let all_devices = axdriver::init_drivers();
 
         #[cfg(feature = "fs")]
-        axfs::init_filesystems(all_devices.block);
+        init_filesystems(all_devices.block);
+        #[cfg(all(feature = "fs", feature = "alloc"))]
+        write_iomem();
+        #[cfg(feature = "ninep")]
+        axfs::init_ninep(all_devices.ninep);
+
+        // after the filesystems, to add the device files
+        #[cfg(feature = "console")]
+        console::init_consoles(all_devices.console);
 
         #[cfg(feature = "net")]
         axnet::init_network(all_devices.net);
 
+        #[cfg(all(feature = "fs", feature = "net"))]
+        {
+            if let Ok(conf) = axfs::api::read_to_string("/etc/resolv.conf") {
+                axnet::load_resolv_conf(&conf);
+            }
+        }
+
         #[cfg(feature = "display")]
         axdisplay::init_display(all_devices.display);
     }
@@ -203,6 +280,20 @@ pub extern "C" fn rust_main(cpu_id: usize, dtb: usize) -> ! {
     }
 }
 
+/// Initializes the filesystems, from the initial RAM disk loaded by the
+/// bootloader if there's one.
+#[cfg(feature = "fs")]
+fn init_filesystems(blk_devs: axdriver::AxDeviceContainer<axdriver::prelude::AxBlockDevice>) {
+    #[cfg(feature = "initramfs")]
+    if let Some((paddr, size)) = axhal::mem::initrd() {
+        info!("Found an initramfs at {:#x}, size {:#x}", paddr, size);
+        let vaddr = axhal::mem::phys_to_virt(paddr);
+        let cpio = unsafe { core::slice::from_raw_parts(vaddr.as_ptr(), size) };
+        return axfs::init_initramfs(cpio, blk_devs);
+    }
+    axfs::init_filesystems(blk_devs);
+}
+
 #[cfg(feature = "alloc")]
 fn init_allocator() {
     use axhal::mem::{MemRegionFlags, memory_regions, phys_to_virt};
@@ -210,6 +301,8 @@ fn init_allocator() {
     info!("Initialize global memory allocator...");
     info!("  use {} allocator.", axalloc::global_allocator().name());
 
+    axhal::mem::finish_early_alloc();
+
     let mut max_region_size = 0;
     let mut max_region_paddr = 0.into();
     for r in memory_regions() {
@@ -230,35 +323,64 @@ fn init_allocator() {
                 .expect("add heap memory region failed");
         }
     }
+    if let Some((paddr, size)) = axhal::mem::cma_region() {
+        axalloc::cma_init(phys_to_virt(paddr).as_usize(), size);
+    }
+}
+
+/// Writes the memory map to `/proc/iomem`.
+#[cfg(all(feature = "fs", feature = "alloc"))]
+fn write_iomem() {
+    let mut iomem = alloc::string::String::new();
+    axhal::mem::write_iomem(&mut iomem).ok();
+    // fails if there's no procfs
+    axfs::api::write("/proc/iomem", iomem).ok();
 }
 
 #[cfg(feature = "irq")]
 fn init_interrupt() {
     use axhal::time::TIMER_IRQ_NUM;
 
-    // Setup timer interrupt handler
-    const PERIODIC_INTERVAL_NANOS: u64 =
-        axhal::time::NANOS_PER_SEC / axconfig::TICKS_PER_SEC as u64;
+    // Setup timer interrupt handler. With multitasking, the task manager
+    // programs the timer for its next event, otherwise the timer is periodic.
+    // The profiler samples the code interrupted by each tick.
+    #[cfg(feature = "multitask")]
+    {
+        fn on_timer_tick() {
+            #[cfg(feature = "prof")]
+            axprof::sample();
+            axtask::on_timer_tick();
+        }
 
-    #[percpu::def_percpu]
-    static NEXT_DEADLINE: u64 = 0;
+        axhal::irq::register_handler(TIMER_IRQ_NUM, on_timer_tick);
+    }
 
-    fn update_timer() {
-        let now_ns = axhal::time::monotonic_time_nanos();
-        // Safety: we have disabled preemption in IRQ handler.
-        let mut deadline = unsafe { NEXT_DEADLINE.read_current_raw() };
-        if now_ns >= deadline {
-            deadline = now_ns + PERIODIC_INTERVAL_NANOS;
+    #[cfg(not(feature = "multitask"))]
+    {
+        const PERIODIC_INTERVAL_NANOS: u64 =
+            axhal::time::NANOS_PER_SEC / axconfig::TICKS_PER_SEC as u64;
+
+        #[percpu::def_percpu]
+        static NEXT_DEADLINE: u64 = 0;
+
+        fn update_timer() {
+            #[cfg(feature = "prof")]
+            axprof::sample();
+            let now_ns = axhal::time::monotonic_time_nanos();
+            // Safety: we have disabled preemption in IRQ handler.
+            let mut deadline = unsafe { NEXT_DEADLINE.read_current_raw() };
+            if now_ns >= deadline {
+                deadline = now_ns + PERIODIC_INTERVAL_NANOS;
+            }
+            unsafe { NEXT_DEADLINE.write_current_raw(deadline + PERIODIC_INTERVAL_NANOS) };
+            axhal::time::set_oneshot_timer(deadline);
         }
-        unsafe { NEXT_DEADLINE.write_current_raw(deadline + PERIODIC_INTERVAL_NANOS) };
-        axhal::time::set_oneshot_timer(deadline);
+
+        axhal::irq::register_handler(TIMER_IRQ_NUM, update_timer);
     }
 
-    axhal::irq::register_handler(TIMER_IRQ_NUM, || {
-        update_timer();
-        #[cfg(feature = "multitask")]
-        axtask::on_timer_tick();
-    });
+    #[cfg(feature = "smp")]
+    axhal::irq::ipi::init();
 
     // Enable IRQs before starting app
     axhal::arch::enable_irqs();
@@ -3,19 +3,33 @@
 //! Currently supported primitives:
 //!
 //! - [`Mutex`]: A mutual exclusion primitive.
+//! - [`RwLock`]: A reader-writer lock, which prefers writers. Readers are
+//!   preferred by [`ReaderPreferredRwLock`].
+//! - mod [`rcu`]: Read-copy-update, for data read much more often than
+//!   written.
 //! - mod [`spin`]: spinlocks imported from the [`kspin`] crate.
 //!
 //! # Cargo Features
 //!
 //! - `multitask`: For use in the multi-threaded environments. If the feature is
-//!   not enabled, [`Mutex`] will be an alias of [`spin::SpinNoIrq`]. This
-//!   feature is enabled by default.
+//!   not enabled, [`Mutex`] will be an alias of [`spin::SpinNoIrq`], [`RwLock`]
+//!   will spin instead of blocking, and RCU grace periods will end at once.
+//!   This feature is enabled by default.
 
 #![cfg_attr(not(test), no_std)]
 #![feature(doc_cfg)]
 
+extern crate alloc;
+
 pub use kspin as spin;
 
+pub mod rcu;
+mod rwlock;
+
+pub use self::rwlock::{
+    RawRwLock, ReaderPreferredRwLock, RwLock, RwLockReadGuard, RwLockWriteGuard,
+};
+
 #[cfg(feature = "multitask")]
 mod mutex;
 
@@ -26,3 +40,10 @@ pub use self::mutex::{Mutex, MutexGuard, RawMutex};
 #[cfg(not(feature = "multitask"))]
 #[doc(cfg(not(feature = "multitask")))]
 pub use kspin::{SpinNoIrq as Mutex, SpinNoIrqGuard as MutexGuard};
+
+/// Initializes the scheduler once for all the tests.
+#[cfg(test)]
+fn init_test_scheduler() {
+    static INIT: std::sync::Once = std::sync::Once::new();
+    INIT.call_once(axtask::init_scheduler);
+}
@@ -0,0 +1,214 @@
+//! Read-copy-update (RCU) for mostly-read data.
+//!
+//! Readers access the data without locks or atomic read-modify-write
+//! operations, with preemption disabled for the duration of the read-side
+//! critical section. Writers publish a new copy of the data, and free the old
+//! one after a grace period, once all the CPUs have passed through a
+//! quiescent state: a point where no read-side critical section can be
+//! running, as accounted by the scheduler (see [`axtask::quiescent_states`]).
+//!
+//! Read-side critical sections must not block or yield, since a task can't be
+//! preempted inside them.
+
+use alloc::boxed::Box;
+use core::marker::PhantomData;
+use core::ops::Deref;
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+use kernel_guard::{BaseGuard, NoPreempt};
+
+use crate::Mutex;
+
+/// Waits for a grace period: all the RCU read-side critical sections running
+/// when it's called have ended when it returns.
+///
+/// It must not be called in a read-side critical section.
+pub fn synchronize_rcu() {
+    #[cfg(feature = "multitask")]
+    {
+        let snapshot = axtask::quiescent_states();
+        loop {
+            let states = axtask::quiescent_states();
+            // Each CPU was idle at the snapshot, or has passed through a
+            // quiescent state since.
+            if snapshot
+                .iter()
+                .zip(&states)
+                .all(|(&old, &new)| old & 1 == 1 || old != new)
+            {
+                break;
+            }
+            axtask::yield_now();
+        }
+    }
+}
+
+/// A guard of an RCU read-side critical section, created by [`rcu_read_lock`].
+///
+/// Preemption is disabled until it's dropped.
+pub struct RcuReadGuard {
+    state: <NoPreempt as BaseGuard>::State,
+    /// The critical section must end on the CPU it started on.
+    _not_send: PhantomData<*mut ()>,
+}
+
+/// Starts an RCU read-side critical section, which lasts until the returned
+/// guard is dropped.
+pub fn rcu_read_lock() -> RcuReadGuard {
+    RcuReadGuard {
+        state: NoPreempt::acquire(),
+        _not_send: PhantomData,
+    }
+}
+
+impl Drop for RcuReadGuard {
+    fn drop(&mut self) {
+        NoPreempt::release(self.state);
+    }
+}
+
+/// Data protected by RCU.
+///
+/// [`read`](Self::read) returns a reference to the current copy of the data,
+/// without blocking. [`update`](Self::update) and [`replace`](Self::replace)
+/// publish a new copy, and wait for the readers of the old one before dropping
+/// it. Writers are serialized by a [`Mutex`].
+pub struct Rcu<T> {
+    ptr: AtomicPtr<T>,
+    writer: Mutex<()>,
+    _marker: PhantomData<Box<T>>,
+}
+
+// Readers share the data between tasks, and writers drop old copies on
+// another task.
+unsafe impl<T: Send> Send for Rcu<T> {}
+unsafe impl<T: Send + Sync> Sync for Rcu<T> {}
+
+/// A reference to the data of an [`Rcu`], which holds an RCU read-side
+/// critical section.
+pub struct RcuRef<'a, T> {
+    data: &'a T,
+    _guard: RcuReadGuard,
+}
+
+impl<T> Rcu<T> {
+    /// Creates a new [`Rcu`] holding `data`.
+    pub fn new(data: T) -> Self {
+        Self {
+            ptr: AtomicPtr::new(Box::into_raw(Box::new(data))),
+            writer: Mutex::new(()),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns a reference to the current copy of the data.
+    ///
+    /// Preemption is disabled until the reference is dropped.
+    pub fn read(&self) -> RcuRef<'_, T> {
+        let guard = rcu_read_lock();
+        // SAFETY: the copy isn't dropped before the critical section ends.
+        let data = unsafe { &*self.ptr.load(Ordering::Acquire) };
+        RcuRef {
+            data,
+            _guard: guard,
+        }
+    }
+
+    /// Publishes a new copy of the data built by `f` from the current one.
+    ///
+    /// It waits for a grace period before dropping the old copy.
+    pub fn update<F>(&self, f: F)
+    where
+        F: FnOnce(&T) -> T,
+    {
+        let _writer = self.writer.lock();
+        // SAFETY: the writer lock is held, so the copy can't be replaced.
+        let new = f(unsafe { &*self.ptr.load(Ordering::Relaxed) });
+        drop(self.publish(new));
+    }
+
+    /// Publishes `data` as the new copy, and returns the old one after a
+    /// grace period.
+    pub fn replace(&self, data: T) -> T {
+        let _writer = self.writer.lock();
+        *self.publish(data)
+    }
+
+    /// Returns a mutable reference to the data, which needs no
+    /// synchronization as `self` is borrowed mutably.
+    pub fn get_mut(&mut self) -> &mut T {
+        // SAFETY: there are no readers, as they would borrow `self`.
+        unsafe { &mut *self.ptr.load(Ordering::Relaxed) }
+    }
+
+    fn publish(&self, data: T) -> Box<T> {
+        let old = self
+            .ptr
+            .swap(Box::into_raw(Box::new(data)), Ordering::AcqRel);
+        synchronize_rcu();
+        // SAFETY: the readers of the old copy are gone.
+        unsafe { Box::from_raw(old) }
+    }
+}
+
+impl<T: Default> Default for Rcu<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T> Drop for Rcu<T> {
+    fn drop(&mut self) {
+        // SAFETY: there are no readers, as they would borrow `self`.
+        drop(unsafe { Box::from_raw(*self.ptr.get_mut()) });
+    }
+}
+
+impl<T> Deref for RcuRef<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Rcu;
+    use axtask as thread;
+    use std::sync::LazyLock;
+
+    #[test]
+    fn update_while_reading() {
+        crate::init_test_scheduler();
+
+        const NUM_TASKS: u32 = 10;
+        const NUM_ITERS: u32 = 1_000;
+        static R: LazyLock<Rcu<(u32, u32)>> = LazyLock::new(|| Rcu::new((0, 0)));
+
+        for _ in 0..NUM_TASKS {
+            thread::spawn(|| {
+                for _ in 0..NUM_ITERS {
+                    R.update(|&(a, b)| (a + 1, b + 1));
+                    thread::yield_now();
+                }
+            });
+            thread::spawn(|| {
+                for _ in 0..NUM_ITERS {
+                    let val = R.read();
+                    // an update never shows half done
+                    assert_eq!(val.0, val.1);
+                    drop(val);
+                    thread::yield_now();
+                }
+            });
+        }
+
+        println!("spawn OK");
+        while R.read().0 != NUM_ITERS * NUM_TASKS {
+            thread::yield_now();
+        }
+        assert_eq!(*R.read(), (NUM_ITERS * NUM_TASKS, NUM_ITERS * NUM_TASKS));
+        println!("Rcu test OK");
+    }
+}
@@ -0,0 +1,221 @@
+//! A sleeping reader-writer lock.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+#[cfg(feature = "multitask")]
+use axtask::WaitQueue;
+
+/// Without tasks to switch to, waiting is spinning.
+#[cfg(not(feature = "multitask"))]
+struct WaitQueue;
+
+#[cfg(not(feature = "multitask"))]
+impl WaitQueue {
+    const fn new() -> Self {
+        Self
+    }
+
+    fn wait_until(&self, condition: impl Fn() -> bool) {
+        while !condition() {
+            core::hint::spin_loop();
+        }
+    }
+
+    fn notify_one(&self, _resched: bool) -> bool {
+        false
+    }
+
+    fn notify_all(&self, _resched: bool) {}
+}
+
+const WRITER: usize = 1;
+const READER: usize = 2;
+
+/// A [`lock_api::RawRwLock`] implementation.
+///
+/// When the lock can't be acquired, the current task will block and be put
+/// into a wait queue, one for the readers and one for the writers.
+///
+/// If `WRITER_PREFERRED` is set, new readers wait while a writer is waiting,
+/// so writers can't be starved by a stream of readers. A task must not take
+/// the read lock again while holding it then, or it may deadlock with a
+/// waiting writer. Otherwise, readers only wait while a writer holds the lock.
+pub struct RawRwLock<const WRITER_PREFERRED: bool = true> {
+    /// The number of readers times [`READER`], plus [`WRITER`] if a writer
+    /// holds the lock.
+    state: AtomicUsize,
+    /// The number of writers waiting for the lock.
+    waiting_writers: AtomicUsize,
+    readers: WaitQueue,
+    writers: WaitQueue,
+}
+
+impl<const WRITER_PREFERRED: bool> RawRwLock<WRITER_PREFERRED> {
+    /// Creates a [`RawRwLock`].
+    #[inline(always)]
+    pub const fn new() -> Self {
+        Self {
+            state: AtomicUsize::new(0),
+            waiting_writers: AtomicUsize::new(0),
+            readers: WaitQueue::new(),
+            writers: WaitQueue::new(),
+        }
+    }
+
+    fn can_read(&self, state: usize) -> bool {
+        state & WRITER == 0
+            && !(WRITER_PREFERRED && self.waiting_writers.load(Ordering::Relaxed) > 0)
+    }
+}
+
+unsafe impl<const WRITER_PREFERRED: bool> lock_api::RawRwLock for RawRwLock<WRITER_PREFERRED> {
+    const INIT: Self = Self::new();
+
+    type GuardMarker = lock_api::GuardSend;
+
+    fn lock_shared(&self) {
+        while !self.try_lock_shared() {
+            // Wait until the lock looks available before retrying
+            self.readers
+                .wait_until(|| self.can_read(self.state.load(Ordering::Relaxed)));
+        }
+    }
+
+    fn try_lock_shared(&self) -> bool {
+        let mut state = self.state.load(Ordering::Relaxed);
+        while self.can_read(state) {
+            match self.state.compare_exchange_weak(
+                state,
+                state + READER,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return true,
+                Err(s) => state = s,
+            }
+        }
+        false
+    }
+
+    unsafe fn unlock_shared(&self) {
+        let state = self.state.fetch_sub(READER, Ordering::Release);
+        if state == READER {
+            // the last reader
+            self.writers.notify_one(true);
+        }
+    }
+
+    fn lock_exclusive(&self) {
+        if self.try_lock_exclusive() {
+            return;
+        }
+        self.waiting_writers.fetch_add(1, Ordering::Relaxed);
+        while !self.try_lock_exclusive() {
+            // Wait until the lock looks unlocked before retrying
+            self.writers
+                .wait_until(|| self.state.load(Ordering::Relaxed) == 0);
+        }
+        self.waiting_writers.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    fn try_lock_exclusive(&self) -> bool {
+        self.state
+            .compare_exchange(0, WRITER, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    unsafe fn unlock_exclusive(&self) {
+        self.state.fetch_and(!WRITER, Ordering::Release);
+        if WRITER_PREFERRED && self.waiting_writers.load(Ordering::Relaxed) > 0 {
+            // the readers are woken up when the last waiting writer unlocks
+            self.writers.notify_one(true);
+        } else {
+            self.readers.notify_all(true);
+            self.writers.notify_one(true);
+        }
+    }
+
+    fn is_locked(&self) -> bool {
+        self.state.load(Ordering::Relaxed) != 0
+    }
+
+    fn is_locked_exclusive(&self) -> bool {
+        self.state.load(Ordering::Relaxed) & WRITER != 0
+    }
+}
+
+/// An alias of [`lock_api::RwLock`], which prefers writers.
+pub type RwLock<T> = lock_api::RwLock<RawRwLock, T>;
+/// An alias of [`lock_api::RwLockReadGuard`].
+pub type RwLockReadGuard<'a, T> = lock_api::RwLockReadGuard<'a, RawRwLock, T>;
+/// An alias of [`lock_api::RwLockWriteGuard`].
+pub type RwLockWriteGuard<'a, T> = lock_api::RwLockWriteGuard<'a, RawRwLock, T>;
+
+/// An alias of [`lock_api::RwLock`], which prefers readers.
+pub type ReaderPreferredRwLock<T> = lock_api::RwLock<RawRwLock<false>, T>;
+
+#[cfg(test)]
+mod tests {
+    use crate::RwLock;
+    use axtask as thread;
+
+    fn may_interrupt() {
+        // simulate interrupts
+        if rand::random::<u32>() % 3 == 0 {
+            thread::yield_now();
+        }
+    }
+
+    #[test]
+    fn readers_and_writers() {
+        crate::init_test_scheduler();
+
+        const NUM_TASKS: u32 = 10;
+        const NUM_ITERS: u32 = 10_000;
+        static L: RwLock<(u32, u32)> = RwLock::new((0, 0));
+
+        fn write(delta: u32) {
+            for _ in 0..NUM_ITERS {
+                let mut val = L.write();
+                val.0 += delta;
+                may_interrupt();
+                val.1 += delta;
+                drop(val);
+                may_interrupt();
+            }
+        }
+
+        fn read() {
+            for _ in 0..NUM_ITERS {
+                let val = L.read();
+                may_interrupt();
+                // a writer never runs while it's read
+                assert_eq!(val.0, val.1);
+                drop(val);
+                may_interrupt();
+            }
+        }
+
+        for _ in 0..NUM_TASKS {
+            thread::spawn(|| write(1));
+            thread::spawn(read);
+            thread::spawn(|| write(2));
+        }
+
+        println!("spawn OK");
+        loop {
+            let val = L.read();
+            if val.0 == NUM_ITERS * NUM_TASKS * 3 {
+                break;
+            }
+            drop(val);
+            may_interrupt();
+        }
+
+        assert_eq!(
+            *L.read(),
+            (NUM_ITERS * NUM_TASKS * 3, NUM_ITERS * NUM_TASKS * 3)
+        );
+        println!("RwLock test OK");
+    }
+}
@@ -3,18 +3,26 @@
 use alloc::{
     string::String,
     sync::{Arc, Weak},
+    vec::Vec,
 };
 
 use kernel_guard::NoPreemptIrqSave;
 
 pub(crate) use crate::run_queue::{current_run_queue, select_run_queue};
 
+#[doc(cfg(feature = "multitask"))]
+pub use crate::group::{
+    GroupUsage, TaskGroup, account_heap, account_open_files, current_group, proc_task_groups,
+    set_current_group, task_group, task_groups,
+};
+#[doc(cfg(feature = "multitask"))]
+pub use crate::run_queue::RunQueueStats;
 #[doc(cfg(feature = "multitask"))]
 pub use crate::task::{CurrentTask, TaskId, TaskInner};
 #[doc(cfg(feature = "multitask"))]
 pub use crate::task_ext::{TaskExtMut, TaskExtRef};
 #[doc(cfg(feature = "multitask"))]
-pub use crate::wait_queue::WaitQueue;
+pub use crate::wait_queue::{WaitQueue, WaitResult};
 
 /// The reference type of a task.
 pub type AxTaskRef = Arc<AxTask>;
@@ -94,17 +102,20 @@ pub fn init_scheduler_secondary() {
     crate::timers::init();
 }
 
-/// Handles periodic timer ticks for the task manager.
+/// Handles the timer interrupt for the task manager.
 ///
-/// For example, advance scheduler states, checks timed events, etc.
+/// It wakes up the tasks whose deadlines have passed, advances the scheduler
+/// states on scheduler ticks, and programs the timer for the next event. The
+/// timer is not periodic, so it must be the handler of the timer interrupt.
 #[cfg(feature = "irq")]
 #[doc(cfg(feature = "irq"))]
 pub fn on_timer_tick() {
     use kernel_guard::NoOp;
-    crate::timers::check_events();
-    // Since irq and preemption are both disabled here,
-    // we can get current run queue with the default `kernel_guard::NoOp`.
-    current_run_queue::<NoOp>().scheduler_timer_tick();
+    if crate::timers::on_timer_irq() {
+        // Since irq and preemption are both disabled here,
+        // we can get current run queue with the default `kernel_guard::NoOp`.
+        current_run_queue::<NoOp>().scheduler_timer_tick();
+    }
 }
 
 /// Adds the given task to the run queue, returns the task reference.
@@ -124,6 +135,17 @@ where
     spawn_task(TaskInner::new(f, name, stack_size))
 }
 
+/// Spawns a new task with the given parameters, or returns `None` if its
+/// stack can't be allocated.
+///
+/// Returns the task reference.
+pub fn try_spawn_raw<F>(f: F, name: String, stack_size: usize) -> Option<AxTaskRef>
+where
+    F: FnOnce() + Send + 'static,
+{
+    Some(spawn_task(TaskInner::try_new(f, name, stack_size)?))
+}
+
 /// Spawns a new task with the default parameters.
 ///
 /// The default task name is an empty string. The default task stack size is
@@ -150,11 +172,17 @@ pub fn set_priority(prio: isize) -> bool {
     current_run_queue::<NoPreemptIrqSave>().set_current_priority(prio)
 }
 
+/// Sets the priority of the given task, as [`set_priority`] does for the
+/// current task.
+///
+/// Returns `true` if the priority is set successfully.
+pub fn set_task_priority(task: &AxTaskRef, prio: isize) -> bool {
+    current_run_queue::<NoPreemptIrqSave>().set_task_priority(task, prio)
+}
+
 /// Set the affinity for the current task.
 /// [`AxCpuMask`] is used to specify the CPU affinity.
 /// Returns `true` if the affinity is set successfully.
-///
-/// TODO: support set the affinity for other tasks.
 pub fn set_current_affinity(cpumask: AxCpuMask) -> bool {
     if cpumask.is_empty() {
         false
@@ -166,16 +194,8 @@ pub fn set_current_affinity(cpumask: AxCpuMask) -> bool {
         // the affinity. If not, we need to migrate the task to the correct CPU.
         #[cfg(feature = "smp")]
         if !cpumask.get(axhal::cpu::this_cpu_id()) {
-            const MIGRATION_TASK_STACK_SIZE: usize = 4096;
-            // Spawn a new migration task for migrating.
-            let migration_task = TaskInner::new(
-                move || crate::run_queue::migrate_entry(curr),
-                "migration-task".into(),
-                MIGRATION_TASK_STACK_SIZE,
-            )
-            .into_arc();
-
             // Migrate the current task to the correct CPU using the migration task.
+            let migration_task = crate::run_queue::new_migration_task(curr);
             current_run_queue::<NoPreemptIrqSave>().migrate_current(migration_task);
 
             assert!(cpumask.get(axhal::cpu::this_cpu_id()), "Migration failed");
@@ -184,6 +204,93 @@ pub fn set_current_affinity(cpumask: AxCpuMask) -> bool {
     }
 }
 
+/// Set the affinity for the given task.
+/// Returns `true` if the affinity is set successfully.
+///
+/// The current task is migrated at once, as with [`set_current_affinity`].
+/// Other tasks are moved to an allowed CPU when they're woken up or
+/// preempted, so a task running on another CPU may run there until its time
+/// slice ends.
+pub fn set_affinity(task: &AxTaskRef, cpumask: AxCpuMask) -> bool {
+    if current().ptr_eq(task) {
+        set_current_affinity(cpumask)
+    } else if cpumask.is_empty() {
+        false
+    } else {
+        task.set_cpumask(cpumask);
+        true
+    }
+}
+
+/// Returns the statistics of the run queues of all CPUs.
+pub fn run_queue_stats() -> Vec<RunQueueStats> {
+    crate::run_queue::stats()
+}
+
+/// Returns the statistics of the run queues, one line per online CPU, for
+/// `/proc/schedstat`.
+pub fn proc_schedstat() -> String {
+    use core::fmt::Write;
+
+    let mut content = String::from("cpu   ready     switches   stolen migrations\n");
+    for stats in run_queue_stats() {
+        writeln!(
+            content,
+            "{:<5} {:5} {:12} {:8} {:10}",
+            stats.cpu_id, stats.nr_ready, stats.nr_switches, stats.nr_stolen, stats.nr_migrations,
+        )
+        .unwrap();
+    }
+    content
+}
+
+/// Returns the RCU quiescent state counters of all CPUs, indexed by CPU ID.
+///
+/// A counter changes whenever its CPU passes through a quiescent state, where
+/// no RCU read-side critical section can be running: when it reschedules, or
+/// when a timer tick interrupts code with preemption enabled. It's odd while
+/// the CPU runs the idle task, which is a quiescent state as long as it lasts.
+/// Offline CPUs report an odd value too.
+pub fn quiescent_states() -> Vec<usize> {
+    crate::run_queue::quiescent_states()
+}
+
+/// Interrupts `task`: its interruptible waits (such as
+/// [`WaitQueue::wait_timeout_interruptible`]) return
+/// [`WaitResult::Interrupted`] until it calls [`clear_interrupt`].
+///
+/// A task blocked in an interruptible wait is woken up right away.
+pub fn interrupt(task: &AxTaskRef) {
+    task.set_interrupted(true);
+    task.with_interruptible_wq(|wq| wq.interrupt_task(task));
+}
+
+/// Clears the pending interrupt of the current task, once it's handled.
+pub fn clear_interrupt() {
+    current().set_interrupted(false);
+}
+
+/// Returns whether the current task has an interrupt pending.
+pub fn interrupt_pending() -> bool {
+    current().is_interrupted()
+}
+
+/// Returns the depth of the system calls the current task is in, or 0 if it
+/// runs the code of the application.
+///
+/// The system calls keep it up to date with [`set_syscall_depth`]. A task is
+/// only diverted on the return from a trap (e.g., to run a signal handler)
+/// out of the system calls, where it holds no lock of the kernel.
+pub fn syscall_depth() -> usize {
+    current().syscall_depth()
+}
+
+/// Sets the depth of the system calls the current task is in, see
+/// [`syscall_depth`].
+pub fn set_syscall_depth(depth: usize) {
+    current().set_syscall_depth(depth);
+}
+
 /// Current task gives up the CPU time voluntarily, and switches to another
 /// ready task.
 pub fn yield_now() {
@@ -20,3 +20,13 @@ pub fn sleep(dur: core::time::Duration) {
 pub fn sleep_until(deadline: axhal::time::TimeValue) {
     axhal::time::busy_wait_until(deadline);
 }
+
+/// For single-task situation, there's no other task to interrupt the current
+/// one.
+pub fn interrupt_pending() -> bool {
+    false
+}
+
+/// For single-task situation, there are no task groups to account the
+/// network usage to.
+pub fn account_net(_rx_bytes: usize, _tx_bytes: usize) {}
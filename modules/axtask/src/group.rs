@@ -0,0 +1,181 @@
+//! Task groups, to account the resources used by a set of tasks, e.g. the
+//! ones of a service.
+//!
+//! A task is in the group of the task that spawned it, until it's moved to
+//! another one by [`set_current_group`]. The CPU time is accounted at each
+//! context switch. The other resources are charged by the modules that
+//! allocate them, to the group of the current task, and credited to the group
+//! of the task that frees them, which is usually the same.
+
+use alloc::{collections::BTreeMap, string::String, sync::Arc, vec::Vec};
+use core::fmt::Write;
+use core::sync::atomic::{AtomicIsize, AtomicU64, AtomicUsize, Ordering};
+use core::time::Duration;
+
+use kspin::SpinNoIrq;
+
+use crate::current_may_uninit;
+
+/// The groups by name. They are never removed, so that a service keeps its
+/// usage across restarts.
+static GROUPS: SpinNoIrq<BTreeMap<String, Arc<TaskGroup>>> = SpinNoIrq::new(BTreeMap::new());
+
+/// A named group of tasks, and the resources they use.
+pub struct TaskGroup {
+    name: String,
+    tasks: AtomicUsize,
+    cpu_time: AtomicU64,
+    heap_bytes: AtomicIsize,
+    open_files: AtomicIsize,
+    net_rx_bytes: AtomicU64,
+    net_tx_bytes: AtomicU64,
+}
+
+/// The resources used by a [`TaskGroup`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GroupUsage {
+    /// The number of tasks in the group.
+    pub tasks: usize,
+    /// The CPU time consumed by the tasks, until they were last switched out.
+    pub cpu_time: Duration,
+    /// The heap memory allocated and not freed by the tasks, in bytes.
+    pub heap_bytes: usize,
+    /// The number of file descriptors opened and not closed by the tasks.
+    pub open_files: usize,
+    /// The number of bytes received by the tasks from sockets.
+    pub net_rx_bytes: u64,
+    /// The number of bytes sent by the tasks to sockets.
+    pub net_tx_bytes: u64,
+}
+
+impl TaskGroup {
+    fn new(name: String) -> Self {
+        Self {
+            name,
+            tasks: AtomicUsize::new(0),
+            cpu_time: AtomicU64::new(0),
+            heap_bytes: AtomicIsize::new(0),
+            open_files: AtomicIsize::new(0),
+            net_rx_bytes: AtomicU64::new(0),
+            net_tx_bytes: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns the name of the group.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the resources used by the tasks of the group.
+    pub fn usage(&self) -> GroupUsage {
+        // Resources freed by another group may be credited to this one, don't
+        // let them go below zero.
+        let positive = |n: &AtomicIsize| n.load(Ordering::Relaxed).max(0) as usize;
+        GroupUsage {
+            tasks: self.tasks.load(Ordering::Relaxed),
+            cpu_time: Duration::from_nanos(self.cpu_time.load(Ordering::Relaxed)),
+            heap_bytes: positive(&self.heap_bytes),
+            open_files: positive(&self.open_files),
+            net_rx_bytes: self.net_rx_bytes.load(Ordering::Relaxed),
+            net_tx_bytes: self.net_tx_bytes.load(Ordering::Relaxed),
+        }
+    }
+
+    pub(crate) fn add_task(&self) {
+        self.tasks.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn remove_task(&self) {
+        self.tasks.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_cpu_time(&self, nanos: u64) {
+        self.cpu_time.fetch_add(nanos, Ordering::Relaxed);
+    }
+}
+
+/// Returns the group named `name`, created if it doesn't exist.
+pub fn task_group(name: &str) -> Arc<TaskGroup> {
+    GROUPS
+        .lock()
+        .entry(name.into())
+        .or_insert_with(|| Arc::new(TaskGroup::new(name.into())))
+        .clone()
+}
+
+/// Returns all the groups, sorted by name.
+pub fn task_groups() -> Vec<Arc<TaskGroup>> {
+    GROUPS.lock().values().cloned().collect()
+}
+
+/// Moves the current task to `group`, or out of its group if `None`.
+///
+/// The tasks it spawns from now on are in the same group.
+pub fn set_current_group(group: Option<Arc<TaskGroup>>) {
+    crate::current().set_group(group);
+}
+
+/// Returns the group of the current task.
+pub fn current_group() -> Option<Arc<TaskGroup>> {
+    current_may_uninit().and_then(|curr| curr.group())
+}
+
+/// Charges heap memory to the group of the current task, or credits it if
+/// `delta` is negative.
+///
+/// It's called by the global allocator, so it must not allocate.
+pub fn account_heap(delta: isize) {
+    if let Some(curr) = current_may_uninit() {
+        curr.with_group(|group| {
+            group.heap_bytes.fetch_add(delta, Ordering::Relaxed);
+        });
+    }
+}
+
+/// Charges opened file descriptors to the group of the current task, or
+/// credits closed ones if `delta` is negative.
+pub fn account_open_files(delta: isize) {
+    if let Some(curr) = current_may_uninit() {
+        curr.with_group(|group| {
+            group.open_files.fetch_add(delta, Ordering::Relaxed);
+        });
+    }
+}
+
+/// Charges the bytes received from and sent to sockets to the group of the
+/// current task.
+pub fn account_net(rx_bytes: usize, tx_bytes: usize) {
+    if let Some(curr) = current_may_uninit() {
+        curr.with_group(|group| {
+            group
+                .net_rx_bytes
+                .fetch_add(rx_bytes as u64, Ordering::Relaxed);
+            group
+                .net_tx_bytes
+                .fetch_add(tx_bytes as u64, Ordering::Relaxed);
+        });
+    }
+}
+
+/// Returns the usage of each group, one line per group, for `/proc/groups`.
+pub fn proc_task_groups() -> String {
+    let mut content = String::from(
+        "name             tasks   cpu_ms     heap_kb  files     rx_bytes     tx_bytes\n",
+    );
+    for group in task_groups() {
+        let usage = group.usage();
+        writeln!(
+            content,
+            "{:<16} {:5} {:8} {:11} {:6} {:12} {:12}",
+            group.name(),
+            usage.tasks,
+            usage.cpu_time.as_millis(),
+            usage.heap_bytes / 1024,
+            usage.open_files,
+            usage.net_rx_bytes,
+            usage.net_tx_bytes,
+        )
+        .unwrap();
+    }
+    content
+}
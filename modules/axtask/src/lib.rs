@@ -11,15 +11,20 @@
 //!   Otherwise, only a few APIs with naive implementation is available.
 //! - `irq`: Interrupts are enabled. If this feature is enabled, timer-based
 //!    APIs can be used, such as [`sleep`], [`sleep_until`], and
-//!    [`WaitQueue::wait_timeout`].
+//!    [`WaitQueue::wait_timeout`] and [`WaitQueue::wait_timeout_interruptible`].
 //! - `preempt`: Enable preemptive scheduling.
+//! - `paging`: Map the kernel stacks of the tasks with a guard area below
+//!   each of them, where an overflow faults rather than corrupting the
+//!   memory around.
 //! - `sched_fifo`: Use the [FIFO cooperative scheduler][1]. It also enables the
 //!   `multitask` feature if it is enabled. This feature is enabled by default,
 //!   and it can be overriden by other scheduler features.
 //! - `sched_rr`: Use the [Round-robin preemptive scheduler][2]. It also enables
 //!   the `multitask` and `preempt` features if it is enabled.
 //! - `sched_cfs`: Use the [Completely Fair Scheduler][3]. It also enables the
-//!   the `multitask` and `preempt` features if it is enabled.
+//!   the `multitask` and `preempt` features if it is enabled. It's the only
+//!   scheduler with priorities: tasks get CPU time in proportion to the
+//!   weights of their nice values, set by [`set_priority`].
 //!
 //! [1]: scheduler::FifoScheduler
 //! [2]: scheduler::RRScheduler
@@ -43,17 +48,22 @@ cfg_if::cfg_if! {
         mod run_queue;
         mod task;
         mod task_ext;
+        mod group;
         mod api;
         mod wait_queue;
 
+        #[cfg(any(feature = "irq", test))]
+        #[cfg_attr(not(feature = "irq"), allow(dead_code))]
+        mod timer_wheel;
         #[cfg(feature = "irq")]
         mod timers;
 
         #[doc(cfg(feature = "multitask"))]
         pub use self::api::*;
-        pub use self::api::{sleep, sleep_until, yield_now};
+        pub use self::api::{interrupt_pending, sleep, sleep_until, yield_now};
+        pub use self::group::account_net;
     } else {
         mod api_s;
-        pub use self::api_s::{sleep, sleep_until, yield_now};
+        pub use self::api_s::{account_net, interrupt_pending, sleep, sleep_until, yield_now};
     }
 }
@@ -1,6 +1,8 @@
 use alloc::collections::VecDeque;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 
 #[cfg(feature = "smp")]
 use alloc::sync::Weak;
@@ -53,6 +55,14 @@ static mut RUN_QUEUES: [MaybeUninit<&'static mut AxRunQueue>; axconfig::SMP] =
 #[allow(clippy::declare_interior_mutable_const)] // It's ok because it's used only for initialization `RUN_QUEUES`.
 const ARRAY_REPEAT_VALUE: MaybeUninit<&'static mut AxRunQueue> = MaybeUninit::uninit();
 
+/// Whether the run queue of each CPU in `RUN_QUEUES` has been initialized.
+static RUN_QUEUES_ONLINE: [AtomicBool; axconfig::SMP] =
+    [const { AtomicBool::new(false) }; axconfig::SMP];
+
+/// The interval of the periodic load balancing, in scheduler ticks (100ms).
+#[cfg(all(feature = "smp", feature = "irq"))]
+const BALANCE_INTERVAL_TICKS: usize = axconfig::TICKS_PER_SEC.div_ceil(10);
+
 /// Returns a reference to the current run queue in [`CurrentRunQueueRef`].
 ///
 /// ## Safety
@@ -135,6 +145,13 @@ fn get_run_queue(index: usize) -> &'static mut AxRunQueue {
     unsafe { RUN_QUEUES[index].assume_init_mut() }
 }
 
+/// Returns the run queues that have been initialized.
+fn online_run_queues() -> impl Iterator<Item = &'static AxRunQueue> {
+    (0..axconfig::SMP)
+        .filter(|&index| RUN_QUEUES_ONLINE[index].load(Ordering::Acquire))
+        .map(|index| unsafe { &**RUN_QUEUES[index].assume_init_ref() })
+}
+
 /// Selects the appropriate run queue for the provided task.
 ///
 /// * In a single-core system, this function always returns a reference to the global run queue.
@@ -186,6 +203,22 @@ pub(crate) struct AxRunQueue {
     /// Since irq and preempt are preserved by the kernel guard hold by `AxRunQueueRef`,
     /// we just use a simple raw spin lock here.
     scheduler: SpinRaw<Scheduler>,
+    /// The number of tasks in the scheduler, read by other CPUs for load
+    /// balancing.
+    nr_ready: AtomicUsize,
+    /// The number of context switches.
+    nr_switches: AtomicU64,
+    /// The number of tasks taken from other run queues.
+    nr_stolen: AtomicU64,
+    /// The number of tasks moved to this run queue as their CPU affinity
+    /// changed.
+    nr_migrations: AtomicU64,
+    /// Counts the RCU quiescent states of this CPU, shifted left by one. The
+    /// lowest bit is set while the CPU is idle.
+    quiescent_state: AtomicUsize,
+    /// The ticks since the last periodic load balancing.
+    #[cfg(all(feature = "smp", feature = "irq"))]
+    balance_ticks: usize,
 }
 
 /// A reference to the run queue with specific guard.
@@ -238,6 +271,9 @@ impl<G: BaseGuard> AxRunQueueRef<'_, G> {
         );
         assert!(task.is_ready());
         self.inner.scheduler.lock().add_task(task);
+        self.inner.nr_ready.fetch_add(1, Ordering::Relaxed);
+        #[cfg(all(feature = "smp", feature = "irq"))]
+        crate::timers::kick(self.inner.cpu_id);
     }
 
     /// Unblock one task by inserting it into the run queue.
@@ -264,6 +300,8 @@ impl<G: BaseGuard> AxRunQueueRef<'_, G> {
                 #[cfg(feature = "preempt")]
                 crate::current().set_preempt_pending(true);
             }
+            #[cfg(all(feature = "smp", feature = "irq"))]
+            crate::timers::kick(cpu_id);
         }
     }
 }
@@ -277,6 +315,14 @@ impl<G: BaseGuard> CurrentRunQueueRef<'_, G> {
             #[cfg(feature = "preempt")]
             curr.set_preempt_pending(true);
         }
+        // The interrupted code could be preempted, so it's not in an RCU
+        // read-side critical section. The IRQ handler disables preemption once.
+        #[cfg(feature = "preempt")]
+        if curr.can_preempt(1) {
+            self.inner.note_quiescent_state(curr.is_idle());
+        }
+        #[cfg(feature = "smp")]
+        self.inner.balance_tick();
     }
 
     /// Yield the current task and reschedule.
@@ -287,12 +333,26 @@ impl<G: BaseGuard> CurrentRunQueueRef<'_, G> {
         trace!("task yield: {}", curr.id_name());
         assert!(curr.is_running());
 
+        #[cfg(feature = "smp")]
+        if self.current_needs_migration() {
+            let migration_task = new_migration_task(curr.clone());
+            self.migrate_current(migration_task);
+            return;
+        }
+
         self.inner
             .put_task_with_state(curr.clone(), TaskState::Running, false);
 
         self.inner.resched();
     }
 
+    /// Whether the current task is no longer allowed to run on this CPU, as
+    /// its affinity was changed by another task.
+    #[cfg(feature = "smp")]
+    fn current_needs_migration(&self) -> bool {
+        !self.current_task.cpumask().get(self.inner.cpu_id)
+    }
+
     /// Migrate the current task to a new run queue matching its CPU affinity and reschedule.
     /// This function will spawn a new `migration_task` to perform the migration, which will set
     /// current task to `Ready` state and select a proper run queue for it according to its CPU affinity,
@@ -342,6 +402,12 @@ impl<G: BaseGuard> CurrentRunQueueRef<'_, G> {
             can_preempt
         );
         if can_preempt {
+            #[cfg(feature = "smp")]
+            if self.current_needs_migration() {
+                let migration_task = new_migration_task(curr.clone());
+                self.migrate_current(migration_task);
+                return;
+            }
             self.inner
                 .put_task_with_state(curr.clone(), TaskState::Running, true);
             self.inner.resched();
@@ -428,6 +494,8 @@ impl<G: BaseGuard> CurrentRunQueueRef<'_, G> {
 
         let now = axhal::time::wall_time();
         if now < deadline {
+            // the timers run on the monotonic clock
+            let deadline = axhal::time::monotonic_time() + (deadline - now);
             crate::timers::set_alarm_wakeup(deadline, curr.clone());
             curr.set_state(TaskState::Blocked);
             self.inner.resched();
@@ -435,10 +503,18 @@ impl<G: BaseGuard> CurrentRunQueueRef<'_, G> {
     }
 
     pub fn set_current_priority(&mut self, prio: isize) -> bool {
-        self.inner
-            .scheduler
-            .lock()
-            .set_priority(self.current_task.as_task_ref(), prio)
+        let curr = self.current_task.as_task_ref().clone();
+        self.set_task_priority(&curr, prio)
+    }
+
+    /// Sets the priority of a task, which may be in the run queue of another
+    /// CPU. The schedulers only keep the priority in the task.
+    pub fn set_task_priority(&mut self, task: &AxTaskRef, prio: isize) -> bool {
+        let ok = self.inner.scheduler.lock().set_priority(task, prio);
+        if ok {
+            TaskInner::set_priority(task, prio);
+        }
+        ok
     }
 }
 
@@ -455,6 +531,73 @@ impl AxRunQueue {
         Self {
             cpu_id,
             scheduler: SpinRaw::new(scheduler),
+            nr_ready: AtomicUsize::new(1),
+            nr_switches: AtomicU64::new(0),
+            nr_stolen: AtomicU64::new(0),
+            nr_migrations: AtomicU64::new(0),
+            quiescent_state: AtomicUsize::new(0),
+            #[cfg(all(feature = "smp", feature = "irq"))]
+            balance_ticks: 0,
+        }
+    }
+
+    /// Puts a ready task into the scheduler.
+    fn enqueue(&self, task: AxTaskRef, preempt: bool) {
+        self.scheduler.lock().put_prev_task(task, preempt);
+        self.nr_ready.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Takes the next task to run from the scheduler.
+    fn dequeue(&self) -> Option<AxTaskRef> {
+        let task = self.scheduler.lock().pick_next_task()?;
+        self.nr_ready.fetch_sub(1, Ordering::Relaxed);
+        Some(task)
+    }
+
+    /// Takes a task that may run on this CPU from the busiest of the other run
+    /// queues, if it has more than `min_ready` ready tasks.
+    ///
+    /// Only the next task of the busiest queue is considered, so that it's
+    /// cheap enough to be done whenever this CPU runs out of tasks.
+    #[cfg(feature = "smp")]
+    fn steal_task(&self, min_ready: usize) -> Option<AxTaskRef> {
+        let busiest = online_run_queues()
+            .filter(|rq| rq.cpu_id != self.cpu_id)
+            .max_by_key(|rq| rq.nr_ready.load(Ordering::Relaxed))?;
+        if busiest.nr_ready.load(Ordering::Relaxed) <= min_ready {
+            return None;
+        }
+        let task = busiest.dequeue()?;
+        if !task.cpumask().get(self.cpu_id) {
+            // put it back in front, as if it were preempted
+            busiest.enqueue(task, true);
+            return None;
+        }
+        debug!(
+            "task steal: {} from run_queue {} to {}",
+            task.id_name(),
+            busiest.cpu_id,
+            self.cpu_id
+        );
+        self.nr_stolen.fetch_add(1, Ordering::Relaxed);
+        Some(task)
+    }
+
+    /// Pulls a task from the busiest run queue every [`BALANCE_INTERVAL_TICKS`]
+    /// ticks, if it has at least two more ready tasks than this one.
+    #[cfg(all(feature = "smp", feature = "irq"))]
+    fn balance_tick(&mut self) {
+        self.balance_ticks += 1;
+        if self.balance_ticks < BALANCE_INTERVAL_TICKS {
+            return;
+        }
+        self.balance_ticks = 0;
+        if let Some(task) = self.steal_task(self.nr_ready.load(Ordering::Relaxed) + 1) {
+            self.enqueue(task, false);
+        }
+        // the idle CPUs with their ticks stopped don't come to take the tasks
+        if self.nr_ready.load(Ordering::Relaxed) > 0 {
+            crate::timers::kick_idle_cpu();
         }
     }
 
@@ -494,7 +637,7 @@ impl AxRunQueue {
                 }
             }
             // TODO: priority
-            self.scheduler.lock().put_prev_task(task, preempt);
+            self.enqueue(task, preempt);
             true
         } else {
             false
@@ -504,23 +647,32 @@ impl AxRunQueue {
     /// Core reschedule subroutine.
     /// Pick the next task to run and switch to it.
     fn resched(&mut self) {
-        let next = self
-            .scheduler
-            .lock()
-            .pick_next_task()
-            .unwrap_or_else(|| unsafe {
-                // Safety: IRQs must be disabled at this time.
-                IDLE_TASK.current_ref_raw().get_unchecked().clone()
-            });
+        let next = self.dequeue();
+        // Take a task from other CPUs before going idle.
+        #[cfg(feature = "smp")]
+        let next = next.or_else(|| self.steal_task(0));
+        let next = next.unwrap_or_else(|| unsafe {
+            // Safety: IRQs must be disabled at this time.
+            IDLE_TASK.current_ref_raw().get_unchecked().clone()
+        });
         assert!(
             next.is_ready(),
             "next {} is not ready: {:?}",
             next.id_name(),
             next.state()
         );
+        self.note_quiescent_state(next.is_idle());
         self.switch_to(crate::current(), next);
     }
 
+    /// Records that this CPU passed through an RCU quiescent state, and
+    /// whether it's going to run the idle task.
+    fn note_quiescent_state(&self, idle: bool) {
+        let state = self.quiescent_state.load(Ordering::Relaxed);
+        self.quiescent_state
+            .store(((state | 1) + 1) | idle as usize, Ordering::Release);
+    }
+
     fn switch_to(&mut self, prev_task: CurrentTask, next_task: AxTaskRef) {
         // Make sure that IRQs are disabled by kernel guard or other means.
         #[cfg(all(not(test), feature = "irq"))] // Note: irq is faked under unit tests.
@@ -539,6 +691,27 @@ impl AxRunQueue {
         if prev_task.ptr_eq(&next_task) {
             return;
         }
+        #[cfg(feature = "trace")]
+        axtrace::tracepoint!(
+            "sched",
+            "sched_switch",
+            prev_pid = prev_task.id().as_u64(),
+            prev_state = prev_task.state() as u8,
+            next_pid = next_task.id().as_u64(),
+        );
+        prev_task.account_switch_to(&next_task);
+        self.nr_switches.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "irq")]
+        if prev_task.is_idle() {
+            crate::timers::restart_tick();
+        }
+
+        // A task taken from another CPU may still be in the middle of being
+        // switched out there. Wait until it's done, see `put_task_with_state()`.
+        #[cfg(feature = "smp")]
+        while next_task.on_cpu() {
+            core::hint::spin_loop();
+        }
 
         // Claim the task as running, we do this before switching to it
         // such that any running task will have this set.
@@ -597,17 +770,28 @@ fn gc_entry() {
     }
 }
 
+/// Creates a task that moves `task` to a run queue matching its CPU affinity,
+/// see [`CurrentRunQueueRef::migrate_current`].
+#[cfg(feature = "smp")]
+pub(crate) fn new_migration_task(task: AxTaskRef) -> AxTaskRef {
+    const MIGRATION_TASK_STACK_SIZE: usize = 4096;
+    TaskInner::new(
+        move || migrate_entry(task),
+        "migration-task".into(),
+        MIGRATION_TASK_STACK_SIZE,
+    )
+    .into_arc()
+}
+
 /// The task routine for migrating the current task to the correct CPU.
 ///
 /// It calls `select_run_queue` to get the correct run queue for the task, and
 /// then puts the task to the scheduler of target run queue.
 #[cfg(feature = "smp")]
 pub(crate) fn migrate_entry(migrated_task: AxTaskRef) {
-    select_run_queue::<kernel_guard::NoPreemptIrqSave>(&migrated_task)
-        .inner
-        .scheduler
-        .lock()
-        .put_prev_task(migrated_task, false)
+    let rq = select_run_queue::<kernel_guard::NoPreemptIrqSave>(&migrated_task);
+    rq.inner.nr_migrations.fetch_add(1, Ordering::Relaxed);
+    rq.inner.enqueue(migrated_task, false)
 }
 
 /// Clear the `on_cpu` field of previous task running on this CPU.
@@ -644,6 +828,7 @@ pub(crate) fn init() {
     unsafe {
         RUN_QUEUES[cpu_id].write(RUN_QUEUE.current_ref_mut_raw());
     }
+    RUN_QUEUES_ONLINE[cpu_id].store(true, Ordering::Release);
 }
 
 pub(crate) fn init_secondary() {
@@ -663,4 +848,46 @@ pub(crate) fn init_secondary() {
     unsafe {
         RUN_QUEUES[cpu_id].write(RUN_QUEUE.current_ref_mut_raw());
     }
+    RUN_QUEUES_ONLINE[cpu_id].store(true, Ordering::Release);
+}
+
+/// Statistics of the run queue of a CPU, returned by [`run_queue_stats`].
+///
+/// [`run_queue_stats`]: crate::run_queue_stats
+#[derive(Debug, Clone, Copy)]
+pub struct RunQueueStats {
+    /// The ID of the CPU.
+    pub cpu_id: usize,
+    /// The number of tasks ready to run, excluding the running one.
+    pub nr_ready: usize,
+    /// The number of context switches.
+    pub nr_switches: u64,
+    /// The number of tasks taken from the run queues of other CPUs, when this
+    /// CPU ran out of tasks or during the periodic load balancing.
+    pub nr_stolen: u64,
+    /// The number of tasks moved to this CPU as their CPU affinity changed.
+    pub nr_migrations: u64,
+}
+
+/// Returns the RCU quiescent state counters of all CPUs, indexed by CPU ID.
+///
+/// Offline CPUs report an odd value, as if they were idle.
+pub(crate) fn quiescent_states() -> Vec<usize> {
+    let mut states = alloc::vec![1; axconfig::SMP];
+    for rq in online_run_queues() {
+        states[rq.cpu_id] = rq.quiescent_state.load(Ordering::Acquire);
+    }
+    states
+}
+
+pub(crate) fn stats() -> Vec<RunQueueStats> {
+    online_run_queues()
+        .map(|rq| RunQueueStats {
+            cpu_id: rq.cpu_id,
+            nr_ready: rq.nr_ready.load(Ordering::Relaxed),
+            nr_switches: rq.nr_switches.load(Ordering::Relaxed),
+            nr_stolen: rq.nr_stolen.load(Ordering::Relaxed),
+            nr_migrations: rq.nr_migrations.load(Ordering::Relaxed),
+        })
+        .collect()
 }
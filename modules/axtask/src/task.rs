@@ -1,20 +1,21 @@
 use alloc::{boxed::Box, string::String, sync::Arc};
 use core::ops::Deref;
-use core::sync::atomic::{AtomicBool, AtomicI32, AtomicU8, AtomicU64, Ordering};
-use core::{alloc::Layout, cell::UnsafeCell, fmt, ptr::NonNull};
-
-#[cfg(feature = "preempt")]
-use core::sync::atomic::AtomicUsize;
+use core::sync::atomic::{
+    AtomicBool, AtomicI32, AtomicIsize, AtomicU8, AtomicU64, AtomicUsize, Ordering,
+};
+#[cfg(not(feature = "paging"))]
+use core::{alloc::Layout, ptr::NonNull};
+use core::{cell::UnsafeCell, fmt};
 
 use kspin::SpinNoIrq;
-use memory_addr::{VirtAddr, align_up_4k};
+use memory_addr::{PAGE_SIZE_4K, VirtAddr};
 
 use axhal::arch::TaskContext;
 #[cfg(feature = "tls")]
 use axhal::tls::TlsArea;
 
 use crate::task_ext::AxTaskExt;
-use crate::{AxCpuMask, AxTask, AxTaskRef, WaitQueue};
+use crate::{AxCpuMask, AxTask, AxTaskRef, TaskGroup, WaitQueue};
 
 /// A unique identifier for a thread.
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
@@ -47,9 +48,19 @@ pub struct TaskInner {
 
     /// CPU affinity mask.
     cpumask: SpinNoIrq<AxCpuMask>,
+    /// The priority set by [`set_priority`](crate::set_priority).
+    priority: AtomicIsize,
 
     /// Mark whether the task is in the wait queue.
     in_wait_queue: AtomicBool,
+    /// Whether the task is interrupted, see [`interrupt`](crate::interrupt).
+    interrupted: AtomicBool,
+    /// The address of the wait queue of the interruptible wait the task is
+    /// in, or 0 if it's not in one.
+    interruptible_wq: SpinNoIrq<usize>,
+    /// The depth of the system calls the task is in, see
+    /// [`syscall_depth`](crate::syscall_depth).
+    syscall_depth: AtomicUsize,
 
     /// Used to indicate whether the task is running on a CPU.
     #[cfg(feature = "smp")]
@@ -69,6 +80,14 @@ pub struct TaskInner {
     exit_code: AtomicI32,
     wait_for_exit: WaitQueue,
 
+    /// The CPU time consumed until the task was last switched out, in
+    /// nanoseconds.
+    cpu_time: AtomicU64,
+    /// The monotonic time when the task was last switched in, in nanoseconds.
+    switched_in_at: AtomicU64,
+    /// The group the resources used by the task are accounted to.
+    group: SpinNoIrq<Option<Arc<TaskGroup>>>,
+
     kstack: Option<TaskStack>,
     ctx: UnsafeCell<TaskContext>,
     task_ext: AxTaskExt,
@@ -107,26 +126,38 @@ unsafe impl Sync for TaskInner {}
 
 impl TaskInner {
     /// Create a new task with the given entry function and stack size.
+    ///
+    /// Panics if the stack can't be allocated, see [`TaskInner::try_new`].
     pub fn new<F>(entry: F, name: String, stack_size: usize) -> Self
     where
         F: FnOnce() + Send + 'static,
     {
+        Self::try_new(entry, name, stack_size).expect("failed to allocate the kernel stack")
+    }
+
+    /// Create a new task with the given entry function and stack size, or
+    /// returns `None` if the stack can't be allocated.
+    pub fn try_new<F>(entry: F, name: String, stack_size: usize) -> Option<Self>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let kstack = TaskStack::try_alloc(stack_size.checked_next_multiple_of(PAGE_SIZE_4K)?)?;
         let mut t = Self::new_common(TaskId::new(), name);
         debug!("new task: {}", t.id_name());
-        let kstack = TaskStack::alloc(align_up_4k(stack_size));
 
         #[cfg(feature = "tls")]
         let tls = VirtAddr::from(t.tls.tls_ptr() as usize);
         #[cfg(not(feature = "tls"))]
         let tls = VirtAddr::from(0);
 
+        t.set_group(crate::current_group());
         t.entry = Some(Box::into_raw(Box::new(entry)));
         t.ctx_mut().init(task_entry as usize, kstack.top(), tls);
         t.kstack = Some(kstack);
         if t.name() == "idle" {
             t.is_idle = true;
         }
-        t
+        Some(t)
     }
 
     /// Gets the ID of the task.
@@ -217,6 +248,20 @@ impl TaskInner {
         *self.cpumask.lock() = cpumask
     }
 
+    /// Gets the priority of the task.
+    ///
+    /// It's 0 unless set by [`set_priority`](crate::set_priority), and its
+    /// meaning depends on the scheduler.
+    #[inline]
+    pub fn priority(&self) -> isize {
+        self.priority.load(Ordering::Acquire)
+    }
+
+    #[inline]
+    pub(crate) fn set_priority(&self, prio: isize) {
+        self.priority.store(prio, Ordering::Release)
+    }
+
     /// Read the top address of the kernel stack for the task.
     #[inline]
     pub fn get_kernel_stack_top(&self) -> Option<usize> {
@@ -230,6 +275,40 @@ impl TaskInner {
     pub fn exit_code(&self) -> i32 {
         self.exit_code.load(Ordering::Acquire)
     }
+
+    /// Returns the CPU time consumed by the task.
+    pub fn cpu_time(&self) -> core::time::Duration {
+        let mut nanos = self.cpu_time.load(Ordering::Acquire);
+        if self.is_running() {
+            let now = axhal::time::monotonic_time_nanos();
+            nanos += now.saturating_sub(self.switched_in_at.load(Ordering::Acquire));
+        }
+        core::time::Duration::from_nanos(nanos)
+    }
+
+    /// Returns the group of the task.
+    pub fn group(&self) -> Option<Arc<TaskGroup>> {
+        self.group.lock().clone()
+    }
+
+    /// Moves the task to `group`, or out of its group if `None`.
+    pub fn set_group(&self, group: Option<Arc<TaskGroup>>) {
+        if let Some(group) = &group {
+            group.add_task();
+        }
+        // drop the old group out of the lock, as the allocator takes it
+        let old = core::mem::replace(&mut *self.group.lock(), group);
+        if let Some(old) = old {
+            old.remove_task();
+        }
+    }
+
+    /// Calls `f` with the group of the task, if it's in one.
+    pub(crate) fn with_group(&self, f: impl FnOnce(&TaskGroup)) {
+        if let Some(group) = self.group.lock().as_deref() {
+            f(group);
+        }
+    }
 }
 
 // private methods
@@ -244,7 +323,11 @@ impl TaskInner {
             state: AtomicU8::new(TaskState::Ready as u8),
             // By default, the task is allowed to run on all CPUs.
             cpumask: SpinNoIrq::new(AxCpuMask::full()),
+            priority: AtomicIsize::new(0),
             in_wait_queue: AtomicBool::new(false),
+            interrupted: AtomicBool::new(false),
+            interruptible_wq: SpinNoIrq::new(0),
+            syscall_depth: AtomicUsize::new(0),
             #[cfg(feature = "irq")]
             timer_ticket_id: AtomicU64::new(0),
             #[cfg(feature = "smp")]
@@ -255,6 +338,9 @@ impl TaskInner {
             preempt_disable_count: AtomicUsize::new(0),
             exit_code: AtomicI32::new(0),
             wait_for_exit: WaitQueue::new(),
+            cpu_time: AtomicU64::new(0),
+            switched_in_at: AtomicU64::new(axhal::time::monotonic_time_nanos()),
+            group: SpinNoIrq::new(None),
             kstack: None,
             ctx: UnsafeCell::new(TaskContext::new()),
             task_ext: AxTaskExt::empty(),
@@ -343,6 +429,46 @@ impl TaskInner {
         self.in_wait_queue.store(in_wait_queue, Ordering::Release);
     }
 
+    #[inline]
+    pub(crate) fn is_interrupted(&self) -> bool {
+        self.interrupted.load(Ordering::Acquire)
+    }
+
+    #[inline]
+    pub(crate) fn set_interrupted(&self, interrupted: bool) {
+        self.interrupted.store(interrupted, Ordering::Release);
+    }
+
+    #[inline]
+    pub(crate) fn syscall_depth(&self) -> usize {
+        self.syscall_depth.load(Ordering::Relaxed)
+    }
+
+    #[inline]
+    pub(crate) fn set_syscall_depth(&self, depth: usize) {
+        self.syscall_depth.store(depth, Ordering::Relaxed);
+    }
+
+    /// Records the wait queue of the interruptible wait the task enters, or
+    /// clears it with `None` when the task leaves it.
+    ///
+    /// The wait queue can't be freed while it's being interrupted, as clearing
+    /// it waits for [`interrupt`](crate::interrupt) to finish.
+    pub(crate) fn set_interruptible_wq(&self, wq: Option<&WaitQueue>) {
+        *self.interruptible_wq.lock() = wq.map_or(0, |wq| wq as *const _ as usize);
+    }
+
+    /// Runs `f` with the wait queue of the interruptible wait the task is in,
+    /// if any.
+    pub(crate) fn with_interruptible_wq(&self, f: impl FnOnce(&WaitQueue)) {
+        let wq = self.interruptible_wq.lock();
+        if *wq != 0 {
+            // SAFETY: the wait queue outlives the wait, which clears it
+            // with the lock held.
+            f(unsafe { &*(*wq as *const WaitQueue) });
+        }
+    }
+
     /// Returns task's current timer ticket ID.
     #[inline]
     #[cfg(feature = "irq")]
@@ -439,6 +565,16 @@ impl TaskInner {
     pub(crate) fn set_on_cpu(&self, on_cpu: bool) {
         self.on_cpu.store(on_cpu, Ordering::Release)
     }
+
+    /// Accounts the CPU time at a context switch from this task to `next`.
+    pub(crate) fn account_switch_to(&self, next: &TaskInner) {
+        let now = axhal::time::monotonic_time_nanos();
+        let start = self.switched_in_at.load(Ordering::Acquire);
+        let elapsed = now.saturating_sub(start);
+        self.cpu_time.fetch_add(elapsed, Ordering::AcqRel);
+        self.with_group(|group| group.add_cpu_time(elapsed));
+        next.switched_in_at.store(now, Ordering::Release);
+    }
 }
 
 impl fmt::Debug for TaskInner {
@@ -454,21 +590,26 @@ impl fmt::Debug for TaskInner {
 impl Drop for TaskInner {
     fn drop(&mut self) {
         debug!("task drop: {}", self.id_name());
+        if let Some(group) = self.group.get_mut() {
+            group.remove_task();
+        }
     }
 }
 
+#[cfg(not(feature = "paging"))]
 struct TaskStack {
     ptr: NonNull<u8>,
     layout: Layout,
 }
 
+#[cfg(not(feature = "paging"))]
 impl TaskStack {
-    pub fn alloc(size: usize) -> Self {
-        let layout = Layout::from_size_align(size, 16).unwrap();
-        Self {
-            ptr: NonNull::new(unsafe { alloc::alloc::alloc(layout) }).unwrap(),
+    pub fn try_alloc(size: usize) -> Option<Self> {
+        let layout = Layout::from_size_align(size, 16).ok()?;
+        Some(Self {
+            ptr: NonNull::new(unsafe { alloc::alloc::alloc(layout) })?,
             layout,
-        }
+        })
     }
 
     pub const fn top(&self) -> VirtAddr {
@@ -476,12 +617,39 @@ impl TaskStack {
     }
 }
 
+#[cfg(not(feature = "paging"))]
 impl Drop for TaskStack {
     fn drop(&mut self) {
         unsafe { alloc::alloc::dealloc(self.ptr.as_ptr(), self.layout) }
     }
 }
 
+/// A kernel stack mapped with a guard area below, where an overflow faults.
+#[cfg(feature = "paging")]
+struct TaskStack {
+    top: VirtAddr,
+    size: usize,
+}
+
+#[cfg(feature = "paging")]
+impl TaskStack {
+    pub fn try_alloc(size: usize) -> Option<Self> {
+        let top = axmm::alloc_kernel_stack(size).ok()?;
+        Some(Self { top, size })
+    }
+
+    pub const fn top(&self) -> VirtAddr {
+        self.top
+    }
+}
+
+#[cfg(feature = "paging")]
+impl Drop for TaskStack {
+    fn drop(&mut self) {
+        axmm::dealloc_kernel_stack(self.top, self.size);
+    }
+}
+
 use core::mem::ManuallyDrop;
 
 /// A wrapper of [`AxTaskRef`] as the current task.
@@ -1,7 +1,7 @@
-use core::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::{Mutex, Once};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Once};
 
-use crate::{WaitQueue, api as axtask, current};
+use crate::{WaitQueue, WaitResult, api as axtask, current};
 
 static INIT: Once = Once::new();
 static SERIAL: Mutex<()> = Mutex::new(());
@@ -128,3 +128,72 @@ fn test_task_join() {
         assert_eq!(tasks[i].join(), Some(i as _));
     }
 }
+
+#[test]
+fn test_interruptible_wait() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    static WQ: WaitQueue = WaitQueue::new();
+    static CLEARED: AtomicBool = AtomicBool::new(false);
+
+    let task = axtask::spawn(|| {
+        assert_eq!(WQ.wait_interruptible(), WaitResult::Interrupted);
+        // the interrupt is pending until cleared
+        assert_eq!(WQ.wait_interruptible(), WaitResult::Interrupted);
+        assert!(axtask::interrupt_pending());
+        axtask::clear_interrupt();
+        CLEARED.store(true, Ordering::Release);
+
+        assert_eq!(WQ.wait_interruptible(), WaitResult::Notified);
+        assert!(!current().in_wait_queue());
+        axtask::exit(0);
+    });
+
+    while !task.in_wait_queue() {
+        axtask::yield_now();
+    }
+    axtask::interrupt(&task);
+    while !CLEARED.load(Ordering::Acquire) || !task.in_wait_queue() {
+        axtask::yield_now();
+    }
+    assert!(WQ.notify_one(true));
+    assert_eq!(task.join(), Some(0));
+    assert!(WQ.is_empty());
+}
+
+#[test]
+fn test_task_group() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    let group = axtask::task_group("test");
+    axtask::set_current_group(Some(group.clone()));
+    let task = axtask::spawn(|| {
+        // spawned tasks inherit the group
+        assert_eq!(axtask::current_group().unwrap().name(), "test");
+        axtask::account_net(10, 20);
+        axtask::exit(0);
+    });
+    axtask::set_current_group(None);
+    assert!(axtask::current_group().is_none());
+    assert_eq!(group.usage().tasks, 1);
+
+    assert_eq!(task.join(), Some(0));
+    let usage = group.usage();
+    assert_eq!((usage.net_rx_bytes, usage.net_tx_bytes), (10, 20));
+    assert!(Arc::ptr_eq(&axtask::task_group("test"), &group));
+}
+
+#[test]
+fn test_try_spawn_huge_stack() {
+    let _lock = SERIAL.lock();
+    INIT.call_once(axtask::init_scheduler);
+
+    // too large to be rounded up, or to be allocated
+    for size in [usize::MAX, isize::MAX as usize + 1] {
+        assert!(axtask::try_spawn_raw(|| unreachable!(), "huge".into(), size).is_none());
+    }
+    let task = axtask::try_spawn_raw(|| axtask::exit(0), "small".into(), 0x1000).unwrap();
+    assert_eq!(task.join(), Some(0));
+}
@@ -0,0 +1,189 @@
+//! A hierarchical timer wheel.
+//!
+//! Time is divided into slots of `1 << GRANULARITY_SHIFT` nanoseconds. Each
+//! level of the wheel has 64 slots, and a slot of a level spans all the slots
+//! of the level below. A timer is put into the lowest level whose current span
+//! covers its deadline, so the timers of a level always expire before the ones
+//! of the levels above. When the clock of the wheel enters a new span, the
+//! timers of that span are cascaded down to the lower levels.
+//!
+//! Timers keep their exact deadlines, the slots only bound the cost of finding
+//! and expiring them.
+
+use alloc::vec::Vec;
+
+const GRANULARITY_SHIFT: u32 = 16;
+const LEVEL_BITS: u32 = 6;
+const LEVEL_SIZE: usize = 1 << LEVEL_BITS;
+const LEVEL_MASK: u64 = LEVEL_SIZE as u64 - 1;
+/// Enough levels to cover all 64-bit deadlines.
+const LEVELS: usize = (64 - GRANULARITY_SHIFT).div_ceil(LEVEL_BITS) as usize;
+
+/// A timer wheel of events of type `E`, with deadlines in nanoseconds.
+pub(crate) struct TimerWheel<E> {
+    /// The current slot. The events of earlier slots have all expired.
+    clock: u64,
+    slots: [[Vec<(u64, E)>; LEVEL_SIZE]; LEVELS],
+}
+
+const fn level_shift(level: usize) -> u32 {
+    level as u32 * LEVEL_BITS
+}
+
+impl<E> TimerWheel<E> {
+    pub fn new() -> Self {
+        Self {
+            clock: 0,
+            slots: core::array::from_fn(|_| core::array::from_fn(|_| Vec::new())),
+        }
+    }
+
+    /// Adds an event that expires at `deadline`.
+    pub fn set(&mut self, deadline: u64, event: E) {
+        // events in the past expire from the current slot
+        let slot = (deadline >> GRANULARITY_SHIFT).max(self.clock);
+        let diff = slot ^ self.clock;
+        let level = if diff == 0 {
+            0
+        } else {
+            ((u64::BITS - 1 - diff.leading_zeros()) / LEVEL_BITS) as usize
+        };
+        let index = (slot >> level_shift(level)) & LEVEL_MASK;
+        self.slots[level][index as usize].push((deadline, event));
+    }
+
+    /// Returns the level and start of the first non-empty slot after the
+    /// current one.
+    fn next_slot(&self) -> Option<(usize, u64)> {
+        for level in 0..LEVELS {
+            let shift = level_shift(level);
+            let current = (self.clock >> shift) & LEVEL_MASK;
+            for index in current + 1..LEVEL_SIZE as u64 {
+                if !self.slots[level][index as usize].is_empty() {
+                    let span_shift = shift + LEVEL_BITS;
+                    let base = self.clock.checked_shr(span_shift).unwrap_or(0) << span_shift;
+                    return Some((level, base | (index << shift)));
+                }
+            }
+        }
+        None
+    }
+
+    /// Returns the earliest deadline of the events.
+    pub fn next_deadline(&self) -> Option<u64> {
+        let current = &self.slots[0][(self.clock & LEVEL_MASK) as usize];
+        let slot = if !current.is_empty() {
+            current
+        } else {
+            let (level, start) = self.next_slot()?;
+            &self.slots[level][((start >> level_shift(level)) & LEVEL_MASK) as usize]
+        };
+        slot.iter().map(|(deadline, _)| *deadline).min()
+    }
+
+    /// Moves the clock to `slot`, cascading the timers of the new spans.
+    ///
+    /// There must be no events in the slots skipped over.
+    fn advance(&mut self, slot: u64) {
+        let old = self.clock;
+        self.clock = slot;
+        for level in (1..LEVELS).rev() {
+            let shift = level_shift(level);
+            if old >> shift != slot >> shift {
+                let index = (slot >> shift) & LEVEL_MASK;
+                for (deadline, event) in core::mem::take(&mut self.slots[level][index as usize]) {
+                    self.set(deadline, event);
+                }
+            }
+        }
+    }
+
+    /// Removes and returns an event whose deadline is not after `now`.
+    pub fn expire_one(&mut self, now: u64) -> Option<(u64, E)> {
+        let target = now >> GRANULARITY_SHIFT;
+        loop {
+            let current = &mut self.slots[0][(self.clock & LEVEL_MASK) as usize];
+            if let Some(i) = current.iter().position(|(deadline, _)| *deadline <= now) {
+                return Some(current.swap_remove(i));
+            }
+            if self.clock >= target {
+                return None;
+            }
+            let next = self
+                .next_slot()
+                .map_or(target, |(_, start)| start.min(target));
+            self.advance(next);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SLOT: u64 = 1 << GRANULARITY_SHIFT;
+
+    fn expire_all(wheel: &mut TimerWheel<char>, now: u64) -> Vec<char> {
+        core::iter::from_fn(|| wheel.expire_one(now))
+            .map(|(_, event)| event)
+            .collect()
+    }
+
+    #[test]
+    fn test_expire_in_order() {
+        let mut wheel = TimerWheel::new();
+        wheel.set(3 * SLOT + 5, 'a');
+        wheel.set(SLOT, 'b');
+        // in the second level
+        wheel.set(70 * SLOT, 'c');
+        assert_eq!(wheel.next_deadline(), Some(SLOT));
+        assert_eq!(wheel.expire_one(SLOT - 1), None);
+        assert_eq!(expire_all(&mut wheel, 3 * SLOT + 5), ['b', 'a']);
+        assert_eq!(wheel.next_deadline(), Some(70 * SLOT));
+        assert!(expire_all(&mut wheel, 70 * SLOT - 1).is_empty());
+        assert_eq!(expire_all(&mut wheel, 70 * SLOT), ['c']);
+        assert_eq!(wheel.next_deadline(), None);
+    }
+
+    #[test]
+    fn test_exact_deadlines() {
+        let mut wheel = TimerWheel::new();
+        wheel.set(SLOT + 200, 'x');
+        wheel.set(SLOT + 100, 'y');
+        assert_eq!(wheel.next_deadline(), Some(SLOT + 100));
+        assert_eq!(wheel.expire_one(SLOT + 150), Some((SLOT + 100, 'y')));
+        assert_eq!(wheel.expire_one(SLOT + 150), None);
+        assert_eq!(wheel.next_deadline(), Some(SLOT + 200));
+        assert_eq!(wheel.expire_one(SLOT + 200), Some((SLOT + 200, 'x')));
+    }
+
+    #[test]
+    fn test_cascade() {
+        let mut wheel = TimerWheel::new();
+        let far = 1 << 40;
+        // in the fifth level, cascaded down level by level
+        wheel.set(far + 1, 'f');
+        wheel.set(far - SLOT, 'e');
+        assert_eq!(wheel.next_deadline(), Some(far - SLOT));
+        assert_eq!(expire_all(&mut wheel, far), ['e']);
+        assert_eq!(wheel.next_deadline(), Some(far + 1));
+        assert_eq!(expire_all(&mut wheel, far + 1), ['f']);
+
+        // the last slot of the top level
+        wheel.set(u64::MAX, 'm');
+        assert_eq!(wheel.next_deadline(), Some(u64::MAX));
+        assert!(expire_all(&mut wheel, u64::MAX - 1).is_empty());
+        assert_eq!(expire_all(&mut wheel, u64::MAX), ['m']);
+    }
+
+    #[test]
+    fn test_past_deadlines() {
+        let mut wheel = TimerWheel::new();
+        wheel.set(100 * SLOT, 'a');
+        assert_eq!(expire_all(&mut wheel, 100 * SLOT), ['a']);
+        // expire from the current slot
+        wheel.set(5, 'p');
+        assert_eq!(wheel.next_deadline(), Some(5));
+        assert_eq!(wheel.expire_one(100 * SLOT), Some((5, 'p')));
+    }
+}
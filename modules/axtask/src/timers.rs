@@ -1,26 +1,60 @@
+//! Timed events and the timer interrupt.
+//!
+//! Each CPU has a [timer wheel](TimerWheel) of the tasks waiting for a
+//! deadline. The timer is not periodic: it's programmed to the nearest
+//! deadline, or to the next scheduler tick if the CPU is running a task. An
+//! idle CPU stops the tick and sleeps until the next deadline, so that it's
+//! woken up only when there's something to do.
+//!
+//! With multiple CPUs, a CPU putting a task into the run queue of an idle CPU
+//! whose tick is stopped wakes it up with an IPI ([`kick`]). The busy CPUs
+//! also wake up an idle one on their load balancing ticks, to take the tasks
+//! waiting in their run queues, as the idle CPUs don't tick to take them.
+//! Without `preempt`, a task queued right before the idle CPU halts waits for
+//! the next timer interrupt of the CPU, as it does on a single CPU.
+
+#[cfg(feature = "smp")]
+use core::sync::atomic::{AtomicBool, fence};
 use core::sync::atomic::{AtomicU64, Ordering};
 
-use kernel_guard::NoOp;
+use kernel_guard::{NoOp, NoPreemptIrqSave};
 use lazyinit::LazyInit;
-use timer_list::{TimeValue, TimerEvent, TimerList};
 
-use axhal::time::wall_time;
+use axhal::time::{NANOS_PER_SEC, TimeValue, monotonic_time_nanos};
 
+use crate::timer_wheel::TimerWheel;
 use crate::{AxTaskRef, select_run_queue};
 
+/// The interval between scheduler ticks.
+const TICK_NANOS: u64 = NANOS_PER_SEC / axconfig::TICKS_PER_SEC as u64;
+
+/// The maximum interval the timer is programmed with, to stay in the range of
+/// the hardware timers.
+const MAX_INTERVAL_NANOS: u64 = NANOS_PER_SEC;
+
 static TIMER_TICKET_ID: AtomicU64 = AtomicU64::new(1);
 
 percpu_static! {
-    TIMER_LIST: LazyInit<TimerList<TaskWakeupEvent>> = LazyInit::new(),
+    TIMER_WHEEL: LazyInit<TimerWheel<TaskWakeupEvent>> = LazyInit::new(),
+    /// The time of the next scheduler tick, or `u64::MAX` if the tick is
+    /// stopped.
+    NEXT_TICK: u64 = u64::MAX,
+    /// The deadline the timer is programmed with.
+    TIMER_DEADLINE: u64 = u64::MAX,
 }
 
+/// Whether each CPU is idle with its tick stopped.
+#[cfg(feature = "smp")]
+static TICK_STOPPED: [AtomicBool; axconfig::SMP] =
+    [const { AtomicBool::new(false) }; axconfig::SMP];
+
 struct TaskWakeupEvent {
     ticket_id: u64,
     task: AxTaskRef,
 }
 
-impl TimerEvent for TaskWakeupEvent {
-    fn callback(self, _now: TimeValue) {
+impl TaskWakeupEvent {
+    fn callback(self) {
         // Ignore the timer event if timeout was set but not triggered
         // (wake up by `WaitQueue::notify()`).
         // Judge if this timer event is still valid by checking the ticket ID.
@@ -35,32 +69,140 @@ impl TimerEvent for TaskWakeupEvent {
     }
 }
 
+/// Whether the current CPU needs the scheduler tick.
+fn needs_tick() -> bool {
+    !crate::current().is_idle()
+}
+
+/// Records whether the tick of the current CPU is stopped.
+#[cfg(feature = "smp")]
+fn set_tick_stopped(stopped: bool) {
+    TICK_STOPPED[axhal::cpu::this_cpu_id()].store(stopped, Ordering::SeqCst);
+    // pairs with the fence in `kick`: either the CPU putting a task into the
+    // run queue sees the tick stopped, or the idle task sees the task
+    fence(Ordering::SeqCst);
+}
+
+/// Wakes up the CPU `cpu_id` if it's idle with its tick stopped, after a
+/// task is put into its run queue.
+#[cfg(feature = "smp")]
+pub fn kick(cpu_id: usize) {
+    fence(Ordering::SeqCst);
+    if TICK_STOPPED[cpu_id].load(Ordering::SeqCst) {
+        axhal::irq::ipi::wake_up_cpu(cpu_id);
+    }
+}
+
+/// Wakes up an idle CPU with its tick stopped, if any, to take the tasks
+/// waiting in the run queue of the current CPU.
+#[cfg(feature = "smp")]
+pub fn kick_idle_cpu() {
+    if let Some(cpu_id) = (0..axconfig::SMP).find(|&i| TICK_STOPPED[i].load(Ordering::Acquire)) {
+        axhal::irq::ipi::wake_up_cpu(cpu_id);
+    }
+}
+
+/// Runs on the CPUs woken up by [`kick`], in the IPI handler.
+#[cfg(feature = "smp")]
+fn on_wakeup() {
+    // leave the idle task when the IRQ returns, even if it's about to halt
+    #[cfg(feature = "preempt")]
+    if crate::current().is_idle() {
+        crate::current().set_preempt_pending(true);
+    }
+}
+
+/// Programs the timer with the nearest of the next tick and the first
+/// deadline of the timer wheel.
+///
+/// IRQs must be disabled.
+fn program_timer(now: u64) {
+    // Safety: IRQs are disabled at this time.
+    let (wheel, next_tick) =
+        unsafe { (TIMER_WHEEL.current_ref_raw(), NEXT_TICK.read_current_raw()) };
+    let deadline = wheel
+        .next_deadline()
+        .unwrap_or(u64::MAX)
+        .min(next_tick)
+        .min(now + MAX_INTERVAL_NANOS);
+    unsafe { TIMER_DEADLINE.write_current_raw(deadline) };
+    axhal::time::set_oneshot_timer(deadline);
+}
+
+/// Wakes up `task` at `deadline`, in monotonic time.
 pub fn set_alarm_wakeup(deadline: TimeValue, task: AxTaskRef) {
-    TIMER_LIST.with_current(|timer_list| {
-        let ticket_id = TIMER_TICKET_ID.fetch_add(1, Ordering::AcqRel);
-        task.set_timer_ticket(ticket_id);
-        timer_list.set(deadline, TaskWakeupEvent { ticket_id, task });
-    })
+    let _guard = NoPreemptIrqSave::new();
+    let deadline = deadline.as_nanos() as u64;
+    let ticket_id = TIMER_TICKET_ID.fetch_add(1, Ordering::AcqRel);
+    task.set_timer_ticket(ticket_id);
+    // Safety: IRQs are disabled at this time.
+    unsafe { TIMER_WHEEL.current_ref_mut_raw() }.set(deadline, TaskWakeupEvent { ticket_id, task });
+    if deadline < unsafe { TIMER_DEADLINE.read_current_raw() } {
+        program_timer(monotonic_time_nanos());
+    }
+}
+
+/// Restarts the scheduler tick when the CPU leaves the idle task.
+///
+/// IRQs must be disabled.
+pub fn restart_tick() {
+    // Safety: IRQs are disabled at this time.
+    if unsafe { NEXT_TICK.read_current_raw() } == u64::MAX {
+        #[cfg(feature = "smp")]
+        set_tick_stopped(false);
+        let now = monotonic_time_nanos();
+        unsafe { NEXT_TICK.write_current_raw(now + TICK_NANOS) };
+        program_timer(now);
+    }
 }
 
-pub fn check_events() {
+/// Handles the timer interrupt: wakes up the tasks whose deadlines have
+/// passed, and reprograms the timer.
+///
+/// Returns whether it's time for a scheduler tick.
+pub fn on_timer_irq() -> bool {
     loop {
-        let now = wall_time();
+        let now = monotonic_time_nanos();
         let event = unsafe {
             // Safety: IRQs are disabled at this time.
-            TIMER_LIST.current_ref_mut_raw()
+            TIMER_WHEEL.current_ref_mut_raw()
         }
         .expire_one(now);
         if let Some((_deadline, event)) = event {
-            event.callback(now);
+            event.callback();
         } else {
             break;
         }
     }
+
+    let now = monotonic_time_nanos();
+    let next_tick = unsafe { NEXT_TICK.read_current_raw() };
+    let tick = now >= next_tick;
+    let next_tick = if !needs_tick() {
+        u64::MAX
+    } else if next_tick == u64::MAX || now >= next_tick + TICK_NANOS {
+        // restarted, or too late for the next tick
+        now + TICK_NANOS
+    } else if tick {
+        next_tick + TICK_NANOS
+    } else {
+        next_tick
+    };
+    unsafe { NEXT_TICK.write_current_raw(next_tick) };
+    #[cfg(feature = "smp")]
+    if next_tick == u64::MAX {
+        set_tick_stopped(true);
+    }
+    program_timer(now);
+    tick
 }
 
 pub fn init() {
-    TIMER_LIST.with_current(|timer_list| {
-        timer_list.init_once(TimerList::new());
+    TIMER_WHEEL.with_current(|wheel| {
+        wheel.init_once(TimerWheel::new());
     });
+    #[cfg(feature = "smp")]
+    axhal::irq::ipi::set_wakeup_handler(on_wakeup);
+    let _guard = NoPreemptIrqSave::new();
+    restart_tick();
 }
@@ -33,6 +33,18 @@ pub struct WaitQueue {
     queue: SpinNoIrq<VecDeque<AxTaskRef>>,
 }
 
+/// How an interruptible wait ended, see
+/// [`WaitQueue::wait_timeout_interruptible`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaitResult {
+    /// The task was notified, or the condition it waited for became true.
+    Notified,
+    /// The timeout elapsed.
+    TimedOut,
+    /// The task was interrupted by [`interrupt`](crate::interrupt).
+    Interrupted,
+}
+
 pub(crate) type WaitQueueGuard<'a> = SpinNoIrqGuard<'a, VecDeque<AxTaskRef>>;
 
 impl WaitQueue {
@@ -67,7 +79,7 @@ impl WaitQueue {
         if _from_timer_list {
             curr.timer_ticket_expired();
             // Note:
-            //  this task is still not removed from timer list of target CPU,
+            //  this task is still not removed from the timer wheel of target CPU,
             //  which may cause some redundant timer events because it still needs to
             //  go through the process of expiring an event from the timer list and invoking the callback.
             //  (it can be considered a lazy-removal strategy, it will be ignored when it is about to take effect.)
@@ -109,7 +121,7 @@ impl WaitQueue {
     pub fn wait_timeout(&self, dur: core::time::Duration) -> bool {
         let mut rq = current_run_queue::<NoPreemptIrqSave>();
         let curr = crate::current();
-        let deadline = axhal::time::wall_time() + dur;
+        let deadline = axhal::time::monotonic_time() + dur;
         debug!(
             "task wait_timeout: {} deadline={:?}",
             curr.id_name(),
@@ -137,7 +149,7 @@ impl WaitQueue {
         F: Fn() -> bool,
     {
         let curr = crate::current();
-        let deadline = axhal::time::wall_time() + dur;
+        let deadline = axhal::time::monotonic_time() + dur;
         debug!(
             "task wait_timeout: {}, deadline={:?}",
             curr.id_name(),
@@ -148,7 +160,7 @@ impl WaitQueue {
         let mut timeout = true;
         loop {
             let mut rq = current_run_queue::<NoPreemptIrqSave>();
-            if axhal::time::wall_time() >= deadline {
+            if axhal::time::monotonic_time() >= deadline {
                 break;
             }
             let wq = self.queue.lock();
@@ -165,6 +177,136 @@ impl WaitQueue {
         timeout
     }
 
+    /// Blocks the current task and put it into the wait queue, until other tasks
+    /// notify it, or it's interrupted by [`interrupt`](crate::interrupt).
+    ///
+    /// It returns right away if the task has an interrupt pending, which is
+    /// kept until [`clear_interrupt`](crate::clear_interrupt) is called.
+    pub fn wait_interruptible(&self) -> WaitResult {
+        self.wait_interruptible_impl(None)
+    }
+
+    /// Blocks the current task and put it into the wait queue, until other tasks
+    /// notify it, it's interrupted by [`interrupt`](crate::interrupt), or the
+    /// given duration has elapsed.
+    ///
+    /// Unlike [`wait_timeout`](Self::wait_timeout), the result tells the three
+    /// cases apart. A notification racing with the timeout or an interrupt wins.
+    #[cfg(feature = "irq")]
+    pub fn wait_timeout_interruptible(&self, dur: core::time::Duration) -> WaitResult {
+        self.wait_interruptible_impl(Some(axhal::time::monotonic_time() + dur))
+    }
+
+    fn wait_interruptible_impl(&self, deadline: Option<axhal::time::TimeValue>) -> WaitResult {
+        let curr = crate::current();
+        if curr.is_interrupted() {
+            return WaitResult::Interrupted;
+        }
+        #[cfg(feature = "irq")]
+        if let Some(deadline) = deadline {
+            debug!(
+                "task wait_timeout_interruptible: {} deadline={:?}",
+                curr.id_name(),
+                deadline
+            );
+            crate::timers::set_alarm_wakeup(deadline, curr.clone());
+        }
+        curr.set_interruptible_wq(Some(self));
+
+        let mut rq = current_run_queue::<NoPreemptIrqSave>();
+        let wq = self.queue.lock();
+        // Checked with the wait queue locked, so `interrupt()` either comes
+        // before, or finds the task in the wait queue.
+        let notified = if curr.is_interrupted() {
+            drop(wq);
+            false
+        } else {
+            rq.blocked_resched(wq);
+            // still in the wait queue, must have timed out or been interrupted
+            !curr.in_wait_queue()
+        };
+        drop(rq);
+
+        self.cancel_events(curr.clone(), deadline.is_some());
+        curr.set_interruptible_wq(None);
+        if notified {
+            WaitResult::Notified
+        } else if curr.is_interrupted() {
+            WaitResult::Interrupted
+        } else {
+            WaitResult::TimedOut
+        }
+    }
+
+    /// Blocks the current task and put it into the wait queue, until the given
+    /// `condition` becomes true, or it's interrupted by
+    /// [`interrupt`](crate::interrupt).
+    ///
+    /// The condition is checked first, so [`WaitResult::Notified`] is returned
+    /// if it holds even with an interrupt pending.
+    pub fn wait_until_interruptible<F>(&self, condition: F) -> WaitResult
+    where
+        F: Fn() -> bool,
+    {
+        self.wait_until_interruptible_impl(None, condition)
+    }
+
+    /// Blocks the current task and put it into the wait queue, until the given
+    /// `condition` becomes true, it's interrupted by
+    /// [`interrupt`](crate::interrupt), or the given duration has elapsed.
+    #[cfg(feature = "irq")]
+    pub fn wait_timeout_until_interruptible<F>(
+        &self,
+        dur: core::time::Duration,
+        condition: F,
+    ) -> WaitResult
+    where
+        F: Fn() -> bool,
+    {
+        self.wait_until_interruptible_impl(Some(axhal::time::monotonic_time() + dur), condition)
+    }
+
+    fn wait_until_interruptible_impl<F>(
+        &self,
+        deadline: Option<axhal::time::TimeValue>,
+        condition: F,
+    ) -> WaitResult
+    where
+        F: Fn() -> bool,
+    {
+        let curr = crate::current();
+        #[cfg(feature = "irq")]
+        if let Some(deadline) = deadline {
+            debug!(
+                "task wait_timeout_interruptible: {}, deadline={:?}",
+                curr.id_name(),
+                deadline
+            );
+            crate::timers::set_alarm_wakeup(deadline, curr.clone());
+        }
+        curr.set_interruptible_wq(Some(self));
+
+        let result = loop {
+            let mut rq = current_run_queue::<NoPreemptIrqSave>();
+            let wq = self.queue.lock();
+            if condition() {
+                break WaitResult::Notified;
+            }
+            if curr.is_interrupted() {
+                break WaitResult::Interrupted;
+            }
+            if deadline.is_some_and(|ddl| axhal::time::monotonic_time() >= ddl) {
+                break WaitResult::TimedOut;
+            }
+            rq.blocked_resched(wq);
+            // Preemption may occur here.
+        };
+
+        self.cancel_events(curr.clone(), deadline.is_some());
+        curr.set_interruptible_wq(None);
+        result
+    }
+
     /// Wakes up one task in the wait queue, usually the first one.
     ///
     /// If `resched` is true, the current task will be preempted when the
@@ -189,6 +331,15 @@ impl WaitQueue {
         }
     }
 
+    /// Wakes up `task` if it's blocked in the wait queue, leaving it there to
+    /// find out it was interrupted.
+    pub(crate) fn interrupt_task(&self, task: &AxTaskRef) {
+        let _wq = self.queue.lock();
+        if task.in_wait_queue() {
+            select_run_queue::<NoOp>(task).unblock_task(task.clone(), true);
+        }
+    }
+
     /// Wake up the given task in the wait queue.
     ///
     /// If `resched` is true, the current task will be preempted when the
@@ -0,0 +1,125 @@
+//! Dumping the events recorded.
+//!
+//! The binary format is little-endian, starting with a header:
+//!
+//! - [`BINARY_MAGIC`] (8 bytes), [`BINARY_VERSION`] (u32), the number of the
+//!   CPUs (u32), the number of the tracepoints (u32);
+//!
+//! followed by each tracepoint:
+//!
+//! - its ID (u32), the number of the arguments of its events (u32), then its
+//!   subsystem, its name and the names of the arguments, each as a length
+//!   (u16) followed by the bytes;
+//!
+//! and by each event until the end, in 48 bytes:
+//!
+//! - its timestamp in nanoseconds (u64), its task ID (u64), its CPU (u32),
+//!   the ID of its tracepoint (u32, 0 if unknown), and [`MAX_ARGS`] arguments
+//!   (u64), the ones not given being 0.
+
+use core::fmt::{self, Write};
+
+use axconfig::SMP;
+use crate_interface::call_interface;
+
+use crate::ring::{Events, event_count, lost_count};
+use crate::{MAX_ARGS, TraceIf, tracepoint_by_id, tracepoint_count};
+
+/// The magic number at the start of the binary format.
+pub const BINARY_MAGIC: [u8; 8] = *b"AXTRACE\0";
+
+/// The version of the binary format.
+pub const BINARY_VERSION: u32 = 1;
+
+/// An argument of an event, shown in decimal if it fits in an `i32` as a
+/// signed number (e.g. an error code), or in hexadecimal otherwise.
+struct Arg(u64);
+
+impl fmt::Display for Arg {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let value = self.0 as i64;
+        if value >= i32::MIN as i64 && value <= i32::MAX as i64 {
+            write!(f, "{}", value)
+        } else {
+            write!(f, "{:#x}", self.0)
+        }
+    }
+}
+
+/// Writes the events recorded as text, in the format of the `trace` file of
+/// ftrace.
+///
+/// The tasks are shown as `<...>`, followed by their IDs.
+pub fn dump_text<W: Write>(w: &mut W) -> fmt::Result {
+    let events = Events::new();
+    writeln!(w, "# tracer: nop")?;
+    writeln!(w, "#")?;
+    writeln!(
+        w,
+        "# entries-in-buffer/entries-lost: {}/{}   #P:{}",
+        event_count(),
+        lost_count(),
+        SMP
+    )?;
+    writeln!(w, "#")?;
+    writeln!(w, "#           TASK-PID     CPU#     TIMESTAMP  FUNCTION")?;
+    writeln!(w, "#              | |         |         |         |")?;
+    for (cpu, event) in events {
+        let nanos = call_interface!(TraceIf::ticks_to_nanos, event.ticks);
+        write!(
+            w,
+            "{:>16}-{:<7} [{:03}] {:>6}.{:06}: {}:",
+            "<...>",
+            event.task,
+            cpu,
+            nanos / 1_000_000_000,
+            nanos % 1_000_000_000 / 1_000,
+            event.tracepoint.name()
+        )?;
+        for (field, &arg) in event.tracepoint.fields().iter().zip(&event.args) {
+            write!(w, " {}={}", field, Arg(arg))?;
+        }
+        writeln!(w)?;
+    }
+    Ok(())
+}
+
+fn write_str(out: &mut impl FnMut(&[u8]), s: &str) {
+    out(&(s.len() as u16).to_le_bytes());
+    out(s.as_bytes());
+}
+
+/// Writes the events recorded in the binary format (see the module), by
+/// passing the bytes to `out` in pieces.
+pub fn dump_binary(mut out: impl FnMut(&[u8])) {
+    // the tracepoints of the events are given IDs before they're recorded
+    let events = Events::new();
+    let count = tracepoint_count();
+
+    out(&BINARY_MAGIC);
+    out(&BINARY_VERSION.to_le_bytes());
+    out(&(SMP as u32).to_le_bytes());
+    out(&(count as u32).to_le_bytes());
+    for tp in (1..=count).filter_map(tracepoint_by_id) {
+        out(&tp.id().to_le_bytes());
+        out(&(tp.fields().len() as u32).to_le_bytes());
+        write_str(&mut out, tp.system());
+        write_str(&mut out, tp.name());
+        for field in tp.fields() {
+            write_str(&mut out, field);
+        }
+    }
+
+    for (cpu, event) in events {
+        let mut buf = [0; 24 + 8 * MAX_ARGS];
+        let nanos = call_interface!(TraceIf::ticks_to_nanos, event.ticks);
+        buf[..8].copy_from_slice(&nanos.to_le_bytes());
+        buf[8..16].copy_from_slice(&event.task.to_le_bytes());
+        buf[16..20].copy_from_slice(&(cpu as u32).to_le_bytes());
+        buf[20..24].copy_from_slice(&event.tracepoint.id().to_le_bytes());
+        for (i, arg) in event.args.iter().enumerate() {
+            buf[24 + 8 * i..32 + 8 * i].copy_from_slice(&arg.to_le_bytes());
+        }
+        out(&buf);
+    }
+}
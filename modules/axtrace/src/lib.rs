@@ -0,0 +1,201 @@
+//! [ArceOS](https://github.com/arceos-org/arceos) kernel tracepoints and event
+//! tracing.
+//!
+//! Tracepoints are static events in the kernel, e.g. the entry and exit of the
+//! syscalls, the context switches, the IRQs and the block I/Os, emitted with
+//! [`tracepoint!`]. While tracing is on (see [`set_tracing_on`]), each event
+//! is recorded with its arguments, the current task and a timestamp in the
+//! ticks of the timer, in a ring of the current CPU whose oldest events are
+//! overwritten when it's full. Otherwise, a tracepoint only costs loading a
+//! flag.
+//!
+//! The events recorded are dumped in the order of their timestamps, either as
+//! text in the format of the `trace` file of ftrace ([`dump_text`]), or in a
+//! simple binary format ([`dump_binary`]).
+//!
+//! The users need to implement the [`TraceIf`] to provide the timer and the
+//! IDs of the current CPU and task.
+
+#![no_std]
+
+mod dump;
+mod ring;
+
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+use crate_interface::call_interface;
+use kspin::SpinNoIrq;
+
+pub use self::dump::{BINARY_MAGIC, BINARY_VERSION, dump_binary, dump_text};
+pub use self::ring::{EVENTS_PER_CPU, clear, event_count, lost_count};
+
+/// The maximum number of arguments of an event.
+pub const MAX_ARGS: usize = 3;
+
+/// The maximum number of tracepoints with an ID, the others are dumped as
+/// unknown ones by [`dump_binary`].
+pub const MAX_TRACEPOINTS: usize = 512;
+
+/// Extern functions needed by the tracer.
+#[crate_interface::def_interface]
+pub trait TraceIf {
+    /// Gets the current time in ticks of the timer.
+    fn current_ticks() -> u64;
+
+    /// Converts ticks of the timer to nanoseconds.
+    fn ticks_to_nanos(ticks: u64) -> u64;
+
+    /// Gets the current CPU ID.
+    fn current_cpu_id() -> usize;
+
+    /// Gets the current task ID, or 0 if there's no task yet.
+    fn current_task_id() -> u64;
+}
+
+/// A static event of the kernel, defined by [`tracepoint!`].
+pub struct Tracepoint {
+    system: &'static str,
+    name: &'static str,
+    fields: &'static [&'static str],
+    /// The ID, or 0 if it's not given yet.
+    id: AtomicU32,
+}
+
+/// The tracepoints with an ID, where the one of ID `n` is at `n - 1`.
+struct Registry {
+    tracepoints: [Option<&'static Tracepoint>; MAX_TRACEPOINTS],
+    len: usize,
+}
+
+static REGISTRY: SpinNoIrq<Registry> = SpinNoIrq::new(Registry {
+    tracepoints: [None; MAX_TRACEPOINTS],
+    len: 0,
+});
+
+static TRACING_ON: AtomicBool = AtomicBool::new(false);
+
+impl Tracepoint {
+    /// Creates a tracepoint `name` of the subsystem `system`, whose events
+    /// have the arguments named `fields`.
+    pub const fn new(
+        system: &'static str,
+        name: &'static str,
+        fields: &'static [&'static str],
+    ) -> Self {
+        assert!(fields.len() <= MAX_ARGS, "too many arguments of an event");
+        Self {
+            system,
+            name,
+            fields,
+            id: AtomicU32::new(0),
+        }
+    }
+
+    /// Returns the subsystem of the tracepoint, e.g. `sched`.
+    pub fn system(&self) -> &'static str {
+        self.system
+    }
+
+    /// Returns the name of the tracepoint, e.g. `sched_switch`.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Returns the names of the arguments of the events.
+    pub fn fields(&self) -> &'static [&'static str] {
+        self.fields
+    }
+
+    /// Returns the ID of the tracepoint, given when its first event is
+    /// recorded, counting from 1, or 0 if it has none.
+    pub fn id(&self) -> u32 {
+        self.id.load(Ordering::Acquire)
+    }
+
+    /// Gives the tracepoint an ID if it has none and there's room left.
+    fn register(&'static self) {
+        if self.id() != 0 {
+            return;
+        }
+        let mut registry = REGISTRY.lock();
+        // it may be registered by another CPU in the meantime
+        if self.id() == 0 && registry.len < MAX_TRACEPOINTS {
+            let len = registry.len;
+            registry.tracepoints[len] = Some(self);
+            registry.len += 1;
+            self.id.store(registry.len as u32, Ordering::Release);
+        }
+    }
+}
+
+/// Returns the number of the tracepoints with an ID, i.e. the greatest ID.
+fn tracepoint_count() -> usize {
+    REGISTRY.lock().len
+}
+
+/// Returns the tracepoint of ID `id`.
+fn tracepoint_by_id(id: usize) -> Option<&'static Tracepoint> {
+    let registry = REGISTRY.lock();
+    registry.tracepoints[..registry.len]
+        .get(id.checked_sub(1)?)
+        .copied()
+        .flatten()
+}
+
+/// An event recorded.
+#[derive(Clone, Copy)]
+struct Event {
+    ticks: u64,
+    task: u64,
+    tracepoint: &'static Tracepoint,
+    args: [u64; MAX_ARGS],
+}
+
+/// Whether the events are recorded.
+#[inline]
+pub fn tracing_on() -> bool {
+    TRACING_ON.load(Ordering::Relaxed)
+}
+
+/// Turns the recording of the events on or off.
+///
+/// The events recorded are kept when it's turned off, until [`clear`].
+pub fn set_tracing_on(on: bool) {
+    TRACING_ON.store(on, Ordering::Relaxed);
+}
+
+/// Records an event of `tracepoint`, used by [`tracepoint!`].
+#[doc(hidden)]
+pub fn record(tracepoint: &'static Tracepoint, args: &[u64]) {
+    tracepoint.register();
+    let mut event = Event {
+        ticks: call_interface!(TraceIf::current_ticks),
+        task: call_interface!(TraceIf::current_task_id),
+        tracepoint,
+        args: [0; MAX_ARGS],
+    };
+    event.args[..args.len()].copy_from_slice(args);
+    ring::push(call_interface!(TraceIf::current_cpu_id), event);
+}
+
+/// Emits an event of a tracepoint.
+///
+/// The tracepoint is given by the names of its subsystem and itself, followed
+/// by at most [`MAX_ARGS`] named arguments of the event, which are converted
+/// to `u64` with `as`. The arguments aren't evaluated unless tracing is on.
+///
+/// # Examples
+///
+/// ```ignore
+/// axtrace::tracepoint!("sched", "sched_switch", prev_pid = prev, next_pid = next);
+/// ```
+#[macro_export]
+macro_rules! tracepoint {
+    ($system:expr, $name:expr $(, $field:ident = $value:expr)* $(,)?) => {
+        if $crate::tracing_on() {
+            static TRACEPOINT: $crate::Tracepoint =
+                $crate::Tracepoint::new($system, $name, &[$(stringify!($field)),*]);
+            $crate::record(&TRACEPOINT, &[$($value as u64),*]);
+        }
+    };
+}
@@ -0,0 +1,138 @@
+//! The rings of the events of each CPU.
+
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use axconfig::SMP;
+use kspin::SpinNoIrq;
+
+use crate::Event;
+
+/// The number of the latest events kept of each CPU.
+pub const EVENTS_PER_CPU: usize = 4096;
+
+struct Ring {
+    events: [MaybeUninit<Event>; EVENTS_PER_CPU],
+    /// The sequence number of the oldest event.
+    first: u64,
+    /// The sequence number of the next event.
+    next: u64,
+    /// The number of the events overwritten.
+    overruns: usize,
+}
+
+impl Ring {
+    const fn new() -> Self {
+        Self {
+            events: [MaybeUninit::uninit(); EVENTS_PER_CPU],
+            first: 0,
+            next: 0,
+            overruns: 0,
+        }
+    }
+
+    fn push(&mut self, event: Event) {
+        self.events[(self.next % EVENTS_PER_CPU as u64) as usize].write(event);
+        self.next += 1;
+        if self.next - self.first > EVENTS_PER_CPU as u64 {
+            self.first += 1;
+            self.overruns += 1;
+        }
+    }
+
+    fn get(&self, seq: u64) -> Option<Event> {
+        if seq < self.first || seq >= self.next {
+            return None;
+        }
+        // the events from `first` to `next` are written
+        Some(unsafe { self.events[(seq % EVENTS_PER_CPU as u64) as usize].assume_init() })
+    }
+}
+
+static RINGS: [SpinNoIrq<Ring>; SMP] = [const { SpinNoIrq::new(Ring::new()) }; SMP];
+
+/// The number of the events dropped as the ring was busy, e.g. an IRQ
+/// arriving while dumping on the same CPU.
+static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+/// Records `event` in the ring of `cpu`.
+pub(crate) fn push(cpu: usize, event: Event) {
+    match RINGS.get(cpu).and_then(|ring| ring.try_lock()) {
+        Some(mut ring) => ring.push(event),
+        None => {
+            DROPPED.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Discards the events recorded.
+pub fn clear() {
+    for ring in &RINGS {
+        let mut ring = ring.lock();
+        ring.first = ring.next;
+        ring.overruns = 0;
+    }
+    DROPPED.store(0, Ordering::Relaxed);
+}
+
+/// Returns the number of the events recorded and kept.
+pub fn event_count() -> usize {
+    RINGS
+        .iter()
+        .map(|ring| {
+            let ring = ring.lock();
+            (ring.next - ring.first) as usize
+        })
+        .sum()
+}
+
+/// Returns the number of the events lost since the last [`clear`], either
+/// overwritten by newer ones or dropped.
+pub fn lost_count() -> usize {
+    let overruns: usize = RINGS.iter().map(|ring| ring.lock().overruns).sum();
+    overruns + DROPPED.load(Ordering::Relaxed)
+}
+
+/// Iterates over the events recorded when it's created, in the order of their
+/// timestamps, with their CPUs.
+///
+/// The events overwritten in the meantime are skipped.
+pub(crate) struct Events {
+    next: [u64; SMP],
+    end: [u64; SMP],
+}
+
+impl Events {
+    pub(crate) fn new() -> Self {
+        let mut next = [0; SMP];
+        let mut end = [0; SMP];
+        for (cpu, ring) in RINGS.iter().enumerate() {
+            let ring = ring.lock();
+            next[cpu] = ring.first;
+            end[cpu] = ring.next;
+        }
+        Self { next, end }
+    }
+}
+
+impl Iterator for Events {
+    type Item = (usize, Event);
+
+    fn next(&mut self) -> Option<(usize, Event)> {
+        let mut earliest: Option<(usize, Event)> = None;
+        for (cpu, ring) in RINGS.iter().enumerate() {
+            let ring = ring.lock();
+            self.next[cpu] = self.next[cpu].max(ring.first);
+            if self.next[cpu] >= self.end[cpu] {
+                continue;
+            }
+            let event = ring.get(self.next[cpu]).unwrap();
+            if earliest.is_none_or(|(_, e)| event.ticks < e.ticks) {
+                earliest = Some((cpu, event));
+            }
+        }
+        let (cpu, _) = earliest?;
+        self.next[cpu] += 1;
+        earliest
+    }
+}
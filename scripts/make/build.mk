@@ -7,6 +7,7 @@ ifeq ($(APP_TYPE), c)
 else
   rust_package := $(shell cat $(APP)/Cargo.toml | sed -n 's/^name = "\([a-z0-9A-Z_\-]*\)"/\1/p')
   rust_elf := $(TARGET_DIR)/$(TARGET)/$(MODE)/$(rust_package)
+  ksyms_file := $(TARGET_DIR)/$(TARGET)/$(MODE)/ksyms_$(rust_package).txt
 endif
 
 ifneq ($(filter $(MAKECMDGOALS),doc doc_check_missing),)
@@ -41,6 +42,13 @@ _cargo_build: oldconfig
 	@printf "    $(GREEN_C)Building$(END_C) App: $(APP_NAME), Arch: $(ARCH), Platform: $(PLAT_NAME), App type: $(APP_TYPE)\n"
 ifeq ($(APP_TYPE), rust)
 	$(call cargo_build,$(APP),$(AX_FEAT) $(LIB_FEAT) $(APP_FEAT))
+  ifeq ($(KSYMS), y)
+	@printf "    $(GREEN_C)Embedding$(END_C) kernel symbols\n"
+	$(call run_cmd,$(NM),-n -C --defined-only $(rust_elf) | grep " [tTwW] \| _etext$$" > $(ksyms_file))
+	$(call run_cmd,AX_KSYMS=$(ksyms_file) cargo -C $(APP) build,$(build_args) --features "$(strip $(AX_FEAT) $(LIB_FEAT) $(APP_FEAT))")
+	@$(NM) -n -C --defined-only $(rust_elf) | grep " [tTwW] \| _etext$$" | cmp -s - $(ksyms_file) || \
+		printf "    $(YELLOW_C)Warning$(END_C) kernel symbols moved after embedding the table\n"
+  endif
 	@cp $(rust_elf) $(OUT_ELF)
 else ifeq ($(APP_TYPE), c)
 	$(call cargo_build,ulib/axlibc,$(AX_FEAT) $(LIB_FEAT))
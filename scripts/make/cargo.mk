@@ -19,6 +19,21 @@ build_args := \
 
 RUSTFLAGS:= -A unsafe_op_in_unsafe_fn
 RUSTFLAGS_LINK_ARGS := -C link-arg=-T$(LD_SCRIPT) -C link-arg=-no-pie -C link-arg=-znostart-stop-gc
+ifneq ($(filter alloc-check alloc-track,$(FEATURES)),)
+  # for the allocation backtraces recorded by the heap checker
+  RUSTFLAGS += -C force-frame-pointers=yes
+else ifeq ($(KSYMS), y)
+  # for the backtraces of panics
+  RUSTFLAGS += -C force-frame-pointers=yes
+else ifneq ($(filter prof,$(FEATURES)),)
+  # for the stacks sampled by the profiler
+  RUSTFLAGS += -C force-frame-pointers=yes
+endif
+ifeq ($(KCOV), y)
+  RUSTFLAGS += -C passes=sancov-module -C llvm-args=-sanitizer-coverage-level=3 \
+    -C llvm-args=-sanitizer-coverage-trace-pc-guard
+endif
+
 RUSTDOCFLAGS := -Z unstable-options --enable-index-page -D rustdoc::broken_intra_doc_links
 
 ifeq ($(MAKECMDGOALS), doc_check_missing)
@@ -51,5 +66,6 @@ endef
 define unit_test
   $(call run_cmd,cargo test,-p axfs $(1) $(verbose) -- --nocapture)
   $(call run_cmd,cargo test,-p axfs $(1) --features "myfs" $(verbose) -- --nocapture)
+  $(call run_cmd,cargo test,-p axhal $(1) --features "iommu" $(verbose) -- --nocapture)
   $(call run_cmd,cargo test,--workspace --exclude axfs $(1) $(verbose) -- --nocapture)
 endef
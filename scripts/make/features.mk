@@ -13,7 +13,7 @@
 
 ifeq ($(APP_TYPE),c)
   ax_feat_prefix := axfeat/
-  lib_features := fp_simd irq alloc multitask fs net fd pipe select epoll
+  lib_features := fp_simd irq alloc multitask fs net fd pipe pty select epoll kcov mmap display trace prof
 else
   ifeq ($(NO_AXSTD),y)
     ax_feat_prefix := axfeat/
@@ -31,7 +31,13 @@ ifeq ($(APP_TYPE), c)
   ifneq ($(wildcard $(APP)/features.txt),)    # check features.txt exists
     override FEATURES += $(shell cat $(APP)/features.txt)
   endif
-  ifneq ($(filter fs net pipe select epoll,$(FEATURES)),)
+  ifeq ($(KCOV),y)
+    override FEATURES += kcov
+  endif
+  ifneq ($(filter display,$(FEATURES)),)
+    override FEATURES += mmap
+  endif
+  ifneq ($(filter fs net pipe pty select epoll kcov mmap trace prof,$(FEATURES)),)
     override FEATURES += fd
   endif
 endif
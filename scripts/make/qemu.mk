@@ -46,6 +46,15 @@ qemu_args-loongarch64 := \
 
 qemu_args-y := -m $(MEM) -smp $(SMP) $(qemu_args-$(ARCH))
 
+# The IOMMU must be created before the PCI devices behind it
+ifeq ($(IOMMU), y)
+  ifeq ($(ARCH), x86_64)
+    qemu_args-y += -device intel-iommu,intremap=off
+  else ifeq ($(ARCH), aarch64)
+    qemu_args-y += -machine iommu=smmuv3 -global arm-smmuv3.stage=2
+  endif
+endif
+
 qemu_args-$(BLK) += \
   -device virtio-blk-$(vdev-suffix),drive=disk0 \
   -drive id=disk0,if=none,format=raw,file=$(DISK_IMG)
@@ -78,6 +87,24 @@ qemu_args-$(GRAPHIC) += \
   -device virtio-gpu-$(vdev-suffix) -vga none \
   -serial mon:stdio
 
+# Port 0 shares the terminal with the UART and the monitor
+qemu_args-$(CONSOLE) += \
+  -chardev stdio,id=con0,mux=on,signal=off \
+  -serial chardev:con0 -mon chardev=con0 \
+  -device virtio-serial-$(vdev-suffix),max_ports=$(CONSOLE_PORTS) \
+  -device virtconsole,chardev=con0,nr=0 \
+  $(foreach i,$(shell seq 1 $$(($(CONSOLE_PORTS) - 1))),-chardev pty,id=con$(i) -device virtconsole,chardev=con$(i),nr=$(i))
+
+ifneq ($(INITRD),)
+  qemu_args-y += -initrd $(INITRD)
+endif
+
+ifneq ($(SHARE_DIR),)
+  qemu_args-y += \
+    -fsdev local,id=fsdev0,path=$(SHARE_DIR),security_model=none \
+    -device virtio-9p-$(vdev-suffix),fsdev=fsdev0,mount_tag=host
+endif
+
 ifeq ($(GRAPHIC), n)
   qemu_args-y += -nographic
 endif
@@ -16,6 +16,31 @@ char *getenv(const char *name)
     return 0;
 }
 
+#ifdef AX_CONFIG_ALLOC
+
+// TODO: remove these functions in future work
+int ax_setenv(const char *name, const char *value, int overwrite);
+int ax_unsetenv(const char *name);
+char **ax_environ(void);
+
+int setenv(const char *name, const char *value, int overwrite)
+{
+    int ret = ax_setenv(name, value, overwrite);
+    if (ret == 0)
+        environ = ax_environ();
+    return ret;
+}
+
+int unsetenv(const char *name)
+{
+    int ret = ax_unsetenv(name);
+    if (ret == 0)
+        environ = ax_environ();
+    return ret;
+}
+
+#else
+
 // TODO
 int setenv(const char *__name, const char *__value, int __replace)
 {
@@ -29,3 +54,5 @@ int unsetenv(const char *__name)
     unimplemented();
     return 0;
 }
+
+#endif // AX_CONFIG_ALLOC
@@ -0,0 +1,22 @@
+#include <stdint.h>
+
+#ifdef AX_CONFIG_KCOV
+
+// Implemented in Rust, which is instrumented and calls back into the hook.
+void ax_kcov_trace(uint32_t *guard);
+
+static int in_hook;
+
+// This file is C and not instrumented, so it can guard against the recursion.
+void __sanitizer_cov_trace_pc_guard(uint32_t *guard)
+{
+    if (__atomic_exchange_n(&in_hook, 1, __ATOMIC_ACQUIRE))
+        return;
+    ax_kcov_trace(guard);
+    __atomic_store_n(&in_hook, 0, __ATOMIC_RELEASE);
+}
+
+// The guard addresses are used as edge IDs, so there's nothing to initialize.
+void __sanitizer_cov_trace_pc_guard_init(uint32_t *start, uint32_t *stop) {}
+
+#endif // AX_CONFIG_KCOV
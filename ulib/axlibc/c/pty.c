@@ -0,0 +1,80 @@
+#ifdef AX_CONFIG_PTY
+
+#include <errno.h>
+#include <fcntl.h>
+#include <pty.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/ioctl.h>
+#include <sys/types.h>
+#include <termios.h>
+#include <unistd.h>
+
+int posix_openpt(int flags)
+{
+    return open("/dev/ptmx", flags);
+}
+
+// There are no owners or permissions to set on the slave.
+int grantpt(int fd)
+{
+    return 0;
+}
+
+int unlockpt(int fd)
+{
+    int unlock = 0;
+    return ioctl(fd, TIOCSPTLCK, &unlock);
+}
+
+int ptsname_r(int fd, char *buf, size_t len)
+{
+    int pty;
+    if (ioctl(fd, TIOCGPTN, &pty))
+        return errno;
+    if ((size_t)snprintf(buf, len, "/dev/pts/%d", pty) >= len)
+        return ERANGE;
+    return 0;
+}
+
+char *ptsname(int fd)
+{
+    static char buf[9 + sizeof(int) * 3 + 1];
+    int err = ptsname_r(fd, buf, sizeof(buf));
+    if (err) {
+        errno = err;
+        return NULL;
+    }
+    return buf;
+}
+
+int openpty(int *pm, int *ps, char *name, const struct termios *tio, const struct winsize *ws)
+{
+    char buf[9 + sizeof(int) * 3 + 1];
+    int m, s;
+
+    m = posix_openpt(O_RDWR | O_NOCTTY);
+    if (m < 0)
+        return -1;
+    if (unlockpt(m) || ptsname_r(m, buf, sizeof(buf)))
+        goto fail;
+    s = open(buf, O_RDWR | O_NOCTTY);
+    if (s < 0)
+        goto fail;
+    if (tio)
+        tcsetattr(s, TCSANOW, tio);
+    if (ws)
+        ioctl(s, TIOCSWINSZ, ws);
+    if (name)
+        strcpy(name, buf);
+    *pm = m;
+    *ps = s;
+    return 0;
+
+fail:
+    close(m);
+    return -1;
+}
+
+#endif // AX_CONFIG_PTY
@@ -0,0 +1,30 @@
+#include <errno.h>
+#include <sys/ioctl.h>
+#include <termios.h>
+
+int tcgetattr(int fd, struct termios *tio)
+{
+    if (ioctl(fd, TCGETS, tio))
+        return -1;
+    return 0;
+}
+
+int tcsetattr(int fd, int act, const struct termios *tio)
+{
+    if (act < 0 || act > 2) {
+        errno = EINVAL;
+        return -1;
+    }
+    return ioctl(fd, TCSETS + act, tio);
+}
+
+void cfmakeraw(struct termios *t)
+{
+    t->c_iflag &= ~(IGNBRK | BRKINT | PARMRK | ISTRIP | INLCR | IGNCR | ICRNL | IXON);
+    t->c_oflag &= ~OPOST;
+    t->c_lflag &= ~(ECHO | ECHONL | ICANON | ISIG | IEXTEN);
+    t->c_cflag &= ~(CSIZE | PARENB);
+    t->c_cflag |= CS8;
+    t->c_cc[VMIN] = 1;
+    t->c_cc[VTIME] = 0;
+}
@@ -2,7 +2,10 @@
 #include <fcntl.h>
 #include <stdio.h>
 #include <stdlib.h>
+#include <sys/ioctl.h>
+#include <sys/time.h>
 #include <sys/types.h>
+#include <termios.h>
 #include <time.h>
 #include <unistd.h>
 
@@ -20,18 +23,42 @@ uid_t getuid(void)
     return 0;
 }
 
+#ifndef AX_CONFIG_MULTITASK
 // TODO
 pid_t setsid(void)
 {
     unimplemented();
     return 0;
 }
+#endif
+
+#ifdef AX_CONFIG_MULTITASK
+unsigned alarm(unsigned seconds)
+{
+    struct itimerval it = {.it_value.tv_sec = seconds}, old = {0};
+    setitimer(ITIMER_REAL, &it, &old);
+    return old.it_value.tv_sec + !!old.it_value.tv_usec;
+}
+#endif
+
+pid_t tcgetpgrp(int fd)
+{
+    int pgrp;
+    if (ioctl(fd, TIOCGPGRP, &pgrp) < 0)
+        return -1;
+    return pgrp;
+}
+
+int tcsetpgrp(int fd, pid_t pgrp)
+{
+    int pgrp_int = pgrp;
+    return ioctl(fd, TIOCSPGRP, &pgrp_int);
+}
 
-// TODO
 int isatty(int fd)
 {
-    unimplemented();
-    return 0;
+    struct winsize wsz;
+    return ioctl(fd, TIOCGWINSZ, &wsz) == 0;
 }
 
 unsigned int sleep(unsigned int seconds)
@@ -0,0 +1,12 @@
+#ifndef _LINUX_KCOV_H
+#define _LINUX_KCOV_H
+
+#define KCOV_INIT_TRACE 0x80086301
+#define KCOV_ENABLE     0x6364
+#define KCOV_DISABLE    0x6365
+
+enum {
+    KCOV_TRACE_PC = 0,
+};
+
+#endif // _LINUX_KCOV_H
@@ -78,6 +78,8 @@ const char *hstrerror(int ecode);
 
 int getaddrinfo(const char *, const char *, const struct addrinfo *, struct addrinfo **);
 void freeaddrinfo(struct addrinfo *);
+int getnameinfo(const struct sockaddr *__restrict, socklen_t, char *__restrict, socklen_t,
+                char *__restrict, socklen_t, int);
 const char *gai_strerror(int __ecode);
 
 #endif // AX_CONFIG_NET
@@ -47,6 +47,28 @@ uint16_t ntohs(uint16_t);
 #define IPPROTO_MPTCP    262
 #define IPPROTO_MAX      263
 
+#define IP_TOS          1
+#define IP_TTL          2
+#define IP_HDRINCL      3
+#define IP_OPTIONS      4
+#define IP_ROUTER_ALERT 5
+#define IP_RECVOPTS     6
+#define IP_RETOPTS      7
+#define IP_PKTINFO      8
+#define IP_PKTOPTIONS   9
+#define IP_MTU_DISCOVER 10
+#define IP_RECVERR      11
+#define IP_RECVTTL      12
+#define IP_RECVTOS      13
+#define IP_MTU          14
+#define IP_FREEBIND     15
+
+#define IP_MULTICAST_IF    32
+#define IP_MULTICAST_TTL   33
+#define IP_MULTICAST_LOOP  34
+#define IP_ADD_MEMBERSHIP  35
+#define IP_DROP_MEMBERSHIP 36
+
 #define IPV6_ADDRFORM             1
 #define IPV6_2292PKTINFO          2
 #define IPV6_2292HOPOPTS          3
@@ -100,6 +122,11 @@ struct in_addr {
     in_addr_t s_addr;
 };
 
+struct ip_mreq {
+    struct in_addr imr_multiaddr;
+    struct in_addr imr_interface;
+};
+
 struct sockaddr_in {
     sa_family_t sin_family;
     in_port_t sin_port;
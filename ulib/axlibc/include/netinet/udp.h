@@ -0,0 +1,13 @@
+#ifndef _NETINET_UDP_H
+#define _NETINET_UDP_H
+
+#define UDP_CORK         1
+#define UDP_ENCAP        100
+#define UDP_NO_CHECK6_TX 101
+#define UDP_NO_CHECK6_RX 102
+#define UDP_SEGMENT      103
+#define UDP_GRO          104
+
+#define SOL_UDP 17
+
+#endif // _NETINET_UDP_H
@@ -11,6 +11,7 @@ typedef ssize_t ptrdiff_t;
 
 typedef long clock_t;
 typedef int clockid_t;
+typedef void *timer_t;
 
 #ifdef __cplusplus
 #define NULL 0L
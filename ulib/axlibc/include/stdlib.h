@@ -57,4 +57,10 @@ int setenv(const char *, const char *, int);
 int unsetenv(const char *);
 int system(const char *);
 
+int posix_openpt(int);
+int grantpt(int);
+int unlockpt(int);
+char *ptsname(int);
+int ptsname_r(int, char *, size_t);
+
 #endif //__STDLIB_H__
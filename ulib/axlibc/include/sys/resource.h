@@ -2,6 +2,7 @@
 #define _SYS_RESOURCE_H
 
 #include <sys/time.h>
+#include <sys/types.h>
 
 typedef unsigned long long rlim_t;
 
@@ -10,6 +11,10 @@ struct rlimit {
     rlim_t rlim_max;
 };
 
+#define RLIM_INFINITY  (~0ULL)
+#define RLIM_SAVED_CUR RLIM_INFINITY
+#define RLIM_SAVED_MAX RLIM_INFINITY
+
 #define RLIMIT_CPU   0
 #define RLIMIT_FSIZE 1
 #define RLIMIT_DATA  2
@@ -30,6 +35,12 @@ struct rlimit {
 #define RLIMIT_RTTIME     15
 #define RLIMIT_NLIMITS    16
 
+#define PRIO_MIN     (-20)
+#define PRIO_MAX     20
+#define PRIO_PROCESS 0
+#define PRIO_PGRP    1
+#define PRIO_USER    2
+
 #define RUSAGE_SELF     0
 #define RUSAGE_CHILDREN -1
 
@@ -57,6 +68,11 @@ struct rusage {
 
 int setrlimit(int __resource, struct rlimit *__rlimits);
 int getrlimit(int __resource, struct rlimit *__rlimits);
+int prlimit(pid_t __pid, int __resource, const struct rlimit *__new_limit,
+            struct rlimit *__old_limit);
+
+int getpriority(int __which, id_t __who);
+int setpriority(int __which, id_t __who, int __prio);
 
 int getrusage(int __who, struct rusage *__usage);
 
@@ -16,5 +16,6 @@ typedef int64_t blkcnt_t;
 typedef int pid_t;
 typedef unsigned uid_t;
 typedef unsigned gid_t;
+typedef unsigned id_t;
 
 #endif // __SYS_TYPES_H__
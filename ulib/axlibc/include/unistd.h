@@ -30,6 +30,7 @@ ssize_t read(int, void *, size_t);
 ssize_t write(int, const void *, size_t);
 ssize_t pread(int, void *, size_t, off_t);
 ssize_t pwrite(int, const void *, size_t, off_t);
+ssize_t copy_file_range(int, off_t *, int, off_t *, size_t, unsigned);
 
 int chown(const char *, uid_t, gid_t);
 int fchown(int, uid_t, gid_t);
@@ -69,6 +70,8 @@ pid_t fork(void);
 int execve(const char *, char *const[], char *const[]);
 _Noreturn void _exit(int);
 
+int nice(int);
+
 pid_t getpid(void);
 pid_t getppid(void);
 pid_t getpgrp(void);
@@ -94,6 +97,8 @@ int setegid(gid_t);
 
 long sysconf(int);
 
+int getentropy(void *, size_t);
+
 #define _SC_ARG_MAX                      0
 #define _SC_CHILD_MAX                    1
 #define _SC_CLK_TCK                      2
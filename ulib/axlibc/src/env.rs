@@ -0,0 +1,27 @@
+use core::ffi::{c_char, c_int};
+
+use arceos_posix_api::{sys_environ, sys_setenv, sys_unsetenv};
+
+use crate::utils::e;
+
+/// Returns the environment as a NULL-terminated array of `KEY=VALUE` strings.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ax_environ() -> *mut *mut c_char {
+    sys_environ()
+}
+
+/// Add or change an environment variable.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ax_setenv(
+    name: *const c_char,
+    value: *const c_char,
+    overwrite: c_int,
+) -> c_int {
+    e(sys_setenv(name, value, overwrite))
+}
+
+/// Remove an environment variable.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ax_unsetenv(name: *const c_char) -> c_int {
+    e(sys_unsetenv(name))
+}
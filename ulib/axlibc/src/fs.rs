@@ -1,7 +1,9 @@
-use core::ffi::{c_char, c_int};
+use core::ffi::{c_char, c_int, c_uint, c_void};
 
 use arceos_posix_api::{
-    sys_fstat, sys_getcwd, sys_lseek, sys_lstat, sys_open, sys_rename, sys_stat,
+    sys_copy_file_range, sys_fgetxattr, sys_flock, sys_fstat, sys_getcwd, sys_getxattr,
+    sys_listxattr, sys_lseek, sys_lstat, sys_open, sys_removexattr, sys_rename, sys_sendfile,
+    sys_setxattr, sys_stat,
 };
 
 use crate::{ctypes, utils::e};
@@ -27,6 +29,43 @@ pub unsafe extern "C" fn lseek(fd: c_int, offset: ctypes::off_t, whence: c_int)
     e(sys_lseek(fd, offset, whence) as _) as _
 }
 
+/// Copy data from the file `in_fd` to `out_fd` in the kernel.
+///
+/// Return the number of bytes copied.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sendfile(
+    out_fd: c_int,
+    in_fd: c_int,
+    offset: *mut ctypes::off_t,
+    count: usize,
+) -> ctypes::ssize_t {
+    e(sys_sendfile(out_fd, in_fd, offset, count) as _) as _
+}
+
+/// Copy a range of data from the file `fd_in` to the file `fd_out` in the
+/// kernel.
+///
+/// Return the number of bytes copied.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn copy_file_range(
+    fd_in: c_int,
+    off_in: *mut ctypes::off_t,
+    fd_out: c_int,
+    off_out: *mut ctypes::off_t,
+    len: usize,
+    flags: c_uint,
+) -> ctypes::ssize_t {
+    e(sys_copy_file_range(fd_in, off_in, fd_out, off_out, len, flags) as _) as _
+}
+
+/// Apply or remove an advisory lock on the open file `fd`.
+///
+/// Return 0 if success.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn flock(fd: c_int, operation: c_int) -> c_int {
+    e(sys_flock(fd, operation))
+}
+
 /// Get the file metadata by `path` and write into `buf`.
 ///
 /// Return 0 if success.
@@ -65,3 +104,63 @@ pub unsafe extern "C" fn getcwd(buf: *mut c_char, size: usize) -> *mut c_char {
 pub unsafe extern "C" fn rename(old: *const c_char, new: *const c_char) -> c_int {
     e(sys_rename(old, new))
 }
+
+/// Get the value of the extended attribute `name` of the file at `path`.
+///
+/// Return the size of the value. If `size` is 0, only the size is returned.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn getxattr(
+    path: *const c_char,
+    name: *const c_char,
+    value: *mut c_void,
+    size: usize,
+) -> ctypes::ssize_t {
+    e(sys_getxattr(path, name, value, size) as _) as _
+}
+
+/// Get the value of the extended attribute `name` of the file opened as `fd`.
+///
+/// Return the size of the value. If `size` is 0, only the size is returned.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn fgetxattr(
+    fd: c_int,
+    name: *const c_char,
+    value: *mut c_void,
+    size: usize,
+) -> ctypes::ssize_t {
+    e(sys_fgetxattr(fd, name, value, size) as _) as _
+}
+
+/// Set the value of the extended attribute `name` of the file at `path`.
+///
+/// Return 0 if success.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn setxattr(
+    path: *const c_char,
+    name: *const c_char,
+    value: *const c_void,
+    size: usize,
+    flags: c_int,
+) -> c_int {
+    e(sys_setxattr(path, name, value, size, flags))
+}
+
+/// List the names of the extended attributes of the file at `path`.
+///
+/// Return the size of the list. If `size` is 0, only the size is returned.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn listxattr(
+    path: *const c_char,
+    list: *mut c_char,
+    size: usize,
+) -> ctypes::ssize_t {
+    e(sys_listxattr(path, list, size) as _) as _
+}
+
+/// Remove the extended attribute `name` of the file at `path`.
+///
+/// Return 0 if success.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn removexattr(path: *const c_char, name: *const c_char) -> c_int {
+    e(sys_removexattr(path, name))
+}
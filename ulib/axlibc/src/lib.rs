@@ -18,8 +18,11 @@
 //! - Lib C functions
 //!     - `fd`: Enable file descriptor table.
 //!     - `pipe`: Enable pipe support.
+//!     - `pty`: Enable pseudo-terminal (`/dev/ptmx`) support.
 //!     - `select`: Enable synchronous I/O multiplexing ([select]) support.
 //!     - `epoll`: Enable event polling ([epoll]) support.
+//!     - `trace`: Enable kernel event tracing (`/sys/kernel/tracing`).
+//!     - `prof`: Enable the sampling profiler (`/proc/profile`).
 //!
 //! [ArceOS]: https://github.com/arceos-org/arceos
 //! [select]: https://man7.org/linux/man-pages/man2/select.2.html
@@ -49,24 +52,36 @@ mod ctypes {
 #[macro_use]
 mod utils;
 
+#[cfg(feature = "alloc")]
+mod env;
 #[cfg(feature = "fd")]
 mod fd_ops;
 #[cfg(feature = "fs")]
 mod fs;
 #[cfg(any(feature = "select", feature = "epoll"))]
 mod io_mpx;
+#[cfg(feature = "kcov")]
+mod kcov;
 #[cfg(feature = "alloc")]
 mod malloc;
+#[cfg(feature = "mmap")]
+mod mman;
 #[cfg(feature = "net")]
 mod net;
 #[cfg(feature = "pipe")]
 mod pipe;
 #[cfg(feature = "multitask")]
 mod pthread;
+#[cfg(feature = "multitask")]
+mod sched;
+#[cfg(feature = "multitask")]
+mod signal;
 #[cfg(feature = "alloc")]
 mod strftime;
 #[cfg(feature = "fp_simd")]
 mod strtod;
+#[cfg(feature = "multitask")]
+mod timer;
 
 mod errno;
 mod io;
@@ -85,33 +100,55 @@ pub use self::io::{read, writev};
 pub use self::errno::strerror;
 pub use self::mktime::mktime;
 pub use self::rand::{rand, random, srand};
-pub use self::resource::{getrlimit, setrlimit};
+pub use self::resource::{getrlimit, prlimit, setrlimit};
 pub use self::setjmp::{longjmp, setjmp};
-pub use self::sys::sysconf;
-pub use self::time::{clock_gettime, nanosleep};
-pub use self::unistd::{abort, exit, getpid};
+pub use self::sys::{getentropy, getrandom, klogctl, personality, sysconf};
+pub use self::time::{clock_getres, clock_gettime, clock_settime, nanosleep};
+pub use self::unistd::{abort, exit, getpid, membarrier};
 
+#[cfg(feature = "alloc")]
+pub use self::env::{ax_environ, ax_setenv, ax_unsetenv};
 #[cfg(feature = "alloc")]
 pub use self::malloc::{free, malloc};
 #[cfg(feature = "alloc")]
 pub use self::strftime::strftime;
 
 #[cfg(feature = "fd")]
-pub use self::fd_ops::{ax_fcntl, close, dup, dup2, dup3};
+pub use self::fd_ops::{ax_fcntl, ax_ioctl, close, dup, dup2, dup3};
 
 #[cfg(feature = "fs")]
-pub use self::fs::{ax_open, fstat, getcwd, lseek, lstat, rename, stat};
+pub use self::fs::{
+    ax_open, copy_file_range, fgetxattr, flock, fstat, getcwd, getxattr, listxattr, lseek, lstat,
+    removexattr, rename, sendfile, setxattr, stat,
+};
+
+#[cfg(feature = "kcov")]
+pub use self::kcov::ax_kcov_trace;
+
+#[cfg(feature = "mmap")]
+pub use self::mman::{mmap, munmap};
 
 #[cfg(feature = "net")]
 pub use self::net::{
-    accept, bind, connect, freeaddrinfo, getaddrinfo, getpeername, getsockname, listen, recv,
-    recvfrom, send, sendto, shutdown, socket,
+    accept, bind, connect, freeaddrinfo, getaddrinfo, getnameinfo, getpeername, getsockname,
+    getsockopt, listen, recv, recvfrom, send, sendto, setsockopt, shutdown, socket, unshare,
 };
 
 #[cfg(feature = "multitask")]
 pub use self::pthread::{pthread_create, pthread_exit, pthread_join, pthread_self};
 #[cfg(feature = "multitask")]
 pub use self::pthread::{pthread_mutex_init, pthread_mutex_lock, pthread_mutex_unlock};
+#[cfg(feature = "multitask")]
+pub use self::sched::{getcpu, sched_getaffinity, sched_getcpu, sched_setaffinity};
+#[cfg(feature = "multitask")]
+pub use self::signal::{
+    kill, pthread_kill, pthread_sigmask, raise, sigaction, sigaltstack, sigpending, sigprocmask,
+};
+#[cfg(feature = "multitask")]
+pub use self::timer::{
+    getitimer, setitimer, timer_create, timer_delete, timer_getoverrun, timer_gettime,
+    timer_settime,
+};
 
 #[cfg(feature = "pipe")]
 pub use self::pipe::pipe;
@@ -10,7 +10,10 @@ use alloc::alloc::{alloc, dealloc};
 use core::alloc::Layout;
 use core::ffi::c_void;
 
-use crate::ctypes;
+use arceos_posix_api::{charge_memory, uncharge_memory};
+use axerrno::LinuxError;
+
+use crate::{ctypes, errno::set_errno};
 
 struct MemoryControlBlock {
     size: usize,
@@ -20,16 +23,31 @@ const CTRL_BLK_SIZE: usize = core::mem::size_of::<MemoryControlBlock>();
 
 /// Allocate memory and return the memory address.
 ///
-/// Returns 0 on failure (the current implementation does not trigger an exception)
+/// Returns 0 and sets `errno` to `ENOMEM` on failure, or if the allocated
+/// memory would exceed `RLIMIT_AS` or `RLIMIT_DATA`.
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn malloc(size: ctypes::size_t) -> *mut c_void {
     // Allocate `(actual length) + 8`. The lowest 8 Bytes are stored in the actual allocated space size.
     // This is because free(uintptr_t) has only one parameter representing the address,
     // So we need to save in advance to know the size of the memory space that needs to be released
-    let layout = Layout::from_size_align(size + CTRL_BLK_SIZE, 8).unwrap();
+    let Some(layout) = size
+        .checked_add(CTRL_BLK_SIZE)
+        .and_then(|total| Layout::from_size_align(total, 8).ok())
+    else {
+        set_errno(LinuxError::ENOMEM.code());
+        return core::ptr::null_mut();
+    };
+    if charge_memory(layout.size()).is_err() {
+        set_errno(LinuxError::ENOMEM.code());
+        return core::ptr::null_mut();
+    }
     unsafe {
         let ptr = alloc(layout).cast::<MemoryControlBlock>();
-        assert!(!ptr.is_null(), "malloc failed");
+        if ptr.is_null() {
+            uncharge_memory(layout.size());
+            set_errno(LinuxError::ENOMEM.code());
+            return core::ptr::null_mut();
+        }
         ptr.write(MemoryControlBlock { size });
         ptr.add(1).cast()
     }
@@ -51,6 +69,7 @@ pub unsafe extern "C" fn free(ptr: *mut c_void) {
         let ptr = ptr.sub(1);
         let size = ptr.read().size;
         let layout = Layout::from_size_align(size + CTRL_BLK_SIZE, 8).unwrap();
-        dealloc(ptr.cast(), layout)
+        dealloc(ptr.cast(), layout);
+        uncharge_memory(layout.size());
     }
 }
@@ -0,0 +1,34 @@
+use crate::{ctypes, utils::e};
+use arceos_posix_api::{sys_mmap, sys_munmap};
+use core::ffi::{c_int, c_void};
+
+const MAP_FAILED: *mut c_void = usize::MAX as *mut c_void;
+
+/// Map `len` bytes at `off` of the file `fd` into memory.
+///
+/// Only shared mappings of the files backed by memory are supported.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mmap(
+    addr: *mut c_void,
+    len: ctypes::size_t,
+    prot: c_int,
+    flags: c_int,
+    fd: c_int,
+    off: ctypes::off_t,
+) -> *mut c_void {
+    let ret = sys_mmap(addr, len, prot, flags, fd, off);
+    // the errors are returned as negative numbers, in the last page
+    let code = ret as isize;
+    if (-4095..0).contains(&code) {
+        crate::errno::set_errno(-code as i32);
+        MAP_FAILED
+    } else {
+        ret
+    }
+}
+
+/// Remove a mapping created by `mmap`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn munmap(addr: *mut c_void, len: ctypes::size_t) -> c_int {
+    e(sys_munmap(addr, len))
+}
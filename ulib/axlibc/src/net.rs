@@ -1,8 +1,9 @@
 use arceos_posix_api::{
-    sys_accept, sys_bind, sys_connect, sys_freeaddrinfo, sys_getaddrinfo, sys_getpeername,
-    sys_getsockname, sys_listen, sys_recv, sys_recvfrom, sys_send, sys_sendto, sys_shutdown,
-    sys_socket,
+    sys_accept, sys_bind, sys_connect, sys_freeaddrinfo, sys_getaddrinfo, sys_getnameinfo,
+    sys_getpeername, sys_getsockname, sys_getsockopt, sys_listen, sys_recv, sys_recvfrom, sys_send,
+    sys_sendto, sys_setsockopt, sys_shutdown, sys_socket, sys_unshare,
 };
+use axerrno::LinuxError;
 use core::ffi::{c_char, c_int, c_void};
 
 use crate::{ctypes, utils::e};
@@ -159,6 +160,35 @@ pub unsafe extern "C" fn freeaddrinfo(res: *mut ctypes::addrinfo) {
     sys_freeaddrinfo(res);
 }
 
+/// Translate a socket address to a host name and a service name.
+///
+/// Return 0 if success, or an `EAI_*` error code.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn getnameinfo(
+    addr: *const ctypes::sockaddr,
+    addrlen: ctypes::socklen_t,
+    host: *mut c_char,
+    hostlen: ctypes::socklen_t,
+    serv: *mut c_char,
+    servlen: ctypes::socklen_t,
+    flags: c_int,
+) -> c_int {
+    let ret = sys_getnameinfo(addr, addrlen, host, hostlen, serv, servlen, flags);
+    if ret >= 0 {
+        return 0;
+    }
+    match LinuxError::try_from(-ret) {
+        Ok(LinuxError::EAFNOSUPPORT) => ctypes::EAI_FAMILY,
+        Ok(LinuxError::ENOENT) => ctypes::EAI_NONAME,
+        Ok(LinuxError::EAGAIN) => ctypes::EAI_AGAIN,
+        Ok(LinuxError::ENOSPC) => ctypes::EAI_OVERFLOW,
+        _ => {
+            crate::errno::set_errno(-ret);
+            ctypes::EAI_SYSTEM
+        }
+    }
+}
+
 /// Get current address to which the socket sockfd is bound.
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn getsockname(
@@ -178,3 +208,41 @@ pub unsafe extern "C" fn getpeername(
 ) -> c_int {
     e(sys_getpeername(sock_fd, addr, addrlen))
 }
+
+/// Get options on a socket.
+///
+/// Return 0 if success.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn getsockopt(
+    socket_fd: c_int,
+    level: c_int,
+    optname: c_int,
+    optval: *mut c_void,
+    optlen: *mut ctypes::socklen_t,
+) -> c_int {
+    e(sys_getsockopt(socket_fd, level, optname, optval, optlen))
+}
+
+/// Set options on a socket.
+///
+/// Return 0 if success.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn setsockopt(
+    socket_fd: c_int,
+    level: c_int,
+    optname: c_int,
+    optval: *const c_void,
+    optlen: ctypes::socklen_t,
+) -> c_int {
+    e(sys_setsockopt(socket_fd, level, optname, optval, optlen))
+}
+
+/// Move the calling process into new namespaces.
+///
+/// Only `CLONE_NEWNET` is supported.
+///
+/// Return 0 if success.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn unshare(flags: c_int) -> c_int {
+    e(sys_unshare(flags))
+}
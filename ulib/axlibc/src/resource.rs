@@ -1,9 +1,15 @@
 use core::ffi::c_int;
 
-use arceos_posix_api::{sys_getrlimit, sys_setrlimit};
+use arceos_posix_api::{sys_getrlimit, sys_prlimit64, sys_setrlimit};
 
 use crate::utils::e;
 
+#[cfg(feature = "multitask")]
+use {
+    arceos_posix_api::{sys_getpriority, sys_setpriority},
+    core::ffi::c_uint,
+};
+
 /// Get resource limitations
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn getrlimit(resource: c_int, rlimits: *mut crate::ctypes::rlimit) -> c_int {
@@ -15,3 +21,31 @@ pub unsafe extern "C" fn getrlimit(resource: c_int, rlimits: *mut crate::ctypes:
 pub unsafe extern "C" fn setrlimit(resource: c_int, rlimits: *mut crate::ctypes::rlimit) -> c_int {
     e(sys_setrlimit(resource, rlimits))
 }
+
+/// Get and set the resource limitations of a process
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn prlimit(
+    pid: c_int,
+    resource: c_int,
+    new_limit: *const crate::ctypes::rlimit,
+    old_limit: *mut crate::ctypes::rlimit,
+) -> c_int {
+    e(sys_prlimit64(pid, resource, new_limit, old_limit))
+}
+
+/// Get the scheduling priority (nice value) of the current task.
+///
+/// As -1 is a valid nice value, the caller must check `errno` to detect errors.
+#[cfg(feature = "multitask")]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn getpriority(which: c_int, who: c_uint) -> c_int {
+    let ret = e(sys_getpriority(which, who as c_int));
+    if ret < 0 { ret } else { 20 - ret }
+}
+
+/// Set the scheduling priority (nice value) of the current task.
+#[cfg(feature = "multitask")]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn setpriority(which: c_int, who: c_uint, prio: c_int) -> c_int {
+    e(sys_setpriority(which, who as c_int, prio))
+}
@@ -0,0 +1,41 @@
+use core::ffi::{c_int, c_uint};
+
+use arceos_posix_api::{sys_getcpu, sys_sched_getaffinity, sys_sched_setaffinity};
+
+use crate::{ctypes, utils::e};
+
+/// Set the CPU affinity mask of a thread.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sched_setaffinity(
+    pid: c_int,
+    cpusetsize: usize,
+    mask: *const ctypes::cpu_set_t,
+) -> c_int {
+    e(sys_sched_setaffinity(pid, cpusetsize, mask))
+}
+
+/// Get the CPU affinity mask of a thread.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sched_getaffinity(
+    pid: c_int,
+    cpusetsize: usize,
+    mask: *mut ctypes::cpu_set_t,
+) -> c_int {
+    e(sys_sched_getaffinity(pid, cpusetsize, mask))
+}
+
+/// Get the CPU and the NUMA node the calling thread is running on.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn getcpu(cpu: *mut c_uint, node: *mut c_uint) -> c_int {
+    e(sys_getcpu(cpu, node))
+}
+
+/// Get the CPU the calling thread is running on.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sched_getcpu() -> c_int {
+    let mut cpu = 0;
+    match e(sys_getcpu(&mut cpu, core::ptr::null_mut())) {
+        0 => cpu as c_int,
+        err => err,
+    }
+}
@@ -0,0 +1,75 @@
+use core::ffi::c_int;
+
+use arceos_posix_api::{
+    sys_getpid, sys_kill, sys_pthread_kill, sys_rt_sigaction, sys_rt_sigprocmask, sys_sigaltstack,
+    sys_sigpending,
+};
+
+use crate::{ctypes, utils::e};
+
+/// Examine and change the action of a signal.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sigaction(
+    signum: c_int,
+    act: *const ctypes::sigaction,
+    oldact: *mut ctypes::sigaction,
+) -> c_int {
+    e(sys_rt_sigaction(signum, act, oldact))
+}
+
+/// Examine and change the blocked signals of the current thread.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sigprocmask(
+    how: c_int,
+    set: *const ctypes::sigset_t,
+    oldset: *mut ctypes::sigset_t,
+) -> c_int {
+    e(sys_rt_sigprocmask(how, set, oldset))
+}
+
+/// Examine and change the blocked signals of the current thread.
+///
+/// Returns the error number instead of setting `errno`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pthread_sigmask(
+    how: c_int,
+    set: *const ctypes::sigset_t,
+    oldset: *mut ctypes::sigset_t,
+) -> c_int {
+    -sys_rt_sigprocmask(how, set, oldset)
+}
+
+/// Get the signals pending on the current thread.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sigpending(set: *mut ctypes::sigset_t) -> c_int {
+    e(sys_sigpending(set))
+}
+
+/// Set and/or get the alternate signal stack of the current thread.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn sigaltstack(
+    ss: *const ctypes::stack_t,
+    old_ss: *mut ctypes::stack_t,
+) -> c_int {
+    e(sys_sigaltstack(ss, old_ss))
+}
+
+/// Send a signal to a thread, identified by its ID.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn kill(pid: c_int, sig: c_int) -> c_int {
+    e(sys_kill(pid, sig))
+}
+
+/// Send a signal to the current thread.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn raise(sig: c_int) -> c_int {
+    e(sys_kill(sys_getpid(), sig))
+}
+
+/// Send a signal to a thread.
+///
+/// Returns the error number instead of setting `errno`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn pthread_kill(thread: ctypes::pthread_t, sig: c_int) -> c_int {
+    -sys_pthread_kill(thread, sig)
+}
@@ -1,5 +1,8 @@
-use arceos_posix_api::sys_sysconf;
-use core::ffi::{c_int, c_long};
+use arceos_posix_api::{sys_getrandom, sys_personality, sys_sysconf, sys_syslog};
+use axerrno::LinuxError;
+use core::ffi::{c_char, c_int, c_long, c_uint, c_ulong, c_void};
+
+use crate::{ctypes::ssize_t, utils::e};
 
 /// Return system configuration infomation
 ///
@@ -8,3 +11,31 @@ use core::ffi::{c_int, c_long};
 pub unsafe extern "C" fn sysconf(name: c_int) -> c_long {
     sys_sysconf(name)
 }
+
+/// Set the process execution domain, returns the previous one.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn personality(persona: c_ulong) -> c_int {
+    e(sys_personality(persona))
+}
+
+/// Read or control the kernel log, as `syslog(2)`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn klogctl(typ: c_int, buf: *mut c_char, len: c_int) -> c_int {
+    e(sys_syslog(typ, buf, len))
+}
+
+/// Fill the buffer with random bytes from the kernel CSPRNG.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn getrandom(buf: *mut c_void, buflen: usize, flags: c_uint) -> ssize_t {
+    e(sys_getrandom(buf, buflen, flags) as _) as _
+}
+
+/// Fill the buffer with at most 256 random bytes from the kernel CSPRNG.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn getentropy(buffer: *mut c_void, length: usize) -> c_int {
+    if length > 256 {
+        crate::errno::set_errno(LinuxError::EIO.code());
+        return -1;
+    }
+    e(sys_getrandom(buffer, length, 0) as _).min(0)
+}
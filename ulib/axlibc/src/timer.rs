@@ -0,0 +1,78 @@
+use core::ffi::c_int;
+
+use arceos_posix_api::{
+    sys_getitimer, sys_setitimer, sys_timer_create, sys_timer_delete, sys_timer_getoverrun,
+    sys_timer_gettime, sys_timer_settime,
+};
+
+use crate::{ctypes, utils::e};
+
+/// Get the value of an interval timer.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn getitimer(which: c_int, curr_value: *mut ctypes::itimerval) -> c_int {
+    e(sys_getitimer(which, curr_value))
+}
+
+/// Set the value of an interval timer.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn setitimer(
+    which: c_int,
+    new_value: *const ctypes::itimerval,
+    old_value: *mut ctypes::itimerval,
+) -> c_int {
+    e(sys_setitimer(which, new_value, old_value))
+}
+
+/// Create a POSIX timer.
+///
+/// The `timer_t` handle holds the ID of the timer.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn timer_create(
+    clockid: ctypes::clockid_t,
+    sevp: *mut ctypes::sigevent,
+    timerid: *mut ctypes::timer_t,
+) -> c_int {
+    let mut id = 0;
+    let ret = e(sys_timer_create(clockid, sevp, &mut id));
+    if ret == 0 {
+        unsafe { *timerid = id as usize as ctypes::timer_t };
+    }
+    ret
+}
+
+/// Delete a POSIX timer.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn timer_delete(timerid: ctypes::timer_t) -> c_int {
+    e(sys_timer_delete(timerid as usize as c_int))
+}
+
+/// Arm or disarm a POSIX timer.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn timer_settime(
+    timerid: ctypes::timer_t,
+    flags: c_int,
+    new_value: *const ctypes::itimerspec,
+    old_value: *mut ctypes::itimerspec,
+) -> c_int {
+    e(sys_timer_settime(
+        timerid as usize as c_int,
+        flags,
+        new_value,
+        old_value,
+    ))
+}
+
+/// Get the time until the next expiration of a POSIX timer.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn timer_gettime(
+    timerid: ctypes::timer_t,
+    curr_value: *mut ctypes::itimerspec,
+) -> c_int {
+    e(sys_timer_gettime(timerid as usize as c_int, curr_value))
+}
+
+/// Get the overrun count of a POSIX timer.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn timer_getoverrun(timerid: ctypes::timer_t) -> c_int {
+    e(sys_timer_getoverrun(timerid as usize as c_int))
+}
@@ -1,5 +1,11 @@
-use arceos_posix_api::{sys_exit, sys_getpid};
-use core::ffi::c_int;
+use crate::utils::e;
+use arceos_posix_api::{sys_exit, sys_getpid, sys_membarrier};
+use core::ffi::{c_int, c_uint};
+
+#[cfg(feature = "multitask")]
+use arceos_posix_api::{
+    sys_getpgid, sys_getpriority, sys_getsid, sys_nice, sys_setpgid, sys_setsid,
+};
 
 /// Get current thread ID.
 #[unsafe(no_mangle)]
@@ -7,6 +13,61 @@ pub unsafe extern "C" fn getpid() -> c_int {
     sys_getpid()
 }
 
+/// Issue a memory barrier on all the CPUs running the threads.
+///
+/// There's no `syscall` function, so it's provided as a function of its own.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn membarrier(cmd: c_int, flags: c_uint, cpu_id: c_int) -> c_int {
+    e(sys_membarrier(cmd, flags, cpu_id))
+}
+
+/// Get the process group ID of a task.
+#[cfg(feature = "multitask")]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn getpgid(pid: c_int) -> c_int {
+    e(sys_getpgid(pid))
+}
+
+/// Get the process group ID of the current task.
+#[cfg(feature = "multitask")]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn getpgrp() -> c_int {
+    e(sys_getpgid(0))
+}
+
+/// Set the process group ID of a task.
+#[cfg(feature = "multitask")]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn setpgid(pid: c_int, pgid: c_int) -> c_int {
+    e(sys_setpgid(pid, pgid))
+}
+
+/// Get the session ID of a task.
+#[cfg(feature = "multitask")]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn getsid(pid: c_int) -> c_int {
+    e(sys_getsid(pid))
+}
+
+/// Create a new session led by the current task.
+#[cfg(feature = "multitask")]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn setsid() -> c_int {
+    e(sys_setsid())
+}
+
+/// Add `inc` to the nice value of the current task.
+///
+/// Returns the new nice value, or -1 on error.
+#[cfg(feature = "multitask")]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn nice(inc: c_int) -> c_int {
+    if e(sys_nice(inc)) < 0 {
+        return -1;
+    }
+    20 - sys_getpriority(0, 0)
+}
+
 /// Abort the current process.
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn abort() -> ! {
@@ -1,10 +1,16 @@
 //! Inspection and manipulation of the process’s environment.
+//!
+//! Each process has its own environment variables, copied from its parent.
 
-#[cfg(feature = "fs")]
+#[cfg(any(feature = "alloc", feature = "fs"))]
 extern crate alloc;
 
 #[cfg(feature = "fs")]
-use {crate::io, alloc::string::String};
+use crate::io;
+#[cfg(any(feature = "alloc", feature = "fs"))]
+use alloc::string::String;
+#[cfg(feature = "alloc")]
+use {alloc::vec, core::fmt};
 
 /// Returns the current working directory as a [`String`].
 #[cfg(feature = "fs")]
@@ -17,3 +23,83 @@ pub fn current_dir() -> io::Result<String> {
 pub fn set_current_dir(path: &str) -> io::Result<()> {
     arceos_api::fs::ax_set_current_dir(path)
 }
+
+/// The error type for operations interacting with environment variables,
+/// returned by [`var`].
+#[cfg(feature = "alloc")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VarError {
+    /// The specified environment variable was not present in the current
+    /// environment.
+    NotPresent,
+}
+
+#[cfg(feature = "alloc")]
+impl fmt::Display for VarError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VarError::NotPresent => write!(f, "environment variable not found"),
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl core::error::Error for VarError {}
+
+/// An iterator over a snapshot of the environment variables, returned by
+/// [`vars`].
+#[cfg(feature = "alloc")]
+#[derive(Debug)]
+pub struct Vars {
+    inner: vec::IntoIter<(String, String)>,
+}
+
+#[cfg(feature = "alloc")]
+impl Iterator for Vars {
+    type Item = (String, String);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+/// Fetches the environment variable `key`.
+#[cfg(feature = "alloc")]
+pub fn var(key: &str) -> Result<String, VarError> {
+    arceos_api::env::ax_getenv(key).ok_or(VarError::NotPresent)
+}
+
+/// Returns an iterator of `(key, value)` pairs of all environment variables,
+/// as of the time of this invocation.
+#[cfg(feature = "alloc")]
+pub fn vars() -> Vars {
+    Vars {
+        inner: arceos_api::env::ax_envs().into_iter(),
+    }
+}
+
+/// Sets the environment variable `key` to `value`.
+///
+/// # Panics
+///
+/// Panics if `key` is empty, contains the ASCII equals sign `'='` or the NUL
+/// character `'\0'`, or when `value` contains the NUL character.
+#[cfg(feature = "alloc")]
+pub fn set_var(key: &str, value: &str) {
+    arceos_api::env::ax_setenv(key, value).unwrap_or_else(|e| {
+        panic!(
+            "failed to set environment variable `{:?}` to `{:?}`: {:?}",
+            key, value, e
+        )
+    })
+}
+
+/// Removes the environment variable `key`.
+#[cfg(feature = "alloc")]
+pub fn remove_var(key: &str) {
+    arceos_api::env::ax_unsetenv(key)
+}
@@ -64,6 +64,15 @@ impl OpenOptions {
         self
     }
 
+    /// Sets the option for direct I/O (`O_DIRECT`), bypassing the block cache.
+    ///
+    /// The offsets, lengths and buffers of the reads and writes must then be
+    /// aligned to the block size.
+    pub fn direct(&mut self, direct: bool) -> &mut Self {
+        self.0.direct(direct);
+        self
+    }
+
     /// Opens a file at `path` with the options specified by `self`.
     pub fn open(&self, path: &str) -> Result<File> {
         api::ax_open_file(path, &self.0).map(|inner| File { inner })
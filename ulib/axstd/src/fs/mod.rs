@@ -11,6 +11,9 @@ use alloc::{string::String, vec::Vec};
 pub use self::dir::{DirBuilder, DirEntry, ReadDir};
 pub use self::file::{File, FileType, Metadata, OpenOptions, Permissions};
 
+/// Disk space usage of a filesystem, in bytes.
+pub use arceos_api::fs::AxDiskUsage as DiskUsage;
+
 /// Read the entire contents of a file into a bytes vector.
 #[cfg(feature = "alloc")]
 pub fn read(path: &str) -> io::Result<Vec<u8>> {
@@ -75,3 +78,15 @@ pub fn remove_file(path: &str) -> io::Result<()> {
 pub fn rename(old: &str, new: &str) -> io::Result<()> {
     arceos_api::fs::ax_rename(old, new)
 }
+
+/// Returns the disk space usage of the filesystem containing `path`.
+pub fn disk_usage(path: &str) -> io::Result<DiskUsage> {
+    arceos_api::fs::ax_disk_usage(path)
+}
+
+/// Returns all mount points with the disk space usage of their filesystems,
+/// starting with the root filesystem.
+#[cfg(feature = "alloc")]
+pub fn mounts() -> io::Result<Vec<(String, DiskUsage)>> {
+    arceos_api::fs::ax_mounts()
+}
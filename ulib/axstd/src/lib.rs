@@ -30,9 +30,18 @@
 //! - Upperlayer stacks
 //!     - `fs`: Enable file system support.
 //!     - `myfs`: Allow users to define their custom filesystems to override the default.
+//!     - `squashfs`: Use a read-only SquashFS image as the root filesystem.
+//!     - `ninep`: Mount the directories shared by the host through virtio-9p on
+//!       `/mnt/<mount tag>`.
+//!     - `overlay`: Allow mounting a writable directory over read-only ones.
+//!     - `initramfs`: Boot from the cpio archive loaded by the bootloader (e.g.
+//!       QEMU `-initrd`), unpacked into a RAM-backed root.
 //!     - `net`: Enable networking support.
 //!     - `dns`: Enable DNS lookup support.
 //!     - `display`: Enable graphics support.
+//!     - `console`: Enable console devices, one of which can be the primary
+//!       console.
+//!     - `plugin`: Enable running programs loaded as kernel-space plugins.
 //! - Device drivers
 //!     - `bus-mmio`: Use device tree to probe all MMIO devices.
 //!     - `bus-pci`: Use PCI bus to probe all PCI devices.
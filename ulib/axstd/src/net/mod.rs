@@ -5,7 +5,8 @@
 //!
 //! # Organization
 //!
-//! * [`TcpListener`] and [`TcpStream`] provide functionality for communication over TCP
+//! * [`TcpListener`] and [`TcpStream`] provide functionality for communication over TCP;
+//!   [`ConnectOptions`] configures how [`TcpStream`] connects to hosts with several addresses
 //! * [`UdpSocket`] provides functionality for communication over UDP
 //! * [`IpAddr`] represents IP addresses of either IPv4 or IPv6; [`Ipv4Addr`] and
 //!   [`Ipv6Addr`] are respectively IPv4 and IPv6 addresses
@@ -20,7 +21,7 @@ mod udp;
 
 pub use self::socket_addr::{IpAddr, Ipv4Addr, Ipv6Addr};
 pub use self::socket_addr::{SocketAddr, SocketAddrV4, SocketAddrV6, ToSocketAddrs};
-pub use self::tcp::{TcpListener, TcpStream};
+pub use self::tcp::{ConnectOptions, TcpListener, TcpStream};
 pub use self::udp::UdpSocket;
 
 use crate::io;
@@ -1,5 +1,9 @@
+extern crate alloc;
+
 use super::{SocketAddr, ToSocketAddrs};
 use crate::io::{self, prelude::*};
+use crate::time::Duration;
+use alloc::vec::Vec;
 
 use arceos_api::net::{self as api, AxTcpSocketHandle};
 
@@ -9,6 +13,14 @@ pub struct TcpStream(AxTcpSocketHandle);
 /// A TCP socket server, listening for connections.
 pub struct TcpListener(AxTcpSocketHandle);
 
+/// Options of [`TcpStream::connect_with`].
+#[derive(Debug, Clone, Copy)]
+pub struct ConnectOptions {
+    attempt_delay: Duration,
+    attempt_timeout: Option<Duration>,
+    timeout: Option<Duration>,
+}
+
 impl TcpStream {
     /// Opens a TCP connection to a remote host.
     ///
@@ -16,17 +28,40 @@ impl TcpStream {
     /// [`ToSocketAddrs`] trait can be supplied for the address; see this trait
     /// documentation for concrete examples.
     ///
-    /// If `addr` yields multiple addresses, `connect` will be attempted with
-    /// each of the addresses until a connection is successful. If none of
-    /// the addresses result in a successful connection, the error returned from
-    /// the last connection attempt (the last address) is returned.
+    /// If `addr` yields multiple addresses, a connection is attempted with
+    /// each of them in order, starting the next attempt 250 ms after the
+    /// previous one or as soon as it fails ("Happy Eyeballs"). The attempts
+    /// run in parallel, and the first successful connection is returned. If
+    /// none of the addresses result in a successful connection, the error
+    /// returned from the last failed attempt is returned.
     pub fn connect<A: ToSocketAddrs>(addr: A) -> io::Result<TcpStream> {
-        super::each_addr(addr, |addr: io::Result<&SocketAddr>| {
-            let addr = addr?;
-            let socket = api::ax_tcp_socket();
-            api::ax_tcp_connect(&socket, *addr)?;
-            Ok(TcpStream(socket))
-        })
+        Self::connect_with(addr, &ConnectOptions::new())
+    }
+
+    /// Opens a TCP connection to a remote host like [`TcpStream::connect`],
+    /// with the delay between the attempts and the timeouts set by `options`.
+    pub fn connect_with<A: ToSocketAddrs>(
+        addr: A,
+        options: &ConnectOptions,
+    ) -> io::Result<TcpStream> {
+        let addrs: Vec<_> = addr.to_socket_addrs()?.collect();
+        api::ax_tcp_connect_any(
+            &addrs,
+            options.attempt_delay,
+            options.attempt_timeout,
+            options.timeout,
+        )
+        .map(TcpStream)
+    }
+
+    /// Opens a TCP connection to a remote host with a timeout.
+    ///
+    /// Unlike [`TcpStream::connect`], `connect_timeout` takes a single
+    /// [`SocketAddr`] since a timeout must be applied to individual addresses.
+    pub fn connect_timeout(addr: &SocketAddr, timeout: Duration) -> io::Result<TcpStream> {
+        let socket = api::ax_tcp_socket();
+        api::ax_tcp_connect_timeout(&socket, *addr, timeout)?;
+        Ok(TcpStream(socket))
     }
 
     /// Returns the socket address of the local half of this TCP connection.
@@ -61,6 +96,43 @@ impl Write for TcpStream {
     }
 }
 
+impl ConnectOptions {
+    /// Creates the default options: attempts 250 ms apart, without timeouts.
+    pub const fn new() -> Self {
+        Self {
+            attempt_delay: Duration::from_millis(250),
+            attempt_timeout: None,
+            timeout: None,
+        }
+    }
+
+    /// Sets the delay before starting the next attempt, while the previous
+    /// ones are still in progress.
+    pub const fn attempt_delay(mut self, delay: Duration) -> Self {
+        self.attempt_delay = delay;
+        self
+    }
+
+    /// Sets the timeout of each attempt. `None` waits until the attempt fails.
+    pub const fn attempt_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.attempt_timeout = timeout;
+        self
+    }
+
+    /// Sets the timeout of the whole connection. `None` waits until all the
+    /// attempts fail.
+    pub const fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+impl Default for ConnectOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl TcpListener {
     /// Creates a new `TcpListener` which will be bound to the specified
     /// address.
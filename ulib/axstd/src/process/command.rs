@@ -0,0 +1,241 @@
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use alloc::{format, vec};
+use core::fmt;
+
+use arceos_api::process as api;
+use arceos_api::task::{self as task_api, AxTaskHandle};
+use axerrno::ax_err_type;
+
+use crate::sync::Mutex;
+use crate::thread::{Thread, ThreadId};
+use crate::{env, io};
+
+/// A builder for running a program, similar to `std::process::Command`.
+///
+/// The program is an ELF shared object that defines a C `main` function, which
+/// is loaded as a kernel-space plugin and run in a new thread. The program
+/// ends when `main` returns or it calls `exit`, which only ends its thread.
+///
+/// It only gets its arguments and environment variables, and gives back its
+/// exit status: it shares the memory, file descriptors and working directory
+/// with the rest of the system, so its standard I/O and working directory are
+/// the ones of the system.
+#[derive(Debug)]
+pub struct Command {
+    program: String,
+    args: Vec<String>,
+    envs: BTreeMap<String, Option<String>>,
+    env_clear: bool,
+}
+
+impl Command {
+    /// Constructs a new `Command` for running the program at path `program`,
+    /// with no arguments.
+    ///
+    /// `argv[0]` is set to `program`, and the program inherits the current
+    /// environment variables.
+    pub fn new(program: &str) -> Command {
+        Command {
+            program: program.into(),
+            args: vec![program.into()],
+            envs: BTreeMap::new(),
+            env_clear: false,
+        }
+    }
+
+    /// Adds an argument to pass to the program.
+    pub fn arg(&mut self, arg: &str) -> &mut Command {
+        self.args.push(arg.into());
+        self
+    }
+
+    /// Adds multiple arguments to pass to the program.
+    pub fn args<I, S>(&mut self, args: I) -> &mut Command
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.args
+            .extend(args.into_iter().map(|arg| arg.as_ref().into()));
+        self
+    }
+
+    /// Inserts or updates an environment variable.
+    pub fn env(&mut self, key: &str, val: &str) -> &mut Command {
+        self.envs.insert(key.into(), Some(val.into()));
+        self
+    }
+
+    /// Inserts or updates multiple environment variables.
+    pub fn envs<I, K, V>(&mut self, vars: I) -> &mut Command
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        for (key, val) in vars {
+            self.env(key.as_ref(), val.as_ref());
+        }
+        self
+    }
+
+    /// Removes an environment variable, whether it's inherited or not.
+    pub fn env_remove(&mut self, key: &str) -> &mut Command {
+        self.envs.insert(key.into(), None);
+        self
+    }
+
+    /// Clears all environment variables, including the inherited ones.
+    pub fn env_clear(&mut self) -> &mut Command {
+        self.envs.clear();
+        self.env_clear = true;
+        self
+    }
+
+    /// Returns the path to the program.
+    pub fn get_program(&self) -> &str {
+        &self.program
+    }
+
+    /// Loads the program and runs it in a new thread, returning a handle to
+    /// it.
+    pub fn spawn(&mut self) -> io::Result<Child> {
+        let args = self.args.iter().map(String::as_str).collect::<Vec<_>>();
+        let envs = self.capture_envs();
+        let envs = envs.iter().map(String::as_str).collect::<Vec<_>>();
+        let run = Box::new(Run {
+            program: api::ax_load_program(&self.program)?,
+            args: api::ax_program_args(&args, &envs)?,
+            error: Mutex::new(None),
+        });
+        // The program may end its thread by `exit` without returning, so the
+        // thread owns nothing, and `run` is freed by the `Child` once the
+        // thread exits.
+        let run_ptr = &*run as *const Run as usize;
+        let task = task_api::ax_spawn(
+            move || {
+                // SAFETY: `run` is alive until the thread exits.
+                let run = unsafe { &*(run_ptr as *const Run) };
+                let code = match api::ax_run_program(&run.program, &run.args) {
+                    Ok(code) => code,
+                    Err(e) => {
+                        *run.error.lock() = Some(e);
+                        -1
+                    }
+                };
+                task_api::ax_exit(code)
+            },
+            self.program.clone(),
+            arceos_api::config::TASK_STACK_SIZE,
+        );
+        Ok(Child {
+            task: Some(task),
+            run: Some(run),
+        })
+    }
+
+    /// Runs the program and waits for it to finish, returning its exit
+    /// status.
+    pub fn status(&mut self) -> io::Result<ExitStatus> {
+        self.spawn()?.wait()
+    }
+
+    /// Returns the environment of the program, in the form of `KEY=VALUE`.
+    fn capture_envs(&self) -> Vec<String> {
+        let mut envs = BTreeMap::new();
+        if !self.env_clear {
+            envs.extend(env::vars());
+        }
+        for (key, val) in &self.envs {
+            match val {
+                Some(val) => envs.insert(key.clone(), val.clone()),
+                None => envs.remove(key),
+            };
+        }
+        envs.iter()
+            .map(|(key, val)| format!("{}={}", key, val))
+            .collect()
+    }
+}
+
+/// What a running program uses.
+struct Run {
+    program: api::AxProgram,
+    args: api::AxProgramArgs,
+    /// The error of the thread, if the program failed to run.
+    error: Mutex<Option<io::Error>>,
+}
+
+/// A running or exited program, returned by [`Command::spawn`].
+///
+/// Dropping it without waiting for the program detaches it.
+pub struct Child {
+    task: Option<AxTaskHandle>,
+    run: Option<Box<Run>>,
+}
+
+impl Child {
+    /// Returns the identifier of the thread running the program.
+    pub fn id(&self) -> ThreadId {
+        Thread::from_id(self.task.as_ref().unwrap().id()).id()
+    }
+
+    /// Waits for the program to exit completely, returning its exit status.
+    pub fn wait(mut self) -> io::Result<ExitStatus> {
+        let task = self.task.take().unwrap();
+        let code = task_api::ax_wait_for_exit(task).ok_or_else(|| ax_err_type!(BadState))?;
+        let run = self.run.take().unwrap();
+        match run.error.lock().take() {
+            Some(e) => Err(e),
+            None => Ok(ExitStatus(code)),
+        }
+    }
+}
+
+impl Drop for Child {
+    fn drop(&mut self) {
+        if let (Some(task), Some(run)) = (self.task.take(), self.run.take()) {
+            // free what the program uses once it exits
+            task_api::ax_spawn(
+                move || {
+                    task_api::ax_wait_for_exit(task);
+                    drop(run);
+                },
+                "reap".into(),
+                arceos_api::config::TASK_STACK_SIZE,
+            );
+        }
+    }
+}
+
+impl fmt::Debug for Child {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Child").field("id", &self.id()).finish()
+    }
+}
+
+/// The exit status of a program, i.e. the return value of its `main`
+/// function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExitStatus(i32);
+
+impl ExitStatus {
+    /// Returns `true` if the program exited with code 0.
+    pub fn success(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns the exit code of the program.
+    pub fn code(&self) -> Option<i32> {
+        Some(self.0)
+    }
+}
+
+impl fmt::Display for ExitStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "exit status: {}", self.0)
+    }
+}
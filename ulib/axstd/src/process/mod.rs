@@ -0,0 +1,25 @@
+//! A module for working with processes.
+//!
+//! Since ArceOS is a unikernel, there is no concept of processes. The
+//! process-related functions will affect the entire system, such as [`exit`]
+//! will shutdown the whole system.
+//!
+//! Programs loaded from files can still be run by `Command` (with the
+//! `plugin` and `multitask` features), but they are not isolated from the rest
+//! of the system. A program calling the C `exit` only ends itself, not the
+//! system. With the `fs` feature as well, [`service`] starts them at
+//! boot and restarts them when they exit.
+
+#[cfg(all(feature = "plugin", feature = "multitask"))]
+mod command;
+
+#[cfg(all(feature = "plugin", feature = "multitask"))]
+pub use self::command::{Child, Command, ExitStatus};
+
+#[cfg(all(feature = "plugin", feature = "multitask", feature = "fs"))]
+pub mod service;
+
+/// Shutdown the whole system.
+pub fn exit(_exit_code: i32) -> ! {
+    arceos_api::sys::ax_terminate();
+}
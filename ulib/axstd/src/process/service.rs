@@ -0,0 +1,398 @@
+//! A minimal service manager, to start programs at boot and keep them
+//! running.
+//!
+//! Each file in [`SERVICE_DIR`] defines the service of the same name, with
+//! one `key = value` per line (`#` starts a comment):
+//!
+//! ```text
+//! # /etc/services/httpd
+//! command = /bin/httpd.so --port 80
+//! after = netcfg logger
+//! restart = on-failure
+//! ```
+//!
+//! - `command`: the program and its arguments, run by [`Command`].
+//! - `after`: the services started before it. A `oneshot` service must have
+//!   exited successfully, and another one must be running.
+//! - `restart`: `no` (the default), `on-failure` or `always`. The service is
+//!   restarted after a delay that doubles each time it fails quickly, up to
+//!   [`MAX_BACKOFF`].
+//! - `type`: `simple` (the default), or `oneshot` for a program that sets
+//!   something up and exits, e.g. mounts a filesystem.
+//!
+//! The services are started by [`start`] from the `main` function of the
+//! application, so the filesystems and the network are already up. Each one
+//! runs in a thread that waits for it to exit, so that no exited program is
+//! left without being reaped.
+//!
+//! When each service starts, exits and restarts is logged to
+//! `/var/log/<name>.log`, rotated when it reaches [`MAX_LOG_SIZE`]. What the
+//! programs print isn't, as they share the standard output with the rest of
+//! the system.
+//!
+//! The tasks of each service are in the task group of the same name, which
+//! accounts the resources they use, see [`ServiceManager::usage`]. The usage
+//! of all the groups is also listed in `/proc/groups`.
+
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::time::Duration;
+
+use super::Command;
+use crate::fs::OpenOptions;
+use crate::io::{self, Error, prelude::*};
+use crate::sync::{Arc, Mutex};
+use crate::time::Instant;
+use crate::{fs, thread};
+
+/// The resources used by a service, see [`ServiceManager::usage`].
+pub use arceos_api::task::AxGroupUsage as ServiceUsage;
+
+/// The directory of the definitions of the services.
+pub const SERVICE_DIR: &str = "/etc/services";
+
+/// The delay before a service is restarted the first time.
+pub const MIN_BACKOFF: Duration = Duration::from_millis(100);
+/// The maximum delay before a service is restarted.
+pub const MAX_BACKOFF: Duration = Duration::from_secs(30);
+/// The time after which a service is considered started successfully, and
+/// its delay is reset.
+const STABLE_TIME: Duration = Duration::from_secs(10);
+
+/// The directory of the logs of the services.
+pub const LOG_DIR: &str = "/var/log";
+/// The size from which the log of a service is rotated.
+pub const MAX_LOG_SIZE: u64 = 64 * 1024;
+/// The number of rotated logs kept, `<name>.log.1` being the most recent.
+const LOG_ROTATIONS: usize = 2;
+
+/// When a service is restarted after it exits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Restart {
+    /// Never.
+    No,
+    /// If it fails to start, or exits with a non-zero code.
+    OnFailure,
+    /// Whenever it exits.
+    Always,
+}
+
+/// The definition of a service.
+#[derive(Debug, Clone)]
+pub struct ServiceConfig {
+    /// The name of the service.
+    pub name: String,
+    /// The program and its arguments.
+    pub command: Vec<String>,
+    /// The services started before it.
+    pub after: Vec<String>,
+    /// When the service is restarted.
+    pub restart: Restart,
+    /// Whether the program exits once the service is set up.
+    pub oneshot: bool,
+}
+
+impl ServiceConfig {
+    /// Parses the definition of the service `name`.
+    pub fn parse(name: &str, text: &str) -> io::Result<Self> {
+        let mut config = ServiceConfig {
+            name: name.into(),
+            command: Vec::new(),
+            after: Vec::new(),
+            restart: Restart::No,
+            oneshot: false,
+        };
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or(Error::InvalidData)
+                .map(|(key, value)| (key.trim(), value.trim()))?;
+            let words = || value.split_whitespace().map(String::from);
+            match key {
+                "command" => config.command = words().collect(),
+                "after" => config.after = words().collect(),
+                "restart" => {
+                    config.restart = match value {
+                        "no" => Restart::No,
+                        "on-failure" => Restart::OnFailure,
+                        "always" => Restart::Always,
+                        _ => return Err(Error::InvalidData),
+                    }
+                }
+                "type" => {
+                    config.oneshot = match value {
+                        "simple" => false,
+                        "oneshot" => true,
+                        _ => return Err(Error::InvalidData),
+                    }
+                }
+                _ => return Err(Error::InvalidData),
+            }
+        }
+        if config.command.is_empty() {
+            return Err(Error::InvalidData);
+        }
+        Ok(config)
+    }
+}
+
+/// Reads the definitions of the services in the directory `dir`.
+///
+/// The invalid definitions are reported and skipped.
+pub fn load_dir(dir: &str) -> io::Result<Vec<ServiceConfig>> {
+    let mut configs = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let path = format!("{}/{}", dir.trim_end_matches('/'), name);
+        match fs::read_to_string(&path).and_then(|text| ServiceConfig::parse(&name, &text)) {
+            Ok(config) => configs.push(config),
+            Err(e) => println!("service: {}: {}", path, e),
+        }
+    }
+    Ok(configs)
+}
+
+/// The state of a service.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServiceState {
+    /// Waiting for the services it's started after.
+    Waiting,
+    /// Not started, as one of the services it's started after failed, or as
+    /// they depend on each other.
+    DependencyFailed,
+    /// Running, after it has been restarted this many times.
+    Running(usize),
+    /// Waiting to be restarted.
+    Restarting,
+    /// Exited with the given code, and not restarted.
+    Exited(i32),
+    /// The program could not be run, and is not restarted.
+    Failed,
+}
+
+impl fmt::Display for ServiceState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Waiting => write!(f, "waiting"),
+            Self::DependencyFailed => write!(f, "dependency failed"),
+            Self::Running(0) => write!(f, "running"),
+            Self::Running(n) => write!(f, "running ({} restarts)", n),
+            Self::Restarting => write!(f, "restarting"),
+            Self::Exited(code) => write!(f, "exited ({})", code),
+            Self::Failed => write!(f, "failed"),
+        }
+    }
+}
+
+type States = Arc<Mutex<BTreeMap<String, ServiceState>>>;
+
+/// The running services, returned by [`start`].
+pub struct ServiceManager {
+    states: States,
+}
+
+impl ServiceManager {
+    /// Returns the name and the state of each service.
+    pub fn states(&self) -> Vec<(String, ServiceState)> {
+        let states = self.states.lock();
+        states
+            .iter()
+            .map(|(name, state)| (name.clone(), *state))
+            .collect()
+    }
+
+    /// Returns the resources used by the service `name`, since it was first
+    /// started.
+    ///
+    /// The heap memory and the file descriptors are accounted to the service
+    /// that frees or closes them, which is usually the one that allocated or
+    /// opened them.
+    pub fn usage(&self, name: &str) -> Option<ServiceUsage> {
+        arceos_api::task::ax_group_usage(name)
+    }
+}
+
+/// Sorts the services so that each one comes after the ones it's started
+/// after. The ones that depend on missing services, or on each other, are
+/// returned separately.
+fn sort(configs: Vec<ServiceConfig>) -> (Vec<ServiceConfig>, Vec<ServiceConfig>) {
+    let mut pending = configs;
+    let mut sorted: Vec<ServiceConfig> = Vec::new();
+    loop {
+        let (ready, rest): (Vec<_>, Vec<_>) = pending.into_iter().partition(|c| {
+            c.after
+                .iter()
+                .all(|dep| sorted.iter().any(|s| s.name == *dep))
+        });
+        pending = rest;
+        if ready.is_empty() {
+            return (sorted, pending);
+        }
+        sorted.extend(ready);
+    }
+}
+
+/// Starts the services in the order of their dependencies, and returns once
+/// the `oneshot` ones have exited and the other ones are running.
+pub fn start(configs: Vec<ServiceConfig>) -> ServiceManager {
+    let states: States = Arc::new(Mutex::new(
+        configs
+            .iter()
+            .map(|c| (c.name.clone(), ServiceState::Waiting))
+            .collect(),
+    ));
+    let (sorted, unresolved) = sort(configs);
+    for config in unresolved {
+        println!(
+            "service: {}: missing or circular dependencies: {}",
+            config.name,
+            config.after.join(" ")
+        );
+        states
+            .lock()
+            .insert(config.name, ServiceState::DependencyFailed);
+    }
+
+    for config in sorted {
+        let deps_ready = config.after.iter().all(|dep| {
+            matches!(
+                states.lock().get(dep),
+                Some(ServiceState::Running(_) | ServiceState::Exited(0))
+            )
+        });
+        if !deps_ready {
+            println!("service: {}: a dependency failed", config.name);
+            states
+                .lock()
+                .insert(config.name, ServiceState::DependencyFailed);
+            continue;
+        }
+        if config.oneshot {
+            supervise(&config, &states);
+            arceos_api::task::ax_set_current_group(None);
+            continue;
+        }
+        states
+            .lock()
+            .insert(config.name.clone(), ServiceState::Running(0));
+        let thread_states = states.clone();
+        let builder = thread::Builder::new().name(format!("service-{}", config.name));
+        if let Err(e) = builder.spawn(move || supervise(&config, &thread_states)) {
+            println!("service: failed to start a supervisor: {}", e);
+        }
+    }
+    ServiceManager { states }
+}
+
+/// Returns the path of the log of the service `name`.
+pub fn log_path(name: &str) -> String {
+    format!("{}/{}.log", LOG_DIR, name)
+}
+
+/// The log of a service, rotated when it reaches [`MAX_LOG_SIZE`].
+struct ServiceLog {
+    path: String,
+}
+
+impl ServiceLog {
+    fn new(name: &str) -> Self {
+        let _ = fs::create_dir_all(LOG_DIR);
+        Self {
+            path: log_path(name),
+        }
+    }
+
+    /// Appends a line with the time since boot. The errors are ignored, so
+    /// that a full or read-only filesystem doesn't stop the service.
+    fn write(&self, args: fmt::Arguments) {
+        if fs::metadata(&self.path).is_ok_and(|m| m.len() >= MAX_LOG_SIZE) {
+            self.rotate();
+        }
+        let now = arceos_api::time::ax_monotonic_time();
+        let line = format!(
+            "[{:5}.{:06}] {}\n",
+            now.as_secs(),
+            now.subsec_micros(),
+            args
+        );
+        let file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&self.path);
+        if let Ok(mut file) = file {
+            let _ = file.write_all(line.as_bytes());
+        }
+    }
+
+    /// Shifts `<name>.log.N` to `<name>.log.N+1`, dropping the oldest one.
+    fn rotate(&self) {
+        for i in (1..LOG_ROTATIONS).rev() {
+            let from = format!("{}.{}", self.path, i);
+            let _ = fs::rename(&from, &format!("{}.{}", self.path, i + 1));
+        }
+        let _ = fs::rename(&self.path, &format!("{}.1", self.path));
+    }
+}
+
+/// Runs the service until it exits and isn't restarted.
+fn supervise(config: &ServiceConfig, states: &States) {
+    let set_state = |state| {
+        states.lock().insert(config.name.clone(), state);
+    };
+    // the programs are run by tasks spawned from this one, in its group
+    arceos_api::task::ax_set_current_group(Some(&config.name));
+    let log = ServiceLog::new(&config.name);
+    let mut backoff = MIN_BACKOFF;
+    let mut restarts = 0;
+    loop {
+        set_state(ServiceState::Running(restarts));
+        log.write(format_args!("started: {}", config.command.join(" ")));
+        let started = Instant::now();
+        let result = Command::new(&config.command[0])
+            .args(&config.command[1..])
+            .status();
+        let failed = match &result {
+            Ok(status) => {
+                log.write(format_args!("exited: {}", status));
+                if !status.success() {
+                    println!("service: {}: {}", config.name, status);
+                }
+                !status.success()
+            }
+            Err(e) => {
+                log.write(format_args!("failed to run: {}", e));
+                println!("service: {}: {}", config.name, e);
+                true
+            }
+        };
+        let restart = match config.restart {
+            Restart::No => false,
+            Restart::OnFailure => failed,
+            Restart::Always => true,
+        };
+        if !restart {
+            set_state(match result {
+                Ok(status) => ServiceState::Exited(status.code().unwrap_or(-1)),
+                Err(_) => ServiceState::Failed,
+            });
+            return;
+        }
+
+        if started.elapsed() >= STABLE_TIME {
+            backoff = MIN_BACKOFF;
+        }
+        set_state(ServiceState::Restarting);
+        log.write(format_args!("restarting in {:?}", backoff));
+        thread::sleep(backoff);
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+        restarts += 1;
+    }
+}
@@ -27,7 +27,7 @@ impl ThreadId {
 }
 
 impl Thread {
-    fn from_id(id: u64) -> Self {
+    pub(crate) fn from_id(id: u64) -> Self {
         Self {
             id: ThreadId(NonZeroU64::new(id).unwrap()),
         }